@@ -87,7 +87,42 @@ fn main() {
             ItemProperty::AuxiliaryType(aux_type) => {
                 eprintln!("  [{}]: auxC type={:?}", i, aux_type);
             }
-            ItemProperty::Unknown => {
+            ItemProperty::LHevcConfig(cfg) => {
+                eprintln!("  [{}]: lhvC nal_units={}", i, cfg.nal_units.len());
+            }
+            ItemProperty::LayerSelector(layer_id) => {
+                eprintln!("  [{}]: lsel layer_id={}", i, layer_id);
+            }
+            ItemProperty::TargetOutputLayerSet(ols_idx) => {
+                eprintln!("  [{}]: tols target_ols_idx={}", i, ols_idx);
+            }
+            ItemProperty::OperatingPoints(oinf) => {
+                eprintln!(
+                    "  [{}]: oinf operating_points={}",
+                    i,
+                    oinf.operating_points.len()
+                );
+            }
+            ItemProperty::ContentLightLevel(clli) => {
+                eprintln!(
+                    "  [{}]: clli max_cll={} max_fall={}",
+                    i, clli.max_content_light_level, clli.max_pic_average_light_level
+                );
+            }
+            ItemProperty::MasteringDisplayColourVolume(mdcv) => {
+                eprintln!(
+                    "  [{}]: mdcv primaries={:?} white_point={:?} luminance={}..{}",
+                    i,
+                    mdcv.display_primaries,
+                    mdcv.white_point,
+                    mdcv.min_luminance,
+                    mdcv.max_luminance
+                );
+            }
+            ItemProperty::PixelInformation(bits_per_channel) => {
+                eprintln!("  [{}]: pixi bits_per_channel={:?}", i, bits_per_channel);
+            }
+            _ => {
                 eprintln!("  [{}]: (unknown)", i);
             }
         }
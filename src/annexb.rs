@@ -0,0 +1,295 @@
+//! Export of coded image items as Annex B byte streams
+
+use alloc::vec::Vec;
+
+use crate::GridLayout;
+use crate::error::{HeicError, Result};
+use crate::heif::{self, HeifContainer, ItemType};
+
+/// Start code written before every NAL unit
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// nal_unit_type of suffix SEI NAL units
+const SUFFIX_SEI_NUT: u8 = 40;
+
+/// An Annex B (H.265 Annex B byte stream) bitstream of one coded image
+///
+/// Made by [`DecoderConfig::extract_hevc_annexb`]; the data can be fed to
+/// any HEVC decoder that reads raw `.h265` / `.hevc` streams.
+///
+/// [`DecoderConfig::extract_hevc_annexb`]: crate::DecoderConfig::extract_hevc_annexb
+#[derive(Debug, Clone)]
+pub struct HevcBitstream {
+    /// ID of the coded image item
+    pub item_id: u32,
+    /// Column and row of the tile, for the tiles of a grid
+    pub tile: Option<(u32, u32)>,
+    /// The NAL units, each after a four-byte start code: the parameter sets
+    /// and other leading NAL units of the decoder configurations, then per
+    /// layer the item's NAL units followed by its configuration's suffix SEI.
+    /// An lhv1 item's stream starts with the items holding its lower layers,
+    /// found through `tbas` references.
+    pub data: Vec<u8>,
+}
+
+/// Annex B bitstreams of an item: one for a coded image, one per tile in
+/// row-major order for a grid
+pub(crate) fn extract_hevc_annexb(data: &[u8], item_id: u32) -> Result<Vec<HevcBitstream>> {
+    let container = heif::parse(data)?;
+    let item = container
+        .get_item(item_id)
+        .ok_or(HeicError::InvalidData("no such item"))?;
+    match item.item_type {
+        ItemType::Hvc1 | ItemType::Lhv1 => {
+            Ok(alloc::vec![coded_item_annexb(&container, &item, None)?])
+        }
+        ItemType::Grid => {
+            let grid = GridLayout::parse(&container, &item)?;
+            grid.tile_ids
+                .iter()
+                .enumerate()
+                .map(|(index, &tile_id)| {
+                    let tile = container
+                        .get_item(tile_id)
+                        .ok_or(HeicError::InvalidData("Missing tile item"))?;
+                    let index = index as u32;
+                    coded_item_annexb(
+                        &container,
+                        &tile,
+                        Some((index % grid.cols, index / grid.cols)),
+                    )
+                })
+                .collect()
+        }
+        _ => Err(HeicError::Unsupported("extracting HEVC from this item type").into()),
+    }
+}
+
+/// Annex B bitstream of an hvc1 or lhv1 item and the items with its lower
+/// layers
+fn coded_item_annexb(
+    container: &HeifContainer<'_>,
+    item: &heif::Item,
+    tile: Option<(u32, u32)>,
+) -> Result<HevcBitstream> {
+    let bases = crate::base_layer_items(container, item)?;
+    let layers: Vec<&heif::Item> = bases.iter().chain([item]).collect();
+
+    // VPS, SPS and PPS lead, in that order and base layer first; SEI and
+    // other NAL units of the configurations keep their order after them,
+    // except for suffix SEI, which follows its layer's NAL units
+    let mut leading: Vec<&[u8]> = layers
+        .iter()
+        .flat_map(|layer| config_nal_units(layer))
+        .filter(|nal| nal_unit_type(nal) != SUFFIX_SEI_NUT)
+        .collect();
+    leading.sort_by_key(|nal| parameter_set_rank(nal_unit_type(nal)));
+
+    let mut out = Vec::with_capacity(256);
+    for nal in leading {
+        out.extend_from_slice(&START_CODE);
+        out.extend_from_slice(nal);
+    }
+    for layer in layers {
+        write_item_nal_units(&mut out, container, layer)?;
+        for nal in config_nal_units(layer).filter(|nal| nal_unit_type(nal) == SUFFIX_SEI_NUT) {
+            out.extend_from_slice(&START_CODE);
+            out.extend_from_slice(nal);
+        }
+    }
+
+    Ok(HevcBitstream {
+        item_id: item.id,
+        tile,
+        data: out,
+    })
+}
+
+/// Non-empty NAL units of an item's hvcC and lhvC
+fn config_nal_units(item: &heif::Item) -> impl Iterator<Item = &[u8]> {
+    item.hevc_config
+        .iter()
+        .flat_map(|c| &c.nal_units)
+        .chain(item.lhevc_config.iter().flat_map(|c| &c.nal_units))
+        .filter(|nal| !nal.is_empty())
+        .map(Vec::as_slice)
+}
+
+/// nal_unit_type from the first byte of a NAL unit header
+fn nal_unit_type(nal: &[u8]) -> u8 {
+    (nal[0] >> 1) & 0x3F
+}
+
+/// Append the length-prefixed NAL units of an item's data, each after a
+/// start code
+fn write_item_nal_units(
+    out: &mut Vec<u8>,
+    container: &HeifContainer<'_>,
+    item: &heif::Item,
+) -> Result<()> {
+    let image_data = container
+        .get_item_data(item.id)
+        .ok_or(HeicError::InvalidData("Missing image data"))?;
+    let length_size_minus_one = match (&item.lhevc_config, &item.hevc_config) {
+        (Some(config), _) => config.length_size_minus_one,
+        (None, Some(config)) => config.length_size_minus_one,
+        (None, None) => return Err(HeicError::InvalidData("Missing hvcC config").into()),
+    };
+    let length_size = usize::from(length_size_minus_one) + 1;
+
+    out.reserve(image_data.len());
+    let mut pos = 0;
+    while pos < image_data.len() {
+        let prefix = image_data
+            .get(pos..pos + length_size)
+            .ok_or(HeicError::InvalidData("truncated NAL unit length"))?;
+        let len = prefix
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | usize::from(b));
+        pos += length_size;
+        let nal = image_data
+            .get(pos..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or(HeicError::InvalidData("NAL unit length exceeds item data"))?;
+        out.extend_from_slice(&START_CODE);
+        out.extend_from_slice(nal);
+        pos += len;
+    }
+    Ok(())
+}
+
+/// Sort key putting VPS, SPS and PPS ahead of other NAL unit types
+fn parameter_set_rank(nal_type: u8) -> u8 {
+    match nal_type {
+        32..=34 => nal_type - 32,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::tests::test_pixels;
+    use crate::heif::{FileProperty, FourCC, ItemProperty, ItemReference, LHevcDecoderConfig};
+    use crate::hevc::bitstream::{NalType, parse_nal_units};
+    use crate::{EncoderConfig, HeifEditor, PixelLayout};
+
+    /// A suffix user data SEI NAL unit of a layer
+    fn suffix_sei(layer_id: u8) -> Vec<u8> {
+        let mut nal = alloc::vec![0x50, (layer_id << 3) | 1, 5, 16];
+        nal.extend_from_slice(&[0x11; 16]);
+        nal.push(0x80);
+        nal
+    }
+
+    #[test]
+    fn test_extract_grid_tiles() {
+        let (width, height) = (150, 70);
+        let pixels: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i % 251) as u8, (i / width) as u8, 90])
+            .collect();
+        let heic = EncoderConfig::new()
+            .with_tile_size(64)
+            .encode(&pixels, width, height, PixelLayout::Rgb8)
+            .unwrap();
+        let container = heif::parse(&heic).unwrap();
+
+        let tiles = extract_hevc_annexb(&heic, container.primary_item_id).unwrap();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[4].tile, Some((1, 1)));
+        for tile in &tiles {
+            assert!(tile.data.starts_with(&[0, 0, 0, 1, 0x40]), "VPS first");
+            let frame = crate::hevc::decode(&tile.data).unwrap();
+            assert_eq!((frame.width, frame.height), (64, 64));
+        }
+
+        let single = extract_hevc_annexb(&heic, tiles[0].item_id).unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].tile, None);
+        assert_eq!(single[0].data, tiles[0].data);
+    }
+
+    #[test]
+    fn test_extract_layered_item() {
+        let heic = EncoderConfig::new()
+            .with_alpha(false)
+            .encode(&test_pixels(64, 48), 64, 48, PixelLayout::Rgba8)
+            .unwrap();
+        let mut file = HeifEditor::new(&heic).unwrap().into_file();
+        let base = file.primary_item_id;
+        for property in &mut file.properties {
+            if let ItemProperty::HevcConfig(config) = &mut property.property {
+                config.nal_units.push(suffix_sei(0));
+                property.raw = None;
+            }
+        }
+
+        // An lhv1 item with a layer 1 slice, its PPS and a suffix SEI in
+        // lhvC, referencing the primary image as its base layer
+        let slice = [0x02, 0x09, 0xAF, 0x80];
+        let mut data = (slice.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(&slice);
+        let layered = file.add_item(FourCC(*b"lhv1"), "", data);
+        let nal_units = alloc::vec![alloc::vec![0x44, 0x09, 0xC0], suffix_sei(1)];
+        let mut content = alloc::vec![1, 0xF0, 0x00, 0xFC, 0xCB, 2];
+        for nal in &nal_units {
+            content.push(0x80 | nal_unit_type(nal));
+            content.extend_from_slice(&1u16.to_be_bytes());
+            content.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            content.extend_from_slice(nal);
+        }
+        let mut raw = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        raw.extend_from_slice(b"lhvC");
+        raw.extend_from_slice(&content);
+        file.properties.push(FileProperty {
+            property: ItemProperty::LHevcConfig(LHevcDecoderConfig {
+                config_version: 1,
+                length_size_minus_one: 3,
+                nal_units,
+            }),
+            raw: Some(raw),
+        });
+        let mut assoc = file.property_associations[0].clone();
+        assoc.item_id = layered;
+        assoc.properties = alloc::vec![(file.properties.len() as u16, true)];
+        file.property_associations.push(assoc);
+        file.item_references.push(ItemReference {
+            reference_type: FourCC::TBAS,
+            from_item_id: layered,
+            to_item_ids: alloc::vec![base],
+        });
+        let bytes = file.to_bytes().unwrap();
+
+        // Parameter sets of both layers lead; each layer's suffix SEI
+        // follows its slice
+        let streams = extract_hevc_annexb(&bytes, layered).unwrap();
+        assert_eq!(streams.len(), 1);
+        let nal_units = parse_nal_units(&streams[0].data).unwrap();
+        let order: Vec<(NalType, u8)> = nal_units
+            .iter()
+            .map(|nal| (nal.nal_type, nal.nuh_layer_id))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (NalType::VpsNut, 0),
+                (NalType::SpsNut, 0),
+                (NalType::PpsNut, 0),
+                (NalType::PpsNut, 1),
+                (NalType::IdrNLp, 0),
+                (NalType::SuffixSeiNut, 0),
+                (NalType::TrailR, 1),
+                (NalType::SuffixSeiNut, 1),
+            ]
+        );
+
+        // The base layer decodes from either stream
+        let base_stream = extract_hevc_annexb(&bytes, base).unwrap();
+        let base_nal_units = parse_nal_units(&base_stream[0].data).unwrap();
+        let last = base_nal_units.last().unwrap();
+        assert_eq!(last.nal_type, NalType::SuffixSeiNut);
+        let expected = crate::hevc::decode(&base_stream[0].data).unwrap();
+        let decoded = crate::hevc::decode(&streams[0].data).unwrap();
+        assert_eq!(decoded.y_plane, expected.y_plane);
+    }
+}
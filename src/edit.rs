@@ -0,0 +1,613 @@
+//! Lossless editing of HEIF metadata
+
+use alloc::vec::Vec;
+
+use crate::Result;
+use crate::exif;
+use crate::heif::{
+    self, FileProperty, FourCC, HeifFile, ImageMirror, ImageRotation, ItemProperty, ItemReference,
+    PropertyAssociation,
+};
+
+/// MIME type of XMP items
+const XMP_CONTENT_TYPE: &str = "application/rdf+xml";
+
+/// Editor for the metadata items of a HEIF image file
+///
+/// Adds, replaces and removes EXIF, XMP and other metadata items and
+/// thumbnails, then writes a new file. Coded image data is copied, never
+/// re-encoded. Files with image sequence tracks are not supported.
+///
+/// # Example
+///
+/// ```ignore
+/// use heic_decoder::HeifEditor;
+///
+/// let mut editor = HeifEditor::new(&data)?;
+/// editor.remove_exif();
+/// editor.set_xmp(xmp.as_bytes());
+/// editor.remove_thumbnails();
+/// let edited = editor.to_bytes()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct HeifEditor {
+    file: HeifFile,
+}
+
+impl HeifEditor {
+    /// Load a HEIF file for editing
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the container is malformed, has image sequence
+    /// tracks or has items whose data cannot be read.
+    pub fn new(data: &[u8]) -> Result<Self> {
+        let container = heif::parse(data)?;
+        Ok(Self {
+            file: HeifFile::from_container(&container)?,
+        })
+    }
+
+    /// The file being edited
+    pub fn file(&self) -> &HeifFile {
+        &self.file
+    }
+
+    /// The file being edited, for edits not covered by the editor's methods
+    pub fn file_mut(&mut self) -> &mut HeifFile {
+        &mut self.file
+    }
+
+    /// The EXIF (TIFF) data, as [`DecoderConfig::extract_exif`] returns it
+    ///
+    /// [`DecoderConfig::extract_exif`]: crate::DecoderConfig::extract_exif
+    pub fn exif(&self) -> Option<&[u8]> {
+        let data = self.file.item_data(self.exif_item()?)?;
+        let offset = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        data.get(4usize.checked_add(offset)?..)
+    }
+
+    /// Replace the EXIF data with `tiff`, which starts with the TIFF header,
+    /// adding an EXIF item for the primary image if there is none
+    pub fn set_exif(&mut self, tiff: &[u8]) {
+        let mut data = Vec::with_capacity(4 + tiff.len());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(tiff);
+        match self.exif_item() {
+            Some(item_id) => self.replace(item_id, data),
+            None => {
+                self.add_metadata(FourCC(*b"Exif"), "", data);
+            }
+        }
+    }
+
+    /// Remove all EXIF items
+    pub fn remove_exif(&mut self) {
+        for item_id in self.items(|i| i.item_type == FourCC(*b"Exif")) {
+            self.remove(item_id);
+        }
+    }
+
+    /// The XMP data
+    pub fn xmp(&self) -> Option<&[u8]> {
+        self.file.item_data(self.xmp_item()?)
+    }
+
+    /// Replace the XMP data, adding an XMP item for the primary image if
+    /// there is none
+    pub fn set_xmp(&mut self, xmp: &[u8]) {
+        match self.xmp_item() {
+            Some(item_id) => self.replace(item_id, xmp.to_vec()),
+            None => {
+                self.add_metadata(FourCC(*b"mime"), XMP_CONTENT_TYPE, xmp.to_vec());
+            }
+        }
+    }
+
+    /// Remove all XMP items
+    pub fn remove_xmp(&mut self) {
+        for item_id in self.items(is_xmp) {
+            self.remove(item_id);
+        }
+    }
+
+    /// Add a metadata item describing the primary image (through a `cdsc`
+    /// reference), returning its ID
+    ///
+    /// `content_type` is the MIME type of `mime` items and ignored for other
+    /// item types.
+    pub fn add_metadata(&mut self, item_type: FourCC, content_type: &str, data: Vec<u8>) -> u32 {
+        let content_type = if item_type == FourCC(*b"mime") {
+            content_type
+        } else {
+            ""
+        };
+        let item_id = self.file.add_item(item_type, content_type, data);
+        self.file.item_references.push(ItemReference {
+            reference_type: FourCC(*b"cdsc"),
+            from_item_id: item_id,
+            to_item_ids: alloc::vec![self.file.primary_item_id],
+        });
+        item_id
+    }
+
+    /// Replace the data of an item
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such item.
+    pub fn replace_item_data(&mut self, item_id: u32, data: Vec<u8>) -> Result<()> {
+        self.file.set_item_data(item_id, data)
+    }
+
+    /// Remove an item and everything referring to it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when asked to remove the primary item.
+    pub fn remove_item(&mut self, item_id: u32) -> Result<()> {
+        self.file.remove_item(item_id)
+    }
+
+    /// Remove the thumbnails of every image, along with their auxiliary
+    /// images, unless the primary image needs them
+    pub fn remove_thumbnails(&mut self) {
+        let thumbnails: Vec<u32> = self
+            .file
+            .item_references
+            .iter()
+            .filter(|r| r.reference_type == FourCC::THMB)
+            .map(|r| r.from_item_id)
+            .collect();
+        let needed = self.image_items(self.file.primary_item_id);
+        for thumbnail in thumbnails {
+            for item_id in self.image_items(thumbnail) {
+                if !needed.contains(&item_id) {
+                    self.remove(item_id);
+                }
+            }
+        }
+    }
+
+    /// Orientation of the primary image, from its `irot` and `imir`
+    /// properties
+    pub fn orientation(&self) -> Orientation {
+        let mut turn = Turn::default();
+        for property in self.associated(self.file.primary_item_id) {
+            match &self.file.properties[property].property {
+                ItemProperty::Rotation(rotation) => turn = turn.rotate(rotation.angle / 90),
+                ItemProperty::Mirror(mirror) => turn = turn.mirror(mirror.axis),
+                _ => {}
+            }
+        }
+        turn.orientation()
+    }
+
+    /// Set the orientation of the primary image, its thumbnails and their
+    /// auxiliary images (alpha, depth) without re-encoding
+    ///
+    /// Replaces the images' `irot` and `imir` properties, keeping them where
+    /// the old ones were in the property order, after any clean aperture.
+    /// The EXIF orientation tag, if there is EXIF data, is set to match.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        let turn = Turn::from_orientation(orientation);
+        // A mirror applied after rotating by `angle` gives `turn`
+        let angle = if turn.mirrored {
+            (4 - turn.quarters) % 4 * 90
+        } else {
+            turn.quarters * 90
+        };
+        let mut transforms = Vec::new();
+        if angle != 0 {
+            let rotation = ItemProperty::Rotation(ImageRotation { angle });
+            transforms.push(self.add_property(rotation));
+        }
+        if turn.mirrored {
+            let mirror = ItemProperty::Mirror(ImageMirror { axis: 1 });
+            transforms.push(self.add_property(mirror));
+        }
+
+        let primary = self.file.primary_item_id;
+        let thumbnails = self
+            .file
+            .item_references
+            .iter()
+            .filter(|r| r.reference_type == FourCC::THMB && r.to_item_ids.contains(&primary));
+        let mut items: Vec<u32> = core::iter::once(primary)
+            .chain(thumbnails.map(|r| r.from_item_id))
+            .collect();
+        let auxiliary: Vec<u32> = self
+            .file
+            .item_references
+            .iter()
+            .filter(|r| r.reference_type == FourCC::AUXL)
+            .filter(|r| r.to_item_ids.iter().any(|id| items.contains(id)))
+            .map(|r| r.from_item_id)
+            .collect();
+        items.extend(auxiliary);
+        for item_id in items {
+            self.set_transforms(item_id, &transforms);
+        }
+
+        if let Some(tiff) = self.exif()
+            && let Some(tiff) = exif::with_orientation(tiff, orientation as u16)
+        {
+            self.set_exif(&tiff);
+        }
+    }
+
+    /// Write the edited file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a property cannot be serialized.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.file.to_bytes()
+    }
+
+    /// Give back the file being edited
+    pub fn into_file(self) -> HeifFile {
+        self.file
+    }
+
+    fn exif_item(&self) -> Option<u32> {
+        self.items(|i| i.item_type == FourCC(*b"Exif"))
+            .into_iter()
+            .next()
+    }
+
+    fn xmp_item(&self) -> Option<u32> {
+        self.items(is_xmp).into_iter().next()
+    }
+
+    fn items(&self, filter: impl Fn(&heif::ItemInfo) -> bool) -> Vec<u32> {
+        self.file
+            .item_infos
+            .iter()
+            .filter(|i| filter(i))
+            .map(|i| i.item_id)
+            .collect()
+    }
+
+    /// Replace the data of an item known to exist
+    fn replace(&mut self, item_id: u32, data: Vec<u8>) {
+        let replaced = self.file.set_item_data(item_id, data);
+        debug_assert!(replaced.is_ok());
+    }
+
+    /// Remove an item known not to be the primary
+    fn remove(&mut self, item_id: u32) {
+        let removed = self.file.remove_item(item_id);
+        debug_assert!(removed.is_ok());
+    }
+
+    /// 0-based indices of the properties associated with an item, in order
+    fn associated(&self, item_id: u32) -> Vec<usize> {
+        self.file
+            .property_associations
+            .iter()
+            .filter(|a| a.item_id == item_id)
+            .flat_map(|a| &a.properties)
+            .filter_map(|&(index, _)| usize::from(index).checked_sub(1))
+            .filter(|&i| i < self.file.properties.len())
+            .collect()
+    }
+
+    /// Add a property, returning its 1-based index
+    fn add_property(&mut self, property: ItemProperty) -> u16 {
+        self.file.properties.push(FileProperty::new(property));
+        self.file.properties.len() as u16
+    }
+
+    /// Replace the `irot` and `imir` associations of an item with
+    /// `transforms`, at the position of the first one replaced
+    fn set_transforms(&mut self, item_id: u32, transforms: &[u16]) {
+        let properties = &self.file.properties;
+        let is_orientation = |index: u16| {
+            usize::from(index)
+                .checked_sub(1)
+                .and_then(|i| properties.get(i))
+                .is_some_and(|p| {
+                    matches!(
+                        p.property,
+                        ItemProperty::Rotation(_) | ItemProperty::Mirror(_)
+                    )
+                })
+        };
+        let associations = &mut self.file.property_associations;
+        let assoc = match associations.iter_mut().position(|a| a.item_id == item_id) {
+            Some(i) => &mut associations[i],
+            None => {
+                associations.push(PropertyAssociation {
+                    item_id,
+                    properties: Vec::new(),
+                });
+                associations.last_mut().unwrap()
+            }
+        };
+        let position = assoc
+            .properties
+            .iter()
+            .position(|&(index, _)| is_orientation(index))
+            .unwrap_or(assoc.properties.len());
+        assoc
+            .properties
+            .retain(|&(index, _)| !is_orientation(index));
+        let new = transforms.iter().map(|&index| (index, true));
+        assoc.properties.splice(position..position, new);
+    }
+
+    /// Image `item_id` with its alternatives, derived-from and auxiliary
+    /// images, recursively
+    fn image_items(&self, item_id: u32) -> Vec<u32> {
+        let refs = &self.file.item_references;
+        let mut items = Vec::new();
+        let mut pending = alloc::vec![item_id];
+        while let Some(id) = pending.pop() {
+            if items.contains(&id) {
+                continue;
+            }
+            items.push(id);
+            for r in refs {
+                if r.reference_type == FourCC::DIMG && r.from_item_id == id {
+                    pending.extend(&r.to_item_ids);
+                }
+                if r.reference_type == FourCC::AUXL && r.to_item_ids.contains(&id) {
+                    pending.push(r.from_item_id);
+                }
+            }
+            for group in &self.file.entity_groups {
+                if group.grouping_type == FourCC::ALTR && group.entity_ids.contains(&id) {
+                    pending.extend(&group.entity_ids);
+                }
+            }
+        }
+        items
+    }
+}
+
+/// Display orientation of an image, numbered as the EXIF orientation tag
+///
+/// Each value names the transform from the coded image to the displayed one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// As coded
+    Normal = 1,
+    /// Mirrored left to right
+    MirrorHorizontal = 2,
+    /// Rotated 180°
+    Rotate180 = 3,
+    /// Mirrored top to bottom
+    MirrorVertical = 4,
+    /// Mirrored about the top-left to bottom-right diagonal
+    Transpose = 5,
+    /// Rotated 90° clockwise
+    Rotate90 = 6,
+    /// Mirrored about the top-right to bottom-left diagonal
+    Transverse = 7,
+    /// Rotated 270° clockwise
+    Rotate270 = 8,
+}
+
+impl Orientation {
+    /// The orientation for an EXIF orientation tag value
+    pub fn from_exif(value: u16) -> Option<Self> {
+        const ALL: [Orientation; 8] = [
+            Orientation::Normal,
+            Orientation::MirrorHorizontal,
+            Orientation::Rotate180,
+            Orientation::MirrorVertical,
+            Orientation::Transpose,
+            Orientation::Rotate90,
+            Orientation::Transverse,
+            Orientation::Rotate270,
+        ];
+        ALL.get(usize::from(value).checked_sub(1)?).copied()
+    }
+}
+
+/// An orientation as an optional left-right mirror followed by clockwise
+/// quarter turns
+#[derive(Debug, Clone, Copy, Default)]
+struct Turn {
+    mirrored: bool,
+    quarters: u16,
+}
+
+impl Turn {
+    const TABLE: [(Orientation, Turn); 8] = [
+        (Orientation::Normal, Turn::new(false, 0)),
+        (Orientation::Rotate90, Turn::new(false, 1)),
+        (Orientation::Rotate180, Turn::new(false, 2)),
+        (Orientation::Rotate270, Turn::new(false, 3)),
+        (Orientation::MirrorHorizontal, Turn::new(true, 0)),
+        (Orientation::Transverse, Turn::new(true, 1)),
+        (Orientation::MirrorVertical, Turn::new(true, 2)),
+        (Orientation::Transpose, Turn::new(true, 3)),
+    ];
+
+    const fn new(mirrored: bool, quarters: u16) -> Self {
+        Self { mirrored, quarters }
+    }
+
+    fn from_orientation(orientation: Orientation) -> Self {
+        Self::TABLE
+            .iter()
+            .find(|(o, _)| *o == orientation)
+            .map_or(Self::default(), |&(_, turn)| turn)
+    }
+
+    fn orientation(self) -> Orientation {
+        Self::TABLE
+            .iter()
+            .find(|(_, t)| t.mirrored == self.mirrored && t.quarters == self.quarters)
+            .map_or(Orientation::Normal, |&(o, _)| o)
+    }
+
+    /// Follow with `quarters` clockwise quarter turns
+    fn rotate(self, quarters: u16) -> Self {
+        Self::new(self.mirrored, (self.quarters + quarters) % 4)
+    }
+
+    /// Follow with an `imir` mirror, as the decoder applies it: axis 1 flips
+    /// left to right, axis 0 top to bottom
+    fn mirror(self, axis: u8) -> Self {
+        // A left-right flip after a rotation equals the inverse rotation
+        // after the flip; a top-to-bottom flip is that plus a half turn
+        let quarters = (4 - self.quarters) % 4;
+        let turned = Self::new(!self.mirrored, quarters);
+        if axis & 1 == 1 {
+            turned
+        } else {
+            turned.rotate(2)
+        }
+    }
+}
+
+fn is_xmp(info: &heif::ItemInfo) -> bool {
+    info.item_type == FourCC(*b"mime")
+        && (info.content_type.contains("xmp") || info.content_type.contains("rdf+xml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ranges::tests::sample_file;
+
+    #[test]
+    fn test_edit_metadata() {
+        let sample = sample_file();
+        let mut editor = HeifEditor::new(&sample.file).unwrap();
+        assert_eq!(editor.exif(), Some(&sample.exif[4..]));
+
+        // Unedited files round-trip item for item
+        let copy = editor.to_bytes().unwrap();
+        let container = heif::parse(&copy).unwrap();
+        assert_eq!(container.primary_item_id, 1);
+        assert_eq!(container.get_item_data(1), Some(&sample.image[..]));
+        assert_eq!(container.get_item_data(3), Some(&sample.thumb[..]));
+        assert_eq!(container.get_item_references(3, FourCC::THMB), [1]);
+        assert_eq!(HeifEditor::new(&copy).unwrap().to_bytes().unwrap(), copy);
+
+        editor.set_exif(b"MM\0*edited");
+        editor.set_xmp(b"<x:xmpmeta/>");
+        editor.remove_thumbnails();
+        assert!(editor.remove_item(1).is_err());
+        let edited = editor.to_bytes().unwrap();
+
+        let config = crate::DecoderConfig::new();
+        assert_eq!(
+            config.extract_exif(&edited).unwrap(),
+            Some(&b"MM\0*edited"[..])
+        );
+        assert_eq!(
+            config.extract_xmp(&edited).unwrap(),
+            Some(&b"<x:xmpmeta/>"[..])
+        );
+        let container = heif::parse(&edited).unwrap();
+        assert!(container.item_infos.iter().all(|i| i.item_id != 3));
+        assert!(
+            container
+                .item_references
+                .iter()
+                .all(|r| r.reference_type != FourCC::THMB)
+        );
+        assert_eq!(container.get_item_data(1), Some(&sample.image[..]));
+
+        let mut editor = HeifEditor::new(&edited).unwrap();
+        editor.remove_exif();
+        editor.remove_xmp();
+        let stripped = editor.to_bytes().unwrap();
+        assert_eq!(config.extract_exif(&stripped).unwrap(), None);
+        assert_eq!(config.extract_xmp(&stripped).unwrap(), None);
+    }
+
+    #[test]
+    fn test_set_orientation() {
+        let sample = sample_file();
+        let mut editor = HeifEditor::new(&sample.file).unwrap();
+        editor.set_exif(b"MM\0*\0\0\0\x08\0\0\0\0\0\0");
+        assert_eq!(editor.orientation(), Orientation::Normal);
+
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value).unwrap();
+            editor.set_orientation(orientation);
+            assert_eq!(editor.orientation(), orientation);
+            let file = editor.to_bytes().unwrap();
+            let mut reopened = HeifEditor::new(&file).unwrap();
+            assert_eq!(reopened.orientation(), orientation);
+            let tiff = reopened.exif().unwrap();
+            assert_eq!(exif::tests::orientation(tiff), Some(value));
+
+            // Thumbnails follow the primary image
+            let container = heif::parse(&file).unwrap();
+            let primary = container.get_item(1).unwrap();
+            let thumbnail = container.get_item(3).unwrap();
+            assert_eq!(primary.transforms.len(), thumbnail.transforms.len());
+            assert!(primary.transforms.len() <= 2);
+            reopened.set_orientation(Orientation::Normal);
+            assert!(
+                heif::parse(&reopened.to_bytes().unwrap())
+                    .unwrap()
+                    .properties
+                    .is_empty()
+            );
+        }
+        assert_eq!(Orientation::from_exif(9), None);
+    }
+
+    #[test]
+    fn test_hdr_properties() {
+        use crate::hevc::{ContentLightLevel, HdrStaticMetadata, MasteringDisplayColourVolume};
+        use crate::{DecoderConfig, EncoderConfig, ImageInfo, PixelLayout};
+
+        let (width, height) = (64, 32);
+        let pixels: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i % 251) as u8, 60, 90])
+            .collect();
+        let heic = EncoderConfig::new()
+            .encode(&pixels, width, height, PixelLayout::Rgb8)
+            .unwrap();
+        let info = ImageInfo::from_bytes(&heic).unwrap();
+        assert_eq!(info.hdr_metadata, HdrStaticMetadata::default());
+
+        // BT.2020 primaries (G, B, R), D65, 1000 to 0.005 cd/m²
+        let expected = HdrStaticMetadata {
+            content_light_level: Some(ContentLightLevel {
+                max_content_light_level: 1000,
+                max_pic_average_light_level: 400,
+            }),
+            mastering_display_colour_volume: Some(MasteringDisplayColourVolume {
+                display_primaries: [(8500, 39850), (6550, 2300), (35400, 14600)],
+                white_point: (15635, 16450),
+                max_luminance: 10_000_000,
+                min_luminance: 50,
+            }),
+        };
+        let mut editor = HeifEditor::new(&heic).unwrap();
+        let indices = [
+            editor.add_property(ItemProperty::ContentLightLevel(
+                expected.content_light_level.unwrap(),
+            )),
+            editor.add_property(ItemProperty::MasteringDisplayColourVolume(
+                expected.mastering_display_colour_volume.unwrap(),
+            )),
+        ];
+        let primary = editor.file().primary_item_id;
+        editor
+            .file_mut()
+            .property_associations
+            .iter_mut()
+            .find(|a| a.item_id == primary)
+            .unwrap()
+            .properties
+            .extend(indices.map(|index| (index, false)));
+        let file = editor.to_bytes().unwrap();
+
+        assert_eq!(ImageInfo::from_bytes(&file).unwrap().hdr_metadata, expected);
+        let output = DecoderConfig::new()
+            .decode(&file, PixelLayout::Rgb8)
+            .unwrap();
+        assert_eq!(output.hdr_metadata, expected);
+    }
+}
@@ -0,0 +1,620 @@
+//! HEIC encoding with the intra-only HEVC encoder
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::PixelLayout;
+use crate::error::{HeicError, Result};
+use crate::heif::{
+    CleanAperture, ColorInfo, FileProperty, FourCC, HeifFile, HevcDecoderConfig,
+    ImageSpatialExtents, ItemProperty, ItemReference, PropertyAssociation,
+};
+use crate::hevc::DecodedFrame;
+use crate::hevc::encoder::{
+    self, CONSTRAINT_INDICATOR_FLAGS, DEBLOCKING_CANDIDATES, EncodedPicture,
+    PROFILE_COMPATIBILITY_FLAGS, PROFILE_IDC_MAIN,
+};
+
+/// auxC type of alpha planes
+const ALPHA_AUX_TYPE: &str = "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha";
+
+/// Coded picture dimensions are multiples of this
+const BLOCK_SIZE: u32 = 8;
+
+/// Most tile rows or columns a grid descriptor can hold
+const MAX_GRID_TILES: u32 = 256;
+
+/// Matrix coefficients of the YCbCr conversion: BT.601
+const MATRIX_BT601: u8 = 6;
+
+/// pixi bits per channel of colour images
+const RGB_BITS: [u8; 3] = [8; 3];
+
+/// pixi bits per channel of alpha images
+const ALPHA_BITS: [u8; 1] = [8];
+
+/// Encoder configuration. Reusable across multiple encode operations.
+///
+/// Produces HEIC files holding one 8-bit 4:2:0 HEVC Main image, coded as a
+/// grid of tiles when larger than the tile size, with an auxiliary alpha
+/// image when the input has transparency.
+///
+/// # Example
+///
+/// ```ignore
+/// use heic_decoder::{EncoderConfig, PixelLayout};
+///
+/// let config = EncoderConfig::new().with_quality(80);
+/// let heic = config.encode(&rgba, width, height, PixelLayout::Rgba8)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EncoderConfig {
+    quality: u8,
+    tile_size: u32,
+    alpha: bool,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EncoderConfig {
+    /// Create a new encoder configuration with sensible defaults.
+    ///
+    /// Quality 75, 512x512 grid tiles, alpha kept.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            quality: 75,
+            tile_size: 512,
+            alpha: true,
+        }
+    }
+
+    /// Set the quality from 0 (smallest files) to 100 (best), clamped.
+    ///
+    /// Maps linearly onto the HEVC quantization parameter, from 50 at
+    /// quality 0 down to 10 at quality 100.
+    #[must_use]
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = quality.min(100);
+        self
+    }
+
+    /// Set the side of the square tiles that images larger than one tile
+    /// are split into, rounded up to a multiple of 8 and at least 64.
+    #[must_use]
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size.max(64).next_multiple_of(BLOCK_SIZE);
+        self
+    }
+
+    /// Set whether transparency is kept as an auxiliary alpha image.
+    ///
+    /// Alpha channels that are fully opaque are never stored.
+    #[must_use]
+    pub fn with_alpha(mut self, alpha: bool) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Encode pixels to a HEIC file.
+    ///
+    /// The pixels are converted to full-range BT.601 YCbCr.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for 16-bit layouts, empty images, or if `pixels`
+    /// is smaller than `width * height` pixels of `layout`.
+    pub fn encode(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        layout: PixelLayout,
+    ) -> Result<Vec<u8>> {
+        if matches!(layout, PixelLayout::Rgb16 | PixelLayout::Rgba16) {
+            return Err(HeicError::Unsupported("encoding 16-bit pixels").into());
+        }
+        if width == 0 || height == 0 {
+            return Err(HeicError::InvalidData("empty image").into());
+        }
+        let required = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(layout.bytes_per_pixel()))
+            .ok_or(HeicError::LimitExceeded("image dimensions overflow"))?;
+        if pixels.len() < required {
+            return Err(HeicError::BufferTooSmall {
+                required,
+                actual: pixels.len(),
+            }
+            .into());
+        }
+        width
+            .checked_mul(height)
+            .ok_or(HeicError::LimitExceeded("image dimensions overflow"))?;
+
+        self.encode_frame(&rgb_to_frame(&pixels[..required], width, height, layout))
+    }
+
+    /// Encode a YCbCr frame to a HEIC file.
+    ///
+    /// The frame's cropped area is coded; its `full_range` and
+    /// `matrix_coeffs` are signalled, and its alpha plane is kept unless
+    /// disabled. The inverse of [`DecoderConfig::decode_to_frame`].
+    ///
+    /// [`DecoderConfig::decode_to_frame`]: crate::DecoderConfig::decode_to_frame
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for frames that are not 8-bit 4:2:0, empty frames,
+    /// or images needing more than 256 tiles across or down.
+    pub fn encode_frame(&self, frame: &DecodedFrame) -> Result<Vec<u8>> {
+        if frame.bit_depth != 8 || frame.chroma_format != 1 {
+            let error = HeicError::Unsupported("encoding frames other than 8-bit 4:2:0");
+            return Err(error.into());
+        }
+        let (width, height) = (frame.cropped_width(), frame.cropped_height());
+        if width == 0 || height == 0 {
+            return Err(HeicError::InvalidData("empty image").into());
+        }
+        let tile_size = self.tile_size;
+        if width.div_ceil(tile_size).max(height.div_ceil(tile_size)) > MAX_GRID_TILES {
+            let error = HeicError::LimitExceeded("more than 256 grid tiles across or down");
+            return Err(error.into());
+        }
+
+        let qp = self.qp();
+        let mut file = HeifFile {
+            brand: FourCC(*b"heic"),
+            compatible_brands: vec![FourCC(*b"mif1"), FourCC(*b"heic")],
+            primary_item_id: 0,
+            item_infos: Vec::new(),
+            item_data: Vec::new(),
+            properties: Vec::new(),
+            property_associations: Vec::new(),
+            item_references: Vec::new(),
+            entity_groups: Vec::new(),
+        };
+        let colr = ItemProperty::ColorInfo(ColorInfo::Nclx {
+            color_primaries: 1,
+            transfer_characteristics: 13,
+            matrix_coefficients: u16::from(frame.matrix_coeffs),
+            full_range: frame.full_range,
+        });
+
+        let alpha = frame.alpha_plane.as_ref().filter(|_| self.alpha);
+        if alpha.is_some_and(|alpha| alpha.len() != width as usize * height as usize) {
+            let error = HeicError::InvalidData("alpha plane size differs from the image size");
+            return Err(error.into());
+        }
+
+        let primary = add_tiled_image(&mut file, frame, qp, tile_size, &RGB_BITS, Some(colr));
+        file.primary_item_id = primary;
+
+        if let Some(alpha) = alpha
+            && alpha.iter().any(|&a| a != 255)
+        {
+            let mut alpha_frame = DecodedFrame::with_params(width, height, 8, 1);
+            alpha_frame.y_plane.copy_from_slice(alpha);
+            alpha_frame.cb_plane.fill(128);
+            alpha_frame.cr_plane.fill(128);
+            alpha_frame.full_range = true;
+            alpha_frame.matrix_coeffs = MATRIX_BT601;
+
+            // Tiled like the primary image
+            let bits = &ALPHA_BITS;
+            let item_id = add_tiled_image(&mut file, &alpha_frame, qp, tile_size, bits, None);
+            set_hidden(&mut file, item_id);
+            let aux_type = push_property(
+                &mut file,
+                ItemProperty::AuxiliaryType(ALPHA_AUX_TYPE.into()),
+            );
+            associate(&mut file, item_id, aux_type, true);
+            file.item_references.push(ItemReference {
+                reference_type: FourCC::AUXL,
+                from_item_id: item_id,
+                to_item_ids: vec![primary],
+            });
+        }
+
+        file.to_bytes()
+    }
+
+    /// Quantization parameter for the quality setting
+    fn qp(&self) -> u8 {
+        50 - self.quality * 2 / 5
+    }
+}
+
+/// Convert 8-bit pixels to a full-range BT.601 4:2:0 frame
+///
+/// Chroma is converted from the average colour of each 2x2 block.
+fn rgb_to_frame(pixels: &[u8], width: u32, height: u32, layout: PixelLayout) -> DecodedFrame {
+    let bpp = layout.bytes_per_pixel();
+    let (r_idx, b_idx) = match layout {
+        PixelLayout::Bgr8 | PixelLayout::Bgra8 => (2, 0),
+        _ => (0, 2),
+    };
+    let rgb = |x: u32, y: u32| {
+        let i = (y as usize * width as usize + x as usize) * bpp;
+        [pixels[i + r_idx], pixels[i + 1], pixels[i + b_idx]].map(i32::from)
+    };
+
+    let mut frame = DecodedFrame::with_params(width, height, 8, 1);
+    frame.full_range = true;
+    frame.matrix_coeffs = MATRIX_BT601;
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b] = rgb(x, y);
+            let luma = (19595 * r + 38470 * g + 7471 * b + 32768) >> 16;
+            frame.set_y(x, y, luma as u16);
+        }
+    }
+    for cy in 0..height.div_ceil(2) {
+        for cx in 0..width.div_ceil(2) {
+            let mut sum = [0i32; 3];
+            let mut count = 0;
+            for y in (2 * cy)..(2 * cy + 2).min(height) {
+                for x in (2 * cx)..(2 * cx + 2).min(width) {
+                    let color = rgb(x, y);
+                    for (s, c) in sum.iter_mut().zip(color) {
+                        *s += c;
+                    }
+                    count += 1;
+                }
+            }
+            let [r, g, b] = sum.map(|s| (s + count / 2) / count);
+            let cb = (-11059 * r - 21709 * g + 32768 * b + (128 << 16) + 32768) >> 16;
+            let cr = (32768 * r - 27439 * g - 5329 * b + (128 << 16) + 32768) >> 16;
+            frame.set_cb(cx, cy, cb.clamp(0, 255) as u16);
+            frame.set_cr(cx, cy, cr.clamp(0, 255) as u16);
+        }
+    }
+
+    if layout.has_alpha() {
+        let alpha = (0..width as usize * height as usize)
+            .map(|i| u16::from(pixels[i * bpp + 3]))
+            .collect();
+        frame.alpha_plane = Some(alpha);
+    }
+    frame
+}
+
+/// Copy a `width` x `height` region at (`x`, `y`) of a frame's cropped area
+/// into a `coded_width` x `coded_height` picture, repeating the last column
+/// and row of the region into the padding
+///
+/// `x` and `y` must be even.
+fn padded_picture(
+    frame: &DecodedFrame,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+    (coded_width, coded_height): (u32, u32),
+) -> DecodedFrame {
+    let mut picture = DecodedFrame::with_params(coded_width, coded_height, 8, 1);
+    picture.full_range = frame.full_range;
+    picture.matrix_coeffs = frame.matrix_coeffs;
+
+    let (left, top) = (frame.crop_left + x, frame.crop_top + y);
+    for py in 0..coded_height {
+        let row = (top + py.min(height - 1)) * frame.width;
+        for px in 0..coded_width {
+            let sample = frame.y_plane[(row + left + px.min(width - 1)) as usize];
+            picture.y_plane[(py * coded_width + px) as usize] = sample;
+        }
+    }
+
+    let frame_chroma_width = frame.width.div_ceil(2);
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let coded_chroma_width = coded_width / 2;
+    for py in 0..coded_height / 2 {
+        let row = (top / 2 + py.min(chroma_height - 1)) * frame_chroma_width;
+        for px in 0..coded_chroma_width {
+            let src = (row + left / 2 + px.min(chroma_width - 1)) as usize;
+            let dst = (py * coded_chroma_width + px) as usize;
+            picture.cb_plane[dst] = frame.cb_plane[src];
+            picture.cr_plane[dst] = frame.cr_plane[src];
+        }
+    }
+    picture
+}
+
+/// Code a frame's cropped area as one image item, or as a grid when it is
+/// larger than a tile, returning the item's ID
+///
+/// `bits` is the bits per channel of the image's pixi; `colr` is associated
+/// with the image when given.
+fn add_tiled_image(
+    file: &mut HeifFile,
+    frame: &DecodedFrame,
+    qp: u8,
+    tile_size: u32,
+    bits: &[u8],
+    colr: Option<ItemProperty>,
+) -> u32 {
+    let (width, height) = (frame.cropped_width(), frame.cropped_height());
+    if width > tile_size || height > tile_size {
+        return add_grid(file, frame, qp, tile_size, bits, colr);
+    }
+    let item_id = add_image(file, frame, qp, bits);
+    if let Some(colr) = colr {
+        let colr = push_property(file, colr);
+        associate(file, item_id, colr, false);
+    }
+    add_clean_aperture(file, item_id, width, height);
+    item_id
+}
+
+/// Code a frame's cropped area as one image item, returning its ID
+///
+/// The picture is padded to whole coding blocks and cropped back to even
+/// dimensions by the conformance window; odd dimensions need a clean
+/// aperture on top, see [`add_clean_aperture`].
+fn add_image(file: &mut HeifFile, frame: &DecodedFrame, qp: u8, bits: &[u8]) -> u32 {
+    let (width, height) = (frame.cropped_width(), frame.cropped_height());
+    let coded = (
+        width.next_multiple_of(BLOCK_SIZE),
+        height.next_multiple_of(BLOCK_SIZE),
+    );
+    let mut picture = padded_picture(frame, (0, 0), (width, height), coded);
+    let (even_width, even_height) = (width.next_multiple_of(2), height.next_multiple_of(2));
+    picture.set_crop(0, coded.0 - even_width, 0, coded.1 - even_height);
+
+    let encoded = encoder::encode_picture(&picture, qp, &DEBLOCKING_CANDIDATES);
+    let item_id = add_coded_item(file, &encoded);
+    let config = push_property(file, hevc_config(encoded));
+    let extents = push_property(file, extents_property(even_width, even_height));
+    let pixi = push_property(file, ItemProperty::PixelInformation(bits.to_vec()));
+    associate(file, item_id, config, true);
+    associate(file, item_id, extents, false);
+    associate(file, item_id, pixi, false);
+    item_id
+}
+
+/// Code a frame's cropped area as a grid of `tile_size` tiles, returning
+/// the ID of the grid item
+///
+/// Every tile shares the first tile's parameter sets, which the grid's
+/// tiles must.
+fn add_grid(
+    file: &mut HeifFile,
+    frame: &DecodedFrame,
+    qp: u8,
+    tile_size: u32,
+    bits: &[u8],
+    colr: Option<ItemProperty>,
+) -> u32 {
+    let (width, height) = (frame.cropped_width(), frame.cropped_height());
+    let (cols, rows) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+    let encode_tile = |index: u32, deblocking: &[Option<(i8, i8)>]| {
+        let (x, y) = ((index % cols) * tile_size, (index / cols) * tile_size);
+        let region = ((width - x).min(tile_size), (height - y).min(tile_size));
+        let picture = padded_picture(frame, (x, y), region, (tile_size, tile_size));
+        encoder::encode_picture(&picture, qp, deblocking)
+    };
+
+    let first = encode_tile(0, &DEBLOCKING_CANDIDATES);
+    let deblocking = [first.deblocking];
+    #[cfg(feature = "parallel")]
+    let rest: Vec<EncodedPicture> = (1..rows * cols)
+        .into_par_iter()
+        .map(|index| encode_tile(index, &deblocking))
+        .collect();
+    #[cfg(not(feature = "parallel"))]
+    let rest: Vec<EncodedPicture> = (1..rows * cols)
+        .map(|index| encode_tile(index, &deblocking))
+        .collect();
+
+    let tile_ids: Vec<u32> = core::iter::once(&first)
+        .chain(&rest)
+        .map(|tile| {
+            let item_id = add_coded_item(file, tile);
+            set_hidden(file, item_id);
+            item_id
+        })
+        .collect();
+    let config = push_property(file, hevc_config(first));
+    let extents = push_property(file, extents_property(tile_size, tile_size));
+    let pixi = push_property(file, ItemProperty::PixelInformation(bits.to_vec()));
+    for &item_id in &tile_ids {
+        associate(file, item_id, config, true);
+        associate(file, item_id, extents, false);
+        associate(file, item_id, pixi, false);
+    }
+
+    // ImageGrid: version, flags (32-bit fields), rows and columns minus one
+    let wide = width > 0xFFFF || height > 0xFFFF;
+    let mut descriptor = vec![0, u8::from(wide), (rows - 1) as u8, (cols - 1) as u8];
+    if wide {
+        descriptor.extend_from_slice(&width.to_be_bytes());
+        descriptor.extend_from_slice(&height.to_be_bytes());
+    } else {
+        descriptor.extend_from_slice(&(width as u16).to_be_bytes());
+        descriptor.extend_from_slice(&(height as u16).to_be_bytes());
+    }
+    let grid_id = file.add_item(FourCC(*b"grid"), "", descriptor);
+    file.item_references.push(ItemReference {
+        reference_type: FourCC::DIMG,
+        from_item_id: grid_id,
+        to_item_ids: tile_ids,
+    });
+    let extents = push_property(file, extents_property(width, height));
+    associate(file, grid_id, extents, false);
+    associate(file, grid_id, pixi, false);
+    if let Some(colr) = colr {
+        let colr = push_property(file, colr);
+        associate(file, grid_id, colr, false);
+    }
+    grid_id
+}
+
+/// Add an hvc1 item holding a coded picture's slice with a 4-byte length
+fn add_coded_item(file: &mut HeifFile, encoded: &EncodedPicture) -> u32 {
+    let mut data = Vec::with_capacity(4 + encoded.slice.len());
+    data.extend_from_slice(&(encoded.slice.len() as u32).to_be_bytes());
+    data.extend_from_slice(&encoded.slice);
+    file.add_item(FourCC::HVC1, "", data)
+}
+
+/// Crop an image item to `width` x `height` with a clean aperture when its
+/// coded size rounded either up to an even number
+fn add_clean_aperture(file: &mut HeifFile, item_id: u32, width: u32, height: u32) {
+    if width.is_multiple_of(2) && height.is_multiple_of(2) {
+        return;
+    }
+    // Offsets of -1/2 keep the left column and top row
+    let clap = ItemProperty::CleanAperture(CleanAperture {
+        width_n: width,
+        width_d: 1,
+        height_n: height,
+        height_d: 1,
+        horiz_off_n: -((width % 2) as i32),
+        horiz_off_d: 2,
+        vert_off_n: -((height % 2) as i32),
+        vert_off_d: 2,
+    });
+    let clap = push_property(file, clap);
+    associate(file, item_id, clap, true);
+}
+
+/// hvcC of a coded picture
+fn hevc_config(encoded: EncodedPicture) -> ItemProperty {
+    ItemProperty::HevcConfig(HevcDecoderConfig {
+        config_version: 1,
+        general_profile_space: 0,
+        general_tier_flag: false,
+        general_profile_idc: PROFILE_IDC_MAIN,
+        general_profile_compatibility_flags: PROFILE_COMPATIBILITY_FLAGS,
+        general_constraint_indicator_flags: CONSTRAINT_INDICATOR_FLAGS,
+        general_level_idc: encoded.level_idc,
+        chroma_format: 1,
+        bit_depth_luma_minus8: 0,
+        bit_depth_chroma_minus8: 0,
+        length_size_minus_one: 3,
+        nal_units: encoded.parameter_sets,
+    })
+}
+
+/// ispe of a `width` x `height` image
+fn extents_property(width: u32, height: u32) -> ItemProperty {
+    ItemProperty::ImageExtents(ImageSpatialExtents { width, height })
+}
+
+/// Add a property, returning its 1-based index
+fn push_property(file: &mut HeifFile, property: ItemProperty) -> u16 {
+    file.properties.push(FileProperty::new(property));
+    file.properties.len() as u16
+}
+
+/// Associate the property at 1-based `index` with an item
+fn associate(file: &mut HeifFile, item_id: u32, index: u16, essential: bool) {
+    match file
+        .property_associations
+        .iter_mut()
+        .find(|a| a.item_id == item_id)
+    {
+        Some(association) => association.properties.push((index, essential)),
+        None => file.property_associations.push(PropertyAssociation {
+            item_id,
+            properties: vec![(index, essential)],
+        }),
+    }
+}
+
+/// Mark an item hidden
+fn set_hidden(file: &mut HeifFile, item_id: u32) {
+    if let Some(info) = file.item_infos.iter_mut().find(|i| i.item_id == item_id) {
+        info.hidden = true;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::DecoderConfig;
+
+    /// RGBA pixels with gradients, edges and a transparent corner
+    pub(crate) fn test_pixels(width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let edge = if (x / 20 + y / 12) % 2 == 0 { 60 } else { 0 };
+                let alpha = if x < 16 && y < 16 { 0 } else { 255 };
+                pixels.extend_from_slice(&[
+                    (40 + x + edge) as u8,
+                    (60 + y + x / 2) as u8,
+                    (200 - y - edge / 2) as u8,
+                    alpha,
+                ]);
+            }
+        }
+        pixels
+    }
+
+    /// Whether the mean squared error is below 255² / 10^(psnr / 10)
+    fn psnr_above(a: &[u8], b: &[u8], min_sse_ratio: u64) -> bool {
+        let sse: u64 = a
+            .iter()
+            .zip(b)
+            .map(|(&x, &y)| u64::from(x.abs_diff(y)).pow(2))
+            .sum();
+        sse * min_sse_ratio < 255 * 255 * a.len() as u64
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        // Odd dimensions, once as a single image and once as a grid
+        let (width, height) = (101, 67);
+        let pixels = test_pixels(width, height);
+        for tile_size in [512, 64] {
+            let config = EncoderConfig::new()
+                .with_quality(90)
+                .with_tile_size(tile_size);
+            let heic = config
+                .encode(&pixels, width, height, PixelLayout::Rgba8)
+                .unwrap();
+            let container = crate::heif::parse(&heic).unwrap();
+            let primary = container.get_item(container.primary_item_id).unwrap();
+            let grid = primary.item_type == crate::heif::ItemType::Grid;
+            assert_eq!(grid, tile_size < width);
+            // pixi on every image item: 3x8 for colour, 1x8 for the alpha
+            // image and its tiles
+            let alpha = crate::alpha_item(&container, primary.id).unwrap();
+            let mut alpha_items = container.get_item_references(alpha, FourCC::DIMG);
+            alpha_items.push(alpha);
+            for info in &container.item_infos {
+                let item = container.get_item(info.item_id).unwrap();
+                let expected: &[u8] = if alpha_items.contains(&item.id) {
+                    &ALPHA_BITS
+                } else {
+                    &RGB_BITS
+                };
+                assert_eq!(item.bits_per_channel.as_deref(), Some(expected));
+            }
+
+            let decoded = DecoderConfig::new()
+                .decode(&heic, PixelLayout::Rgba8)
+                .unwrap();
+            assert_eq!((decoded.width, decoded.height), (width, height));
+            // PSNR above 30 dB
+            assert!(psnr_above(&decoded.data, &pixels, 1000));
+            // Alpha survives: transparent corner, opaque elsewhere
+            assert!(decoded.data[3] < 8);
+            assert!(decoded.data[pixels.len() - 1] > 247);
+        }
+    }
+
+    #[test]
+    fn test_alpha_size_mismatch() {
+        let mut frame = DecodedFrame::new(64, 64);
+        frame.alpha_plane = Some(vec![255; 64 * 63]);
+        let error = EncoderConfig::new().encode_frame(&frame).unwrap_err();
+        assert!(matches!(error.error(), HeicError::InvalidData(_)));
+    }
+}
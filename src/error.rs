@@ -36,6 +36,9 @@ pub enum HeicError {
     LimitExceeded(&'static str),
     /// Operation was cancelled via cooperative cancellation
     Cancelled(StopReason),
+    /// Reading from the source failed
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
 }
 
 impl fmt::Display for HeicError {
@@ -51,6 +54,8 @@ impl fmt::Display for HeicError {
             }
             Self::LimitExceeded(msg) => write!(f, "limit exceeded: {msg}"),
             Self::Cancelled(reason) => write!(f, "{reason}"),
+            #[cfg(feature = "std")]
+            Self::Io(e) => write!(f, "I/O error: {e}"),
         }
     }
 }
@@ -59,6 +64,8 @@ impl core::error::Error for HeicError {
     fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self {
             Self::HevcDecode(e) => Some(e),
+            #[cfg(feature = "std")]
+            Self::Io(e) => Some(e),
             _ => None,
         }
     }
@@ -120,6 +127,8 @@ pub enum HevcError {
     Unsupported(&'static str),
     /// Decoding error
     DecodingError(&'static str),
+    /// The decoded picture does not match its decoded picture hash SEI
+    PictureHashMismatch,
 }
 
 impl fmt::Display for HevcError {
@@ -137,6 +146,7 @@ impl fmt::Display for HevcError {
             }
             Self::Unsupported(msg) => write!(f, "unsupported: {msg}"),
             Self::DecodingError(msg) => write!(f, "decoding error: {msg}"),
+            Self::PictureHashMismatch => write!(f, "decoded picture hash mismatch"),
         }
     }
 }
@@ -148,7 +158,16 @@ impl core::error::Error for HevcError {}
 #[non_exhaustive]
 pub enum ProbeError {
     /// Not enough bytes to parse the header
-    NeedMoreData,
+    ///
+    /// The bytes `offset..offset + len` of the file are needed next; more
+    /// may be asked for once they are available. When probing a prefix of
+    /// the file, pass at least `offset + len` bytes.
+    NeedMoreData {
+        /// Offset of the first byte needed
+        offset: u64,
+        /// Number of bytes needed
+        len: u64,
+    },
     /// Data is not a recognized HEIC/HEIF format
     InvalidFormat,
     /// Header is present but malformed
@@ -158,7 +177,9 @@ pub enum ProbeError {
 impl fmt::Display for ProbeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::NeedMoreData => write!(f, "not enough data to parse header"),
+            Self::NeedMoreData { offset, len } => {
+                write!(f, "not enough data to parse header: need {len} bytes at {offset}")
+            }
             Self::InvalidFormat => write!(f, "not a valid HEIC/HEIF file"),
             Self::Corrupt(e) => write!(f, "corrupt header: {e}"),
         }
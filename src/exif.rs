@@ -0,0 +1,226 @@
+//! Reading and patching tags of EXIF (TIFF) metadata
+
+use alloc::vec::Vec;
+
+/// Orientation tag of IFD0
+const ORIENTATION: u16 = 0x0112;
+/// TIFF SHORT field type
+const SHORT: u16 = 3;
+/// Exif IFD pointer of IFD0
+const EXIF_IFD: u16 = 0x8769;
+/// Maker note tag of the Exif IFD
+const MAKER_NOTE: u16 = 0x927C;
+/// Start of Apple maker notes; a version and a byte order mark follow, then
+/// an IFD at byte 14 with offsets from the start of the note
+const APPLE_MAKER_NOTE: &[u8] = b"Apple iOS\0";
+
+/// Byte order and IFD0 location of a TIFF structure
+struct Tiff {
+    big_endian: bool,
+    ifd0: usize,
+    entries: usize,
+}
+
+impl Tiff {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let big_endian = match data.get(..4)? {
+            b"MM\0*" => true,
+            b"II*\0" => false,
+            _ => return None,
+        };
+        let mut tiff = Self {
+            big_endian,
+            ifd0: 0,
+            entries: 0,
+        };
+        tiff.ifd0 = tiff.u32_at(data, 4)? as usize;
+        tiff.entries = usize::from(tiff.u16_at(data, tiff.ifd0)?);
+        // The entries and the next IFD offset must be present
+        data.get(tiff.ifd0..tiff.entry(tiff.entries) + 4)?;
+        Some(tiff)
+    }
+
+    /// Offset of IFD0 entry `index`
+    fn entry(&self, index: usize) -> usize {
+        self.ifd0 + 2 + 12 * index
+    }
+
+    fn u16_at(&self, data: &[u8], pos: usize) -> Option<u16> {
+        let bytes = data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, data: &[u8], pos: usize) -> Option<u32> {
+        let bytes = data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u16_bytes(&self, value: u16) -> [u8; 2] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    fn u32_bytes(&self, value: u32) -> [u8; 4] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    /// Index of the IFD0 entry with `tag`
+    fn find(&self, data: &[u8], tag: u16) -> Option<usize> {
+        (0..self.entries).find(|&i| self.u16_at(data, self.entry(i)) == Some(tag))
+    }
+
+    /// Offset of the entry with `tag` in the IFD at `ifd`
+    fn find_in(&self, data: &[u8], ifd: usize, tag: u16) -> Option<usize> {
+        let entries = usize::from(self.u16_at(data, ifd)?);
+        (0..entries)
+            .map(|i| ifd + 2 + 12 * i)
+            .find(|&entry| self.u16_at(data, entry) == Some(tag))
+    }
+
+    /// Value of the (S)RATIONAL entry with `tag` in the IFD at `ifd`
+    fn rational_in(&self, data: &[u8], ifd: usize, tag: u16) -> Option<f32> {
+        let offset = self.u32_at(data, self.find_in(data, ifd, tag)? + 8)? as usize;
+        // Signed, which reads unsigned values in range the same
+        let numerator = self.u32_at(data, offset)? as i32;
+        let denominator = self.u32_at(data, offset + 4)? as i32;
+        (denominator != 0).then(|| numerator as f32 / denominator as f32)
+    }
+
+    /// A SHORT entry holding `value`
+    fn short_entry(&self, tag: u16, value: u16) -> [u8; 12] {
+        let mut entry = [0; 12];
+        entry[..2].copy_from_slice(&self.u16_bytes(tag));
+        entry[2..4].copy_from_slice(&self.u16_bytes(SHORT));
+        entry[4..8].copy_from_slice(&self.u32_bytes(1));
+        entry[8..10].copy_from_slice(&self.u16_bytes(value));
+        entry
+    }
+}
+
+/// Copy of EXIF data with its orientation tag set to `value`
+///
+/// The tag is rewritten where it is. When IFD0 has no orientation tag, a
+/// copy of IFD0 with the tag added is appended and the header pointed at
+/// it; every other offset stays valid as nothing else moves. Returns `None`
+/// if the data is not a TIFF structure.
+pub(crate) fn with_orientation(tiff: &[u8], value: u16) -> Option<Vec<u8>> {
+    let parsed = Tiff::parse(tiff)?;
+    let new_entry = parsed.short_entry(ORIENTATION, value);
+    let mut out = tiff.to_vec();
+    if let Some(index) = parsed.find(tiff, ORIENTATION) {
+        let entry = parsed.entry(index);
+        out[entry..entry + 12].copy_from_slice(&new_entry);
+        return Some(out);
+    }
+
+    // Entries are sorted by tag
+    let insert_at = (0..parsed.entries)
+        .find(|&i| parsed.u16_at(tiff, parsed.entry(i)) > Some(ORIENTATION))
+        .unwrap_or(parsed.entries);
+    let entries = u16::try_from(parsed.entries + 1).ok()?;
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let ifd0 = u32::try_from(out.len()).ok()?;
+    out.extend_from_slice(&parsed.u16_bytes(entries));
+    out.extend_from_slice(&tiff[parsed.entry(0)..parsed.entry(insert_at)]);
+    out.extend_from_slice(&new_entry);
+    out.extend_from_slice(&tiff[parsed.entry(insert_at)..parsed.entry(parsed.entries) + 4]);
+    out[4..8].copy_from_slice(&parsed.u32_bytes(ifd0));
+    Some(out)
+}
+
+/// Apple maker note tags 0x0021 and 0x0030 of EXIF data, from which Apple
+/// derives the headroom of its HDR photos
+pub(crate) fn apple_hdr_tags(tiff: &[u8]) -> Option<(f32, f32)> {
+    let parsed = Tiff::parse(tiff)?;
+    let exif_ifd = parsed.find_in(tiff, parsed.ifd0, EXIF_IFD)?;
+    let exif_ifd = parsed.u32_at(tiff, exif_ifd + 8)? as usize;
+    let maker_note = parsed.find_in(tiff, exif_ifd, MAKER_NOTE)?;
+    let len = parsed.u32_at(tiff, maker_note + 4)? as usize;
+    let offset = parsed.u32_at(tiff, maker_note + 8)? as usize;
+    let note = tiff.get(offset..offset.checked_add(len)?)?;
+    if !note.starts_with(APPLE_MAKER_NOTE) {
+        return None;
+    }
+    let apple = Tiff {
+        big_endian: note.get(12..14)? == b"MM",
+        ifd0: 14,
+        entries: 0,
+    };
+    Some((
+        apple.rational_in(note, 14, 0x0021)?,
+        apple.rational_in(note, 14, 0x0030)?,
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The orientation tag of EXIF data starting with the TIFF header
+    pub(crate) fn orientation(tiff: &[u8]) -> Option<u16> {
+        let parsed = Tiff::parse(tiff)?;
+        let entry = parsed.entry(parsed.find(tiff, ORIENTATION)?);
+        parsed.u16_at(tiff, entry + 8)
+    }
+
+    #[test]
+    fn test_set_orientation() {
+        // Little-endian IFD0 with Make (pointing past the IFD) and Model
+        let mut tiff = b"II*\0\x08\0\0\0\x02\0".to_vec();
+        tiff.extend_from_slice(&[0x0F, 0x01, 2, 0, 4, 0, 0, 0, 38, 0, 0, 0]);
+        tiff.extend_from_slice(&[0x10, 0x01, 2, 0, 3, 0, 0, 0, b'X', b'1', 0, 0]);
+        tiff.extend_from_slice(&[0; 4]);
+        tiff.extend_from_slice(b"ACM\0");
+        assert_eq!(orientation(&tiff), None);
+
+        let added = with_orientation(&tiff, 6).unwrap();
+        assert_eq!(orientation(&added), Some(6));
+        assert_eq!(&added[8..tiff.len()], &tiff[8..]);
+        let parsed = Tiff::parse(&added).unwrap();
+        let tags: Vec<_> = (0..parsed.entries)
+            .map(|i| parsed.u16_at(&added, parsed.entry(i)).unwrap())
+            .collect();
+        assert_eq!(tags, [0x010F, 0x0110, ORIENTATION]);
+        assert_eq!(parsed.u32_at(&added, parsed.entry(0) + 8), Some(38));
+
+        let replaced = with_orientation(&added, 3).unwrap();
+        assert_eq!(replaced.len(), added.len());
+        assert_eq!(orientation(&replaced), Some(3));
+        assert_eq!(with_orientation(b"MM\0*", 1), None);
+    }
+
+    #[test]
+    fn test_apple_hdr_tags() {
+        // Big-endian IFD0 with the Exif IFD pointer; the Exif IFD with the
+        // maker note at 40
+        let mut tiff = b"MM\0*\0\0\0\x08\0\x01".to_vec();
+        tiff.extend_from_slice(&[0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 26, 0, 0, 0, 0]);
+        tiff.extend_from_slice(&[0, 1, 0x92, 0x7C, 0, 7, 0, 0, 0, 56, 0, 0, 0, 40]);
+        // Maker note: header, two SRATIONAL entries, their values at 40
+        tiff.extend_from_slice(b"Apple iOS\0\0\x01MM\0\x02");
+        tiff.extend_from_slice(&[0, 0x21, 0, 10, 0, 0, 0, 1, 0, 0, 0, 40]);
+        tiff.extend_from_slice(&[0, 0x30, 0, 10, 0, 0, 0, 1, 0, 0, 0, 48]);
+        tiff.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 100]);
+        assert_eq!(apple_hdr_tags(&tiff), Some((1.5, 0.01)));
+
+        tiff[40] = b'a';
+        assert_eq!(apple_hdr_tags(&tiff), None);
+    }
+}
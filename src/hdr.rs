@@ -0,0 +1,296 @@
+//! HDR reconstruction from an SDR base image and an Apple HDR gain map
+//!
+//! The base image is linearised with the sRGB transfer function, as is the
+//! gain map, and each pixel is scaled by `1 + (headroom - 1) * gain`. For a
+//! display with less headroom than the content the scale is reduced in the
+//! log domain, by the ratio of the two headrooms in stops.
+
+use alloc::vec::Vec;
+use core::f64::consts::LN_2;
+
+use crate::HdrGainMap;
+
+/// Luminance of SDR white in PQ output (ITU-R BT.2408 reference white)
+const SDR_WHITE_NITS: f64 = 203.0;
+/// Scene light of HLG's 75% reference white signal (ITU-R BT.2408)
+const HLG_REFERENCE_WHITE: f64 = 0.264_962_6;
+
+/// Linear-light HDR image reconstructed from an Apple HDR gain map
+///
+/// Made by [`DecoderConfig::decode_hdr`](crate::DecoderConfig::decode_hdr).
+#[derive(Debug, Clone)]
+pub struct HdrImage {
+    /// Interleaved linear RGB, where 1.0 is SDR white, in the primaries of
+    /// the base image (Display P3 for iPhone photos)
+    pub data: Vec<f32>,
+    /// Image width in pixels
+    pub width: u32,
+    /// Image height in pixels
+    pub height: u32,
+    /// Headroom of the content: the gain of a full-strength gain map pixel
+    pub headroom: f32,
+    /// The base image did not match its decoded picture hash SEI (only set
+    /// with [`PictureHashCheck::Warn`](crate::PictureHashCheck::Warn))
+    pub picture_hash_mismatch: bool,
+}
+
+/// Transfer function of 16-bit HDR output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HdrTransfer {
+    /// SMPTE ST 2084 perceptual quantizer, SDR white at 203 cd/m²
+    Pq,
+    /// ARIB STD-B67 hybrid log-gamma, SDR white at the 75% reference
+    /// level, so values above about 3.8 times SDR white are clipped
+    Hlg,
+}
+
+/// Apply a gain map to an SDR image
+///
+/// `sdr` is 16-bit RGB with the sRGB transfer function; the gain map is
+/// resized to the image with bilinear filtering.
+pub(crate) fn reconstruct(
+    sdr: &[u16],
+    width: u32,
+    height: u32,
+    gain_map: &HdrGainMap,
+    headroom: f32,
+    display_headroom: f32,
+) -> HdrImage {
+    let headroom = f64::from(headroom.max(1.0));
+    // Stops of the display over those of the content
+    let weight = if headroom > 1.0 {
+        (log2(f64::from(display_headroom.max(1.0))) / log2(headroom)).min(1.0)
+    } else {
+        0.0
+    };
+
+    let to_linear: Vec<f32> = (0..=u16::MAX)
+        .map(|v| srgb_eotf(f64::from(v) / 65535.0) as f32)
+        .collect();
+    let gain: Vec<f64> = gain_map
+        .data
+        .iter()
+        .map(|&g| srgb_eotf(f64::from(g)))
+        .collect();
+    let (gain_width, gain_height) = (gain_map.width as usize, gain_map.height as usize);
+    let (width, height) = (width as usize, height as usize);
+
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        let (y0, y1, fy) = source_position(y, height, gain_height);
+        for x in 0..width {
+            let (x0, x1, fx) = source_position(x, width, gain_width);
+            let at = |gx: usize, gy: usize| gain.get(gy * gain_width + gx).copied().unwrap_or(0.0);
+            let g = (at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx) * (1.0 - fy)
+                + (at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx) * fy;
+            let full = 1.0 + (headroom - 1.0) * g;
+            let scale = if weight >= 1.0 {
+                full
+            } else {
+                powf(full, weight)
+            } as f32;
+            let pixel = (y * width + x) * 3;
+            for &sample in &sdr[pixel..pixel + 3] {
+                data.push(to_linear[usize::from(sample)] * scale);
+            }
+        }
+    }
+
+    HdrImage {
+        data,
+        width: width as u32,
+        height: height as u32,
+        headroom: headroom as f32,
+        picture_hash_mismatch: false,
+    }
+}
+
+/// Encode linear HDR samples as native-endian 16-bit RGB
+pub(crate) fn encode(image: &HdrImage, transfer: HdrTransfer) -> Vec<u8> {
+    image
+        .data
+        .iter()
+        .flat_map(|&v| {
+            let v = f64::from(v.max(0.0));
+            let signal = match transfer {
+                HdrTransfer::Pq => pq_inverse_eotf(v * SDR_WHITE_NITS / 10000.0),
+                HdrTransfer::Hlg => hlg_oetf(v * HLG_REFERENCE_WHITE),
+            };
+            ((signal.clamp(0.0, 1.0) * 65535.0 + 0.5) as u16).to_ne_bytes()
+        })
+        .collect()
+}
+
+/// Headroom from XMP `HDRGainMap:HDRGainMapHeadroom`, as an attribute or an
+/// element
+pub(crate) fn xmp_headroom(xmp: &[u8]) -> Option<f32> {
+    let xmp = core::str::from_utf8(xmp).ok()?;
+    let (_, rest) = xmp.split_once("HDRGainMap:HDRGainMapHeadroom")?;
+    let rest = rest.trim_start();
+    let value = if let Some(rest) = rest.strip_prefix('=') {
+        let rest = rest.trim_start();
+        let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+        rest[1..].split(quote).next()?
+    } else {
+        rest.strip_prefix('>')?.split('<').next()?
+    };
+    value.trim().parse().ok().filter(|h: &f32| h.is_finite())
+}
+
+/// Headroom from Apple maker note tags 0x0021 and 0x0030, as Apple
+/// documents for its HDR photos
+pub(crate) fn maker_note_headroom(maker33: f32, maker48: f32) -> f32 {
+    let stops = match (maker33 < 1.0, maker48 <= 0.01) {
+        (true, true) => -20.0 * maker48 + 1.8,
+        (true, false) => -0.101 * maker48 + 1.601,
+        (false, true) => -70.0 * maker48 + 3.0,
+        (false, false) => -0.303 * maker48 + 2.303,
+    };
+    exp2(f64::from(stops.max(0.0))) as f32
+}
+
+/// Samples either side of output position `i` when scaling `src` samples to
+/// `dst`, aligning pixel centres, and the weight of the second
+fn source_position(i: usize, dst: usize, src: usize) -> (usize, usize, f64) {
+    let s = ((i as f64 + 0.5) * src as f64 / dst as f64 - 0.5).max(0.0);
+    let s0 = (crate::floor_f64(s) as usize).min(src.saturating_sub(1));
+    (s0, (s0 + 1).min(src.saturating_sub(1)), s - s0 as f64)
+}
+
+fn srgb_eotf(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        powf((v + 0.055) / 1.055, 2.4)
+    }
+}
+
+/// PQ signal of a luminance relative to 10000 cd/m²
+fn pq_inverse_eotf(y: f64) -> f64 {
+    const M1: f64 = 2610.0 / 16384.0;
+    const M2: f64 = 2523.0 / 4096.0 * 128.0;
+    const C1: f64 = 3424.0 / 4096.0;
+    const C2: f64 = 2413.0 / 4096.0 * 32.0;
+    const C3: f64 = 2392.0 / 4096.0 * 32.0;
+    let p = powf(y.min(1.0), M1);
+    powf((C1 + C2 * p) / (1.0 + C3 * p), M2)
+}
+
+/// HLG signal of normalised scene light
+fn hlg_oetf(e: f64) -> f64 {
+    const A: f64 = 0.178_832_77;
+    const B: f64 = 0.284_668_92;
+    const C: f64 = 0.559_910_73;
+    let e = e.min(1.0);
+    if e <= 1.0 / 12.0 {
+        powf(3.0 * e, 0.5)
+    } else {
+        A * log2(12.0 * e - B) * LN_2 + C
+    }
+}
+
+/// `x` to the power `y` for `x >= 0` (the float math of std is not in core)
+fn powf(x: f64, y: f64) -> f64 {
+    if x <= 0.0 { 0.0 } else { exp2(y * log2(x)) }
+}
+
+/// Base-2 logarithm of a positive normal number
+fn log2(x: f64) -> f64 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7FF) as i64 - 1023;
+    let mantissa = f64::from_bits((bits & 0x000F_FFFF_FFFF_FFFF) | 0x3FF0_0000_0000_0000);
+    // ln(m) = 2 atanh(t) with t = (m - 1) / (m + 1) below 1/3
+    let t = (mantissa - 1.0) / (mantissa + 1.0);
+    let t2 = t * t;
+    let (mut term, mut sum) = (t, 0.0);
+    for k in 0..14 {
+        sum += term / f64::from(2 * k + 1);
+        term *= t2;
+    }
+    exponent as f64 + 2.0 * sum / LN_2
+}
+
+/// 2 to the power `x`
+fn exp2(x: f64) -> f64 {
+    let x = x.clamp(-1000.0, 1000.0);
+    let whole = crate::floor_f64(x);
+    // e^(f ln 2) for the fraction f in [0, 1)
+    let y = (x - whole) * LN_2;
+    let (mut term, mut sum) = (1.0, 1.0);
+    for k in 1..18 {
+        term *= y / f64::from(k);
+        sum += term;
+    }
+    let (half, rest) = (whole as i64 / 2, whole as i64 - whole as i64 / 2);
+    // Two factors keep the exponents in range
+    sum * f64::from_bits(((half + 1023) as u64) << 52)
+        * f64::from_bits(((rest + 1023) as u64) << 52)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_functions() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9 * b.abs().max(1.0);
+        assert!(close(exp2(0.5), core::f64::consts::SQRT_2));
+        assert!(close(exp2(-3.25), 0.105_112_051_906_714_31));
+        assert!(close(log2(10.0), core::f64::consts::LOG2_10));
+        assert!(close(powf(0.5, 2.4), 0.189_464_570_813_799_78));
+        assert!(close(srgb_eotf(1.0), 1.0));
+
+        // PQ: 10000 cd/m² is full scale, 100 cd/m² about 0.508
+        assert!(close(pq_inverse_eotf(1.0), 1.0));
+        assert!((pq_inverse_eotf(0.01) - 0.508_078).abs() < 1e-5);
+        assert!((hlg_oetf(HLG_REFERENCE_WHITE) - 0.75).abs() < 1e-4);
+        assert!((hlg_oetf(1.0) - 1.0).abs() < 1e-8);
+        assert!(close(hlg_oetf(1.0 / 12.0), 0.5));
+
+        assert!((maker_note_headroom(0.9, 0.005) - exp2(1.7) as f32).abs() < 1e-5);
+        assert!((maker_note_headroom(1.2, 0.5) - exp2(2.1515) as f32).abs() < 1e-5);
+        assert_eq!(maker_note_headroom(1.2, 20.0), 1.0);
+    }
+
+    #[test]
+    fn test_reconstruct() {
+        let xmp = br#"<rdf:Description HDRGainMap:HDRGainMapVersion="65536"
+            HDRGainMap:HDRGainMapHeadroom="4.000000"/>"#;
+        assert_eq!(xmp_headroom(xmp), Some(4.0));
+        let element = b"<HDRGainMap:HDRGainMapHeadroom>2.5</HDRGainMap:HDRGainMapHeadroom>";
+        assert_eq!(xmp_headroom(element), Some(2.5));
+        assert_eq!(xmp_headroom(b"<x:xmpmeta/>"), None);
+
+        // SDR white everywhere; a 2x1 gain map, none on the left, full on
+        // the right
+        let sdr = alloc::vec![u16::MAX; 4 * 2 * 3];
+        let gain_map = HdrGainMap {
+            data: alloc::vec![0.0, 1.0],
+            width: 2,
+            height: 1,
+        };
+        let full = reconstruct(&sdr, 4, 2, &gain_map, 4.0, 8.0);
+        assert_eq!((full.width, full.height, full.headroom), (4, 2, 4.0));
+        let row: Vec<f32> = full.data[..12].iter().step_by(3).copied().collect();
+        assert_eq!(row, [1.0, 1.75, 3.25, 4.0]);
+        assert_eq!(full.data[12..], full.data[..12]);
+
+        // Half the content's stops on the display
+        let half = reconstruct(&sdr, 4, 2, &gain_map, 4.0, 2.0);
+        assert!((half.data[9] - 2.0).abs() < 1e-5);
+        let sdr_only = reconstruct(&sdr, 4, 2, &gain_map, 4.0, 1.0);
+        assert!(sdr_only.data.iter().all(|&v| v == 1.0));
+
+        let pq = encode(&full, HdrTransfer::Pq);
+        let sample = |data: &[u8], i: usize| u16::from_ne_bytes([data[2 * i], data[2 * i + 1]]);
+        assert_eq!(pq.len(), 4 * 2 * 6);
+        assert_eq!(
+            sample(&pq, 0),
+            (pq_inverse_eotf(0.0203) * 65535.0 + 0.5) as u16
+        );
+        let hlg = encode(&full, HdrTransfer::Hlg);
+        assert!(sample(&hlg, 0).abs_diff(49151) <= 2);
+        assert_eq!(sample(&hlg, 9), u16::MAX);
+    }
+}
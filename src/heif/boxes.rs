@@ -3,6 +3,8 @@
 use alloc::string::String;
 use alloc::vec::Vec;
 
+use crate::hevc::{ContentLightLevel, MasteringDisplayColourVolume};
+
 /// Four-character code identifying a box type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FourCC(pub [u8; 4]);
@@ -36,6 +38,14 @@ impl FourCC {
     pub const HVCB: Self = Self(*b"hvcB");
     /// HEVC decoder configuration
     pub const HVCC: Self = Self(*b"hvcC");
+    /// Layered HEVC decoder configuration
+    pub const LHVC: Self = Self(*b"lhvC");
+    /// Layer selector property
+    pub const LSEL: Self = Self(*b"lsel");
+    /// Target output layer set property
+    pub const TOLS: Self = Self(*b"tols");
+    /// Operating points information property
+    pub const OINF: Self = Self(*b"oinf");
     /// Color information property
     pub const COLR: Self = Self(*b"colr");
     /// Pixel information property
@@ -58,6 +68,66 @@ impl FourCC {
     pub const IMIR: Self = Self(*b"imir");
     /// Thumbnail reference
     pub const THMB: Self = Self(*b"thmb");
+    /// Base layer reference, from a layered image to its lower layers
+    pub const TBAS: Self = Self(*b"tbas");
+    /// Content light level property
+    pub const CLLI: Self = Self(*b"clli");
+    /// Mastering display colour volume property
+    pub const MDCV: Self = Self(*b"mdcv");
+    /// Groups list box
+    pub const GRPL: Self = Self(*b"grpl");
+    /// Alternatives entity group
+    pub const ALTR: Self = Self(*b"altr");
+    /// Stereo pair entity group
+    pub const STER: Self = Self(*b"ster");
+    /// Burst entity group
+    pub const BRST: Self = Self(*b"brst");
+    /// Image pyramid entity group
+    pub const PYMD: Self = Self(*b"pymd");
+    /// Movie box
+    pub const MOOV: Self = Self(*b"moov");
+    /// Movie header box
+    pub const MVHD: Self = Self(*b"mvhd");
+    /// Track box
+    pub const TRAK: Self = Self(*b"trak");
+    /// Track header box
+    pub const TKHD: Self = Self(*b"tkhd");
+    /// Edit box
+    pub const EDTS: Self = Self(*b"edts");
+    /// Edit list box
+    pub const ELST: Self = Self(*b"elst");
+    /// Media box
+    pub const MDIA: Self = Self(*b"mdia");
+    /// Media header box
+    pub const MDHD: Self = Self(*b"mdhd");
+    /// Media information box
+    pub const MINF: Self = Self(*b"minf");
+    /// Sample table box
+    pub const STBL: Self = Self(*b"stbl");
+    /// Sample description box
+    pub const STSD: Self = Self(*b"stsd");
+    /// Sample size box
+    pub const STSZ: Self = Self(*b"stsz");
+    /// Compact sample size box
+    pub const STZ2: Self = Self(*b"stz2");
+    /// Sample-to-chunk box
+    pub const STSC: Self = Self(*b"stsc");
+    /// Chunk offset box (32-bit)
+    pub const STCO: Self = Self(*b"stco");
+    /// Chunk offset box (64-bit)
+    pub const CO64: Self = Self(*b"co64");
+    /// Decoding time-to-sample box
+    pub const STTS: Self = Self(*b"stts");
+    /// Composition time-to-sample box
+    pub const CTTS: Self = Self(*b"ctts");
+    /// Sync sample box
+    pub const STSS: Self = Self(*b"stss");
+    /// HEVC sample entry (parameter sets in the sample entry only)
+    pub const HVC1: Self = Self(*b"hvc1");
+    /// HEVC sample entry (parameter sets may be in-band)
+    pub const HEV1: Self = Self(*b"hev1");
+    /// Coding constraints box
+    pub const CCST: Self = Self(*b"ccst");
 
     /// Create from bytes
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
@@ -107,6 +177,12 @@ impl<'a> Box<'a> {
         self.header.box_type
     }
 
+    /// The whole box, header included, within the `data` it was read from
+    pub(crate) fn raw<'d>(&self, data: &'d [u8]) -> &'d [u8] {
+        let header_size = self.header.size as usize - self.content.len();
+        &data[self.header.content_offset - header_size..][..self.header.size as usize]
+    }
+
     /// Get full box version and flags (for full boxes)
     #[allow(dead_code)]
     pub fn version_flags(&self) -> Option<(u8, u32)> {
@@ -217,7 +293,7 @@ pub struct ItemInfo {
 }
 
 /// Clean aperture from clap box (ISO 14496-12)
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CleanAperture {
     /// Clean aperture width numerator
     pub width_n: u32,
@@ -238,14 +314,14 @@ pub struct CleanAperture {
 }
 
 /// Image rotation from irot box
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ImageRotation {
     /// Rotation angle in degrees counter-clockwise (0, 90, 180, 270)
     pub angle: u16,
 }
 
 /// Image mirror from imir box
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ImageMirror {
     /// Mirror axis: 0 = vertical axis (left-right flip), 1 = horizontal axis (top-bottom flip)
     pub axis: u8,
@@ -289,6 +365,49 @@ pub struct HevcDecoderConfig {
     pub nal_units: Vec<Vec<u8>>,
 }
 
+/// Layered HEVC decoder configuration from lhvC box
+///
+/// Carries the parameter sets of the enhancement layers of an L-HEVC item;
+/// the base layer parameter sets stay in the item's hvcC.
+#[derive(Debug, Clone)]
+pub struct LHevcDecoderConfig {
+    /// Configuration version
+    pub config_version: u8,
+    /// Length size minus one
+    pub length_size_minus_one: u8,
+    /// NAL units (VPS, SPS, PPS, etc.)
+    pub nal_units: Vec<Vec<u8>>,
+}
+
+/// Operating points information from oinf box
+#[derive(Debug, Clone)]
+pub struct OperatingPointsInfo {
+    /// Scalability mask of the bitstream (as in the VPS extension)
+    pub scalability_mask: u16,
+    /// Operating points, one per output layer set
+    pub operating_points: Vec<OperatingPoint>,
+}
+
+/// Operating point of an L-HEVC bitstream
+#[derive(Debug, Clone)]
+pub struct OperatingPoint {
+    /// Output layer set index in the VPS
+    pub output_layer_set_idx: u16,
+    /// Highest temporal ID of the operating point
+    pub max_temporal_id: u8,
+    /// Layers of the operating point
+    pub layers: Vec<OperatingPointLayer>,
+}
+
+/// Layer of an operating point
+#[derive(Debug, Clone, Copy)]
+pub struct OperatingPointLayer {
+    /// nuh_layer_id of the layer
+    pub layer_id: u8,
+    /// Whether the layer is an output layer of the operating point
+    pub is_output_layer: bool,
+}
+
 /// Color information from colr box
 #[derive(Debug, Clone)]
 pub enum ColorInfo {
@@ -309,7 +428,7 @@ pub enum ColorInfo {
 
 /// Ordered transformative property for an item.
 /// HEIF spec requires these be applied in the order listed in ipma.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Transform {
     /// Clean aperture crop (clap)
     CleanAperture(CleanAperture),
@@ -321,11 +440,20 @@ pub enum Transform {
 
 /// Item property (indexed in ipco)
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum ItemProperty {
     /// Image spatial extents (ispe)
     ImageExtents(ImageSpatialExtents),
     /// HEVC decoder config (hvcC)
     HevcConfig(HevcDecoderConfig),
+    /// Layered HEVC decoder config (lhvC)
+    LHevcConfig(LHevcDecoderConfig),
+    /// Layer selector (lsel): nuh_layer_id of the layer to render
+    LayerSelector(u16),
+    /// Target output layer set (tols)
+    TargetOutputLayerSet(u16),
+    /// Operating points information (oinf)
+    OperatingPoints(OperatingPointsInfo),
     /// Color info (colr)
     ColorInfo(ColorInfo),
     /// Clean aperture (clap)
@@ -336,6 +464,12 @@ pub enum ItemProperty {
     Mirror(ImageMirror),
     /// Auxiliary type (auxC)
     AuxiliaryType(String),
+    /// Content light level (clli)
+    ContentLightLevel(ContentLightLevel),
+    /// Mastering display colour volume (mdcv)
+    MasteringDisplayColourVolume(MasteringDisplayColourVolume),
+    /// Pixel information (pixi): bits per channel of each channel
+    PixelInformation(Vec<u8>),
     /// Unknown property
     Unknown,
 }
@@ -359,3 +493,170 @@ pub struct ItemReference {
     /// Referenced item IDs
     pub to_item_ids: Vec<u32>,
 }
+
+/// Entity group from grpl box (ISO/IEC 23008-12 6.8)
+#[derive(Debug, Clone)]
+pub struct EntityGroup {
+    /// Grouping type (e.g., "altr", "ster", "brst", "pymd")
+    pub grouping_type: FourCC,
+    /// Group ID
+    pub group_id: u32,
+    /// Member item or track IDs, in listing order
+    pub entity_ids: Vec<u32>,
+    /// Pyramid layout (for "pymd" groups)
+    pub pyramid: Option<ImagePyramid>,
+}
+
+/// Image pyramid layout from a pymd entity group
+#[derive(Debug, Clone)]
+pub struct ImagePyramid {
+    /// Tile width in pixels
+    pub tile_size_x: u16,
+    /// Tile height in pixels
+    pub tile_size_y: u16,
+    /// Layers, one per group entity, in listing order
+    pub layers: Vec<PyramidLayer>,
+}
+
+/// Layer of an image pyramid
+#[derive(Debug, Clone, Copy)]
+pub struct PyramidLayer {
+    /// Item ID of the layer image
+    pub item_id: u32,
+    /// Downsampling factor relative to the full-resolution layer
+    pub layer_binning: u16,
+    /// Number of tiles in each row of the layer minus one
+    pub tiles_in_layer_row_minus1: u16,
+    /// Number of tiles in each column of the layer minus one
+    pub tiles_in_layer_column_minus1: u16,
+}
+
+impl ImagePyramid {
+    /// Size of a layer in pixels, as covered by its tiles
+    pub fn layer_size(&self, layer: &PyramidLayer) -> (u32, u32) {
+        (
+            u32::from(self.tile_size_x) * (u32::from(layer.tiles_in_layer_row_minus1) + 1),
+            u32::from(self.tile_size_y) * (u32::from(layer.tiles_in_layer_column_minus1) + 1),
+        )
+    }
+}
+
+/// Coding constraints from ccst box (ISO/IEC 23008-12 7.2.3)
+#[derive(Debug, Clone, Copy)]
+pub struct CodingConstraints {
+    /// All reference pictures of the track are intra-coded
+    pub all_ref_pics_intra: bool,
+    /// Intra prediction may use inter-coded neighbouring samples
+    pub intra_pred_used: bool,
+    /// Maximum number of reference pictures of any picture (15 = unconstrained)
+    pub max_ref_per_pic: u8,
+}
+
+/// Visual sample entry from stsd (hvc1, hev1, ...)
+#[derive(Debug, Clone)]
+pub struct SampleEntry {
+    /// Sample entry format (e.g., "hvc1")
+    pub format: FourCC,
+    /// Coded width in pixels
+    pub width: u16,
+    /// Coded height in pixels
+    pub height: u16,
+    /// HEVC decoder config (if available)
+    pub hevc_config: Option<HevcDecoderConfig>,
+    /// Color info from colr box (nclx or ICC)
+    pub color_info: Option<ColorInfo>,
+    /// Coding constraints (if available)
+    pub coding_constraints: Option<CodingConstraints>,
+}
+
+/// Sample of a track, resolved from the sample table
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    /// File offset of the sample data
+    pub offset: u64,
+    /// Sample size in bytes
+    pub size: u32,
+    /// Decoding time in media timescale units
+    pub decode_time: u64,
+    /// Duration in media timescale units
+    pub duration: u32,
+    /// Composition time offset in media timescale units
+    pub composition_offset: i32,
+    /// Sync sample (random access point)
+    pub is_sync: bool,
+    /// Index into the track's sample entries (0-based)
+    pub entry_index: u32,
+}
+
+/// Edit list entry from elst box
+#[derive(Debug, Clone, Copy)]
+pub struct EditListEntry {
+    /// Segment duration in movie timescale units
+    pub segment_duration: u64,
+    /// Media start time in media timescale units (-1 = empty edit)
+    pub media_time: i64,
+    /// Media rate as 16.16 fixed point
+    pub media_rate: i32,
+}
+
+/// Track from moov/trak
+#[derive(Debug, Clone)]
+pub struct Track {
+    /// Track ID
+    pub track_id: u32,
+    /// Handler type (e.g., "pict" for image sequences, "vide" for video)
+    pub handler_type: FourCC,
+    /// Track width in pixels (integer part of tkhd width)
+    pub width: u32,
+    /// Track height in pixels (integer part of tkhd height)
+    pub height: u32,
+    /// Movie timescale (ticks per second of edit list durations)
+    pub movie_timescale: u32,
+    /// Media timescale (ticks per second of sample times)
+    pub timescale: u32,
+    /// Sample entries from stsd
+    pub sample_entries: Vec<SampleEntry>,
+    /// Samples in decoding order
+    pub samples: Vec<Sample>,
+    /// Edit list (empty when the track has no elst)
+    pub edits: Vec<EditListEntry>,
+}
+
+impl Track {
+    /// Whether this is an HEVC image sequence or video track
+    pub fn is_hevc_sequence(&self) -> bool {
+        matches!(&self.handler_type.0, b"pict" | b"vide")
+            && self
+                .sample_entries
+                .iter()
+                .any(|e| e.format == FourCC::HVC1 || e.format == FourCC::HEV1)
+    }
+
+    /// Offset from composition time to presentation time, in media timescale units
+    ///
+    /// Leading empty edits delay the presentation; the first media edit maps
+    /// its `media_time` to the start of that edit.
+    pub fn presentation_offset(&self) -> i64 {
+        let mut offset = 0i64;
+        for edit in &self.edits {
+            if edit.media_time == -1 {
+                if self.movie_timescale != 0 {
+                    let ticks = i128::from(edit.segment_duration) * i128::from(self.timescale)
+                        / i128::from(self.movie_timescale);
+                    offset = offset.saturating_add(i64::try_from(ticks).unwrap_or(i64::MAX));
+                }
+            } else {
+                return offset.saturating_sub(edit.media_time);
+            }
+        }
+        offset
+    }
+
+    /// Presentation time of a sample in media timescale units
+    pub fn presentation_time(&self, sample: &Sample) -> i64 {
+        i64::try_from(sample.decode_time)
+            .unwrap_or(i64::MAX)
+            .saturating_add(i64::from(sample.composition_offset))
+            .saturating_add(self.presentation_offset())
+    }
+}
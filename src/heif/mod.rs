@@ -6,9 +6,16 @@
 
 mod boxes;
 mod parser;
+pub(crate) mod subset;
+mod track;
+pub(crate) mod writer;
 
 pub use boxes::{
-    CleanAperture, ColorInfo, FourCC, HevcDecoderConfig, ImageMirror, ImageRotation,
-    ImageSpatialExtents, ItemProperty, Transform,
+    CleanAperture, CodingConstraints, ColorInfo, EditListEntry, EntityGroup, FourCC,
+    HevcDecoderConfig, ImageMirror, ImagePyramid, ImageRotation, ImageSpatialExtents, ItemInfo,
+    ItemLocation, ItemProperty, ItemReference, LHevcDecoderConfig, OperatingPoint,
+    OperatingPointLayer, OperatingPointsInfo, PropertyAssociation, PyramidLayer, Sample,
+    SampleEntry, Track, Transform,
 };
 pub use parser::{HeifContainer, Item, ItemType, parse};
+pub use writer::{FileProperty, HeifFile};
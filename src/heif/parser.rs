@@ -5,11 +5,14 @@ use alloc::vec::Vec;
 use core::str;
 
 use super::boxes::{
-    Box, BoxIterator, CleanAperture, ColorInfo, FourCC, HevcDecoderConfig, ImageMirror,
-    ImageRotation, ImageSpatialExtents, ItemInfo, ItemLocation, ItemProperty, ItemReference,
-    PropertyAssociation, Transform,
+    Box, BoxIterator, CleanAperture, ColorInfo, EntityGroup, FourCC, HevcDecoderConfig,
+    ImageMirror, ImagePyramid, ImageRotation, ImageSpatialExtents, ItemInfo, ItemLocation,
+    ItemProperty, ItemReference, LHevcDecoderConfig, OperatingPoint, OperatingPointLayer,
+    OperatingPointsInfo, PropertyAssociation, PyramidLayer, Sample, Track, Transform,
 };
+use super::track::parse_moov;
 use crate::error::{HeicError, Result};
+use crate::hevc::{ContentLightLevel, MasteringDisplayColourVolume};
 
 /// Parsed HEIF container
 #[derive(Debug)]
@@ -28,6 +31,8 @@ pub struct HeifContainer<'a> {
     pub item_infos: Vec<ItemInfo>,
     /// Item properties in order (1-based indexing in ipma, 0-based here)
     pub properties: Vec<ItemProperty>,
+    /// Raw boxes of the item properties, header included, in the same order
+    property_boxes: Vec<&'a [u8]>,
     /// Image spatial extents (indexed by property index) - DEPRECATED, use properties
     pub image_extents: Vec<ImageSpatialExtents>,
     /// HEVC decoder configs (indexed by property index) - DEPRECATED, use properties
@@ -38,6 +43,10 @@ pub struct HeifContainer<'a> {
     pub property_associations: Vec<PropertyAssociation>,
     /// Item references (from iref box)
     pub item_references: Vec<ItemReference>,
+    /// Entity groups (from grpl box)
+    pub entity_groups: Vec<EntityGroup>,
+    /// Tracks (from moov box)
+    pub tracks: Vec<Track>,
     /// Item data (from idat box inside meta)
     idat_data: Option<&'a [u8]>,
     /// Media data offset
@@ -51,6 +60,8 @@ pub struct HeifContainer<'a> {
 pub enum ItemType {
     /// HEVC coded image
     Hvc1,
+    /// Layered HEVC coded image
+    Lhv1,
     /// Image grid
     Grid,
     /// Image overlay
@@ -69,6 +80,7 @@ impl From<FourCC> for ItemType {
     fn from(fourcc: FourCC) -> Self {
         match &fourcc.0 {
             b"hvc1" => Self::Hvc1,
+            b"lhv1" => Self::Lhv1,
             b"grid" => Self::Grid,
             b"iovl" => Self::Iovl,
             b"iden" => Self::Iden,
@@ -105,6 +117,20 @@ pub struct Item {
     pub color_info: Option<ColorInfo>,
     /// Auxiliary type URI (from auxC property, e.g. "urn:mpeg:hevc:2015:auxid:1" for alpha)
     pub auxiliary_type: Option<String>,
+    /// Layered HEVC config (if available)
+    pub lhevc_config: Option<LHevcDecoderConfig>,
+    /// Layer to render, from the lsel property (if available)
+    pub layer_selector: Option<u16>,
+    /// Target output layer set, from the tols property (if available)
+    pub target_output_layer_set: Option<u16>,
+    /// Operating points information, from the oinf property (if available)
+    pub operating_points: Option<OperatingPointsInfo>,
+    /// Content light level, from the clli property (if available)
+    pub content_light_level: Option<ContentLightLevel>,
+    /// Mastering display colour volume, from the mdcv property (if available)
+    pub mastering_display_colour_volume: Option<MasteringDisplayColourVolume>,
+    /// Bits per channel, from the pixi property (if available)
+    pub bits_per_channel: Option<Vec<u8>>,
 }
 
 impl<'a> HeifContainer<'a> {
@@ -131,6 +157,13 @@ impl<'a> HeifContainer<'a> {
         let mut transforms = Vec::new();
         let mut color_info = None;
         let mut auxiliary_type = None;
+        let mut lhevc_config = None;
+        let mut layer_selector = None;
+        let mut target_output_layer_set = None;
+        let mut operating_points = None;
+        let mut content_light_level = None;
+        let mut mastering_display_colour_volume = None;
+        let mut bits_per_channel = None;
 
         if let Some(assoc) = assoc {
             for &(prop_idx, _essential) in &assoc.properties {
@@ -164,6 +197,27 @@ impl<'a> HeifContainer<'a> {
                         ItemProperty::AuxiliaryType(s) => {
                             auxiliary_type = Some(s.clone());
                         }
+                        ItemProperty::LHevcConfig(config) => {
+                            lhevc_config = Some(config.clone());
+                        }
+                        ItemProperty::LayerSelector(layer_id) => {
+                            layer_selector = Some(*layer_id);
+                        }
+                        ItemProperty::TargetOutputLayerSet(ols_idx) => {
+                            target_output_layer_set = Some(*ols_idx);
+                        }
+                        ItemProperty::OperatingPoints(oinf) => {
+                            operating_points = Some(oinf.clone());
+                        }
+                        ItemProperty::ContentLightLevel(clli) => {
+                            content_light_level = Some(*clli);
+                        }
+                        ItemProperty::MasteringDisplayColourVolume(mdcv) => {
+                            mastering_display_colour_volume = Some(*mdcv);
+                        }
+                        ItemProperty::PixelInformation(bits) => {
+                            bits_per_channel = Some(bits.clone());
+                        }
                         _ => {}
                     }
                 }
@@ -182,6 +236,13 @@ impl<'a> HeifContainer<'a> {
             transforms,
             color_info,
             auxiliary_type,
+            lhevc_config,
+            layer_selector,
+            target_output_layer_set,
+            operating_points,
+            content_light_level,
+            mastering_display_colour_volume,
+            bits_per_channel,
         })
     }
 
@@ -223,6 +284,44 @@ impl<'a> HeifContainer<'a> {
         }
     }
 
+    /// Get the data of an item with all of its extents concatenated
+    ///
+    /// Unlike [`get_item_data`](Self::get_item_data), which returns only the
+    /// first extent without copying, this copies every extent.
+    pub fn read_item_data(&self, item_id: u32) -> Option<Vec<u8>> {
+        let loc = self.item_locations.iter().find(|l| l.item_id == item_id)?;
+        let source = match loc.construction_method {
+            0 => self.data,
+            1 => self.idat_data?,
+            _ => return None,
+        };
+
+        let mut data = Vec::new();
+        for &(offset, length) in &loc.extents {
+            let offset = usize::try_from(loc.base_offset.checked_add(offset)?).ok()?;
+            let length = usize::try_from(length).ok()?;
+            data.extend_from_slice(source.get(offset..offset.checked_add(length)?)?);
+        }
+        Some(data)
+    }
+
+    /// Get the raw box of a property, header included, by 0-based index
+    pub fn property_box(&self, index: usize) -> Option<&'a [u8]> {
+        self.property_boxes.get(index).copied()
+    }
+
+    /// Get the first HEVC image sequence track
+    pub fn sequence_track(&self) -> Option<&Track> {
+        self.tracks.iter().find(|t| t.is_hevc_sequence())
+    }
+
+    /// Get raw data for a track sample
+    pub fn get_sample_data(&self, sample: &Sample) -> Option<&'a [u8]> {
+        let offset = usize::try_from(sample.offset).ok()?;
+        let end = offset.checked_add(sample.size as usize)?;
+        self.data.get(offset..end)
+    }
+
     /// Find auxiliary items that reference a given target item, filtered by aux type prefix.
     ///
     /// `auxl` references point FROM the auxiliary item TO the primary item.
@@ -266,6 +365,44 @@ impl<'a> HeifContainer<'a> {
             .map(|r| r.from_item_id)
             .collect()
     }
+
+    /// Get the entity groups of a grouping type
+    pub fn groups_of_type(&self, grouping_type: FourCC) -> impl Iterator<Item = &EntityGroup> {
+        self.entity_groups
+            .iter()
+            .filter(move |g| g.grouping_type == grouping_type)
+    }
+
+    /// Get the alternatives of an item in preference order
+    ///
+    /// Returns the members of the first `altr` group containing the item, or
+    /// just the item itself if it has no alternatives.
+    pub fn alternatives(&self, item_id: u32) -> Vec<u32> {
+        self.groups_of_type(FourCC::ALTR)
+            .find(|g| g.entity_ids.contains(&item_id))
+            .map_or_else(|| alloc::vec![item_id], |g| g.entity_ids.clone())
+    }
+
+    /// Get the (left, right) item IDs of the first stereo pair (`ster` group)
+    pub fn stereo_pair(&self) -> Option<(u32, u32)> {
+        self.groups_of_type(FourCC::STER)
+            .find_map(|g| match g.entity_ids[..] {
+                [left, right, ..] => Some((left, right)),
+                _ => None,
+            })
+    }
+
+    /// Get the burst groups (`brst`) an item belongs to
+    pub fn burst_groups(&self, item_id: u32) -> impl Iterator<Item = &EntityGroup> {
+        self.groups_of_type(FourCC::BRST)
+            .filter(move |g| g.entity_ids.contains(&item_id))
+    }
+
+    /// Get the first image pyramid (`pymd` group)
+    pub fn image_pyramid(&self) -> Option<&ImagePyramid> {
+        self.groups_of_type(FourCC::PYMD)
+            .find_map(|g| g.pyramid.as_ref())
+    }
 }
 
 /// Parse a HEIF container
@@ -278,11 +415,14 @@ pub fn parse(data: &[u8]) -> Result<HeifContainer<'_>> {
         item_locations: Vec::new(),
         item_infos: Vec::new(),
         properties: Vec::new(),
+        property_boxes: Vec::new(),
         image_extents: Vec::new(),
         hevc_configs: Vec::new(),
         color_infos: Vec::new(),
         property_associations: Vec::new(),
         item_references: Vec::new(),
+        entity_groups: Vec::new(),
+        tracks: Vec::new(),
         idat_data: None,
         mdat_offset: None,
         mdat_length: None,
@@ -293,6 +433,7 @@ pub fn parse(data: &[u8]) -> Result<HeifContainer<'_>> {
         match top_box.box_type() {
             FourCC::FTYP => parse_ftyp(&top_box, &mut container)?,
             FourCC::META => parse_meta(&top_box, &mut container)?,
+            FourCC::MOOV => container.tracks = parse_moov(&top_box, data.len())?,
             FourCC::MDAT => {
                 container.mdat_offset = Some(top_box.header.content_offset);
                 container.mdat_length = Some(top_box.content.len());
@@ -364,6 +505,7 @@ fn parse_meta<'a>(meta: &Box<'a>, container: &mut HeifContainer<'a>) -> Result<(
             FourCC::IINF => parse_iinf(&child, container)?,
             FourCC::IPRP => parse_iprp(&child, container)?,
             FourCC::IREF => parse_iref(&child, container)?,
+            FourCC::GRPL => parse_grpl(&child, container)?,
             FourCC::IDAT => {
                 container.idat_data = Some(child.content);
             }
@@ -374,6 +516,65 @@ fn parse_meta<'a>(meta: &Box<'a>, container: &mut HeifContainer<'a>) -> Result<(
     Ok(())
 }
 
+fn parse_grpl(grpl: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
+    for child in BoxIterator::new(grpl.content) {
+        // EntityToGroupBox: full box header, group_id, num_entities_in_group
+        let content = child.content;
+        if content.len() < 12 {
+            return Err(HeicError::InvalidContainer("entity group too short").into());
+        }
+        let group_id = u32::from_be_bytes([content[4], content[5], content[6], content[7]]);
+        let num_entities =
+            u32::from_be_bytes([content[8], content[9], content[10], content[11]]) as usize;
+        let ids = &content[12..];
+        if num_entities > ids.len() / 4 {
+            return Err(HeicError::InvalidContainer("entity group too short").into());
+        }
+        let entity_ids: Vec<u32> = ids
+            .chunks_exact(4)
+            .take(num_entities)
+            .map(|id| u32::from_be_bytes([id[0], id[1], id[2], id[3]]))
+            .collect();
+
+        let pyramid = if child.box_type() == FourCC::PYMD {
+            Some(parse_pymd(&ids[num_entities * 4..], &entity_ids)?)
+        } else {
+            None
+        };
+
+        container.entity_groups.push(EntityGroup {
+            grouping_type: child.box_type(),
+            group_id,
+            entity_ids,
+            pyramid,
+        });
+    }
+    Ok(())
+}
+
+/// Parse the pymd fields following the entity IDs
+fn parse_pymd(content: &[u8], entity_ids: &[u32]) -> Result<ImagePyramid> {
+    if content.len() < 4 + entity_ids.len() * 6 {
+        return Err(HeicError::InvalidContainer("pymd too short").into());
+    }
+    let field = |pos: usize| u16::from_be_bytes([content[pos], content[pos + 1]]);
+    let layers = entity_ids
+        .iter()
+        .enumerate()
+        .map(|(i, &item_id)| PyramidLayer {
+            item_id,
+            layer_binning: field(4 + i * 6),
+            tiles_in_layer_row_minus1: field(6 + i * 6),
+            tiles_in_layer_column_minus1: field(8 + i * 6),
+        })
+        .collect();
+    Ok(ImagePyramid {
+        tile_size_x: field(0),
+        tile_size_y: field(2),
+        layers,
+    })
+}
+
 fn parse_pitm(pitm: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
     let content = pitm.content;
     if content.len() < 4 {
@@ -634,7 +835,7 @@ fn parse_infe(infe: &Box<'_>) -> Result<ItemInfo> {
     })
 }
 
-fn parse_iprp(iprp: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
+fn parse_iprp<'a>(iprp: &Box<'a>, container: &mut HeifContainer<'a>) -> Result<()> {
     for child in BoxIterator::new(iprp.content) {
         match child.box_type() {
             FourCC::IPCO => parse_ipco(&child, container)?,
@@ -645,7 +846,7 @@ fn parse_iprp(iprp: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
     Ok(())
 }
 
-fn parse_ipco(ipco: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
+fn parse_ipco<'a>(ipco: &Box<'a>, container: &mut HeifContainer<'a>) -> Result<()> {
     // Properties are stored in order - index is implicit (1-based in ipma, 0-based here)
     for child in BoxIterator::new(ipco.content) {
         let prop = match child.box_type() {
@@ -665,6 +866,34 @@ fn parse_ipco(ipco: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
                     ItemProperty::Unknown
                 }
             }
+            FourCC::LHVC => {
+                if let Ok(config) = parse_lhvc(&child) {
+                    ItemProperty::LHevcConfig(config)
+                } else {
+                    ItemProperty::Unknown
+                }
+            }
+            FourCC::LSEL => {
+                if let Ok(layer_id) = parse_lsel(&child) {
+                    ItemProperty::LayerSelector(layer_id)
+                } else {
+                    ItemProperty::Unknown
+                }
+            }
+            FourCC::TOLS => {
+                if let Ok(ols_idx) = parse_tols(&child) {
+                    ItemProperty::TargetOutputLayerSet(ols_idx)
+                } else {
+                    ItemProperty::Unknown
+                }
+            }
+            FourCC::OINF => {
+                if let Ok(oinf) = parse_oinf(&child) {
+                    ItemProperty::OperatingPoints(oinf)
+                } else {
+                    ItemProperty::Unknown
+                }
+            }
             FourCC::COLR => {
                 if let Ok(color) = parse_colr(&child) {
                     container.color_infos.push(color.clone()); // Keep deprecated for now
@@ -694,6 +923,13 @@ fn parse_ipco(ipco: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
                     ItemProperty::Unknown
                 }
             }
+            FourCC::PIXI => {
+                if let Ok(bits) = parse_pixi(&child) {
+                    ItemProperty::PixelInformation(bits)
+                } else {
+                    ItemProperty::Unknown
+                }
+            }
             FourCC::AUXC => {
                 if let Ok(aux_type) = parse_auxc(&child) {
                     ItemProperty::AuxiliaryType(aux_type)
@@ -701,9 +937,24 @@ fn parse_ipco(ipco: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
                     ItemProperty::Unknown
                 }
             }
+            FourCC::CLLI => {
+                if let Ok(clli) = parse_clli(&child) {
+                    ItemProperty::ContentLightLevel(clli)
+                } else {
+                    ItemProperty::Unknown
+                }
+            }
+            FourCC::MDCV => {
+                if let Ok(mdcv) = parse_mdcv(&child) {
+                    ItemProperty::MasteringDisplayColourVolume(mdcv)
+                } else {
+                    ItemProperty::Unknown
+                }
+            }
             _ => ItemProperty::Unknown,
         };
         container.properties.push(prop);
+        container.property_boxes.push(child.raw(ipco.content));
     }
 
     Ok(())
@@ -779,6 +1030,55 @@ fn parse_auxc(auxc: &Box<'_>) -> Result<String> {
     Ok(aux_type)
 }
 
+fn parse_pixi(pixi: &Box<'_>) -> Result<Vec<u8>> {
+    let content = pixi.content;
+    // pixi is a full box: version/flags (4 bytes), num_channels, then the
+    // bits per channel of each channel
+    let num_channels = *content
+        .get(4)
+        .ok_or(HeicError::InvalidContainer("pixi too short"))? as usize;
+    let bits = content
+        .get(5..5 + num_channels)
+        .ok_or(HeicError::InvalidContainer("pixi too short"))?;
+    Ok(bits.to_vec())
+}
+
+fn parse_clli(clli: &Box<'_>) -> Result<ContentLightLevel> {
+    let content = clli.content;
+    // clli box: MaxCLL and MaxFALL, 2 bytes each (no version/flags)
+    if content.len() < 4 {
+        return Err(HeicError::InvalidContainer("clli too short").into());
+    }
+    Ok(ContentLightLevel {
+        max_content_light_level: u16::from_be_bytes([content[0], content[1]]),
+        max_pic_average_light_level: u16::from_be_bytes([content[2], content[3]]),
+    })
+}
+
+fn parse_mdcv(mdcv: &Box<'_>) -> Result<MasteringDisplayColourVolume> {
+    let content = mdcv.content;
+    // mdcv box: 3 primaries and the white point as (x, y) u16 pairs, then
+    // max and min luminance as u32 (no version/flags)
+    if content.len() < 24 {
+        return Err(HeicError::InvalidContainer("mdcv too short").into());
+    }
+    let u16_at = |pos: usize| u16::from_be_bytes([content[pos], content[pos + 1]]);
+    let u32_at = |pos: usize| {
+        u32::from_be_bytes([
+            content[pos],
+            content[pos + 1],
+            content[pos + 2],
+            content[pos + 3],
+        ])
+    };
+    Ok(MasteringDisplayColourVolume {
+        display_primaries: [0, 4, 8].map(|pos| (u16_at(pos), u16_at(pos + 2))),
+        white_point: (u16_at(12), u16_at(14)),
+        max_luminance: u32_at(16),
+        min_luminance: u32_at(20),
+    })
+}
+
 fn parse_ispe(ispe: &Box<'_>) -> Result<ImageSpatialExtents> {
     let content = ispe.content;
     if content.len() < 12 {
@@ -792,7 +1092,7 @@ fn parse_ispe(ispe: &Box<'_>) -> Result<ImageSpatialExtents> {
     Ok(ImageSpatialExtents { width, height })
 }
 
-fn parse_hvcc(hvcc: &Box<'_>) -> Result<HevcDecoderConfig> {
+pub(super) fn parse_hvcc(hvcc: &Box<'_>) -> Result<HevcDecoderConfig> {
     let content = hvcc.content;
     if content.len() < 23 {
         return Err(HeicError::InvalidContainer("hvcC too short").into());
@@ -823,8 +1123,48 @@ fn parse_hvcc(hvcc: &Box<'_>) -> Result<HevcDecoderConfig> {
     // Skip avgFrameRate (2 bytes)
     let length_size_minus_one = content[21] & 0x3;
 
-    let num_arrays = content[22];
-    let mut pos = 23;
+    let nal_units = parse_nal_unit_arrays(content, 22);
+
+    Ok(HevcDecoderConfig {
+        config_version,
+        general_profile_space,
+        general_tier_flag,
+        general_profile_idc,
+        general_profile_compatibility_flags,
+        general_constraint_indicator_flags,
+        general_level_idc,
+        chroma_format,
+        bit_depth_luma_minus8,
+        bit_depth_chroma_minus8,
+        length_size_minus_one,
+        nal_units,
+    })
+}
+
+fn parse_lhvc(lhvc: &Box<'_>) -> Result<LHevcDecoderConfig> {
+    let content = lhvc.content;
+    if content.len() < 6 {
+        return Err(HeicError::InvalidContainer("lhvC too short").into());
+    }
+
+    let config_version = content[0];
+    // Skip min_spatial_segmentation_idc (2 bytes)
+    // Skip parallelismType (1 byte)
+    // Skip numTemporalLayers and temporalIdNested
+    let length_size_minus_one = content[4] & 0x3;
+    let nal_units = parse_nal_unit_arrays(content, 5);
+
+    Ok(LHevcDecoderConfig {
+        config_version,
+        length_size_minus_one,
+        nal_units,
+    })
+}
+
+/// Parse the NAL unit arrays shared by hvcC and lhvC, starting at numOfArrays
+fn parse_nal_unit_arrays(content: &[u8], num_arrays_pos: usize) -> Vec<Vec<u8>> {
+    let num_arrays = content[num_arrays_pos];
+    let mut pos = num_arrays_pos + 1;
     let mut nal_units = Vec::new();
 
     for _ in 0..num_arrays {
@@ -856,23 +1196,87 @@ fn parse_hvcc(hvcc: &Box<'_>) -> Result<HevcDecoderConfig> {
         }
     }
 
-    Ok(HevcDecoderConfig {
-        config_version,
-        general_profile_space,
-        general_tier_flag,
-        general_profile_idc,
-        general_profile_compatibility_flags,
-        general_constraint_indicator_flags,
-        general_level_idc,
-        chroma_format,
-        bit_depth_luma_minus8,
-        bit_depth_chroma_minus8,
-        length_size_minus_one,
-        nal_units,
+    nal_units
+}
+
+fn parse_lsel(lsel: &Box<'_>) -> Result<u16> {
+    let content = lsel.content;
+    // lsel box: 2 bytes layer_id (no version/flags)
+    if content.len() < 2 {
+        return Err(HeicError::InvalidContainer("lsel too short").into());
+    }
+    Ok(u16::from_be_bytes([content[0], content[1]]))
+}
+
+fn parse_tols(tols: &Box<'_>) -> Result<u16> {
+    let content = tols.content;
+    // tols is a full box: version/flags (4 bytes) + 2 bytes target_ols_idx
+    if content.len() < 6 {
+        return Err(HeicError::InvalidContainer("tols too short").into());
+    }
+    Ok(u16::from_be_bytes([content[4], content[5]]))
+}
+
+fn parse_oinf(oinf: &Box<'_>) -> Result<OperatingPointsInfo> {
+    let content = oinf.content;
+    let too_short = || HeicError::InvalidContainer("oinf too short");
+    let bytes = |pos: usize, len: usize| content.get(pos..pos + len).ok_or_else(too_short);
+
+    // Full box: skip version/flags (4 bytes)
+    let head = bytes(4, 3)?;
+    let scalability_mask = u16::from_be_bytes([head[0], head[1]]);
+    let num_profile_tier_level = (head[2] & 0x3F) as usize;
+    // Each profile/tier/level entry is 12 bytes
+    let mut pos = 7 + num_profile_tier_level * 12;
+
+    let count = bytes(pos, 2)?;
+    let num_operating_points = u16::from_be_bytes([count[0], count[1]]);
+    pos += 2;
+
+    let mut operating_points = Vec::new();
+    for _ in 0..num_operating_points {
+        let op = bytes(pos, 4)?;
+        let output_layer_set_idx = u16::from_be_bytes([op[0], op[1]]);
+        let max_temporal_id = op[2];
+        let layer_count = op[3] as usize;
+        pos += 4;
+
+        // Per layer: ptl_idx (1 byte), layer_id (6 bits), is_outputlayer,
+        // is_alternate_outputlayer
+        let layers = bytes(pos, layer_count * 2)?
+            .chunks_exact(2)
+            .map(|layer| OperatingPointLayer {
+                layer_id: layer[1] >> 2,
+                is_output_layer: layer[1] & 0x02 != 0,
+            })
+            .collect();
+        pos += layer_count * 2;
+
+        // Skip min/max picture dimensions (8 bytes); then chroma format,
+        // bit depth and the frame rate / bit rate info flags
+        let flags = bytes(pos + 8, 1)?[0];
+        pos += 9;
+        if flags & 0x02 != 0 {
+            pos += 3; // avgFrameRate, constantFrameRate
+        }
+        if flags & 0x01 != 0 {
+            pos += 8; // maxBitRate, avgBitRate
+        }
+
+        operating_points.push(OperatingPoint {
+            output_layer_set_idx,
+            max_temporal_id,
+            layers,
+        });
+    }
+
+    Ok(OperatingPointsInfo {
+        scalability_mask,
+        operating_points,
     })
 }
 
-fn parse_colr(colr: &Box<'_>) -> Result<ColorInfo> {
+pub(super) fn parse_colr(colr: &Box<'_>) -> Result<ColorInfo> {
     let content = colr.content;
     if content.len() < 4 {
         return Err(HeicError::InvalidContainer("colr too short").into());
@@ -1045,3 +1449,78 @@ fn parse_ipma(ipma: &Box<'_>, container: &mut HeifContainer<'_>) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heif::writer::write_box;
+
+    /// A file with `ftyp` and a `meta` box holding only `grpl` with `groups`
+    fn file_with_groups(groups: &[u8]) -> Vec<u8> {
+        let mut file = Vec::new();
+        write_box(&mut file, FourCC::FTYP, b"heic\0\0\0\0mif1heic");
+        let mut meta = alloc::vec![0, 0, 0, 0];
+        write_box(&mut meta, FourCC::GRPL, groups);
+        write_box(&mut file, FourCC::META, &meta);
+        file
+    }
+
+    /// EntityToGroupBox with version/flags, group ID, entity IDs and `extra`
+    /// fields
+    fn entity_group(grouping_type: FourCC, group_id: u32, ids: &[u32], extra: &[u16]) -> Vec<u8> {
+        let mut content = alloc::vec![0, 0, 0, 0];
+        content.extend_from_slice(&group_id.to_be_bytes());
+        content.extend_from_slice(&(ids.len() as u32).to_be_bytes());
+        content.extend(ids.iter().flat_map(|id| id.to_be_bytes()));
+        content.extend(extra.iter().flat_map(|field| field.to_be_bytes()));
+        let mut group = Vec::new();
+        write_box(&mut group, grouping_type, &content);
+        group
+    }
+
+    #[test]
+    fn test_parse_grpl() {
+        // Pyramid of 256x128 tiles: item 4 binned by 2 in one tile, item 1
+        // in 2x3 tiles
+        let pymd = [256, 128, 2, 0, 0, 1, 1, 2];
+        let groups = [
+            entity_group(FourCC::STER, 10, &[1, 2], &[]),
+            entity_group(FourCC::ALTR, 11, &[3, 1], &[]),
+            entity_group(FourCC::PYMD, 12, &[4, 1], &pymd),
+        ]
+        .concat();
+        let file = file_with_groups(&groups);
+        let container = parse(&file).unwrap();
+
+        let ids: Vec<_> = container.entity_groups.iter().map(|g| g.group_id).collect();
+        assert_eq!(ids, [10, 11, 12]);
+        assert_eq!(container.stereo_pair(), Some((1, 2)));
+        assert_eq!(container.alternatives(1), [3, 1]);
+        assert_eq!(container.alternatives(2), [2]);
+
+        let pyramid = container.image_pyramid().unwrap();
+        assert_eq!((pyramid.tile_size_x, pyramid.tile_size_y), (256, 128));
+        let layers: Vec<_> = pyramid
+            .layers
+            .iter()
+            .map(|l| {
+                let tiles = (l.tiles_in_layer_row_minus1, l.tiles_in_layer_column_minus1);
+                (l.item_id, l.layer_binning, tiles)
+            })
+            .collect();
+        assert_eq!(layers, [(4, 2, (0, 0)), (1, 1, (1, 2))]);
+    }
+
+    #[test]
+    fn test_parse_grpl_truncated() {
+        // More entities than IDs
+        let mut group = entity_group(FourCC::ALTR, 11, &[3, 1], &[]);
+        group.truncate(group.len() - 4);
+        group[3] -= 4; // box size
+        assert!(parse(&file_with_groups(&group)).is_err());
+
+        // pymd without the fields of its second layer
+        let group = entity_group(FourCC::PYMD, 12, &[4, 1], &[256, 128, 2, 0, 0]);
+        assert!(parse(&file_with_groups(&group)).is_err());
+    }
+}
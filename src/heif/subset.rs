@@ -0,0 +1,187 @@
+//! Self-contained HEIF files holding only the items an operation needs
+//!
+//! Sources that are read piecewise load the `ftyp` and `meta` boxes first,
+//! which describe every item. The items an operation needs are picked with
+//! [`image_items`], [`exif_items`] or [`xmp_items`], their bytes fetched
+//! from the ranges [`item_ranges`] gives, and [`assemble`] rebuilds a small
+//! file from them that the slice-based decoding functions accept unchanged.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::boxes::{BoxIterator, FourCC, ItemLocation};
+use super::parser::HeifContainer;
+use super::writer;
+use crate::error::{HeicError, Result};
+
+/// Items needed to decode image `item_id`: the item, its alternatives, the
+/// images it is derived from, the items holding its lower layers and its
+/// auxiliary images, recursively
+pub(crate) fn image_items(container: &HeifContainer<'_>, item_id: u32) -> Vec<u32> {
+    let mut items = Vec::new();
+    let mut pending = vec![item_id];
+    while let Some(id) = pending.pop() {
+        if items.contains(&id) {
+            continue;
+        }
+        items.push(id);
+        pending.extend(container.alternatives(id));
+        pending.extend(container.get_item_references(id, FourCC::DIMG));
+        pending.extend(container.get_item_references(id, FourCC::TBAS));
+        pending.extend(
+            container
+                .item_references
+                .iter()
+                .filter(|r| r.reference_type == FourCC::AUXL && r.to_item_ids.contains(&id))
+                .map(|r| r.from_item_id),
+        );
+    }
+    items
+}
+
+/// EXIF metadata items
+pub(crate) fn exif_items(container: &HeifContainer<'_>) -> Vec<u32> {
+    container
+        .item_infos
+        .iter()
+        .filter(|i| i.item_type == FourCC(*b"Exif"))
+        .map(|i| i.item_id)
+        .collect()
+}
+
+/// XMP metadata items (`mime` items with an XMP content type)
+pub(crate) fn xmp_items(container: &HeifContainer<'_>) -> Vec<u32> {
+    container
+        .item_infos
+        .iter()
+        .filter(|i| {
+            i.item_type == FourCC(*b"mime")
+                && (i.content_type.contains("xmp") || i.content_type.contains("rdf+xml"))
+        })
+        .map(|i| i.item_id)
+        .collect()
+}
+
+/// File byte ranges (offset, length) holding the data of `item_id`, in order
+///
+/// Returns `None` for items that are not stored in the file body, such as
+/// items in the `meta` box's `idat`, which need no fetching. An extent
+/// length of 0 means the rest of the file.
+pub(crate) fn item_ranges(
+    container: &HeifContainer<'_>,
+    item_id: u32,
+    file_len: u64,
+) -> Result<Option<Vec<(u64, u64)>>> {
+    let Some(loc) = container
+        .item_locations
+        .iter()
+        .find(|l| l.item_id == item_id && l.construction_method == 0)
+    else {
+        return Ok(None);
+    };
+
+    let mut ranges = Vec::with_capacity(loc.extents.len());
+    for &(offset, length) in &loc.extents {
+        let start = loc
+            .base_offset
+            .checked_add(offset)
+            .filter(|&start| start <= file_len)
+            .ok_or(HeicError::InvalidContainer(
+                "item extent beyond end of file",
+            ))?;
+        let length = if length == 0 {
+            file_len - start
+        } else {
+            length
+        };
+        if length > file_len - start {
+            return Err(HeicError::InvalidContainer("item extent beyond end of file").into());
+        }
+        ranges.push((start, length));
+    }
+    Ok(Some(ranges))
+}
+
+/// Build a file from the `ftyp` and `meta` boxes in `header` and the data
+/// of some of its items
+///
+/// `items` pairs item IDs with their data, the concatenation of the ranges
+/// from [`item_ranges`]. The file keeps every box of the original `meta`
+/// but locates only the given items and the items stored in `idat`, each
+/// loaded item as a single extent of a trailing `mdat`.
+pub(crate) fn assemble(
+    header: &[u8],
+    container: &HeifContainer<'_>,
+    items: &[(u32, Vec<u8>)],
+) -> Result<Vec<u8>> {
+    let (mut ftyp, mut meta) = (None, None);
+    for top_box in BoxIterator::new(header) {
+        match top_box.box_type() {
+            FourCC::FTYP if ftyp.is_none() => ftyp = Some(top_box.raw(header)),
+            FourCC::META if meta.is_none() => meta = Some(top_box),
+            _ => {}
+        }
+    }
+    let ftyp = ftyp.ok_or(HeicError::InvalidContainer("missing ftyp box"))?;
+    let meta = meta.ok_or(HeicError::InvalidContainer("missing meta box"))?;
+    if meta.content.len() < 4 {
+        return Err(HeicError::InvalidContainer("meta box too short").into());
+    }
+
+    // Loaded items become consecutive extents of the new mdat; offsets are
+    // relative to its content until the layout is known
+    let mut locations = Vec::with_capacity(container.item_locations.len());
+    let mut data = Vec::new();
+    let mut data_len = 0u64;
+    for loc in &container.item_locations {
+        match loc.construction_method {
+            1 => locations.push(loc.clone()),
+            0 => {
+                if let Some((_, item_data)) = items.iter().find(|(id, _)| *id == loc.item_id) {
+                    locations.push(ItemLocation {
+                        item_id: loc.item_id,
+                        construction_method: 0,
+                        base_offset: 0,
+                        extents: vec![(data_len, item_data.len() as u64)],
+                    });
+                    data.push(item_data.as_slice());
+                    data_len += item_data.len() as u64;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let children = &meta.content[4..];
+    let meta_content_len: usize = BoxIterator::new(children)
+        .map(|child| match child.box_type() {
+            FourCC::ILOC => writer::iloc_size(&locations),
+            _ => child.raw(children).len(),
+        })
+        .sum::<usize>()
+        + 4;
+    let data_start = ftyp.len() as u64
+        + writer::box_size(meta_content_len as u64)
+        + (writer::box_size(data_len) - data_len);
+    for loc in locations.iter_mut().filter(|l| l.construction_method == 0) {
+        loc.extents[0].0 += data_start;
+    }
+
+    let mut meta_content = Vec::with_capacity(meta_content_len);
+    meta_content.extend_from_slice(&meta.content[..4]);
+    for child in BoxIterator::new(children) {
+        match child.box_type() {
+            FourCC::ILOC => writer::write_iloc(&mut meta_content, &locations),
+            _ => meta_content.extend_from_slice(child.raw(children)),
+        }
+    }
+
+    let mut out = Vec::with_capacity(data_start as usize + data_len as usize);
+    out.extend_from_slice(ftyp);
+    writer::write_box(&mut out, FourCC::META, &meta_content);
+    writer::write_box_header(&mut out, FourCC::MDAT, data_len);
+    for item_data in data {
+        out.extend_from_slice(item_data);
+    }
+    Ok(out)
+}
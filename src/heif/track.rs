@@ -0,0 +1,454 @@
+//! Track parser for image sequences (moov/trak, ISO 14496-12 8.3-8.7)
+
+use alloc::vec::Vec;
+
+use super::boxes::{
+    Box, BoxIterator, CodingConstraints, EditListEntry, FourCC, Sample, SampleEntry, Track,
+};
+use super::parser::{parse_colr, parse_hvcc};
+use crate::error::{HeicError, Result};
+
+/// Size of the VisualSampleEntry fields before its child boxes
+const VISUAL_SAMPLE_ENTRY_SIZE: usize = 78;
+
+fn be_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn be_u64(data: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(pos..pos + 8)?.try_into().ok()?))
+}
+
+/// Entry count of a full box table and the table bytes, checked to hold
+/// `entry_size` bytes per entry
+fn table(content: &[u8], header: usize, entry_size: usize) -> Result<(usize, &[u8])> {
+    let count = be_u32(content, header - 4)
+        .ok_or(HeicError::InvalidContainer("table box too short"))? as usize;
+    let entries = &content[header..];
+    if count > entries.len() / entry_size {
+        return Err(HeicError::InvalidContainer("table entry count exceeds box size").into());
+    }
+    Ok((count, entries))
+}
+
+/// Parse all tracks of a moov box
+///
+/// `file_len` bounds the sample count of tables that do not store one entry
+/// per sample.
+pub(super) fn parse_moov(moov: &Box<'_>, file_len: usize) -> Result<Vec<Track>> {
+    let mut movie_timescale = 0;
+    let mut tracks = Vec::new();
+
+    for child in BoxIterator::new(moov.content) {
+        match child.box_type() {
+            FourCC::MVHD => {
+                let pos = if child.content.first() == Some(&1) {
+                    20
+                } else {
+                    12
+                };
+                movie_timescale = be_u32(child.content, pos)
+                    .ok_or(HeicError::InvalidContainer("mvhd too short"))?;
+            }
+            FourCC::TRAK => tracks.push(parse_trak(&child, file_len)?),
+            _ => {}
+        }
+    }
+
+    for track in &mut tracks {
+        track.movie_timescale = movie_timescale;
+    }
+    Ok(tracks)
+}
+
+fn parse_trak(trak: &Box<'_>, file_len: usize) -> Result<Track> {
+    let mut track = Track {
+        track_id: 0,
+        handler_type: FourCC(*b"    "),
+        width: 0,
+        height: 0,
+        movie_timescale: 0,
+        timescale: 0,
+        sample_entries: Vec::new(),
+        samples: Vec::new(),
+        edits: Vec::new(),
+    };
+
+    for child in BoxIterator::new(trak.content) {
+        match child.box_type() {
+            FourCC::TKHD => parse_tkhd(&child, &mut track)?,
+            FourCC::EDTS => {
+                if let Some(elst) =
+                    BoxIterator::new(child.content).find(|b| b.box_type() == FourCC::ELST)
+                {
+                    track.edits = parse_elst(&elst)?;
+                }
+            }
+            FourCC::MDIA => parse_mdia(&child, &mut track, file_len)?,
+            _ => {}
+        }
+    }
+
+    Ok(track)
+}
+
+fn parse_tkhd(tkhd: &Box<'_>, track: &mut Track) -> Result<()> {
+    let content = tkhd.content;
+    // creation/modification times and duration are 64-bit in version 1
+    let (id_pos, size_pos) = if content.first() == Some(&1) {
+        (20, 88)
+    } else {
+        (12, 76)
+    };
+    track.track_id =
+        be_u32(content, id_pos).ok_or(HeicError::InvalidContainer("tkhd too short"))?;
+    // Width and height are 16.16 fixed point
+    track.width = be_u32(content, size_pos).unwrap_or(0) >> 16;
+    track.height = be_u32(content, size_pos + 4).unwrap_or(0) >> 16;
+    Ok(())
+}
+
+fn parse_elst(elst: &Box<'_>) -> Result<Vec<EditListEntry>> {
+    let version = elst.content.first().copied().unwrap_or(0);
+    let entry_size = if version == 1 { 20 } else { 12 };
+    let (count, entries) = table(elst.content, 8, entry_size)?;
+
+    let mut edits = Vec::with_capacity(count);
+    for entry in entries.chunks_exact(entry_size).take(count) {
+        let (segment_duration, media_time, rate_pos) = if version == 1 {
+            (
+                be_u64(entry, 0).unwrap_or(0),
+                be_u64(entry, 8).unwrap_or(0) as i64,
+                16,
+            )
+        } else {
+            (
+                u64::from(be_u32(entry, 0).unwrap_or(0)),
+                i64::from(be_u32(entry, 4).unwrap_or(0) as i32),
+                8,
+            )
+        };
+        edits.push(EditListEntry {
+            segment_duration,
+            media_time,
+            media_rate: be_u32(entry, rate_pos).unwrap_or(0) as i32,
+        });
+    }
+    Ok(edits)
+}
+
+fn parse_mdia(mdia: &Box<'_>, track: &mut Track, file_len: usize) -> Result<()> {
+    for child in BoxIterator::new(mdia.content) {
+        match child.box_type() {
+            FourCC::MDHD => {
+                let pos = if child.content.first() == Some(&1) {
+                    20
+                } else {
+                    12
+                };
+                track.timescale = be_u32(child.content, pos)
+                    .ok_or(HeicError::InvalidContainer("mdhd too short"))?;
+            }
+            FourCC::HDLR => {
+                // version/flags, pre_defined, then handler_type
+                if let Some(handler) = child.content.get(8..12).and_then(FourCC::from_bytes) {
+                    track.handler_type = handler;
+                }
+            }
+            FourCC::MINF => {
+                if let Some(stbl) =
+                    BoxIterator::new(child.content).find(|b| b.box_type() == FourCC::STBL)
+                {
+                    parse_stbl(&stbl, track, file_len)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_stbl(stbl: &Box<'_>, track: &mut Track, file_len: usize) -> Result<()> {
+    let mut sizes: Vec<u32> = Vec::new();
+    let mut chunk_offsets: Vec<u64> = Vec::new();
+    // (first_chunk, samples_per_chunk, sample_description_index)
+    let mut chunk_runs: Vec<(u32, u32, u32)> = Vec::new();
+    // (sample_count, sample_delta)
+    let mut time_runs: Vec<(u32, u32)> = Vec::new();
+    let mut composition_runs: Vec<(u32, i32)> = Vec::new();
+    let mut sync_samples: Option<Vec<u32>> = None;
+
+    for child in BoxIterator::new(stbl.content) {
+        let content = child.content;
+        match child.box_type() {
+            FourCC::STSD => track.sample_entries = parse_stsd(&child)?,
+            FourCC::STSZ => {
+                let sample_size =
+                    be_u32(content, 4).ok_or(HeicError::InvalidContainer("stsz too short"))?;
+                if sample_size == 0 {
+                    let (count, entries) = table(content, 12, 4)?;
+                    sizes = entries
+                        .chunks_exact(4)
+                        .take(count)
+                        .map(|e| be_u32(e, 0).unwrap_or(0))
+                        .collect();
+                } else {
+                    let count = be_u32(content, 8)
+                        .ok_or(HeicError::InvalidContainer("stsz too short"))?
+                        as usize;
+                    // Every sample occupies file bytes, so a larger count is corrupt
+                    if count > file_len / sample_size as usize {
+                        return Err(HeicError::InvalidContainer(
+                            "stsz sample count exceeds file size",
+                        )
+                        .into());
+                    }
+                    sizes = alloc::vec![sample_size; count];
+                }
+            }
+            FourCC::STZ2 => {
+                let field_size = content.get(7).copied().unwrap_or(0);
+                let count = be_u32(content, 8)
+                    .ok_or(HeicError::InvalidContainer("stz2 too short"))?
+                    as usize;
+                let entries = &content[12..];
+                let bits = usize::from(field_size);
+                if !matches!(field_size, 4 | 8 | 16) || count > entries.len() * 8 / bits {
+                    return Err(HeicError::InvalidContainer("invalid stz2").into());
+                }
+                sizes = (0..count)
+                    .map(|i| match field_size {
+                        4 => u32::from((entries[i / 2] >> (4 - 4 * (i % 2))) & 0xF),
+                        8 => u32::from(entries[i]),
+                        _ => u32::from(be_u16(entries, i * 2).unwrap_or(0)),
+                    })
+                    .collect();
+            }
+            FourCC::STCO => {
+                let (count, entries) = table(content, 8, 4)?;
+                chunk_offsets = entries
+                    .chunks_exact(4)
+                    .take(count)
+                    .map(|e| u64::from(be_u32(e, 0).unwrap_or(0)))
+                    .collect();
+            }
+            FourCC::CO64 => {
+                let (count, entries) = table(content, 8, 8)?;
+                chunk_offsets = entries
+                    .chunks_exact(8)
+                    .take(count)
+                    .map(|e| be_u64(e, 0).unwrap_or(0))
+                    .collect();
+            }
+            FourCC::STSC => {
+                let (count, entries) = table(content, 8, 12)?;
+                chunk_runs = entries
+                    .chunks_exact(12)
+                    .take(count)
+                    .map(|e| {
+                        (
+                            be_u32(e, 0).unwrap_or(0),
+                            be_u32(e, 4).unwrap_or(0),
+                            be_u32(e, 8).unwrap_or(0),
+                        )
+                    })
+                    .collect();
+            }
+            FourCC::STTS => {
+                let (count, entries) = table(content, 8, 8)?;
+                time_runs = entries
+                    .chunks_exact(8)
+                    .take(count)
+                    .map(|e| (be_u32(e, 0).unwrap_or(0), be_u32(e, 4).unwrap_or(0)))
+                    .collect();
+            }
+            FourCC::CTTS => {
+                // Version 0 offsets are unsigned, but writers commonly store
+                // negative offsets in them as well
+                let (count, entries) = table(content, 8, 8)?;
+                composition_runs = entries
+                    .chunks_exact(8)
+                    .take(count)
+                    .map(|e| (be_u32(e, 0).unwrap_or(0), be_u32(e, 4).unwrap_or(0) as i32))
+                    .collect();
+            }
+            FourCC::STSS => {
+                let (count, entries) = table(content, 8, 4)?;
+                sync_samples = Some(
+                    entries
+                        .chunks_exact(4)
+                        .take(count)
+                        .map(|e| be_u32(e, 0).unwrap_or(0))
+                        .collect(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    track.samples = build_samples(
+        &sizes,
+        &chunk_offsets,
+        &chunk_runs,
+        &time_runs,
+        &composition_runs,
+        sync_samples.as_deref(),
+    )?;
+    Ok(())
+}
+
+/// Resolve per-sample offsets and times from the sample table
+fn build_samples(
+    sizes: &[u32],
+    chunk_offsets: &[u64],
+    chunk_runs: &[(u32, u32, u32)],
+    time_runs: &[(u32, u32)],
+    composition_runs: &[(u32, i32)],
+    sync_samples: Option<&[u32]>,
+) -> Result<Vec<Sample>> {
+    let mut samples = Vec::with_capacity(sizes.len());
+
+    // Sample offsets: walk the chunks, each run of stsc applying up to the
+    // first chunk of the next run
+    let mut sample_idx = 0usize;
+    for (run_idx, &(first_chunk, samples_per_chunk, description_index)) in
+        chunk_runs.iter().enumerate()
+    {
+        let end_chunk = chunk_runs
+            .get(run_idx + 1)
+            .map_or(chunk_offsets.len() as u64, |next| {
+                u64::from(next.0).saturating_sub(1)
+            });
+        let first_chunk = u64::from(first_chunk.max(1)) - 1;
+        for chunk in first_chunk..end_chunk.min(chunk_offsets.len() as u64) {
+            let mut offset = chunk_offsets[chunk as usize];
+            for _ in 0..samples_per_chunk {
+                let Some(&size) = sizes.get(sample_idx) else {
+                    break;
+                };
+                samples.push(Sample {
+                    offset,
+                    size,
+                    decode_time: 0,
+                    duration: 0,
+                    composition_offset: 0,
+                    is_sync: sync_samples.is_none(),
+                    entry_index: description_index.saturating_sub(1),
+                });
+                offset = offset.saturating_add(u64::from(size));
+                sample_idx += 1;
+            }
+        }
+    }
+    if samples.len() != sizes.len() {
+        return Err(HeicError::InvalidContainer("sample table does not cover all samples").into());
+    }
+
+    let mut time = 0u64;
+    let mut durations = time_runs
+        .iter()
+        .flat_map(|&(count, delta)| core::iter::repeat_n(delta, count as usize));
+    for sample in &mut samples {
+        let duration = durations.next().unwrap_or(0);
+        sample.decode_time = time;
+        sample.duration = duration;
+        time = time.saturating_add(u64::from(duration));
+    }
+
+    let mut offsets = composition_runs
+        .iter()
+        .flat_map(|&(count, offset)| core::iter::repeat_n(offset, count as usize));
+    for sample in &mut samples {
+        sample.composition_offset = offsets.next().unwrap_or(0);
+    }
+
+    for &number in sync_samples.unwrap_or(&[]) {
+        if let Some(sample) = (number as usize)
+            .checked_sub(1)
+            .and_then(|i| samples.get_mut(i))
+        {
+            sample.is_sync = true;
+        }
+    }
+
+    Ok(samples)
+}
+
+fn parse_stsd(stsd: &Box<'_>) -> Result<Vec<SampleEntry>> {
+    if stsd.content.len() < 8 {
+        return Err(HeicError::InvalidContainer("stsd too short").into());
+    }
+
+    let mut entries = Vec::new();
+    for entry in BoxIterator::new(&stsd.content[8..]) {
+        let content = entry.content;
+        let mut sample_entry = SampleEntry {
+            format: entry.box_type(),
+            width: be_u16(content, 24).unwrap_or(0),
+            height: be_u16(content, 26).unwrap_or(0),
+            hevc_config: None,
+            color_info: None,
+            coding_constraints: None,
+        };
+
+        if content.len() >= VISUAL_SAMPLE_ENTRY_SIZE {
+            for child in BoxIterator::new(&content[VISUAL_SAMPLE_ENTRY_SIZE..]) {
+                match child.box_type() {
+                    FourCC::HVCC => sample_entry.hevc_config = parse_hvcc(&child).ok(),
+                    // Keep the first colr box
+                    FourCC::COLR if sample_entry.color_info.is_none() => {
+                        sample_entry.color_info = parse_colr(&child).ok();
+                    }
+                    FourCC::CCST => sample_entry.coding_constraints = parse_ccst(&child),
+                    _ => {}
+                }
+            }
+        }
+        entries.push(sample_entry);
+    }
+
+    Ok(entries)
+}
+
+fn parse_ccst(ccst: &Box<'_>) -> Option<CodingConstraints> {
+    let flags = be_u32(ccst.content, 4)?;
+    Some(CodingConstraints {
+        all_ref_pics_intra: flags >> 31 != 0,
+        intra_pred_used: (flags >> 30) & 1 != 0,
+        max_ref_per_pic: ((flags >> 26) & 0xF) as u8,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_samples() {
+        // Two chunks of 2 and 1 samples, the last using the second entry
+        let samples = build_samples(
+            &[10, 20, 30],
+            &[100, 500],
+            &[(1, 2, 1), (2, 1, 2)],
+            &[(3, 512)],
+            &[(1, 1024), (2, 0)],
+            Some(&[1]),
+        )
+        .unwrap();
+
+        let offsets: Vec<_> = samples.iter().map(|s| s.offset).collect();
+        assert_eq!(offsets, [100, 110, 500]);
+        let times: Vec<_> = samples.iter().map(|s| s.decode_time).collect();
+        assert_eq!(times, [0, 512, 1024]);
+        assert_eq!(samples[0].composition_offset, 1024);
+        assert_eq!(samples[2].entry_index, 1);
+        assert!(samples[0].is_sync && !samples[1].is_sync);
+
+        // Sizes not covered by the chunk table are rejected
+        assert!(build_samples(&[10, 20], &[100], &[(1, 1, 1)], &[], &[], None).is_err());
+    }
+}
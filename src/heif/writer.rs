@@ -0,0 +1,614 @@
+//! ISOBMFF box serialization
+
+use alloc::vec::Vec;
+
+use alloc::string::String;
+use alloc::vec;
+
+use super::boxes::{
+    ColorInfo, EntityGroup, FourCC, HevcDecoderConfig, ItemInfo, ItemLocation, ItemProperty,
+    ItemReference, PropertyAssociation,
+};
+use super::parser::HeifContainer;
+use crate::error::{HeicError, Result};
+
+/// Size of a box with `content_len` bytes of content as [`write_box`] writes it
+pub(crate) fn box_size(content_len: u64) -> u64 {
+    if content_len + 8 <= u64::from(u32::MAX) {
+        content_len + 8
+    } else {
+        content_len + 16
+    }
+}
+
+/// Append the header of a box of `box_type` with `content_len` bytes of
+/// content, using a 64-bit size when the box does not fit a 32-bit one
+pub(crate) fn write_box_header(out: &mut Vec<u8>, box_type: FourCC, content_len: u64) {
+    let size = box_size(content_len);
+    if let Ok(size) = u32::try_from(size) {
+        out.extend_from_slice(&size.to_be_bytes());
+        out.extend_from_slice(&box_type.0);
+    } else {
+        out.extend_from_slice(&1u32.to_be_bytes());
+        out.extend_from_slice(&box_type.0);
+        out.extend_from_slice(&size.to_be_bytes());
+    }
+}
+
+/// Append a box of `box_type` holding `content`
+pub(crate) fn write_box(out: &mut Vec<u8>, box_type: FourCC, content: &[u8]) {
+    write_box_header(out, box_type, content.len() as u64);
+    out.extend_from_slice(content);
+}
+
+/// Size in bytes of the iloc box [`write_iloc`] produces for `locations`
+pub(crate) fn iloc_size(locations: &[ItemLocation]) -> usize {
+    let wide_ids = locations.iter().any(|l| l.item_id > 0xFFFF);
+    let id_size = if wide_ids { 4 } else { 2 };
+    let header = 8 + 4 + 2 + id_size;
+    let entries: usize = locations
+        .iter()
+        .map(|l| id_size + 2 + 2 + 8 + 2 + l.extents.len() * 16)
+        .sum();
+    header + entries
+}
+
+/// Append an iloc box listing `locations`
+///
+/// Written as version 1 (version 2 when an item ID needs 32 bits) with
+/// 64-bit offsets, lengths and base offsets, so the box size does not depend
+/// on the values and can be computed up front with [`iloc_size`].
+pub(crate) fn write_iloc(out: &mut Vec<u8>, locations: &[ItemLocation]) {
+    let wide_ids = locations.iter().any(|l| l.item_id > 0xFFFF);
+    let mut content = Vec::with_capacity(iloc_size(locations) - 8);
+    content.push(if wide_ids { 2 } else { 1 });
+    content.extend_from_slice(&[0, 0, 0]);
+    // offset_size, length_size, base_offset_size (8 bytes each), index_size 0
+    content.extend_from_slice(&[0x88, 0x80]);
+    if wide_ids {
+        content.extend_from_slice(&(locations.len() as u32).to_be_bytes());
+    } else {
+        content.extend_from_slice(&(locations.len() as u16).to_be_bytes());
+    }
+    for loc in locations {
+        if wide_ids {
+            content.extend_from_slice(&loc.item_id.to_be_bytes());
+        } else {
+            content.extend_from_slice(&(loc.item_id as u16).to_be_bytes());
+        }
+        content.extend_from_slice(&u16::from(loc.construction_method & 0xF).to_be_bytes());
+        // Data reference index 0: this file
+        content.extend_from_slice(&[0, 0]);
+        content.extend_from_slice(&loc.base_offset.to_be_bytes());
+        content.extend_from_slice(&(loc.extents.len() as u16).to_be_bytes());
+        for &(offset, length) in &loc.extents {
+            content.extend_from_slice(&offset.to_be_bytes());
+            content.extend_from_slice(&length.to_be_bytes());
+        }
+    }
+    write_box(out, FourCC::ILOC, &content);
+}
+
+/// An item property of a [`HeifFile`]
+#[derive(Debug, Clone)]
+pub struct FileProperty {
+    /// The parsed property
+    pub property: ItemProperty,
+    /// The box the property was read from, written back unchanged; when
+    /// `None` the box is serialized from `property`
+    pub raw: Option<Vec<u8>>,
+}
+
+impl FileProperty {
+    /// A new property, serialized from its parsed form
+    pub fn new(property: ItemProperty) -> Self {
+        Self {
+            property,
+            raw: None,
+        }
+    }
+}
+
+/// Owned contents of a HEIF image file that serialize back to a file
+///
+/// Made from a parsed container with every item's data loaded, edited
+/// through its fields, and written with [`to_bytes`](Self::to_bytes) as
+/// `ftyp`, `meta` and one `mdat` holding all item data. Properties are
+/// written as read unless replaced, and properties no item uses any more
+/// are dropped.
+#[derive(Debug, Clone)]
+pub struct HeifFile {
+    /// File type brand
+    pub brand: FourCC,
+    /// Compatible brands
+    pub compatible_brands: Vec<FourCC>,
+    /// Primary item ID
+    pub primary_item_id: u32,
+    /// Item info entries
+    pub item_infos: Vec<ItemInfo>,
+    /// Item data by item ID, for the items that have any
+    pub item_data: Vec<(u32, Vec<u8>)>,
+    /// Item properties in order (1-based indexing in ipma, 0-based here)
+    pub properties: Vec<FileProperty>,
+    /// Property associations
+    pub property_associations: Vec<PropertyAssociation>,
+    /// Item references
+    pub item_references: Vec<ItemReference>,
+    /// Entity groups
+    pub entity_groups: Vec<EntityGroup>,
+}
+
+impl HeifFile {
+    /// Load every item of a parsed container
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for files with image sequence tracks, whose sample
+    /// offsets cannot be rewritten, or items whose data cannot be read.
+    pub fn from_container(container: &HeifContainer<'_>) -> Result<Self> {
+        if !container.tracks.is_empty() {
+            return Err(HeicError::Unsupported("rewriting image sequence tracks").into());
+        }
+
+        let mut item_data = Vec::with_capacity(container.item_locations.len());
+        for loc in &container.item_locations {
+            let data = container
+                .read_item_data(loc.item_id)
+                .ok_or(HeicError::InvalidContainer("item data out of bounds"))?;
+            item_data.push((loc.item_id, data));
+        }
+        let properties = container
+            .properties
+            .iter()
+            .enumerate()
+            .map(|(index, property)| FileProperty {
+                property: property.clone(),
+                raw: container.property_box(index).map(<[u8]>::to_vec),
+            })
+            .collect();
+
+        Ok(Self {
+            brand: container.brand,
+            compatible_brands: container.compatible_brands.clone(),
+            primary_item_id: container.primary_item_id,
+            item_infos: container.item_infos.clone(),
+            item_data,
+            properties,
+            property_associations: container.property_associations.clone(),
+            item_references: container.item_references.clone(),
+            entity_groups: container.entity_groups.clone(),
+        })
+    }
+
+    /// Data of an item, if it has any
+    pub fn item_data(&self, item_id: u32) -> Option<&[u8]> {
+        self.item_data
+            .iter()
+            .find(|(id, _)| *id == item_id)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// An unused ID for a new item (items and entity groups share IDs)
+    pub fn next_item_id(&self) -> u32 {
+        let items = self.item_infos.iter().map(|i| i.item_id);
+        let groups = self.entity_groups.iter().map(|g| g.group_id);
+        items.chain(groups).max().unwrap_or(0) + 1
+    }
+
+    /// Add an item with its data, returning its ID
+    pub fn add_item(&mut self, item_type: FourCC, content_type: &str, data: Vec<u8>) -> u32 {
+        let item_id = self.next_item_id();
+        self.item_infos.push(ItemInfo {
+            item_id,
+            item_type,
+            item_name: String::new(),
+            content_type: content_type.into(),
+            hidden: false,
+        });
+        self.item_data.push((item_id, data));
+        item_id
+    }
+
+    /// Replace the data of an item
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such item.
+    pub fn set_item_data(&mut self, item_id: u32, data: Vec<u8>) -> Result<()> {
+        if !self.item_infos.iter().any(|i| i.item_id == item_id) {
+            return Err(HeicError::InvalidData("no such item").into());
+        }
+        match self.item_data.iter_mut().find(|(id, _)| *id == item_id) {
+            Some((_, old)) => *old = data,
+            None => self.item_data.push((item_id, data)),
+        }
+        Ok(())
+    }
+
+    /// Remove an item along with its data, property associations, the
+    /// references from it and its membership of references and groups
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when asked to remove the primary item.
+    pub fn remove_item(&mut self, item_id: u32) -> Result<()> {
+        if item_id == self.primary_item_id {
+            return Err(HeicError::InvalidData("cannot remove the primary item").into());
+        }
+        self.item_infos.retain(|i| i.item_id != item_id);
+        self.item_data.retain(|(id, _)| *id != item_id);
+        self.property_associations.retain(|a| a.item_id != item_id);
+        self.item_references.retain_mut(|r| {
+            r.to_item_ids.retain(|&id| id != item_id);
+            r.from_item_id != item_id && !r.to_item_ids.is_empty()
+        });
+        self.entity_groups.retain_mut(|g| {
+            g.entity_ids.retain(|&id| id != item_id);
+            if let Some(pyramid) = &mut g.pyramid {
+                pyramid.layers.retain(|l| l.item_id != item_id);
+            }
+            !g.entity_ids.is_empty()
+        });
+        Ok(())
+    }
+
+    /// Serialize the file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a property without its original box is of a kind
+    /// that cannot be serialized.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let wide_ids = self
+            .item_infos
+            .iter()
+            .map(|i| i.item_id)
+            .chain(self.entity_groups.iter().map(|g| g.group_id))
+            .any(|id| id > 0xFFFF);
+
+        let mut ftyp = Vec::with_capacity(8 + 4 * self.compatible_brands.len());
+        ftyp.extend_from_slice(&self.brand.0);
+        ftyp.extend_from_slice(&0u32.to_be_bytes());
+        for brand in &self.compatible_brands {
+            ftyp.extend_from_slice(&brand.0);
+        }
+
+        // Everything in meta but iloc, whose size does not depend on offsets
+        let mut meta = vec![0, 0, 0, 0];
+        let mut hdlr = vec![0; 8];
+        hdlr[4..8].copy_from_slice(b"pict");
+        hdlr.extend_from_slice(&[0; 13]);
+        write_full_box(&mut meta, FourCC::HDLR, 0, &hdlr);
+        let mut pitm = Vec::new();
+        push_id(&mut pitm, self.primary_item_id, wide_ids);
+        write_full_box(&mut meta, FourCC::PITM, u8::from(wide_ids), &pitm);
+        self.write_iinf(&mut meta);
+        self.write_iref(&mut meta, wide_ids);
+        self.write_iprp(&mut meta)?;
+        self.write_grpl(&mut meta);
+
+        let mut locations: Vec<ItemLocation> = Vec::with_capacity(self.item_data.len());
+        let mut data_len = 0u64;
+        for (item_id, data) in &self.item_data {
+            locations.push(ItemLocation {
+                item_id: *item_id,
+                construction_method: 0,
+                base_offset: 0,
+                extents: vec![(data_len, data.len() as u64)],
+            });
+            data_len += data.len() as u64;
+        }
+        let meta_len = meta.len() + iloc_size(&locations);
+        let data_start = box_size(ftyp.len() as u64)
+            + box_size(meta_len as u64)
+            + (box_size(data_len) - data_len);
+        for loc in &mut locations {
+            loc.extents[0].0 += data_start;
+        }
+        write_iloc(&mut meta, &locations);
+
+        let mut out = Vec::with_capacity((data_start + data_len) as usize);
+        write_box(&mut out, FourCC::FTYP, &ftyp);
+        write_box(&mut out, FourCC::META, &meta);
+        write_box_header(&mut out, FourCC::MDAT, data_len);
+        for (_, data) in &self.item_data {
+            out.extend_from_slice(data);
+        }
+        Ok(out)
+    }
+
+    fn write_iinf(&self, out: &mut Vec<u8>) {
+        let mut iinf = Vec::new();
+        let version = if self.item_infos.len() > 0xFFFF {
+            iinf.extend_from_slice(&(self.item_infos.len() as u32).to_be_bytes());
+            1
+        } else {
+            iinf.extend_from_slice(&(self.item_infos.len() as u16).to_be_bytes());
+            0
+        };
+        for info in &self.item_infos {
+            let wide = info.item_id > 0xFFFF;
+            let mut infe = Vec::new();
+            push_id(&mut infe, info.item_id, wide);
+            // Item protection index: unprotected
+            infe.extend_from_slice(&[0, 0]);
+            infe.extend_from_slice(&info.item_type.0);
+            push_string(&mut infe, &info.item_name);
+            if info.item_type == FourCC(*b"mime") {
+                push_string(&mut infe, &info.content_type);
+            }
+            let flags = u32::from(info.hidden);
+            let mut content = Vec::with_capacity(4 + infe.len());
+            content.push(if wide { 3 } else { 2 });
+            content.extend_from_slice(&flags.to_be_bytes()[1..]);
+            content.extend_from_slice(&infe);
+            write_box(&mut iinf, FourCC::INFE, &content);
+        }
+        write_full_box(out, FourCC::IINF, version, &iinf);
+    }
+
+    fn write_iref(&self, out: &mut Vec<u8>, wide_ids: bool) {
+        if self.item_references.is_empty() {
+            return;
+        }
+        let mut iref = Vec::new();
+        for reference in &self.item_references {
+            let mut content = Vec::new();
+            push_id(&mut content, reference.from_item_id, wide_ids);
+            content.extend_from_slice(&(reference.to_item_ids.len() as u16).to_be_bytes());
+            for &id in &reference.to_item_ids {
+                push_id(&mut content, id, wide_ids);
+            }
+            write_box(&mut iref, reference.reference_type, &content);
+        }
+        write_full_box(out, FourCC::IREF, u8::from(wide_ids), &iref);
+    }
+
+    /// Write ipco with the properties in use and ipma renumbered to match
+    fn write_iprp(&self, out: &mut Vec<u8>) -> Result<()> {
+        // New 1-based index of each property in use, in file order
+        let mut used = vec![false; self.properties.len()];
+        for assoc in &self.property_associations {
+            for &(index, _) in &assoc.properties {
+                if let Some(used) = usize::from(index)
+                    .checked_sub(1)
+                    .and_then(|i| used.get_mut(i))
+                {
+                    *used = true;
+                }
+            }
+        }
+        let mut new_index = vec![0u16; self.properties.len() + 1];
+        let mut ipco = Vec::new();
+        let mut count = 0u16;
+        for (i, property) in self.properties.iter().enumerate() {
+            if !used[i] {
+                continue;
+            }
+            count += 1;
+            new_index[i + 1] = count;
+            match &property.raw {
+                Some(raw) => ipco.extend_from_slice(raw),
+                None => write_property(&mut ipco, &property.property)?,
+            }
+        }
+
+        let wide_ids = self
+            .property_associations
+            .iter()
+            .any(|a| a.item_id > 0xFFFF);
+        let wide_indices = count > 0x7F;
+        let mut ipma = Vec::new();
+        ipma.extend_from_slice(&(self.property_associations.len() as u32).to_be_bytes());
+        for assoc in &self.property_associations {
+            push_id(&mut ipma, assoc.item_id, wide_ids);
+            let kept: Vec<_> = assoc
+                .properties
+                .iter()
+                .filter_map(|&(index, essential)| {
+                    let index = *new_index.get(usize::from(index))?;
+                    (index != 0).then_some((index, essential))
+                })
+                .collect();
+            ipma.push(kept.len() as u8);
+            for (index, essential) in kept {
+                if wide_indices {
+                    ipma.extend_from_slice(&(u16::from(essential) << 15 | index).to_be_bytes());
+                } else {
+                    ipma.push(u8::from(essential) << 7 | index as u8);
+                }
+            }
+        }
+
+        let mut iprp = Vec::new();
+        write_box(&mut iprp, FourCC::IPCO, &ipco);
+        let mut content = vec![u8::from(wide_ids), 0, 0, u8::from(wide_indices)];
+        content.extend_from_slice(&ipma);
+        write_box(&mut iprp, FourCC::IPMA, &content);
+        write_box(out, FourCC::IPRP, &iprp);
+        Ok(())
+    }
+
+    fn write_grpl(&self, out: &mut Vec<u8>) {
+        if self.entity_groups.is_empty() {
+            return;
+        }
+        let mut grpl = Vec::new();
+        for group in &self.entity_groups {
+            let mut content = Vec::new();
+            content.extend_from_slice(&group.group_id.to_be_bytes());
+            content.extend_from_slice(&(group.entity_ids.len() as u32).to_be_bytes());
+            for id in &group.entity_ids {
+                content.extend_from_slice(&id.to_be_bytes());
+            }
+            if let Some(pyramid) = &group.pyramid {
+                content.extend_from_slice(&pyramid.tile_size_x.to_be_bytes());
+                content.extend_from_slice(&pyramid.tile_size_y.to_be_bytes());
+                for layer in &pyramid.layers {
+                    content.extend_from_slice(&layer.layer_binning.to_be_bytes());
+                    content.extend_from_slice(&layer.tiles_in_layer_row_minus1.to_be_bytes());
+                    content.extend_from_slice(&layer.tiles_in_layer_column_minus1.to_be_bytes());
+                }
+            }
+            write_full_box(&mut grpl, group.grouping_type, 0, &content);
+        }
+        write_box(out, FourCC::GRPL, &grpl);
+    }
+}
+
+/// Append a full box with the given version, zero flags and `content`
+fn write_full_box(out: &mut Vec<u8>, box_type: FourCC, version: u8, content: &[u8]) {
+    write_box_header(out, box_type, content.len() as u64 + 4);
+    out.extend_from_slice(&[version, 0, 0, 0]);
+    out.extend_from_slice(content);
+}
+
+/// Append an item ID as 16 or 32 bits
+fn push_id(out: &mut Vec<u8>, id: u32, wide: bool) {
+    if wide {
+        out.extend_from_slice(&id.to_be_bytes());
+    } else {
+        out.extend_from_slice(&(id as u16).to_be_bytes());
+    }
+}
+
+/// Append a null-terminated string
+fn push_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    out.push(0);
+}
+
+/// Content of an hvcC box (ISO/IEC 14496-15 8.3.3.1)
+///
+/// The NAL units are grouped into one array per NAL unit type, in order of
+/// first appearance, each marked complete.
+fn hvcc_content(config: &HevcDecoderConfig) -> Vec<u8> {
+    let mut content = vec![
+        config.config_version,
+        (config.general_profile_space << 6)
+            | (u8::from(config.general_tier_flag) << 5)
+            | (config.general_profile_idc & 0x1F),
+    ];
+    content.extend_from_slice(&config.general_profile_compatibility_flags.to_be_bytes());
+    content.extend_from_slice(&config.general_constraint_indicator_flags.to_be_bytes()[2..]);
+    content.push(config.general_level_idc);
+    // min_spatial_segmentation_idc 0, parallelismType 0 (unknown)
+    content.extend_from_slice(&[0xF0, 0x00, 0xFC]);
+    content.push(0xFC | (config.chroma_format & 0x3));
+    content.push(0xF8 | (config.bit_depth_luma_minus8 & 0x7));
+    content.push(0xF8 | (config.bit_depth_chroma_minus8 & 0x7));
+    // avgFrameRate 0; constantFrameRate 0, numTemporalLayers 1, temporalIdNested 1
+    content.extend_from_slice(&[0, 0]);
+    content.push((1 << 3) | (1 << 2) | (config.length_size_minus_one & 0x3));
+
+    let mut types: Vec<u8> = Vec::new();
+    for nal in config.nal_units.iter().filter(|nal| !nal.is_empty()) {
+        let nal_type = (nal[0] >> 1) & 0x3F;
+        if !types.contains(&nal_type) {
+            types.push(nal_type);
+        }
+    }
+    content.push(types.len() as u8);
+    for nal_type in types {
+        let nals: Vec<&Vec<u8>> = config
+            .nal_units
+            .iter()
+            .filter(|nal| nal.first().is_some_and(|b| (b >> 1) & 0x3F == nal_type))
+            .collect();
+        content.push(0x80 | nal_type);
+        content.extend_from_slice(&(nals.len() as u16).to_be_bytes());
+        for nal in nals {
+            content.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            content.extend_from_slice(nal);
+        }
+    }
+    content
+}
+
+/// Append the box of a property that has no original box
+fn write_property(out: &mut Vec<u8>, property: &ItemProperty) -> Result<()> {
+    match property {
+        ItemProperty::ImageExtents(ext) => {
+            let content = [ext.width.to_be_bytes(), ext.height.to_be_bytes()].concat();
+            write_full_box(out, FourCC::ISPE, 0, &content);
+        }
+        ItemProperty::ColorInfo(ColorInfo::Nclx {
+            color_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
+            full_range,
+        }) => {
+            let mut content = b"nclx".to_vec();
+            content.extend_from_slice(&color_primaries.to_be_bytes());
+            content.extend_from_slice(&transfer_characteristics.to_be_bytes());
+            content.extend_from_slice(&matrix_coefficients.to_be_bytes());
+            content.push(u8::from(*full_range) << 7);
+            write_box(out, FourCC::COLR, &content);
+        }
+        ItemProperty::ColorInfo(ColorInfo::IccProfile(icc)) => {
+            write_box(out, FourCC::COLR, &[&b"prof"[..], icc].concat());
+        }
+        ItemProperty::CleanAperture(clap) => {
+            let fields = [
+                clap.width_n,
+                clap.width_d,
+                clap.height_n,
+                clap.height_d,
+                clap.horiz_off_n as u32,
+                clap.horiz_off_d,
+                clap.vert_off_n as u32,
+                clap.vert_off_d,
+            ];
+            let content: Vec<u8> = fields.iter().flat_map(|f| f.to_be_bytes()).collect();
+            write_box(out, FourCC::CLAP, &content);
+        }
+        ItemProperty::Rotation(rotation) => {
+            // irot counts quarter turns anticlockwise; the angle is clockwise
+            let turns = ((360 - u32::from(rotation.angle) % 360) / 90 % 4) as u8;
+            write_box(out, FourCC::IROT, &[turns]);
+        }
+        ItemProperty::Mirror(mirror) => write_box(out, FourCC::IMIR, &[mirror.axis & 1]),
+        ItemProperty::AuxiliaryType(aux_type) => {
+            let mut content = Vec::new();
+            push_string(&mut content, aux_type);
+            write_full_box(out, FourCC::AUXC, 0, &content);
+        }
+        ItemProperty::LayerSelector(layer_id) => {
+            write_box(out, FourCC::LSEL, &layer_id.to_be_bytes());
+        }
+        ItemProperty::TargetOutputLayerSet(ols_idx) => {
+            write_full_box(out, FourCC::TOLS, 0, &ols_idx.to_be_bytes());
+        }
+        ItemProperty::HevcConfig(config) => write_box(out, FourCC::HVCC, &hvcc_content(config)),
+        ItemProperty::ContentLightLevel(clli) => {
+            let content = [
+                clli.max_content_light_level.to_be_bytes(),
+                clli.max_pic_average_light_level.to_be_bytes(),
+            ]
+            .concat();
+            write_box(out, FourCC::CLLI, &content);
+        }
+        ItemProperty::MasteringDisplayColourVolume(mdcv) => {
+            let mut content: Vec<u8> = mdcv
+                .display_primaries
+                .iter()
+                .chain([&mdcv.white_point])
+                .flat_map(|&(x, y)| [x.to_be_bytes(), y.to_be_bytes()].concat())
+                .collect();
+            content.extend_from_slice(&mdcv.max_luminance.to_be_bytes());
+            content.extend_from_slice(&mdcv.min_luminance.to_be_bytes());
+            write_box(out, FourCC::MDCV, &content);
+        }
+        ItemProperty::PixelInformation(bits) => {
+            let mut content = vec![bits.len() as u8];
+            content.extend_from_slice(bits);
+            write_full_box(out, FourCC::PIXI, 0, &content);
+        }
+        ItemProperty::LHevcConfig(_)
+        | ItemProperty::OperatingPoints(_)
+        | ItemProperty::Unknown => {
+            return Err(HeicError::Unsupported("serializing this item property").into());
+        }
+    }
+    Ok(())
+}
@@ -2,6 +2,7 @@
 
 use crate::error::HevcError;
 use alloc::vec::Vec;
+use core::ops::Range;
 
 type Result<T> = core::result::Result<T, HevcError>;
 
@@ -155,17 +156,20 @@ impl NalType {
     }
 
     /// Check if this is a RASL picture
-    #[allow(dead_code)]
     pub fn is_rasl(self) -> bool {
         matches!(self, Self::RaslN | Self::RaslR)
     }
 
     /// Check if this is a RADL picture
-    #[allow(dead_code)]
     pub fn is_radl(self) -> bool {
         matches!(self, Self::RadlN | Self::RadlR)
     }
 
+    /// Check if this is a sub-layer non-reference picture (even VCL types below 16)
+    pub fn is_sub_layer_non_reference(self) -> bool {
+        (self as u8) < 16 && (self as u8).is_multiple_of(2)
+    }
+
     /// Check if this is an IRAP picture
     pub fn is_irap(self) -> bool {
         matches!(
@@ -251,7 +255,8 @@ fn parse_annexb(data: &[u8]) -> Result<Vec<NalUnit<'_>>> {
                 }
             }
 
-            if nal_end > nal_start + 2 {
+            // EOS and EOB NAL units are just the two header bytes
+            if nal_end >= nal_start + 2 {
                 let raw_data = &data[nal_start..nal_end];
                 if let Ok(nal) = parse_nal_header(raw_data) {
                     nals.push(nal);
@@ -307,6 +312,42 @@ pub fn parse_length_prefixed_ext(data: &[u8], length_size: usize) -> Result<Vec<
     Ok(nals)
 }
 
+/// Split a stream's NAL units into access units (H.265 7.4.2.4.4)
+///
+/// An access unit ends before the first access unit delimiter, parameter
+/// set, prefix SEI or reserved prefix NAL unit that follows its last slice
+/// segment and precedes the next picture's first slice segment. End of
+/// sequence and end of bitstream NAL units stay with the access unit they
+/// end. Only base layer slices start pictures.
+pub fn access_units(nal_units: &[NalUnit<'_>]) -> Vec<Range<usize>> {
+    let mut units = Vec::new();
+    let mut start = 0;
+    let mut has_slice = false;
+    // First NAL unit after the last slice segment that can open a new unit
+    let mut next_start = None;
+    for (i, nal) in nal_units.iter().enumerate() {
+        if nal.nal_type.is_slice() {
+            let first_slice = nal.payload.first().is_some_and(|&b| b & 0x80 != 0);
+            if first_slice && nal.nuh_layer_id == 0 && has_slice {
+                let end = next_start.unwrap_or(i);
+                units.push(start..end);
+                start = end;
+            }
+            has_slice = true;
+            next_start = None;
+        } else if has_slice && next_start.is_none() {
+            let nal_type = nal.raw_data.first().map_or(0, |&b| (b >> 1) & 0x3F);
+            if matches!(nal_type, 32..=35 | 39 | 41..=44 | 48..=55) {
+                next_start = Some(i);
+            }
+        }
+    }
+    if start < nal_units.len() {
+        units.push(start..nal_units.len());
+    }
+    units
+}
+
 /// Parse a single NAL unit (for hvcC parameter sets)
 pub fn parse_single_nal(data: &[u8]) -> Result<NalUnit<'_>> {
     parse_nal_header(data)
@@ -487,3 +528,102 @@ impl<'a> BitstreamReader<'a> {
         self.byte_offset
     }
 }
+
+/// Bitstream writer producing RBSP data, the counterpart of [`BitstreamReader`]
+#[derive(Default)]
+pub struct BitstreamWriter {
+    data: Vec<u8>,
+    /// Bits of the partial last byte, MSB first
+    current: u8,
+    bit_count: u8,
+}
+
+impl BitstreamWriter {
+    /// Create an empty writer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if at byte boundary
+    pub fn is_byte_aligned(&self) -> bool {
+        self.bit_count == 0
+    }
+
+    /// Write a single bit
+    pub fn write_bit(&mut self, bit: u8) {
+        self.current = (self.current << 1) | (bit & 1);
+        self.bit_count += 1;
+        if self.bit_count == 8 {
+            self.data.push(self.current);
+            self.current = 0;
+            self.bit_count = 0;
+        }
+    }
+
+    /// Write the low `n` bits of `value`, up to 32
+    pub fn write_bits(&mut self, value: u32, n: u8) {
+        for i in (0..n).rev() {
+            self.write_bit((value >> i) as u8 & 1);
+        }
+    }
+
+    /// Write a flag
+    pub fn write_flag(&mut self, flag: bool) {
+        self.write_bit(u8::from(flag));
+    }
+
+    /// Write unsigned Exp-Golomb code
+    pub fn write_ue(&mut self, value: u32) {
+        let code = u64::from(value) + 1;
+        let len = 64 - code.leading_zeros() as u8;
+        self.write_bits(0, len - 1);
+        for i in (0..len).rev() {
+            self.write_bit((code >> i) as u8 & 1);
+        }
+    }
+
+    /// Write signed Exp-Golomb code
+    pub fn write_se(&mut self, value: i32) {
+        let mapped = if value > 0 {
+            value as u32 * 2 - 1
+        } else {
+            value.unsigned_abs() * 2
+        };
+        self.write_ue(mapped);
+    }
+
+    /// Write a one bit, then zero bits up to the byte boundary (rbsp_trailing_bits
+    /// and the slice header's byte_alignment)
+    pub fn write_trailing_bits(&mut self) {
+        self.write_bit(1);
+        while !self.is_byte_aligned() {
+            self.write_bit(0);
+        }
+    }
+
+    /// The bytes written, with a partial last byte padded with zero bits
+    pub fn finish(mut self) -> Vec<u8> {
+        while !self.is_byte_aligned() {
+            self.write_bit(0);
+        }
+        self.data
+    }
+}
+
+/// Build a NAL unit from its header fields and RBSP, inserting emulation
+/// prevention bytes (0x00 0x00 0x0X -> 0x00 0x00 0x03 0x0X)
+pub fn write_nal_unit(nal_type: NalType, nuh_layer_id: u8, rbsp: &[u8]) -> Vec<u8> {
+    let mut nal = Vec::with_capacity(rbsp.len() + rbsp.len() / 64 + 2);
+    nal.push(((nal_type as u8) << 1) | (nuh_layer_id >> 5));
+    nal.push((nuh_layer_id << 3) | 1);
+    let mut zeros = 0;
+    for &byte in rbsp {
+        if zeros >= 2 && byte <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+        nal.push(byte);
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+    }
+    nal
+}
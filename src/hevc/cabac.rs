@@ -1,8 +1,9 @@
-//! CABAC (Context-Adaptive Binary Arithmetic Coding) decoder
+//! CABAC (Context-Adaptive Binary Arithmetic Coding) decoder and encoder
 //!
 //! CABAC is the entropy coding method used in HEVC. It uses arithmetic coding
 //! with context models that adapt based on previously coded symbols.
 
+use super::bitstream::BitstreamWriter;
 use crate::error::HevcError;
 
 type Result<T> = core::result::Result<T, HevcError>;
@@ -147,6 +148,66 @@ impl ContextModel {
             self.mps = 0;
         }
     }
+
+    /// Advance the state after coding `bin`, as decoding it would
+    pub fn update(&mut self, bin: u8) {
+        if bin == self.mps {
+            self.state = STATE_TRANS_MPS[self.state as usize];
+        } else {
+            if self.state == 0 {
+                self.mps = 1 - self.mps;
+            }
+            self.state = STATE_TRANS_LPS[self.state as usize];
+        }
+    }
+
+    /// Estimated cost of coding `bin` in this state, in 1/256 bits
+    pub fn cost(&self, bin: u8) -> u32 {
+        BIT_COST[self.state as usize][usize::from(bin != self.mps)]
+    }
+}
+
+/// log2(x) in 1/256 units, for x > 0
+const fn log2_q8(x: u32) -> u32 {
+    let int = 31 - x.leading_zeros();
+    // Mantissa in [1, 2) with 16 fraction bits; squaring yields one bit of
+    // the logarithm at a time
+    let mut m = ((x as u64) << 16) >> int;
+    let mut frac = 0;
+    let mut i = 0;
+    while i < 8 {
+        m = (m * m) >> 16;
+        frac <<= 1;
+        if m >= 1 << 17 {
+            m >>= 1;
+            frac |= 1;
+        }
+        i += 1;
+    }
+    int * 256 + frac
+}
+
+/// Cost of an MPS and an LPS bin per state in 1/256 bits, averaged over the
+/// four range quarters of `LPS_TABLE`
+static BIT_COST: [[u32; 2]; 64] = build_bit_costs();
+
+const fn build_bit_costs() -> [[u32; 2]; 64] {
+    let mut costs = [[0; 2]; 64];
+    let mut state = 0;
+    while state < 64 {
+        let mut q = 0;
+        while q < 4 {
+            let range = 288 + 64 * q as u32;
+            let lps = LPS_TABLE[state][q] as u32;
+            costs[state][0] += log2_q8(range) - log2_q8(range - lps);
+            costs[state][1] += log2_q8(range) - log2_q8(lps);
+            q += 1;
+        }
+        costs[state][0] /= 4;
+        costs[state][1] /= 4;
+        state += 1;
+    }
+    costs
 }
 
 /// CABAC decoder (libde265-compatible implementation)
@@ -389,6 +450,118 @@ impl<'a> CabacDecoder<'a> {
     }
 }
 
+/// CABAC encoder (H.265 9.3.4.4), the counterpart of [`CabacDecoder`]
+///
+/// Writes into a [`BitstreamWriter`] after whatever it already holds, which
+/// for slice data is the byte-aligned slice header.
+pub struct CabacEncoder {
+    writer: BitstreamWriter,
+    low: u32,
+    range: u32,
+    bits_outstanding: u32,
+    first_bit: bool,
+}
+
+impl CabacEncoder {
+    /// Start encoding at the end of `writer`, which must be byte aligned
+    pub fn new(writer: BitstreamWriter) -> Self {
+        debug_assert!(writer.is_byte_aligned());
+        Self {
+            writer,
+            low: 0,
+            range: 510,
+            bits_outstanding: 0,
+            first_bit: true,
+        }
+    }
+
+    /// Encode a bin using a context model
+    pub fn encode_bin(&mut self, ctx: &mut ContextModel, bin: u8) {
+        let q_range_idx = (self.range >> 6) & 3;
+        let lps_range = LPS_TABLE[ctx.state as usize][q_range_idx as usize] as u32;
+        self.range -= lps_range;
+        if bin != ctx.mps {
+            self.low += self.range;
+            self.range = lps_range;
+        }
+        ctx.update(bin);
+        self.renormalize();
+    }
+
+    /// Encode a bypass bin (equal probability)
+    pub fn encode_bypass(&mut self, bin: u8) {
+        self.low <<= 1;
+        if bin != 0 {
+            self.low += self.range;
+        }
+        if self.low >= 1024 {
+            self.put_bit(1);
+            self.low -= 1024;
+        } else if self.low < 512 {
+            self.put_bit(0);
+        } else {
+            self.low -= 512;
+            self.bits_outstanding += 1;
+        }
+    }
+
+    /// Encode the low `n` bits of `value` as bypass bins, MSB first
+    pub fn encode_bypass_bits(&mut self, value: u32, n: u8) {
+        for i in (0..n).rev() {
+            self.encode_bypass((value >> i) as u8 & 1);
+        }
+    }
+
+    /// Encode a terminate bin; a 1 ends the arithmetic code and writes the
+    /// rbsp_stop_one_bit
+    pub fn encode_terminate(&mut self, bin: u8) {
+        self.range -= 2;
+        if bin != 0 {
+            self.low += self.range;
+            self.range = 2;
+            self.renormalize();
+            self.put_bit(((self.low >> 9) & 1) as u8);
+            self.writer.write_bits(((self.low >> 7) & 3) | 1, 2);
+        } else {
+            self.renormalize();
+        }
+    }
+
+    /// The writer with everything encoded so far; complete only after a
+    /// terminate bin of 1
+    pub fn finish(self) -> BitstreamWriter {
+        self.writer
+    }
+
+    fn renormalize(&mut self) {
+        while self.range < 256 {
+            if self.low < 256 {
+                self.put_bit(0);
+            } else if self.low >= 512 {
+                self.low -= 512;
+                self.put_bit(1);
+            } else {
+                self.low -= 256;
+                self.bits_outstanding += 1;
+            }
+            self.range <<= 1;
+            self.low <<= 1;
+        }
+    }
+
+    fn put_bit(&mut self, bit: u8) {
+        if self.first_bit {
+            self.first_bit = false;
+        } else {
+            self.writer.write_bit(bit);
+        }
+        while self.bits_outstanding > 0 {
+            self.writer.write_bit(1 - bit);
+            self.bits_outstanding -= 1;
+        }
+    }
+}
+
 /// Context indices for various syntax elements
 #[allow(dead_code)]
 pub mod context {
@@ -421,7 +594,9 @@ pub mod context {
     /// Abs MVD greater 0 flag
     pub const ABS_MVD_GREATER0_FLAG: usize = 25;
     /// Abs MVD greater 1 flag
-    pub const ABS_MVD_GREATER1_FLAG: usize = 27;
+    pub const ABS_MVD_GREATER1_FLAG: usize = 26;
+    /// RQT root CBF
+    pub const RQT_ROOT_CBF: usize = 27;
     /// Split transform flag
     pub const SPLIT_TRANSFORM_FLAG: usize = 28;
     /// CBF luma
@@ -460,44 +635,221 @@ pub mod context {
     pub const NUM_CONTEXTS: usize = 170;
 }
 
-/// Initial context values from H.265 spec
-pub static INIT_VALUES: [u8; context::NUM_CONTEXTS] = [
-    // SPLIT_CU_FLAG (3)
-    139, 141, 157, // CU_TRANSQUANT_BYPASS_FLAG (1)
-    154, // CU_SKIP_FLAG (3)
-    197, 185, 201, // PALETTE_MODE_FLAG (1)
-    154, // PRED_MODE_FLAG (1)
-    149, // PART_MODE (4)
-    184, 154, 139, 154, // PREV_INTRA_LUMA_PRED_FLAG (1)
-    184, // INTRA_CHROMA_PRED_MODE (1)
-    63,  // INTER_PRED_IDC (5)
-    95, 79, 63, 31, 31,  // MERGE_FLAG (1)
-    110, // MERGE_IDX (1)
-    122, // MVP_LX_FLAG (1)
-    168, // REF_IDX (2)
-    153, 153, // ABS_MVD_GREATER0_FLAG (2)
-    140, 198, // ABS_MVD_GREATER1_FLAG (1)
-    140, // SPLIT_TRANSFORM_FLAG (3)
-    153, 138, 138, // CBF_LUMA (2)
-    111, 141, // CBF_CBCR (5)
-    94, 138, 182, 154, 154, // TRANSFORM_SKIP_FLAG (2)
-    139, 139, // LAST_SIG_COEFF_X_PREFIX (18)
-    110, 110, 124, 125, 140, 153, 125, 127, 140, 109, 111, 143, 127, 111, 79, 108, 123, 63,
-    // LAST_SIG_COEFF_Y_PREFIX (18)
-    110, 110, 124, 125, 140, 153, 125, 127, 140, 109, 111, 143, 127, 111, 79, 108, 123, 63,
-    // CODED_SUB_BLOCK_FLAG (4)
-    91, 171, 134, 141, // SIG_COEFF_FLAG (44)
-    111, 111, 125, 110, 110, 94, 124, 108, 124, 107, 125, 141, 179, 153, 125, 107, 125, 141, 179,
-    153, 125, 107, 125, 141, 179, 153, 125, 140, 139, 182, 182, 152, 136, 152, 136, 153, 136, 139,
-    111, 136, 139, 111, 155, 154, // COEFF_ABS_LEVEL_GREATER1_FLAG (24)
-    140, 92, 137, 138, 140, 152, 138, 139, 153, 74, 149, 92, 139, 107, 122, 152, 140, 179, 166,
-    182, 140, 227, 122, 197, // COEFF_ABS_LEVEL_GREATER2_FLAG (6)
-    138, 153, 136, 167, 152, 152, // SAO_MERGE_FLAG (1)
-    153, // SAO_TYPE_IDX (1)
-    200, // CU_QP_DELTA_ABS (2)
-    154, 154, // CU_CHROMA_QP_OFFSET_FLAG (1)
-    154, // CU_CHROMA_QP_OFFSET_IDX (1)
-    154, // LOG2_RES_SCALE_ABS_PLUS1 (8)
-    154, 154, 154, 154, 154, 154, 154, 154, // RES_SCALE_SIGN_FLAG (2)
-    154, 154,
+/// Initial context values from H.265 spec, indexed by initType
+/// (0 for I slices, 1 and 2 for P/B slices depending on `cabac_init_flag`)
+#[rustfmt::skip]
+pub static INIT_VALUES: [[u8; context::NUM_CONTEXTS]; 3] = [
+    [
+        // SPLIT_CU_FLAG (3)
+        139, 141, 157,
+        // CU_TRANSQUANT_BYPASS_FLAG (1)
+        154,
+        // CU_SKIP_FLAG (3)
+        197, 185, 201,
+        // PALETTE_MODE_FLAG (1)
+        154,
+        // PRED_MODE_FLAG (1)
+        149,
+        // PART_MODE (4)
+        184, 154, 139, 154,
+        // PREV_INTRA_LUMA_PRED_FLAG (1)
+        184,
+        // INTRA_CHROMA_PRED_MODE (1)
+        63,
+        // INTER_PRED_IDC (5)
+        95, 79, 63, 31, 31,
+        // MERGE_FLAG (1)
+        110,
+        // MERGE_IDX (1)
+        122,
+        // MVP_LX_FLAG (1)
+        168,
+        // REF_IDX (2)
+        153, 153,
+        // ABS_MVD_GREATER0_FLAG (1)
+        140,
+        // ABS_MVD_GREATER1_FLAG (1)
+        198,
+        // RQT_ROOT_CBF (1)
+        79,
+        // SPLIT_TRANSFORM_FLAG (3)
+        153, 138, 138,
+        // CBF_LUMA (2)
+        111, 141,
+        // CBF_CBCR (5)
+        94, 138, 182, 154, 154,
+        // TRANSFORM_SKIP_FLAG (2)
+        139, 139,
+        // LAST_SIG_COEFF_X_PREFIX (18)
+        110, 110, 124, 125, 140, 153, 125, 127, 140, 109, 111, 143, 127, 111, 79, 108, 123, 63,
+        // LAST_SIG_COEFF_Y_PREFIX (18)
+        110, 110, 124, 125, 140, 153, 125, 127, 140, 109, 111, 143, 127, 111, 79, 108, 123, 63,
+        // CODED_SUB_BLOCK_FLAG (4)
+        91, 171, 134, 141,
+        // SIG_COEFF_FLAG (44)
+        111, 111, 125, 110, 110, 94, 124, 108, 124, 107, 125, 141, 179, 153, 125, 107, 125, 141,
+        179, 153, 125, 107, 125, 141, 179, 153, 125, 140, 139, 182, 182, 152, 136, 152, 136, 153,
+        136, 139, 111, 136, 139, 111, 155, 154,
+        // COEFF_ABS_LEVEL_GREATER1_FLAG (24)
+        140, 92, 137, 138, 140, 152, 138, 139, 153, 74, 149, 92, 139, 107, 122, 152, 140, 179,
+        166, 182, 140, 227, 122, 197,
+        // COEFF_ABS_LEVEL_GREATER2_FLAG (6)
+        138, 153, 136, 167, 152, 152,
+        // SAO_MERGE_FLAG (1)
+        153,
+        // SAO_TYPE_IDX (1)
+        200,
+        // CU_QP_DELTA_ABS (2)
+        154, 154,
+        // CU_CHROMA_QP_OFFSET_FLAG (1)
+        154,
+        // CU_CHROMA_QP_OFFSET_IDX (1)
+        154,
+        // LOG2_RES_SCALE_ABS_PLUS1 (8)
+        154, 154, 154, 154, 154, 154, 154, 154,
+        // RES_SCALE_SIGN_FLAG (2)
+        154, 154,
+    ],
+    [
+        // SPLIT_CU_FLAG (3)
+        107, 139, 126,
+        // CU_TRANSQUANT_BYPASS_FLAG (1)
+        154,
+        // CU_SKIP_FLAG (3)
+        197, 185, 201,
+        // PALETTE_MODE_FLAG (1)
+        154,
+        // PRED_MODE_FLAG (1)
+        149,
+        // PART_MODE (4)
+        154, 139, 154, 154,
+        // PREV_INTRA_LUMA_PRED_FLAG (1)
+        154,
+        // INTRA_CHROMA_PRED_MODE (1)
+        152,
+        // INTER_PRED_IDC (5)
+        95, 79, 63, 31, 31,
+        // MERGE_FLAG (1)
+        110,
+        // MERGE_IDX (1)
+        122,
+        // MVP_LX_FLAG (1)
+        168,
+        // REF_IDX (2)
+        153, 153,
+        // ABS_MVD_GREATER0_FLAG (1)
+        140,
+        // ABS_MVD_GREATER1_FLAG (1)
+        198,
+        // RQT_ROOT_CBF (1)
+        79,
+        // SPLIT_TRANSFORM_FLAG (3)
+        124, 138, 94,
+        // CBF_LUMA (2)
+        153, 111,
+        // CBF_CBCR (5)
+        149, 107, 167, 154, 154,
+        // TRANSFORM_SKIP_FLAG (2)
+        139, 139,
+        // LAST_SIG_COEFF_X_PREFIX (18)
+        125, 110, 94, 110, 95, 79, 125, 111, 110, 78, 110, 111, 111, 95, 94, 108, 123, 108,
+        // LAST_SIG_COEFF_Y_PREFIX (18)
+        125, 110, 94, 110, 95, 79, 125, 111, 110, 78, 110, 111, 111, 95, 94, 108, 123, 108,
+        // CODED_SUB_BLOCK_FLAG (4)
+        121, 140, 61, 154,
+        // SIG_COEFF_FLAG (44)
+        155, 154, 139, 153, 139, 123, 123, 63, 153, 166, 183, 140, 136, 153, 154, 166, 183, 140,
+        136, 153, 154, 166, 183, 140, 136, 153, 154, 170, 153, 123, 123, 107, 121, 107, 121, 167,
+        151, 183, 140, 151, 183, 140, 140, 140,
+        // COEFF_ABS_LEVEL_GREATER1_FLAG (24)
+        154, 196, 196, 167, 154, 152, 167, 182, 182, 134, 149, 136, 153, 121, 136, 137, 169, 194,
+        166, 167, 154, 167, 137, 182,
+        // COEFF_ABS_LEVEL_GREATER2_FLAG (6)
+        107, 167, 91, 122, 107, 167,
+        // SAO_MERGE_FLAG (1)
+        153,
+        // SAO_TYPE_IDX (1)
+        185,
+        // CU_QP_DELTA_ABS (2)
+        154, 154,
+        // CU_CHROMA_QP_OFFSET_FLAG (1)
+        154,
+        // CU_CHROMA_QP_OFFSET_IDX (1)
+        154,
+        // LOG2_RES_SCALE_ABS_PLUS1 (8)
+        154, 154, 154, 154, 154, 154, 154, 154,
+        // RES_SCALE_SIGN_FLAG (2)
+        154, 154,
+    ],
+    [
+        // SPLIT_CU_FLAG (3)
+        107, 139, 126,
+        // CU_TRANSQUANT_BYPASS_FLAG (1)
+        154,
+        // CU_SKIP_FLAG (3)
+        197, 185, 201,
+        // PALETTE_MODE_FLAG (1)
+        154,
+        // PRED_MODE_FLAG (1)
+        134,
+        // PART_MODE (4)
+        154, 139, 154, 154,
+        // PREV_INTRA_LUMA_PRED_FLAG (1)
+        183,
+        // INTRA_CHROMA_PRED_MODE (1)
+        152,
+        // INTER_PRED_IDC (5)
+        95, 79, 63, 31, 31,
+        // MERGE_FLAG (1)
+        154,
+        // MERGE_IDX (1)
+        137,
+        // MVP_LX_FLAG (1)
+        168,
+        // REF_IDX (2)
+        153, 153,
+        // ABS_MVD_GREATER0_FLAG (1)
+        169,
+        // ABS_MVD_GREATER1_FLAG (1)
+        198,
+        // RQT_ROOT_CBF (1)
+        79,
+        // SPLIT_TRANSFORM_FLAG (3)
+        224, 167, 122,
+        // CBF_LUMA (2)
+        153, 111,
+        // CBF_CBCR (5)
+        149, 92, 167, 154, 154,
+        // TRANSFORM_SKIP_FLAG (2)
+        139, 139,
+        // LAST_SIG_COEFF_X_PREFIX (18)
+        125, 110, 124, 110, 95, 94, 125, 111, 111, 79, 125, 126, 111, 111, 79, 108, 123, 93,
+        // LAST_SIG_COEFF_Y_PREFIX (18)
+        125, 110, 124, 110, 95, 94, 125, 111, 111, 79, 125, 126, 111, 111, 79, 108, 123, 93,
+        // CODED_SUB_BLOCK_FLAG (4)
+        121, 140, 61, 154,
+        // SIG_COEFF_FLAG (44)
+        170, 154, 139, 153, 139, 123, 123, 63, 124, 166, 183, 140, 136, 153, 154, 166, 183, 140,
+        136, 153, 154, 166, 183, 140, 136, 153, 154, 170, 153, 138, 138, 122, 121, 122, 121, 167,
+        151, 183, 140, 151, 183, 140, 140, 140,
+        // COEFF_ABS_LEVEL_GREATER1_FLAG (24)
+        154, 196, 167, 167, 154, 152, 167, 182, 182, 134, 149, 136, 153, 121, 136, 122, 169, 208,
+        166, 167, 154, 152, 167, 182,
+        // COEFF_ABS_LEVEL_GREATER2_FLAG (6)
+        107, 167, 91, 107, 107, 167,
+        // SAO_MERGE_FLAG (1)
+        153,
+        // SAO_TYPE_IDX (1)
+        160,
+        // CU_QP_DELTA_ABS (2)
+        154, 154,
+        // CU_CHROMA_QP_OFFSET_FLAG (1)
+        154,
+        // CU_CHROMA_QP_OFFSET_IDX (1)
+        154,
+        // LOG2_RES_SCALE_ABS_PLUS1 (8)
+        154, 154, 154, 154, 154, 154, 154, 154,
+        // RES_SCALE_SIGN_FLAG (2)
+        154, 154,
+    ],
 ];
@@ -25,12 +25,18 @@ macro_rules! debug_trace {
 
 use super::cabac::{CabacDecoder, ContextModel, INIT_VALUES, context};
 use super::debug;
+use super::dpb::RefPicLists;
+use super::inter::{self, InterBuffers};
 use super::intra;
+use super::motion::{
+    BLOCK_CODED, BLOCK_DECODED, BLOCK_INTRA, BLOCK_SKIP, BlockInfo, ColPicture, MotionField, Mv,
+    MvContext, PbMotion, PredictionBlock,
+};
 use super::params::{Pps, Sps};
 use super::picture::DecodedFrame;
 use super::residual::{self, ScanOrder};
 use super::sao::SaoMap;
-use super::slice::{IntraPredMode, PartMode, PredMode, SliceHeader};
+use super::slice::{IntraPredMode, PartMode, PredMode, SliceHeader, SliceType};
 use super::transform;
 use archmage::incant;
 #[cfg(target_arch = "x86_64")]
@@ -40,6 +46,11 @@ use crate::error::HevcError;
 
 type Result<T> = core::result::Result<T, HevcError>;
 
+/// Called with the picture, its motion field and SAO map each time the CTB
+/// row `ctb_y` has been completely decoded
+pub type RowDone<'c> =
+    dyn FnMut(&mut DecodedFrame, &MotionField, &SaoMap, u32) -> Result<()> + 'c;
+
 /// Global SE counter for syntax element tracing
 pub static SE_COUNTER: AtomicU32 = AtomicU32::new(0);
 pub const SE_TRACE_LIMIT: u32 = 0;
@@ -65,7 +76,7 @@ fn se_trace(name: &str, val: i64, cabac: &CabacDecoder) {
 
 /// Chroma QP mapping table (H.265 Table 8-10)
 /// Maps qPi (0-57) to QpC for 8-bit video
-fn chroma_qp_mapping(qp_i: i32) -> i32 {
+pub(super) fn chroma_qp_mapping(qp_i: i32) -> i32 {
     // Table 8-10: qPi to QpC mapping
     // For qPi 0-29, QpC = qPi
     // For qPi 30-57, QpC follows the table
@@ -77,6 +88,27 @@ fn chroma_qp_mapping(qp_i: i32) -> i32 {
     CHROMA_QP_TABLE[qp_i.clamp(0, 57) as usize]
 }
 
+/// inter_pred_idc of a list 0 prediction (list 1 is 1)
+const PRED_L0: u8 = 0;
+/// inter_pred_idc of a bi-prediction
+const PRED_BI: u8 = 2;
+
+/// Offset and size `(x, y, w, h)` of each prediction block of a CU (Table 7-10)
+fn prediction_blocks(part_mode: PartMode, cb_size: u32) -> ([(u32, u32, u32, u32); 4], usize) {
+    let (s, h, q) = (cb_size, cb_size / 2, cb_size / 4);
+    let pad = (0, 0, 0, 0);
+    match part_mode {
+        PartMode::Part2Nx2N => ([(0, 0, s, s), pad, pad, pad], 1),
+        PartMode::Part2NxN => ([(0, 0, s, h), (0, h, s, h), pad, pad], 2),
+        PartMode::PartNx2N => ([(0, 0, h, s), (h, 0, h, s), pad, pad], 2),
+        PartMode::Part2NxnU => ([(0, 0, s, q), (0, q, s, s - q), pad, pad], 2),
+        PartMode::Part2NxnD => ([(0, 0, s, s - q), (0, s - q, s, q), pad, pad], 2),
+        PartMode::PartnLx2N => ([(0, 0, q, s), (q, 0, s - q, s), pad, pad], 2),
+        PartMode::PartnRx2N => ([(0, 0, s - q, s), (s - q, 0, q, s), pad, pad], 2),
+        PartMode::PartNxN => ([(0, 0, h, h), (h, 0, h, h), (0, h, h, h), (h, h, h, h)], 4),
+    }
+}
+
 /// Decoding context for a slice
 pub struct SliceContext<'a> {
     /// Sequence parameter set
@@ -139,6 +171,22 @@ pub struct SliceContext<'a> {
     current_qg_y: i32,
     /// SAO parameters per CTB
     pub sao_map: SaoMap,
+    /// Motion and block flags of the picture
+    motion: &'a mut MotionField,
+    /// Index of this slice in `motion.slices`
+    slice_idx: u16,
+    /// PicOrderCntVal of the current picture
+    poc: i32,
+    /// RefPicList0 / RefPicList1 pictures
+    ref_frames: [Vec<&'a DecodedFrame>; 2],
+    /// Collocated picture for temporal motion vector prediction
+    col: Option<ColPicture<'a>>,
+    /// Scratch buffers for inter prediction
+    inter_bufs: InterBuffers,
+    /// CuPredMode of the current CU
+    cu_pred_mode: PredMode,
+    /// Reusable 16-bit coefficient buffer for the SIMD dequantize/transform path
+    coeff_buf: [i16; 1024],
     /// Reusable residual buffer (inverse transform writes all elements, no re-zeroing needed)
     residual_buf: [i16; 1024],
     /// Reusable scaling matrix buffer
@@ -147,11 +195,17 @@ pub struct SliceContext<'a> {
 
 impl<'a> SliceContext<'a> {
     /// Create a new slice context
+    ///
+    /// The slice's [`SliceInfo`](super::motion::SliceInfo) must already be
+    /// the last entry of `motion.slices`.
     pub fn new(
         sps: &'a Sps,
         pps: &'a Pps,
         header: &'a SliceHeader,
         slice_data: &'a [u8],
+        motion: &'a mut MotionField,
+        refs: RefPicLists<'a>,
+        poc: i32,
     ) -> Result<Self> {
         // DEBUG: Print first few bytes of slice data
         debug_trace!(
@@ -188,7 +242,13 @@ impl<'a> SliceContext<'a> {
         let mut ctx = [ContextModel::new(154); context::NUM_CONTEXTS];
         let slice_qp = header.slice_qp_y;
 
-        for (i, init_val) in INIT_VALUES.iter().enumerate() {
+        // initType (9.3.2.2): P and B slices swap tables with cabac_init_flag
+        let init_type = match header.slice_type {
+            SliceType::I => 0,
+            SliceType::P => 1 + header.cabac_init_flag as usize,
+            SliceType::B => 2 - header.cabac_init_flag as usize,
+        };
+        for (i, init_val) in INIT_VALUES[init_type].iter().enumerate() {
             ctx[i].init(*init_val, slice_qp);
         }
 
@@ -198,9 +258,10 @@ impl<'a> SliceContext<'a> {
         let qp_i_cb = slice_qp + pps.pps_cb_qp_offset as i32 + header.slice_cb_qp_offset as i32;
         let qp_i_cr = slice_qp + pps.pps_cr_qp_offset as i32 + header.slice_cr_qp_offset as i32;
 
-        // Apply chroma QP mapping table (H.265 Table 8-10)
-        let qp_cb = chroma_qp_mapping(qp_i_cb.clamp(0, 57));
-        let qp_cr = chroma_qp_mapping(qp_i_cr.clamp(0, 57));
+        // Apply chroma QP mapping table (H.265 Table 8-10, 4:2:0 only)
+        let chroma_array_type = sps.chroma_array_type();
+        let qp_cb = Self::chroma_qp_from_luma(qp_i_cb.clamp(0, 57), chroma_array_type);
+        let qp_cr = Self::chroma_qp_from_luma(qp_i_cr.clamp(0, 57), chroma_array_type);
 
         debug_trace!(
             "DEBUG: Chroma QP: qp_y={}, qp_cb={}, qp_cr={}",
@@ -271,6 +332,14 @@ impl<'a> SliceContext<'a> {
             current_qg_x: -1,
             current_qg_y: -1,
             sao_map: SaoMap::new(sps.pic_width_in_ctbs(), sps.pic_height_in_ctbs()),
+            slice_idx: motion.slices.len().saturating_sub(1) as u16,
+            motion,
+            poc,
+            ref_frames: refs.frames,
+            col: refs.col,
+            inter_bufs: InterBuffers::default(),
+            cu_pred_mode: PredMode::Intra,
+            coeff_buf: [0i16; 1024],
             residual_buf: [0i16; 1024],
             scaling_buf: [16u8; 1024],
         })
@@ -278,6 +347,16 @@ impl<'a> SliceContext<'a> {
 
     /// Decode all CTUs in the slice
     pub fn decode_slice(&mut self, frame: &mut DecodedFrame) -> Result<()> {
+        self.decode_slice_rows(frame, &mut |_, _, _, _| Ok(()))
+    }
+
+    /// Decode all CTUs in the slice, calling `row_done` after the last CTU
+    /// of each CTB row
+    pub fn decode_slice_rows(
+        &mut self,
+        frame: &mut DecodedFrame,
+        row_done: &mut RowDone<'_>,
+    ) -> Result<()> {
         // Initialize CABAC tracker for debugging
         debug::init_tracker();
 
@@ -340,6 +419,10 @@ impl<'a> SliceContext<'a> {
                 wpp_saved_ctx = Some(self.ctx);
             }
 
+            if self.ctb_x == pic_width_in_ctbs - 1 {
+                row_done(frame, self.motion, &self.sao_map, self.ctb_y)?;
+            }
+
             // Check for end of slice segment
             let end_of_slice = self.cabac.decode_terminate()?;
             se_trace("end_of_slice", end_of_slice as i64, &self.cabac);
@@ -414,7 +497,7 @@ impl<'a> SliceContext<'a> {
         if x_ctb > 0 {
             let pic_width_ctbs = self.sps.pic_width_in_ctbs();
             let ctb_addr_rs = y_ctb * pic_width_ctbs + x_ctb;
-            let slice_addr_rs = 0u32;
+            let slice_addr_rs = self.header.slice_segment_address;
             let left_in_slice = ctb_addr_rs > slice_addr_rs;
             if left_in_slice {
                 let ctx_idx = context::SAO_MERGE_FLAG;
@@ -427,7 +510,7 @@ impl<'a> SliceContext<'a> {
         if y_ctb > 0 && !sao_merge_left_flag {
             let pic_width_ctbs = self.sps.pic_width_in_ctbs();
             let ctb_addr_rs = y_ctb * pic_width_ctbs + x_ctb;
-            let slice_addr_rs = 0u32;
+            let slice_addr_rs = self.header.slice_segment_address;
             let up_in_slice = ctb_addr_rs >= pic_width_ctbs + slice_addr_rs;
             if up_in_slice {
                 let ctx_idx = context::SAO_MERGE_FLAG;
@@ -442,7 +525,7 @@ impl<'a> SliceContext<'a> {
             *self.sao_map.get(x_ctb, y_ctb - 1)
         } else {
             let mut info = super::sao::SaoInfo::default();
-            let is_mono = self.sps.chroma_format_idc == 0;
+            let is_mono = self.sps.chroma_array_type() == 0;
             let n_chroma = if is_mono { 1 } else { 3 };
 
             #[allow(unused_assignments)]
@@ -477,13 +560,20 @@ impl<'a> SliceContext<'a> {
                 info.sao_type_idx[c_idx] = sao_type_idx;
 
                 if sao_type_idx != 0 {
-                    let bit_depth = if c_idx == 0 {
-                        self.sps.bit_depth_y() as u32
+                    let (bit_depth, log2_offset_scale) = if c_idx == 0 {
+                        (
+                            self.sps.bit_depth_y() as u32,
+                            self.pps.range_extension.log2_sao_offset_scale_luma,
+                        )
                     } else {
-                        self.sps.bit_depth_c() as u32
+                        (
+                            self.sps.bit_depth_c() as u32,
+                            self.pps.range_extension.log2_sao_offset_scale_chroma,
+                        )
                     };
                     let c_max = (1u32 << (bit_depth.min(10) - 5)) - 1;
-                    let offset_scale = 1i32 << (bit_depth.saturating_sub(bit_depth.min(10)));
+                    // SaoOffsetVal = offset << log2OffsetScale (H.265 7.4.9.3.2)
+                    let offset_scale = 1i32 << log2_offset_scale;
 
                     let mut offsets_abs = [0u32; 4];
                     for elem in &mut offsets_abs {
@@ -493,12 +583,12 @@ impl<'a> SliceContext<'a> {
 
                     if sao_type_idx == 1 {
                         // Band offset: decode signs + band position
-                        let mut signed_offsets = [0i8; 4];
+                        let mut signed_offsets = [0i16; 4];
                         for i in 0..4 {
                             if offsets_abs[i] != 0 {
                                 let sign = self.cabac.decode_bypass()?;
                                 se_trace("sao_offset_sign", sign as i64, &self.cabac);
-                                let val = (offsets_abs[i] as i32 * offset_scale) as i8;
+                                let val = (offsets_abs[i] as i32 * offset_scale) as i16;
                                 signed_offsets[i] = if sign != 0 { -val } else { val };
                             }
                         }
@@ -510,7 +600,7 @@ impl<'a> SliceContext<'a> {
                     } else {
                         // Edge offset: store absolute values (sign applied during filtering)
                         for (i, &offset) in offsets_abs.iter().enumerate() {
-                            info.sao_offset_val[c_idx][i] = (offset as i32 * offset_scale) as i8;
+                            info.sao_offset_val[c_idx][i] = (offset as i32 * offset_scale) as i16;
                         }
 
                         if c_idx <= 1 {
@@ -692,12 +782,9 @@ impl<'a> SliceContext<'a> {
         }
     }
 
-    /// Check if a neighbor position is available (within picture bounds)
+    /// Check if a neighbor position is available (decoded, in the same slice)
     fn is_neighbor_available(&self, x: i32, y: i32) -> bool {
-        x >= 0
-            && y >= 0
-            && (x as u32) < self.sps.pic_width_in_luma_samples
-            && (y as u32) < self.sps.pic_height_in_luma_samples
+        self.motion.is_available(self.slice_idx, x, y)
     }
 
     /// Decode split_cu_flag using CABAC
@@ -744,7 +831,6 @@ impl<'a> SliceContext<'a> {
         frame: &mut DecodedFrame,
     ) -> Result<()> {
         let cb_size = 1u32 << log2_cb_size;
-        let _ = cb_size; // Used in PartNxN
 
         // Track CU base position for transform unit QP derivation
         self.cu_base_x = x0;
@@ -758,9 +844,6 @@ impl<'a> SliceContext<'a> {
         // Set ct_depth for this CU (used by split_cu_flag context derivation)
         self.set_ct_depth(x0, y0, log2_cb_size, ct_depth);
 
-        // For I-slices, prediction mode is always INTRA
-        let pred_mode = PredMode::Intra;
-
         // Decode transquant_bypass_flag if enabled
         self.cu_transquant_bypass_flag = if self.pps.transquant_bypass_enabled_flag {
             let ctx_idx = context::CU_TRANSQUANT_BYPASS_FLAG;
@@ -769,6 +852,35 @@ impl<'a> SliceContext<'a> {
             false
         };
 
+        // P/B slices signal skipped CUs and the prediction mode of each CU
+        let pred_mode = if self.header.slice_type.is_intra() {
+            PredMode::Intra
+        } else if self.decode_cu_skip_flag(x0, y0)? {
+            PredMode::Skip
+        } else if self.cabac.decode_bin(&mut self.ctx[context::PRED_MODE_FLAG])? != 0 {
+            PredMode::Intra
+        } else {
+            PredMode::Inter
+        };
+        self.cu_pred_mode = pred_mode;
+
+        if pred_mode != PredMode::Intra {
+            self.decode_inter_coding_unit(x0, y0, log2_cb_size, ct_depth, frame)?;
+            frame.store_block_qp(x0, y0, cb_size, self.current_qpy as i8);
+            return Ok(());
+        }
+        self.motion.set(
+            x0,
+            y0,
+            cb_size,
+            cb_size,
+            BlockInfo {
+                motion: PbMotion::default(),
+                flags: BLOCK_DECODED | BLOCK_INTRA,
+                slice: self.slice_idx,
+            },
+        );
+
         // Decode partition mode
         let part_mode = if log2_cb_size == self.sps.log2_min_cb_size() {
             // At minimum size, can be 2Nx2N or NxN
@@ -882,37 +994,307 @@ impl<'a> SliceContext<'a> {
             }
         };
 
-        // Decode rqt_root_cbf (residual quad-tree coded block flag)
-        // For intra, this is always coded (not signaled, assumed 1)
-        // unless transquant_bypass is enabled
-        if !self.cu_transquant_bypass_flag {
-            // Decode transform tree
-            let intra_split_flag = part_mode == PartMode::PartNxN;
+        // rqt_root_cbf is not coded for intra CUs and inferred to be 1
+        let intra_split_flag = part_mode == PartMode::PartNxN;
+        self.decode_transform_tree(
+            x0,
+            y0,
+            log2_cb_size,
+            0, // trafo_depth
+            intra_luma_mode,
+            intra_chroma_mode,
+            intra_split_flag,
+            frame,
+        )?;
+
+        if self.debug_ctu {
+            let (r, o) = self.cabac.get_state();
+            debug_trace!(
+                "  CTU37: After transform_tree at ({},{}) log2={} (r={},o={})",
+                x0,
+                y0,
+                log2_cb_size,
+                r,
+                o
+            );
+        }
+
+        // QpY applies to the whole CU, including TUs decoded before cu_qp_delta
+        frame.store_block_qp(x0, y0, cb_size, self.current_qpy as i8);
+
+        Ok(())
+    }
+
+    /// Decode cu_skip_flag; the context depends on skipped left/above neighbours
+    fn decode_cu_skip_flag(&mut self, x0: u32, y0: u32) -> Result<bool> {
+        let skipped = |x: i32, y: i32| {
+            self.is_neighbor_available(x, y)
+                && self.motion.block(x as u32, y as u32).flags & BLOCK_SKIP != 0
+        };
+        let cond_l = skipped(x0 as i32 - 1, y0 as i32) as usize;
+        let cond_a = skipped(x0 as i32, y0 as i32 - 1) as usize;
+        let ctx_idx = context::CU_SKIP_FLAG + cond_l + cond_a;
+        let bin = self.cabac.decode_bin(&mut self.ctx[ctx_idx])?;
+        se_trace("cu_skip_flag", bin as i64, &self.cabac);
+        Ok(bin != 0)
+    }
+
+    /// Decode the prediction units and residual of an inter or skipped CU
+    fn decode_inter_coding_unit(
+        &mut self,
+        x0: u32,
+        y0: u32,
+        log2_cb_size: u8,
+        ct_depth: u8,
+        frame: &mut DecodedFrame,
+    ) -> Result<()> {
+        let cb_size = 1u32 << log2_cb_size;
+        let skip = self.cu_pred_mode == PredMode::Skip;
+        let part_mode = if skip {
+            PartMode::Part2Nx2N
+        } else {
+            self.decode_part_mode(PredMode::Inter, log2_cb_size)?
+        };
+
+        let (parts, num_parts) = prediction_blocks(part_mode, cb_size);
+        let mut merge_flag = false;
+        for (part_idx, &(dx, dy, w, h)) in parts[..num_parts].iter().enumerate() {
+            let pb = PredictionBlock {
+                x_cb: x0,
+                y_cb: y0,
+                n_cb_s: cb_size,
+                x_pb: x0 + dx,
+                y_pb: y0 + dy,
+                w,
+                h,
+                part_idx: part_idx as u8,
+                part_mode,
+            };
+            merge_flag = self.decode_prediction_unit(&pb, ct_depth, frame)?;
+        }
+
+        // Coding block edges are transform block edges even without residual
+        frame.mark_tu_boundary(x0, y0, cb_size);
+
+        let rqt_root_cbf = if skip {
+            false
+        } else if part_mode == PartMode::Part2Nx2N && merge_flag {
+            true
+        } else {
+            let bin = self.cabac.decode_bin(&mut self.ctx[context::RQT_ROOT_CBF])?;
+            se_trace("rqt_root_cbf", bin as i64, &self.cabac);
+            bin != 0
+        };
+
+        if rqt_root_cbf {
+            // interSplitFlag: without transform hierarchy, non-square
+            // partitions still split the residual quadtree once
+            let inter_split_flag =
+                self.sps.max_transform_hierarchy_depth_inter == 0 && part_mode != PartMode::Part2Nx2N;
             self.decode_transform_tree(
                 x0,
                 y0,
                 log2_cb_size,
-                0, // trafo_depth
-                intra_luma_mode,
-                intra_chroma_mode,
-                intra_split_flag,
+                0,
+                IntraPredMode::Dc,
+                IntraPredMode::Dc,
+                inter_split_flag,
                 frame,
             )?;
+        }
 
-            if self.debug_ctu {
-                let (r, o) = self.cabac.get_state();
-                debug_trace!(
-                    "  CTU37: After transform_tree at ({},{}) log2={} (r={},o={})",
-                    x0,
-                    y0,
-                    log2_cb_size,
-                    r,
-                    o
+        Ok(())
+    }
+
+    /// Decode one prediction unit, derive its motion and predict its samples
+    ///
+    /// Returns merge_flag.
+    fn decode_prediction_unit(
+        &mut self,
+        pb: &PredictionBlock,
+        ct_depth: u8,
+        frame: &mut DecodedFrame,
+    ) -> Result<bool> {
+        let skip = self.cu_pred_mode == PredMode::Skip;
+        let merge_flag = skip || {
+            let bin = self.cabac.decode_bin(&mut self.ctx[context::MERGE_FLAG])?;
+            se_trace("merge_flag", bin as i64, &self.cabac);
+            bin != 0
+        };
+
+        let motion = if merge_flag {
+            let merge_idx = self.decode_merge_idx()?;
+            self.motion.derive_merge(&self.mv_context(), pb, merge_idx)
+        } else {
+            let inter_pred_idc = if self.header.slice_type == SliceType::B {
+                self.decode_inter_pred_idc(pb.w + pb.h, ct_depth)?
+            } else {
+                PRED_L0
+            };
+
+            let mut motion = PbMotion::default();
+            let mut mvd = [Mv::default(); 2];
+            let mut mvp_flag = [0u8; 2];
+            for list in 0..2 {
+                if inter_pred_idc != PRED_BI && inter_pred_idc != list as u8 {
+                    continue;
+                }
+                let num_active = self.header.num_ref_idx_active[list];
+                let ref_idx = if num_active > 1 {
+                    self.decode_ref_idx(num_active - 1)?
+                } else {
+                    0
+                };
+                if list == 0 || !(self.header.mvd_l1_zero_flag && inter_pred_idc == PRED_BI) {
+                    mvd[list] = self.decode_mvd()?;
+                }
+                mvp_flag[list] = self.cabac.decode_bin(&mut self.ctx[context::MVP_LX_FLAG])?;
+                se_trace("mvp_flag", mvp_flag[list] as i64, &self.cabac);
+                motion.pred_flag[list] = true;
+                motion.ref_idx[list] = ref_idx as i8;
+            }
+
+            let mv_ctx = self.mv_context();
+            for list in 0..2 {
+                if motion.pred_flag[list] {
+                    let mvp = self.motion.derive_amvp(&mv_ctx, pb, list, motion.ref_idx[list], mvp_flag[list]);
+                    motion.mv[list] = Mv {
+                        x: mvp.x.wrapping_add(mvd[list].x),
+                        y: mvp.y.wrapping_add(mvd[list].y),
+                    };
+                }
+            }
+            motion
+        };
+
+        let mut refs = [None; 2];
+        for (list, reference) in refs.iter_mut().enumerate() {
+            if motion.pred_flag[list] {
+                *reference = Some(
+                    *self.ref_frames[list]
+                        .get(motion.ref_idx[list] as usize)
+                        .ok_or(HevcError::InvalidBitstream("ref_idx out of range"))?,
                 );
             }
         }
+        inter::predict_block(
+            frame,
+            self.sps,
+            refs,
+            &motion,
+            pb.x_pb,
+            pb.y_pb,
+            pb.w,
+            pb.h,
+            self.header.pred_weight_table.as_ref(),
+            &mut self.inter_bufs,
+        );
 
-        Ok(())
+        self.motion.set(
+            pb.x_pb,
+            pb.y_pb,
+            pb.w,
+            pb.h,
+            BlockInfo {
+                motion,
+                flags: BLOCK_DECODED | if skip { BLOCK_SKIP } else { 0 },
+                slice: self.slice_idx,
+            },
+        );
+        frame.mark_pu_boundary(pb.x_pb, pb.y_pb, pb.w, pb.h);
+
+        Ok(merge_flag)
+    }
+
+    /// Motion vector prediction context of the current slice
+    fn mv_context(&self) -> MvContext<'a> {
+        MvContext {
+            sps: self.sps,
+            pps: self.pps,
+            header: self.header,
+            slice: self.slice_idx,
+            poc: self.poc,
+            col: self.col,
+        }
+    }
+
+    /// Decode merge_idx: truncated rice, first bin context coded
+    fn decode_merge_idx(&mut self) -> Result<u8> {
+        let c_max = self.header.max_num_merge_cand.saturating_sub(1);
+        let mut idx = 0;
+        if c_max > 0 && self.cabac.decode_bin(&mut self.ctx[context::MERGE_IDX])? != 0 {
+            idx = 1;
+            while idx < c_max && self.cabac.decode_bypass()? != 0 {
+                idx += 1;
+            }
+        }
+        se_trace("merge_idx", idx as i64, &self.cabac);
+        Ok(idx)
+    }
+
+    /// Decode inter_pred_idc (PRED_L0, PRED_L1 or PRED_BI)
+    fn decode_inter_pred_idc(&mut self, pb_size_sum: u32, ct_depth: u8) -> Result<u8> {
+        // 8x4 and 4x8 blocks cannot be bi-predicted
+        if pb_size_sum != 12 {
+            let ctx_idx = context::INTER_PRED_IDC + ct_depth as usize;
+            if self.cabac.decode_bin(&mut self.ctx[ctx_idx])? != 0 {
+                se_trace("inter_pred_idc", PRED_BI as i64, &self.cabac);
+                return Ok(PRED_BI);
+            }
+        }
+        let idc = self.cabac.decode_bin(&mut self.ctx[context::INTER_PRED_IDC + 4])?;
+        se_trace("inter_pred_idc", idc as i64, &self.cabac);
+        Ok(idc)
+    }
+
+    /// Decode ref_idx_lX: truncated rice, first two bins context coded
+    fn decode_ref_idx(&mut self, c_max: u8) -> Result<u8> {
+        let mut idx = 0;
+        while idx < c_max {
+            let bin = if idx < 2 {
+                self.cabac.decode_bin(&mut self.ctx[context::REF_IDX + idx as usize])?
+            } else {
+                self.cabac.decode_bypass()?
+            };
+            if bin == 0 {
+                break;
+            }
+            idx += 1;
+        }
+        se_trace("ref_idx", idx as i64, &self.cabac);
+        Ok(idx)
+    }
+
+    /// Decode mvd_coding (7.3.8.9)
+    fn decode_mvd(&mut self) -> Result<Mv> {
+        let mut greater0 = [false; 2];
+        for flag in &mut greater0 {
+            *flag = self.cabac.decode_bin(&mut self.ctx[context::ABS_MVD_GREATER0_FLAG])? != 0;
+        }
+        let mut greater1 = [false; 2];
+        for (flag, &g0) in greater1.iter_mut().zip(&greater0) {
+            if g0 {
+                *flag = self.cabac.decode_bin(&mut self.ctx[context::ABS_MVD_GREATER1_FLAG])? != 0;
+            }
+        }
+        let mut mvd = [0i32; 2];
+        for comp in 0..2 {
+            if greater0[comp] {
+                let abs = if greater1[comp] {
+                    self.cabac.decode_egk_bypass(1)? as i32 + 2
+                } else {
+                    1
+                };
+                mvd[comp] = if self.cabac.decode_bypass()? != 0 { -abs } else { abs };
+            }
+        }
+        se_trace("mvd_x", mvd[0] as i64, &self.cabac);
+        se_trace("mvd_y", mvd[1] as i64, &self.cabac);
+        // MvdLX is in -2^15..2^15-1 for conforming streams
+        Ok(Mv {
+            x: mvd[0] as i16,
+            y: mvd[1] as i16,
+        })
     }
 
     /// Decode transform tree recursively
@@ -928,7 +1310,7 @@ impl<'a> SliceContext<'a> {
         intra_split_flag: bool,
         frame: &mut DecodedFrame,
     ) -> Result<()> {
-        // For 4:2:0, start with root having chroma responsibility
+        // Start with root having chroma responsibility
         self.decode_transform_tree_inner(
             x0,
             y0,
@@ -937,14 +1319,15 @@ impl<'a> SliceContext<'a> {
             intra_luma_mode,
             intra_chroma_mode,
             intra_split_flag,
-            true,
-            true,
+            [true; 2],
+            [true; 2],
             frame,
         )
     }
 
     /// Inner transform tree decoding
-    /// cbf_cb_parent/cbf_cr_parent: whether parent says chroma has residuals (or true at root)
+    /// cbf_cb_parent/cbf_cr_parent: whether parent says chroma has residuals (or true at root).
+    /// Index 1 is the lower square chroma block, only coded for 4:2:2.
     #[allow(clippy::too_many_arguments)]
     fn decode_transform_tree_inner(
         &mut self,
@@ -955,13 +1338,19 @@ impl<'a> SliceContext<'a> {
         intra_luma_mode: IntraPredMode,
         intra_chroma_mode: IntraPredMode,
         intra_split_flag: bool,
-        cbf_cb_parent: bool,
-        cbf_cr_parent: bool,
+        cbf_cb_parent: [bool; 2],
+        cbf_cr_parent: [bool; 2],
         frame: &mut DecodedFrame,
     ) -> Result<()> {
         // Per H.265: MaxTrafoDepth = max_transform_hierarchy_depth_intra + IntraSplitFlag
-        let max_trafo_depth =
-            self.sps.max_transform_hierarchy_depth_intra + if intra_split_flag { 1 } else { 0 };
+        // for intra CUs. For inter CUs `intra_split_flag` carries interSplitFlag,
+        // which forces the first split without raising MaxTrafoDepth.
+        let intra = self.cu_pred_mode == PredMode::Intra;
+        let max_trafo_depth = if intra {
+            self.sps.max_transform_hierarchy_depth_intra + if intra_split_flag { 1 } else { 0 }
+        } else {
+            self.sps.max_transform_hierarchy_depth_inter
+        };
         let log2_min_trafo_size = self.sps.log2_min_tb_size();
         let log2_max_trafo_size = self.sps.log2_max_tb_size();
 
@@ -1003,32 +1392,33 @@ impl<'a> SliceContext<'a> {
         };
 
         // Step 2: Decode cbf_cb and cbf_cr
-        // For 4:2:0, decode chroma cbf at this level if log2_size > 2
-        // cbf_cb/cbf_cr decoded if log2_size > 2 AND (trafoDepth == 0 OR parent cbf is set)
-        let (cbf_cb, cbf_cr) = if log2_size > 2 {
-            // Decode cbf_cb if trafo_depth == 0 (always) or parent had cbf_cb
-            let cb = if trafo_depth == 0 || cbf_cb_parent {
-                let ctx_idx = context::CBF_CBCR + trafo_depth as usize;
-                let val = self.cabac.decode_bin(&mut self.ctx[ctx_idx])? != 0;
-                se_trace("cbf_cb", val as i64, &self.cabac);
-                val
-            } else {
-                false
-            };
-            // Decode cbf_cr if trafo_depth == 0 (always) or parent had cbf_cr
-            let cr = if trafo_depth == 0 || cbf_cr_parent {
-                let ctx_idx = context::CBF_CBCR + trafo_depth as usize;
-                let val = self.cabac.decode_bin(&mut self.ctx[ctx_idx])? != 0;
-                se_trace("cbf_cr", val as i64, &self.cabac);
-                val
+        // Chroma cbf is decoded at this level if log2_size > 2 (or always for 4:4:4)
+        // AND (trafoDepth == 0 OR parent cbf is set).
+        // For 4:2:2 the chroma TB is two stacked squares; both cbfs are coded
+        // where the chroma TB is not split further (leaf, or 8x8 splitting to 4x4).
+        let chroma_array_type = self.sps.chroma_array_type();
+        let (cbf_cb, cbf_cr) =
+            if (log2_size > 2 && chroma_array_type != 0) || chroma_array_type == 3 {
+                let two_blocks = chroma_array_type == 2 && (!split_transform || log2_size == 3);
+                // Decode cbf_cb if trafo_depth == 0 (always) or parent had cbf_cb
+                let cb = if trafo_depth == 0 || cbf_cb_parent[0] {
+                    self.decode_cbf_chroma(trafo_depth, two_blocks, "cbf_cb")?
+                } else {
+                    [false; 2]
+                };
+                // Decode cbf_cr if trafo_depth == 0 (always) or parent had cbf_cr
+                let cr = if trafo_depth == 0 || cbf_cr_parent[0] {
+                    self.decode_cbf_chroma(trafo_depth, two_blocks, "cbf_cr")?
+                } else {
+                    [false; 2]
+                };
+                (cb, cr)
+            } else if chroma_array_type != 0 {
+                // log2_size == 2: inherit from parent (chroma decoded at parent level)
+                (cbf_cb_parent, cbf_cr_parent)
             } else {
-                false
+                ([false; 2], [false; 2])
             };
-            (cb, cr)
-        } else {
-            // log2_size == 2: inherit from parent (chroma decoded at parent level)
-            (cbf_cb_parent, cbf_cr_parent)
-        };
 
         if split_transform {
             let half = 1u32 << (log2_size - 1);
@@ -1084,23 +1474,10 @@ impl<'a> SliceContext<'a> {
                 frame,
             )?;
 
-            // For 4:2:0, if we split from 8x8 to 4x4, predict + decode chroma now
-            // (because 4x4 children can't have chroma TUs)
-            if log2_size == 3 {
-                let sis = self.sps.strong_intra_smoothing_enabled_flag;
-                let scan_order = residual::get_scan_order(2, intra_chroma_mode.as_u8(), 1);
-
-                // Predict and apply Cb
-                intra::predict_intra(frame, x0 / 2, y0 / 2, 2, intra_chroma_mode, 1, sis);
-                if cbf_cb {
-                    self.decode_and_apply_residual(x0 / 2, y0 / 2, 2, 1, scan_order, frame)?;
-                }
-
-                // Predict and apply Cr
-                intra::predict_intra(frame, x0 / 2, y0 / 2, 2, intra_chroma_mode, 2, sis);
-                if cbf_cr {
-                    self.decode_and_apply_residual(x0 / 2, y0 / 2, 2, 2, scan_order, frame)?;
-                }
+            // For 4:2:0 and 4:2:2, if we split from 8x8 to 4x4, predict + decode
+            // chroma now (because 4x4 children can't have chroma TUs)
+            if log2_size == 3 && chroma_array_type != 0 && chroma_array_type != 3 {
+                self.decode_chroma_blocks(x0, y0, 2, intra_chroma_mode, cbf_cb, cbf_cr, frame)?;
             }
         } else {
             // Decode transform unit (leaf node)
@@ -1135,24 +1512,30 @@ impl<'a> SliceContext<'a> {
         trafo_depth: u8,
         _intra_luma_mode: IntraPredMode,
         intra_chroma_mode: IntraPredMode,
-        cbf_cb: bool,
-        cbf_cr: bool,
+        cbf_cb: [bool; 2],
+        cbf_cr: [bool; 2],
         frame: &mut DecodedFrame,
     ) -> Result<()> {
         let debug_tt = self.debug_ctu;
+        let cbf_chroma = cbf_cb[0] || cbf_cb[1] || cbf_cr[0] || cbf_cr[1];
 
         // Decode cbf_luma - per H.265 spec 7.3.8.6:
         // cbf_luma is coded if: CuPredMode == MODE_INTRA || trafoDepth != 0 || cbf_cb || cbf_cr
-        // For INTRA mode (all HEIC images), cbf_luma is ALWAYS coded
+        // and otherwise inferred to be 1 (an inter CU with rqt_root_cbf has some residual)
         // Context: offset 0 if trafo_depth > 0, offset 1 if trafo_depth == 0
-        let ctx_offset = if trafo_depth == 0 { 1 } else { 0 };
-        let ctx_idx = context::CBF_LUMA + ctx_offset;
-        let cbf_luma = self.cabac.decode_bin(&mut self.ctx[ctx_idx])? != 0;
+        let intra = self.cu_pred_mode == PredMode::Intra;
+        let cbf_luma = if intra || trafo_depth != 0 || cbf_chroma {
+            let ctx_offset = if trafo_depth == 0 { 1 } else { 0 };
+            let ctx_idx = context::CBF_LUMA + ctx_offset;
+            self.cabac.decode_bin(&mut self.ctx[ctx_idx])? != 0
+        } else {
+            true
+        };
         se_trace("cbf_luma", cbf_luma as i64, &self.cabac);
 
         // Per H.265 7.3.8.11: decode cu_qp_delta before residuals
-        // Condition: (cbf_luma || cbf_cb || cbf_cr) && cu_qp_delta_enabled_flag && !IsCuQpDeltaCoded
-        if (cbf_luma || cbf_cb || cbf_cr)
+        // Condition: (cbf_luma || cbfChroma) && cu_qp_delta_enabled_flag && !IsCuQpDeltaCoded
+        if (cbf_luma || cbf_chroma)
             && self.pps.cu_qp_delta_enabled_flag
             && !self.is_cu_qp_delta_coded
         {
@@ -1180,15 +1563,20 @@ impl<'a> SliceContext<'a> {
         frame.mark_tu_boundary(x0, y0, tu_size);
         frame.store_block_qp(x0, y0, tu_size, self.current_qpy as i8);
 
-        // Look up intra mode at actual TU position (correct for NxN where sub-TUs differ)
-        let actual_luma_mode = self.get_intra_mode_at(x0, y0);
-        let sis = self.sps.strong_intra_smoothing_enabled_flag;
+        let scan_order = if intra {
+            // Look up intra mode at actual TU position (correct for NxN where sub-TUs differ)
+            let actual_luma_mode = self.get_intra_mode_at(x0, y0);
+            let sis = self.sps.strong_intra_smoothing_enabled_flag;
+            let isd = self.sps.range_extension.intra_smoothing_disabled_flag;
 
-        // Predict luma at TU level BEFORE residual application
-        // This ensures each TU reads reconstructed neighbors from prior TUs
-        intra::predict_intra(frame, x0, y0, log2_size, actual_luma_mode, 0, sis);
+            // Predict luma at TU level BEFORE residual application
+            // This ensures each TU reads reconstructed neighbors from prior TUs
+            intra::predict_intra(frame, x0, y0, log2_size, actual_luma_mode, 0, sis, isd);
 
-        let scan_order = residual::get_scan_order(log2_size, actual_luma_mode.as_u8(), 0);
+            residual::get_scan_order(log2_size, actual_luma_mode.as_u8(), 0)
+        } else {
+            ScanOrder::Diagonal
+        };
 
         // Decode and apply luma residuals (adds to prediction already in frame)
         if cbf_luma {
@@ -1204,61 +1592,108 @@ impl<'a> SliceContext<'a> {
                 );
             }
             self.decode_and_apply_residual(x0, y0, log2_size, 0, scan_order, frame)?;
+            self.motion.add_flags(x0, y0, tu_size, tu_size, BLOCK_CODED);
         }
 
         // Decode chroma: predict + residual per component if not handled by parent
-        if log2_size >= 3 {
-            let chroma_log2_size = log2_size - 1;
-            let chroma_scan_order =
-                residual::get_scan_order(chroma_log2_size, intra_chroma_mode.as_u8(), 1);
-
-            // Predict and apply Cb
-            intra::predict_intra(
-                frame,
-                x0 / 2,
-                y0 / 2,
-                chroma_log2_size,
+        let chroma_array_type = self.sps.chroma_array_type();
+        if chroma_array_type == 3 || (chroma_array_type != 0 && log2_size >= 3) {
+            let log2_size_c = if chroma_array_type == 3 {
+                log2_size
+            } else {
+                log2_size - 1
+            };
+            self.decode_chroma_blocks(
+                x0,
+                y0,
+                log2_size_c,
                 intra_chroma_mode,
-                1,
-                sis,
-            );
-            if cbf_cb {
-                self.decode_and_apply_residual(
-                    x0 / 2,
-                    y0 / 2,
-                    chroma_log2_size,
-                    1,
-                    chroma_scan_order,
-                    frame,
-                )?;
-            }
-
-            // Predict and apply Cr
-            intra::predict_intra(
+                cbf_cb,
+                cbf_cr,
                 frame,
-                x0 / 2,
-                y0 / 2,
-                chroma_log2_size,
-                intra_chroma_mode,
-                2,
-                sis,
-            );
-            if cbf_cr {
-                self.decode_and_apply_residual(
-                    x0 / 2,
-                    y0 / 2,
-                    chroma_log2_size,
-                    2,
-                    chroma_scan_order,
-                    frame,
-                )?;
-            }
+            )?;
         }
         // Note: if log2_size < 3, chroma was predicted+decoded by parent when splitting from 8x8
 
         Ok(())
     }
 
+    /// Decode cbf_cb or cbf_cr at the given transform depth.
+    ///
+    /// With `two_blocks` (4:2:2), a second flag for the lower square chroma
+    /// block follows, using the same context.
+    fn decode_cbf_chroma(
+        &mut self,
+        trafo_depth: u8,
+        two_blocks: bool,
+        name: &str,
+    ) -> Result<[bool; 2]> {
+        let ctx_idx = context::CBF_CBCR + trafo_depth as usize;
+        let mut cbf = [false; 2];
+        for flag in cbf.iter_mut().take(if two_blocks { 2 } else { 1 }) {
+            *flag = self.cabac.decode_bin(&mut self.ctx[ctx_idx])? != 0;
+            se_trace(name, *flag as i64, &self.cabac);
+        }
+        Ok(cbf)
+    }
+
+    /// Predict and reconstruct the Cb and Cr blocks of a transform unit.
+    ///
+    /// `(x0, y0)` is the luma position of the TU and `log2_size_c` the chroma
+    /// block size. For 4:2:2 the chroma TB is twice as tall as it is wide and is
+    /// coded as two square blocks stacked vertically; the upper block is fully
+    /// reconstructed before the lower one is predicted from it.
+    #[allow(clippy::too_many_arguments)]
+    fn decode_chroma_blocks(
+        &mut self,
+        x0: u32,
+        y0: u32,
+        log2_size_c: u8,
+        intra_chroma_mode: IntraPredMode,
+        cbf_cb: [bool; 2],
+        cbf_cr: [bool; 2],
+        frame: &mut DecodedFrame,
+    ) -> Result<()> {
+        let xc = x0 / self.sps.sub_width_c();
+        let yc = y0 / self.sps.sub_height_c();
+        let num_blocks = if self.sps.chroma_array_type() == 2 {
+            2
+        } else {
+            1
+        };
+        let sis = self.sps.strong_intra_smoothing_enabled_flag;
+        let isd = self.sps.range_extension.intra_smoothing_disabled_flag;
+        let intra = self.cu_pred_mode == PredMode::Intra;
+        let scan_order = if intra {
+            residual::get_scan_order(log2_size_c, intra_chroma_mode.as_u8(), 1)
+        } else {
+            ScanOrder::Diagonal
+        };
+
+        for (c_idx, cbf) in [(1u8, cbf_cb), (2u8, cbf_cr)] {
+            for (blk, &coded) in cbf.iter().enumerate().take(num_blocks) {
+                let yb = yc + ((blk as u32) << log2_size_c);
+                if intra {
+                    intra::predict_intra(
+                        frame,
+                        xc,
+                        yb,
+                        log2_size_c,
+                        intra_chroma_mode,
+                        c_idx,
+                        sis,
+                        isd,
+                    );
+                }
+                if coded {
+                    self.decode_and_apply_residual(xc, yb, log2_size_c, c_idx, scan_order, frame)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Decode cu_qp_delta_abs per H.265 section 7.3.8.11
     /// TU prefix (up to 5 context-coded bins) + EGk bypass suffix
     fn decode_cu_qp_delta_abs(&mut self) -> Result<u32> {
@@ -1297,6 +1732,15 @@ impl<'a> SliceContext<'a> {
         scan_order: ScanOrder,
         frame: &mut DecodedFrame,
     ) -> Result<()> {
+        let (qp, bit_depth) = match c_idx {
+            0 => (self.qp_y, self.sps.bit_depth_y()),
+            1 => (self.qp_cb, self.sps.bit_depth_c()),
+            2 => (self.qp_cr, self.sps.bit_depth_c()),
+            _ => (self.qp_y, self.sps.bit_depth_y()),
+        };
+        let extended_precision = self.sps.range_extension.extended_precision_processing_flag;
+        let log2_range = self.sps.log2_transform_range(bit_depth);
+
         // Decode coefficients via CABAC
         let (mut coeff_buf, transform_skip) = residual::decode_residual(
             &mut self.cabac,
@@ -1307,6 +1751,9 @@ impl<'a> SliceContext<'a> {
             self.pps.sign_data_hiding_enabled_flag,
             self.cu_transquant_bypass_flag,
             self.pps.transform_skip_enabled_flag,
+            self.pps.log2_max_transform_skip_size(),
+            log2_range,
+            extended_precision,
             x0,
             y0,
         )?;
@@ -1317,16 +1764,24 @@ impl<'a> SliceContext<'a> {
 
         let size = 1usize << log2_size;
         let num_coeffs = size * size;
+        let intra = self.cu_pred_mode == PredMode::Intra;
 
-        // Dequantize coefficients in-place
-        let coeffs = &mut coeff_buf.coeffs;
+        // Lossless CUs use the coefficients as the residual (8.6.2)
+        if self.cu_transquant_bypass_flag {
+            let max_val = (1i32 << bit_depth) - 1;
+            let (plane, stride) = frame.plane_mut(c_idx);
+            for py in 0..size {
+                let row_start = (y0 as usize + py) * stride + x0 as usize;
+                for px in 0..size {
+                    if let Some(sample) = plane.get_mut(row_start + px) {
+                        let r = coeff_buf.coeffs[py * size + px];
+                        *sample = (*sample as i32 + r).clamp(0, max_val) as u16;
+                    }
+                }
+            }
+            return Ok(());
+        }
 
-        let (qp, bit_depth) = match c_idx {
-            0 => (self.qp_y, self.sps.bit_depth_y()),
-            1 => (self.qp_cb, self.sps.bit_depth_c()),
-            2 => (self.qp_cr, self.sps.bit_depth_c()),
-            _ => (self.qp_y, self.sps.bit_depth_y()),
-        };
         let dequant_params = transform::DequantParams {
             qp,
             bit_depth,
@@ -1334,8 +1789,9 @@ impl<'a> SliceContext<'a> {
         };
 
         // Use scaling list if enabled (H.265 8.6.3)
-        // Per spec: use PPS scaling list if present, else SPS scaling list
-        let scaling_list = if self.sps.scaling_list_enabled_flag && !transform_skip {
+        // Per spec: use PPS scaling list if present, else SPS scaling list.
+        // Transform-skip blocks larger than 4x4 use the flat m = 16.
+        let scaling_list = if self.sps.scaling_list_enabled_flag && (!transform_skip || size == 4) {
             self.pps
                 .pps_scaling_list
                 .as_ref()
@@ -1345,8 +1801,8 @@ impl<'a> SliceContext<'a> {
         };
 
         if let Some(sl) = scaling_list {
-            // matrixId: intra Y=0, Cb=1, Cr=2 (all HEIC is intra)
-            let matrix_id = c_idx;
+            // matrixId: intra Y=0, Cb=1, Cr=2; inter Y=3, Cb=4, Cr=5
+            let matrix_id = if intra { c_idx } else { 3 + c_idx };
             // Build scaling matrix in raster order for this TU (reuse persistent buffer)
             let scaling_matrix = &mut self.scaling_buf;
             for py in 0..size {
@@ -1355,10 +1811,69 @@ impl<'a> SliceContext<'a> {
                         sl.get_scaling_factor(log2_size, matrix_id, px as u32, py as u32);
                 }
             }
+        }
+
+        // Above 12 bits (or with extended precision) coefficients and residuals
+        // no longer fit the 16-bit SIMD pipeline
+        if extended_precision || bit_depth > 12 {
+            let coeffs = &mut coeff_buf.coeffs;
+            transform::dequantize_wide(
+                &mut coeffs[..num_coeffs],
+                dequant_params,
+                scaling_list.map(|_| &self.scaling_buf[..num_coeffs]),
+                log2_range,
+            );
+
+            let mut residual = [0i32; transform::MAX_COEFF];
+            if transform_skip {
+                transform::transform_skip_wide(
+                    coeffs,
+                    &mut residual,
+                    log2_size,
+                    bit_depth,
+                    extended_precision,
+                );
+            } else {
+                let is_intra_4x4_luma = intra && log2_size == 2 && c_idx == 0;
+                transform::inverse_transform_wide(
+                    coeffs,
+                    &mut residual,
+                    size,
+                    bit_depth,
+                    is_intra_4x4_luma,
+                    log2_range,
+                    extended_precision,
+                );
+            }
+
+            let max_val = (1i64 << bit_depth) - 1;
+            let (plane, stride) = frame.plane_mut(c_idx);
+            for py in 0..size {
+                let row_start = (y0 as usize + py) * stride + x0 as usize;
+                for px in 0..size {
+                    let idx = row_start + px;
+                    if idx < plane.len() {
+                        let pred = plane[idx] as i64;
+                        let r = residual[py * size + px] as i64;
+                        plane[idx] = (pred + r).clamp(0, max_val) as u16;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        // Coefficients are within CoeffMin..=CoeffMax = i16 range here
+        let coeffs = &mut self.coeff_buf;
+        for (dst, &src) in coeffs[..num_coeffs].iter_mut().zip(&coeff_buf.coeffs) {
+            *dst = src as i16;
+        }
+
+        // Dequantize coefficients in-place
+        if scaling_list.is_some() {
             transform::dequantize_scaled(
                 &mut coeffs[..num_coeffs],
                 dequant_params,
-                &scaling_matrix[..num_coeffs],
+                &self.scaling_buf[..num_coeffs],
             );
         } else {
             transform::dequantize(&mut coeffs[..num_coeffs], dequant_params);
@@ -1384,7 +1899,7 @@ impl<'a> SliceContext<'a> {
                 residual[i] = ((c + rnd) >> bd_shift) as i16;
             }
         } else {
-            let is_intra_4x4_luma = log2_size == 2 && c_idx == 0;
+            let is_intra_4x4_luma = intra && log2_size == 2 && c_idx == 0;
             transform::inverse_transform(
                 coeffs,
                 residual,
@@ -1436,8 +1951,42 @@ impl<'a> SliceContext<'a> {
                 }
             }
         } else {
-            // Inter partition modes (not implemented)
-            Err(HevcError::Unsupported("inter partition modes"))
+            // Inter binarization (Table 9-43): 1 = 2Nx2N, then the split
+            // direction, then NxN or an asymmetric partition
+            let log2_min_cb_size = self.sps.log2_min_cb_size();
+            let part_mode = if self.cabac.decode_bin(&mut self.ctx[context::PART_MODE])? != 0 {
+                PartMode::Part2Nx2N
+            } else {
+                let horizontal = self.cabac.decode_bin(&mut self.ctx[context::PART_MODE + 1])? != 0;
+                if log2_cb_size == log2_min_cb_size {
+                    if horizontal {
+                        PartMode::Part2NxN
+                    } else if log2_cb_size == 3
+                        || self.cabac.decode_bin(&mut self.ctx[context::PART_MODE + 2])? != 0
+                    {
+                        PartMode::PartNx2N
+                    } else {
+                        PartMode::PartNxN
+                    }
+                } else if !self.sps.amp_enabled_flag
+                    || self.cabac.decode_bin(&mut self.ctx[context::PART_MODE + 3])? != 0
+                {
+                    if horizontal {
+                        PartMode::Part2NxN
+                    } else {
+                        PartMode::PartNx2N
+                    }
+                } else {
+                    match (horizontal, self.cabac.decode_bypass()? != 0) {
+                        (true, false) => PartMode::Part2NxnU,
+                        (true, true) => PartMode::Part2NxnD,
+                        (false, false) => PartMode::PartnLx2N,
+                        (false, true) => PartMode::PartnRx2N,
+                    }
+                }
+            };
+            se_trace("part_mode", part_mode as i64, &self.cabac);
+            Ok(part_mode)
         }
     }
 
@@ -1507,13 +2056,19 @@ impl<'a> SliceContext<'a> {
     /// - First bin (context-coded): if 0 → mode 4 (derived from luma)
     /// - If first bin is 1: read 2 fixed-length bypass bits → modes 0-3
     /// - If candidate mode collides with luma mode → Angular34
+    /// - For 4:2:2, the result is remapped through Table 8-3
     fn decode_intra_chroma_mode(&mut self, luma_mode: IntraPredMode) -> Result<IntraPredMode> {
+        // Not coded without chroma (monochrome or separate colour planes)
+        if self.sps.chroma_array_type() == 0 {
+            return Ok(luma_mode);
+        }
+
         let ctx_idx = context::INTRA_CHROMA_PRED_MODE;
         let first_bin = self.cabac.decode_bin(&mut self.ctx[ctx_idx])?;
         if first_bin == 0 {
             // Mode 4: derived from luma
             se_trace("intra_chroma_mode", 4, &self.cabac);
-            return Ok(luma_mode);
+            return Ok(self.map_chroma_mode(luma_mode));
         }
 
         // Read 2 fixed-length bypass bits for modes 0-3
@@ -1534,7 +2089,16 @@ impl<'a> SliceContext<'a> {
             candidate
         };
 
-        Ok(intra_chroma_mode)
+        Ok(self.map_chroma_mode(intra_chroma_mode))
+    }
+
+    /// Apply the 4:2:2 chroma mode mapping (Table 8-3) when ChromaArrayType == 2
+    fn map_chroma_mode(&self, mode: IntraPredMode) -> IntraPredMode {
+        if self.sps.chroma_array_type() == 2 {
+            intra::map_chroma_mode_422(mode)
+        } else {
+            mode
+        }
     }
 
     /// Decode intra prediction modes (luma + chroma) for Part2Nx2N
@@ -1697,10 +2261,14 @@ impl<'a> SliceContext<'a> {
         Ok(val)
     }
 
-    /// H.265 Table 8-6: chroma QP mapping for 4:2:0
-    fn chroma_qp_from_luma(qpi: i32) -> i32 {
+    /// H.265 Table 8-10: chroma QP mapping
+    ///
+    /// The table only applies to 4:2:0; other chroma formats use Min(qPi, 51).
+    fn chroma_qp_from_luma(qpi: i32, chroma_array_type: u8) -> i32 {
         static TAB8_22: [i32; 13] = [29, 30, 31, 32, 33, 33, 34, 34, 35, 35, 36, 36, 37];
-        if qpi < 30 {
+        if chroma_array_type != 1 {
+            qpi.min(51)
+        } else if qpi < 30 {
             qpi
         } else if qpi >= 43 {
             qpi - 6
@@ -1828,7 +2396,7 @@ impl<'a> SliceContext<'a> {
             self.qp_y = 0;
         }
 
-        // Compute chroma QP (4:2:0)
+        // Compute chroma QP
         let qp_bd_offset_c = 6 * (self.sps.bit_depth_c() as i32 - 8);
         let qpi_cb =
             (qpy + self.pps.pps_cb_qp_offset as i32 + self.header.slice_cb_qp_offset as i32)
@@ -1837,8 +2405,9 @@ impl<'a> SliceContext<'a> {
             (qpy + self.pps.pps_cr_qp_offset as i32 + self.header.slice_cr_qp_offset as i32)
                 .clamp(-qp_bd_offset_c, 57);
 
-        self.qp_cb = Self::chroma_qp_from_luma(qpi_cb) + qp_bd_offset_c;
-        self.qp_cr = Self::chroma_qp_from_luma(qpi_cr) + qp_bd_offset_c;
+        let chroma_array_type = self.sps.chroma_array_type();
+        self.qp_cb = Self::chroma_qp_from_luma(qpi_cb, chroma_array_type) + qp_bd_offset_c;
+        self.qp_cr = Self::chroma_qp_from_luma(qpi_cr, chroma_array_type) + qp_bd_offset_c;
 
         self.current_qpy = qpy;
     }
@@ -1,9 +1,18 @@
 //! Deblocking filter (H.265 Section 8.7.2)
 //!
-//! Applies strong/weak filtering at CU and TU boundaries to reduce blocking artifacts.
-//! For I-slices (HEIC still images), all boundaries have bS=2 since both sides are intra-coded.
+//! Applies strong/weak filtering at CU, PU and TU boundaries to reduce blocking artifacts.
+//! Edges next to intra blocks have bS=2; inter edges get bS=1 or 0 depending on
+//! coded residuals and motion (8.7.2.4). Chroma is only filtered where bS=2.
 
-use super::picture::{DEBLOCK_FLAG_HORIZ, DEBLOCK_FLAG_VERT, DecodedFrame};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::motion::{BLOCK_CODED, BLOCK_INTRA, BlockInfo, MotionField, Mv};
+use super::picture::{
+    DEBLOCK_FLAG_HORIZ, DEBLOCK_FLAG_PU_HORIZ, DEBLOCK_FLAG_PU_VERT, DEBLOCK_FLAG_VERT,
+    DecodedFrame,
+};
 
 /// Beta prime values for deblocking filter (Table 8-12)
 /// Index 0-51 maps QP to beta prime threshold
@@ -31,9 +40,13 @@ static CHROMA_QP_TABLE: [i32; 13] = [
     29, 30, 31, 32, 33, 33, 34, 34, 35, 35, 36, 36, 37,
 ];
 
-/// Map intermediate chroma QP to actual chroma QP (4:2:0)
-fn chroma_qp_mapping(qp_i: i32) -> i32 {
-    if qp_i < 30 {
+/// Map intermediate chroma QP to actual chroma QP
+///
+/// Table 8-10 only applies to 4:2:0; other chroma formats use Min(qPi, 51).
+fn chroma_qp_mapping(qp_i: i32, chroma_format: u8) -> i32 {
+    if chroma_format != 1 {
+        qp_i.min(51)
+    } else if qp_i < 30 {
         qp_i
     } else if qp_i >= 43 {
         qp_i - 6
@@ -42,33 +55,147 @@ fn chroma_qp_mapping(qp_i: i32) -> i32 {
     }
 }
 
+/// Boundary filtering strength of the left (vertical) or top edge of every
+/// 4x4 block on the 8x8 grid (8.7.2.3, 8.7.2.4)
+///
+/// Only blocks in luma rows `rows` are evaluated; the result is indexed from
+/// the first block of `rows.start`.
+///
+/// Edges are not filtered in slices with slice_deblocking_filter_disabled_flag,
+/// nor across the boundary of a slice that disables filtering across slices;
+/// both are decided by the slice containing the q block.
+fn edge_strengths(
+    frame: &DecodedFrame,
+    motion: &MotionField,
+    vertical: bool,
+    rows: &Range<u32>,
+) -> Vec<u8> {
+    let (tu_flag, pu_flag) = if vertical {
+        (DEBLOCK_FLAG_VERT, DEBLOCK_FLAG_PU_VERT)
+    } else {
+        (DEBLOCK_FLAG_HORIZ, DEBLOCK_FLAG_PU_HORIZ)
+    };
+    let stride = frame.deblock_stride;
+    let base = (rows.start / 4 * stride) as usize;
+    let end = ((rows.end.div_ceil(4) * stride) as usize).min(frame.deblock_flags.len());
+    let mut strengths = vec![0u8; end.saturating_sub(base)];
+    for (idx, &flags) in frame.deblock_flags[base.min(end)..end].iter().enumerate() {
+        let idx = idx + base;
+        let (bx, by) = (idx as u32 % stride, idx as u32 / stride);
+        let grid = if vertical { bx } else { by };
+        if flags & (tu_flag | pu_flag) == 0 || grid == 0 || grid % 2 != 0 {
+            continue;
+        }
+        let (xq, yq) = (bx * 4, by * 4);
+        if xq >= frame.width || yq >= frame.height {
+            continue;
+        }
+        let (xp, yp) = if vertical { (xq - 4, yq) } else { (xq, yq - 4) };
+        let q = motion.block(xq, yq);
+        let p = motion.block(xp, yp);
+        let Some(slice) = motion.slices.get(q.slice as usize) else {
+            continue;
+        };
+        if slice.deblocking_disabled || (p.slice != q.slice && !slice.loop_filter_across_slices) {
+            continue;
+        }
+        strengths[idx - base] = if (p.flags | q.flags) & BLOCK_INTRA != 0 {
+            2
+        } else if flags & tu_flag != 0 && (p.flags | q.flags) & BLOCK_CODED != 0 {
+            1
+        } else {
+            motion_differs(motion, p, q) as u8
+        };
+    }
+    strengths
+}
+
+/// Whether p and q use different reference pictures, a different number of
+/// motion vectors, or vectors at least one integer sample apart
+fn motion_differs(motion: &MotionField, p: &BlockInfo, q: &BlockInfo) -> bool {
+    // Reference pictures are compared by identity (POC), not by list index
+    let refs = |b: &BlockInfo| {
+        let slice = motion.slices.get(b.slice as usize);
+        [0, 1].map(|list| {
+            b.motion.pred_flag[list].then(|| {
+                let poc = slice
+                    .and_then(|s| s.refs[list].get(b.motion.ref_idx[list] as usize))
+                    .map_or(i32::MIN, |r| r.poc);
+                (poc, b.motion.mv[list])
+            })
+        })
+    };
+    let far = |a: Mv, b: Mv| (a.x as i32 - b.x as i32).abs() >= 4 || (a.y as i32 - b.y as i32).abs() >= 4;
+
+    match (refs(p), refs(q)) {
+        ([Some((rp0, mp0)), Some((rp1, mp1))], [Some((rq0, mq0)), Some((rq1, mq1))]) => {
+            if !((rp0 == rq0 && rp1 == rq1) || (rp0 == rq1 && rp1 == rq0)) {
+                true
+            } else if rp0 != rp1 {
+                // Compare the vectors that refer to the same picture
+                if rp0 == rq0 {
+                    far(mp0, mq0) || far(mp1, mq1)
+                } else {
+                    far(mp0, mq1) || far(mp1, mq0)
+                }
+            } else {
+                // Both vectors refer to the same picture: either pairing may match
+                (far(mp0, mq0) || far(mp1, mq1)) && (far(mp0, mq1) || far(mp1, mq0))
+            }
+        }
+        ([Some(a), None] | [None, Some(a)], [Some(b), None] | [None, Some(b)]) => {
+            a.0 != b.0 || far(a.1, b.1)
+        }
+        _ => true,
+    }
+}
+
 /// Apply the deblocking filter to a decoded frame.
 ///
-/// `beta_offset` and `tc_offset` come from slice header (slice_beta_offset_div2 * 2
-/// and slice_tc_offset_div2 * 2).
+/// The slice of each edge's q block (from `motion`) provides the beta and tC
+/// offsets and whether the edge is filtered at all.
 /// `cb_qp_offset` and `cr_qp_offset` come from PPS (pps_cb_qp_offset / pps_cr_qp_offset).
 pub fn apply_deblocking_filter(
     frame: &mut DecodedFrame,
-    beta_offset: i32,
-    tc_offset: i32,
+    motion: &MotionField,
     cb_qp_offset: i32,
     cr_qp_offset: i32,
 ) {
-    let width = frame.width;
     let height = frame.height;
+    deblock_rows(frame, motion, cb_qp_offset, cr_qp_offset, 0..height);
+}
+
+/// Apply the deblocking filter to the vertical edges in luma rows `rows` and
+/// the horizontal edges whose q side starts in them.
+///
+/// `rows` must start on the 8x8 grid. Filtering consecutive ranges from the
+/// top gives the same result as [`apply_deblocking_filter`]: horizontal edges
+/// read at most four rows above them, which the previous range has already
+/// filtered vertically.
+pub fn deblock_rows(
+    frame: &mut DecodedFrame,
+    motion: &MotionField,
+    cb_qp_offset: i32,
+    cr_qp_offset: i32,
+    rows: Range<u32>,
+) {
+    let width = frame.width;
+    let rows = rows.start..rows.end.min(frame.height);
+    let base = (rows.start / 4 * frame.deblock_stride) as usize;
+    let bs_vert = edge_strengths(frame, motion, true, &rows);
+    let bs_horiz = edge_strengths(frame, motion, false, &rows);
 
     // Pass 1: Vertical edges
     // Process at 8-sample intervals in x, 4-sample intervals in y
     let mut x = 8u32;
     while x < width {
-        let mut y = 0u32;
-        while y < height {
+        let mut y = rows.start;
+        while y < rows.end {
             let bx = x / 4;
             let by = y / 4;
             let idx = (by * frame.deblock_stride + bx) as usize;
-            if idx < frame.deblock_flags.len()
-                && (frame.deblock_flags[idx] & DEBLOCK_FLAG_VERT) != 0
-            {
+            if bs_vert.get(idx - base).is_some_and(|&bs| bs != 0) {
+                let slice = &motion.slices[motion.block(x, y).slice as usize];
                 // Get QP on both sides
                 let qp_q = frame.qp_map[idx] as i32;
                 let qp_p = if bx > 0 {
@@ -77,7 +204,17 @@ pub fn apply_deblocking_filter(
                     qp_q
                 };
 
-                filter_edge_luma(frame, x, y, true, qp_p, qp_q, beta_offset, tc_offset);
+                filter_edge_luma(
+                    frame,
+                    x,
+                    y,
+                    true,
+                    qp_p,
+                    qp_q,
+                    bs_vert[idx - base] as i32,
+                    slice.beta_offset,
+                    slice.tc_offset,
+                );
             }
             y += 4;
         }
@@ -86,16 +223,15 @@ pub fn apply_deblocking_filter(
 
     // Pass 2: Horizontal edges
     // Process at 4-sample intervals in x, 8-sample intervals in y
-    let mut y = 8u32;
-    while y < height {
+    let mut y = rows.start.next_multiple_of(8).max(8);
+    while y < rows.end {
         let mut x = 0u32;
         while x < width {
             let bx = x / 4;
             let by = y / 4;
             let idx = (by * frame.deblock_stride + bx) as usize;
-            if idx < frame.deblock_flags.len()
-                && (frame.deblock_flags[idx] & DEBLOCK_FLAG_HORIZ) != 0
-            {
+            if bs_horiz.get(idx - base).is_some_and(|&bs| bs != 0) {
+                let slice = &motion.slices[motion.block(x, y).slice as usize];
                 let qp_q = frame.qp_map[idx] as i32;
                 let qp_p = if by > 0 {
                     frame.qp_map[((by - 1) * frame.deblock_stride + bx) as usize] as i32
@@ -103,16 +239,33 @@ pub fn apply_deblocking_filter(
                     qp_q
                 };
 
-                filter_edge_luma(frame, x, y, false, qp_p, qp_q, beta_offset, tc_offset);
+                filter_edge_luma(
+                    frame,
+                    x,
+                    y,
+                    false,
+                    qp_p,
+                    qp_q,
+                    bs_horiz[idx - base] as i32,
+                    slice.beta_offset,
+                    slice.tc_offset,
+                );
             }
             x += 4;
         }
         y += 8;
     }
 
-    // Chroma deblocking (only for bS=2, which is all edges for I-slices)
+    // Chroma deblocking (only for bS=2)
     if frame.chroma_format > 0 {
-        apply_chroma_deblocking(frame, tc_offset, cb_qp_offset, cr_qp_offset);
+        apply_chroma_deblocking(
+            frame,
+            motion,
+            [&bs_vert, &bs_horiz],
+            cb_qp_offset,
+            cr_qp_offset,
+            rows,
+        );
     }
 }
 
@@ -130,15 +283,13 @@ fn filter_edge_luma(
     vertical: bool,
     qp_p: i32,
     qp_q: i32,
+    bs: i32,
     beta_offset: i32,
     tc_offset: i32,
 ) {
     let bit_depth = frame.bit_depth as i32;
     let max_val = (1i32 << bit_depth) - 1;
 
-    // For I-slices, bS is always 2 at boundaries
-    let bs = 2i32;
-
     // Compute thresholds
     let qp_l = (qp_q + qp_p + 1) >> 1;
     let q_beta = (qp_l + beta_offset).clamp(0, 51);
@@ -299,21 +450,26 @@ fn filter_edge_luma(
 
 /// Apply chroma deblocking filter
 ///
-/// For I-slices, all edges have bS=2, so chroma deblocking applies everywhere.
-/// Chroma deblocking only modifies p0 and q0 (one sample on each side).
+/// Only edges with bS=2 (`strengths` holds the vertical and horizontal edge
+/// strengths of luma rows `rows`) are filtered. Chroma deblocking only
+/// modifies p0 and q0 (one sample on each side).
 fn apply_chroma_deblocking(
     frame: &mut DecodedFrame,
-    tc_offset: i32,
+    motion: &MotionField,
+    strengths: [&[u8]; 2],
     cb_qp_offset: i32,
     cr_qp_offset: i32,
+    rows: Range<u32>,
 ) {
     let width = frame.width;
     let height = frame.height;
+    let base = (rows.start / 4 * frame.deblock_stride) as usize;
     let bit_depth_c = frame.bit_depth as i32; // Same as luma for typical HEIC
     let max_val = (1i32 << bit_depth_c) - 1;
 
     // Chroma subsampling factors
-    let (sub_x, sub_y) = match frame.chroma_format {
+    let chroma_format = frame.chroma_format;
+    let (sub_x, sub_y) = match chroma_format {
         1 => (2u32, 2u32),
         2 => (2, 1),
         3 => (1, 1),
@@ -325,6 +481,9 @@ fn apply_chroma_deblocking(
     let c_width = width / sub_x;
 
     // For 4:2:0: chroma edges are at 8-chroma-pixel intervals (16 luma pixels).
+    // For 4:2:2 the horizontal edge grid stays at 8 luma rows since chroma is
+    // full height. Edges are still taken from the luma transform tree, so the
+    // midline between the two square chroma blocks of a 4:2:2 TU is not filtered.
     // Per H.265 8.7.2, chroma deblocking requires both sides to have width/height >= 8
     // in chroma samples. The edge processing unit is 4 chroma samples along the edge.
     //
@@ -338,14 +497,13 @@ fn apply_chroma_deblocking(
     // Pass 1: Vertical edges
     let mut x = x_step_vert;
     while x < width {
-        let mut y = 0u32;
-        while y < height {
+        let mut y = rows.start;
+        while y < rows.end {
             let bx = x / 4;
             let by = y / 4;
             let idx = (by * frame.deblock_stride + bx) as usize;
-            if idx < frame.deblock_flags.len()
-                && (frame.deblock_flags[idx] & DEBLOCK_FLAG_VERT) != 0
-            {
+            if strengths[0].get(idx - base) == Some(&2) {
+                let tc_offset = motion.slices[motion.block(x, y).slice as usize].tc_offset;
                 let qp_q = frame.qp_map[idx] as i32;
                 let qp_p = if bx > 0 {
                     frame.qp_map[(by * frame.deblock_stride + bx - 1) as usize] as i32
@@ -363,7 +521,7 @@ fn apply_chroma_deblocking(
                         cr_qp_offset
                     };
                     let qp_i = ((qp_q + qp_p + 1) >> 1) + qp_offset;
-                    let qp_c = chroma_qp_mapping(qp_i);
+                    let qp_c = chroma_qp_mapping(qp_i, chroma_format);
                     let q_tc = (qp_c + 2 + tc_offset).clamp(0, 53);
                     let tc = (TC_PRIME[q_tc as usize] as i32) << (bit_depth_c - 8);
 
@@ -406,16 +564,15 @@ fn apply_chroma_deblocking(
     }
 
     // Pass 2: Horizontal edges
-    let mut y = y_step_horiz;
-    while y < height {
+    let mut y = rows.start.next_multiple_of(y_step_horiz).max(y_step_horiz);
+    while y < rows.end {
         let mut x = 0u32;
         while x < width {
             let bx = x / 4;
             let by = y / 4;
             let idx = (by * frame.deblock_stride + bx) as usize;
-            if idx < frame.deblock_flags.len()
-                && (frame.deblock_flags[idx] & DEBLOCK_FLAG_HORIZ) != 0
-            {
+            if strengths[1].get(idx - base) == Some(&2) {
+                let tc_offset = motion.slices[motion.block(x, y).slice as usize].tc_offset;
                 let qp_q = frame.qp_map[idx] as i32;
                 let qp_p = if by > 0 {
                     frame.qp_map[((by - 1) * frame.deblock_stride + bx) as usize] as i32
@@ -433,7 +590,7 @@ fn apply_chroma_deblocking(
                         cr_qp_offset
                     };
                     let qp_i = ((qp_q + qp_p + 1) >> 1) + qp_offset;
-                    let qp_c = chroma_qp_mapping(qp_i);
+                    let qp_c = chroma_qp_mapping(qp_i, chroma_format);
                     let q_tc = (qp_c + 2 + tc_offset).clamp(0, 53);
                     let tc = (TC_PRIME[q_tc as usize] as i32) << (bit_depth_c - 8);
 
@@ -476,3 +633,22 @@ fn apply_chroma_deblocking(
         y += y_step_horiz;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chroma_qp_mapping() {
+        // Table 8-10 for 4:2:0
+        assert_eq!(chroma_qp_mapping(29, 1), 29);
+        assert_eq!(chroma_qp_mapping(34, 1), 33);
+        assert_eq!(chroma_qp_mapping(45, 1), 39);
+        // Min(qPi, 51) for 4:2:2 and 4:4:4
+        for chroma_format in [2, 3] {
+            assert_eq!(chroma_qp_mapping(34, chroma_format), 34);
+            assert_eq!(chroma_qp_mapping(45, chroma_format), 45);
+            assert_eq!(chroma_qp_mapping(55, chroma_format), 51);
+        }
+    }
+}
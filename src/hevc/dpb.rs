@@ -0,0 +1,588 @@
+//! Decoded picture buffer (H.265 8.3 and C.5.2)
+//!
+//! Derives picture order counts, applies the reference picture set of each
+//! picture, builds reference picture lists and outputs pictures in POC
+//! order using the "bumping" process. Inter-layer reference pictures of
+//! the current picture are held alongside the layer's own pictures until
+//! the picture is stored.
+
+use alloc::vec::Vec;
+
+use super::bitstream::NalType;
+use super::motion::{ColPicture, MotionField, RefPicInfo};
+use super::params::Sps;
+use super::picture::DecodedFrame;
+use super::slice::SliceHeader;
+use crate::error::HevcError;
+
+type Result<T> = core::result::Result<T, HevcError>;
+
+/// Reference marking of a picture in the DPB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefMarking {
+    /// Unused for reference
+    Unused,
+    /// Used for short-term reference
+    ShortTerm,
+    /// Used for long-term reference
+    LongTerm,
+}
+
+/// A decoded picture stored in the DPB
+#[derive(Debug)]
+pub struct DpbPicture {
+    /// Unique id, increasing in decoding order
+    id: u64,
+    /// Reconstructed picture
+    pub frame: DecodedFrame,
+    /// Motion of the picture (for TMVP)
+    pub motion: MotionField,
+    /// PicOrderCntVal
+    pub poc: i32,
+    /// Reference marking
+    pub marking: RefMarking,
+    /// Marked as "needed for output"
+    pub needed_for_output: bool,
+    /// Inter-layer reference picture of the current picture
+    inter_layer: bool,
+    /// Caller's tag of the access unit the picture was decoded from
+    tag: u64,
+    /// PicLatencyCount
+    latency_count: u32,
+    /// Position in output order once bumped, until handed to the caller
+    output_seq: Option<u64>,
+}
+
+/// Reference picture set of the current picture, as DPB picture ids (8.3.2)
+#[derive(Debug, Clone, Default)]
+pub struct RefPicSet {
+    /// RefPicSetStCurrBefore
+    pub st_curr_before: Vec<u64>,
+    /// RefPicSetStCurrAfter
+    pub st_curr_after: Vec<u64>,
+    /// RefPicSetLtCurr
+    pub lt_curr: Vec<u64>,
+    /// RefPicSetInterLayer0 and RefPicSetInterLayer1 (F.8.3.4)
+    pub inter_layer: [Vec<u64>; 2],
+}
+
+/// Reference picture lists of one slice
+pub struct RefPicLists<'a> {
+    /// Pictures of RefPicList0 and RefPicList1
+    pub frames: [Vec<&'a DecodedFrame>; 2],
+    /// POC and long-term marking of every list entry
+    pub info: [Vec<RefPicInfo>; 2],
+    /// Collocated picture for temporal motion vector prediction
+    pub col: Option<ColPicture<'a>>,
+}
+
+/// Decoded picture buffer and picture sequencing state
+#[derive(Debug)]
+pub struct Dpb {
+    pictures: Vec<DpbPicture>,
+    next_id: u64,
+    next_output_seq: u64,
+    /// PicOrderCntVal of the previous TemporalId 0 picture
+    prev_tid0_poc: i32,
+    /// The next picture starts a new coded video sequence (start, after EOS)
+    first_in_sequence: bool,
+    /// RASL pictures are skipped (associated IRAP had NoRaslOutputFlag)
+    skip_rasl: bool,
+}
+
+impl Default for Dpb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dpb {
+    /// Create an empty DPB
+    pub fn new() -> Self {
+        Self {
+            pictures: Vec::new(),
+            next_id: 0,
+            next_output_seq: 0,
+            prev_tid0_poc: 0,
+            first_in_sequence: true,
+            skip_rasl: false,
+        }
+    }
+
+    /// Start decoding a picture
+    ///
+    /// Derives the POC (8.3.1), applies the RPS (8.3.2), generating
+    /// unavailable reference pictures, and outputs or removes pictures
+    /// before the current picture is decoded (C.5.2.2). Returns None for a
+    /// RASL picture that cannot be decoded and must be skipped.
+    pub fn start_picture(
+        &mut self,
+        nal_type: NalType,
+        temporal_id: u8,
+        header: &SliceHeader,
+        sps: &Sps,
+    ) -> Option<(i32, RefPicSet)> {
+        let irap = nal_type.is_irap();
+        let no_rasl_output = irap && (nal_type.is_idr() || self.first_in_sequence);
+        if irap {
+            self.skip_rasl = no_rasl_output;
+        }
+        if nal_type.is_rasl() && self.skip_rasl {
+            return None;
+        }
+        self.first_in_sequence = false;
+
+        // 8.3.1 picture order count
+        let max_lsb = 1i32 << (sps.log2_max_pic_order_cnt_lsb_minus4 + 4);
+        let lsb = header.slice_pic_order_cnt_lsb as i32;
+        let msb = if irap && no_rasl_output {
+            0
+        } else {
+            let prev_lsb = self.prev_tid0_poc & (max_lsb - 1);
+            let prev_msb = self.prev_tid0_poc - prev_lsb;
+            if lsb < prev_lsb && prev_lsb - lsb >= max_lsb / 2 {
+                prev_msb + max_lsb
+            } else if lsb > prev_lsb && lsb - prev_lsb > max_lsb / 2 {
+                prev_msb - max_lsb
+            } else {
+                prev_msb
+            }
+        };
+        let poc = msb + lsb;
+        if temporal_id == 0
+            && !nal_type.is_rasl()
+            && !nal_type.is_radl()
+            && !nal_type.is_sub_layer_non_reference()
+        {
+            self.prev_tid0_poc = poc;
+        }
+
+        // 8.3.2 reference picture set
+        if irap && no_rasl_output {
+            for pic in &mut self.pictures {
+                pic.marking = RefMarking::Unused;
+            }
+        }
+        let rps = self.apply_rps(header, sps, poc, max_lsb);
+
+        // C.5.2.2 output and removal of pictures
+        if irap && no_rasl_output {
+            if header.no_output_of_prior_pics_flag {
+                for pic in &mut self.pictures {
+                    pic.needed_for_output = false;
+                }
+            }
+            while self.bump() {}
+        } else {
+            self.remove_unused();
+            while self.needs_bumping(sps, true) && self.bump() {}
+        }
+        self.remove_unused();
+
+        Some((poc, rps))
+    }
+
+    /// Mark reference pictures per the RPS of the current picture (8.3.2)
+    fn apply_rps(&mut self, header: &SliceHeader, sps: &Sps, poc: i32, max_lsb: i32) -> RefPicSet {
+        let st = &header.st_rps;
+        let mut st_curr_before = Vec::new();
+        let mut st_curr_after = Vec::new();
+        let mut st_foll = Vec::new();
+        for (delta, &used) in st.delta_poc_s0.iter().zip(&st.used_by_curr_pic_s0) {
+            if used {
+                st_curr_before.push(poc + delta);
+            } else {
+                st_foll.push(poc + delta);
+            }
+        }
+        for (delta, &used) in st.delta_poc_s1.iter().zip(&st.used_by_curr_pic_s1) {
+            if used {
+                st_curr_after.push(poc + delta);
+            } else {
+                st_foll.push(poc + delta);
+            }
+        }
+
+        // Long-term pictures are identified by their full POC when the MSB
+        // cycle is signalled, otherwise by the POC LSBs
+        let mut lt_curr = Vec::new();
+        let mut lt_foll = Vec::new();
+        for lt in &header.long_term_refs {
+            let mut poc_lt = lt.poc_lsb as i32;
+            if lt.delta_poc_msb_present_flag {
+                poc_lt += poc
+                    - (lt.delta_poc_msb_cycle as i32).wrapping_mul(max_lsb)
+                    - (poc & (max_lsb - 1));
+            }
+            let entry = (poc_lt, lt.delta_poc_msb_present_flag);
+            if lt.used_by_curr_pic {
+                lt_curr.push(entry);
+            } else {
+                lt_foll.push(entry);
+            }
+        }
+
+        let find_lt = |pictures: &[DpbPicture], (poc_lt, full): (i32, bool)| {
+            pictures
+                .iter()
+                .find(|p| {
+                    p.marking != RefMarking::Unused
+                        && if full {
+                            p.poc == poc_lt
+                        } else {
+                            p.poc & (max_lsb - 1) == poc_lt
+                        }
+                })
+                .map(|p| p.id)
+        };
+        let lt_curr_ids: Vec<Option<u64>> = lt_curr
+            .iter()
+            .map(|&e| find_lt(&self.pictures, e))
+            .collect();
+        let lt_foll_ids: Vec<u64> = lt_foll
+            .iter()
+            .filter_map(|&e| find_lt(&self.pictures, e))
+            .collect();
+        for pic in &mut self.pictures {
+            if lt_curr_ids.contains(&Some(pic.id)) || lt_foll_ids.contains(&pic.id) {
+                pic.marking = RefMarking::LongTerm;
+            }
+        }
+
+        let find_st = |pictures: &[DpbPicture], poc: i32| {
+            pictures
+                .iter()
+                .find(|p| p.marking == RefMarking::ShortTerm && p.poc == poc)
+                .map(|p| p.id)
+        };
+        let before_ids: Vec<Option<u64>> = st_curr_before
+            .iter()
+            .map(|&p| find_st(&self.pictures, p))
+            .collect();
+        let after_ids: Vec<Option<u64>> = st_curr_after
+            .iter()
+            .map(|&p| find_st(&self.pictures, p))
+            .collect();
+        let foll_ids: Vec<u64> = st_foll
+            .iter()
+            .filter_map(|&p| find_st(&self.pictures, p))
+            .collect();
+
+        // Everything not in the RPS is no longer used for reference
+        for pic in &mut self.pictures {
+            let id = Some(pic.id);
+            if !(lt_curr_ids.contains(&id)
+                || lt_foll_ids.contains(&pic.id)
+                || before_ids.contains(&id)
+                || after_ids.contains(&id)
+                || foll_ids.contains(&pic.id))
+            {
+                pic.marking = RefMarking::Unused;
+            }
+        }
+
+        // Generate unavailable reference pictures (8.3.3)
+        let mut resolve =
+            |ids: Vec<Option<u64>>, pocs: Vec<i32>, marking: RefMarking| -> Vec<u64> {
+                ids.into_iter()
+                    .zip(pocs)
+                    .map(|(id, poc)| id.unwrap_or_else(|| self.generate_missing(sps, poc, marking)))
+                    .collect()
+            };
+        RefPicSet {
+            st_curr_before: resolve(before_ids, st_curr_before, RefMarking::ShortTerm),
+            st_curr_after: resolve(after_ids, st_curr_after, RefMarking::ShortTerm),
+            lt_curr: resolve(
+                lt_curr_ids,
+                lt_curr.iter().map(|&(p, _)| p).collect(),
+                RefMarking::LongTerm,
+            ),
+            inter_layer: [Vec::new(), Vec::new()],
+        }
+    }
+
+    /// Insert a mid-grey intra picture for a missing reference (8.3.3.2)
+    fn generate_missing(&mut self, sps: &Sps, poc: i32, marking: RefMarking) -> u64 {
+        let (width, height) = (
+            sps.pic_width_in_luma_samples,
+            sps.pic_height_in_luma_samples,
+        );
+        let mut frame =
+            DecodedFrame::with_params(width, height, sps.bit_depth_y(), sps.chroma_format_idc);
+        frame.y_plane.fill(1 << (sps.bit_depth_y() - 1));
+        frame.cb_plane.fill(1 << (sps.bit_depth_c() - 1));
+        frame.cr_plane.fill(1 << (sps.bit_depth_c() - 1));
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pictures.push(DpbPicture {
+            id,
+            frame,
+            motion: MotionField::intra(width, height),
+            poc,
+            marking,
+            needed_for_output: false,
+            inter_layer: false,
+            tag: 0,
+            latency_count: 0,
+            output_seq: None,
+        });
+        id
+    }
+
+    /// Add an inter-layer reference picture of the current picture (F.8.3.4)
+    ///
+    /// The picture is marked as used for long-term reference and has the
+    /// current picture's POC. It stays until the current picture is stored.
+    pub fn insert_inter_layer(
+        &mut self,
+        frame: DecodedFrame,
+        motion: MotionField,
+        poc: i32,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pictures.push(DpbPicture {
+            id,
+            frame,
+            motion,
+            poc,
+            marking: RefMarking::LongTerm,
+            needed_for_output: false,
+            inter_layer: true,
+            tag: 0,
+            latency_count: 0,
+            output_seq: None,
+        });
+        id
+    }
+
+    /// The picture decoded last, if it is still in the DPB
+    pub fn latest(&self) -> Option<&DpbPicture> {
+        self.pictures
+            .iter()
+            .filter(|p| !p.inter_layer)
+            .max_by_key(|p| p.id)
+    }
+
+    /// Build the reference picture lists of a slice (8.3.4, F.8.3.4)
+    pub fn ref_pic_lists(&self, rps: &RefPicSet, header: &SliceHeader) -> Result<RefPicLists<'_>> {
+        let [inter_layer0, inter_layer1] = &rps.inter_layer;
+        let num_pic_total_curr = rps.st_curr_before.len()
+            + rps.st_curr_after.len()
+            + rps.lt_curr.len()
+            + inter_layer0.len()
+            + inter_layer1.len();
+        let mut frames = [Vec::new(), Vec::new()];
+        let mut info = [Vec::new(), Vec::new()];
+        if header.slice_type.is_intra() {
+            return Ok(RefPicLists {
+                frames,
+                info,
+                col: None,
+            });
+        }
+        if num_pic_total_curr == 0 {
+            return Err(HevcError::InvalidBitstream(
+                "inter slice without reference pictures",
+            ));
+        }
+
+        let num_lists = if header.slice_type == super::slice::SliceType::B {
+            2
+        } else {
+            1
+        };
+        for list in 0..num_lists {
+            let num_active = header.num_ref_idx_active[list] as usize;
+            // Each inter-layer set follows the short-term pictures on its own
+            // side; the other one comes last
+            let (first, second) = if list == 0 {
+                (&rps.st_curr_before, &rps.st_curr_after)
+            } else {
+                (&rps.st_curr_after, &rps.st_curr_before)
+            };
+            let (own, other) = if list == 0 {
+                (inter_layer0, inter_layer1)
+            } else {
+                (inter_layer1, inter_layer0)
+            };
+            let mut temp = Vec::with_capacity(num_active.max(num_pic_total_curr));
+            while temp.len() < num_active.max(num_pic_total_curr) {
+                let short_term = first.iter().chain(own).chain(second);
+                temp.extend(short_term.chain(&rps.lt_curr).chain(other));
+            }
+            for idx in 0..num_active {
+                let entry = match &header.list_entry[list] {
+                    Some(entries) => *entries.get(idx).unwrap_or(&0) as usize,
+                    None => idx,
+                };
+                let id = *temp
+                    .get(entry)
+                    .ok_or(HevcError::InvalidBitstream("list_entry out of range"))?;
+                let pic = self
+                    .pictures
+                    .iter()
+                    .find(|p| p.id == id)
+                    .ok_or(HevcError::InvalidBitstream("reference picture missing"))?;
+                frames[list].push(&pic.frame);
+                info[list].push(RefPicInfo {
+                    poc: pic.poc,
+                    long_term: pic.marking == RefMarking::LongTerm,
+                });
+            }
+        }
+
+        let col = if header.slice_temporal_mvp_enabled_flag {
+            let list = if header.collocated_from_l0_flag { 0 } else { 1 };
+            let id_poc = info[list]
+                .get(header.collocated_ref_idx as usize)
+                .map(|r| r.poc);
+            id_poc.and_then(|poc| {
+                self.pictures
+                    .iter()
+                    .find(|p| p.poc == poc && p.marking != RefMarking::Unused)
+                    .map(|p| ColPicture {
+                        field: &p.motion,
+                        poc: p.poc,
+                    })
+            })
+        } else {
+            None
+        };
+
+        Ok(RefPicLists { frames, info, col })
+    }
+
+    /// Store the decoded current picture and run the "additional bumping"
+    /// process (C.5.2.3)
+    pub fn insert(
+        &mut self,
+        frame: DecodedFrame,
+        motion: MotionField,
+        poc: i32,
+        output: bool,
+        tag: u64,
+        sps: &Sps,
+    ) {
+        self.pictures.retain(|p| !p.inter_layer);
+        for pic in &mut self.pictures {
+            if pic.needed_for_output {
+                pic.latency_count += 1;
+            }
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pictures.push(DpbPicture {
+            id,
+            frame,
+            motion,
+            poc,
+            marking: RefMarking::ShortTerm,
+            needed_for_output: output,
+            inter_layer: false,
+            tag,
+            latency_count: 0,
+            output_seq: None,
+        });
+        while self.needs_bumping(sps, false) && self.bump() {}
+    }
+
+    /// Output all remaining pictures and empty the DPB
+    ///
+    /// Also used at an end of sequence NAL unit: the next picture starts a
+    /// new coded video sequence.
+    pub fn flush(&mut self) {
+        for pic in &mut self.pictures {
+            pic.marking = RefMarking::Unused;
+        }
+        while self.bump() {}
+        self.first_in_sequence = true;
+    }
+
+    /// Hand over the pictures output so far, in output order
+    ///
+    /// Pictures still used for reference stay in the DPB and are copied.
+    pub fn take_output(&mut self) -> Vec<DecodedFrame> {
+        self.take_tagged_output()
+            .into_iter()
+            .map(|(_, frame)| frame)
+            .collect()
+    }
+
+    /// Hand over the pictures output so far with their tags, in output order
+    pub fn take_tagged_output(&mut self) -> Vec<(u64, DecodedFrame)> {
+        let mut queued: Vec<(u64, u64)> = self
+            .pictures
+            .iter()
+            .filter_map(|p| Some((p.output_seq?, p.id)))
+            .collect();
+        queued.sort_unstable();
+
+        let mut output = Vec::with_capacity(queued.len());
+        for (_, id) in queued {
+            let Some(idx) = self.pictures.iter().position(|p| p.id == id) else {
+                continue;
+            };
+            if self.pictures[idx].marking == RefMarking::Unused {
+                let pic = self.pictures.remove(idx);
+                output.push((pic.tag, pic.frame));
+            } else {
+                let pic = &mut self.pictures[idx];
+                pic.output_seq = None;
+                output.push((pic.tag, pic.frame.clone()));
+            }
+        }
+        output
+    }
+
+    /// Check the bumping conditions (C.5.2.2 / C.5.2.3)
+    fn needs_bumping(&self, sps: &Sps, before_decoding: bool) -> bool {
+        let waiting = self.pictures.iter().filter(|p| p.needed_for_output);
+        let num_waiting = waiting.clone().count();
+        if num_waiting == 0 {
+            return false;
+        }
+        if num_waiting > sps.max_num_reorder_pics as usize {
+            return true;
+        }
+        if sps.max_latency_increase_plus1 != 0 {
+            let max_latency = sps.max_num_reorder_pics as u32 + sps.max_latency_increase_plus1 - 1;
+            if waiting.clone().any(|p| p.latency_count >= max_latency) {
+                return true;
+            }
+        }
+        before_decoding
+            && self
+                .pictures
+                .iter()
+                .filter(|p| p.output_seq.is_none() || p.marking != RefMarking::Unused)
+                .count()
+                > sps.max_dec_pic_buffering_minus1 as usize
+    }
+
+    /// Output the picture with the smallest POC (C.5.2.4)
+    ///
+    /// Returns false if no picture is waiting for output.
+    fn bump(&mut self) -> bool {
+        let Some(pic) = self
+            .pictures
+            .iter_mut()
+            .filter(|p| p.needed_for_output)
+            .min_by_key(|p| p.poc)
+        else {
+            self.remove_unused();
+            return false;
+        };
+        pic.needed_for_output = false;
+        pic.output_seq = Some(self.next_output_seq);
+        self.next_output_seq += 1;
+        true
+    }
+
+    /// Remove pictures that are neither needed for output nor for reference
+    fn remove_unused(&mut self) {
+        self.pictures.retain(|p| {
+            p.needed_for_output || p.output_seq.is_some() || p.marking != RefMarking::Unused
+        });
+    }
+}
@@ -0,0 +1,108 @@
+//! Bin sinks for the slice data syntax
+//!
+//! The same syntax writers drive both the rate estimate used by mode
+//! decisions and the arithmetic coder of the final pass.
+
+use crate::hevc::bitstream::BitstreamWriter;
+use crate::hevc::cabac::{CabacEncoder, ContextModel, INIT_VALUES, context};
+
+/// Receiver of the bins of the slice data syntax
+pub(super) trait BinWriter {
+    /// Code a bin with the context model at `ctx_idx`
+    fn bin(&mut self, ctx_idx: usize, bin: u8);
+
+    /// Code a bypass bin
+    fn bypass(&mut self, bin: u8);
+
+    /// Code the `n` low bits of `value` as bypass bins, most significant first
+    fn bypass_bits(&mut self, value: u32, n: u8) {
+        for i in (0..n).rev() {
+            self.bypass(((value >> i) & 1) as u8);
+        }
+    }
+}
+
+/// Context models of initType `init_type` (0 for I slices) at `slice_qp`
+/// (H.265 9.3.2.2)
+fn initial_contexts(init_type: usize, slice_qp: i32) -> [ContextModel; context::NUM_CONTEXTS] {
+    let mut ctx = [ContextModel::new(154); context::NUM_CONTEXTS];
+    for (model, &init_value) in ctx.iter_mut().zip(INIT_VALUES[init_type].iter()) {
+        model.init(init_value, slice_qp);
+    }
+    ctx
+}
+
+/// Arithmetic codes bins into slice data
+pub(super) struct CabacWriter {
+    cabac: CabacEncoder,
+    ctx: [ContextModel; context::NUM_CONTEXTS],
+}
+
+impl CabacWriter {
+    /// Start slice data after the (byte aligned) slice header in `writer`,
+    /// with the contexts of initType `init_type`
+    pub fn new(writer: BitstreamWriter, init_type: usize, slice_qp: i32) -> Self {
+        Self {
+            cabac: CabacEncoder::new(writer),
+            ctx: initial_contexts(init_type, slice_qp),
+        }
+    }
+
+    /// Code end_of_slice_segment_flag
+    pub fn end_of_slice_segment(&mut self, last: bool) {
+        self.cabac.encode_terminate(u8::from(last));
+    }
+
+    /// Writer holding the slice header and the flushed slice data
+    pub fn finish(self) -> BitstreamWriter {
+        self.cabac.finish()
+    }
+}
+
+impl BinWriter for CabacWriter {
+    fn bin(&mut self, ctx_idx: usize, bin: u8) {
+        self.cabac.encode_bin(&mut self.ctx[ctx_idx], bin);
+    }
+
+    fn bypass(&mut self, bin: u8) {
+        self.cabac.encode_bypass(bin);
+    }
+
+    fn bypass_bits(&mut self, value: u32, n: u8) {
+        self.cabac.encode_bypass_bits(value, n);
+    }
+}
+
+/// Estimates the cost of bins while tracking context states as coding would
+#[derive(Clone)]
+pub(super) struct BitCounter {
+    ctx: [ContextModel; context::NUM_CONTEXTS],
+    /// Accumulated cost in 1/256 bits
+    pub bits: u64,
+}
+
+impl BitCounter {
+    /// Start counting with the contexts of an I slice at `slice_qp`
+    pub fn new(slice_qp: i32) -> Self {
+        Self {
+            ctx: initial_contexts(0, slice_qp),
+            bits: 0,
+        }
+    }
+}
+
+impl BinWriter for BitCounter {
+    fn bin(&mut self, ctx_idx: usize, bin: u8) {
+        let model = &mut self.ctx[ctx_idx];
+        self.bits += u64::from(model.cost(bin));
+        model.update(bin);
+    }
+
+    fn bypass(&mut self, _bin: u8) {
+        self.bits += 256;
+    }
+
+    fn bypass_bits(&mut self, _value: u32, n: u8) {
+        self.bits += 256 * u64::from(n);
+    }
+}
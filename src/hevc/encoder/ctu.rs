@@ -0,0 +1,759 @@
+//! Coding quadtree decisions and syntax (H.265 7.3.8.4 - 7.3.8.10)
+//!
+//! Every decision reconstructs its candidate exactly as the decoder will and
+//! compares the rate-distortion cost `SSE + lambda * bits`, with the rate
+//! estimated from the context states of a [`BitCounter`].
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::bins::{BinWriter, BitCounter};
+use super::residual::write_residual;
+use crate::hevc::cabac::context;
+use crate::hevc::intra;
+use crate::hevc::picture::{DecodedFrame, UNINIT_SAMPLE};
+use crate::hevc::residual::{ScanOrder, get_scan_order};
+use crate::hevc::slice::IntraPredMode;
+use crate::hevc::transform::{self, DequantParams, MAX_COEFF};
+
+/// CTB size (log2)
+pub(super) const LOG2_CTB_SIZE: u8 = 5;
+
+/// Minimum coding block size (log2)
+pub(super) const LOG2_MIN_CB_SIZE: u8 = 3;
+
+/// Quantizer rounding offset, in 1/512 (a third, as usual for intra)
+const QUANT_ROUNDING: i64 = 171;
+
+/// Luma modes kept from the SATD pre-selection for a full rate-distortion check
+const RD_MODE_CANDIDATES: usize = 3;
+
+/// Quantized coefficients of a transform block, in raster order
+pub(super) struct TransformBlock {
+    coeffs: Vec<i16>,
+    log2_size: u8,
+    scan_order: ScanOrder,
+}
+
+impl TransformBlock {
+    /// Whether the block has nonzero coefficients (its cbf)
+    fn coded(&self) -> bool {
+        self.coeffs.iter().any(|&c| c != 0)
+    }
+}
+
+/// An intra coding unit with its prediction modes and residual
+pub(super) struct CodingUnit {
+    x: u32,
+    y: u32,
+    log2_size: u8,
+    /// PartMode NxN: four luma prediction and transform blocks
+    nxn: bool,
+    luma_modes: [IntraPredMode; 4],
+    /// intra_chroma_pred_mode syntax element (4 = same as luma)
+    chroma_syntax: u8,
+    luma: Vec<TransformBlock>,
+    /// Cb and Cr blocks, `None` for monochrome pictures
+    chroma: Option<[TransformBlock; 2]>,
+}
+
+/// Coding quadtree of a CTB
+pub(super) enum CodingTree {
+    /// Children inside the picture, in z-order
+    Split(Vec<CodingTree>),
+    /// A coding unit covering the node
+    Leaf(Box<CodingUnit>),
+}
+
+/// Intra modes and coding quadtree depths of the coded part of the picture
+#[derive(Clone)]
+pub(super) struct BlockMaps {
+    /// Luma intra mode per 4x4 block
+    modes: Vec<IntraPredMode>,
+    /// Coding quadtree depth per minimum coding block
+    depths: Vec<u8>,
+    width4: usize,
+    width8: usize,
+}
+
+impl BlockMaps {
+    fn new(width: u32, height: u32) -> Self {
+        let (width4, width8) = ((width / 4) as usize, (width / 8) as usize);
+        Self {
+            modes: vec![IntraPredMode::Dc; width4 * (height / 4) as usize],
+            depths: vec![0; width8 * (height / 8) as usize],
+            width4,
+            width8,
+        }
+    }
+
+    fn mode_at(&self, x: u32, y: u32) -> IntraPredMode {
+        self.modes[(y / 4) as usize * self.width4 + (x / 4) as usize]
+    }
+
+    fn set_mode(&mut self, x: u32, y: u32, size: u32, mode: IntraPredMode) {
+        for by in y / 4..(y + size) / 4 {
+            let row = by as usize * self.width4;
+            self.modes[row + (x / 4) as usize..row + ((x + size) / 4) as usize].fill(mode);
+        }
+    }
+
+    fn set_depth(&mut self, x: u32, y: u32, size: u32, depth: u8) {
+        for by in y / 8..(y + size) / 8 {
+            let row = by as usize * self.width8;
+            self.depths[row + (x / 8) as usize..row + ((x + size) / 8) as usize].fill(depth);
+        }
+    }
+
+    /// Most probable modes of the prediction block at (x, y) (H.265 8.4.2)
+    fn mpm(&self, x: u32, y: u32) -> [IntraPredMode; 3] {
+        let left = if x == 0 {
+            IntraPredMode::Dc
+        } else {
+            self.mode_at(x - 1, y)
+        };
+        // The above neighbour only counts inside the same CTB row
+        let above = if y & ((1 << LOG2_CTB_SIZE) - 1) == 0 {
+            IntraPredMode::Dc
+        } else {
+            self.mode_at(x, y - 1)
+        };
+        intra::fill_mpm_candidates(left, above)
+    }
+
+    /// Context increment of split_cu_flag (H.265 9.3.4.2.2)
+    fn split_ctx(&self, x: u32, y: u32, depth: u8) -> usize {
+        let deeper = |x: u32, y: u32| {
+            usize::from(self.depths[(y / 8) as usize * self.width8 + (x / 8) as usize] > depth)
+        };
+        let left = if x > 0 { deeper(x - 1, y) } else { 0 };
+        let above = if y > 0 { deeper(x, y - 1) } else { 0 };
+        left + above
+    }
+}
+
+/// Saved samples and maps of a square region, to undo a trial
+struct Region {
+    x: u32,
+    y: u32,
+    size: u32,
+    planes: [Vec<u16>; 3],
+    maps: BlockMaps,
+}
+
+/// Chooses and reconstructs the coding trees of a picture
+pub(super) struct PictureEncoder<'a> {
+    source: &'a DecodedFrame,
+    recon: DecodedFrame,
+    maps: BlockMaps,
+    bits: BitCounter,
+    qp: i32,
+    chroma_qp: i32,
+    /// Lagrange multiplier for SSE decisions, in 1/256
+    lambda: u64,
+    /// Lagrange multiplier for SATD decisions, in 1/256
+    sqrt_lambda: u64,
+}
+
+impl<'a> PictureEncoder<'a> {
+    /// Start encoding `source`, whose dimensions are multiples of the minimum
+    /// coding block size
+    ///
+    /// A monochrome (4:0:0) source codes luma only, as each colour plane is
+    /// coded with separate_colour_plane_flag.
+    pub fn new(source: &'a DecodedFrame, qp: i32) -> Self {
+        let chroma_format = if source.chroma_format == 0 { 0 } else { 1 };
+        let mut recon = DecodedFrame::with_params(source.width, source.height, 8, chroma_format);
+        recon.full_range = source.full_range;
+        recon.matrix_coeffs = source.matrix_coeffs;
+        let lambda = lambda_q8(qp);
+        Self {
+            source,
+            recon,
+            maps: BlockMaps::new(source.width, source.height),
+            bits: BitCounter::new(qp),
+            qp,
+            chroma_qp: chroma_qp(qp),
+            lambda,
+            sqrt_lambda: (lambda << 8).isqrt(),
+        }
+    }
+
+    /// Lagrange multiplier for SSE decisions, in 1/256
+    pub fn lambda(&self) -> u64 {
+        self.lambda
+    }
+
+    /// The reconstructed picture before in-loop filtering, and the maps for
+    /// writing the chosen coding trees
+    pub fn finish(self) -> (DecodedFrame, BlockMaps) {
+        (self.recon, self.maps)
+    }
+
+    /// Choose and reconstruct the coding tree of the CTB at luma position (x, y)
+    pub fn encode_ctb(&mut self, x: u32, y: u32) -> CodingTree {
+        let tree = self.decide(x, y, LOG2_CTB_SIZE, 0).0;
+        mark_edges(&mut self.recon, &tree, self.qp);
+        tree
+    }
+
+    /// Rate-distortion cost in 1/256 units
+    fn cost(&self, sse: u64, bits_q8: u64) -> u64 {
+        (sse << 8) + ((self.lambda * bits_q8) >> 8)
+    }
+
+    fn inside(&self, x: u32, y: u32, size: u32) -> bool {
+        x + size <= self.source.width && y + size <= self.source.height
+    }
+
+    /// Best coding tree of a node and its cost
+    fn decide(&mut self, x: u32, y: u32, log2_size: u8, depth: u8) -> (CodingTree, u64) {
+        let size = 1u32 << log2_size;
+        if !self.inside(x, y, size) {
+            // Nodes crossing the picture boundary split implicitly
+            let mut children = Vec::with_capacity(4);
+            let mut cost = 0;
+            for (cx, cy) in quadrants(x, y, log2_size, self.source.width, self.source.height) {
+                let (child, child_cost) = self.decide(cx, cy, log2_size - 1, depth + 1);
+                children.push(child);
+                cost += child_cost;
+            }
+            return (CodingTree::Split(children), cost);
+        }
+
+        let start = self.bits.clone();
+        let (cu, leaf_cost) = self.code_leaf(x, y, log2_size, depth);
+        if log2_size == LOG2_MIN_CB_SIZE {
+            return (CodingTree::Leaf(cu), leaf_cost);
+        }
+
+        let leaf_bits = core::mem::replace(&mut self.bits, start);
+        let leaf_region = self.save(x, y, size);
+        self.clear(x, y, size);
+        let before = self.bits.bits;
+        let split_ctx = self.maps.split_ctx(x, y, depth);
+        self.bits.bin(context::SPLIT_CU_FLAG + split_ctx, 1);
+        let mut split_cost = self.cost(0, self.bits.bits - before);
+        let mut children = Vec::with_capacity(4);
+        for (cx, cy) in quadrants(x, y, log2_size, self.source.width, self.source.height) {
+            if split_cost >= leaf_cost {
+                break;
+            }
+            let (child, child_cost) = self.decide(cx, cy, log2_size - 1, depth + 1);
+            children.push(child);
+            split_cost += child_cost;
+        }
+
+        if split_cost < leaf_cost {
+            (CodingTree::Split(children), split_cost)
+        } else {
+            self.bits = leaf_bits;
+            self.restore(leaf_region);
+            (CodingTree::Leaf(cu), leaf_cost)
+        }
+    }
+
+    /// Code the node as a single coding unit, trying NxN at the minimum size
+    fn code_leaf(&mut self, x: u32, y: u32, log2_size: u8, depth: u8) -> (Box<CodingUnit>, u64) {
+        let size = 1u32 << log2_size;
+        self.maps.set_depth(x, y, size, depth);
+        let before = self.bits.bits;
+        if log2_size > LOG2_MIN_CB_SIZE {
+            let split_ctx = self.maps.split_ctx(x, y, depth);
+            self.bits.bin(context::SPLIT_CU_FLAG + split_ctx, 0);
+        }
+        let start = self.bits.clone();
+        let (cu, sse) = self.code_cu(x, y, log2_size, false);
+        let cost = self.cost(sse, self.bits.bits - before);
+        if log2_size > LOG2_MIN_CB_SIZE {
+            return (cu, cost);
+        }
+
+        let bits_2nx2n = core::mem::replace(&mut self.bits, start);
+        let region = self.save(x, y, size);
+        self.clear(x, y, size);
+        let (cu_nxn, sse_nxn) = self.code_cu(x, y, log2_size, true);
+        let cost_nxn = self.cost(sse_nxn, self.bits.bits - before);
+        if cost_nxn < cost {
+            (cu_nxn, cost_nxn)
+        } else {
+            self.bits = bits_2nx2n;
+            self.restore(region);
+            (cu, cost)
+        }
+    }
+
+    /// Choose modes for, reconstruct and count a coding unit; returns its SSE
+    fn code_cu(&mut self, x: u32, y: u32, log2_size: u8, nxn: bool) -> (Box<CodingUnit>, u64) {
+        let log2_pb = if nxn { log2_size - 1 } else { log2_size };
+        let mut luma_modes = [IntraPredMode::Dc; 4];
+        let mut luma = Vec::with_capacity(4);
+        let mut sse = 0;
+        for (i, mode_slot) in luma_modes
+            .iter_mut()
+            .take(if nxn { 4 } else { 1 })
+            .enumerate()
+        {
+            let px = x + ((i as u32 & 1) << log2_pb);
+            let py = y + ((i as u32 >> 1) << log2_pb);
+            let mode = self.choose_luma_mode(px, py, log2_pb, nxn);
+            self.maps.set_mode(px, py, 1 << log2_pb, mode);
+            let (block, block_sse) = self.code_block(0, px, py, log2_pb, mode);
+            luma.push(block);
+            sse += block_sse;
+            *mode_slot = mode;
+        }
+
+        let (mut chroma_syntax, mut chroma) = (4, None);
+        if self.recon.chroma_format != 0 {
+            let (xc, yc, log2_c) = (x / 2, y / 2, log2_size - 1);
+            let (syntax, mode) = self.choose_chroma_mode(xc, yc, log2_c, luma_modes[0]);
+            let (cb, cb_sse) = self.code_block(1, xc, yc, log2_c, mode);
+            let (cr, cr_sse) = self.code_block(2, xc, yc, log2_c, mode);
+            sse += cb_sse + cr_sse;
+            chroma_syntax = syntax;
+            chroma = Some([cb, cr]);
+        }
+
+        let cu = Box::new(CodingUnit {
+            x,
+            y,
+            log2_size,
+            nxn,
+            luma_modes,
+            chroma_syntax,
+            luma,
+            chroma,
+        });
+        write_coding_unit(&mut self.bits, &cu, &self.maps);
+        (cu, sse)
+    }
+
+    /// Luma mode of a prediction block: SATD pre-selection, then a full
+    /// rate-distortion check of the best few
+    fn choose_luma_mode(&mut self, x: u32, y: u32, log2_size: u8, nxn: bool) -> IntraPredMode {
+        let size = 1u32 << log2_size;
+        let mpm = self.maps.mpm(x, y);
+        let mut ranked: Vec<(u64, IntraPredMode)> = (0..35)
+            .filter_map(IntraPredMode::from_u8)
+            .map(|mode| {
+                intra::predict_intra(&mut self.recon, x, y, log2_size, mode, 0, true, false);
+                let mode_bits = match mpm.iter().position(|&m| m == mode) {
+                    Some(0) => 2,
+                    Some(_) => 3,
+                    None => 6,
+                };
+                let satd = self.satd(0, x, y, size);
+                ((satd << 8) + self.sqrt_lambda * mode_bits, mode)
+            })
+            .collect();
+        ranked.sort_unstable_by_key(|&(cost, _)| cost);
+
+        let mut best = (u64::MAX, ranked[0].1);
+        for &(_, mode) in ranked.iter().take(RD_MODE_CANDIDATES) {
+            let (block, sse) = self.code_block(0, x, y, log2_size, mode);
+            let mut bits = self.bits.clone();
+            let before = bits.bits;
+            write_luma_mode(&mut bits, mode, &mpm);
+            bits.bin(
+                context::CBF_LUMA + usize::from(!nxn),
+                u8::from(block.coded()),
+            );
+            if block.coded() {
+                write_residual(&mut bits, &block.coeffs, log2_size, 0, block.scan_order);
+            }
+            let cost = self.cost(sse, bits.bits - before);
+            if cost < best.0 {
+                best = (cost, mode);
+            }
+        }
+        best.1
+    }
+
+    /// Chroma mode of a coding unit by SATD: returns intra_chroma_pred_mode
+    /// and the mode it selects
+    fn choose_chroma_mode(
+        &mut self,
+        x: u32,
+        y: u32,
+        log2_size: u8,
+        luma_mode: IntraPredMode,
+    ) -> (u8, IntraPredMode) {
+        let size = 1u32 << log2_size;
+        let mut best = (u64::MAX, 4, luma_mode);
+        for syntax in [4, 0, 1, 2, 3] {
+            let mode = chroma_mode(syntax, luma_mode);
+            let mut satd = 0;
+            for c_idx in 1..3 {
+                intra::predict_intra(&mut self.recon, x, y, log2_size, mode, c_idx, true, false);
+                satd += self.satd(c_idx, x, y, size);
+            }
+            let mode_bits = if syntax == 4 { 1 } else { 3 };
+            let cost = (satd << 8) + self.sqrt_lambda * mode_bits;
+            if cost < best.0 {
+                best = (cost, syntax, mode);
+            }
+        }
+        (best.1, best.2)
+    }
+
+    /// Predict, transform, quantize and reconstruct one transform block
+    fn code_block(
+        &mut self,
+        c_idx: u8,
+        x: u32,
+        y: u32,
+        log2_size: u8,
+        mode: IntraPredMode,
+    ) -> (TransformBlock, u64) {
+        let size = 1usize << log2_size;
+        let n = size * size;
+        intra::predict_intra(&mut self.recon, x, y, log2_size, mode, c_idx, true, false);
+
+        let mut residual = [0i16; MAX_COEFF];
+        {
+            let (src, src_stride) = self.source.plane(c_idx);
+            let (pred, stride) = self.recon.plane(c_idx);
+            for j in 0..size {
+                let src_row = (y as usize + j) * src_stride + x as usize;
+                let pred_row = (y as usize + j) * stride + x as usize;
+                for i in 0..size {
+                    residual[j * size + i] = src[src_row + i] as i16 - pred[pred_row + i] as i16;
+                }
+            }
+        }
+
+        let dst = c_idx == 0 && log2_size == 2;
+        let params = DequantParams {
+            qp: if c_idx == 0 { self.qp } else { self.chroma_qp },
+            bit_depth: 8,
+            log2_tr_size: log2_size,
+        };
+        let mut coeffs = vec![0i16; n];
+        transform::forward_transform(&residual[..n], &mut coeffs, size, 8, dst);
+        transform::quantize(&mut coeffs, params, QUANT_ROUNDING);
+
+        let block = TransformBlock {
+            coeffs,
+            log2_size,
+            scan_order: get_scan_order(log2_size, mode.as_u8(), c_idx),
+        };
+        if block.coded() {
+            let mut dequantized = block.coeffs.clone();
+            transform::dequantize(&mut dequantized, params);
+            transform::inverse_transform(&dequantized, &mut residual, size, 8, dst);
+            let (plane, stride) = self.recon.plane_mut(c_idx);
+            for j in 0..size {
+                let row = (y as usize + j) * stride + x as usize;
+                for (sample, &r) in plane[row..row + size].iter_mut().zip(&residual[j * size..]) {
+                    *sample = (i32::from(*sample) + i32::from(r)).clamp(0, 255) as u16;
+                }
+            }
+        }
+        (block, self.sse(c_idx, x, y, size as u32))
+    }
+
+    /// Sum of squared errors between the source and the reconstruction
+    fn sse(&self, c_idx: u8, x: u32, y: u32, size: u32) -> u64 {
+        let (src, src_stride) = self.source.plane(c_idx);
+        let (rec, stride) = self.recon.plane(c_idx);
+        let mut sum = 0u64;
+        for j in 0..size as usize {
+            let src_row = (y as usize + j) * src_stride + x as usize;
+            let rec_row = (y as usize + j) * stride + x as usize;
+            for i in 0..size as usize {
+                let d = i64::from(src[src_row + i]) - i64::from(rec[rec_row + i]);
+                sum += (d * d) as u64;
+            }
+        }
+        sum
+    }
+
+    /// Sum of absolute 4x4 Hadamard transformed differences between the
+    /// source and the prediction
+    fn satd(&self, c_idx: u8, x: u32, y: u32, size: u32) -> u64 {
+        let (src, src_stride) = self.source.plane(c_idx);
+        let (pred, stride) = self.recon.plane(c_idx);
+        let mut sum = 0;
+        for by in (0..size as usize).step_by(4) {
+            for bx in (0..size as usize).step_by(4) {
+                let mut diff = [0i32; 16];
+                for j in 0..4 {
+                    let src_row = (y as usize + by + j) * src_stride + x as usize + bx;
+                    let pred_row = (y as usize + by + j) * stride + x as usize + bx;
+                    for i in 0..4 {
+                        diff[j * 4 + i] =
+                            i32::from(src[src_row + i]) - i32::from(pred[pred_row + i]);
+                    }
+                }
+                sum += hadamard_4x4(&diff);
+            }
+        }
+        sum
+    }
+
+    /// Number of coded colour components
+    fn components(&self) -> u8 {
+        if self.recon.chroma_format == 0 { 1 } else { 3 }
+    }
+
+    /// Save the samples and maps of a region
+    fn save(&self, x: u32, y: u32, size: u32) -> Region {
+        let planes = [0u8, 1, 2].map(|c_idx| {
+            if c_idx >= self.components() {
+                return Vec::new();
+            }
+            let (x, y, size) = component_rect(c_idx, x, y, size);
+            let (plane, stride) = self.recon.plane(c_idx);
+            (y..y + size)
+                .flat_map(|j| {
+                    let row = j as usize * stride + x as usize;
+                    plane[row..row + size as usize].iter().copied()
+                })
+                .collect()
+        });
+        Region {
+            x,
+            y,
+            size,
+            planes,
+            maps: self.maps.clone(),
+        }
+    }
+
+    /// Undo everything coded since a region was saved
+    fn restore(&mut self, region: Region) {
+        for (c_idx, saved) in region.planes.iter().enumerate() {
+            let (x, y, size) = component_rect(c_idx as u8, region.x, region.y, region.size);
+            let (plane, stride) = self.recon.plane_mut(c_idx as u8);
+            for (j, rows) in saved.chunks_exact(size as usize).enumerate() {
+                let row = (y as usize + j) * stride + x as usize;
+                plane[row..row + size as usize].copy_from_slice(rows);
+            }
+        }
+        self.maps = region.maps;
+    }
+
+    /// Mark a region as not yet decoded, so predictions ignore its samples
+    fn clear(&mut self, x: u32, y: u32, size: u32) {
+        for c_idx in 0..self.components() {
+            let (x, y, size) = component_rect(c_idx, x, y, size);
+            let (plane, stride) = self.recon.plane_mut(c_idx);
+            for j in y..y + size {
+                let row = j as usize * stride + x as usize;
+                plane[row..row + size as usize].fill(UNINIT_SAMPLE);
+            }
+        }
+    }
+}
+
+/// Positions of the quadrants of a node that lie inside the picture, in
+/// z-order
+fn quadrants(
+    x: u32,
+    y: u32,
+    log2_size: u8,
+    width: u32,
+    height: u32,
+) -> impl Iterator<Item = (u32, u32)> {
+    let half = 1u32 << (log2_size - 1);
+    [(0, 0), (1, 0), (0, 1), (1, 1)]
+        .into_iter()
+        .map(move |(i, j)| (x + i * half, y + j * half))
+        .filter(move |&(cx, cy)| cx < width && cy < height)
+}
+
+/// Position and size of a luma region in a 4:2:0 component
+fn component_rect(c_idx: u8, x: u32, y: u32, size: u32) -> (u32, u32, u32) {
+    if c_idx == 0 {
+        (x, y, size)
+    } else {
+        (x / 2, y / 2, size / 2)
+    }
+}
+
+/// Lagrange multiplier 0.57 * 2^((qp - 12) / 3) of the HEVC test model, in 1/256
+fn lambda_q8(qp: i32) -> u64 {
+    const BASE: [u64; 3] = [146, 184, 232];
+    let steps = (qp - 12).div_euclid(3);
+    let base = BASE[(qp - 12).rem_euclid(3) as usize];
+    if steps >= 0 {
+        base << steps
+    } else {
+        (base >> -steps).max(1)
+    }
+}
+
+/// Chroma QP without offsets (H.265 Table 8-10)
+fn chroma_qp(qp: i32) -> i32 {
+    crate::hevc::ctu::chroma_qp_mapping(qp.clamp(0, 57))
+}
+
+/// Chroma mode selected by intra_chroma_pred_mode (H.265 Table 8-2)
+fn chroma_mode(syntax: u8, luma_mode: IntraPredMode) -> IntraPredMode {
+    let candidate = match syntax {
+        0 => IntraPredMode::Planar,
+        1 => IntraPredMode::Angular26,
+        2 => IntraPredMode::Angular10,
+        3 => IntraPredMode::Dc,
+        _ => return luma_mode,
+    };
+    if candidate == luma_mode {
+        IntraPredMode::Angular34
+    } else {
+        candidate
+    }
+}
+
+fn hadamard_4x4(diff: &[i32; 16]) -> u64 {
+    let mut m = [0i32; 16];
+    for j in 0..4 {
+        let r = &diff[j * 4..j * 4 + 4];
+        let (s0, s1, d0, d1) = (r[0] + r[3], r[1] + r[2], r[0] - r[3], r[1] - r[2]);
+        m[j * 4] = s0 + s1;
+        m[j * 4 + 1] = d0 + d1;
+        m[j * 4 + 2] = s0 - s1;
+        m[j * 4 + 3] = d0 - d1;
+    }
+    let mut sum = 0u64;
+    for i in 0..4 {
+        let (s0, s1) = (m[i] + m[12 + i], m[4 + i] + m[8 + i]);
+        let (d0, d1) = (m[i] - m[12 + i], m[4 + i] - m[8 + i]);
+        sum += u64::from((s0 + s1).unsigned_abs())
+            + u64::from((d0 + d1).unsigned_abs())
+            + u64::from((s0 - s1).unsigned_abs())
+            + u64::from((d0 - d1).unsigned_abs());
+    }
+    sum / 2
+}
+
+/// Mark the coding and transform block edges and QPs of a coding tree for
+/// the deblocking filter
+fn mark_edges(frame: &mut DecodedFrame, tree: &CodingTree, qp: i32) {
+    match tree {
+        CodingTree::Split(children) => {
+            for child in children {
+                mark_edges(frame, child, qp);
+            }
+        }
+        CodingTree::Leaf(cu) => {
+            let size = 1u32 << cu.log2_size;
+            frame.mark_tu_boundary(cu.x, cu.y, size);
+            frame.store_block_qp(cu.x, cu.y, size, qp as i8);
+            if cu.nxn {
+                let half = size / 2;
+                for (i, j) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    frame.mark_tu_boundary(cu.x + i * half, cu.y + j * half, half);
+                }
+            }
+        }
+    }
+}
+
+/// Write the coding quadtree of a CTB
+pub(super) fn write_coding_quadtree<W: BinWriter>(
+    w: &mut W,
+    tree: &CodingTree,
+    maps: &BlockMaps,
+    width: u32,
+    height: u32,
+    (x, y, log2_size, depth): (u32, u32, u8, u8),
+) {
+    let size = 1u32 << log2_size;
+    let split = matches!(tree, CodingTree::Split(_));
+    if log2_size > LOG2_MIN_CB_SIZE && x + size <= width && y + size <= height {
+        w.bin(
+            context::SPLIT_CU_FLAG + maps.split_ctx(x, y, depth),
+            u8::from(split),
+        );
+    }
+    match tree {
+        CodingTree::Split(children) => {
+            let positions = quadrants(x, y, log2_size, width, height);
+            for (child, (cx, cy)) in children.iter().zip(positions) {
+                let node = (cx, cy, log2_size - 1, depth + 1);
+                write_coding_quadtree(w, child, maps, width, height, node);
+            }
+        }
+        CodingTree::Leaf(cu) => write_coding_unit(w, cu, maps),
+    }
+}
+
+/// Write an intra coding unit of an I slice, from part_mode to its transform
+/// tree
+fn write_coding_unit<W: BinWriter>(w: &mut W, cu: &CodingUnit, maps: &BlockMaps) {
+    if cu.log2_size == LOG2_MIN_CB_SIZE {
+        w.bin(context::PART_MODE, u8::from(!cu.nxn));
+    }
+
+    let num_pb = if cu.nxn { 4 } else { 1 };
+    let log2_pb = cu.log2_size - u8::from(cu.nxn);
+    let mut pb_mpm = [(None, [IntraPredMode::Dc; 3]); 4];
+    for (i, (pos, mpm)) in pb_mpm.iter_mut().take(num_pb).enumerate() {
+        let px = cu.x + ((i as u32 & 1) << log2_pb);
+        let py = cu.y + ((i as u32 >> 1) << log2_pb);
+        *mpm = maps.mpm(px, py);
+        *pos = mpm.iter().position(|&m| m == cu.luma_modes[i]);
+        w.bin(context::PREV_INTRA_LUMA_PRED_FLAG, u8::from(pos.is_some()));
+    }
+    for (i, (pos, mpm)) in pb_mpm.iter().take(num_pb).enumerate() {
+        write_mpm_or_rem(w, cu.luma_modes[i], *pos, mpm);
+    }
+
+    if let Some([cb, cr]) = &cu.chroma {
+        w.bin(
+            context::INTRA_CHROMA_PRED_MODE,
+            u8::from(cu.chroma_syntax != 4),
+        );
+        if cu.chroma_syntax != 4 {
+            w.bypass_bits(u32::from(cu.chroma_syntax), 2);
+        }
+
+        // Transform tree: chroma cbfs at depth 0, then the luma blocks at the
+        // prediction block size, then the chroma blocks
+        w.bin(context::CBF_CBCR, u8::from(cb.coded()));
+        w.bin(context::CBF_CBCR, u8::from(cr.coded()));
+    }
+    for block in &cu.luma {
+        w.bin(
+            context::CBF_LUMA + usize::from(!cu.nxn),
+            u8::from(block.coded()),
+        );
+        if block.coded() {
+            write_residual(w, &block.coeffs, block.log2_size, 0, block.scan_order);
+        }
+    }
+    for (c_idx, block) in (1..).zip(cu.chroma.iter().flatten()) {
+        if block.coded() {
+            write_residual(w, &block.coeffs, block.log2_size, c_idx, block.scan_order);
+        }
+    }
+}
+
+/// Write prev_intra_luma_pred_flag with mpm_idx or rem_intra_luma_pred_mode,
+/// as used for rate estimates of a single prediction block
+fn write_luma_mode<W: BinWriter>(w: &mut W, mode: IntraPredMode, mpm: &[IntraPredMode; 3]) {
+    let pos = mpm.iter().position(|&m| m == mode);
+    w.bin(context::PREV_INTRA_LUMA_PRED_FLAG, u8::from(pos.is_some()));
+    write_mpm_or_rem(w, mode, pos, mpm);
+}
+
+/// Write mpm_idx (truncated rice, bypass) when the mode is the candidate
+/// `pos` of `mpm`, else rem_intra_luma_pred_mode
+fn write_mpm_or_rem<W: BinWriter>(
+    w: &mut W,
+    mode: IntraPredMode,
+    pos: Option<usize>,
+    mpm: &[IntraPredMode; 3],
+) {
+    match pos {
+        Some(0) => w.bypass(0),
+        Some(1) => w.bypass_bits(0b10, 2),
+        Some(_) => w.bypass_bits(0b11, 2),
+        None => {
+            let below = mpm.iter().filter(|m| m.as_u8() < mode.as_u8()).count() as u8;
+            w.bypass_bits(u32::from(mode.as_u8() - below), 5);
+        }
+    }
+}
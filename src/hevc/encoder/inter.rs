@@ -0,0 +1,223 @@
+//! P and B pictures for decoder tests
+//!
+//! Every coding unit of the inter pictures is a whole 32x32 CTB without
+//! residual. The P picture codes one motion vector difference in its first
+//! coding unit and merges it everywhere else; the B picture merges every
+//! coding unit, starting from the temporal candidate found in the P picture.
+
+use alloc::vec::Vec;
+
+use super::bins::{BinWriter, CabacWriter};
+use super::ctu::LOG2_CTB_SIZE;
+use super::encode_picture;
+use super::syntax::{self, StreamParams};
+use crate::hevc::bitstream::{BitstreamWriter, NalType, write_nal_unit};
+use crate::hevc::cabac::context;
+use crate::hevc::picture::DecodedFrame;
+
+/// slice_type of B slices
+const SLICE_B: u32 = 0;
+/// slice_type of P slices
+pub(super) const SLICE_P: u32 = 1;
+
+/// Explicit weighted prediction of a P picture: the weight, in units of
+/// 1/64, and the offset of the luma, Cb and Cr samples
+pub(crate) type Weights = [(i32, i32); 3];
+
+/// luma_log2_weight_denom, also used for chroma
+const LOG2_WEIGHT_DENOM: u32 = 6;
+
+/// An IDR picture (POC 0), a P picture (POC 2) predicted from it with the
+/// luma motion vector (`mv_x`, 0) in quarter samples, and a B picture (POC 1)
+/// predicted from both, as NAL units in decoding order
+///
+/// The picture's dimensions must be multiples of 32.
+pub(crate) fn encode_ipb(picture: &DecodedFrame, qp: u8, mv_x: i16) -> Vec<Vec<u8>> {
+    let (params, mut nal_units) = encode_ip(picture, qp, mv_x, None);
+    nal_units.push(inter_slice(&params, SLICE_B, 1, &[1], &[1], [0, 0], None));
+    nal_units
+}
+
+/// An IDR picture (POC 0) and a P picture (POC 2) predicted from it with the
+/// luma motion vector (`mv_x`, 0) in quarter samples and explicit `weights`,
+/// as NAL units in decoding order
+///
+/// The picture's dimensions must be multiples of 32.
+pub(crate) fn encode_weighted_ip(
+    picture: &DecodedFrame,
+    qp: u8,
+    mv_x: i16,
+    weights: &Weights,
+) -> Vec<Vec<u8>> {
+    encode_ip(picture, qp, mv_x, Some(weights)).1
+}
+
+/// Parameters and NAL units of the stream up to its P picture
+fn encode_ip(
+    picture: &DecodedFrame,
+    qp: u8,
+    mv_x: i16,
+    weights: Option<&Weights>,
+) -> (StreamParams, Vec<Vec<u8>>) {
+    let ctb_size = 1u32 << LOG2_CTB_SIZE;
+    assert!(picture.width.is_multiple_of(ctb_size) && picture.height.is_multiple_of(ctb_size));
+    let intra = encode_picture(picture, qp, &[Some((0, 0))]);
+    let params = StreamParams {
+        width: picture.width,
+        height: picture.height,
+        crop_right: 0,
+        crop_bottom: 0,
+        full_range: picture.full_range,
+        matrix_coeffs: picture.matrix_coeffs,
+        qp: i32::from(qp.min(51)),
+        deblocking: intra.deblocking,
+        inter: true,
+        weighted_pred: weights.is_some(),
+        separate_colour_planes: false,
+        layer_id: 0,
+    };
+    let nal_units = alloc::vec![
+        syntax::video_parameter_set(&params),
+        syntax::sequence_parameter_set(&params),
+        syntax::picture_parameter_set(&params),
+        intra.slice,
+        inter_slice(&params, SLICE_P, 2, &[2], &[], [mv_x, 0], weights),
+    ];
+    (params, nal_units)
+}
+
+/// Slice NAL unit of a P or B picture with the given reference picture set
+/// (POC distances before and after it)
+///
+/// The first coding unit of a P slice codes `mvd` against a zero predictor;
+/// all other coding units are skipped with merge_idx 0. `weights` are given
+/// to P slices of streams with weighted prediction.
+fn inter_slice(
+    params: &StreamParams,
+    slice_type: u32,
+    poc: u32,
+    negative: &[u32],
+    positive: &[u32],
+    mvd: [i16; 2],
+    weights: Option<&Weights>,
+) -> Vec<u8> {
+    let mut w = BitstreamWriter::new();
+    w.write_flag(true); // first_slice_segment_in_pic_flag
+    w.write_ue(0); // slice_pic_parameter_set_id
+    w.write_ue(slice_type);
+    w.write_bits(poc, 8); // slice_pic_order_cnt_lsb
+    w.write_flag(false); // short_term_ref_pic_set_sps_flag
+    w.write_ue(negative.len() as u32); // num_negative_pics
+    w.write_ue(positive.len() as u32); // num_positive_pics
+    for deltas in [negative, positive] {
+        let mut previous = 0;
+        for &delta in deltas {
+            w.write_ue(delta - previous - 1); // delta_poc_sX_minus1
+            w.write_flag(true); // used_by_curr_pic_sX_flag
+            previous = delta;
+        }
+    }
+    w.write_flag(true); // slice_temporal_mvp_enabled_flag
+    w.write_flag(false); // slice_sao_luma_flag
+    w.write_flag(false); // slice_sao_chroma_flag
+    w.write_flag(false); // num_ref_idx_active_override_flag
+    if slice_type == SLICE_B {
+        w.write_flag(false); // mvd_l1_zero_flag
+        w.write_flag(false); // collocated_from_l0_flag
+    }
+    if let Some(weights) = weights {
+        write_pred_weight_table(&mut w, weights);
+    }
+    w.write_ue(0); // five_minus_max_num_merge_cand
+    w.write_se(0); // slice_qp_delta
+    w.write_trailing_bits(); // byte_alignment()
+
+    // initType 1 for P and 2 for B slices without cabac_init_flag
+    let init_type = if slice_type == SLICE_P { 1 } else { 2 };
+    let mut cabac = CabacWriter::new(w, init_type, params.qp);
+    let width_ctbs = params.width >> LOG2_CTB_SIZE;
+    let height_ctbs = params.height >> LOG2_CTB_SIZE;
+    let mut skipped = Vec::with_capacity((width_ctbs * height_ctbs) as usize);
+    for y_ctb in 0..height_ctbs {
+        for x_ctb in 0..width_ctbs {
+            let i = (y_ctb * width_ctbs + x_ctb) as usize;
+            // Neighbouring CUs share the depth 0, so split_cu_flag has ctxInc 0
+            cabac.bin(context::SPLIT_CU_FLAG, 0);
+            let left = x_ctb > 0 && skipped[i - 1];
+            let above = y_ctb > 0 && skipped[i - width_ctbs as usize];
+            let skip = i != 0 || slice_type == SLICE_B;
+            let ctx_inc = usize::from(left) + usize::from(above);
+            cabac.bin(context::CU_SKIP_FLAG + ctx_inc, u8::from(skip));
+            if skip {
+                cabac.bin(context::MERGE_IDX, 0);
+            } else {
+                cabac.bin(context::PRED_MODE_FLAG, 0); // MODE_INTER
+                cabac.bin(context::PART_MODE, 1); // PART_2Nx2N
+                cabac.bin(context::MERGE_FLAG, 0);
+                write_mvd(&mut cabac, mvd);
+                cabac.bin(context::MVP_LX_FLAG, 0);
+                cabac.bin(context::RQT_ROOT_CBF, 0);
+            }
+            skipped.push(skip);
+            cabac.end_of_slice_segment(i + 1 == (width_ctbs * height_ctbs) as usize);
+        }
+    }
+
+    let nal_type = if slice_type == SLICE_P {
+        NalType::TrailR
+    } else {
+        NalType::TrailN
+    };
+    write_nal_unit(nal_type, 0, &cabac.finish().finish())
+}
+
+/// pred_weight_table() (H.265 7.3.6.3) of a P slice with one 8-bit 4:2:0
+/// reference picture
+fn write_pred_weight_table(w: &mut BitstreamWriter, weights: &Weights) {
+    w.write_ue(LOG2_WEIGHT_DENOM); // luma_log2_weight_denom
+    w.write_se(0); // delta_chroma_log2_weight_denom
+    w.write_flag(true); // luma_weight_l0_flag
+    w.write_flag(true); // chroma_weight_l0_flag
+    let [(luma_weight, luma_offset), chroma @ ..] = *weights;
+    w.write_se(luma_weight - (1 << LOG2_WEIGHT_DENOM)); // delta_luma_weight_l0
+    w.write_se(luma_offset); // luma_offset_l0
+    for (weight, offset) in chroma {
+        w.write_se(weight - (1 << LOG2_WEIGHT_DENOM)); // delta_chroma_weight_l0
+        // Coded against an offset derived from the weight, with
+        // wpOffsetHalfRangeC 128
+        let predicted = 128 - ((128 * weight) >> LOG2_WEIGHT_DENOM);
+        w.write_se(offset - predicted); // delta_chroma_offset_l0
+    }
+}
+
+/// mvd_coding() (H.265 7.3.8.9)
+fn write_mvd<W: BinWriter>(w: &mut W, mvd: [i16; 2]) {
+    for c in mvd {
+        w.bin(context::ABS_MVD_GREATER0_FLAG, u8::from(c != 0));
+    }
+    for c in mvd {
+        if c != 0 {
+            let greater1 = c.unsigned_abs() > 1;
+            w.bin(context::ABS_MVD_GREATER1_FLAG, u8::from(greater1));
+        }
+    }
+    for c in mvd {
+        if c != 0 {
+            if c.unsigned_abs() > 1 {
+                write_egk(w, u32::from(c.unsigned_abs()) - 2, 1); // abs_mvd_minus2
+            }
+            w.bypass(u8::from(c < 0)); // mvd_sign_flag
+        }
+    }
+}
+
+/// k-th order Exp-Golomb bypass bins (H.265 9.3.3.5)
+fn write_egk<W: BinWriter>(w: &mut W, mut value: u32, mut k: u8) {
+    while value >= 1 << k {
+        w.bypass(1);
+        value -= 1 << k;
+        k += 1;
+    }
+    w.bypass(0);
+    w.bypass_bits(value, k);
+}
@@ -0,0 +1,171 @@
+//! Two-layer (SHVC) streams for decoder tests
+//!
+//! The base layer is an intra picture from [`encode_picture`]. The
+//! enhancement layer is an IDR P picture whose only reference is the base
+//! layer picture, resampled to the enhancement layer size: every coding
+//! unit is a whole 32x32 CTB skipped with the zero merge candidate, so the
+//! decoded enhancement layer is the inter-layer reference picture itself.
+
+use alloc::vec::Vec;
+
+use super::bins::{BinWriter, CabacWriter};
+use super::ctu::LOG2_CTB_SIZE;
+use super::encode_picture;
+use super::inter::SLICE_P;
+use super::syntax::{self, StreamParams};
+use crate::hevc::bitstream::{BitstreamWriter, NalType, write_nal_unit};
+use crate::hevc::cabac::context;
+use crate::hevc::picture::DecodedFrame;
+
+/// Index of spatial or SNR scalability in scalability_mask_flag
+const SCALABILITY_SPATIAL: u32 = 2;
+
+/// NAL units of a base layer coding `picture` and an enhancement layer
+/// (nuh_layer_id 1) of `width` x `height` predicted from it, in decoding
+/// order
+///
+/// The dimensions of both layers must be multiples of 32.
+pub(crate) fn encode_two_layers(
+    picture: &DecodedFrame,
+    qp: u8,
+    width: u32,
+    height: u32,
+) -> Vec<Vec<u8>> {
+    let ctb_size = 1u32 << LOG2_CTB_SIZE;
+    assert!(picture.width.is_multiple_of(ctb_size) && picture.height.is_multiple_of(ctb_size));
+    assert!(width.is_multiple_of(ctb_size) && height.is_multiple_of(ctb_size));
+    let base = encode_picture(picture, qp, &[Some((0, 0))]);
+    let params = StreamParams {
+        width,
+        height,
+        crop_right: 0,
+        crop_bottom: 0,
+        full_range: picture.full_range,
+        matrix_coeffs: picture.matrix_coeffs,
+        qp: i32::from(qp.min(51)),
+        deblocking: base.deblocking,
+        inter: false,
+        weighted_pred: false,
+        separate_colour_planes: false,
+        layer_id: 1,
+    };
+    alloc::vec![
+        video_parameter_set(picture, &params),
+        base.parameter_sets[1].clone(),
+        base.parameter_sets[2].clone(),
+        syntax::sequence_parameter_set(&params),
+        syntax::picture_parameter_set(&params),
+        base.slice,
+        enhancement_slice(&params),
+    ]
+}
+
+/// Video parameter set of the base layer and an enhancement layer that
+/// depends on it, with every reference layer active by default
+///
+/// The extension stops after the fields the decoder reads, before
+/// dpb_size().
+fn video_parameter_set(base: &DecodedFrame, params: &StreamParams) -> Vec<u8> {
+    let mut w = BitstreamWriter::new();
+    w.write_bits(0, 4); // vps_video_parameter_set_id
+    w.write_flag(true); // vps_base_layer_internal_flag
+    w.write_flag(true); // vps_base_layer_available_flag
+    w.write_bits(1, 6); // vps_max_layers_minus1
+    w.write_bits(0, 3); // vps_max_sub_layers_minus1
+    w.write_flag(true); // vps_temporal_id_nesting_flag
+    w.write_bits(0xFFFF, 16);
+    syntax::write_profile_tier_level(&mut w, params.level_idc());
+    w.write_flag(true); // vps_sub_layer_ordering_info_present_flag
+    w.write_ue(0); // vps_max_dec_pic_buffering_minus1
+    w.write_ue(0); // vps_max_num_reorder_pics
+    w.write_ue(0); // vps_max_latency_increase_plus1
+    w.write_bits(1, 6); // vps_max_layer_id
+    w.write_ue(1); // vps_num_layer_sets_minus1
+    w.write_flag(true); // layer_id_included_flag[1][0]
+    w.write_flag(true); // layer_id_included_flag[1][1]
+    w.write_flag(false); // vps_timing_info_present_flag
+    w.write_flag(true); // vps_extension_flag
+    while !w.is_byte_aligned() {
+        w.write_flag(true); // vps_extension_alignment_bit_equal_to_one
+    }
+
+    // vps_extension()
+    w.write_bits(u32::from(params.level_idc()), 8); // base layer general_level_idc
+    w.write_flag(false); // splitting_flag
+    w.write_bits(1 << (15 - SCALABILITY_SPATIAL), 16); // scalability_mask_flag
+    w.write_bits(0, 3); // dimension_id_len_minus1
+    w.write_flag(false); // vps_nuh_layer_id_present_flag
+    w.write_bits(1, 1); // dimension_id[1][0]
+    w.write_bits(0, 4); // view_id_len
+    w.write_flag(true); // direct_dependency_flag[1][0]
+    w.write_flag(false); // vps_sub_layers_max_minus1_present_flag
+    w.write_flag(false); // max_tid_ref_present_flag
+    w.write_flag(true); // default_ref_layers_active_flag
+    w.write_ue(0); // vps_num_profile_tier_level_minus1
+    w.write_ue(0); // num_add_olss
+    w.write_bits(1, 2); // default_output_layer_idc: highest layer only
+    w.write_flag(false); // alt_output_layer_flag[1]
+    w.write_ue(1); // vps_num_rep_formats_minus1
+    let base_crop = (base.crop_right, base.crop_bottom);
+    write_rep_format(&mut w, base.width, base.height, base_crop);
+    write_rep_format(&mut w, params.width, params.height, (0, 0));
+    w.write_flag(false); // rep_format_idx_present_flag
+    w.write_flag(false); // max_one_active_ref_layer_flag
+    w.write_flag(false); // vps_poc_lsb_aligned_flag
+    w.write_trailing_bits();
+    write_nal_unit(NalType::VpsNut, 0, &w.finish())
+}
+
+/// rep_format() of an 8-bit 4:2:0 layer cropped on the right and at the
+/// bottom
+fn write_rep_format(w: &mut BitstreamWriter, width: u32, height: u32, crop: (u32, u32)) {
+    w.write_bits(width, 16);
+    w.write_bits(height, 16);
+    w.write_flag(true); // chroma_and_bit_depth_vps_present_flag
+    w.write_bits(1, 2); // chroma_format_vps_idc: 4:2:0
+    w.write_bits(0, 4); // bit_depth_vps_luma_minus8
+    w.write_bits(0, 4); // bit_depth_vps_chroma_minus8
+    let cropped = crop != (0, 0);
+    w.write_flag(cropped); // conformance_window_vps_flag
+    if cropped {
+        // Offsets are in chroma sample units
+        w.write_ue(0);
+        w.write_ue(crop.0 / 2);
+        w.write_ue(0);
+        w.write_ue(crop.1 / 2);
+    }
+}
+
+/// Slice NAL unit of the enhancement layer IDR picture: a P slice whose
+/// coding units are all skipped with merge_idx 0
+fn enhancement_slice(params: &StreamParams) -> Vec<u8> {
+    let mut w = BitstreamWriter::new();
+    w.write_flag(true); // first_slice_segment_in_pic_flag
+    w.write_flag(false); // no_output_of_prior_pics_flag
+    w.write_ue(u32::from(params.layer_id)); // slice_pic_parameter_set_id
+    w.write_ue(SLICE_P);
+    w.write_bits(0, 8); // slice_pic_order_cnt_lsb
+    w.write_flag(false); // slice_sao_luma_flag
+    w.write_flag(false); // slice_sao_chroma_flag
+    w.write_flag(false); // num_ref_idx_active_override_flag
+    w.write_ue(0); // five_minus_max_num_merge_cand
+    w.write_se(0); // slice_qp_delta
+    w.write_trailing_bits(); // byte_alignment()
+
+    // initType 1 for P slices without cabac_init_flag
+    let mut cabac = CabacWriter::new(w, 1, params.qp);
+    let width_ctbs = params.width >> LOG2_CTB_SIZE;
+    let height_ctbs = params.height >> LOG2_CTB_SIZE;
+    for y_ctb in 0..height_ctbs {
+        for x_ctb in 0..width_ctbs {
+            // Neighbouring CUs share the depth 0, so split_cu_flag has ctxInc 0
+            cabac.bin(context::SPLIT_CU_FLAG, 0);
+            let ctx_inc = usize::from(x_ctb > 0) + usize::from(y_ctb > 0);
+            cabac.bin(context::CU_SKIP_FLAG + ctx_inc, 1);
+            cabac.bin(context::MERGE_IDX, 0);
+            let last = x_ctb + 1 == width_ctbs && y_ctb + 1 == height_ctbs;
+            cabac.end_of_slice_segment(last);
+        }
+    }
+    write_nal_unit(NalType::IdrNLp, params.layer_id, &cabac.finish().finish())
+}
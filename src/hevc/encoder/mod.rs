@@ -0,0 +1,201 @@
+//! Intra-only HEVC encoder
+//!
+//! Codes a single 8-bit 4:2:0 picture as one IDR slice of the Main profile.
+//! Candidates are reconstructed with the decoder's own intra prediction,
+//! inverse transforms and loop filters, so the encoder's reconstruction is
+//! exactly what a decoder will produce; the forward transform, quantizer and
+//! CABAC encoder write the chosen syntax.
+//!
+//! Coding choices: 32x32 CTBs split down to 8x8 coding units (with NxN
+//! partitions at 8x8), transform blocks matching the prediction blocks, one
+//! QP for the picture, and deblocking offsets and SAO parameters chosen
+//! against the source.
+
+mod bins;
+mod ctu;
+#[cfg(test)]
+pub(crate) mod inter;
+#[cfg(test)]
+pub(crate) mod layers;
+#[cfg(test)]
+pub(crate) mod planes;
+mod residual;
+mod sao;
+mod syntax;
+
+use alloc::vec::Vec;
+
+use self::bins::CabacWriter;
+use self::ctu::{LOG2_CTB_SIZE, PictureEncoder, write_coding_quadtree};
+use self::syntax::StreamParams;
+pub use self::syntax::{CONSTRAINT_INDICATOR_FLAGS, PROFILE_COMPATIBILITY_FLAGS, PROFILE_IDC_MAIN};
+use super::bitstream::{NalType, write_nal_unit};
+use super::deblock;
+use super::motion::MotionField;
+use super::picture::DecodedFrame;
+
+/// Deblocking beta and tC offsets (divided by two) worth trying on a
+/// reconstruction, `None` for no deblocking
+pub const DEBLOCKING_CANDIDATES: [Option<(i8, i8)>; 4] =
+    [Some((0, 0)), Some((-2, -2)), Some((2, 2)), None];
+
+/// A coded picture
+#[derive(Debug, Clone)]
+pub struct EncodedPicture {
+    /// VPS, SPS and PPS NAL units
+    pub parameter_sets: Vec<Vec<u8>>,
+    /// The slice NAL unit
+    pub slice: Vec<u8>,
+    /// general_level_idc of the stream
+    pub level_idc: u8,
+    /// The chosen deblocking offsets, `None` if deblocking is disabled
+    pub deblocking: Option<(i8, i8)>,
+}
+
+/// Encode an 8-bit 4:2:0 picture at a fixed QP (0-51)
+///
+/// The picture's dimensions must be multiples of 8. Its conformance window
+/// may crop columns on the right and rows at the bottom, in even numbers;
+/// `full_range` and `matrix_coeffs` are signalled in the VUI. Deblocking uses
+/// whichever of `deblocking` leaves the picture closest to the source, so
+/// pictures given a single candidate share their parameter sets.
+///
+/// # Panics
+///
+/// Panics if `deblocking` is empty.
+pub fn encode_picture(
+    picture: &DecodedFrame,
+    qp: u8,
+    deblocking: &[Option<(i8, i8)>],
+) -> EncodedPicture {
+    let qp = i32::from(qp.min(51));
+    let (width, height) = (picture.width, picture.height);
+    let ctb_size = 1u32 << LOG2_CTB_SIZE;
+    let width_ctbs = width.div_ceil(ctb_size);
+    let height_ctbs = height.div_ceil(ctb_size);
+
+    let mut encoder = PictureEncoder::new(picture, qp);
+    let mut trees = Vec::with_capacity((width_ctbs * height_ctbs) as usize);
+    for y_ctb in 0..height_ctbs {
+        for x_ctb in 0..width_ctbs {
+            trees.push(encoder.encode_ctb(x_ctb * ctb_size, y_ctb * ctb_size));
+        }
+    }
+    let lambda = encoder.lambda();
+    let (recon, maps) = encoder.finish();
+
+    // Keep the deblocking strength closest to the source
+    let (_, deblocking, deblocked) = deblocking
+        .iter()
+        .map(|&candidate| {
+            let mut frame = recon.clone();
+            if let Some((beta_offset_div2, tc_offset_div2)) = candidate {
+                let mut motion = MotionField::intra(width, height);
+                motion.slices[0].beta_offset = 2 * i32::from(beta_offset_div2);
+                motion.slices[0].tc_offset = 2 * i32::from(tc_offset_div2);
+                deblock::apply_deblocking_filter(&mut frame, &motion, 0, 0);
+            }
+            (frame_sse(picture, &frame), candidate, frame)
+        })
+        .min_by_key(|&(sse, _, _)| sse)
+        .expect("deblocking candidates");
+    let sao_map = sao::choose_sao(picture, &deblocked, ctb_size, lambda);
+
+    let params = StreamParams {
+        width,
+        height,
+        crop_right: picture.crop_right,
+        crop_bottom: picture.crop_bottom,
+        full_range: picture.full_range,
+        matrix_coeffs: picture.matrix_coeffs,
+        qp,
+        deblocking,
+        inter: false,
+        weighted_pred: false,
+        separate_colour_planes: false,
+        layer_id: 0,
+    };
+    let mut cabac = CabacWriter::new(syntax::slice_header(), 0, qp);
+    for (i, tree) in trees.iter().enumerate() {
+        let (x_ctb, y_ctb) = (i as u32 % width_ctbs, i as u32 / width_ctbs);
+        sao::write_sao(&mut cabac, &sao_map, x_ctb, y_ctb);
+        let node = (x_ctb * ctb_size, y_ctb * ctb_size, LOG2_CTB_SIZE, 0);
+        write_coding_quadtree(&mut cabac, tree, &maps, width, height, node);
+        cabac.end_of_slice_segment(i + 1 == trees.len());
+    }
+
+    EncodedPicture {
+        parameter_sets: alloc::vec![
+            syntax::video_parameter_set(&params),
+            syntax::sequence_parameter_set(&params),
+            syntax::picture_parameter_set(&params),
+        ],
+        slice: write_nal_unit(NalType::IdrNLp, 0, &cabac.finish().finish()),
+        level_idc: params.level_idc(),
+        deblocking,
+    }
+}
+
+/// Sum of squared errors over all components
+fn frame_sse(a: &DecodedFrame, b: &DecodedFrame) -> u64 {
+    [
+        (&a.y_plane, &b.y_plane),
+        (&a.cb_plane, &b.cb_plane),
+        (&a.cr_plane, &b.cr_plane),
+    ]
+    .iter()
+    .flat_map(|(x, y)| x.iter().zip(y.iter()))
+    .map(|(&x, &y)| {
+        let d = i64::from(x) - i64::from(y);
+        (d * d) as u64
+    })
+    .sum()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A picture with smooth gradients, edges and texture
+    pub(crate) fn test_picture(width: u32, height: u32) -> DecodedFrame {
+        let mut frame = DecodedFrame::with_params(width, height, 8, 1);
+        for y in 0..height {
+            for x in 0..width {
+                let texture = (x * 7 + y * 13) % 23;
+                let edge = if (x / 24 + y / 16) % 2 == 0 { 40 } else { 0 };
+                frame.set_y(x, y, ((40 + x + y / 2 + edge + texture) % 256) as u16);
+            }
+        }
+        for y in 0..height / 2 {
+            for x in 0..width / 2 {
+                frame.set_cb(x, y, (128 + x as i32 - y as i32).clamp(0, 255) as u16);
+                frame.set_cr(x, y, (100 + (x * y) % 40) as u16);
+            }
+        }
+        frame
+    }
+
+    /// Parameter sets and slice of a picture in Annex B format
+    pub(crate) fn annex_b(encoded: &EncodedPicture) -> Vec<u8> {
+        let mut annex_b = Vec::new();
+        for nal in encoded.parameter_sets.iter().chain([&encoded.slice]) {
+            annex_b.extend_from_slice(&[0, 0, 0, 1]);
+            annex_b.extend_from_slice(nal);
+        }
+        annex_b
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let picture = test_picture(72, 40);
+        let encoded = encode_picture(&picture, 22, &DEBLOCKING_CANDIDATES);
+        let annex_b = annex_b(&encoded);
+        let decoded = crate::hevc::decode(&annex_b).unwrap();
+        assert_eq!((decoded.width, decoded.height), (72, 40));
+
+        let sse = frame_sse(&picture, &decoded);
+        let samples = picture.y_plane.len() * 3 / 2;
+        // PSNR above 38 dB
+        assert!(sse * 6310 < (255 * 255) * samples as u64, "sse {sse}");
+    }
+}
@@ -0,0 +1,109 @@
+//! Pictures with separately coded colour planes for decoder tests
+//!
+//! A 4:4:4 picture is coded with separate_colour_plane_flag: each of Y, Cb
+//! and Cr is chosen and reconstructed as a monochrome picture and written as
+//! its own I slice with colour_plane_id 0, 1 and 2. Deblocking applies to
+//! every plane; SAO is off.
+
+use alloc::vec::Vec;
+
+use super::bins::CabacWriter;
+use super::ctu::{LOG2_CTB_SIZE, PictureEncoder, write_coding_quadtree};
+use super::syntax::{self, StreamParams};
+use crate::hevc::bitstream::{BitstreamWriter, NalType, write_nal_unit};
+use crate::hevc::deblock;
+use crate::hevc::motion::MotionField;
+use crate::hevc::picture::DecodedFrame;
+
+/// Parameter sets and the three colour plane slices of an 8-bit 4:4:4
+/// picture as NAL units, and the picture a decoder reconstructs from them
+///
+/// The picture's dimensions must be multiples of 32.
+pub(crate) fn encode_separate_planes(
+    picture: &DecodedFrame,
+    qp: u8,
+) -> (Vec<Vec<u8>>, DecodedFrame) {
+    let ctb_size = 1u32 << LOG2_CTB_SIZE;
+    let (width, height) = (picture.width, picture.height);
+    assert!(width.is_multiple_of(ctb_size) && height.is_multiple_of(ctb_size));
+    assert_eq!(picture.chroma_format, 3);
+    let params = StreamParams {
+        width,
+        height,
+        crop_right: 0,
+        crop_bottom: 0,
+        full_range: picture.full_range,
+        matrix_coeffs: picture.matrix_coeffs,
+        qp: i32::from(qp.min(51)),
+        deblocking: Some((0, 0)),
+        inter: false,
+        weighted_pred: false,
+        separate_colour_planes: true,
+        layer_id: 0,
+    };
+    let mut nal_units = alloc::vec![
+        syntax::video_parameter_set(&params),
+        syntax::sequence_parameter_set(&params),
+        syntax::picture_parameter_set(&params),
+    ];
+
+    let mut expected = DecodedFrame::with_params(width, height, 8, 3);
+    expected.full_range = picture.full_range;
+    expected.matrix_coeffs = picture.matrix_coeffs;
+    for colour_plane_id in 0..3u8 {
+        let mut plane = DecodedFrame::with_params(width, height, 8, 0);
+        plane.y_plane = picture.plane(colour_plane_id).0.to_vec();
+        let (slice, recon) = plane_slice(&params, &plane, colour_plane_id);
+        nal_units.push(slice);
+        let (samples, _) = expected.plane_mut(colour_plane_id);
+        samples.copy_from_slice(&recon.y_plane);
+    }
+    (nal_units, expected)
+}
+
+/// Slice NAL unit coding a monochrome `plane` as `colour_plane_id`, and its
+/// deblocked reconstruction
+fn plane_slice(
+    params: &StreamParams,
+    plane: &DecodedFrame,
+    colour_plane_id: u8,
+) -> (Vec<u8>, DecodedFrame) {
+    let ctb_size = 1u32 << LOG2_CTB_SIZE;
+    let (width_ctbs, height_ctbs) = (params.width / ctb_size, params.height / ctb_size);
+    let mut encoder = PictureEncoder::new(plane, params.qp);
+    let mut trees = Vec::with_capacity((width_ctbs * height_ctbs) as usize);
+    for y_ctb in 0..height_ctbs {
+        for x_ctb in 0..width_ctbs {
+            trees.push(encoder.encode_ctb(x_ctb * ctb_size, y_ctb * ctb_size));
+        }
+    }
+    let (mut recon, maps) = encoder.finish();
+    let motion = MotionField::intra(params.width, params.height);
+    deblock::apply_deblocking_filter(&mut recon, &motion, 0, 0);
+
+    let mut w = BitstreamWriter::new();
+    let first = colour_plane_id == 0;
+    w.write_flag(first); // first_slice_segment_in_pic_flag
+    w.write_flag(false); // no_output_of_prior_pics_flag
+    w.write_ue(0); // slice_pic_parameter_set_id
+    if !first {
+        // Every colour plane starts at the first CTB
+        let address_bits = (width_ctbs * height_ctbs).next_power_of_two().ilog2();
+        w.write_bits(0, address_bits as u8); // slice_segment_address
+    }
+    w.write_ue(2); // slice_type: I
+    w.write_bits(u32::from(colour_plane_id), 2); // colour_plane_id
+    w.write_flag(false); // slice_sao_luma_flag
+    w.write_se(0); // slice_qp_delta
+    w.write_trailing_bits(); // byte_alignment()
+
+    let mut cabac = CabacWriter::new(w, 0, params.qp);
+    for (i, tree) in trees.iter().enumerate() {
+        let (x_ctb, y_ctb) = (i as u32 % width_ctbs, i as u32 / width_ctbs);
+        let node = (x_ctb * ctb_size, y_ctb * ctb_size, LOG2_CTB_SIZE, 0);
+        write_coding_quadtree(&mut cabac, tree, &maps, params.width, params.height, node);
+        cabac.end_of_slice_segment(i + 1 == trees.len());
+    }
+    let slice = write_nal_unit(NalType::IdrNLp, 0, &cabac.finish().finish());
+    (slice, recon)
+}
@@ -0,0 +1,246 @@
+//! Residual coding syntax (H.265 7.3.8.11)
+//!
+//! Mirrors `decode_residual` without sign data hiding, transform skip or the
+//! range extension tools, none of which the encoder enables.
+
+use super::bins::BinWriter;
+use crate::hevc::cabac::context;
+use crate::hevc::residual::{ScanOrder, calc_sig_coeff_flag_ctx, get_scan_4x4, get_scan_sub_block};
+
+/// Write the coefficient levels of a transform block stored in raster order
+///
+/// At least one coefficient must be nonzero (the block's cbf is set).
+pub(super) fn write_residual<W: BinWriter>(
+    w: &mut W,
+    coeffs: &[i16],
+    log2_size: u8,
+    c_idx: u8,
+    scan_order: ScanOrder,
+) {
+    let size = 1usize << log2_size;
+    let scan_sub = get_scan_sub_block(log2_size, scan_order);
+    let scan_pos = get_scan_4x4(scan_order);
+    let scan_idx = scan_order as u8;
+    let chroma = usize::from(c_idx > 0);
+    let level = |sb: (u8, u8), pos: usize| {
+        let (px, py) = scan_pos[pos];
+        coeffs[(sb.1 as usize * 4 + py as usize) * size + sb.0 as usize * 4 + px as usize]
+    };
+
+    // Last significant coefficient in scan order
+    let Some((last_sb_idx, last_pos)) = (0..scan_sub.len())
+        .rev()
+        .flat_map(|i| (0..16).rev().map(move |n| (i, n)))
+        .find(|&(i, n)| level(scan_sub[i], n) != 0)
+    else {
+        return;
+    };
+    let (sb_x, sb_y) = scan_sub[last_sb_idx];
+    let last_x = u32::from(sb_x) * 4 + u32::from(scan_pos[last_pos].0);
+    let last_y = u32::from(sb_y) * 4 + u32::from(scan_pos[last_pos].1);
+    let (last_x, last_y) = if scan_order == ScanOrder::Vertical {
+        (last_y, last_x)
+    } else {
+        (last_x, last_y)
+    };
+    write_last_sig_coeff_pos(w, last_x, last_y, log2_size, c_idx);
+
+    let sb_width = size / 4;
+    let mut coded_sb_flags = [[false; 8]; 8];
+    let mut prev_subblock_had_gt1 = false;
+
+    for sb_idx in (0..=last_sb_idx).rev() {
+        let sb = scan_sub[sb_idx];
+        let (sb_x, sb_y) = (sb.0 as usize, sb.1 as usize);
+        let right_coded = sb_x + 1 < sb_width && coded_sb_flags[sb_y][sb_x + 1];
+        let below_coded = sb_y + 1 < sb_width && coded_sb_flags[sb_y + 1][sb_x];
+        let prev_csbf = u8::from(right_coded) | (u8::from(below_coded) << 1);
+
+        let is_last = sb_idx == last_sb_idx;
+        let mut infer_dc = false;
+        if sb_idx > 0 && !is_last {
+            let coded = (0..16).any(|n| level(sb, n) != 0);
+            let ctx_idx = context::CODED_SUB_BLOCK_FLAG + usize::from(prev_csbf != 0) + 2 * chroma;
+            w.bin(ctx_idx, u8::from(coded));
+            if !coded {
+                continue;
+            }
+            infer_dc = true;
+        }
+        coded_sb_flags[sb_y][sb_x] = true;
+
+        // significant_coeff_flag, highest scan position first
+        let start_pos = if is_last { last_pos } else { 15 };
+        let sig_ctx = |n: usize| {
+            let (px, py) = scan_pos[n];
+            calc_sig_coeff_flag_ctx(
+                sb.0 * 4 + px,
+                sb.1 * 4 + py,
+                log2_size,
+                c_idx,
+                scan_idx,
+                prev_csbf,
+            )
+        };
+        let first_flag = if is_last {
+            start_pos.saturating_sub(1)
+        } else {
+            15
+        };
+        for n in (1..=first_flag).rev() {
+            let sig = level(sb, n) != 0;
+            w.bin(sig_ctx(n), u8::from(sig));
+            infer_dc &= !sig;
+        }
+        if start_pos > 0 && !infer_dc {
+            w.bin(sig_ctx(0), u8::from(level(sb, 0) != 0));
+        }
+
+        let mut levels = [0i16; 16];
+        let mut num_sig = 0;
+        for n in (0..=start_pos).rev() {
+            let value = level(sb, n);
+            if value != 0 {
+                levels[num_sig] = value;
+                num_sig += 1;
+            }
+        }
+        let levels = &levels[..num_sig];
+
+        // coeff_abs_level_greater1_flag for up to 8 coefficients
+        let ctx_set =
+            if sb_idx == 0 || c_idx > 0 { 0 } else { 2 } + usize::from(prev_subblock_had_gt1);
+        let mut greater1_ctx = 1usize;
+        let mut last_greater1 = false;
+        let mut first_g1 = None;
+        for (k, &value) in levels.iter().take(8).enumerate() {
+            if k > 0 && greater1_ctx > 0 {
+                greater1_ctx = if last_greater1 { 0 } else { greater1_ctx + 1 };
+            }
+            let g1 = value.unsigned_abs() > 1;
+            let ctx_idx = context::COEFF_ABS_LEVEL_GREATER1_FLAG
+                + 16 * chroma
+                + 4 * ctx_set
+                + greater1_ctx.min(3);
+            w.bin(ctx_idx, u8::from(g1));
+            last_greater1 = g1;
+            if g1 && first_g1.is_none() {
+                first_g1 = Some(k);
+            }
+        }
+        if let Some(k) = first_g1 {
+            let ctx_idx = context::COEFF_ABS_LEVEL_GREATER2_FLAG + 4 * chroma + ctx_set;
+            w.bin(ctx_idx, u8::from(levels[k].unsigned_abs() > 2));
+        }
+
+        for &value in levels {
+            w.bypass(u8::from(value < 0));
+        }
+
+        // coeff_abs_level_remaining above the level the flags signalled
+        let mut rice_param = 0u8;
+        for (k, &value) in levels.iter().enumerate() {
+            let abs = u32::from(value.unsigned_abs());
+            let base = if k >= 8 {
+                1
+            } else if abs == 1 {
+                continue;
+            } else if first_g1 == Some(k) {
+                if abs == 2 {
+                    continue;
+                }
+                3
+            } else {
+                2
+            };
+            write_abs_level_remaining(w, abs - base, rice_param);
+            if abs > 3 << rice_param {
+                rice_param = (rice_param + 1).min(4);
+            }
+        }
+
+        prev_subblock_had_gt1 = first_g1.is_some();
+    }
+}
+
+/// Write last_sig_coeff_{x,y}_prefix and their suffixes
+fn write_last_sig_coeff_pos<W: BinWriter>(w: &mut W, x: u32, y: u32, log2_size: u8, c_idx: u8) {
+    let (x_prefix, x_suffix) = split_last_pos(x);
+    let (y_prefix, y_suffix) = split_last_pos(y);
+
+    let (ctx_offset, ctx_shift) = if c_idx == 0 {
+        let log2 = log2_size as usize;
+        (3 * (log2 - 2) + ((log2 - 1) >> 2), (log2_size + 1) >> 2)
+    } else {
+        (15, log2_size - 2)
+    };
+    let max_prefix = u32::from((log2_size << 1) - 1);
+    for (ctx_base, prefix) in [
+        (context::LAST_SIG_COEFF_X_PREFIX, x_prefix),
+        (context::LAST_SIG_COEFF_Y_PREFIX, y_prefix),
+    ] {
+        for i in 0..prefix {
+            w.bin(ctx_base + ctx_offset + (i as usize >> ctx_shift), 1);
+        }
+        if prefix < max_prefix {
+            w.bin(ctx_base + ctx_offset + (prefix as usize >> ctx_shift), 0);
+        }
+    }
+
+    for (prefix, suffix) in [(x_prefix, x_suffix), (y_prefix, y_suffix)] {
+        if prefix > 3 {
+            w.bypass_bits(suffix, ((prefix >> 1) - 1) as u8);
+        }
+    }
+}
+
+/// Binarize a last significant position into its prefix and suffix
+fn split_last_pos(pos: u32) -> (u32, u32) {
+    if pos < 4 {
+        return (pos, 0);
+    }
+    let msb = 31 - pos.leading_zeros();
+    let prefix = 2 * msb + ((pos >> (msb - 1)) & 1);
+    let n_bits = (prefix >> 1) - 1;
+    (prefix, pos - ((2 + (prefix & 1)) << n_bits))
+}
+
+/// Write coeff_abs_level_remaining with Rice parameter `rice_param`
+/// (H.265 9.3.3.11)
+fn write_abs_level_remaining<W: BinWriter>(w: &mut W, value: u32, rice_param: u8) {
+    let k = u32::from(rice_param);
+    if value >> k <= 3 {
+        let prefix = value >> k;
+        w.bypass_bits((1 << (prefix + 1)) - 2, (prefix + 1) as u8);
+        w.bypass_bits(value & ((1 << k) - 1), rice_param);
+        return;
+    }
+
+    // Exp-Golomb escape: prefix p > 3 covers ((1 << (p - 3)) + 2) << k onwards
+    let mut prefix = 4;
+    while value >= ((1 << (prefix - 2)) + 2) << k {
+        prefix += 1;
+    }
+    let base = ((1 << (prefix - 3)) + 2) << k;
+    for _ in 0..prefix {
+        w.bypass(1);
+    }
+    w.bypass(0);
+    w.bypass_bits(value - base, (prefix - 3 + k) as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_last_pos() {
+        // Table 9-38 prefix groups: 4-5, 6-7, 8-11, 12-15, 16-23, 24-31
+        assert_eq!(split_last_pos(3), (3, 0));
+        assert_eq!(split_last_pos(5), (4, 1));
+        assert_eq!(split_last_pos(7), (5, 1));
+        assert_eq!(split_last_pos(11), (6, 3));
+        assert_eq!(split_last_pos(12), (7, 0));
+        assert_eq!(split_last_pos(31), (9, 7));
+    }
+}
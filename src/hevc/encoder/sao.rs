@@ -0,0 +1,333 @@
+//! Sample adaptive offset parameter selection and syntax (H.265 7.3.8.3)
+//!
+//! Offsets are derived per CTB from the error statistics between the source
+//! and the deblocked reconstruction, and chosen by estimated
+//! rate-distortion cost against switching SAO off or merging with the left
+//! or above CTB.
+
+use super::bins::BinWriter;
+use crate::hevc::cabac::context;
+use crate::hevc::picture::DecodedFrame;
+use crate::hevc::sao::{SaoInfo, SaoMap};
+
+/// Largest sao_offset_abs for 8-bit samples
+const MAX_OFFSET: i64 = 7;
+
+/// Neighbour positions of the four edge offset classes, as in the filter
+const EO_NEIGHBOURS: [(isize, isize, isize, isize); 4] =
+    [(-1, 0, 1, 0), (0, -1, 0, 1), (-1, -1, 1, 1), (1, -1, -1, 1)];
+
+/// Sample count and sum of source minus reconstruction
+#[derive(Clone, Copy, Default)]
+struct ErrorSum {
+    count: i64,
+    sum: i64,
+}
+
+impl ErrorSum {
+    fn add(&mut self, error: i64) {
+        self.count += 1;
+        self.sum += error;
+    }
+
+    /// SSE change from adding `offset` to the samples
+    fn delta(&self, offset: i64) -> i64 {
+        self.count * offset * offset - 2 * offset * self.sum
+    }
+}
+
+/// Error statistics of one component of a CTB
+struct ComponentStats {
+    /// Per edge offset class, per category (edgeIdx 0, 1, 3, 4)
+    edge: [[ErrorSum; 4]; 4],
+    /// Per band of 8 sample values
+    band: [ErrorSum; 32],
+}
+
+/// Rate-distortion cost of SAO choices, in 1/256 SSE units
+struct CostModel {
+    lambda: i64,
+}
+
+impl CostModel {
+    fn cost(&self, delta: i64, bits: i64) -> i64 {
+        (delta << 8) + self.lambda * bits
+    }
+
+    /// Bits of sao_offset_abs (truncated unary, bypass)
+    fn offset_bits(abs: i64) -> i64 {
+        (abs + 1).min(MAX_OFFSET)
+    }
+
+    /// Best edge offsets of a class (signed values) and their cost
+    fn edge_offsets(&self, sums: &[ErrorSum; 4]) -> ([i64; 4], i64) {
+        let mut offsets = [0; 4];
+        let mut total = 0;
+        for (k, (offset, sums)) in offsets.iter_mut().zip(sums).enumerate() {
+            // Categories 1 and 2 (local minima) only brighten, 3 and 4 only darken
+            let sign = if k < 2 { 1 } else { -1 };
+            let (best, cost) = (0..=MAX_OFFSET)
+                .map(|abs| {
+                    (
+                        abs,
+                        self.cost(sums.delta(sign * abs), Self::offset_bits(abs)),
+                    )
+                })
+                .min_by_key(|&(_, cost)| cost)
+                .unwrap_or_default();
+            *offset = sign * best;
+            total += cost;
+        }
+        (offsets, total)
+    }
+
+    /// Best band offsets and band position and their cost
+    fn band_offsets(&self, bands: &[ErrorSum; 32]) -> ([i64; 4], u8, i64) {
+        let per_band: [(i64, i64); 32] = core::array::from_fn(|band| {
+            (-MAX_OFFSET..=MAX_OFFSET)
+                .map(|offset| {
+                    let bits = Self::offset_bits(offset.abs()) + i64::from(offset != 0);
+                    (offset, self.cost(bands[band].delta(offset), bits))
+                })
+                .min_by_key(|&(_, cost)| cost)
+                .unwrap_or_default()
+        });
+        let mut best = ([0; 4], 0, i64::MAX);
+        for position in 0..32 {
+            let window: [(i64, i64); 4] = core::array::from_fn(|k| per_band[(position + k) & 31]);
+            let cost = window.iter().map(|&(_, cost)| cost).sum::<i64>() + self.lambda * 5;
+            if cost < best.2 {
+                best = (window.map(|(offset, _)| offset), position as u8, cost);
+            }
+        }
+        best
+    }
+}
+
+/// Choose the SAO parameters of every CTB
+///
+/// `lambda` is the Lagrange multiplier of the mode decisions, in 1/256.
+pub(super) fn choose_sao(
+    source: &DecodedFrame,
+    deblocked: &DecodedFrame,
+    ctb_size: u32,
+    lambda: u64,
+) -> SaoMap {
+    let width_ctbs = source.width.div_ceil(ctb_size);
+    let height_ctbs = source.height.div_ceil(ctb_size);
+    let mut map = SaoMap::new(width_ctbs, height_ctbs);
+    let model = CostModel {
+        lambda: lambda as i64,
+    };
+
+    for y_ctb in 0..height_ctbs {
+        for x_ctb in 0..width_ctbs {
+            let stats: [ComponentStats; 3] = core::array::from_fn(|c_idx| {
+                collect_stats(source, deblocked, c_idx as u8, x_ctb, y_ctb, ctb_size)
+            });
+
+            let (mut best, own_cost) = choose_ctb(&model, &stats);
+            let mut best_cost = own_cost;
+            let merge_candidates = [
+                (x_ctb > 0).then(|| (*map.get(x_ctb - 1, y_ctb), 1)),
+                (y_ctb > 0).then(|| (*map.get(x_ctb, y_ctb - 1), 1 + i64::from(x_ctb > 0))),
+            ];
+            for (candidate, bits) in merge_candidates.into_iter().flatten() {
+                let cost = info_delta(&candidate, &stats) * 256 + model.lambda * bits;
+                if cost < best_cost {
+                    (best, best_cost) = (candidate, cost);
+                }
+            }
+            *map.get_mut(x_ctb, y_ctb) = best;
+        }
+    }
+    map
+}
+
+/// Best new SAO parameters of a CTB and their cost
+fn choose_ctb(model: &CostModel, stats: &[ComponentStats; 3]) -> (SaoInfo, i64) {
+    let mut info = SaoInfo::default();
+    let mut total = 0;
+
+    // Luma: off, band, or one of the edge classes
+    let mut luma_best = 0i64;
+    let (offsets, position, cost) = model.band_offsets(&stats[0].band);
+    let band_cost = cost + model.lambda * 2;
+    if band_cost < luma_best {
+        luma_best = band_cost;
+        set_offsets(&mut info, 0, 1, offsets, 0, position);
+    }
+    for class in 0..4 {
+        let (offsets, cost) = model.edge_offsets(&stats[0].edge[class]);
+        let cost = cost + model.lambda * 4;
+        if cost < luma_best {
+            luma_best = cost;
+            set_offsets(&mut info, 0, 2, offsets, class as u8, 0);
+        }
+    }
+    total += luma_best + model.lambda;
+
+    // Chroma: Cb and Cr share the type and the edge class
+    let mut chroma_best = 0i64;
+    let band = [1, 2].map(|c| model.band_offsets(&stats[c].band));
+    let band_cost = band[0].2 + band[1].2 + model.lambda * 2;
+    if band_cost < chroma_best {
+        chroma_best = band_cost;
+        for (c, (offsets, position, _)) in [1, 2].into_iter().zip(band) {
+            set_offsets(&mut info, c, 1, offsets, 0, position);
+        }
+    }
+    for class in 0..4 {
+        let edge = [1, 2].map(|c| model.edge_offsets(&stats[c].edge[class]));
+        let cost = edge[0].1 + edge[1].1 + model.lambda * 4;
+        if cost < chroma_best {
+            chroma_best = cost;
+            for (c, (offsets, _)) in [1, 2].into_iter().zip(edge) {
+                set_offsets(&mut info, c, 2, offsets, class as u8, 0);
+            }
+        }
+    }
+    total += chroma_best + model.lambda;
+
+    (info, total)
+}
+
+fn set_offsets(
+    info: &mut SaoInfo,
+    c_idx: usize,
+    sao_type: u8,
+    offsets: [i64; 4],
+    class: u8,
+    band: u8,
+) {
+    info.sao_type_idx[c_idx] = sao_type;
+    info.sao_eo_class[c_idx] = class;
+    info.sao_band_position[c_idx] = band;
+    // Edge offsets are stored as magnitudes, the filter applies the signs
+    info.sao_offset_val[c_idx] = offsets.map(|o| (if sao_type == 2 { o.abs() } else { o }) as i16);
+}
+
+/// SSE change of a CTB from applying `info`
+fn info_delta(info: &SaoInfo, stats: &[ComponentStats; 3]) -> i64 {
+    let mut delta = 0;
+    for (c_idx, stats) in stats.iter().enumerate() {
+        let offsets = info.sao_offset_val[c_idx].map(i64::from);
+        match info.sao_type_idx[c_idx] {
+            1 => {
+                for (k, &offset) in offsets.iter().enumerate() {
+                    let band = (info.sao_band_position[c_idx] as usize + k) & 31;
+                    delta += stats.band[band].delta(offset);
+                }
+            }
+            2 => {
+                let sums = &stats.edge[info.sao_eo_class[c_idx] as usize];
+                for (k, &offset) in offsets.iter().enumerate() {
+                    delta += sums[k].delta(if k < 2 { offset } else { -offset });
+                }
+            }
+            _ => {}
+        }
+    }
+    delta
+}
+
+fn collect_stats(
+    source: &DecodedFrame,
+    deblocked: &DecodedFrame,
+    c_idx: u8,
+    x_ctb: u32,
+    y_ctb: u32,
+    ctb_size: u32,
+) -> ComponentStats {
+    let mut stats = ComponentStats {
+        edge: [[ErrorSum::default(); 4]; 4],
+        band: [ErrorSum::default(); 32],
+    };
+    let (src, src_stride) = source.plane(c_idx);
+    let (rec, stride) = deblocked.plane(c_idx);
+    let (plane_w, plane_h) = if c_idx == 0 {
+        (source.width as usize, source.height as usize)
+    } else {
+        (source.width as usize / 2, source.height as usize / 2)
+    };
+    let size = if c_idx == 0 { ctb_size } else { ctb_size / 2 } as usize;
+    let (x0, y0) = (x_ctb as usize * size, y_ctb as usize * size);
+
+    for y in y0..(y0 + size).min(plane_h) {
+        for x in x0..(x0 + size).min(plane_w) {
+            let sample = rec[y * stride + x];
+            let error = i64::from(src[y * src_stride + x]) - i64::from(sample);
+            stats.band[(sample >> 3) as usize & 31].add(error);
+
+            for (class, &(dx0, dy0, dx1, dy1)) in EO_NEIGHBOURS.iter().enumerate() {
+                let neighbour = |dx: isize, dy: isize| {
+                    let (nx, ny) = (x.checked_add_signed(dx)?, y.checked_add_signed(dy)?);
+                    (nx < plane_w && ny < plane_h).then(|| rec[ny * stride + nx])
+                };
+                let (Some(a), Some(b)) = (neighbour(dx0, dy0), neighbour(dx1, dy1)) else {
+                    continue;
+                };
+                let edge_idx = 2 + sample.cmp(&a) as i32 + sample.cmp(&b) as i32;
+                let category = match edge_idx {
+                    0 => 0,
+                    1 => 1,
+                    3 => 2,
+                    4 => 3,
+                    _ => continue,
+                };
+                stats.edge[class][category].add(error);
+            }
+        }
+    }
+    stats
+}
+
+/// Write sao() of the CTB at (x_ctb, y_ctb), merging with the left or above
+/// CTB when their parameters are identical
+pub(super) fn write_sao<W: BinWriter>(w: &mut W, map: &SaoMap, x_ctb: u32, y_ctb: u32) {
+    let info = map.get(x_ctb, y_ctb);
+    if x_ctb > 0 {
+        let merge = map.get(x_ctb - 1, y_ctb) == info;
+        w.bin(context::SAO_MERGE_FLAG, u8::from(merge));
+        if merge {
+            return;
+        }
+    }
+    if y_ctb > 0 {
+        let merge = map.get(x_ctb, y_ctb - 1) == info;
+        w.bin(context::SAO_MERGE_FLAG, u8::from(merge));
+        if merge {
+            return;
+        }
+    }
+
+    for c_idx in 0..3 {
+        let sao_type = info.sao_type_idx[c_idx];
+        if c_idx < 2 {
+            w.bin(context::SAO_TYPE_IDX, u8::from(sao_type != 0));
+            if sao_type != 0 {
+                w.bypass(u8::from(sao_type == 2));
+            }
+        }
+        if sao_type == 0 {
+            continue;
+        }
+
+        let offsets = info.sao_offset_val[c_idx];
+        for &offset in &offsets {
+            let abs = u32::from(offset.unsigned_abs());
+            w.bypass_bits((1 << abs) - 1, abs as u8);
+            if abs < MAX_OFFSET as u32 {
+                w.bypass(0);
+            }
+        }
+        if sao_type == 1 {
+            for &offset in offsets.iter().filter(|&&o| o != 0) {
+                w.bypass(u8::from(offset < 0));
+            }
+            w.bypass_bits(u32::from(info.sao_band_position[c_idx]), 5);
+        } else if c_idx < 2 {
+            w.bypass_bits(u32::from(info.sao_eo_class[c_idx]), 2);
+        }
+    }
+}
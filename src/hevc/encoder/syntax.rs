@@ -0,0 +1,233 @@
+//! Parameter sets and slice header (H.265 7.3.2, 7.3.6)
+
+use alloc::vec::Vec;
+
+use super::ctu::{LOG2_CTB_SIZE, LOG2_MIN_CB_SIZE};
+use crate::hevc::bitstream::{BitstreamWriter, NalType, write_nal_unit};
+
+/// general_profile_idc of the Main profile
+pub const PROFILE_IDC_MAIN: u8 = 1;
+
+/// general_profile_compatibility_flag[1] and [2] (Main and Main 10), first flag
+/// in the most significant bit
+pub const PROFILE_COMPATIBILITY_FLAGS: u32 = 0x6000_0000;
+
+/// Progressive source and frame only constraint flags of the 48 general
+/// constraint indicator bits
+pub const CONSTRAINT_INDICATOR_FLAGS: u64 = 0b1001 << 44;
+
+/// Largest transform block size (log2)
+const LOG2_MAX_TB_SIZE: u8 = 5;
+
+/// Stream-level choices shared by the parameter sets and the slice header
+pub(super) struct StreamParams {
+    /// Coded luma width, a multiple of the minimum coding block size
+    pub width: u32,
+    /// Coded luma height, a multiple of the minimum coding block size
+    pub height: u32,
+    /// Luma columns cropped on the right by the conformance window (even)
+    pub crop_right: u32,
+    /// Luma rows cropped at the bottom by the conformance window (even)
+    pub crop_bottom: u32,
+    /// video_full_range_flag
+    pub full_range: bool,
+    /// matrix_coeffs of the colour description
+    pub matrix_coeffs: u8,
+    /// Slice QP
+    pub qp: i32,
+    /// Deblocking beta and tC offsets (divided by two), `None` to disable it
+    pub deblocking: Option<(i8, i8)>,
+    /// Leave room in the DPB for two references and a reordered picture,
+    /// and enable temporal motion vector prediction
+    pub inter: bool,
+    /// Explicit weighted prediction of P slices (weighted_pred_flag)
+    pub weighted_pred: bool,
+    /// Code 4:4:4 as three monochrome colour planes
+    /// (separate_colour_plane_flag) instead of coding 4:2:0
+    pub separate_colour_planes: bool,
+    /// nuh_layer_id of the layer, also the ID of its SPS and PPS
+    pub layer_id: u8,
+}
+
+impl StreamParams {
+    /// Lowest general_level_idc whose picture size limits fit (Table A.8)
+    pub fn level_idc(&self) -> u8 {
+        const LEVELS: [(u64, u8); 8] = [
+            (36_864, 30),
+            (122_880, 60),
+            (245_760, 63),
+            (552_960, 90),
+            (983_040, 93),
+            (2_228_224, 120),
+            (8_912_896, 150),
+            (35_651_584, 180),
+        ];
+        let size = u64::from(self.width) * u64::from(self.height);
+        let max_dim = u64::from(self.width.max(self.height));
+        LEVELS
+            .iter()
+            .find(|&&(max_luma_ps, _)| size <= max_luma_ps && max_dim * max_dim <= 8 * max_luma_ps)
+            .map_or(186, |&(_, level)| level)
+    }
+}
+
+/// profile_tier_level() for a single sub-layer
+pub(super) fn write_profile_tier_level(w: &mut BitstreamWriter, level_idc: u8) {
+    w.write_bits(0, 2); // general_profile_space
+    w.write_flag(false); // general_tier_flag
+    w.write_bits(u32::from(PROFILE_IDC_MAIN), 5);
+    w.write_bits(PROFILE_COMPATIBILITY_FLAGS, 32);
+    w.write_bits((CONSTRAINT_INDICATOR_FLAGS >> 16) as u32, 32);
+    w.write_bits((CONSTRAINT_INDICATOR_FLAGS & 0xFFFF) as u32, 16);
+    w.write_bits(u32::from(level_idc), 8);
+}
+
+/// Video parameter set NAL unit
+pub(super) fn video_parameter_set(params: &StreamParams) -> Vec<u8> {
+    let mut w = BitstreamWriter::new();
+    w.write_bits(0, 4); // vps_video_parameter_set_id
+    w.write_flag(true); // vps_base_layer_internal_flag
+    w.write_flag(true); // vps_base_layer_available_flag
+    w.write_bits(0, 6); // vps_max_layers_minus1
+    w.write_bits(0, 3); // vps_max_sub_layers_minus1
+    w.write_flag(true); // vps_temporal_id_nesting_flag
+    w.write_bits(0xFFFF, 16);
+    write_profile_tier_level(&mut w, params.level_idc());
+    w.write_flag(true); // vps_sub_layer_ordering_info_present_flag
+    w.write_ue(if params.inter { 2 } else { 0 }); // vps_max_dec_pic_buffering_minus1
+    w.write_ue(u32::from(params.inter)); // vps_max_num_reorder_pics
+    w.write_ue(0); // vps_max_latency_increase_plus1
+    w.write_bits(0, 6); // vps_max_layer_id
+    w.write_ue(0); // vps_num_layer_sets_minus1
+    w.write_flag(false); // vps_timing_info_present_flag
+    w.write_flag(false); // vps_extension_flag
+    w.write_trailing_bits();
+    write_nal_unit(NalType::VpsNut, 0, &w.finish())
+}
+
+/// Sequence parameter set NAL unit
+pub(super) fn sequence_parameter_set(params: &StreamParams) -> Vec<u8> {
+    let mut w = BitstreamWriter::new();
+    w.write_bits(0, 4); // sps_video_parameter_set_id
+    w.write_bits(0, 3); // sps_max_sub_layers_minus1
+    w.write_flag(true); // sps_temporal_id_nesting_flag
+    write_profile_tier_level(&mut w, params.level_idc());
+    w.write_ue(u32::from(params.layer_id)); // sps_seq_parameter_set_id
+    if params.separate_colour_planes {
+        w.write_ue(3); // chroma_format_idc: 4:4:4
+        w.write_flag(true); // separate_colour_plane_flag
+    } else {
+        w.write_ue(1); // chroma_format_idc: 4:2:0
+    }
+    w.write_ue(params.width);
+    w.write_ue(params.height);
+    let cropped = params.crop_right != 0 || params.crop_bottom != 0;
+    w.write_flag(cropped);
+    if cropped {
+        // Offsets are in chroma sample units
+        let sub = if params.separate_colour_planes { 1 } else { 2 };
+        w.write_ue(0);
+        w.write_ue(params.crop_right / sub);
+        w.write_ue(0);
+        w.write_ue(params.crop_bottom / sub);
+    }
+    w.write_ue(0); // bit_depth_luma_minus8
+    w.write_ue(0); // bit_depth_chroma_minus8
+    w.write_ue(4); // log2_max_pic_order_cnt_lsb_minus4
+    w.write_flag(true); // sps_sub_layer_ordering_info_present_flag
+    w.write_ue(if params.inter { 2 } else { 0 }); // sps_max_dec_pic_buffering_minus1
+    w.write_ue(u32::from(params.inter)); // sps_max_num_reorder_pics
+    w.write_ue(0); // sps_max_latency_increase_plus1
+    w.write_ue(u32::from(LOG2_MIN_CB_SIZE - 3));
+    w.write_ue(u32::from(LOG2_CTB_SIZE - LOG2_MIN_CB_SIZE));
+    w.write_ue(0); // log2_min_luma_transform_block_size_minus2
+    w.write_ue(u32::from(LOG2_MAX_TB_SIZE - 2));
+    w.write_ue(0); // max_transform_hierarchy_depth_inter
+    w.write_ue(0); // max_transform_hierarchy_depth_intra
+    w.write_flag(false); // scaling_list_enabled_flag
+    w.write_flag(false); // amp_enabled_flag
+    w.write_flag(true); // sample_adaptive_offset_enabled_flag
+    w.write_flag(false); // pcm_enabled_flag
+    w.write_ue(0); // num_short_term_ref_pic_sets
+    w.write_flag(false); // long_term_ref_pics_present_flag
+    w.write_flag(params.inter); // sps_temporal_mvp_enabled_flag
+    w.write_flag(true); // strong_intra_smoothing_enabled_flag
+
+    w.write_flag(true); // vui_parameters_present_flag
+    w.write_flag(false); // aspect_ratio_info_present_flag
+    w.write_flag(false); // overscan_info_present_flag
+    w.write_flag(true); // video_signal_type_present_flag
+    w.write_bits(5, 3); // video_format: unspecified
+    w.write_flag(params.full_range);
+    w.write_flag(true); // colour_description_present_flag
+    w.write_bits(1, 8); // colour_primaries: BT.709
+    w.write_bits(13, 8); // transfer_characteristics: sRGB
+    w.write_bits(u32::from(params.matrix_coeffs), 8);
+    w.write_flag(false); // chroma_loc_info_present_flag
+    w.write_flag(false); // neutral_chroma_indication_flag
+    w.write_flag(false); // field_seq_flag
+    w.write_flag(false); // frame_field_info_present_flag
+    w.write_flag(false); // default_display_window_flag
+    w.write_flag(false); // vui_timing_info_present_flag
+    w.write_flag(false); // bitstream_restriction_flag
+
+    w.write_flag(false); // sps_extension_present_flag
+    w.write_trailing_bits();
+    write_nal_unit(NalType::SpsNut, params.layer_id, &w.finish())
+}
+
+/// Picture parameter set NAL unit
+pub(super) fn picture_parameter_set(params: &StreamParams) -> Vec<u8> {
+    let mut w = BitstreamWriter::new();
+    w.write_ue(u32::from(params.layer_id)); // pps_pic_parameter_set_id
+    w.write_ue(u32::from(params.layer_id)); // pps_seq_parameter_set_id
+    w.write_flag(false); // dependent_slice_segments_enabled_flag
+    w.write_flag(false); // output_flag_present_flag
+    w.write_bits(0, 3); // num_extra_slice_header_bits
+    w.write_flag(false); // sign_data_hiding_enabled_flag
+    w.write_flag(false); // cabac_init_present_flag
+    w.write_ue(0); // num_ref_idx_l0_default_active_minus1
+    w.write_ue(0); // num_ref_idx_l1_default_active_minus1
+    w.write_se(params.qp - 26); // init_qp_minus26
+    w.write_flag(false); // constrained_intra_pred_flag
+    w.write_flag(false); // transform_skip_enabled_flag
+    w.write_flag(false); // cu_qp_delta_enabled_flag
+    w.write_se(0); // pps_cb_qp_offset
+    w.write_se(0); // pps_cr_qp_offset
+    w.write_flag(false); // pps_slice_chroma_qp_offsets_present_flag
+    w.write_flag(params.weighted_pred); // weighted_pred_flag
+    w.write_flag(false); // weighted_bipred_flag
+    w.write_flag(false); // transquant_bypass_enabled_flag
+    w.write_flag(false); // tiles_enabled_flag
+    w.write_flag(false); // entropy_coding_sync_enabled_flag
+    w.write_flag(false); // pps_loop_filter_across_slices_enabled_flag
+    w.write_flag(true); // deblocking_filter_control_present_flag
+    w.write_flag(false); // deblocking_filter_override_enabled_flag
+    w.write_flag(params.deblocking.is_none()); // pps_deblocking_filter_disabled_flag
+    if let Some((beta_offset_div2, tc_offset_div2)) = params.deblocking {
+        w.write_se(i32::from(beta_offset_div2));
+        w.write_se(i32::from(tc_offset_div2));
+    }
+    w.write_flag(false); // pps_scaling_list_data_present_flag
+    w.write_flag(false); // lists_modification_present_flag
+    w.write_ue(0); // log2_parallel_merge_level_minus2
+    w.write_flag(false); // slice_segment_header_extension_present_flag
+    w.write_flag(false); // pps_extension_present_flag
+    w.write_trailing_bits();
+    write_nal_unit(NalType::PpsNut, params.layer_id, &w.finish())
+}
+
+/// Slice segment header of the single IDR I slice, byte aligned for the
+/// slice data that follows
+pub(super) fn slice_header() -> BitstreamWriter {
+    let mut w = BitstreamWriter::new();
+    w.write_flag(true); // first_slice_segment_in_pic_flag
+    w.write_flag(false); // no_output_of_prior_pics_flag
+    w.write_ue(0); // slice_pic_parameter_set_id
+    w.write_ue(2); // slice_type: I
+    w.write_flag(true); // slice_sao_luma_flag
+    w.write_flag(true); // slice_sao_chroma_flag
+    w.write_se(0); // slice_qp_delta
+    w.write_trailing_bits(); // byte_alignment()
+    w
+}
@@ -0,0 +1,278 @@
+//! Film grain synthesis from film grain characteristics SEI (H.265 D.3.22)
+//!
+//! Grain synthesis is informative in HEVC, so the grain is not bit-exact with
+//! other decoders; it is deterministic for a seed. For each colour component
+//! and intensity interval a 32x32 grain pattern with unit variance is made,
+//! band-limited through the inverse DCT (frequency filtering model) or shaped
+//! by an auto-regression (auto-regressive model). Each 16x16 block of the
+//! plane takes a window of the pattern at a random offset, from the interval
+//! holding the block's average intensity, scaled to the interval's standard
+//! deviation.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::picture::DecodedFrame;
+use super::sei::FilmGrainCharacteristics;
+use super::transform;
+
+/// Side of the grain patterns
+const PATTERN_SIZE: usize = 32;
+/// Side of the blocks that share a pattern window and intensity interval
+const BLOCK_SIZE: usize = 16;
+/// Pattern samples with a standard deviation of one, in Q8
+const UNIT: i64 = 256;
+
+/// Add film grain to the planes of a frame
+pub(crate) fn apply(frame: &mut DecodedFrame, grain: &FilmGrainCharacteristics, seed: u32) {
+    if grain.model_id > 1 {
+        return;
+    }
+    let bit_depth = frame.bit_depth;
+    let c_stride = frame.c_stride() as u32;
+    let planes = [
+        (&mut frame.y_plane, frame.width),
+        (&mut frame.cb_plane, c_stride),
+        (&mut frame.cr_plane, c_stride),
+    ];
+    for (c, ((plane, width), intervals)) in planes.into_iter().zip(&grain.components).enumerate() {
+        let Some(intervals) = intervals else {
+            continue;
+        };
+        if width == 0 || plane.is_empty() {
+            continue;
+        }
+        let mut rng = Rng::new(seed ^ (c as u32 + 1).wrapping_mul(0x9E37_79B9));
+        let patterns: Vec<Vec<i32>> = intervals
+            .iter()
+            .map(|interval| {
+                if grain.model_id == 0 {
+                    frequency_pattern(&mut rng, &interval.model_values)
+                } else {
+                    autoregressive_pattern(
+                        &mut rng,
+                        &interval.model_values,
+                        grain.log2_scale_factor,
+                    )
+                }
+            })
+            .collect();
+
+        let width = width as usize;
+        let height = plane.len() / width;
+        let max = (1i64 << bit_depth) - 1;
+        for by in (0..height).step_by(BLOCK_SIZE) {
+            for bx in (0..width).step_by(BLOCK_SIZE) {
+                let offset = rng.next();
+                let (ox, oy) = ((offset & 15) as usize, ((offset >> 4) & 15) as usize);
+                let (bw, bh) = (BLOCK_SIZE.min(width - bx), BLOCK_SIZE.min(height - by));
+
+                let sum: u64 = (by..by + bh)
+                    .flat_map(|y| &plane[y * width + bx..y * width + bx + bw])
+                    .map(|&s| u64::from(s))
+                    .sum();
+                let average = (sum / (bw * bh) as u64) >> (bit_depth - 8);
+                let Some((interval, pattern)) = intervals.iter().zip(&patterns).find(|(i, _)| {
+                    (u64::from(i.lower_bound)..=u64::from(i.upper_bound)).contains(&average)
+                }) else {
+                    continue;
+                };
+                let sigma = i64::from(interval.model_values.first().copied().unwrap_or(0));
+                let shift = 8 + u32::from(grain.log2_scale_factor);
+
+                for y in 0..bh {
+                    let row = &mut plane[(by + y) * width + bx..][..bw];
+                    let pattern_row = &pattern[(oy + y) * PATTERN_SIZE + ox..][..bw];
+                    for (sample, &p) in row.iter_mut().zip(pattern_row) {
+                        let value = i64::from(*sample);
+                        // Grain in sample units at the plane's bit depth
+                        let g = ((i64::from(p) * sigma) << (bit_depth - 8)) >> shift;
+                        let out = if grain.blending_mode_id == 0 {
+                            value + g
+                        } else {
+                            value + ((value * g) >> bit_depth)
+                        };
+                        *sample = out.clamp(0, max) as u16;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Grain pattern of the frequency filtering model: random DCT coefficients
+/// within the cut-off frequencies, inverse transformed
+///
+/// Model values: standard deviation, horizontal and vertical high cut-off,
+/// horizontal and vertical low cut-off (frequencies of a 16x16 DCT); the
+/// cross-component correlation is not modelled.
+fn frequency_pattern(rng: &mut Rng, values: &[i32]) -> Vec<i32> {
+    let value = |i: usize, default: i32| values.get(i).copied().unwrap_or(default);
+    let high_h = value(1, 8);
+    let high_v = value(2, high_h);
+    let (low_h, low_v) = (value(3, 0), value(4, 0));
+
+    let mut coeffs = [0i16; PATTERN_SIZE * PATTERN_SIZE];
+    for (i, coeff) in coeffs.iter_mut().enumerate() {
+        // Every coefficient draws a number so that the sequence does not
+        // depend on the cut-offs
+        let g = rng.gaussian() * 8;
+        let (u, v) = ((i % PATTERN_SIZE) as i32 / 2, (i / PATTERN_SIZE) as i32 / 2);
+        if (low_h..=high_h).contains(&u) && (low_v..=high_v).contains(&v) {
+            *coeff = g as i16;
+        }
+    }
+    let mut samples = [0i16; PATTERN_SIZE * PATTERN_SIZE];
+    transform::idct32(&coeffs, &mut samples, 8);
+    normalize(samples.iter().map(|&s| i32::from(s)).collect())
+}
+
+/// Grain pattern of the auto-regressive model
+///
+/// Model values: standard deviation, correlation with the left and upper
+/// neighbours, cross-component correlation (not modelled), correlation with
+/// the upper diagonal neighbours, aspect ratio (not modelled), correlation
+/// with the neighbours two samples left and up. Correlations are in units of
+/// 2^-log2_scale_factor.
+fn autoregressive_pattern(rng: &mut Rng, values: &[i32], log2_scale_factor: u8) -> Vec<i32> {
+    let value = |i: usize| i64::from(values.get(i).copied().unwrap_or(0));
+    let (c1, c3, c5) = (value(1), value(3), value(5));
+
+    // The recursion starts from zero outside the field; a margin lets the
+    // pattern settle before the part that is kept
+    const MARGIN: usize = 8;
+    let width = PATTERN_SIZE + 2 * MARGIN;
+    let height = PATTERN_SIZE + MARGIN;
+    let mut field = vec![0i64; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: isize, dy: isize| {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                if nx < 0 || ny < 0 || nx >= width as isize {
+                    0
+                } else {
+                    field[ny as usize * width + nx as usize]
+                }
+            };
+            let correlated = c1 * (at(-1, 0) + at(0, -1))
+                + c3 * (at(-1, -1) + at(1, -1))
+                + c5 * (at(-2, 0) + at(0, -2));
+            let sample = i64::from(rng.gaussian()) + (correlated >> log2_scale_factor);
+            field[y * width + x] = sample.clamp(-(1 << 16), 1 << 16);
+        }
+    }
+    let pattern = (MARGIN..height)
+        .flat_map(|y| &field[y * width + MARGIN..y * width + MARGIN + PATTERN_SIZE])
+        .map(|&s| s as i32)
+        .collect();
+    normalize(pattern)
+}
+
+/// Scale a pattern to a standard deviation of [`UNIT`]
+fn normalize(mut pattern: Vec<i32>) -> Vec<i32> {
+    let energy: u64 = pattern
+        .iter()
+        .map(|&s| (i64::from(s) * i64::from(s)) as u64)
+        .sum();
+    let rms = (energy / pattern.len() as u64).isqrt() as i64;
+    for sample in &mut pattern {
+        *sample = if rms == 0 {
+            0
+        } else {
+            (i64::from(*sample) * UNIT / rms) as i32
+        };
+    }
+    pattern
+}
+
+/// xorshift32 pseudo-random generator
+struct Rng(u32);
+
+impl Rng {
+    fn new(seed: u32) -> Self {
+        // xorshift never leaves zero
+        Self(if seed == 0 { 0x2545_F491 } else { seed })
+    }
+
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    /// Roughly normal value with a standard deviation of [`UNIT`]
+    fn gaussian(&mut self) -> i32 {
+        // Sum of four uniform bytes (Irwin-Hall), standard deviation 147.8
+        let sum: i32 = self
+            .next()
+            .to_le_bytes()
+            .iter()
+            .map(|&b| i32::from(b) * 2 - 255)
+            .sum();
+        // Doubled bytes: standard deviation 295.6, scaled to 256
+        sum * 443 / 512
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hevc::{FilmGrainInterval, SeiMessage};
+
+    #[test]
+    fn test_film_grain() {
+        let mut frame = DecodedFrame::with_params(64, 48, 8, 1);
+        frame.y_plane.fill(100);
+        frame.cb_plane.fill(128);
+        frame.cr_plane.fill(128);
+        let clean = frame.clone();
+        assert!(!frame.clone().apply_film_grain(1));
+
+        // Grain of standard deviation 6 on luma only, for intensities up to 150
+        let grain = |model_id, model_values: Vec<i32>| FilmGrainCharacteristics {
+            model_id,
+            colour_description: None,
+            blending_mode_id: 0,
+            log2_scale_factor: 2,
+            components: [
+                Some(alloc::vec![FilmGrainInterval {
+                    lower_bound: 0,
+                    upper_bound: 150,
+                    model_values,
+                }]),
+                None,
+                None,
+            ],
+            persistence: false,
+        };
+        for (model_id, values) in [(0, alloc::vec![24, 10, 6]), (1, alloc::vec![24, 1])] {
+            frame.sei = alloc::vec![SeiMessage::FilmGrainCharacteristics(Some(grain(
+                model_id, values
+            )))];
+            let mut grainy = frame.clone();
+            assert!(grainy.apply_film_grain(7));
+            let mut again = frame.clone();
+            again.apply_film_grain(7);
+            assert_eq!(grainy.y_plane, again.y_plane, "deterministic");
+            let mut other = frame.clone();
+            other.apply_film_grain(8);
+            assert_ne!(grainy.y_plane, other.y_plane);
+
+            assert_eq!(grainy.cb_plane, clean.cb_plane);
+            let energy: i64 = grainy
+                .y_plane
+                .iter()
+                .map(|&s| (i64::from(s) - 100).pow(2))
+                .sum();
+            let variance = energy / grainy.y_plane.len() as i64;
+            assert!((25..=49).contains(&variance), "variance {variance}");
+        }
+
+        // Blocks brighter than the interval get no grain
+        frame.y_plane.fill(200);
+        let mut bright = frame.clone();
+        bright.apply_film_grain(7);
+        assert_eq!(bright.y_plane, frame.y_plane);
+    }
+}
@@ -0,0 +1,235 @@
+//! Decoded picture hash computation (H.265 D.3.19)
+
+use super::picture::DecodedFrame;
+use super::sei::DecodedPictureHash;
+use alloc::vec::Vec;
+
+/// Whether the planes of a frame match a decoded picture hash
+///
+/// The hash covers the whole decoded picture, without conformance window
+/// cropping; it has one value per colour component.
+pub(crate) fn matches(frame: &DecodedFrame, hash: &DecodedPictureHash) -> bool {
+    let components = component_data(frame);
+    let count = match hash {
+        DecodedPictureHash::Md5(values) => values.len(),
+        DecodedPictureHash::Crc(values) => values.len(),
+        DecodedPictureHash::Checksum(values) => values.len(),
+    };
+    if count != components.len() {
+        return false;
+    }
+    components
+        .iter()
+        .enumerate()
+        .all(|(c, &(samples, width))| match hash {
+            DecodedPictureHash::Md5(values) => {
+                md5(&picture_bytes(samples, frame.bit_depth)) == values[c]
+            }
+            DecodedPictureHash::Crc(values) => crc(samples, frame.bit_depth) == values[c],
+            DecodedPictureHash::Checksum(values) => {
+                checksum(samples, width, frame.bit_depth) == values[c]
+            }
+        })
+}
+
+/// Samples and width of each colour component
+fn component_data(frame: &DecodedFrame) -> Vec<(&[u16], u32)> {
+    let chroma_width = match frame.chroma_format {
+        0 => return alloc::vec![(frame.y_plane.as_slice(), frame.width)],
+        3 => frame.width,
+        _ => frame.width.div_ceil(2),
+    };
+    alloc::vec![
+        (frame.y_plane.as_slice(), frame.width),
+        (frame.cb_plane.as_slice(), chroma_width),
+        (frame.cr_plane.as_slice(), chroma_width),
+    ]
+}
+
+/// pictureData: one byte per sample up to 8 bits, else two, low byte first
+fn picture_bytes(samples: &[u16], bit_depth: u8) -> Vec<u8> {
+    if bit_depth > 8 {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    } else {
+        samples.iter().map(|&s| s as u8).collect()
+    }
+}
+
+/// CRC-CCITT over pictureData, MSB first, followed by 16 zero bits
+fn crc(samples: &[u16], bit_depth: u8) -> u16 {
+    let step = |crc: u32, bit: u32| (((crc << 1) | bit) & 0xFFFF) ^ ((crc >> 15) * 0x1021);
+    let mut crc = 0xFFFFu32;
+    for &sample in samples {
+        let bytes = sample.to_le_bytes();
+        for &byte in &bytes[..if bit_depth > 8 { 2 } else { 1 }] {
+            for bit in (0..8).rev() {
+                crc = step(crc, u32::from(byte >> bit) & 1);
+            }
+        }
+    }
+    for _ in 0..16 {
+        crc = step(crc, 0);
+    }
+    crc as u16
+}
+
+/// Sum of the sample bytes, each XORed with a mask of its position
+fn checksum(samples: &[u16], width: u32, bit_depth: u8) -> u32 {
+    let mut sum = 0u32;
+    for (i, &sample) in samples.iter().enumerate() {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        let mask = (x & 0xFF) ^ (y & 0xFF) ^ (x >> 8) ^ (y >> 8);
+        sum = sum.wrapping_add((u32::from(sample) & 0xFF) ^ mask);
+        if bit_depth > 8 {
+            sum = sum.wrapping_add((u32::from(sample) >> 8) ^ mask);
+        }
+    }
+    sum
+}
+
+/// Per-round shift amounts of MD5
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+/// MD5 round constants, floor(abs(sin(i + 1)) * 2^32)
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// MD5 digest (RFC 1321)
+fn md5(data: &[u8]) -> [u8; 16] {
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    // Padding: a one bit, zeros up to 56 mod 64 bytes, the bit length
+    let tail_start = data.len() - data.len() % 64;
+    let mut tail = data[tail_start..].to_vec();
+    tail.push(0x80);
+    while tail.len() % 64 != 56 {
+        tail.push(0);
+    }
+    tail.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in data[..tail_start]
+        .chunks_exact(64)
+        .chain(tail.chunks_exact(64))
+    {
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(MD5_K[i])
+                .wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[i / 16 * 4 + i % 4]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0u8; 16];
+    for (bytes, s) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&s.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5_and_crc() {
+        let hex = |digest: [u8; 16]| {
+            digest
+                .iter()
+                .map(|b| alloc::format!("{b:02x}"))
+                .collect::<alloc::string::String>()
+        };
+        assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        let long: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        assert_eq!(hex(md5(&long)), "de809ff794e91b68f9e91a2b7030bcb0");
+        // CRC-16/AUG-CCITT check value
+        let digits: Vec<u16> = b"123456789".iter().map(|&b| u16::from(b)).collect();
+        assert_eq!(crc(&digits, 8), 0xE5CC);
+    }
+
+    #[test]
+    fn test_verify_picture_hash() {
+        use crate::hevc::bitstream::{NalType, write_nal_unit};
+
+        let mut picture = DecodedFrame::with_params(64, 48, 8, 1);
+        for (i, sample) in picture.y_plane.iter_mut().enumerate() {
+            *sample = (i % 251) as u16;
+        }
+        for (i, (cb, cr)) in picture
+            .cb_plane
+            .iter_mut()
+            .zip(&mut picture.cr_plane)
+            .enumerate()
+        {
+            (*cb, *cr) = (100 + (i % 50) as u16, 128);
+        }
+        let encoded = crate::hevc::encoder::encode_picture(&picture, 30, &[None]);
+        let stream = crate::hevc::encoder::tests::annex_b(&encoded);
+        let decoded = crate::hevc::decode(&stream).unwrap();
+        assert_eq!(decoded.verify_picture_hash(), None);
+
+        // A suffix SEI with each kind of hash of the decoded planes
+        let planes = component_data(&decoded);
+        let hashes: [(u8, Vec<u8>); 3] = [
+            (
+                0,
+                planes
+                    .iter()
+                    .flat_map(|&(s, _)| md5(&picture_bytes(s, 8)))
+                    .collect(),
+            ),
+            (
+                1,
+                planes
+                    .iter()
+                    .flat_map(|&(s, _)| crc(s, 8).to_be_bytes())
+                    .collect(),
+            ),
+            (
+                2,
+                planes
+                    .iter()
+                    .flat_map(|&(s, w)| checksum(s, w, 8).to_be_bytes())
+                    .collect(),
+            ),
+        ];
+        for (hash_type, values) in hashes {
+            let mut rbsp = alloc::vec![132, values.len() as u8 + 1, hash_type];
+            rbsp.extend(values);
+            rbsp.push(0x80);
+            let mut with_hash = stream.clone();
+            with_hash.extend_from_slice(&[0, 0, 0, 1]);
+            with_hash.extend(write_nal_unit(NalType::SuffixSeiNut, 0, &rbsp));
+
+            let mut frame = crate::hevc::decode(&with_hash).unwrap();
+            assert_eq!(frame.verify_picture_hash(), Some(true));
+            assert_eq!(frame.rotate_180().verify_picture_hash(), None);
+            frame.cr_plane[5] ^= 1;
+            assert_eq!(frame.verify_picture_hash(), Some(false));
+        }
+    }
+}
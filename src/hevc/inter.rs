@@ -0,0 +1,381 @@
+//! Inter prediction sample generation (H.265 8.5.3.3)
+//!
+//! Fractional sample interpolation of the reference pictures followed by
+//! default or explicit weighted sample prediction.
+
+use alloc::vec::Vec;
+
+use super::motion::{Mv, PbMotion};
+use super::params::Sps;
+use super::picture::DecodedFrame;
+use super::slice::PredWeightTable;
+
+/// Luma interpolation filter coefficients for quarter positions 1..=3 (Table 8-11)
+static LUMA_FILTER: [[i32; 8]; 3] = [
+    [-1, 4, -10, 58, 17, -5, 1, 0],
+    [-1, 4, -11, 40, 40, -11, 4, -1],
+    [0, 1, -5, 17, 58, -10, 4, -1],
+];
+
+/// Chroma interpolation filter coefficients for eighth positions 1..=7 (Table 8-12)
+static CHROMA_FILTER: [[i32; 4]; 7] = [
+    [-2, 58, 10, -2],
+    [-4, 54, 16, -2],
+    [-6, 46, 28, -4],
+    [-4, 36, 36, -4],
+    [-4, 28, 46, -6],
+    [-2, 16, 54, -4],
+    [-2, 10, 58, -2],
+];
+
+/// Scratch buffers reused across prediction blocks
+#[derive(Default)]
+pub struct InterBuffers {
+    /// Interpolated (14-bit intermediate) samples of list 0 and list 1
+    pred: [Vec<i32>; 2],
+    /// Reference window including the filter margins
+    src: Vec<i32>,
+    /// Horizontally filtered rows for the separable 2-D case
+    tmp: Vec<i32>,
+}
+
+/// Predict all colour components of one prediction block into `frame`
+///
+/// `refs` holds the reference picture of each list used by `motion`.
+#[allow(clippy::too_many_arguments)]
+pub fn predict_block(
+    frame: &mut DecodedFrame,
+    sps: &Sps,
+    refs: [Option<&DecodedFrame>; 2],
+    motion: &PbMotion,
+    x_pb: u32,
+    y_pb: u32,
+    w: u32,
+    h: u32,
+    weights: Option<&PredWeightTable>,
+    bufs: &mut InterBuffers,
+) {
+    let num_components = if sps.chroma_array_type() == 0 { 1 } else { 3 };
+    for c_idx in 0..num_components {
+        let (sub_w, sub_h, bit_depth) = if c_idx == 0 {
+            (1, 1, sps.bit_depth_y())
+        } else {
+            (sps.sub_width_c(), sps.sub_height_c(), sps.bit_depth_c())
+        };
+        let (xc, yc) = (x_pb / sub_w, y_pb / sub_h);
+        let (wc, hc) = ((w / sub_w) as usize, (h / sub_h) as usize);
+
+        let InterBuffers {
+            pred,
+            src: src_buf,
+            tmp,
+        } = &mut *bufs;
+        for (list, dst) in pred.iter_mut().enumerate() {
+            let Some(reference) = refs[list].filter(|_| motion.pred_flag[list]) else {
+                continue;
+            };
+            let (src, stride) = reference.plane(c_idx);
+            let src_w = (reference.width / sub_w) as i32;
+            let src_h = (reference.height / sub_h) as i32;
+            let mv = motion.mv[list];
+            dst.resize(wc * hc, 0);
+            if c_idx == 0 {
+                interpolate(
+                    Window {
+                        src,
+                        stride,
+                        width: src_w,
+                        height: src_h,
+                    },
+                    xc as i32 + (mv.x as i32 >> 2),
+                    yc as i32 + (mv.y as i32 >> 2),
+                    (mv.x & 3) as usize,
+                    (mv.y & 3) as usize,
+                    wc,
+                    hc,
+                    &LUMA_FILTER,
+                    bit_depth,
+                    dst,
+                    src_buf,
+                    tmp,
+                );
+            } else {
+                let mvc = chroma_mv(mv, sub_w, sub_h);
+                interpolate(
+                    Window {
+                        src,
+                        stride,
+                        width: src_w,
+                        height: src_h,
+                    },
+                    xc as i32 + (mvc.0 >> 3),
+                    yc as i32 + (mvc.1 >> 3),
+                    (mvc.0 & 7) as usize,
+                    (mvc.1 & 7) as usize,
+                    wc,
+                    hc,
+                    &CHROMA_FILTER,
+                    bit_depth,
+                    dst,
+                    src_buf,
+                    tmp,
+                );
+            }
+        }
+
+        let explicit = weights.map(|table| {
+            let denom = if c_idx == 0 {
+                table.luma_log2_weight_denom
+            } else {
+                table.chroma_log2_weight_denom
+            };
+            let wo = [0, 1].map(|list| {
+                table.weights[list]
+                    .get(motion.ref_idx[list].max(0) as usize)
+                    .map_or((1 << denom, 0), |pw| {
+                        if c_idx == 0 {
+                            pw.luma
+                        } else {
+                            pw.chroma[c_idx as usize - 1]
+                        }
+                    })
+            });
+            (denom, wo)
+        });
+
+        let (plane, stride) = frame.plane_mut(c_idx);
+        // Lazily sliced: the buffer of an unused list may be empty
+        let preds = [0, 1].map(|list| motion.pred_flag[list].then(|| &bufs.pred[list][..wc * hc]));
+        store_weighted(
+            plane,
+            stride,
+            xc as usize,
+            yc as usize,
+            wc,
+            hc,
+            preds,
+            bit_depth,
+            explicit,
+        );
+    }
+}
+
+/// Chroma motion vector in 1/8 chroma sample units (8-228, 8-229)
+fn chroma_mv(mv: Mv, sub_w: u32, sub_h: u32) -> (i32, i32) {
+    (
+        mv.x as i32 * 2 / sub_w as i32,
+        mv.y as i32 * 2 / sub_h as i32,
+    )
+}
+
+/// A reference sample plane
+struct Window<'a> {
+    src: &'a [u16],
+    stride: usize,
+    width: i32,
+    height: i32,
+}
+
+/// Fractional sample interpolation of a w x h block (8.5.3.3.3)
+///
+/// Reference sample coordinates outside the picture are clamped to the
+/// nearest edge sample. Output samples are at 14-bit intermediate precision.
+#[allow(clippy::too_many_arguments)]
+fn interpolate<const TAPS: usize>(
+    window: Window<'_>,
+    x_int: i32,
+    y_int: i32,
+    frac_x: usize,
+    frac_y: usize,
+    w: usize,
+    h: usize,
+    filters: &[[i32; TAPS]],
+    bit_depth: u8,
+    dst: &mut [i32],
+    src_buf: &mut Vec<i32>,
+    tmp: &mut Vec<i32>,
+) {
+    let shift1 = (bit_depth as i32 - 8).min(4);
+    let shift3 = (14 - bit_depth as i32).max(2);
+    let margin = TAPS / 2 - 1;
+
+    // Copy the reference window with padding so filtering needs no clamping
+    let src_w = w + TAPS - 1;
+    let src_h = h + TAPS - 1;
+    src_buf.resize(src_w * src_h, 0);
+    for row in 0..src_h {
+        let y = (y_int + row as i32 - margin as i32).clamp(0, window.height - 1) as usize;
+        let line = &window.src[y * window.stride..];
+        for col in 0..src_w {
+            let x = (x_int + col as i32 - margin as i32).clamp(0, window.width - 1) as usize;
+            src_buf[row * src_w + col] = line[x] as i32;
+        }
+    }
+    let at = |row: usize, col: usize| src_buf[(row + margin) * src_w + col + margin];
+
+    match (frac_x, frac_y) {
+        (0, 0) => {
+            for y in 0..h {
+                for x in 0..w {
+                    dst[y * w + x] = at(y, x) << shift3;
+                }
+            }
+        }
+        (fx, 0) => {
+            let f = &filters[fx - 1];
+            for y in 0..h {
+                let row = &src_buf[(y + margin) * src_w..];
+                for x in 0..w {
+                    let sum: i32 = (0..TAPS).map(|k| f[k] * row[x + k]).sum();
+                    dst[y * w + x] = sum >> shift1;
+                }
+            }
+        }
+        (0, fy) => {
+            let f = &filters[fy - 1];
+            for y in 0..h {
+                for x in 0..w {
+                    let sum: i32 = (0..TAPS)
+                        .map(|k| f[k] * src_buf[(y + k) * src_w + x + margin])
+                        .sum();
+                    dst[y * w + x] = sum >> shift1;
+                }
+            }
+        }
+        (fx, fy) => {
+            let fh = &filters[fx - 1];
+            let fv = &filters[fy - 1];
+            tmp.resize(src_h * w, 0);
+            for y in 0..src_h {
+                let row = &src_buf[y * src_w..];
+                for x in 0..w {
+                    let sum: i32 = (0..TAPS).map(|k| fh[k] * row[x + k]).sum();
+                    tmp[y * w + x] = sum >> shift1;
+                }
+            }
+            for y in 0..h {
+                for x in 0..w {
+                    let sum: i32 = (0..TAPS).map(|k| fv[k] * tmp[(y + k) * w + x]).sum();
+                    dst[y * w + x] = sum >> 6;
+                }
+            }
+        }
+    }
+}
+
+/// Weighted sample prediction (8.5.3.3.4)
+///
+/// `explicit` carries the log2 weight denominator and (weight, offset) per
+/// list for explicit weighted prediction; without it the default averaging
+/// is used.
+#[allow(clippy::too_many_arguments)]
+fn store_weighted(
+    plane: &mut [u16],
+    stride: usize,
+    x0: usize,
+    y0: usize,
+    w: usize,
+    h: usize,
+    preds: [Option<&[i32]>; 2],
+    bit_depth: u8,
+    explicit: Option<(u8, [(i32, i32); 2])>,
+) {
+    let max_val = (1i32 << bit_depth) - 1;
+    let shift1 = (14 - bit_depth as i32).max(2);
+    let shift2 = (15 - bit_depth as i32).max(3);
+
+    let mut store =
+        |f: fn(&[i32; 6], i32, i32) -> i32, params: [i32; 6], p0: &[i32], p1: &[i32]| {
+            for y in 0..h {
+                let row = &mut plane[(y0 + y) * stride + x0..][..w];
+                for (x, dst) in row.iter_mut().enumerate() {
+                    let i = y * w + x;
+                    *dst =
+                        f(&params, p0[i], p1.get(i).copied().unwrap_or(0)).clamp(0, max_val) as u16;
+                }
+            }
+        };
+
+    match (preds, explicit) {
+        ([Some(p0), Some(p1)], None) => {
+            let offset2 = 1 << (shift2 - 1);
+            store(
+                |c, a, b| (a + b + c[0]) >> c[1],
+                [offset2, shift2, 0, 0, 0, 0],
+                p0,
+                p1,
+            );
+        }
+        ([Some(p0), Some(p1)], Some((denom, [(w0, o0), (w1, o1)]))) => {
+            let log2_wd = denom as i32 + shift1;
+            store(
+                |c, a, b| (a * c[0] + b * c[1] + ((c[2] + c[3] + 1) << c[4])) >> (c[4] + 1),
+                [w0, w1, o0, o1, log2_wd, 0],
+                p0,
+                p1,
+            );
+        }
+        ([Some(p), None], None) | ([None, Some(p)], None) => {
+            let offset1 = 1 << (shift1 - 1);
+            store(
+                |c, a, _| (a + c[0]) >> c[1],
+                [offset1, shift1, 0, 0, 0, 0],
+                p,
+                &[],
+            );
+        }
+        ([Some(p), None], Some((denom, [(wt, o), _])))
+        | ([None, Some(p)], Some((denom, [_, (wt, o)]))) => {
+            // log2WD >= 2 since shift1 >= 2
+            let log2_wd = denom as i32 + shift1;
+            store(
+                |c, a, _| ((a * c[0] + (1 << (c[2] - 1))) >> c[2]) + c[1],
+                [wt, o, log2_wd, 0, 0, 0],
+                p,
+                &[],
+            );
+        }
+        ([None, None], _) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate_flat() {
+        // Any filter phase of a flat reference reproduces the flat value
+        let src = [100u16; 16 * 16];
+        let mut dst = [0i32; 16];
+        let (mut src_buf, mut tmp) = (Vec::new(), Vec::new());
+        for (fx, fy) in [(0, 0), (1, 0), (0, 2), (3, 1)] {
+            interpolate(
+                Window {
+                    src: &src,
+                    stride: 16,
+                    width: 16,
+                    height: 16,
+                },
+                -3,
+                14,
+                fx,
+                fy,
+                4,
+                4,
+                &LUMA_FILTER,
+                8,
+                &mut dst,
+                &mut src_buf,
+                &mut tmp,
+            );
+            assert!(dst.iter().all(|&v| v == 100 << 6), "phase ({fx},{fy})");
+        }
+
+        let mut plane = [0u16; 16];
+        store_weighted(&mut plane, 4, 0, 0, 4, 4, [Some(&dst), None], 8, None);
+        assert!(plane.iter().all(|&v| v == 100));
+        store_weighted(&mut plane, 4, 0, 0, 4, 4, [Some(&dst), Some(&dst)], 8, None);
+        assert!(plane.iter().all(|&v| v == 100));
+    }
+}
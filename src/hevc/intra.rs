@@ -33,6 +33,21 @@ pub static INV_ANGLE: [i32; 15] = [
     -315, -390, -482, -630, -910, -1638, -4096, // modes 19-25
 ];
 
+/// 4:2:2 chroma intra mode mapping (H.265 Table 8-3)
+///
+/// Chroma blocks in 4:2:2 have half the horizontal resolution of luma, so the
+/// mode derived from Table 8-2 is remapped to keep the same prediction angle.
+#[rustfmt::skip]
+static CHROMA_422_MODE_MAP: [u8; 35] = [
+     0,  1,  2,  2,  2,  2,  3,  5,  7,  8, 10, 11, 13, 15, 16, 18, 19, 20,
+    21, 22, 23, 23, 24, 24, 25, 25, 26, 27, 27, 28, 28, 29, 29, 30, 31,
+];
+
+/// Map a chroma prediction mode for 4:2:2 sampling (H.265 Table 8-3)
+pub fn map_chroma_mode_422(mode: IntraPredMode) -> IntraPredMode {
+    IntraPredMode::from_u8(CHROMA_422_MODE_MAP[mode.as_u8() as usize]).unwrap_or(mode)
+}
+
 /// Get inverse angle for a mode (for negative angle modes only)
 fn get_inv_angle(mode: u8) -> i32 {
     if (11..=25).contains(&mode) {
@@ -43,6 +58,7 @@ fn get_inv_angle(mode: u8) -> i32 {
 }
 
 /// Perform intra prediction for a block
+#[allow(clippy::too_many_arguments)]
 pub fn predict_intra(
     frame: &mut DecodedFrame,
     x: u32,
@@ -51,6 +67,7 @@ pub fn predict_intra(
     mode: IntraPredMode,
     c_idx: u8, // 0=Y, 1=Cb, 2=Cr
     strong_intra_smoothing_enabled: bool,
+    intra_smoothing_disabled: bool,
 ) {
     let size = 1u32 << log2_size;
     let bit_depth = frame.bit_depth;
@@ -63,8 +80,9 @@ pub fn predict_intra(
     fill_border_samples(frame, x, y, size, c_idx, &mut border, border_center);
 
     // Reference sample filtering (H.265 8.4.4.2.3)
-    // Only applied for luma, or for chroma in 4:4:4 format
-    if c_idx == 0 || chroma_format == 3 {
+    // Only applied for luma, or for chroma in 4:4:4 format, and never when the
+    // SPS range extension sets intra_smoothing_disabled_flag
+    if !intra_smoothing_disabled && (c_idx == 0 || chroma_format == 3) {
         intra_prediction_sample_filtering(
             &mut border,
             border_center,
@@ -230,8 +248,7 @@ fn fill_border_samples(
     let (frame_w, frame_h) = if c_idx == 0 {
         (frame.width, frame.height)
     } else {
-        // Chroma is half resolution for 4:2:0
-        (frame.width / 2, frame.height / 2)
+        frame.chroma_dims()
     };
 
     let avail_left = x > 0;
@@ -802,4 +819,17 @@ mod tests {
         // Mode 34 should have positive angle
         assert_eq!(INTRA_PRED_ANGLE[34], 32);
     }
+
+    #[test]
+    fn test_chroma_422_mode_mapping() {
+        // Planar, DC and the pure horizontal/vertical modes keep their meaning
+        assert_eq!(map_chroma_mode_422(IntraPredMode::Planar), IntraPredMode::Planar);
+        assert_eq!(map_chroma_mode_422(IntraPredMode::Dc), IntraPredMode::Dc);
+        assert_eq!(map_chroma_mode_422(IntraPredMode::Angular10), IntraPredMode::Angular10);
+        assert_eq!(map_chroma_mode_422(IntraPredMode::Angular26), IntraPredMode::Angular26);
+        // Halving the horizontal resolution pulls diagonals toward vertical
+        assert_eq!(map_chroma_mode_422(IntraPredMode::Angular2), IntraPredMode::Angular2);
+        assert_eq!(map_chroma_mode_422(IntraPredMode::Angular18), IntraPredMode::Angular21);
+        assert_eq!(map_chroma_mode_422(IntraPredMode::Angular34), IntraPredMode::Angular31);
+    }
 }
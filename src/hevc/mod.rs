@@ -9,28 +9,134 @@ mod color_convert;
 mod ctu;
 mod deblock;
 pub(crate) mod debug;
+mod dpb;
+pub(crate) mod encoder;
+mod film_grain;
+mod hash;
+mod inter;
 mod intra;
+mod motion;
 pub(crate) mod params;
 mod picture;
+mod resample;
 mod residual;
+mod rows;
 mod sao;
+mod sei;
 mod slice;
 mod transform;
 mod transform_simd;
 
 pub use picture::DecodedFrame;
+pub use sei::{
+    AlphaChannelInfo, ContentLightLevel, DecodedPictureHash, DepthRepresentationInfo, DepthValue,
+    FilmGrainCharacteristics, FilmGrainColourDescription, FilmGrainInterval, HdrStaticMetadata,
+    MasteringDisplayColourVolume, SeiMessage, UserDataUnregistered,
+};
 
 use crate::error::HevcError;
-use crate::heif::HevcDecoderConfig;
+use crate::heif::{HevcDecoderConfig, LHevcDecoderConfig};
+use alloc::collections::VecDeque;
 use alloc::vec::Vec;
+use core::ops::Range;
+use motion::{MotionField, SliceInfo};
+use sao::SaoMap;
 
 type Result<T> = core::result::Result<T, HevcError>;
 
+/// Receiver of bands of finished rows while a picture is decoded
+///
+/// Called with the picture and a range of its luma rows (before conformance
+/// window cropping) whose samples will not change any more. Returning an
+/// error stops decoding with that error.
+pub type RowCallback<'a> = dyn FnMut(&DecodedFrame, Range<u32>) -> Result<()> + 'a;
+
+/// Which layer of a multi-layer (L-HEVC) bitstream to decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerSelection {
+    /// The layer with this nuh_layer_id
+    Layer(u8),
+    /// The highest output layer of an output layer set of the VPS extension
+    OutputLayerSet(u16),
+    /// The highest non-auxiliary layer that has slice data
+    Highest,
+}
+
 /// Decode HEVC bitstream to pixels (Annex B or raw format)
+///
+/// Returns the first picture in output order; use [`decode_pictures`] for
+/// every picture of a stream.
 pub fn decode(data: &[u8]) -> Result<DecodedFrame> {
     // Parse NAL units
     let nal_units = bitstream::parse_nal_units(data)?;
-    decode_nal_units(&nal_units)
+    decode_nal_units(&nal_units, LayerSelection::Layer(0), None)
+}
+
+/// Decode every picture of an HEVC bitstream (Annex B or raw format)
+///
+/// The stream is split into access units that are decoded one at a time as
+/// the iterator advances; parameter sets are activated by ID for each
+/// picture, so later access units may carry new or replaced ones.
+pub fn decode_pictures(data: &[u8]) -> Result<Pictures<'_>> {
+    let nal_units = bitstream::parse_nal_units(data)?;
+    let access_units = bitstream::access_units(&nal_units);
+    Ok(Pictures {
+        nal_units,
+        access_units: access_units.into_iter(),
+        decoder: SequenceDecoder::new(),
+        pending: VecDeque::new(),
+        flushed: false,
+    })
+}
+
+/// Iterator over the base layer pictures of an HEVC bitstream, in output
+/// order
+///
+/// Created by [`decode_pictures`]. Each picture of an intra-only stream is
+/// yielded as soon as its access unit is decoded; streams with inter
+/// pictures yield them as the decoded picture buffer outputs them. Iteration
+/// ends after the first error.
+#[derive(Debug)]
+pub struct Pictures<'a> {
+    nal_units: Vec<bitstream::NalUnit<'a>>,
+    access_units: alloc::vec::IntoIter<Range<usize>>,
+    decoder: SequenceDecoder,
+    pending: VecDeque<DecodedFrame>,
+    flushed: bool,
+}
+
+impl Pictures<'_> {
+    /// Decode the next access unit, or flush at the end of the stream
+    fn decode_next(&mut self) -> Result<()> {
+        let Some(range) = self.access_units.next() else {
+            self.pending.extend(self.decoder.flush()?);
+            self.flushed = true;
+            return Ok(());
+        };
+        self.decoder.decode_nal_units(&self.nal_units[range], None)?;
+        self.decoder.finish_picture(None)?;
+        self.pending.extend(self.decoder.dpb.take_output());
+        Ok(())
+    }
+}
+
+impl Iterator for Pictures<'_> {
+    type Item = Result<DecodedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(frame) = self.pending.pop_front() {
+                return Some(Ok(frame));
+            }
+            if self.flushed {
+                return None;
+            }
+            if let Err(e) = self.decode_next() {
+                self.flushed = true;
+                return Some(Err(e));
+            }
+        }
+    }
 }
 
 /// Decode HEVC from HEIC container (config + image data)
@@ -38,21 +144,105 @@ pub fn decode(data: &[u8]) -> Result<DecodedFrame> {
 /// This is the preferred method for HEIC files where parameter sets
 /// are stored separately in the hvcC box.
 pub fn decode_with_config(config: &HevcDecoderConfig, image_data: &[u8]) -> Result<DecodedFrame> {
+    decode_layer_with_config(Some(config), None, image_data, LayerSelection::Layer(0))
+}
+
+/// Decode one layer of a multi-layer (L-HEVC) HEIF item
+///
+/// Parameter sets are taken from the base layer hvcC and the layered lhvC
+/// configuration, whichever are present. The layers the selected layer
+/// predicts from are decoded along with it, so their slices must be part of
+/// `image_data`; see [`decode_layer_items`] for lower layers stored in other
+/// items.
+pub fn decode_layer_with_config(
+    config: Option<&HevcDecoderConfig>,
+    layer_config: Option<&LHevcDecoderConfig>,
+    image_data: &[u8],
+    selection: LayerSelection,
+) -> Result<DecodedFrame> {
+    let item = LayerItem {
+        config,
+        layer_config,
+        image_data,
+    };
+    decode_layer_items(&[item], selection)
+}
+
+/// Coded data of one HEIF item of a layered (L-HEVC) image
+#[derive(Debug, Clone, Copy)]
+pub struct LayerItem<'a> {
+    /// hvcC configuration of the item
+    pub config: Option<&'a HevcDecoderConfig>,
+    /// lhvC configuration of the item
+    pub layer_config: Option<&'a LHevcDecoderConfig>,
+    /// Length-prefixed NAL units of the item
+    pub image_data: &'a [u8],
+}
+
+/// Decode one layer of a layered HEIF image whose layers are stored in
+/// several items
+///
+/// `items` run from the item with the base layer up to the layered item,
+/// as linked by `tbas` item references. The parameter sets of all items
+/// are loaded first, then the NAL units of each item are decoded in turn.
+pub fn decode_layer_items(
+    items: &[LayerItem<'_>],
+    selection: LayerSelection,
+) -> Result<DecodedFrame> {
+    let nal_units = config_nal_units(items)?;
+    decode_nal_units(&nal_units, selection, None)
+}
+
+/// Decode HEVC from HEIC container, handing out rows as they are finished
+///
+/// Like [`decode_with_config`], but `on_rows` receives the picture in bands
+/// of rows, top to bottom, as soon as deblocking and SAO are done with them.
+/// Bands are one CTB row high and trail the decoded CTB row by two rows;
+/// pictures coded with tiles or separate colour planes are handed out as a
+/// single band once decoded.
+pub fn decode_rows_with_config(
+    config: &HevcDecoderConfig,
+    image_data: &[u8],
+    on_rows: &mut RowCallback<'_>,
+) -> Result<DecodedFrame> {
+    let item = LayerItem {
+        config: Some(config),
+        layer_config: None,
+        image_data,
+    };
+    let nal_units = config_nal_units(&[item])?;
+    decode_nal_units(&nal_units, LayerSelection::Layer(0), Some(on_rows))
+}
+
+/// Parameter sets of the decoder configurations of all items followed by
+/// the NAL units of each item's length-prefixed image data
+fn config_nal_units<'a>(items: &[LayerItem<'a>]) -> Result<Vec<bitstream::NalUnit<'a>>> {
     let mut nal_units = Vec::new();
 
-    // Parse parameter sets from hvcC
-    for nal_data in &config.nal_units {
-        if let Ok(nal) = bitstream::parse_single_nal(nal_data) {
-            nal_units.push(nal);
+    // Parse parameter sets from hvcC and lhvC
+    for item in items {
+        let config = item.config.map(|c| c.nal_units.as_slice());
+        let layer_config = item.layer_config.map(|c| c.nal_units.as_slice());
+        let config_nals = config.unwrap_or_default().iter().chain(layer_config.unwrap_or_default());
+        for nal_data in config_nals {
+            if let Ok(nal) = bitstream::parse_single_nal(nal_data) {
+                nal_units.push(nal);
+            }
         }
     }
 
     // Parse slice data with correct length size
-    let length_size = (config.length_size_minus_one + 1) as usize;
-    let mut slice_nals = bitstream::parse_length_prefixed_ext(image_data, length_size)?;
-    nal_units.append(&mut slice_nals);
-
-    decode_nal_units(&nal_units)
+    for item in items {
+        let length_size_minus_one = match (item.layer_config, item.config) {
+            (Some(c), _) => c.length_size_minus_one,
+            (None, Some(c)) => c.length_size_minus_one,
+            (None, None) => return Err(HevcError::MissingParameterSet("decoder configuration")),
+        };
+        let length_size = (length_size_minus_one + 1) as usize;
+        let mut slice_nals = bitstream::parse_length_prefixed_ext(item.image_data, length_size)?;
+        nal_units.append(&mut slice_nals);
+    }
+    Ok(nal_units)
 }
 
 /// Get image info from HEIC config
@@ -62,90 +252,783 @@ pub fn get_info_from_config(config: &HevcDecoderConfig) -> Result<ImageInfo> {
             && nal.nal_type == bitstream::NalType::SpsNut
         {
             let sps = params::parse_sps(&nal.payload)?;
-            let (width, height) = get_cropped_dimensions(&sps);
-            return Ok(ImageInfo { width, height });
+            return Ok(ImageInfo::from_sps(&sps));
         }
     }
     Err(HevcError::MissingParameterSet("SPS"))
 }
 
-/// Internal: decode from parsed NAL units
-fn decode_nal_units(nal_units: &[bitstream::NalUnit<'_>]) -> Result<DecodedFrame> {
-    // Find and parse parameter sets
-    let mut _vps = None;
-    let mut sps = None;
-    let mut pps = None;
+/// HDR static metadata from the prefix SEI NAL units of an hvcC
+pub fn hdr_metadata_from_config(config: &HevcDecoderConfig) -> HdrStaticMetadata {
+    let messages: Vec<SeiMessage> = config
+        .nal_units
+        .iter()
+        .filter_map(|nal_data| bitstream::parse_single_nal(nal_data).ok())
+        .filter(|nal| nal.nal_type == bitstream::NalType::PrefixSeiNut)
+        .filter_map(|nal| sei::parse_sei(&nal.payload).ok())
+        .flatten()
+        .collect();
+    HdrStaticMetadata::from_sei(&messages)
+}
+
+/// Internal: decode one layer from parsed NAL units
+///
+/// Returns the first picture output in POC order.
+fn decode_nal_units(
+    nal_units: &[bitstream::NalUnit<'_>],
+    selection: LayerSelection,
+    mut rows: Option<&mut RowCallback<'_>>,
+) -> Result<DecodedFrame> {
+    let mut decoder = SequenceDecoder::with_layer(selection);
+    decoder.decode_nal_units(nal_units, rows.as_deref_mut())?;
+    if !nal_units
+        .iter()
+        .any(|nal| nal.nal_type.is_slice() && Some(nal.nuh_layer_id) == decoder.layer_id)
+    {
+        return Err(HevcError::InvalidBitstream("no slice segments for the selected layer"));
+    }
+    decoder.finish_picture(rows)?;
+    decoder
+        .flush()?
+        .into_iter()
+        .next()
+        .ok_or(HevcError::InvalidBitstream("no picture output"))
+}
+
+/// Decoder for HEVC video sequences with intra and inter (P/B) pictures
+///
+/// Parameter sets are kept across calls. Each call to [`decode`](Self::decode)
+/// or [`decode_sample`](Self::decode_sample) takes one or more complete access
+/// units and returns the pictures that the decoded picture buffer outputs, in
+/// output (POC) order. [`flush`](Self::flush) returns the remaining pictures at
+/// the end of the stream.
+#[derive(Debug)]
+pub struct SequenceDecoder {
+    selection: LayerSelection,
+    /// Resolved nuh_layer_id of the decoded layer
+    layer_id: Option<u8>,
+    /// VPSs by ID
+    vps: Vec<params::Vps>,
+    /// Parameter sets with the nuh_layer_id they were sent in, one per ID
+    /// and layer
+    sps: Vec<(u8, params::Sps)>,
+    pps: Vec<(u8, params::Pps)>,
+    dpb: dpb::Dpb,
+    current: Option<CurrentPicture>,
+    /// Prefix SEI messages for the next picture
+    prefix_sei: Vec<SeiMessage>,
+    /// Film grain characteristics that persist to later pictures
+    film_grain: Option<FilmGrainCharacteristics>,
+    /// Slices of a skipped (RASL) picture are ignored
+    skip_picture: bool,
+    /// Tag given to the pictures of the access units being decoded
+    tag: u64,
+    /// Decoders of the layers the decoded layer depends on, lowest first
+    ref_layers: Vec<SequenceDecoder>,
+    /// Pictures are kept for inter-layer prediction only, never output
+    reference_only: bool,
+}
+
+/// The picture currently being decoded
+#[derive(Debug)]
+struct CurrentPicture {
+    sps: params::Sps,
+    pps: params::Pps,
+    poc: i32,
+    output: bool,
+    tag: u64,
+    rps: dpb::RefPicSet,
+    /// One plane state, or one per colour plane with separate_colour_plane_flag
+    planes: Vec<PlaneState>,
+    /// Loop filter progress when rows are handed out while decoding
+    row_filter: Option<rows::RowFilter>,
+    /// Prefix and suffix SEI messages of the picture
+    sei: Vec<SeiMessage>,
+}
+
+/// Reconstruction state of one coded (colour) plane
+#[derive(Debug)]
+struct PlaneState {
+    frame: DecodedFrame,
+    motion: MotionField,
+    sao_map: SaoMap,
+}
+
+impl Default for SequenceDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SequenceDecoder {
+    /// Create a decoder for the base layer
+    pub fn new() -> Self {
+        Self::with_layer(LayerSelection::Layer(0))
+    }
+
+    /// Create a decoder for one layer of a multi-layer bitstream
+    ///
+    /// [`LayerSelection::Highest`] is resolved from the slices of the first
+    /// call that contains any.
+    pub fn with_layer(selection: LayerSelection) -> Self {
+        Self {
+            selection,
+            layer_id: None,
+            vps: Vec::new(),
+            sps: Vec::new(),
+            pps: Vec::new(),
+            dpb: dpb::Dpb::new(),
+            current: None,
+            prefix_sei: Vec::new(),
+            film_grain: None,
+            skip_picture: false,
+            tag: 0,
+            ref_layers: Vec::new(),
+            reference_only: false,
+        }
+    }
+
+    /// Load the parameter sets of an hvcC configuration
+    pub fn add_config(&mut self, config: &HevcDecoderConfig) -> Result<()> {
+        let nal_units: Vec<_> = config
+            .nal_units
+            .iter()
+            .filter_map(|data| bitstream::parse_single_nal(data).ok())
+            .collect();
+        self.decode_nal_units(&nal_units, None)
+    }
+
+    /// Decode Annex B data containing complete access units
+    pub fn decode(&mut self, data: &[u8]) -> Result<Vec<DecodedFrame>> {
+        let nal_units = bitstream::parse_nal_units(data)?;
+        self.decode_nal_units(&nal_units, None)?;
+        self.finish_picture(None)?;
+        Ok(self.dpb.take_output())
+    }
+
+    /// Decode one length-prefixed sample (an access unit from an `mdat` or track)
+    pub fn decode_sample(&mut self, data: &[u8], length_size: usize) -> Result<Vec<DecodedFrame>> {
+        let frames = self.decode_tagged_sample(data, length_size, 0)?;
+        Ok(frames.into_iter().map(|(_, frame)| frame).collect())
+    }
+
+    /// Decode one length-prefixed sample like
+    /// [`decode_sample`](Self::decode_sample), tagging its picture with `tag`
+    ///
+    /// The pictures output are returned with the tags of the samples they
+    /// were decoded from, so that skipped (RASL) pictures, pictures that are
+    /// not output and reordering do not shift per-sample data such as
+    /// timestamps.
+    pub fn decode_tagged_sample(
+        &mut self,
+        data: &[u8],
+        length_size: usize,
+        tag: u64,
+    ) -> Result<Vec<(u64, DecodedFrame)>> {
+        let nal_units = bitstream::parse_length_prefixed_ext(data, length_size)?;
+        self.tag = tag;
+        self.decode_nal_units(&nal_units, None)?;
+        self.finish_picture(None)?;
+        Ok(self.dpb.take_tagged_output())
+    }
+
+    /// Finish decoding and return all pictures still waiting for output
+    pub fn flush(&mut self) -> Result<Vec<DecodedFrame>> {
+        self.finish_picture(None)?;
+        self.dpb.flush();
+        for decoder in &mut self.ref_layers {
+            decoder.finish_picture(None)?;
+            decoder.dpb.flush();
+        }
+        Ok(self.dpb.take_output())
+    }
+
+    /// Finish decoding and return all pictures still waiting for output,
+    /// with the tags of [`decode_tagged_sample`](Self::decode_tagged_sample)
+    pub fn flush_tagged(&mut self) -> Result<Vec<(u64, DecodedFrame)>> {
+        self.finish_picture(None)?;
+        self.dpb.flush();
+        Ok(self.dpb.take_tagged_output())
+    }
+
+    fn decode_nal_units(
+        &mut self,
+        nal_units: &[bitstream::NalUnit<'_>],
+        mut rows: Option<&mut RowCallback<'_>>,
+    ) -> Result<()> {
+        // Parameter sets ahead of the first slice and not above its layer are
+        // stored early, to find the VPS that selects the decoded layer
+        let first = match self.layer_id {
+            None => nal_units.iter().position(|nal| nal.nal_type.is_slice()),
+            Some(_) => None,
+        };
+        let early = |i: usize, nal: &bitstream::NalUnit<'_>| {
+            first.is_some_and(|first| {
+                i < first
+                    && is_parameter_set(nal)
+                    && nal.nuh_layer_id <= nal_units[first].nuh_layer_id
+            })
+        };
+        if let Some(first) = first {
+            for (i, nal) in nal_units.iter().enumerate() {
+                if early(i, nal) {
+                    self.decode_layer_nal(nal, None, &mut [])?;
+                }
+            }
+            self.select_layer(nal_units, &nal_units[first])?;
+        }
+        for (i, nal) in nal_units.iter().enumerate() {
+            if early(i, nal) {
+                continue;
+            }
+            self.decode_nal(nal, rows.as_deref_mut())?;
+        }
+        Ok(())
+    }
+
+    /// Select the decoded layer with the VPS of the first slice, and set up
+    /// the decoders of the layers it depends on
+    fn select_layer(
+        &mut self,
+        nal_units: &[bitstream::NalUnit<'_>],
+        first_slice: &bitstream::NalUnit<'_>,
+    ) -> Result<()> {
+        let vps = self
+            .find_parameter_sets(first_slice)
+            .ok()
+            .and_then(|(sps, _)| self.vps_by_id(sps.vps_id));
+        let layer_id = resolve_layer(nal_units, vps, self.selection)?;
+        let ext = vps.and_then(|vps| vps.extension.as_ref());
+        let ref_layers = ext.map(|ext| ext.ref_layers(layer_id)).unwrap_or_default();
+        self.ref_layers = ref_layers
+            .into_iter()
+            .map(|ref_layer_id| self.reference_layer_decoder(ref_layer_id))
+            .collect();
+        self.layer_id = Some(layer_id);
+        Ok(())
+    }
+
+    /// Decoder for a layer that the decoded layer depends on, with the
+    /// parameter sets loaded so far
+    fn reference_layer_decoder(&self, layer_id: u8) -> Self {
+        let mut decoder = Self::with_layer(LayerSelection::Layer(layer_id));
+        decoder.layer_id = Some(layer_id);
+        decoder.vps = self.vps.clone();
+        decoder.sps = self.sps.clone();
+        decoder.pps = self.pps.clone();
+        decoder.reference_only = true;
+        decoder
+    }
+
+    /// Decode a NAL unit in the reference layers, then in the decoded layer
+    ///
+    /// The reference layers see every NAL unit first, so that their pictures
+    /// of an access unit are decoded before the pictures predicted from them.
+    fn decode_nal(
+        &mut self,
+        nal: &bitstream::NalUnit<'_>,
+        rows: Option<&mut RowCallback<'_>>,
+    ) -> Result<()> {
+        let mut ref_layers = core::mem::take(&mut self.ref_layers);
+        let result = (0..ref_layers.len())
+            .try_for_each(|i| {
+                let (lower, rest) = ref_layers.split_at_mut(i);
+                rest[0].decode_layer_nal(nal, None, lower)
+            })
+            .and_then(|()| self.decode_layer_nal(nal, rows, &mut ref_layers));
+        self.ref_layers = ref_layers;
+        result
+    }
+
+    /// Decode a NAL unit in this decoder's layer, taking inter-layer
+    /// reference pictures from the decoders of `ref_layers`
+    fn decode_layer_nal(
+        &mut self,
+        nal: &bitstream::NalUnit<'_>,
+        rows: Option<&mut RowCallback<'_>>,
+        ref_layers: &mut [SequenceDecoder],
+    ) -> Result<()> {
+        use bitstream::NalType;
 
-    for nal in nal_units {
+        if self.layer_id.is_some_and(|layer_id| nal.nuh_layer_id > layer_id) {
+            return Ok(());
+        }
         match nal.nal_type {
-            bitstream::NalType::VpsNut => {
-                _vps = Some(params::parse_vps(&nal.payload)?);
+            NalType::VpsNut => {
+                let vps = params::parse_vps(&nal.payload)?;
+                self.vps.retain(|v| v.vps_id != vps.vps_id);
+                self.vps.push(vps);
+            }
+            NalType::SpsNut => {
+                // sps_video_parameter_set_id is the first four bits
+                let vps = nal.payload.first().and_then(|&b| self.vps_by_id(b >> 4));
+                let sps = params::parse_sps_for_layer(&nal.payload, nal.nuh_layer_id, vps)?;
+                store_parameter_set(&mut self.sps, nal.nuh_layer_id, sps, |s| s.sps_id);
+            }
+            NalType::PpsNut => {
+                let pps = params::parse_pps(&nal.payload)?;
+                store_parameter_set(&mut self.pps, nal.nuh_layer_id, pps, |p| p.pps_id);
+            }
+            // An access unit delimiter opens the next access unit
+            NalType::AudNut if nal.nuh_layer_id == 0 => self.finish_picture(rows)?,
+            NalType::EosNut | NalType::EobNut => {
+                self.finish_picture(rows)?;
+                self.dpb.flush();
             }
-            bitstream::NalType::SpsNut => {
-                sps = Some(params::parse_sps(&nal.payload)?);
+            NalType::PrefixSeiNut | NalType::SuffixSeiNut
+                if self.layer_id.is_none_or(|layer_id| layer_id == nal.nuh_layer_id) =>
+            {
+                // SEI is informative; a malformed message does not stop decoding
+                let Ok(messages) = sei::parse_sei(&nal.payload) else {
+                    return Ok(());
+                };
+                match (nal.nal_type, self.current.as_mut()) {
+                    (NalType::PrefixSeiNut, _) => self.prefix_sei.extend(messages),
+                    (_, Some(pic)) => pic.sei.extend(messages),
+                    // Suffix SEI of a skipped picture
+                    (_, None) => {}
+                }
             }
-            bitstream::NalType::PpsNut => {
-                pps = Some(params::parse_pps(&nal.payload)?);
+            t if t.is_slice() && Some(nal.nuh_layer_id) == self.layer_id => {
+                self.decode_slice_nal(nal, rows, ref_layers)?;
             }
             _ => {}
         }
+        Ok(())
+    }
+
+    fn decode_slice_nal(
+        &mut self,
+        nal: &bitstream::NalUnit<'_>,
+        mut rows: Option<&mut RowCallback<'_>>,
+        ref_layers: &mut [SequenceDecoder],
+    ) -> Result<()> {
+        // first_slice_segment_in_pic_flag is the first bit of the slice header
+        let first_slice = nal.payload.first().is_some_and(|&b| b & 0x80 != 0);
+        if first_slice {
+            self.finish_picture(rows.as_deref_mut())?;
+            self.skip_picture = false;
+            let (sps, pps) = self.activate_parameter_sets(nal)?;
+            let parse_result = slice::SliceHeader::parse(nal, &sps, &pps)?;
+            self.start_picture(nal, &parse_result.header, sps, pps)?;
+            if self.current.is_none() {
+                self.skip_picture = true;
+                return Ok(());
+            }
+            self.add_inter_layer_refs(&parse_result.header, ref_layers)?;
+            return self.decode_picture_slice(nal, parse_result, rows);
+        }
+        if self.skip_picture {
+            return Ok(());
+        }
+        let Some(pic) = self.current.as_ref() else {
+            return Err(HevcError::InvalidBitstream("slice segment without first slice of picture"));
+        };
+        let parse_result = slice::SliceHeader::parse(nal, &pic.sps, &pic.pps)?;
+        self.decode_picture_slice(nal, parse_result, rows)
+    }
+
+    /// The VPS with an ID
+    fn vps_by_id(&self, vps_id: u8) -> Option<&params::Vps> {
+        self.vps.iter().find(|vps| vps.vps_id == vps_id)
     }
 
-    let sps = sps.ok_or(HevcError::MissingParameterSet("SPS"))?;
-    let pps = pps.ok_or(HevcError::MissingParameterSet("PPS"))?;
+    /// The stored SPS and PPS that the first slice of a picture refers to
+    ///
+    /// A layer may use parameter sets of its own or of a lower layer; among
+    /// the parameter sets with the referenced ID, the one from the highest
+    /// layer not above the slice's layer is used.
+    fn find_parameter_sets(
+        &self,
+        first_slice: &bitstream::NalUnit<'_>,
+    ) -> Result<(&params::Sps, &params::Pps)> {
+        let layer_id = first_slice.nuh_layer_id;
+        let pps_id = slice::peek_pps_id(first_slice)?;
+        let pps = self
+            .pps
+            .iter()
+            .filter(|(layer, pps)| *layer <= layer_id && pps.pps_id == pps_id)
+            .max_by_key(|(layer, _)| *layer)
+            .map(|(_, pps)| pps)
+            .ok_or(HevcError::MissingParameterSet("PPS"))?;
+        let sps = self
+            .sps
+            .iter()
+            .filter(|(layer, sps)| *layer <= layer_id && sps.sps_id == pps.sps_id)
+            .max_by_key(|(layer, _)| *layer)
+            .map(|(_, sps)| sps)
+            .ok_or(HevcError::MissingParameterSet("SPS"))?;
+        Ok((sps, pps))
+    }
+
+    /// Activate the SPS and PPS referenced by the first slice of a picture,
+    /// with the SPS completed for the slice's layer
+    fn activate_parameter_sets(
+        &self,
+        first_slice: &bitstream::NalUnit<'_>,
+    ) -> Result<(params::Sps, params::Pps)> {
+        let layer_id = first_slice.nuh_layer_id;
+        let (sps, pps) = self.find_parameter_sets(first_slice)?;
+        let pps = pps.clone();
+        let sps = sps.for_layer(self.vps_by_id(sps.vps_id), layer_id)?;
+
+        check_range_extension_tools(&sps, &pps)?;
+        check_multilayer_tools(&sps, &pps)?;
+        // A frame has one bit depth, which deblocking, SAO, colour conversion,
+        // hash checks and film grain use for every plane
+        if sps.chroma_array_type() != 0 && sps.bit_depth_c() != sps.bit_depth_y() {
+            return Err(HevcError::Unsupported("mixed luma and chroma bit depths"));
+        }
 
-    // Sanity-check dimensions before allocating (prevent OOM from malicious SPS)
-    let w = sps.pic_width_in_luma_samples;
-    let h = sps.pic_height_in_luma_samples;
-    if w == 0 || h == 0 || w > 16384 || h > 16384 {
-        return Err(HevcError::InvalidParameterSet {
-            kind: "SPS",
-            msg: alloc::format!("invalid dimensions {}x{}", w, h),
+        // Sanity-check dimensions before allocating (prevent OOM from malicious SPS)
+        let w = sps.pic_width_in_luma_samples;
+        let h = sps.pic_height_in_luma_samples;
+        if w == 0 || h == 0 || w > 16384 || h > 16384 {
+            return Err(HevcError::InvalidParameterSet {
+                kind: "SPS",
+                msg: alloc::format!("invalid dimensions {}x{}", w, h),
+            });
+        }
+        if w.checked_mul(h).is_none() {
+            return Err(HevcError::InvalidParameterSet {
+                kind: "SPS",
+                msg: alloc::format!("dimensions {}x{} overflow u32", w, h),
+            });
+        }
+
+        Ok((sps, pps))
+    }
+
+    /// Derive POC and reference picture set and allocate the picture
+    ///
+    /// Leaves `current` empty for a RASL picture that has to be skipped.
+    fn start_picture(
+        &mut self,
+        nal: &bitstream::NalUnit<'_>,
+        header: &slice::SliceHeader,
+        sps: params::Sps,
+        pps: params::Pps,
+    ) -> Result<()> {
+        let mut sei = core::mem::take(&mut self.prefix_sei);
+        self.carry_film_grain(nal.nal_type.is_irap(), &mut sei);
+        if !header.slice_type.is_intra() {
+            check_inter_tools(&sps, &pps)?;
+        }
+        let temporal_id = nal.nuh_temporal_id_plus1.saturating_sub(1);
+        let Some((poc, rps)) = self.dpb.start_picture(nal.nal_type, temporal_id, header, &sps)
+        else {
+            return Ok(());
+        };
+
+        let (w, h) = (sps.pic_width_in_luma_samples, sps.pic_height_in_luma_samples);
+        let plane = |chroma_format| PlaneState {
+            frame: DecodedFrame::with_params(w, h, sps.bit_depth_y(), chroma_format),
+            motion: MotionField::new(w, h),
+            sao_map: SaoMap::new(sps.pic_width_in_ctbs(), sps.pic_height_in_ctbs()),
+        };
+        // With separate_colour_plane_flag each slice codes a single colour plane
+        // as a monochrome picture; decode the three planes independently and
+        // route colour_plane_id 0/1/2 into Y/Cb/Cr afterwards
+        let mut planes = if sps.separate_colour_plane_flag {
+            (0..3).map(|_| plane(0)).collect()
+        } else {
+            alloc::vec![plane(sps.chroma_format_idc)]
+        };
+
+        // Output parameters are set up front so that rows handed out while
+        // decoding carry them
+        let frame = &mut planes[0].frame;
+        frame.full_range = sps.video_full_range_flag;
+        frame.matrix_coeffs = sps.matrix_coeffs;
+
+        // Set conformance window cropping from SPS
+        // Offsets are in units of SubWidthC/SubHeightC, need to convert to luma samples
+        if sps.conformance_window_flag {
+            let (sub_width_c, sub_height_c) = (sps.sub_width_c(), sps.sub_height_c());
+            frame.set_crop(
+                sps.conf_win_offset.0 * sub_width_c,  // left
+                sps.conf_win_offset.1 * sub_width_c,  // right
+                sps.conf_win_offset.2 * sub_height_c, // top
+                sps.conf_win_offset.3 * sub_height_c, // bottom
+            );
+        }
+
+        self.current = Some(CurrentPicture {
+            poc,
+            output: header.pic_output_flag && !self.reference_only,
+            tag: self.tag,
+            rps,
+            planes,
+            row_filter: None,
+            sei,
+            sps,
+            pps,
         });
+        Ok(())
     }
-    if w.checked_mul(h).is_none() {
-        return Err(HevcError::InvalidParameterSet {
-            kind: "SPS",
-            msg: alloc::format!("dimensions {}x{} overflow u32", w, h),
+
+    /// Give a picture without film grain characteristics SEI the persistent
+    /// ones of an earlier picture, which last until an IRAP picture or a
+    /// new SEI
+    fn carry_film_grain(&mut self, irap: bool, sei: &mut Vec<SeiMessage>) {
+        if irap {
+            self.film_grain = None;
+        }
+        let grain = sei.iter().rev().find_map(|message| match message {
+            SeiMessage::FilmGrainCharacteristics(grain) => Some(grain),
+            _ => None,
         });
+        match grain {
+            Some(grain) => self.film_grain = grain.clone().filter(|grain| grain.persistence),
+            None => {
+                if let Some(grain) = &self.film_grain {
+                    sei.push(SeiMessage::FilmGrainCharacteristics(Some(grain.clone())));
+                }
+            }
+        }
     }
 
-    // Create frame buffer with actual bit depth and chroma format from SPS
-    let mut frame = DecodedFrame::with_params(
-        sps.pic_width_in_luma_samples,
-        sps.pic_height_in_luma_samples,
-        sps.bit_depth_y(),
-        sps.chroma_format_idc,
-    );
-    frame.full_range = sps.video_full_range_flag;
-    frame.matrix_coeffs = sps.matrix_coeffs;
+    /// Resample the pictures of the active reference layers in the current
+    /// access unit into the DPB and add them to the current picture's RPS
+    /// (F.8.3.4)
+    fn add_inter_layer_refs(
+        &mut self,
+        header: &slice::SliceHeader,
+        ref_layers: &mut [SequenceDecoder],
+    ) -> Result<()> {
+        let Some(pic) = self.current.as_mut() else {
+            return Ok(());
+        };
+        let ext = self
+            .vps
+            .iter()
+            .find(|vps| vps.vps_id == pic.sps.vps_id)
+            .and_then(|vps| vps.extension.as_ref());
+        let view_id = |layer_id| ext.map_or(0, |ext| ext.view_id(layer_id));
+        let (base_view, view) = (view_id(0), view_id(pic.sps.layer.nuh_layer_id));
+        for &ref_layer_id in &header.active_ref_layer_ids {
+            let missing = || HevcError::InvalidBitstream("inter-layer reference picture missing");
+            let decoder = ref_layers
+                .iter_mut()
+                .find(|decoder| decoder.layer_id == Some(ref_layer_id))
+                .ok_or_else(missing)?;
+            decoder.finish_picture(None)?;
+            let reference = decoder
+                .dpb
+                .latest()
+                .filter(|reference| reference.poc == pic.poc)
+                .ok_or_else(missing)?;
+            let (frame, motion) = resample::inter_layer_picture(
+                &pic.sps,
+                &pic.pps,
+                ref_layer_id,
+                &reference.frame,
+                &reference.motion,
+            )?;
+            let id = self.dpb.insert_inter_layer(frame, motion, pic.poc);
+            // Reference views on the same side of the current view as the
+            // base view go to RefPicSetInterLayer0
+            let ref_view = view_id(ref_layer_id);
+            let below = view <= base_view && view <= ref_view;
+            let above = view >= base_view && view >= ref_view;
+            let set = if below || above { 0 } else { 1 };
+            pic.rps.inter_layer[set].push(id);
+        }
+        Ok(())
+    }
 
-    // Set conformance window cropping from SPS
-    // Offsets are in units of SubWidthC/SubHeightC, need to convert to luma samples
-    if sps.conformance_window_flag {
-        let (sub_width_c, sub_height_c) = match sps.chroma_format_idc {
-            0 => (1, 1), // Monochrome
-            1 => (2, 2), // 4:2:0
-            2 => (2, 1), // 4:2:2
-            3 => (1, 1), // 4:4:4
-            _ => (2, 2), // Default to 4:2:0
+    fn decode_picture_slice(
+        &mut self,
+        nal: &bitstream::NalUnit<'_>,
+        parse_result: slice::SliceParseResult,
+        rows: Option<&mut RowCallback<'_>>,
+    ) -> Result<()> {
+        let Some(pic) = self.current.as_mut() else {
+            return Ok(());
+        };
+        let header = parse_result.header;
+        let plane = pic
+            .planes
+            .get_mut(header.colour_plane_id as usize)
+            .ok_or(HevcError::InvalidBitstream("colour_plane_id out of range"))?;
+        // Rows are filtered as they are decoded only when CTBs arrive in
+        // raster order and a single plane is coded
+        if rows.is_some()
+            && pic.row_filter.is_none()
+            && !pic.sps.separate_colour_plane_flag
+            && !pic.pps.tiles_enabled_flag
+        {
+            pic.row_filter = Some(rows::RowFilter::new(&pic.sps, &pic.pps));
+        }
+
+        let mut refs = self.dpb.ref_pic_lists(&pic.rps, &header)?;
+        let info = core::mem::take(&mut refs.info);
+        plane.motion.slices.push(SliceInfo::new(&header, info));
+
+        // Use the offset from slice header parsing to skip the header bytes
+        let slice_data = &nal.payload[parse_result.data_offset..];
+        let mut ctx = ctu::SliceContext::new(
+            &pic.sps,
+            &pic.pps,
+            &header,
+            slice_data,
+            &mut plane.motion,
+            refs,
+            pic.poc,
+        )?;
+        // SAO parameters of all slices are collected in one map for the picture
+        ctx.sao_map = core::mem::replace(&mut plane.sao_map, SaoMap::new(0, 0));
+        let result = match (rows, pic.row_filter.as_mut()) {
+            (Some(on_rows), Some(filter)) => {
+                ctx.decode_slice_rows(&mut plane.frame, &mut |frame, motion, sao_map, ctb_y| {
+                    let done = filter.row_decoded(frame, motion, sao_map, ctb_y);
+                    if done.is_empty() {
+                        Ok(())
+                    } else {
+                        on_rows(frame, done)
+                    }
+                })
+            }
+            _ => ctx.decode_slice(&mut plane.frame),
         };
-        frame.set_crop(
-            sps.conf_win_offset.0 * sub_width_c,  // left
-            sps.conf_win_offset.1 * sub_width_c,  // right
-            sps.conf_win_offset.2 * sub_height_c, // top
-            sps.conf_win_offset.3 * sub_height_c, // bottom
-        );
+        plane.sao_map = ctx.sao_map;
+        result
     }
 
-    // Decode slice data (base layer only — skip enhancement layer NALs in L-HEVC streams)
-    for nal in nal_units {
-        if nal.nal_type.is_slice() && nal.nuh_layer_id == 0 {
-            decode_slice(nal, &sps, &pps, &mut frame)?;
+    /// Apply the in-loop filters to the current picture and store it in the DPB
+    ///
+    /// The rows not yet handed to `rows` are handed out before storing.
+    fn finish_picture(&mut self, rows: Option<&mut RowCallback<'_>>) -> Result<()> {
+        let Some(pic) = self.current.take() else {
+            return Ok(());
+        };
+        let mut planes = pic.planes;
+        let remaining = match (pic.row_filter, planes.first_mut()) {
+            (Some(mut filter), Some(plane)) => {
+                filter.finish(&mut plane.frame, &plane.motion, &plane.sao_map)
+            }
+            _ => {
+                for plane in &mut planes {
+                    apply_loop_filters(plane, &pic.sps, &pic.pps);
+                }
+                0..pic.sps.pic_height_in_luma_samples
+            }
+        };
+
+        let mut planes = planes.into_iter();
+        let Some(PlaneState { mut frame, motion, .. }) = planes.next() else {
+            return Ok(());
+        };
+        if pic.sps.separate_colour_plane_flag {
+            let cb = planes.next().map(|p| p.frame.y_plane).unwrap_or_default();
+            let cr = planes.next().map(|p| p.frame.y_plane).unwrap_or_default();
+            frame.chroma_format = 3;
+            frame.cb_plane = cb;
+            frame.cr_plane = cr;
+        }
+        if let Some(on_rows) = rows
+            && !remaining.is_empty()
+        {
+            on_rows(&frame, remaining)?;
         }
+
+        frame.hdr_metadata = HdrStaticMetadata::from_sei(&pic.sei);
+        frame.sei = pic.sei;
+        self.dpb
+            .insert(frame, motion, pic.poc, pic.output, pic.tag, &pic.sps);
+        Ok(())
+    }
+}
+
+/// Store a parameter set, replacing the one with the same ID and layer
+fn store_parameter_set<T>(sets: &mut Vec<(u8, T)>, layer_id: u8, set: T, id: impl Fn(&T) -> u8) {
+    let new_id = id(&set);
+    sets.retain(|(layer, existing)| *layer != layer_id || id(existing) != new_id);
+    sets.push((layer_id, set));
+}
+
+/// Resolve a layer selection to the nuh_layer_id to decode
+fn resolve_layer(
+    nal_units: &[bitstream::NalUnit<'_>],
+    vps: Option<&params::Vps>,
+    selection: LayerSelection,
+) -> Result<u8> {
+    let ext = vps.and_then(|vps| vps.extension.as_ref());
+    match selection {
+        LayerSelection::Layer(layer_id) => Ok(layer_id),
+        LayerSelection::OutputLayerSet(ols_idx) => ext
+            .and_then(|ext| ext.output_layers(ols_idx as usize))
+            .and_then(|layers| layers.last().copied())
+            .ok_or_else(|| HevcError::InvalidParameterSet {
+                kind: "VPS",
+                msg: alloc::format!("output layer set {ols_idx} not present"),
+            }),
+        LayerSelection::Highest => nal_units
+            .iter()
+            .filter(|nal| nal.nal_type.is_slice())
+            .map(|nal| nal.nuh_layer_id)
+            .filter(|&layer_id| ext.is_none_or(|ext| ext.aux_id(layer_id) == 0))
+            .max()
+            .ok_or(HevcError::InvalidBitstream("no slice segments")),
+    }
+}
+
+/// Whether a NAL unit is a VPS, SPS or PPS
+fn is_parameter_set(nal: &bitstream::NalUnit<'_>) -> bool {
+    use bitstream::NalType::{PpsNut, SpsNut, VpsNut};
+    matches!(nal.nal_type, VpsNut | SpsNut | PpsNut)
+}
+
+/// Reject multi-layer coding tools that the decoder does not implement
+fn check_multilayer_tools(sps: &params::Sps, pps: &params::Pps) -> Result<()> {
+    if pps.multilayer_extension.colour_mapping_enabled_flag {
+        return Err(HevcError::Unsupported("colour mapping"));
+    }
+    if sps.scaling_list_ref_layer_id.is_some()
+        || pps.multilayer_extension.scaling_list_ref_layer_id.is_some()
+    {
+        return Err(HevcError::Unsupported("scaling lists inferred from a reference layer"));
     }
+    Ok(())
+}
 
-    Ok(frame)
+/// Reject coding tools of P/B pictures that the decoder does not implement
+fn check_inter_tools(sps: &params::Sps, pps: &params::Pps) -> Result<()> {
+    if pps.constrained_intra_pred_flag {
+        return Err(HevcError::Unsupported("constrained intra prediction"));
+    }
+    if sps.separate_colour_plane_flag {
+        return Err(HevcError::Unsupported("inter prediction with separate colour planes"));
+    }
+    Ok(())
+}
+
+/// Reject range extension coding tools that the decoder does not implement
+///
+/// Extended precision, larger transform-skip blocks, intra smoothing control
+/// and SAO offset scaling are supported; the remaining tools change residual
+/// coding and would otherwise decode silently wrong.
+fn check_range_extension_tools(sps: &params::Sps, pps: &params::Pps) -> Result<()> {
+    let ext = &sps.range_extension;
+    if ext.transform_skip_rotation_enabled_flag || ext.transform_skip_context_enabled_flag {
+        return Err(HevcError::Unsupported("transform skip rotation/context"));
+    }
+    if ext.implicit_rdpcm_enabled_flag || ext.explicit_rdpcm_enabled_flag {
+        return Err(HevcError::Unsupported("residual DPCM"));
+    }
+    if ext.persistent_rice_adaptation_enabled_flag {
+        return Err(HevcError::Unsupported("persistent Rice adaptation"));
+    }
+    if ext.cabac_bypass_alignment_enabled_flag {
+        return Err(HevcError::Unsupported("CABAC bypass alignment"));
+    }
+    if pps.range_extension.cross_component_prediction_enabled_flag {
+        return Err(HevcError::Unsupported("cross-component prediction"));
+    }
+    if pps.range_extension.chroma_qp_offset_list_enabled_flag {
+        return Err(HevcError::Unsupported("chroma QP offset lists"));
+    }
+    Ok(())
 }
 
 /// Get image info without full decoding
@@ -155,8 +1038,7 @@ pub fn get_info(data: &[u8]) -> Result<ImageInfo> {
     for nal in &nal_units {
         if nal.nal_type == bitstream::NalType::SpsNut {
             let sps = params::parse_sps(&nal.payload)?;
-            let (width, height) = get_cropped_dimensions(&sps);
-            return Ok(ImageInfo { width, height });
+            return Ok(ImageInfo::from_sps(&sps));
         }
     }
 
@@ -166,13 +1048,7 @@ pub fn get_info(data: &[u8]) -> Result<ImageInfo> {
 /// Calculate cropped dimensions from SPS conformance window
 fn get_cropped_dimensions(sps: &params::Sps) -> (u32, u32) {
     if sps.conformance_window_flag {
-        let (sub_width_c, sub_height_c) = match sps.chroma_format_idc {
-            0 => (1, 1), // Monochrome
-            1 => (2, 2), // 4:2:0
-            2 => (2, 1), // 4:2:2
-            3 => (1, 1), // 4:4:4
-            _ => (2, 2), // Default to 4:2:0
-        };
+        let (sub_width_c, sub_height_c) = (sps.sub_width_c(), sps.sub_height_c());
         let crop_left = sps.conf_win_offset.0.saturating_mul(sub_width_c);
         let crop_right = sps.conf_win_offset.1.saturating_mul(sub_width_c);
         let crop_top = sps.conf_win_offset.2.saturating_mul(sub_height_c);
@@ -203,37 +1079,27 @@ pub struct ImageInfo {
     pub width: u32,
     /// Height in pixels
     pub height: u32,
+    /// Luma bit depth (8-16)
+    pub bit_depth: u8,
+    /// Chroma format IDC (0=monochrome, 1=4:2:0, 2=4:2:2, 3=4:4:4)
+    pub chroma_format: u8,
 }
 
-fn decode_slice(
-    nal: &bitstream::NalUnit<'_>,
-    sps: &params::Sps,
-    pps: &params::Pps,
-    frame: &mut DecodedFrame,
-) -> Result<()> {
-    // 1. Parse slice header and get data offset
-    let parse_result = slice::SliceHeader::parse(nal, sps, pps)?;
-    let slice_header = parse_result.header;
-    let data_offset = parse_result.data_offset;
-
-    // Verify this is an I-slice (required for HEIC still images)
-    if !slice_header.slice_type.is_intra() {
-        return Err(HevcError::Unsupported(
-            "only I-slices supported for still images",
-        ));
+impl ImageInfo {
+    fn from_sps(sps: &params::Sps) -> Self {
+        let (width, height) = get_cropped_dimensions(sps);
+        Self {
+            width,
+            height,
+            bit_depth: sps.bit_depth_y(),
+            chroma_format: sps.chroma_format_idc,
+        }
     }
+}
 
-    // 2. Get slice data (after header)
-    // Use the offset from slice header parsing to skip the header bytes
-    let slice_data = &nal.payload[data_offset..];
-
-    // 3. Create slice context and decode CTUs
-    let mut ctx = ctu::SliceContext::new(sps, pps, &slice_header, slice_data)?;
-
-    // 4. Decode all CTUs in the slice
-    ctx.decode_slice(frame)?;
-
-    // 5. Apply deblocking filter (skip if HEIC_NOFILTER or HEIC_NODEBLOCK env set)
+/// Whether deblocking and SAO are enabled, (deblock, sao); they are skipped
+/// with the HEIC_NOFILTER, HEIC_NODEBLOCK or HEIC_NOSAO environment variables
+fn loop_filter_switches() -> (bool, bool) {
     #[cfg(feature = "std")]
     let skip_all = std::env::var("HEIC_NOFILTER").is_ok();
     #[cfg(not(feature = "std"))]
@@ -258,18 +1124,366 @@ fn decode_slice(
             false
         }
     };
-    if !skip_deblock && !slice_header.slice_deblocking_filter_disabled_flag {
-        let beta_offset = slice_header.slice_beta_offset_div2 as i32 * 2;
-        let tc_offset = slice_header.slice_tc_offset_div2 as i32 * 2;
+
+    (!skip_deblock, !skip_sao)
+}
+
+/// Deblock and SAO-filter one decoded plane
+fn apply_loop_filters(plane: &mut PlaneState, sps: &params::Sps, pps: &params::Pps) {
+    let (deblock, sao) = loop_filter_switches();
+
+    // Slices with slice_deblocking_filter_disabled_flag are skipped per edge
+    if deblock {
         let cb_qp_offset = pps.pps_cb_qp_offset as i32;
         let cr_qp_offset = pps.pps_cr_qp_offset as i32;
-        deblock::apply_deblocking_filter(frame, beta_offset, tc_offset, cb_qp_offset, cr_qp_offset);
+        deblock::apply_deblocking_filter(&mut plane.frame, &plane.motion, cb_qp_offset, cr_qp_offset);
     }
 
-    // 6. Apply SAO (Sample Adaptive Offset)
-    if !skip_sao && (slice_header.slice_sao_luma_flag || slice_header.slice_sao_chroma_flag) {
-        sao::apply_sao(frame, &ctx.sao_map, sps.ctb_size());
+    // CTBs of slices without SAO keep sao_type_idx 0 and are left unchanged
+    if sao && sps.sample_adaptive_offset_enabled_flag {
+        sao::apply_sao(&mut plane.frame, &plane.sao_map, sps.ctb_size());
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitstream::{BitstreamWriter, NalType, write_nal_unit};
+    use encoder::tests::{annex_b, test_picture};
+    use encoder::{DEBLOCKING_CANDIDATES, encode_picture};
+
+    #[test]
+    fn test_decode_pictures() {
+        // Three access units, the second with its own picture size and an
+        // access unit delimiter in front, the last ended by end of sequence
+        let sizes = [(72, 40), (48, 64), (72, 40)];
+        let mut stream = Vec::new();
+        for (i, &(width, height)) in sizes.iter().enumerate() {
+            if i == 1 {
+                stream.extend_from_slice(&[0, 0, 0, 1]);
+                stream.extend_from_slice(&write_nal_unit(NalType::AudNut, 0, &[0x10]));
+            }
+            let encoded = encode_picture(&test_picture(width, height), 30, &[None]);
+            stream.extend_from_slice(&annex_b(&encoded));
+        }
+        stream.extend_from_slice(&[0, 0, 0, 1]);
+        stream.extend_from_slice(&write_nal_unit(NalType::EosNut, 0, &[]));
+
+        let nal_units = bitstream::parse_nal_units(&stream).unwrap();
+        let units = bitstream::access_units(&nal_units);
+        assert_eq!(units, [0..4, 4..9, 9..14]);
+
+        let frames: Vec<DecodedFrame> = decode_pictures(&stream)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let dims: Vec<_> = frames.iter().map(|f| (f.width, f.height)).collect();
+        assert_eq!(dims, sizes);
+        assert_eq!(frames[0].y_plane, decode(&stream).unwrap().y_plane);
+    }
+
+    #[test]
+    fn test_separate_colour_planes() {
+        // Distinct content in each plane, so a slice routed to the wrong
+        // plane shows up
+        let (width, height) = (64, 32);
+        let mut picture = DecodedFrame::with_params(width, height, 8, 3);
+        let luma = test_picture(width, height);
+        picture.y_plane.copy_from_slice(&luma.y_plane);
+        for y in 0..height {
+            for x in 0..width {
+                let i = (y * width + x) as usize;
+                picture.cb_plane[i] = (60 + x * 2 + (y % 5) * 7) as u16;
+                picture.cr_plane[i] = (200 - y * 3 - (x / 8) * 4) as u16;
+            }
+        }
+        let (nal_units, expected) = encoder::planes::encode_separate_planes(&picture, 24);
+        let mut stream = Vec::new();
+        for nal in &nal_units {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(nal);
+        }
+
+        let decoded = decode(&stream).unwrap();
+        assert_eq!(decoded.chroma_format, 3);
+        assert_eq!(decoded.y_plane, expected.y_plane);
+        assert_eq!(decoded.cb_plane, expected.cb_plane);
+        assert_eq!(decoded.cr_plane, expected.cr_plane);
+        // The reconstruction follows the source
+        for (decoded, source) in [
+            (&decoded.y_plane, &picture.y_plane),
+            (&decoded.cb_plane, &picture.cb_plane),
+            (&decoded.cr_plane, &picture.cr_plane),
+        ] {
+            let max_error = decoded
+                .iter()
+                .zip(source)
+                .map(|(&a, &b)| a.abs_diff(b))
+                .max();
+            assert!(max_error < Some(16), "{max_error:?}");
+        }
+    }
+
+    /// A PPS NAL unit with its pps_pic_parameter_set_id changed from 0
+    fn with_pps_id(pps: &[u8], pps_id: u32) -> Vec<u8> {
+        let rbsp = bitstream::parse_single_nal(pps).unwrap().payload;
+        // Copy the bits after the one-bit ue(0) up to the stop bit
+        let stop = rbsp.len() * 8 - 1 - rbsp[rbsp.len() - 1].trailing_zeros() as usize;
+        let mut w = BitstreamWriter::new();
+        w.write_ue(pps_id);
+        for i in 1..stop {
+            w.write_bit((rbsp[i / 8] >> (7 - i % 8)) & 1);
+        }
+        w.write_trailing_bits();
+        write_nal_unit(NalType::PpsNut, 0, &w.finish())
+    }
+
+    #[test]
+    fn test_parameter_sets_by_id() {
+        let picture = test_picture(64, 48);
+        let encoded = encode_picture(&picture, 22, &DEBLOCKING_CANDIDATES);
+        let expected = decode(&annex_b(&encoded)).unwrap();
+
+        // A PPS for another QP arrives last, under ID 1; the slice still
+        // refers to PPS 0
+        let other = encode_picture(&picture, 37, &[None]);
+        let mut stream = Vec::new();
+        for nal in [
+            &encoded.parameter_sets[0],
+            &encoded.parameter_sets[1],
+            &encoded.parameter_sets[2],
+            &with_pps_id(&other.parameter_sets[2], 1),
+            &encoded.slice,
+        ] {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(nal);
+        }
+        let decoded = decode(&stream).unwrap();
+        assert_eq!(decoded.y_plane, expected.y_plane);
+
+        // PPS IDs above 63 are rejected
+        let mut stream = annex_b(&encoded);
+        stream.extend_from_slice(&[0, 0, 0, 1]);
+        stream.extend_from_slice(&with_pps_id(&other.parameter_sets[2], 64));
+        assert!(decode(&stream).is_err());
+    }
+
+    #[test]
+    fn test_inter_pictures() {
+        // I (POC 0), P (POC 2) shifted right by 4 luma samples, then B (POC 1)
+        // whose temporal merge candidate scales that to -2 and +2 samples
+        let picture = test_picture(64, 64);
+        let mut stream = Vec::new();
+        for nal in encoder::inter::encode_ipb(&picture, 27, -16) {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(&nal);
+        }
+        let frames: Vec<DecodedFrame> = decode_pictures(&stream)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(frames.len(), 3);
+        let (intra, b, p) = (&frames[0], &frames[1], &frames[2]);
+        assert_eq!(intra.y_plane, decode(&stream).unwrap().y_plane);
+
+        // Horizontal displacement with the reference edge replicated
+        let shifted = |plane: &[u16], width: u32, x: u32, y: u32, dx: i32| {
+            let x = (x as i32 + dx).clamp(0, width as i32 - 1) as u32;
+            plane[(y * width + x) as usize]
+        };
+        for y in 0..64 {
+            for x in 0..64 {
+                assert_eq!(p.get_y(x, y), shifted(&intra.y_plane, 64, x, y, -4));
+                let l0 = shifted(&intra.y_plane, 64, x, y, -2);
+                let l1 = shifted(&p.y_plane, 64, x, y, 2);
+                assert_eq!(b.get_y(x, y), (l0 + l1 + 1) >> 1, "({x}, {y})");
+            }
+        }
+        for (plane_i, plane_p, plane_b) in [
+            (&intra.cb_plane, &p.cb_plane, &b.cb_plane),
+            (&intra.cr_plane, &p.cr_plane, &b.cr_plane),
+        ] {
+            for y in 0..32 {
+                for x in 0..32 {
+                    let i = (y * 32 + x) as usize;
+                    assert_eq!(plane_p[i], shifted(plane_i, 32, x, y, -2));
+                    let l0 = shifted(plane_i, 32, x, y, -1);
+                    let l1 = shifted(plane_p, 32, x, y, 1);
+                    assert_eq!(plane_b[i], (l0 + l1 + 1) >> 1);
+                }
+            }
+        }
+    }
+
+    /// Pictures of an Annex B stream of NAL units, in output order
+    fn decode_all(nal_units: Vec<Vec<u8>>) -> Vec<DecodedFrame> {
+        let mut stream = Vec::new();
+        for nal in nal_units {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(&nal);
+        }
+        decode_pictures(&stream)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fractional_motion() {
+        // A P picture displaced by -5/4 luma samples: luma is interpolated
+        // at phase 3/4 with the 8-tap filter, chroma at phase 3/8 with the
+        // 4-tap filter
+        let frames = decode_all(encoder::inter::encode_ipb(&test_picture(64, 64), 27, -5));
+        assert_eq!(frames.len(), 3);
+        let (intra, p) = (&frames[0], &frames[2]);
+
+        // Horizontal filter over a reference row with its edges replicated
+        let filter = |plane: &[u16], width: u32, x: u32, y: u32, x_int: i32, taps: &[i32]| {
+            let first = x as i32 + x_int - (taps.len() as i32 / 2 - 1);
+            let sum: i32 = (first..)
+                .zip(taps)
+                .map(|(xs, tap)| {
+                    let xs = xs.clamp(0, width as i32 - 1) as u32;
+                    tap * i32::from(plane[(y * width + xs) as usize])
+                })
+                .sum();
+            ((sum + 32) >> 6).clamp(0, 255) as u16
+        };
+        let luma_taps = [0, 1, -5, 17, 58, -10, 4, -1];
+        for y in 0..64 {
+            for x in 0..64 {
+                let expected = filter(&intra.y_plane, 64, x, y, -2, &luma_taps);
+                assert_eq!(p.get_y(x, y), expected, "({x}, {y})");
+            }
+        }
+        let chroma_taps = [-6, 46, 28, -4];
+        for (plane_i, plane_p) in [
+            (&intra.cb_plane, &p.cb_plane),
+            (&intra.cr_plane, &p.cr_plane),
+        ] {
+            for y in 0..32 {
+                for x in 0..32 {
+                    let expected = filter(plane_i, 32, x, y, -1, &chroma_taps);
+                    assert_eq!(plane_p[(y * 32 + x) as usize], expected, "({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_weighted_prediction() {
+        // A P picture shifted right by 4 luma samples, with every component
+        // scaled by weight/64 and offset
+        let weights = [(48, 10), (80, -5), (64, 3)];
+        let picture = test_picture(64, 64);
+        let nal_units = encoder::inter::encode_weighted_ip(&picture, 27, -16, &weights);
+        let frames = decode_all(nal_units);
+        assert_eq!(frames.len(), 2);
+        let (intra, p) = (&frames[0], &frames[1]);
+
+        let planes = [
+            (&intra.y_plane, &p.y_plane, 64u32, 4u32),
+            (&intra.cb_plane, &p.cb_plane, 32, 2),
+            (&intra.cr_plane, &p.cr_plane, 32, 2),
+        ];
+        for ((plane_i, plane_p, width, shift), weighting) in planes.into_iter().zip(weights) {
+            let (weight, offset) = weighting;
+            for y in 0..width {
+                for x in 0..width {
+                    let reference = plane_i[(y * width + x.saturating_sub(shift)) as usize];
+                    // 14-bit prediction sample, with log2WD 6 + 6
+                    let sample = i32::from(reference) << 6;
+                    let weighted = ((sample * weight + (1 << 11)) >> 12) + offset;
+                    let decoded = plane_p[(y * width + x) as usize];
+                    assert_eq!(i32::from(decoded), weighted.clamp(0, 255), "({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_inter_layer_prediction() {
+        let picture = test_picture(64, 64);
+        let stream = |nal_units: Vec<Vec<u8>>| {
+            let mut stream = Vec::new();
+            for nal in nal_units {
+                stream.extend_from_slice(&[0, 0, 0, 1]);
+                stream.extend_from_slice(&nal);
+            }
+            stream
+        };
+        let decode_layer = |stream: &[u8], selection| {
+            let mut decoder = SequenceDecoder::with_layer(selection);
+            let mut frames = decoder.decode(stream).unwrap();
+            frames.extend(decoder.flush().unwrap());
+            assert_eq!(frames.len(), 1);
+            frames.remove(0)
+        };
+
+        // SNR scalability: the enhancement layer repeats the base layer
+        let snr = stream(encoder::layers::encode_two_layers(&picture, 30, 64, 64));
+        let base = decode_layer(&snr, LayerSelection::Layer(0));
+        assert_eq!(base.y_plane, decode(&snr).unwrap().y_plane);
+        let enhancement = decode_layer(&snr, LayerSelection::Highest);
+        assert_eq!(enhancement.y_plane, base.y_plane);
+        assert_eq!(enhancement.cb_plane, base.cb_plane);
+        assert_eq!(enhancement.cr_plane, base.cr_plane);
+
+        // 2x spatial scalability: even luma rows are filtered horizontally
+        // only, and even columns of them copy the base layer
+        let nal_units = encoder::layers::encode_two_layers(&picture, 30, 128, 128);
+        let enhancement = decode_layer(&stream(nal_units.clone()), LayerSelection::Highest);
+        assert_eq!((enhancement.width, enhancement.height), (128, 128));
+        for y in 0..64 {
+            for x in 0..64 {
+                let copied = enhancement.get_y(2 * x, 2 * y);
+                assert_eq!(copied, base.get_y(x, y), "({x}, {y})");
+                let sum: i32 = (0..8)
+                    .zip(resample::LUMA_FILTER[8])
+                    .map(|(i, f)| f * i32::from(base.get_y((x + i).clamp(3, 66) - 3, y)))
+                    .sum();
+                let expected = ((sum + 32) >> 6).clamp(0, 255) as u16;
+                assert_eq!(enhancement.get_y(2 * x + 1, 2 * y), expected, "({x}, {y})");
+            }
+        }
+
+        // The layers come from the VPS that the SPS refers to, not from a
+        // single-layer VPS with another ID sent after it
+        let single_layer = encoder::encode_picture(&picture, 30, &[None]);
+        let mut other_vps = single_layer.parameter_sets[0].clone();
+        other_vps[2] = (other_vps[2] & 0x0F) | 1 << 4;
+        let mut with_other = nal_units.clone();
+        with_other.insert(1, other_vps);
+        let selection = LayerSelection::OutputLayerSet(1);
+        let decoded = decode_layer(&stream(with_other), selection);
+        assert_eq!(decoded.y_plane, enhancement.y_plane);
+
+        // Without the base layer picture there is nothing to predict from
+        let mut nal_units = nal_units;
+        nal_units.remove(5);
+        let mut decoder = SequenceDecoder::with_layer(LayerSelection::Highest);
+        assert!(decoder.decode(&stream(nal_units)).is_err());
+    }
+
+    #[test]
+    fn test_tagged_samples() {
+        // Samples in decoding order: parameter sets and I, then P, then B
+        let nal_units = encoder::inter::encode_ipb(&test_picture(64, 32), 30, 8);
+        let mut samples = alloc::vec![Vec::new(); 3];
+        for (i, nal) in nal_units.iter().enumerate() {
+            let sample = &mut samples[i.saturating_sub(3)];
+            sample.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+            sample.extend_from_slice(nal);
+        }
+
+        // Pictures come out in output order, each with its sample's tag
+        let mut decoder = SequenceDecoder::new();
+        let mut tags = Vec::new();
+        for (tag, sample) in (10..).zip(&samples) {
+            let frames = decoder.decode_tagged_sample(sample, 4, tag).unwrap();
+            tags.extend(frames.into_iter().map(|(tag, _)| tag));
+        }
+        tags.extend(decoder.flush_tagged().unwrap().into_iter().map(|(tag, _)| tag));
+        assert_eq!(tags, [10, 12, 11]);
+    }
 }
@@ -0,0 +1,619 @@
+//! Motion data storage and motion vector prediction (H.265 8.5.3.2)
+//!
+//! Motion is stored per 4x4 luma block for the whole picture, together with
+//! the per-block flags that CU syntax context selection and the deblocking
+//! filter need. Reference pictures are identified by their POC, which is
+//! unique among the pictures of one layer in the DPB.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::params::{Pps, Sps};
+use super::slice::{PartMode, SliceHeader, SliceType};
+
+/// Block has been decoded
+pub const BLOCK_DECODED: u8 = 1;
+/// Block belongs to an intra coding unit
+pub const BLOCK_INTRA: u8 = 2;
+/// Block belongs to a skipped coding unit
+pub const BLOCK_SKIP: u8 = 4;
+/// Block lies in a luma transform block with non-zero coefficients
+pub const BLOCK_CODED: u8 = 8;
+
+/// Motion vector in quarter luma sample units
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mv {
+    /// Horizontal component
+    pub x: i16,
+    /// Vertical component
+    pub y: i16,
+}
+
+/// Motion of one prediction block
+///
+/// Unused lists always have `ref_idx == -1` and a zero vector, so two
+/// motions can be compared with `==`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PbMotion {
+    /// predFlagL0 / predFlagL1
+    pub pred_flag: [bool; 2],
+    /// refIdxL0 / refIdxL1
+    pub ref_idx: [i8; 2],
+    /// mvL0 / mvL1
+    pub mv: [Mv; 2],
+}
+
+impl Default for PbMotion {
+    fn default() -> Self {
+        Self {
+            pred_flag: [false; 2],
+            ref_idx: [-1; 2],
+            mv: [Mv::default(); 2],
+        }
+    }
+}
+
+impl PbMotion {
+    /// Drop list 1 of a bi-predicted block (8x4/4x8 restriction)
+    fn drop_l1(&mut self) {
+        self.pred_flag[1] = false;
+        self.ref_idx[1] = -1;
+        self.mv[1] = Mv::default();
+    }
+}
+
+/// Motion and coding flags of one 4x4 luma block
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockInfo {
+    /// Motion of the prediction block covering this block
+    pub motion: PbMotion,
+    /// BLOCK_* flags
+    pub flags: u8,
+    /// Index into [`MotionField::slices`]
+    pub slice: u16,
+}
+
+/// A reference picture as seen from one slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefPicInfo {
+    /// PicOrderCntVal of the reference picture
+    pub poc: i32,
+    /// Marked as used for long-term reference
+    pub long_term: bool,
+}
+
+/// Per-slice data needed after the slice is decoded (TMVP, deblocking)
+#[derive(Debug, Clone, Default)]
+pub struct SliceInfo {
+    /// RefPicList0 / RefPicList1
+    pub refs: [Vec<RefPicInfo>; 2],
+    /// slice_deblocking_filter_disabled_flag
+    pub deblocking_disabled: bool,
+    /// slice_loop_filter_across_slices_enabled_flag
+    pub loop_filter_across_slices: bool,
+    /// slice_beta_offset_div2 * 2
+    pub beta_offset: i32,
+    /// slice_tc_offset_div2 * 2
+    pub tc_offset: i32,
+}
+
+impl SliceInfo {
+    /// Collect the filter parameters of a slice header and its reference lists
+    pub fn new(header: &SliceHeader, refs: [Vec<RefPicInfo>; 2]) -> Self {
+        Self {
+            refs,
+            deblocking_disabled: header.slice_deblocking_filter_disabled_flag,
+            loop_filter_across_slices: header.slice_loop_filter_across_slices_enabled_flag,
+            beta_offset: header.slice_beta_offset_div2 as i32 * 2,
+            tc_offset: header.slice_tc_offset_div2 as i32 * 2,
+        }
+    }
+}
+
+/// Motion data of a whole picture at 4x4 granularity
+#[derive(Debug, Clone)]
+pub struct MotionField {
+    width: u32,
+    height: u32,
+    width4: u32,
+    blocks: Vec<BlockInfo>,
+    /// Slices of the picture, referenced by [`BlockInfo::slice`]
+    pub slices: Vec<SliceInfo>,
+}
+
+impl MotionField {
+    /// Create an empty motion field for a picture of the given luma size
+    pub fn new(width: u32, height: u32) -> Self {
+        let width4 = width.div_ceil(4);
+        let height4 = height.div_ceil(4);
+        Self {
+            width,
+            height,
+            width4,
+            blocks: vec![BlockInfo::default(); (width4 * height4) as usize],
+            slices: Vec::new(),
+        }
+    }
+
+    /// Create a motion field of an all-intra picture (generated reference pictures)
+    pub fn intra(width: u32, height: u32) -> Self {
+        let mut field = Self::new(width, height);
+        for block in &mut field.blocks {
+            block.flags = BLOCK_DECODED | BLOCK_INTRA;
+        }
+        field.slices.push(SliceInfo::default());
+        field
+    }
+
+    /// Block covering luma sample (x, y), which must lie inside the picture
+    #[inline]
+    pub fn block(&self, x: u32, y: u32) -> &BlockInfo {
+        &self.blocks[((y >> 2) * self.width4 + (x >> 2)) as usize]
+    }
+
+    /// Block covering luma sample (x, y), or None outside the picture
+    #[inline]
+    fn block_at(&self, x: i32, y: i32) -> Option<&BlockInfo> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        Some(self.block(x as u32, y as u32))
+    }
+
+    /// Store the same block info for a w x h luma region
+    pub fn set(&mut self, x: u32, y: u32, w: u32, h: u32, info: BlockInfo) {
+        self.update(x, y, w, h, |block| *block = info);
+    }
+
+    /// Add flags to the blocks of a w x h luma region
+    pub fn add_flags(&mut self, x: u32, y: u32, w: u32, h: u32, flags: u8) {
+        self.update(x, y, w, h, |block| block.flags |= flags);
+    }
+
+    fn update(&mut self, x: u32, y: u32, w: u32, h: u32, f: impl Fn(&mut BlockInfo)) {
+        let x_end = (x + w).min(self.width).div_ceil(4);
+        let y_end = (y + h).min(self.height).div_ceil(4);
+        for by in y / 4..y_end {
+            let row = (by * self.width4) as usize;
+            for block in &mut self.blocks[row + (x / 4) as usize..row + x_end as usize] {
+                f(block);
+            }
+        }
+    }
+
+    /// Availability of a neighbouring location in z-scan order (6.4.1)
+    ///
+    /// The location must be inside the picture, already decoded and belong
+    /// to the same slice as the block being decoded.
+    pub fn is_available(&self, slice: u16, x: i32, y: i32) -> bool {
+        self.block_at(x, y)
+            .is_some_and(|block| block.flags & BLOCK_DECODED != 0 && block.slice == slice)
+    }
+
+    /// Motion of a neighbouring prediction block if it is available (6.4.2)
+    fn neighbour(
+        &self,
+        ctx: &MvContext<'_>,
+        pb: &PredictionBlock,
+        x: i32,
+        y: i32,
+    ) -> Option<PbMotion> {
+        let (x_cb, y_cb, n_cb_s) = (pb.x_cb as i32, pb.y_cb as i32, pb.n_cb_s as i32);
+        let same_cb = x_cb <= x && x < x_cb + n_cb_s && y_cb <= y && y < y_cb + n_cb_s;
+        let available = if same_cb {
+            // The second NxN partition must not use the not yet decoded third one
+            !((pb.w << 1) as i32 == n_cb_s
+                && (pb.h << 1) as i32 == n_cb_s
+                && pb.part_idx == 1
+                && y_cb + pb.h as i32 <= y
+                && x_cb + pb.w as i32 > x)
+                && self
+                    .block_at(x, y)
+                    .is_some_and(|b| b.flags & BLOCK_DECODED != 0)
+        } else {
+            self.is_available(ctx.slice, x, y)
+        };
+        let block = self.block_at(x, y)?;
+        (available && block.flags & BLOCK_INTRA == 0).then_some(block.motion)
+    }
+
+    /// Derive the motion of a merge-mode prediction block (8.5.3.2.2)
+    pub fn derive_merge(
+        &self,
+        ctx: &MvContext<'_>,
+        pb: &PredictionBlock,
+        merge_idx: u8,
+    ) -> PbMotion {
+        let orig_size = pb.w + pb.h;
+        let log2_par_mrg_level = ctx.pps.log2_parallel_merge_level_minus2 as u32 + 2;
+        // All PUs of an 8x8 CU share the merge list of the 2Nx2N PU
+        let single_mcl = log2_par_mrg_level > 2 && pb.n_cb_s == 8;
+        let pb = &if single_mcl {
+            PredictionBlock {
+                x_pb: pb.x_cb,
+                y_pb: pb.y_cb,
+                w: 8,
+                h: 8,
+                part_idx: 0,
+                ..*pb
+            }
+        } else {
+            *pb
+        };
+
+        let target = merge_idx as usize;
+        let mut cands = [PbMotion::default(); 5];
+        let mut n = 0;
+
+        // Spatial candidates (8.5.3.2.3)
+        let (xp, yp, w, h) = (pb.x_pb as i32, pb.y_pb as i32, pb.w as i32, pb.h as i32);
+        let l = log2_par_mrg_level;
+        let usable = |x: i32, y: i32| {
+            if (xp >> l) == (x >> l) && (yp >> l) == (y >> l) {
+                None
+            } else {
+                self.neighbour(ctx, pb, x, y)
+            }
+        };
+        let second_vertical = pb.part_idx == 1
+            && matches!(
+                pb.part_mode,
+                PartMode::PartNx2N | PartMode::PartnLx2N | PartMode::PartnRx2N
+            );
+        let second_horizontal = pb.part_idx == 1
+            && matches!(
+                pb.part_mode,
+                PartMode::Part2NxN | PartMode::Part2NxnU | PartMode::Part2NxnD
+            );
+
+        let a1 = if second_vertical {
+            None
+        } else {
+            usable(xp - 1, yp + h - 1)
+        };
+        let b1 = if second_horizontal {
+            None
+        } else {
+            usable(xp + w - 1, yp - 1).filter(|m| Some(*m) != a1)
+        };
+        let b0 = usable(xp + w, yp - 1).filter(|m| Some(*m) != b1);
+        let a0 = usable(xp - 1, yp + h).filter(|m| Some(*m) != a1);
+        let b2 = if [a0, a1, b0, b1].iter().all(Option::is_some) {
+            None
+        } else {
+            usable(xp - 1, yp - 1).filter(|m| Some(*m) != a1 && Some(*m) != b1)
+        };
+        for cand in [a1, b1, b0, a0, b2].into_iter().flatten() {
+            cands[n] = cand;
+            n += 1;
+            if n > target {
+                return finish_merge(cands[target], orig_size);
+            }
+        }
+
+        // Temporal candidate
+        let is_b = ctx.header.slice_type == SliceType::B;
+        let l0 = self.temporal_mv(ctx, pb, 0, 0);
+        let l1 = if is_b {
+            self.temporal_mv(ctx, pb, 1, 0)
+        } else {
+            None
+        };
+        if l0.is_some() || l1.is_some() {
+            let mut cand = PbMotion::default();
+            for (list, mv) in [l0, l1].into_iter().enumerate() {
+                if let Some(mv) = mv {
+                    cand.pred_flag[list] = true;
+                    cand.ref_idx[list] = 0;
+                    cand.mv[list] = mv;
+                }
+            }
+            cands[n] = cand;
+            n += 1;
+            if n > target {
+                return finish_merge(cands[target], orig_size);
+            }
+        }
+
+        // Combined bi-predictive candidates (8.5.3.2.4)
+        let max_num_merge_cand = ctx.header.max_num_merge_cand as usize;
+        let refs = &self.slices[ctx.slice as usize].refs;
+        let num_orig = n;
+        if is_b && num_orig > 1 && num_orig < max_num_merge_cand {
+            const L0_CAND_IDX: [usize; 12] = [0, 1, 0, 2, 1, 2, 0, 3, 1, 3, 2, 3];
+            const L1_CAND_IDX: [usize; 12] = [1, 0, 2, 0, 2, 1, 3, 0, 3, 1, 3, 2];
+            for comb_idx in 0..num_orig * (num_orig - 1) {
+                let l0_cand = cands[L0_CAND_IDX[comb_idx]];
+                let l1_cand = cands[L1_CAND_IDX[comb_idx]];
+                if l0_cand.pred_flag[0] && l1_cand.pred_flag[1] {
+                    let poc0 = ref_poc(refs, 0, l0_cand.ref_idx[0]);
+                    let poc1 = ref_poc(refs, 1, l1_cand.ref_idx[1]);
+                    if poc0 != poc1 || l0_cand.mv[0] != l1_cand.mv[1] {
+                        cands[n] = PbMotion {
+                            pred_flag: [true; 2],
+                            ref_idx: [l0_cand.ref_idx[0], l1_cand.ref_idx[1]],
+                            mv: [l0_cand.mv[0], l1_cand.mv[1]],
+                        };
+                        n += 1;
+                        if n > target {
+                            return finish_merge(cands[target], orig_size);
+                        }
+                        if n == max_num_merge_cand {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Zero motion candidates (8.5.3.2.5)
+        let num_ref_idx = if is_b {
+            ctx.header.num_ref_idx_active[0].min(ctx.header.num_ref_idx_active[1])
+        } else {
+            ctx.header.num_ref_idx_active[0]
+        } as usize;
+        let zero_idx = target - n;
+        let ref_idx = if zero_idx < num_ref_idx {
+            zero_idx as i8
+        } else {
+            0
+        };
+        let cand = PbMotion {
+            pred_flag: [true, is_b],
+            ref_idx: [ref_idx, if is_b { ref_idx } else { -1 }],
+            mv: [Mv::default(); 2],
+        };
+        finish_merge(cand, orig_size)
+    }
+
+    /// Derive the motion vector predictor of an AMVP prediction block
+    /// (8.5.3.2.6, 8.5.3.2.7)
+    pub fn derive_amvp(
+        &self,
+        ctx: &MvContext<'_>,
+        pb: &PredictionBlock,
+        list: usize,
+        ref_idx: i8,
+        mvp_flag: u8,
+    ) -> Mv {
+        let refs = &self.slices[ctx.slice as usize].refs;
+        let Some(target) = refs[list].get(ref_idx as usize).copied() else {
+            return Mv::default();
+        };
+        let lists = [list, 1 - list];
+
+        // Neighbour referring to the same picture: used as is
+        let same_picture = |m: &PbMotion| {
+            lists.into_iter().find_map(|l| {
+                (m.pred_flag[l] && refs[l].get(m.ref_idx[l] as usize) == Some(&target))
+                    .then_some(m.mv[l])
+            })
+        };
+        // Neighbour of the same long-term-ness: scaled by POC distance
+        let scaled = |m: &PbMotion| {
+            lists.into_iter().find_map(|l| {
+                let r = refs[l].get(m.ref_idx[l] as usize)?;
+                (m.pred_flag[l] && r.long_term == target.long_term).then(|| {
+                    if r.long_term {
+                        m.mv[l]
+                    } else {
+                        scale_mv(m.mv[l], ctx.poc - r.poc, ctx.poc - target.poc)
+                    }
+                })
+            })
+        };
+
+        let (xp, yp, w, h) = (pb.x_pb as i32, pb.y_pb as i32, pb.w as i32, pb.h as i32);
+        let nb_a =
+            [(xp - 1, yp + h), (xp - 1, yp + h - 1)].map(|(x, y)| self.neighbour(ctx, pb, x, y));
+        let is_scaled = nb_a.iter().any(Option::is_some);
+        let mut mv_a = nb_a.iter().flatten().find_map(same_picture);
+        if mv_a.is_none() {
+            mv_a = nb_a.iter().flatten().find_map(scaled);
+        }
+
+        let nb_b = [(xp + w, yp - 1), (xp + w - 1, yp - 1), (xp - 1, yp - 1)]
+            .map(|(x, y)| self.neighbour(ctx, pb, x, y));
+        let mut mv_b = nb_b.iter().flatten().find_map(same_picture);
+        if !is_scaled {
+            if mv_b.is_some() {
+                mv_a = mv_b;
+            }
+            mv_b = nb_b.iter().flatten().find_map(scaled);
+        }
+
+        let mut cands = [Mv::default(); 2];
+        let mut n = 0;
+        if let Some(a) = mv_a {
+            cands[n] = a;
+            n += 1;
+        }
+        if let Some(b) = mv_b
+            && (n == 0 || cands[0] != b)
+        {
+            cands[n] = b;
+            n += 1;
+        }
+        if n < 2
+            && let Some(col) = self.temporal_mv(ctx, pb, list, ref_idx)
+        {
+            cands[n] = col;
+        }
+        cands[mvp_flag as usize & 1]
+    }
+
+    /// Temporal luma motion vector prediction (8.5.3.2.8)
+    fn temporal_mv(
+        &self,
+        ctx: &MvContext<'_>,
+        pb: &PredictionBlock,
+        list: usize,
+        ref_idx: i8,
+    ) -> Option<Mv> {
+        let col = ctx.col.as_ref()?;
+        let log2_ctb = ctx.sps.log2_ctb_size();
+        let x_br = pb.x_pb + pb.w;
+        let y_br = pb.y_pb + pb.h;
+        // Bottom-right candidate, only within the current CTB row
+        if (pb.y_pb >> log2_ctb) == (y_br >> log2_ctb)
+            && y_br < ctx.sps.pic_height_in_luma_samples
+            && x_br < ctx.sps.pic_width_in_luma_samples
+            && let Some(mv) =
+                self.collocated_mv(ctx, col, (x_br >> 4) << 4, (y_br >> 4) << 4, list, ref_idx)
+        {
+            return Some(mv);
+        }
+        let x_ctr = pb.x_pb + (pb.w >> 1);
+        let y_ctr = pb.y_pb + (pb.h >> 1);
+        self.collocated_mv(
+            ctx,
+            col,
+            (x_ctr >> 4) << 4,
+            (y_ctr >> 4) << 4,
+            list,
+            ref_idx,
+        )
+    }
+
+    /// Motion vector of a collocated block (8.5.3.2.9)
+    fn collocated_mv(
+        &self,
+        ctx: &MvContext<'_>,
+        col: &ColPicture<'_>,
+        x: u32,
+        y: u32,
+        list: usize,
+        ref_idx: i8,
+    ) -> Option<Mv> {
+        if x >= col.field.width || y >= col.field.height {
+            return None;
+        }
+        let block = col.field.block(x, y);
+        if block.flags & BLOCK_DECODED == 0 || block.flags & BLOCK_INTRA != 0 {
+            return None;
+        }
+        let refs = &self.slices[ctx.slice as usize].refs;
+        let motion = &block.motion;
+        let list_col = if !motion.pred_flag[0] {
+            1
+        } else if !motion.pred_flag[1] {
+            0
+        } else if refs.iter().flatten().all(|r| r.poc <= ctx.poc) {
+            // NoBackwardPredFlag
+            list
+        } else {
+            ctx.header.collocated_from_l0_flag as usize
+        };
+
+        let col_ref = col.field.slices.get(block.slice as usize)?.refs[list_col]
+            .get(motion.ref_idx[list_col] as usize)?;
+        let cur_ref = refs[list].get(ref_idx as usize)?;
+        if col_ref.long_term != cur_ref.long_term {
+            return None;
+        }
+        let mv_col = motion.mv[list_col];
+        let col_poc_diff = col.poc - col_ref.poc;
+        let curr_poc_diff = ctx.poc - cur_ref.poc;
+        if cur_ref.long_term || col_poc_diff == curr_poc_diff {
+            Some(mv_col)
+        } else {
+            Some(scale_mv(mv_col, col_poc_diff, curr_poc_diff))
+        }
+    }
+}
+
+/// Apply the 8x4/4x8 bi-prediction restriction to the selected merge candidate
+fn finish_merge(mut cand: PbMotion, orig_size: u32) -> PbMotion {
+    if cand.pred_flag[0] && cand.pred_flag[1] && orig_size == 12 {
+        cand.drop_l1();
+    }
+    cand
+}
+
+/// POC of a reference picture list entry (or i32::MIN if the index is invalid)
+fn ref_poc(refs: &[Vec<RefPicInfo>; 2], list: usize, ref_idx: i8) -> i32 {
+    refs[list].get(ref_idx as usize).map_or(i32::MIN, |r| r.poc)
+}
+
+/// Scale a motion vector by the ratio of POC distances tb / td (8-183..8-186)
+pub fn scale_mv(mv: Mv, td: i32, tb: i32) -> Mv {
+    let td = td.clamp(-128, 127);
+    let tb = tb.clamp(-128, 127);
+    if td == 0 {
+        return mv;
+    }
+    let tx = (16384 + (td.abs() >> 1)) / td;
+    let dist_scale_factor = ((tb * tx + 32) >> 6).clamp(-4096, 4095);
+    let scale = |v: i16| {
+        let p = dist_scale_factor * v as i32;
+        (p.signum() * ((p.abs() + 127) >> 8)).clamp(-32768, 32767) as i16
+    };
+    Mv {
+        x: scale(mv.x),
+        y: scale(mv.y),
+    }
+}
+
+/// Geometry of the prediction block being decoded and of its coding block
+#[derive(Debug, Clone, Copy)]
+pub struct PredictionBlock {
+    /// Coding block position
+    pub x_cb: u32,
+    /// Coding block position
+    pub y_cb: u32,
+    /// Coding block size
+    pub n_cb_s: u32,
+    /// Prediction block position
+    pub x_pb: u32,
+    /// Prediction block position
+    pub y_pb: u32,
+    /// Prediction block width
+    pub w: u32,
+    /// Prediction block height
+    pub h: u32,
+    /// Index of the prediction block within the coding unit
+    pub part_idx: u8,
+    /// Partition mode of the coding unit
+    pub part_mode: PartMode,
+}
+
+/// Collocated picture used for temporal motion vector prediction
+#[derive(Debug, Clone, Copy)]
+pub struct ColPicture<'a> {
+    /// Motion field of the collocated picture
+    pub field: &'a MotionField,
+    /// PicOrderCntVal of the collocated picture
+    pub poc: i32,
+}
+
+/// Slice-level inputs of motion vector prediction
+pub struct MvContext<'a> {
+    /// Active SPS
+    pub sps: &'a Sps,
+    /// Active PPS
+    pub pps: &'a Pps,
+    /// Header of the current slice
+    pub header: &'a SliceHeader,
+    /// Index of the current slice in the motion field
+    pub slice: u16,
+    /// PicOrderCntVal of the current picture
+    pub poc: i32,
+    /// Collocated picture, if temporal MVP is enabled for the slice
+    pub col: Option<ColPicture<'a>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_mv() {
+        let mv = Mv { x: 64, y: -32 };
+        // Equal distances keep the vector
+        assert_eq!(scale_mv(mv, 2, 2), mv);
+        // Twice the distance doubles it
+        assert_eq!(scale_mv(mv, 1, 2), Mv { x: 128, y: -64 });
+        // Opposite direction flips the sign
+        assert_eq!(scale_mv(mv, 1, -1), Mv { x: -64, y: 32 });
+    }
+}
@@ -8,6 +8,12 @@ use crate::error::HevcError;
 
 type Result<T> = core::result::Result<T, HevcError>;
 
+/// Largest sps_seq_parameter_set_id (16 SPSs per layer)
+pub const MAX_SPS_ID: u8 = 15;
+
+/// Largest pps_pic_parameter_set_id (64 PPSs per layer)
+pub const MAX_PPS_ID: u8 = 63;
+
 /// Video Parameter Set
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -26,6 +32,141 @@ pub struct Vps {
     pub temporal_id_nesting_flag: bool,
     /// Profile tier level
     pub ptl: ProfileTierLevel,
+    /// Layer structure of multi-layer (L-HEVC) streams; `None` for
+    /// single-layer streams or when the extension could not be parsed
+    pub extension: Option<VpsExtension>,
+}
+
+/// VPS extension layer structure (H.265 F.7.3.2.1.1)
+///
+/// Only the parts needed to decode and select layers are kept; parsing stops
+/// after `poc_lsb_not_present_flag` (DPB sizes, dependency types and the VPS
+/// VUI follow).
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct VpsExtension {
+    /// Scalability mask (bit 0 = depth, 1 = multiview, 2 = spatial/quality, 3 = auxiliary)
+    pub scalability_mask: u16,
+    /// nuh_layer_id of each layer, indexed by layer index in the VPS
+    pub layer_id_in_nuh: Vec<u8>,
+    /// ScalabilityId\[layer index\]\[scalability dimension\]
+    pub scalability_id: Vec<[u8; 16]>,
+    /// view_id_val per view order index (empty when view_id_len is 0)
+    pub view_id_val: Vec<u32>,
+    /// Direct dependencies per layer index (bit j set: depends on layer index j)
+    pub direct_dependency: Vec<u64>,
+    /// Direct and indirect dependencies per layer index (DependencyFlag)
+    pub dependency: Vec<u64>,
+    /// nuh_layer_ids of each layer set, including additional layer sets
+    pub layer_sets: Vec<Vec<u8>>,
+    /// Output layer sets
+    pub output_layer_sets: Vec<OutputLayerSet>,
+    /// Representation formats
+    pub rep_formats: Vec<RepFormat>,
+    /// Representation format index per layer index
+    pub rep_format_idx: Vec<u8>,
+    /// Default ref layers active flag (all direct reference layers are used)
+    pub default_ref_layers_active_flag: bool,
+    /// Max one active ref layer flag
+    pub max_one_active_ref_layer_flag: bool,
+    /// POC LSB not present flag per layer index
+    pub poc_lsb_not_present_flag: Vec<bool>,
+}
+
+impl VpsExtension {
+    /// Get the layer index in the VPS (LayerIdxInVps) of a nuh_layer_id
+    pub fn layer_idx(&self, nuh_layer_id: u8) -> Option<usize> {
+        self.layer_id_in_nuh
+            .iter()
+            .position(|&id| id == nuh_layer_id)
+    }
+
+    /// Get the direct reference layers of a layer (IdDirectRefLayer)
+    pub fn direct_ref_layers(&self, nuh_layer_id: u8) -> Vec<u8> {
+        let Some(idx) = self.layer_idx(nuh_layer_id) else {
+            return Vec::new();
+        };
+        self.layer_id_in_nuh
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| self.direct_dependency[idx] >> j & 1 != 0)
+            .map(|(_, &id)| id)
+            .collect()
+    }
+
+    /// Get the layers a layer depends on directly or indirectly, lowest
+    /// layer index first
+    pub fn ref_layers(&self, nuh_layer_id: u8) -> Vec<u8> {
+        let Some(idx) = self.layer_idx(nuh_layer_id) else {
+            return Vec::new();
+        };
+        self.layer_id_in_nuh
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| self.dependency[idx] >> j & 1 != 0)
+            .map(|(_, &id)| id)
+            .collect()
+    }
+
+    /// Get ViewId of a layer (F.7.4.3.1.1)
+    pub fn view_id(&self, nuh_layer_id: u8) -> u32 {
+        self.layer_idx(nuh_layer_id)
+            .and_then(|idx| self.view_id_val.get(self.scalability_id[idx][1] as usize))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Get AuxId of a layer (0 for primary picture layers, 1 alpha, 2 depth)
+    pub fn aux_id(&self, nuh_layer_id: u8) -> u8 {
+        self.layer_idx(nuh_layer_id)
+            .map_or(0, |idx| self.scalability_id[idx][3])
+    }
+
+    /// Get the output layers of an output layer set, in layer set order
+    pub fn output_layers(&self, ols_idx: usize) -> Option<Vec<u8>> {
+        let ols = self.output_layer_sets.get(ols_idx)?;
+        let layer_set = self.layer_sets.get(ols.layer_set_idx)?;
+        Some(
+            layer_set
+                .iter()
+                .zip(&ols.output_layer_flags)
+                .filter(|&(_, &output)| output)
+                .map(|(&id, _)| id)
+                .collect(),
+        )
+    }
+}
+
+/// Output layer set from the VPS extension
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct OutputLayerSet {
+    /// Index of the layer set (OlsIdxToLsIdx)
+    pub layer_set_idx: usize,
+    /// Output layer flag for each layer of the layer set
+    pub output_layer_flags: Vec<bool>,
+}
+
+/// Representation format (H.265 F.7.3.2.1.3)
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct RepFormat {
+    /// Picture width in luma samples
+    pub pic_width_in_luma_samples: u32,
+    /// Picture height in luma samples
+    pub pic_height_in_luma_samples: u32,
+    /// Chroma format IDC
+    pub chroma_format_idc: u8,
+    /// Separate colour plane flag
+    pub separate_colour_plane_flag: bool,
+    /// Bit depth luma minus 8
+    pub bit_depth_luma_minus8: u8,
+    /// Bit depth chroma minus 8
+    pub bit_depth_chroma_minus8: u8,
+    /// Conformance window flag
+    pub conformance_window_flag: bool,
+    /// Conformance window offsets (left, right, top, bottom)
+    pub conf_win_offset: (u32, u32, u32, u32),
 }
 
 /// Sequence Parameter Set
@@ -62,6 +203,12 @@ pub struct Sps {
     pub log2_max_pic_order_cnt_lsb_minus4: u8,
     /// Sub-layer ordering info present flag
     pub sub_layer_ordering_info_present_flag: bool,
+    /// DPB size minus 1 for the highest sub-layer (sps_max_dec_pic_buffering_minus1)
+    pub max_dec_pic_buffering_minus1: u8,
+    /// Max pictures preceding any picture in decoding order and following it in output order
+    pub max_num_reorder_pics: u8,
+    /// Max latency increase plus 1 (0 = no limit)
+    pub max_latency_increase_plus1: u32,
     /// Log2 min luma coding block size minus 3
     pub log2_min_luma_coding_block_size_minus3: u8,
     /// Log2 diff max min luma coding block size
@@ -88,8 +235,14 @@ pub struct Sps {
     pub pcm_params: Option<PcmParams>,
     /// Number of short-term reference picture sets
     pub num_short_term_ref_pic_sets: u8,
+    /// Short-term reference picture sets (st_ref_pic_set(i))
+    pub st_ref_pic_sets: Vec<ShortTermRps>,
     /// Long-term reference pictures present flag
     pub long_term_ref_pics_present_flag: bool,
+    /// Candidate long-term reference picture POC LSBs (lt_ref_pic_poc_lsb_sps)
+    pub lt_ref_pic_poc_lsb_sps: Vec<u32>,
+    /// Whether each candidate long-term picture is used by the current picture
+    pub used_by_curr_pic_lt_sps_flag: Vec<bool>,
     /// Temporal MVP enabled flag
     pub sps_temporal_mvp_enabled_flag: bool,
     /// Strong intra smoothing enabled flag
@@ -100,6 +253,55 @@ pub struct Sps {
     pub video_full_range_flag: bool,
     /// Matrix coefficients (from VUI). 1=BT.709, 5/6=BT.601, 9=BT.2020
     pub matrix_coeffs: u8,
+    /// SPS range extension (all flags false when absent)
+    pub range_extension: SpsRangeExtension,
+    /// nuh_layer_id of the NAL unit carrying the SPS
+    pub nuh_layer_id: u8,
+    /// Multi-layer extension SPS (MultiLayerExtSpsFlag): the picture format
+    /// comes from a VPS rep_format, applied by [`Sps::for_layer`]
+    pub multilayer_ext_sps_flag: bool,
+    /// Explicit rep_format index (sps_rep_format_idx) of a multi-layer extension SPS
+    pub sps_rep_format_idx: Option<u8>,
+    /// Layer whose scaling list is inferred (sps_scaling_list_ref_layer_id)
+    pub scaling_list_ref_layer_id: Option<u8>,
+    /// Inter-layer parameters of the layer the SPS is active for
+    pub layer: LayerParams,
+}
+
+/// Inter-layer parameters of the layer an SPS is active for (H.265 F.7.4.3.1.1)
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct LayerParams {
+    /// nuh_layer_id of the layer
+    pub nuh_layer_id: u8,
+    /// Direct reference layers (IdDirectRefLayer)
+    pub direct_ref_layer_ids: Vec<u8>,
+    /// POC LSB not present flag
+    pub poc_lsb_not_present_flag: bool,
+    /// Default ref layers active flag
+    pub default_ref_layers_active_flag: bool,
+    /// Max one active ref layer flag
+    pub max_one_active_ref_layer_flag: bool,
+}
+
+/// Short-term reference picture set (H.265 7.4.8)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShortTermRps {
+    /// DeltaPocS0: POC deltas of the preceding pictures, closest first
+    pub delta_poc_s0: Vec<i32>,
+    /// UsedByCurrPicS0
+    pub used_by_curr_pic_s0: Vec<bool>,
+    /// DeltaPocS1: POC deltas of the following pictures, closest first
+    pub delta_poc_s1: Vec<i32>,
+    /// UsedByCurrPicS1
+    pub used_by_curr_pic_s1: Vec<bool>,
+}
+
+impl ShortTermRps {
+    /// NumDeltaPocs
+    pub fn num_delta_pocs(&self) -> usize {
+        self.delta_poc_s0.len() + self.delta_poc_s1.len()
+    }
 }
 
 impl Sps {
@@ -112,6 +314,22 @@ impl Sps {
         }
     }
 
+    /// Get SubWidthC (H.265 Table 6-1)
+    pub fn sub_width_c(&self) -> u32 {
+        match self.chroma_format_idc {
+            1 | 2 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Get SubHeightC (H.265 Table 6-1)
+    pub fn sub_height_c(&self) -> u32 {
+        match self.chroma_format_idc {
+            1 => 2,
+            _ => 1,
+        }
+    }
+
     /// Get bit depth for luma
     pub fn bit_depth_y(&self) -> u8 {
         8 + self.bit_depth_luma_minus8
@@ -156,6 +374,148 @@ impl Sps {
     pub fn log2_max_tb_size(&self) -> u8 {
         self.log2_min_tb_size() + self.log2_diff_max_min_luma_transform_block_size
     }
+
+    /// Activate this SPS for a layer of a multi-layer stream
+    ///
+    /// For layers above the base layer, multi-layer extension SPSs and
+    /// base-layer SPSs take their picture format from the layer's VPS
+    /// rep_format (H.265 F.7.4.3.2.1), and the inter-layer parameters the
+    /// slice header depends on are filled in from the VPS extension.
+    pub fn for_layer(&self, vps: Option<&Vps>, nuh_layer_id: u8) -> Result<Sps> {
+        let mut sps = self.clone();
+        sps.layer.nuh_layer_id = nuh_layer_id;
+        if nuh_layer_id == 0 {
+            return Ok(sps);
+        }
+
+        let Some(ext) = vps.and_then(|vps| vps.extension.as_ref()) else {
+            if self.multilayer_ext_sps_flag {
+                return Err(HevcError::MissingParameterSet("VPS extension"));
+            }
+            // No layer structure: the layer is decoded as an independent layer
+            return Ok(sps);
+        };
+        let layer_idx =
+            ext.layer_idx(nuh_layer_id)
+                .ok_or_else(|| HevcError::InvalidParameterSet {
+                    kind: "VPS",
+                    msg: alloc::format!("layer {nuh_layer_id} not described"),
+                })?;
+
+        if self.multilayer_ext_sps_flag || self.nuh_layer_id == 0 {
+            let rep_format_idx = self
+                .sps_rep_format_idx
+                .unwrap_or(ext.rep_format_idx[layer_idx]);
+            let rep_format = ext
+                .rep_formats
+                .get(rep_format_idx as usize)
+                .ok_or_else(|| HevcError::InvalidParameterSet {
+                    kind: "SPS",
+                    msg: "sps_rep_format_idx out of range".to_string(),
+                })?;
+            sps.set_rep_format(rep_format);
+        }
+
+        sps.layer = LayerParams {
+            nuh_layer_id,
+            direct_ref_layer_ids: ext.direct_ref_layers(nuh_layer_id),
+            poc_lsb_not_present_flag: ext.poc_lsb_not_present_flag[layer_idx],
+            default_ref_layers_active_flag: ext.default_ref_layers_active_flag,
+            max_one_active_ref_layer_flag: ext.max_one_active_ref_layer_flag,
+        };
+        Ok(sps)
+    }
+
+    fn set_rep_format(&mut self, rep_format: &RepFormat) {
+        self.chroma_format_idc = rep_format.chroma_format_idc;
+        self.separate_colour_plane_flag = rep_format.separate_colour_plane_flag;
+        self.pic_width_in_luma_samples = rep_format.pic_width_in_luma_samples;
+        self.pic_height_in_luma_samples = rep_format.pic_height_in_luma_samples;
+        self.conformance_window_flag = rep_format.conformance_window_flag;
+        self.conf_win_offset = rep_format.conf_win_offset;
+        self.bit_depth_luma_minus8 = rep_format.bit_depth_luma_minus8;
+        self.bit_depth_chroma_minus8 = rep_format.bit_depth_chroma_minus8;
+    }
+
+    /// Get log2 of the coefficient range for a component of the given bit depth
+    ///
+    /// CoeffMin/CoeffMax = ∓(1 << this) (H.265 Eq. 7-27..7-30): 15 unless
+    /// extended_precision_processing_flag widens it to Max(15, BitDepth + 6).
+    pub fn log2_transform_range(&self, bit_depth: u8) -> u8 {
+        if self.range_extension.extended_precision_processing_flag {
+            15.max(bit_depth + 6)
+        } else {
+            15
+        }
+    }
+}
+
+/// SPS range extension flags (H.265 7.3.2.2.2)
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpsRangeExtension {
+    /// Transform skip rotation enabled flag
+    pub transform_skip_rotation_enabled_flag: bool,
+    /// Transform skip context enabled flag
+    pub transform_skip_context_enabled_flag: bool,
+    /// Implicit RDPCM enabled flag
+    pub implicit_rdpcm_enabled_flag: bool,
+    /// Explicit RDPCM enabled flag
+    pub explicit_rdpcm_enabled_flag: bool,
+    /// Extended precision processing flag (coefficients wider than 16 bits)
+    pub extended_precision_processing_flag: bool,
+    /// Intra smoothing disabled flag
+    pub intra_smoothing_disabled_flag: bool,
+    /// High precision offsets enabled flag (weighted prediction only)
+    pub high_precision_offsets_enabled_flag: bool,
+    /// Persistent Rice adaptation enabled flag
+    pub persistent_rice_adaptation_enabled_flag: bool,
+    /// CABAC bypass alignment enabled flag
+    pub cabac_bypass_alignment_enabled_flag: bool,
+}
+
+/// PPS range extension (H.265 7.3.2.3.2)
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PpsRangeExtension {
+    /// Log2 max transform skip block size minus 2
+    pub log2_max_transform_skip_block_size_minus2: u8,
+    /// Cross-component prediction enabled flag
+    pub cross_component_prediction_enabled_flag: bool,
+    /// Chroma QP offset list enabled flag
+    pub chroma_qp_offset_list_enabled_flag: bool,
+    /// Log2 SAO offset scale for luma
+    pub log2_sao_offset_scale_luma: u8,
+    /// Log2 SAO offset scale for chroma
+    pub log2_sao_offset_scale_chroma: u8,
+}
+
+/// PPS multilayer extension (H.265 F.7.3.2.3.4)
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct PpsMultilayerExtension {
+    /// POC reset info present flag
+    pub poc_reset_info_present_flag: bool,
+    /// Layer whose scaling list is inferred (pps_scaling_list_ref_layer_id)
+    pub scaling_list_ref_layer_id: Option<u8>,
+    /// Reference layer location offsets, one entry per signalled layer
+    pub ref_loc_offsets: Vec<RefLocOffsets>,
+    /// Colour mapping (colour gamut scalability) enabled flag
+    pub colour_mapping_enabled_flag: bool,
+}
+
+/// Reference layer location offsets of one reference layer, as signalled
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct RefLocOffsets {
+    /// nuh_layer_id of the reference layer
+    pub ref_loc_offset_layer_id: u8,
+    /// Scaled reference layer offsets (left, top, right, bottom)
+    pub scaled_ref_layer_offsets: Option<[i32; 4]>,
+    /// Reference region offsets (left, top, right, bottom)
+    pub ref_region_offsets: Option<[i32; 4]>,
+    /// Resampling phases (hor luma, ver luma, hor chroma plus 8, ver chroma plus 8)
+    pub resample_phases: Option<[u32; 4]>,
 }
 
 /// PCM parameters
@@ -386,6 +746,19 @@ pub struct Pps {
     pub log2_parallel_merge_level_minus2: u8,
     /// Slice segment header extension present flag
     pub slice_segment_header_extension_present_flag: bool,
+    /// PPS range extension (defaults when absent)
+    pub range_extension: PpsRangeExtension,
+    /// PPS multilayer extension (defaults when absent)
+    pub multilayer_extension: PpsMultilayerExtension,
+}
+
+impl Pps {
+    /// Get Log2MaxTransformSkipSize
+    pub fn log2_max_transform_skip_size(&self) -> u8 {
+        self.range_extension
+            .log2_max_transform_skip_block_size_minus2
+            + 2
+    }
 }
 
 /// Tile configuration
@@ -452,6 +825,21 @@ pub fn parse_vps(data: &[u8]) -> Result<Vps> {
 
     let ptl = parse_profile_tier_level(&mut reader, true, max_sub_layers_minus1)?;
 
+    // Single-layer decoding never needs the extension, so a malformed one is
+    // treated as absent rather than failing the base layer
+    let extension = if max_layers_minus1 > 0 {
+        parse_vps_extension(
+            &mut reader,
+            base_layer_internal_flag,
+            max_layers_minus1,
+            max_sub_layers_minus1,
+        )
+        .ok()
+        .flatten()
+    } else {
+        None
+    };
+
     Ok(Vps {
         vps_id,
         base_layer_internal_flag,
@@ -460,58 +848,520 @@ pub fn parse_vps(data: &[u8]) -> Result<Vps> {
         max_sub_layers_minus1,
         temporal_id_nesting_flag,
         ptl,
+        extension,
     })
 }
 
-/// Parse Sequence Parameter Set
-pub fn parse_sps(data: &[u8]) -> Result<Sps> {
-    let mut reader = BitstreamReader::new(data);
+/// Parse the rest of the VPS and vps_extension() (H.265 7.3.2.1, F.7.3.2.1.1)
+///
+/// Returns `None` when vps_extension_flag is 0.
+fn parse_vps_extension(
+    reader: &mut BitstreamReader<'_>,
+    base_layer_internal_flag: bool,
+    max_layers_minus1: u8,
+    max_sub_layers_minus1: u8,
+) -> Result<Option<VpsExtension>> {
+    let invalid = |msg: &str| HevcError::InvalidParameterSet {
+        kind: "VPS",
+        msg: msg.to_string(),
+    };
 
-    let vps_id = reader.read_bits(4)? as u8;
-    let max_sub_layers_minus1 = reader.read_bits(3)? as u8;
-    let temporal_id_nesting_flag = reader.read_bit()? != 0;
+    let sub_layer_ordering_info_present_flag = reader.read_bit()? != 0;
+    let start = if sub_layer_ordering_info_present_flag {
+        0
+    } else {
+        max_sub_layers_minus1
+    };
+    for _ in start..=max_sub_layers_minus1 {
+        let _max_dec_pic_buffering_minus1 = reader.read_ue()?;
+        let _max_num_reorder_pics = reader.read_ue()?;
+        let _max_latency_increase_plus1 = reader.read_ue()?;
+    }
 
-    let ptl = parse_profile_tier_level(&mut reader, true, max_sub_layers_minus1)?;
+    // Layer sets: layer set 0 is the base layer alone
+    let vps_max_layer_id = reader.read_bits(6)? as u8;
+    let num_layer_sets_minus1 = reader.read_ue()? as usize;
+    if num_layer_sets_minus1 > 1023 {
+        return Err(invalid("vps_num_layer_sets_minus1 out of range"));
+    }
+    let mut layer_sets = alloc::vec![alloc::vec![0u8]];
+    for _ in 1..=num_layer_sets_minus1 {
+        let mut ids = Vec::new();
+        for id in 0..=vps_max_layer_id {
+            if reader.read_bit()? != 0 {
+                ids.push(id);
+            }
+        }
+        layer_sets.push(ids);
+    }
 
-    let sps_id = reader.read_ue()? as u8;
-    let chroma_format_idc = reader.read_ue()? as u8;
+    let vps_timing_info_present_flag = reader.read_bit()? != 0;
+    if vps_timing_info_present_flag {
+        let _vps_num_units_in_tick = reader.read_bits(32)?;
+        let _vps_time_scale = reader.read_bits(32)?;
+        let vps_poc_proportional_to_timing_flag = reader.read_bit()? != 0;
+        if vps_poc_proportional_to_timing_flag {
+            let _vps_num_ticks_poc_diff_one_minus1 = reader.read_ue()?;
+        }
+        let vps_num_hrd_parameters = reader.read_ue()?;
+        if vps_num_hrd_parameters > 1024 {
+            return Err(invalid("vps_num_hrd_parameters out of range"));
+        }
+        for i in 0..vps_num_hrd_parameters {
+            let _hrd_layer_set_idx = reader.read_ue()?;
+            let cprms_present_flag = i == 0 || reader.read_bit()? != 0;
+            skip_hrd_parameters(reader, cprms_present_flag, max_sub_layers_minus1)?;
+        }
+    }
 
-    let separate_colour_plane_flag = if chroma_format_idc == 3 {
-        reader.read_bit()? != 0
+    let vps_extension_flag = reader.read_bit()? != 0;
+    if !vps_extension_flag {
+        return Ok(None);
+    }
+    while !reader.is_byte_aligned() {
+        let _vps_extension_alignment_bit_equal_to_one = reader.read_bit()?;
+    }
+
+    // vps_extension()
+    let max_layers_minus1 = max_layers_minus1.min(62) as usize;
+    let num_layers = max_layers_minus1 + 1;
+    if base_layer_internal_flag {
+        parse_profile_tier_level(reader, false, max_sub_layers_minus1)?;
+    }
+
+    let splitting_flag = reader.read_bit()? != 0;
+    let mut scalability_mask = 0u16;
+    for sm_idx in 0..16 {
+        if reader.read_bit()? != 0 {
+            scalability_mask |= 1 << sm_idx;
+        }
+    }
+    let num_scalability_types = scalability_mask.count_ones() as usize;
+    let mut dimension_id_len = [0u8; 16];
+    for len in dimension_id_len
+        .iter_mut()
+        .take(num_scalability_types.saturating_sub(splitting_flag as usize))
+    {
+        *len = reader.read_bits(3)? as u8 + 1;
+    }
+    if splitting_flag && num_scalability_types > 0 {
+        // The last dimension takes the remaining bits of the 6-bit layer ID
+        let used: u8 = dimension_id_len[..num_scalability_types - 1].iter().sum();
+        if used > 6 {
+            return Err(invalid("dimension_id_len exceeds nuh_layer_id bits"));
+        }
+        dimension_id_len[num_scalability_types - 1] = 6 - used;
+    }
+
+    let vps_nuh_layer_id_present_flag = reader.read_bit()? != 0;
+    let mut layer_id_in_nuh = alloc::vec![0u8; num_layers];
+    let mut dimension_id = alloc::vec![[0u8; 16]; num_layers];
+    for i in 1..num_layers {
+        layer_id_in_nuh[i] = if vps_nuh_layer_id_present_flag {
+            reader.read_bits(6)? as u8
+        } else {
+            i as u8
+        };
+        if layer_id_in_nuh[i] <= layer_id_in_nuh[i - 1] {
+            return Err(invalid("layer_id_in_nuh not increasing"));
+        }
+        let mut bit_offset = 0;
+        for j in 0..num_scalability_types {
+            let len = dimension_id_len[j];
+            dimension_id[i][j] = if splitting_flag {
+                (layer_id_in_nuh[i] >> bit_offset) & ((1u8 << len) - 1)
+            } else {
+                reader.read_bits(len)? as u8
+            };
+            bit_offset += len;
+        }
+    }
+
+    // ScalabilityId[i][smIdx] from the dimension IDs of the signalled types
+    let mut scalability_id = alloc::vec![[0u8; 16]; num_layers];
+    for (ids, dims) in scalability_id.iter_mut().zip(&dimension_id) {
+        let mut j = 0;
+        for (sm_idx, id) in ids.iter_mut().enumerate() {
+            if scalability_mask >> sm_idx & 1 != 0 {
+                *id = dims[j];
+                j += 1;
+            }
+        }
+    }
+    let mut num_views = 1;
+    for i in 1..num_layers {
+        let view_order_idx = scalability_id[i][1];
+        if scalability_id[..i]
+            .iter()
+            .all(|ids| ids[1] != view_order_idx)
+        {
+            num_views += 1;
+        }
+    }
+    let view_id_len = reader.read_bits(4)? as u8;
+    let mut view_id_val = Vec::new();
+    if view_id_len > 0 {
+        for _ in 0..num_views {
+            view_id_val.push(reader.read_bits(view_id_len)?);
+        }
+    }
+
+    let mut direct_dependency = alloc::vec![0u64; num_layers];
+    for (i, deps) in direct_dependency.iter_mut().enumerate().skip(1) {
+        for j in 0..i {
+            if reader.read_bit()? != 0 {
+                *deps |= 1 << j;
+            }
+        }
+    }
+    // DependencyFlag: reference layers only have lower layer indices
+    let mut dependency = direct_dependency.clone();
+    for i in 0..num_layers {
+        for k in 0..i {
+            if direct_dependency[i] >> k & 1 != 0 {
+                dependency[i] |= dependency[k];
+            }
+        }
+    }
+
+    // Tree partitions rooted at each independent layer (TreePartitionLayerIdList)
+    let mut in_partition = 0u64;
+    let mut tree_partitions: Vec<Vec<u8>> = Vec::new();
+    for i in 0..num_layers {
+        if direct_dependency[i] != 0 {
+            continue;
+        }
+        let mut partition = alloc::vec![layer_id_in_nuh[i]];
+        for j in 0..num_layers {
+            if dependency[j] >> i & 1 != 0 && in_partition >> j & 1 == 0 {
+                partition.push(layer_id_in_nuh[j]);
+                in_partition |= 1 << j;
+            }
+        }
+        tree_partitions.push(partition);
+    }
+
+    let num_add_layer_sets = if tree_partitions.len() > 1 {
+        reader.read_ue()? as usize
     } else {
-        false
+        0
     };
+    if num_add_layer_sets > 1023 {
+        return Err(invalid("num_add_layer_sets out of range"));
+    }
+    for _ in 0..num_add_layer_sets {
+        let mut ids = Vec::new();
+        for partition in &tree_partitions[1..] {
+            let bits = super::slice::ceil_log2(partition.len() as u32 + 1);
+            let highest_layer_idx_plus1 = reader.read_bits(bits)? as usize;
+            let partition_layers = partition
+                .get(..highest_layer_idx_plus1)
+                .ok_or_else(|| invalid("highest_layer_idx_plus1 out of range"))?;
+            ids.extend_from_slice(partition_layers);
+        }
+        layer_sets.push(ids);
+    }
 
-    let pic_width_in_luma_samples = reader.read_ue()?;
-    let pic_height_in_luma_samples = reader.read_ue()?;
+    let vps_sub_layers_max_minus1_present_flag = reader.read_bit()? != 0;
+    if vps_sub_layers_max_minus1_present_flag {
+        for _ in 0..num_layers {
+            let _sub_layers_vps_max_minus1 = reader.read_bits(3)?;
+        }
+    }
+    let max_tid_ref_present_flag = reader.read_bit()? != 0;
+    if max_tid_ref_present_flag {
+        for i in 0..max_layers_minus1 {
+            for deps in &direct_dependency[i + 1..] {
+                if deps >> i & 1 != 0 {
+                    let _max_tid_il_ref_pics_plus1 = reader.read_bits(3)?;
+                }
+            }
+        }
+    }
+    let default_ref_layers_active_flag = reader.read_bit()? != 0;
+
+    let vps_num_profile_tier_level_minus1 = reader.read_ue()?;
+    if vps_num_profile_tier_level_minus1 > 63 {
+        return Err(invalid("vps_num_profile_tier_level_minus1 out of range"));
+    }
+    let first_ptl = if base_layer_internal_flag { 2 } else { 1 };
+    for _ in first_ptl..=vps_num_profile_tier_level_minus1 {
+        let vps_profile_present_flag = reader.read_bit()? != 0;
+        parse_profile_tier_level(reader, vps_profile_present_flag, max_sub_layers_minus1)?;
+    }
+
+    // Output layer sets (F.7.4.3.1.1)
+    let num_layer_sets = layer_sets.len();
+    let (num_add_olss, default_output_layer_idc) = if num_layer_sets > 1 {
+        let num_add_olss = reader.read_ue()? as usize;
+        let default_output_layer_idc = reader.read_bits(2)?.min(2);
+        (num_add_olss, default_output_layer_idc)
+    } else {
+        (0, 0)
+    };
+    if num_add_olss > 1023 {
+        return Err(invalid("num_add_olss out of range"));
+    }
+    let layer_idx = |id: u8| layer_id_in_nuh.iter().position(|&l| l == id);
+    let mut output_layer_sets = alloc::vec![OutputLayerSet {
+        layer_set_idx: 0,
+        output_layer_flags: alloc::vec![true],
+    }];
+    for i in 1..num_layer_sets + num_add_olss {
+        let layer_set_idx = if i < num_layer_sets {
+            i
+        } else if num_layer_sets > 2 {
+            let bits = super::slice::ceil_log2(num_layer_sets as u32 - 1);
+            reader.read_bits(bits)? as usize + 1
+        } else {
+            1
+        };
+        let layer_set = layer_sets
+            .get(layer_set_idx)
+            .ok_or_else(|| invalid("layer_set_idx_for_ols out of range"))?;
+
+        let output_layer_flags: Vec<bool> =
+            if i > num_layer_sets_minus1 || default_output_layer_idc == 2 {
+                (0..layer_set.len())
+                    .map(|_| reader.read_bit().map(|b| b != 0))
+                    .collect::<Result<_>>()?
+            } else if default_output_layer_idc == 0 {
+                alloc::vec![true; layer_set.len()]
+            } else {
+                // Only the highest layer of the set is output
+                let highest = layer_set.iter().copied().max();
+                layer_set.iter().map(|&id| Some(id) == highest).collect()
+            };
+
+        // NecessaryLayerFlag: output layers and everything they depend on
+        let mut necessary = output_layer_flags.clone();
+        for (k, &output) in output_layer_flags.iter().enumerate() {
+            let Some(cur) = layer_idx(layer_set[k]).filter(|_| output) else {
+                continue;
+            };
+            for r in 0..k {
+                if let Some(ref_idx) = layer_idx(layer_set[r])
+                    && dependency[cur] >> ref_idx & 1 != 0
+                {
+                    necessary[r] = true;
+                }
+            }
+        }
+        if vps_num_profile_tier_level_minus1 > 0 {
+            let bits = super::slice::ceil_log2(vps_num_profile_tier_level_minus1 + 1);
+            for _ in necessary.iter().filter(|&&n| n) {
+                let _profile_tier_level_idx = reader.read_bits(bits)?;
+            }
+        }
+
+        let mut output_layers = layer_set
+            .iter()
+            .zip(&output_layer_flags)
+            .filter(|&(_, &output)| output);
+        if let (Some((&highest, _)), None) = (output_layers.next_back(), output_layers.next())
+            && layer_idx(highest).is_some_and(|idx| direct_dependency[idx] != 0)
+        {
+            let _alt_output_layer_flag = reader.read_bit()?;
+        }
+
+        output_layer_sets.push(OutputLayerSet {
+            layer_set_idx,
+            output_layer_flags,
+        });
+    }
 
+    let vps_num_rep_formats_minus1 = reader.read_ue()? as usize;
+    if vps_num_rep_formats_minus1 > 255 {
+        return Err(invalid("vps_num_rep_formats_minus1 out of range"));
+    }
+    let mut rep_formats: Vec<RepFormat> = Vec::with_capacity(vps_num_rep_formats_minus1 + 1);
+    for _ in 0..=vps_num_rep_formats_minus1 {
+        let rep_format = parse_rep_format(reader, rep_formats.last())?;
+        rep_formats.push(rep_format);
+    }
+    let rep_format_idx_present_flag = vps_num_rep_formats_minus1 > 0 && reader.read_bit()? != 0;
+    let mut rep_format_idx: Vec<u8> = (0..num_layers)
+        .map(|i| i.min(vps_num_rep_formats_minus1) as u8)
+        .collect();
+    if rep_format_idx_present_flag {
+        let bits = super::slice::ceil_log2(vps_num_rep_formats_minus1 as u32 + 1);
+        let first = if base_layer_internal_flag { 1 } else { 0 };
+        for idx in &mut rep_format_idx[first..] {
+            *idx = reader.read_bits(bits)? as u8;
+            if *idx as usize > vps_num_rep_formats_minus1 {
+                return Err(invalid("vps_rep_format_idx out of range"));
+            }
+        }
+        if base_layer_internal_flag {
+            rep_format_idx[0] = 0;
+        }
+    }
+
+    let max_one_active_ref_layer_flag = reader.read_bit()? != 0;
+    let _vps_poc_lsb_aligned_flag = reader.read_bit()?;
+    let mut poc_lsb_not_present_flag = alloc::vec![false; num_layers];
+    for i in 1..num_layers {
+        if direct_dependency[i] == 0 {
+            poc_lsb_not_present_flag[i] = reader.read_bit()? != 0;
+        }
+    }
+
+    Ok(Some(VpsExtension {
+        scalability_mask,
+        layer_id_in_nuh,
+        scalability_id,
+        view_id_val,
+        direct_dependency,
+        dependency,
+        layer_sets,
+        output_layer_sets,
+        rep_formats,
+        rep_format_idx,
+        default_ref_layers_active_flag,
+        max_one_active_ref_layer_flag,
+        poc_lsb_not_present_flag,
+    }))
+}
+
+/// Parse rep_format() (H.265 F.7.3.2.1.3)
+fn parse_rep_format(
+    reader: &mut BitstreamReader<'_>,
+    previous: Option<&RepFormat>,
+) -> Result<RepFormat> {
+    let pic_width_in_luma_samples = reader.read_bits(16)?;
+    let pic_height_in_luma_samples = reader.read_bits(16)?;
+    let chroma_and_bit_depth_vps_present_flag = reader.read_bit()? != 0;
+    let (
+        chroma_format_idc,
+        separate_colour_plane_flag,
+        bit_depth_luma_minus8,
+        bit_depth_chroma_minus8,
+    ) = if chroma_and_bit_depth_vps_present_flag {
+        let chroma_format_idc = reader.read_bits(2)? as u8;
+        let separate_colour_plane_flag = chroma_format_idc == 3 && reader.read_bit()? != 0;
+        let bit_depth_luma_minus8 = reader.read_bits(4)? as u8;
+        let bit_depth_chroma_minus8 = reader.read_bits(4)? as u8;
+        (
+            chroma_format_idc,
+            separate_colour_plane_flag,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+        )
+    } else {
+        // Inherited from the previous rep_format; the first must carry them
+        let previous = previous.ok_or_else(|| HevcError::InvalidParameterSet {
+            kind: "VPS",
+            msg: "first rep_format lacks chroma format and bit depth".to_string(),
+        })?;
+        (
+            previous.chroma_format_idc,
+            previous.separate_colour_plane_flag,
+            previous.bit_depth_luma_minus8,
+            previous.bit_depth_chroma_minus8,
+        )
+    };
+    if bit_depth_luma_minus8 > 8 || bit_depth_chroma_minus8 > 8 {
+        return Err(HevcError::InvalidParameterSet {
+            kind: "VPS",
+            msg: "rep_format bit depth out of range".to_string(),
+        });
+    }
     let conformance_window_flag = reader.read_bit()? != 0;
     let conf_win_offset = if conformance_window_flag {
-        let left = reader.read_ue()?;
-        let right = reader.read_ue()?;
-        let top = reader.read_ue()?;
-        let bottom = reader.read_ue()?;
-        (left, right, top, bottom)
+        (
+            reader.read_ue()?,
+            reader.read_ue()?,
+            reader.read_ue()?,
+            reader.read_ue()?,
+        )
     } else {
         (0, 0, 0, 0)
     };
 
-    let bit_depth_luma_minus8 = reader.read_ue()? as u8;
-    let bit_depth_chroma_minus8 = reader.read_ue()? as u8;
-    let log2_max_pic_order_cnt_lsb_minus4 = reader.read_ue()? as u8;
+    Ok(RepFormat {
+        pic_width_in_luma_samples,
+        pic_height_in_luma_samples,
+        chroma_format_idc,
+        separate_colour_plane_flag,
+        bit_depth_luma_minus8,
+        bit_depth_chroma_minus8,
+        conformance_window_flag,
+        conf_win_offset,
+    })
+}
 
-    let sub_layer_ordering_info_present_flag = reader.read_bit()? != 0;
+/// Parse Sequence Parameter Set
+pub fn parse_sps(data: &[u8]) -> Result<Sps> {
+    parse_sps_for_layer(data, 0, None)
+}
 
-    // Skip sub-layer ordering info
-    let start = if sub_layer_ordering_info_present_flag {
-        0
+/// Parse a Sequence Parameter Set carried in a NAL unit of the given layer
+///
+/// SPSs with nuh_layer_id > 0 may use the multi-layer extension syntax
+/// (H.265 F.7.3.2.2.1), which inherits the sub-layer count from `vps`.
+pub fn parse_sps_for_layer(data: &[u8], nuh_layer_id: u8, vps: Option<&Vps>) -> Result<Sps> {
+    let mut reader = BitstreamReader::new(data);
+
+    let vps_id = reader.read_bits(4)? as u8;
+    let sps_ext_or_max_sub_layers_minus1 = reader.read_bits(3)? as u8;
+    let multilayer_ext_sps_flag = nuh_layer_id != 0 && sps_ext_or_max_sub_layers_minus1 == 7;
+
+    let (max_sub_layers_minus1, temporal_id_nesting_flag, ptl) = if multilayer_ext_sps_flag {
+        (
+            vps.map_or(6, |vps| vps.max_sub_layers_minus1),
+            vps.is_none_or(|vps| vps.temporal_id_nesting_flag),
+            ProfileTierLevel::default(),
+        )
     } else {
-        max_sub_layers_minus1
+        let max_sub_layers_minus1 = sps_ext_or_max_sub_layers_minus1;
+        let temporal_id_nesting_flag = reader.read_bit()? != 0;
+        let ptl = parse_profile_tier_level(&mut reader, true, max_sub_layers_minus1)?;
+        (max_sub_layers_minus1, temporal_id_nesting_flag, ptl)
     };
-    for _ in start..=max_sub_layers_minus1 {
-        let _max_dec_pic_buffering_minus1 = reader.read_ue()?;
-        let _max_num_reorder_pics = reader.read_ue()?;
-        let _max_latency_increase_plus1 = reader.read_ue()?;
+
+    let sps_id = read_parameter_set_id(&mut reader, "SPS", MAX_SPS_ID)?;
+
+    let mut sps_rep_format_idx = None;
+    let format = if multilayer_ext_sps_flag {
+        let update_rep_format_flag = reader.read_bit()? != 0;
+        if update_rep_format_flag {
+            sps_rep_format_idx = Some(reader.read_bits(8)? as u8);
+        }
+        // Placeholder until Sps::for_layer applies the VPS rep_format
+        RepFormat {
+            pic_width_in_luma_samples: 0,
+            pic_height_in_luma_samples: 0,
+            chroma_format_idc: 1,
+            separate_colour_plane_flag: false,
+            bit_depth_luma_minus8: 0,
+            bit_depth_chroma_minus8: 0,
+            conformance_window_flag: false,
+            conf_win_offset: (0, 0, 0, 0),
+        }
+    } else {
+        parse_sps_format(&mut reader)?
+    };
+
+    let log2_max_pic_order_cnt_lsb_minus4 = reader.read_ue()? as u8;
+
+    let sub_layer_ordering_info_present_flag = !multilayer_ext_sps_flag && reader.read_bit()? != 0;
+
+    // Only the values of the highest sub-layer are kept; multi-layer
+    // extension SPSs take them from the VPS, so assume the largest DPB
+    let mut max_dec_pic_buffering_minus1 = 15u8;
+    let mut max_num_reorder_pics = 15u8;
+    let mut max_latency_increase_plus1 = 0u32;
+    if !multilayer_ext_sps_flag {
+        let start = if sub_layer_ordering_info_present_flag {
+            0
+        } else {
+            max_sub_layers_minus1
+        };
+        for _ in start..=max_sub_layers_minus1 {
+            max_dec_pic_buffering_minus1 = reader.read_ue()?.min(15) as u8;
+            max_num_reorder_pics = reader.read_ue()?.min(15) as u8;
+            max_latency_increase_plus1 = reader.read_ue()?;
+        }
     }
 
     let log2_min_luma_coding_block_size_minus3 = reader.read_ue()? as u8;
@@ -522,13 +1372,21 @@ pub fn parse_sps(data: &[u8]) -> Result<Sps> {
     let max_transform_hierarchy_depth_intra = reader.read_ue()? as u8;
 
     let scaling_list_enabled_flag = reader.read_bit()? != 0;
+    let mut scaling_list_ref_layer_id = None;
     let scaling_list = if scaling_list_enabled_flag {
-        let scaling_list_data_present = reader.read_bit()? != 0;
-        if scaling_list_data_present {
-            Some(parse_scaling_list_data(&mut reader)?)
-        } else {
-            // Use H.265 default scaling matrices
+        let sps_infer_scaling_list_flag = multilayer_ext_sps_flag && reader.read_bit()? != 0;
+        if sps_infer_scaling_list_flag {
+            // Copied from the reference layer's SPS by the decoder
+            scaling_list_ref_layer_id = Some(reader.read_bits(6)? as u8);
             Some(ScalingListData::new_default())
+        } else {
+            let scaling_list_data_present = reader.read_bit()? != 0;
+            if scaling_list_data_present {
+                Some(parse_scaling_list_data(&mut reader)?)
+            } else {
+                // Use H.265 default scaling matrices
+                Some(ScalingListData::new_default())
+            }
         }
     } else {
         None
@@ -555,19 +1413,35 @@ pub fn parse_sps(data: &[u8]) -> Result<Sps> {
         None
     };
 
-    let num_short_term_ref_pic_sets = reader.read_ue()? as u8;
-    // Skip short term ref pic sets (not needed for still images)
-    for i in 0..num_short_term_ref_pic_sets {
-        skip_short_term_ref_pic_set(&mut reader, i, num_short_term_ref_pic_sets)?;
+    let num_short_term_ref_pic_sets = reader.read_ue()?;
+    if num_short_term_ref_pic_sets > 64 {
+        return Err(HevcError::InvalidParameterSet {
+            kind: "SPS",
+            msg: "num_short_term_ref_pic_sets out of range".to_string(),
+        });
+    }
+    let num_short_term_ref_pic_sets = num_short_term_ref_pic_sets as u8;
+    let mut st_ref_pic_sets = Vec::with_capacity(num_short_term_ref_pic_sets as usize);
+    for _ in 0..num_short_term_ref_pic_sets {
+        let rps = parse_short_term_ref_pic_set(&mut reader, &st_ref_pic_sets, false)?;
+        st_ref_pic_sets.push(rps);
     }
 
     let long_term_ref_pics_present_flag = reader.read_bit()? != 0;
+    let mut lt_ref_pic_poc_lsb_sps = Vec::new();
+    let mut used_by_curr_pic_lt_sps_flag = Vec::new();
     if long_term_ref_pics_present_flag {
         let num_long_term_ref_pics_sps = reader.read_ue()?;
+        if num_long_term_ref_pics_sps > 32 {
+            return Err(HevcError::InvalidParameterSet {
+                kind: "SPS",
+                msg: "num_long_term_ref_pics_sps out of range".to_string(),
+            });
+        }
         for _ in 0..num_long_term_ref_pics_sps {
-            let _lt_ref_pic_poc_lsb_sps =
-                reader.read_bits(log2_max_pic_order_cnt_lsb_minus4 + 4)?;
-            let _used_by_curr_pic_lt_sps_flag = reader.read_bit()?;
+            lt_ref_pic_poc_lsb_sps
+                .push(reader.read_bits(log2_max_pic_order_cnt_lsb_minus4 + 4)?);
+            used_by_curr_pic_lt_sps_flag.push(reader.read_bit()? != 0);
         }
     }
 
@@ -579,6 +1453,7 @@ pub fn parse_sps(data: &[u8]) -> Result<Sps> {
     // Parse VUI color parameters if present
     let mut video_full_range_flag = false; // default: limited range
     let mut matrix_coeffs = 2u8; // default: unspecified
+    let mut vui_complete = true;
     if vui_parameters_present_flag {
         let aspect_ratio_info_present = reader.read_bit()? != 0;
         if aspect_ratio_info_present {
@@ -604,25 +1479,38 @@ pub fn parse_sps(data: &[u8]) -> Result<Sps> {
                 matrix_coeffs = reader.read_bits(8)? as u8;
             }
         }
-        // Don't parse the rest of VUI — we only need color info
+        // The rest of the VUI carries nothing the decoder needs, but has to be
+        // walked to reach the SPS extensions
+        vui_complete = skip_vui_tail(&mut reader, max_sub_layers_minus1).is_ok();
     }
 
+    // Some encoders write malformed VUI; if the tail could not be walked, treat
+    // the extensions as absent rather than rejecting an otherwise decodable SPS.
+    let range_extension = if vui_complete {
+        parse_sps_extensions(&mut reader)?
+    } else {
+        SpsRangeExtension::default()
+    };
+
     Ok(Sps {
         sps_id,
         vps_id,
         max_sub_layers_minus1,
         temporal_id_nesting_flag,
         ptl,
-        chroma_format_idc,
-        separate_colour_plane_flag,
-        pic_width_in_luma_samples,
-        pic_height_in_luma_samples,
-        conformance_window_flag,
-        conf_win_offset,
-        bit_depth_luma_minus8,
-        bit_depth_chroma_minus8,
+        chroma_format_idc: format.chroma_format_idc,
+        separate_colour_plane_flag: format.separate_colour_plane_flag,
+        pic_width_in_luma_samples: format.pic_width_in_luma_samples,
+        pic_height_in_luma_samples: format.pic_height_in_luma_samples,
+        conformance_window_flag: format.conformance_window_flag,
+        conf_win_offset: format.conf_win_offset,
+        bit_depth_luma_minus8: format.bit_depth_luma_minus8,
+        bit_depth_chroma_minus8: format.bit_depth_chroma_minus8,
         log2_max_pic_order_cnt_lsb_minus4,
         sub_layer_ordering_info_present_flag,
+        max_dec_pic_buffering_minus1,
+        max_num_reorder_pics,
+        max_latency_increase_plus1,
         log2_min_luma_coding_block_size_minus3,
         log2_diff_max_min_luma_coding_block_size,
         log2_min_luma_transform_block_size_minus2,
@@ -636,21 +1524,96 @@ pub fn parse_sps(data: &[u8]) -> Result<Sps> {
         pcm_enabled_flag,
         pcm_params,
         num_short_term_ref_pic_sets,
+        st_ref_pic_sets,
         long_term_ref_pics_present_flag,
+        lt_ref_pic_poc_lsb_sps,
+        used_by_curr_pic_lt_sps_flag,
         sps_temporal_mvp_enabled_flag,
         strong_intra_smoothing_enabled_flag,
         vui_parameters_present_flag,
         video_full_range_flag,
         matrix_coeffs,
+        range_extension,
+        nuh_layer_id,
+        multilayer_ext_sps_flag,
+        sps_rep_format_idx,
+        scaling_list_ref_layer_id,
+        layer: LayerParams::default(),
     })
 }
 
+/// Parse the picture format fields of an SPS (chroma format through bit depths)
+fn parse_sps_format(reader: &mut BitstreamReader<'_>) -> Result<RepFormat> {
+    let chroma_format_idc = reader.read_ue()? as u8;
+
+    let separate_colour_plane_flag = if chroma_format_idc == 3 {
+        reader.read_bit()? != 0
+    } else {
+        false
+    };
+
+    let pic_width_in_luma_samples = reader.read_ue()?;
+    let pic_height_in_luma_samples = reader.read_ue()?;
+
+    let conformance_window_flag = reader.read_bit()? != 0;
+    let conf_win_offset = if conformance_window_flag {
+        let left = reader.read_ue()?;
+        let right = reader.read_ue()?;
+        let top = reader.read_ue()?;
+        let bottom = reader.read_ue()?;
+        (left, right, top, bottom)
+    } else {
+        (0, 0, 0, 0)
+    };
+
+    let bit_depth_luma_minus8 = reader.read_ue()?;
+    let bit_depth_chroma_minus8 = reader.read_ue()?;
+    // H.265 7.4.3.2.1: bit_depth_*_minus8 shall be in the range 0..=8
+    if bit_depth_luma_minus8 > 8 || bit_depth_chroma_minus8 > 8 {
+        return Err(HevcError::InvalidParameterSet {
+            kind: "SPS",
+            msg: alloc::format!(
+                "unsupported bit depth luma={} chroma={}",
+                bit_depth_luma_minus8 + 8,
+                bit_depth_chroma_minus8 + 8
+            ),
+        });
+    }
+
+    Ok(RepFormat {
+        pic_width_in_luma_samples,
+        pic_height_in_luma_samples,
+        chroma_format_idc,
+        separate_colour_plane_flag,
+        bit_depth_luma_minus8: bit_depth_luma_minus8 as u8,
+        bit_depth_chroma_minus8: bit_depth_chroma_minus8 as u8,
+        conformance_window_flag,
+        conf_win_offset,
+    })
+}
+
+/// Read a ue(v) parameter set ID, rejecting values above `max`
+fn read_parameter_set_id(
+    reader: &mut BitstreamReader<'_>,
+    kind: &'static str,
+    max: u8,
+) -> Result<u8> {
+    let id = reader.read_ue()?;
+    if id > u32::from(max) {
+        return Err(HevcError::InvalidParameterSet {
+            kind,
+            msg: alloc::format!("parameter set ID {id} above {max}"),
+        });
+    }
+    Ok(id as u8)
+}
+
 /// Parse Picture Parameter Set
 pub fn parse_pps(data: &[u8]) -> Result<Pps> {
     let mut reader = BitstreamReader::new(data);
 
-    let pps_id = reader.read_ue()? as u8;
-    let sps_id = reader.read_ue()? as u8;
+    let pps_id = read_parameter_set_id(&mut reader, "PPS", MAX_PPS_ID)?;
+    let sps_id = read_parameter_set_id(&mut reader, "PPS", MAX_SPS_ID)?;
     let dependent_slice_segments_enabled_flag = reader.read_bit()? != 0;
     let output_flag_present_flag = reader.read_bit()? != 0;
     let num_extra_slice_header_bits = reader.read_bits(3)? as u8;
@@ -743,6 +1706,23 @@ pub fn parse_pps(data: &[u8]) -> Result<Pps> {
     let log2_parallel_merge_level_minus2 = reader.read_ue()? as u8;
     let slice_segment_header_extension_present_flag = reader.read_bit()? != 0;
 
+    let mut range_extension = PpsRangeExtension::default();
+    let mut multilayer_extension = PpsMultilayerExtension::default();
+    let pps_extension_present_flag = reader.read_bit()? != 0;
+    if pps_extension_present_flag {
+        let pps_range_extension_flag = reader.read_bit()? != 0;
+        let pps_multilayer_extension_flag = reader.read_bit()? != 0;
+        let _pps_3d_extension_flag = reader.read_bit()?;
+        let _pps_scc_extension_flag = reader.read_bit()?;
+        let _pps_extension_4bits = reader.read_bits(4)?;
+        if pps_range_extension_flag {
+            range_extension = parse_pps_range_extension(&mut reader, transform_skip_enabled_flag)?;
+        }
+        if pps_multilayer_extension_flag {
+            multilayer_extension = parse_pps_multilayer_extension(&mut reader)?;
+        }
+    }
+
     Ok(Pps {
         pps_id,
         sps_id,
@@ -778,9 +1758,285 @@ pub fn parse_pps(data: &[u8]) -> Result<Pps> {
         lists_modification_present_flag,
         log2_parallel_merge_level_minus2,
         slice_segment_header_extension_present_flag,
+        range_extension,
+        multilayer_extension,
+    })
+}
+
+/// Parse pps_multilayer_extension() (H.265 F.7.3.2.3.4)
+///
+/// The colour mapping table that may follow is not parsed; decoding rejects
+/// PPSs that enable it.
+fn parse_pps_multilayer_extension(
+    reader: &mut BitstreamReader<'_>,
+) -> Result<PpsMultilayerExtension> {
+    let poc_reset_info_present_flag = reader.read_bit()? != 0;
+    let pps_infer_scaling_list_flag = reader.read_bit()? != 0;
+    let scaling_list_ref_layer_id = if pps_infer_scaling_list_flag {
+        Some(reader.read_bits(6)? as u8)
+    } else {
+        None
+    };
+
+    let num_ref_loc_offsets = reader.read_ue()?;
+    if num_ref_loc_offsets > 62 {
+        return Err(HevcError::InvalidParameterSet {
+            kind: "PPS",
+            msg: "num_ref_loc_offsets out of range".to_string(),
+        });
+    }
+    let mut ref_loc_offsets = Vec::with_capacity(num_ref_loc_offsets as usize);
+    for _ in 0..num_ref_loc_offsets {
+        let ref_loc_offset_layer_id = reader.read_bits(6)? as u8;
+        let scaled_ref_layer_offset_present_flag = reader.read_bit()? != 0;
+        let scaled_ref_layer_offsets = if scaled_ref_layer_offset_present_flag {
+            Some([
+                reader.read_se()?,
+                reader.read_se()?,
+                reader.read_se()?,
+                reader.read_se()?,
+            ])
+        } else {
+            None
+        };
+        let ref_region_offset_present_flag = reader.read_bit()? != 0;
+        let ref_region_offsets = if ref_region_offset_present_flag {
+            Some([
+                reader.read_se()?,
+                reader.read_se()?,
+                reader.read_se()?,
+                reader.read_se()?,
+            ])
+        } else {
+            None
+        };
+        let resample_phase_set_present_flag = reader.read_bit()? != 0;
+        let resample_phases = if resample_phase_set_present_flag {
+            Some([
+                reader.read_ue()?,
+                reader.read_ue()?,
+                reader.read_ue()?,
+                reader.read_ue()?,
+            ])
+        } else {
+            None
+        };
+        ref_loc_offsets.push(RefLocOffsets {
+            ref_loc_offset_layer_id,
+            scaled_ref_layer_offsets,
+            ref_region_offsets,
+            resample_phases,
+        });
+    }
+    let colour_mapping_enabled_flag = reader.read_bit()? != 0;
+
+    Ok(PpsMultilayerExtension {
+        poc_reset_info_present_flag,
+        scaling_list_ref_layer_id,
+        ref_loc_offsets,
+        colour_mapping_enabled_flag,
     })
 }
 
+/// Parse pps_range_extension() (H.265 7.3.2.3.2)
+fn parse_pps_range_extension(
+    reader: &mut BitstreamReader<'_>,
+    transform_skip_enabled_flag: bool,
+) -> Result<PpsRangeExtension> {
+    let log2_max_transform_skip_block_size_minus2 = if transform_skip_enabled_flag {
+        reader.read_ue()?
+    } else {
+        0
+    };
+    if log2_max_transform_skip_block_size_minus2 > 3 {
+        return Err(HevcError::InvalidParameterSet {
+            kind: "PPS",
+            msg: "log2_max_transform_skip_block_size_minus2 out of range".to_string(),
+        });
+    }
+    let cross_component_prediction_enabled_flag = reader.read_bit()? != 0;
+    let chroma_qp_offset_list_enabled_flag = reader.read_bit()? != 0;
+    if chroma_qp_offset_list_enabled_flag {
+        let _diff_cu_chroma_qp_offset_depth = reader.read_ue()?;
+        let chroma_qp_offset_list_len_minus1 = reader.read_ue()?;
+        if chroma_qp_offset_list_len_minus1 > 5 {
+            return Err(HevcError::InvalidParameterSet {
+                kind: "PPS",
+                msg: "chroma_qp_offset_list_len_minus1 out of range".to_string(),
+            });
+        }
+        for _ in 0..=chroma_qp_offset_list_len_minus1 {
+            let _cb_qp_offset_list = reader.read_se()?;
+            let _cr_qp_offset_list = reader.read_se()?;
+        }
+    }
+    let log2_sao_offset_scale_luma = reader.read_ue()?;
+    let log2_sao_offset_scale_chroma = reader.read_ue()?;
+    // Bounded by Max(0, BitDepth - 10) <= 6
+    if log2_sao_offset_scale_luma > 6 || log2_sao_offset_scale_chroma > 6 {
+        return Err(HevcError::InvalidParameterSet {
+            kind: "PPS",
+            msg: "log2_sao_offset_scale out of range".to_string(),
+        });
+    }
+
+    Ok(PpsRangeExtension {
+        log2_max_transform_skip_block_size_minus2: log2_max_transform_skip_block_size_minus2 as u8,
+        cross_component_prediction_enabled_flag,
+        chroma_qp_offset_list_enabled_flag,
+        log2_sao_offset_scale_luma: log2_sao_offset_scale_luma as u8,
+        log2_sao_offset_scale_chroma: log2_sao_offset_scale_chroma as u8,
+    })
+}
+
+/// Parse the SPS extension flags, sps_range_extension() and
+/// sps_multilayer_extension() (H.265 7.3.2.2.1/7.3.2.2.2, F.7.3.2.2.4)
+fn parse_sps_extensions(reader: &mut BitstreamReader<'_>) -> Result<SpsRangeExtension> {
+    let sps_extension_present_flag = reader.read_bit()? != 0;
+    if !sps_extension_present_flag {
+        return Ok(SpsRangeExtension::default());
+    }
+    let sps_range_extension_flag = reader.read_bit()? != 0;
+    let sps_multilayer_extension_flag = reader.read_bit()? != 0;
+    let _sps_3d_extension_flag = reader.read_bit()?;
+    let _sps_scc_extension_flag = reader.read_bit()?;
+    let _sps_extension_4bits = reader.read_bits(4)?;
+
+    let range_extension = if sps_range_extension_flag {
+        SpsRangeExtension {
+            transform_skip_rotation_enabled_flag: reader.read_bit()? != 0,
+            transform_skip_context_enabled_flag: reader.read_bit()? != 0,
+            implicit_rdpcm_enabled_flag: reader.read_bit()? != 0,
+            explicit_rdpcm_enabled_flag: reader.read_bit()? != 0,
+            extended_precision_processing_flag: reader.read_bit()? != 0,
+            intra_smoothing_disabled_flag: reader.read_bit()? != 0,
+            high_precision_offsets_enabled_flag: reader.read_bit()? != 0,
+            persistent_rice_adaptation_enabled_flag: reader.read_bit()? != 0,
+            cabac_bypass_alignment_enabled_flag: reader.read_bit()? != 0,
+        }
+    } else {
+        SpsRangeExtension::default()
+    };
+    // sps_multilayer_extension() (F.7.3.2.2.4)
+    if sps_multilayer_extension_flag {
+        let _inter_view_mv_vert_constraint_flag = reader.read_bit()?;
+    }
+    Ok(range_extension)
+}
+
+/// Skip the VUI fields after the video signal type (H.265 E.2.1)
+fn skip_vui_tail(reader: &mut BitstreamReader<'_>, max_sub_layers_minus1: u8) -> Result<()> {
+    let chroma_loc_info_present_flag = reader.read_bit()? != 0;
+    if chroma_loc_info_present_flag {
+        let _chroma_sample_loc_type_top_field = reader.read_ue()?;
+        let _chroma_sample_loc_type_bottom_field = reader.read_ue()?;
+    }
+    let _neutral_chroma_indication_flag = reader.read_bit()?;
+    let _field_seq_flag = reader.read_bit()?;
+    let _frame_field_info_present_flag = reader.read_bit()?;
+    let default_display_window_flag = reader.read_bit()? != 0;
+    if default_display_window_flag {
+        for _ in 0..4 {
+            let _offset = reader.read_ue()?;
+        }
+    }
+    let vui_timing_info_present_flag = reader.read_bit()? != 0;
+    if vui_timing_info_present_flag {
+        let _vui_num_units_in_tick = reader.read_bits(32)?;
+        let _vui_time_scale = reader.read_bits(32)?;
+        let vui_poc_proportional_to_timing_flag = reader.read_bit()? != 0;
+        if vui_poc_proportional_to_timing_flag {
+            let _vui_num_ticks_poc_diff_one_minus1 = reader.read_ue()?;
+        }
+        let vui_hrd_parameters_present_flag = reader.read_bit()? != 0;
+        if vui_hrd_parameters_present_flag {
+            skip_hrd_parameters(reader, true, max_sub_layers_minus1)?;
+        }
+    }
+    let bitstream_restriction_flag = reader.read_bit()? != 0;
+    if bitstream_restriction_flag {
+        let _tiles_fixed_structure_flag = reader.read_bit()?;
+        let _motion_vectors_over_pic_boundaries_flag = reader.read_bit()?;
+        let _restricted_ref_pic_lists_flag = reader.read_bit()?;
+        let _min_spatial_segmentation_idc = reader.read_ue()?;
+        let _max_bytes_per_pic_denom = reader.read_ue()?;
+        let _max_bits_per_min_cu_denom = reader.read_ue()?;
+        let _log2_max_mv_length_horizontal = reader.read_ue()?;
+        let _log2_max_mv_length_vertical = reader.read_ue()?;
+    }
+    Ok(())
+}
+
+/// Skip hrd_parameters() (H.265 E.2.2)
+fn skip_hrd_parameters(
+    reader: &mut BitstreamReader<'_>,
+    common_inf_present_flag: bool,
+    max_sub_layers_minus1: u8,
+) -> Result<()> {
+    let mut nal_hrd_parameters_present_flag = false;
+    let mut vcl_hrd_parameters_present_flag = false;
+    let mut sub_pic_hrd_params_present_flag = false;
+    if common_inf_present_flag {
+        nal_hrd_parameters_present_flag = reader.read_bit()? != 0;
+        vcl_hrd_parameters_present_flag = reader.read_bit()? != 0;
+        if nal_hrd_parameters_present_flag || vcl_hrd_parameters_present_flag {
+            sub_pic_hrd_params_present_flag = reader.read_bit()? != 0;
+            if sub_pic_hrd_params_present_flag {
+                let _tick_divisor_minus2 = reader.read_bits(8)?;
+                let _du_cpb_removal_delay_increment_length_minus1 = reader.read_bits(5)?;
+                let _sub_pic_cpb_params_in_pic_timing_sei_flag = reader.read_bit()?;
+                let _dpb_output_delay_du_length_minus1 = reader.read_bits(5)?;
+            }
+            let _bit_rate_scale = reader.read_bits(4)?;
+            let _cpb_size_scale = reader.read_bits(4)?;
+            if sub_pic_hrd_params_present_flag {
+                let _cpb_size_du_scale = reader.read_bits(4)?;
+            }
+            let _initial_cpb_removal_delay_length_minus1 = reader.read_bits(5)?;
+            let _au_cpb_removal_delay_length_minus1 = reader.read_bits(5)?;
+            let _dpb_output_delay_length_minus1 = reader.read_bits(5)?;
+        }
+    }
+
+    for _ in 0..=max_sub_layers_minus1 {
+        let fixed_pic_rate_general_flag = reader.read_bit()? != 0;
+        let fixed_pic_rate_within_cvs_flag = if fixed_pic_rate_general_flag {
+            true
+        } else {
+            reader.read_bit()? != 0
+        };
+        let mut low_delay_hrd_flag = false;
+        if fixed_pic_rate_within_cvs_flag {
+            let _elemental_duration_in_tc_minus1 = reader.read_ue()?;
+        } else {
+            low_delay_hrd_flag = reader.read_bit()? != 0;
+        }
+        let cpb_cnt_minus1 = if low_delay_hrd_flag {
+            0
+        } else {
+            reader.read_ue()?
+        };
+        if cpb_cnt_minus1 > 31 {
+            return Err(HevcError::InvalidBitstream("cpb_cnt_minus1 out of range"));
+        }
+        let num_sub_layer_hrd =
+            nal_hrd_parameters_present_flag as u8 + vcl_hrd_parameters_present_flag as u8;
+        for _ in 0..num_sub_layer_hrd {
+            // sub_layer_hrd_parameters() (H.265 E.2.3)
+            for _ in 0..=cpb_cnt_minus1 {
+                let _bit_rate_value_minus1 = reader.read_ue()?;
+                let _cpb_size_value_minus1 = reader.read_ue()?;
+                if sub_pic_hrd_params_present_flag {
+                    let _cpb_size_du_value_minus1 = reader.read_ue()?;
+                    let _bit_rate_du_value_minus1 = reader.read_ue()?;
+                }
+                let _cbr_flag = reader.read_bit()?;
+            }
+        }
+    }
+    Ok(())
+}
+
 fn parse_profile_tier_level(
     reader: &mut BitstreamReader<'_>,
     profile_present: bool,
@@ -882,37 +2138,115 @@ fn parse_scaling_list_data(reader: &mut BitstreamReader<'_>) -> Result<ScalingLi
     Ok(data)
 }
 
-fn skip_short_term_ref_pic_set(
+/// Parse st_ref_pic_set(stRpsIdx) (H.265 7.3.7) and derive its POC deltas (7.4.8)
+///
+/// `sets` holds the sets parsed so far from the SPS; the new set has index
+/// `sets.len()`. Slice headers pass all SPS sets with `in_slice_header` set,
+/// which makes `delta_idx_minus1` present.
+pub fn parse_short_term_ref_pic_set(
     reader: &mut BitstreamReader<'_>,
-    idx: u8,
-    _num_sets: u8,
-) -> Result<()> {
+    sets: &[ShortTermRps],
+    in_slice_header: bool,
+) -> Result<ShortTermRps> {
+    let idx = sets.len();
     let inter_ref_pic_set_prediction_flag = if idx != 0 {
         reader.read_bit()? != 0
     } else {
         false
     };
 
-    if inter_ref_pic_set_prediction_flag {
-        // Simplified - skip inter prediction
-        if idx == _num_sets {
-            let _delta_idx_minus1 = reader.read_ue()?;
-        }
-        let _delta_rps_sign = reader.read_bit()?;
-        let _abs_delta_rps_minus1 = reader.read_ue()?;
-        // Would need previous set info to properly parse
-    } else {
+    if !inter_ref_pic_set_prediction_flag {
         let num_negative_pics = reader.read_ue()?;
         let num_positive_pics = reader.read_ue()?;
+        if num_negative_pics > 16 || num_positive_pics > 16 {
+            return Err(HevcError::InvalidBitstream("too many pictures in RPS"));
+        }
+        let mut rps = ShortTermRps::default();
+        let mut poc = 0i32;
         for _ in 0..num_negative_pics {
-            let _delta_poc_s0_minus1 = reader.read_ue()?;
-            let _used_by_curr_pic_s0_flag = reader.read_bit()?;
+            poc -= reader.read_ue()? as i32 + 1;
+            rps.delta_poc_s0.push(poc);
+            rps.used_by_curr_pic_s0.push(reader.read_bit()? != 0);
         }
+        poc = 0;
         for _ in 0..num_positive_pics {
-            let _delta_poc_s1_minus1 = reader.read_ue()?;
-            let _used_by_curr_pic_s1_flag = reader.read_bit()?;
+            poc += reader.read_ue()? as i32 + 1;
+            rps.delta_poc_s1.push(poc);
+            rps.used_by_curr_pic_s1.push(reader.read_bit()? != 0);
         }
+        return Ok(rps);
     }
 
-    Ok(())
+    let delta_idx_minus1 = if in_slice_header {
+        reader.read_ue()? as usize
+    } else {
+        0
+    };
+    let ref_rps = idx
+        .checked_sub(delta_idx_minus1 + 1)
+        .and_then(|i| sets.get(i))
+        .ok_or(HevcError::InvalidBitstream("RPS delta_idx out of range"))?;
+    let delta_rps_sign = reader.read_bit()? != 0;
+    let abs_delta_rps_minus1 = reader.read_ue()?;
+    if abs_delta_rps_minus1 >= 1 << 15 {
+        return Err(HevcError::InvalidBitstream("abs_delta_rps out of range"));
+    }
+    let delta_rps = if delta_rps_sign { -1 } else { 1 } * (abs_delta_rps_minus1 as i32 + 1);
+
+    // Flags are indexed like the reference set's deltas: S0, then S1, then
+    // the reference picture itself
+    let num_neg = ref_rps.delta_poc_s0.len();
+    let num_delta_pocs = ref_rps.num_delta_pocs();
+    let mut used_by_curr_pic_flag = [false; 33];
+    let mut use_delta_flag = [true; 33];
+    for j in 0..=num_delta_pocs {
+        used_by_curr_pic_flag[j] = reader.read_bit()? != 0;
+        if !used_by_curr_pic_flag[j] {
+            use_delta_flag[j] = reader.read_bit()? != 0;
+        }
+    }
+
+    let mut rps = ShortTermRps::default();
+    for (j, &d) in ref_rps.delta_poc_s1.iter().enumerate().rev() {
+        let d_poc = d + delta_rps;
+        if d_poc < 0 && use_delta_flag[num_neg + j] {
+            rps.delta_poc_s0.push(d_poc);
+            rps.used_by_curr_pic_s0.push(used_by_curr_pic_flag[num_neg + j]);
+        }
+    }
+    if delta_rps < 0 && use_delta_flag[num_delta_pocs] {
+        rps.delta_poc_s0.push(delta_rps);
+        rps.used_by_curr_pic_s0.push(used_by_curr_pic_flag[num_delta_pocs]);
+    }
+    for (j, &d) in ref_rps.delta_poc_s0.iter().enumerate() {
+        let d_poc = d + delta_rps;
+        if d_poc < 0 && use_delta_flag[j] {
+            rps.delta_poc_s0.push(d_poc);
+            rps.used_by_curr_pic_s0.push(used_by_curr_pic_flag[j]);
+        }
+    }
+
+    for (j, &d) in ref_rps.delta_poc_s0.iter().enumerate().rev() {
+        let d_poc = d + delta_rps;
+        if d_poc > 0 && use_delta_flag[j] {
+            rps.delta_poc_s1.push(d_poc);
+            rps.used_by_curr_pic_s1.push(used_by_curr_pic_flag[j]);
+        }
+    }
+    if delta_rps > 0 && use_delta_flag[num_delta_pocs] {
+        rps.delta_poc_s1.push(delta_rps);
+        rps.used_by_curr_pic_s1.push(used_by_curr_pic_flag[num_delta_pocs]);
+    }
+    for (j, &d) in ref_rps.delta_poc_s1.iter().enumerate() {
+        let d_poc = d + delta_rps;
+        if d_poc > 0 && use_delta_flag[num_neg + j] {
+            rps.delta_poc_s1.push(d_poc);
+            rps.used_by_curr_pic_s1.push(used_by_curr_pic_flag[num_neg + j]);
+        }
+    }
+
+    if rps.delta_poc_s0.len() > 16 || rps.delta_poc_s1.len() > 16 {
+        return Err(HevcError::InvalidBitstream("too many pictures in RPS"));
+    }
+    Ok(rps)
 }
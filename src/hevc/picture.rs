@@ -4,6 +4,7 @@ use alloc::vec;
 use alloc::vec::Vec;
 
 use super::color_convert;
+use super::sei::{HdrStaticMetadata, SeiMessage};
 
 /// Sentinel value for uninitialized pixels.
 /// Used during decoding to distinguish decoded samples from uninitialized ones
@@ -14,9 +15,13 @@ pub const UNINIT_SAMPLE: u16 = u16::MAX;
 pub const DEBLOCK_FLAG_VERT: u8 = 1;
 /// Horizontal edge flag
 pub const DEBLOCK_FLAG_HORIZ: u8 = 2;
+/// Vertical prediction block edge flag
+pub const DEBLOCK_FLAG_PU_VERT: u8 = 4;
+/// Horizontal prediction block edge flag
+pub const DEBLOCK_FLAG_PU_HORIZ: u8 = 8;
 
 /// Decoded video frame
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DecodedFrame {
     /// Width in pixels (full frame, before cropping)
     pub width: u32,
@@ -28,7 +33,7 @@ pub struct DecodedFrame {
     pub cb_plane: Vec<u16>,
     /// Cr chroma plane (half resolution for 4:2:0)
     pub cr_plane: Vec<u16>,
-    /// Bit depth
+    /// Bit depth of every plane
     pub bit_depth: u8,
     /// Chroma format (1=4:2:0, 2=4:2:2, 3=4:4:4)
     pub chroma_format: u8,
@@ -41,18 +46,30 @@ pub struct DecodedFrame {
     /// Conformance window bottom offset (in luma samples)
     pub crop_bottom: u32,
     /// Deblocking edge flags at 4x4 block granularity
-    /// Bit 0 = vertical edge, Bit 1 = horizontal edge
+    /// Bit 0 = vertical edge, Bit 1 = horizontal edge (transform blocks),
+    /// bits 2 and 3 likewise for prediction blocks
     pub deblock_flags: Vec<u8>,
     /// Stride for deblock_flags (width / 4)
     pub deblock_stride: u32,
     /// QP map at 4x4 block granularity (for deblocking)
     pub qp_map: Vec<i8>,
-    /// Alpha plane (optional, from auxiliary alpha image)
+    /// Alpha plane (optional, from auxiliary alpha image), one sample per
+    /// cropped pixel at the frame's bit depth
     pub alpha_plane: Option<Vec<u16>>,
     /// Video full range flag (from SPS VUI). true = full \[0,255\], false = limited \[16,235\]
     pub full_range: bool,
     /// Matrix coefficients (from SPS VUI). 1=BT.709, 5/6=BT.601, 9=BT.2020, 2=unspecified
     pub matrix_coeffs: u8,
+    /// SEI messages of the picture's access unit, in bitstream order, then
+    /// the persistent film grain characteristics of an earlier picture when
+    /// the access unit has none
+    pub sei: Vec<SeiMessage>,
+    /// Set when a decoded picture hash check found a mismatch (see
+    /// [`PictureHashCheck::Warn`](crate::PictureHashCheck::Warn))
+    pub picture_hash_mismatch: bool,
+    /// Content light level and mastering display colour volume, from the
+    /// SEI or the image item's properties
+    pub hdr_metadata: HdrStaticMetadata,
 }
 
 impl DecodedFrame {
@@ -90,6 +107,9 @@ impl DecodedFrame {
             alpha_plane: None,
             full_range: false,
             matrix_coeffs: 2,
+            sei: Vec::new(),
+            picture_hash_mismatch: false,
+            hdr_metadata: HdrStaticMetadata::default(),
         }
     }
 
@@ -134,6 +154,9 @@ impl DecodedFrame {
             alpha_plane: None,
             full_range: false,
             matrix_coeffs: 2,
+            sei: Vec::new(),
+            picture_hash_mismatch: false,
+            hdr_metadata: HdrStaticMetadata::default(),
         }
     }
 
@@ -164,6 +187,25 @@ impl DecodedFrame {
         }
     }
 
+    /// Mark the left and top edges of a w x h prediction block at luma position (x, y)
+    pub fn mark_pu_boundary(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        let bx = x / 4;
+        let by = y / 4;
+        let rows = self.deblock_flags.len() as u32 / self.deblock_stride.max(1);
+
+        if x > 0 && bx < self.deblock_stride {
+            for j in by..(by + h / 4).min(rows) {
+                self.deblock_flags[(j * self.deblock_stride + bx) as usize] |= DEBLOCK_FLAG_PU_VERT;
+            }
+        }
+
+        if y > 0 && by < rows {
+            for i in bx..(bx + w / 4).min(self.deblock_stride) {
+                self.deblock_flags[(by * self.deblock_stride + i) as usize] |= DEBLOCK_FLAG_PU_HORIZ;
+            }
+        }
+    }
+
     /// Store QP for a block region at 4x4 granularity
     pub fn store_block_qp(&mut self, x: u32, y: u32, size: u32, qp: i8) {
         let bx = x / 4;
@@ -197,6 +239,45 @@ impl DecodedFrame {
         self.height - self.crop_top - self.crop_bottom
     }
 
+    /// Check the planes against the picture's decoded picture hash SEI
+    ///
+    /// Returns `None` when the picture has no hash. Frames made by rotating,
+    /// mirroring or copying rows drop the hash.
+    pub fn verify_picture_hash(&self) -> Option<bool> {
+        self.sei.iter().find_map(|message| match message {
+            SeiMessage::DecodedPictureHash(hash) => Some(super::hash::matches(self, hash)),
+            _ => None,
+        })
+    }
+
+    /// Add film grain described by the picture's film grain characteristics SEI
+    ///
+    /// The same seed always gives the same grain. Returns `false`, leaving
+    /// the planes untouched, when the picture has no film grain SEI or the
+    /// SEI cancels grain.
+    pub fn apply_film_grain(&mut self, seed: u32) -> bool {
+        let grain = self.sei.iter().rev().find_map(|message| match message {
+            SeiMessage::FilmGrainCharacteristics(grain) => Some(grain.clone()),
+            _ => None,
+        });
+        match grain.flatten() {
+            Some(grain) => {
+                super::film_grain::apply(self, &grain, seed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// SEI messages for a frame with rearranged samples: all but the hash
+    fn transformed_sei(&self) -> Vec<SeiMessage> {
+        self.sei
+            .iter()
+            .filter(|message| !matches!(message, SeiMessage::DecodedPictureHash(_)))
+            .cloned()
+            .collect()
+    }
+
     /// Get luma stride (width)
     pub fn y_stride(&self) -> usize {
         self.width as usize
@@ -598,6 +679,134 @@ impl DecodedFrame {
         rgba
     }
 
+    /// Convert a single YCbCr pixel to 16-bit RGB.
+    /// Inputs are samples scaled to 16 bits (chroma centered on 32768).
+    ///
+    /// Same matrices as [`Self::ycbcr_to_rgb`], ×8192 fixed-point for both ranges.
+    #[inline(always)]
+    fn ycbcr_to_rgb16(&self, y_val: i32, cb_val: i32, cr_val: i32) -> (u16, u16, u16) {
+        let cb = cb_val - 32768;
+        let cr = cr_val - 32768;
+
+        let (yv, (cr_r, cb_g, cr_g, cb_b)) = if self.full_range {
+            let coeffs = match self.matrix_coeffs {
+                1 => (12901, -1535, -3835, 15201), // BT.709
+                9 => (12080, -1348, -4681, 15412), // BT.2020
+                _ => (11485, -2819, -5850, 14516), // BT.601 (default/unspecified)
+            };
+            (y_val << 13, coeffs)
+        } else {
+            // Limited range: black at 16 << 8, same combined factors as 8-bit
+            let coeffs = match self.matrix_coeffs {
+                1 => (14744, -1754, -4383, 17373), // BT.709
+                9 => (13806, -1541, -5349, 17615), // BT.2020
+                _ => (13126, -3222, -6686, 16591), // BT.601 (default/unspecified)
+            };
+            ((y_val - 4096) * 9576, coeffs)
+        };
+        let r = (yv + cr_r * cr + 4096) >> 13;
+        let g = (yv + cb_g * cb + cr_g * cr + 4096) >> 13;
+        let b = (yv + cb_b * cb + 4096) >> 13;
+        (
+            r.clamp(0, 65535) as u16,
+            g.clamp(0, 65535) as u16,
+            b.clamp(0, 65535) as u16,
+        )
+    }
+
+    /// Scale a sample at the frame bit depth to the full 16-bit range
+    #[inline(always)]
+    fn scale_to_16(&self, v: u16) -> u16 {
+        // Replicate the high bits into the low ones so the maximum maps to 65535
+        let v = v as u32;
+        let up = 16 - self.bit_depth as u32;
+        ((v << up) | (v >> (self.bit_depth as u32).saturating_sub(up))) as u16
+    }
+
+    /// Visit every pixel of the cropped frame as 16-bit (r, g, b, alpha),
+    /// each scaled to the full 0-65535 range.
+    fn for_each_rgba16(&self, mut f: impl FnMut(u16, u16, u16, u16)) {
+        let up = 16 - self.bit_depth;
+
+        let y_start = self.crop_top;
+        let y_end = self.height - self.crop_bottom;
+        let x_start = self.crop_left;
+        let x_end = self.width - self.crop_right;
+
+        let mut pixel_idx = 0usize;
+        for y in y_start..y_end {
+            for x in x_start..x_end {
+                let y_idx = (y * self.width + x) as usize;
+                let y_val = (self.y_plane[y_idx] as i32) << up;
+                let (cb_val, cr_val) = if self.chroma_format == 0 {
+                    (32768, 32768)
+                } else {
+                    let (cb, cr) = self.get_chroma(x, y, 0);
+                    (cb << up, cr << up)
+                };
+                let (r, g, b) = self.ycbcr_to_rgb16(y_val, cb_val, cr_val);
+                let alpha = match self.alpha_plane {
+                    Some(ref alpha) if pixel_idx < alpha.len() => {
+                        self.scale_to_16(alpha[pixel_idx])
+                    }
+                    _ => u16::MAX,
+                };
+                f(r, g, b, alpha);
+                pixel_idx += 1;
+            }
+        }
+    }
+
+    /// Convert YCbCr to 16-bit RGB with conformance window cropping.
+    /// Samples are scaled to the full 0-65535 range regardless of bit depth.
+    pub fn to_rgb16(&self) -> Vec<u16> {
+        let mut rgb =
+            Vec::with_capacity((self.cropped_width() * self.cropped_height() * 3) as usize);
+        self.for_each_rgba16(|r, g, b, _| rgb.extend_from_slice(&[r, g, b]));
+        rgb
+    }
+
+    /// Convert YCbCr to 16-bit RGBA with conformance window cropping.
+    /// Uses real alpha values from `alpha_plane` if present, otherwise alpha=65535.
+    pub fn to_rgba16(&self) -> Vec<u16> {
+        let mut rgba =
+            Vec::with_capacity((self.cropped_width() * self.cropped_height() * 4) as usize);
+        self.for_each_rgba16(|r, g, b, a| rgba.extend_from_slice(&[r, g, b, a]));
+        rgba
+    }
+
+    /// Write pixels into a pre-allocated buffer as 16-bit RGB
+    /// (native-endian `u16` per channel, full 0-65535 range).
+    /// Returns the number of bytes written.
+    pub fn write_rgb16_into(&self, output: &mut [u8]) -> usize {
+        let mut chunks = output.chunks_exact_mut(6);
+        self.for_each_rgba16(|r, g, b, _| {
+            if let Some(px) = chunks.next() {
+                px[0..2].copy_from_slice(&r.to_ne_bytes());
+                px[2..4].copy_from_slice(&g.to_ne_bytes());
+                px[4..6].copy_from_slice(&b.to_ne_bytes());
+            }
+        });
+        (self.cropped_width() * self.cropped_height() * 6) as usize
+    }
+
+    /// Write pixels into a pre-allocated buffer as 16-bit RGBA
+    /// (native-endian `u16` per channel, full 0-65535 range).
+    /// Uses real alpha values from `alpha_plane` if present, otherwise alpha=65535.
+    /// Returns the number of bytes written.
+    pub fn write_rgba16_into(&self, output: &mut [u8]) -> usize {
+        let mut chunks = output.chunks_exact_mut(8);
+        self.for_each_rgba16(|r, g, b, a| {
+            if let Some(px) = chunks.next() {
+                px[0..2].copy_from_slice(&r.to_ne_bytes());
+                px[2..4].copy_from_slice(&g.to_ne_bytes());
+                px[4..6].copy_from_slice(&b.to_ne_bytes());
+                px[6..8].copy_from_slice(&a.to_ne_bytes());
+            }
+        });
+        (self.cropped_width() * self.cropped_height() * 8) as usize
+    }
+
     /// Get chroma values for a pixel position
     fn get_chroma(&self, x: u32, y: u32, shift: u8) -> (i32, i32) {
         match self.chroma_format {
@@ -758,7 +967,7 @@ impl DecodedFrame {
     }
 
     /// Get chroma plane dimensions (width, height)
-    fn chroma_dims(&self) -> (u32, u32) {
+    pub(crate) fn chroma_dims(&self) -> (u32, u32) {
         match self.chroma_format {
             0 => (0, 0),
             1 => (self.width.div_ceil(2), self.height.div_ceil(2)),
@@ -768,6 +977,48 @@ impl DecodedFrame {
         }
     }
 
+    /// Copy rows `y_start..y_end` into a frame of their own
+    ///
+    /// The copy starts at the luma row of the chroma row containing `y_start`
+    /// and crops the rows above `y_start` off; the horizontal crop is kept.
+    /// The alpha plane and deblocking metadata are not copied.
+    pub fn copy_rows(&self, y_start: u32, y_end: u32) -> Self {
+        let y_end = y_end.min(self.height);
+        let sub_y = if self.chroma_format == 1 { 2 } else { 1 };
+        let first = (y_start - y_start % sub_y).min(y_end);
+        let w = self.width as usize;
+        let c_stride = self.c_stride();
+        let (c_start, c_end) = if self.chroma_format == 0 {
+            (0, 0)
+        } else {
+            let c_rows = self.chroma_dims().1;
+            ((first / sub_y) as usize, y_end.div_ceil(sub_y).min(c_rows) as usize)
+        };
+
+        Self {
+            width: self.width,
+            height: y_end - first,
+            y_plane: self.y_plane[first as usize * w..y_end as usize * w].to_vec(),
+            cb_plane: self.cb_plane[c_start * c_stride..c_end * c_stride].to_vec(),
+            cr_plane: self.cr_plane[c_start * c_stride..c_end * c_stride].to_vec(),
+            bit_depth: self.bit_depth,
+            chroma_format: self.chroma_format,
+            crop_left: self.crop_left,
+            crop_right: self.crop_right,
+            crop_top: y_start.min(y_end) - first,
+            crop_bottom: 0,
+            deblock_flags: Vec::new(),
+            deblock_stride: 0,
+            qp_map: Vec::new(),
+            alpha_plane: None,
+            full_range: self.full_range,
+            matrix_coeffs: self.matrix_coeffs,
+            sei: self.transformed_sei(),
+            picture_hash_mismatch: self.picture_hash_mismatch,
+            hdr_metadata: self.hdr_metadata,
+        }
+    }
+
     /// Rotate the frame 90° clockwise, returning a new frame
     pub fn rotate_90_cw(&self) -> Self {
         let ow = self.width;
@@ -785,6 +1036,9 @@ impl DecodedFrame {
 
         // Rotate alpha plane (same transform as luma)
         let alpha_plane = self.alpha_plane.as_ref().map(|alpha| {
+            // The alpha plane covers the cropped area only
+            let (ow, oh) = (self.cropped_width(), self.cropped_height());
+            let (nw, nh) = (oh, ow);
             let mut rotated = vec![0u16; (nw * nh) as usize];
             for dy in 0..nh {
                 for dx in 0..nw {
@@ -831,6 +1085,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         } else {
             Self {
@@ -851,6 +1108,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         }
     }
@@ -871,6 +1131,8 @@ impl DecodedFrame {
 
         // Rotate alpha plane
         let alpha_plane = self.alpha_plane.as_ref().map(|alpha| {
+            // The alpha plane covers the cropped area only
+            let (w, h) = (self.cropped_width(), self.cropped_height());
             let mut rotated = vec![0u16; (w * h) as usize];
             for dy in 0..h {
                 for dx in 0..w {
@@ -916,6 +1178,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         } else {
             Self {
@@ -936,6 +1201,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         }
     }
@@ -957,6 +1225,9 @@ impl DecodedFrame {
 
         // Rotate alpha plane
         let alpha_plane = self.alpha_plane.as_ref().map(|alpha| {
+            // The alpha plane covers the cropped area only
+            let (ow, oh) = (self.cropped_width(), self.cropped_height());
+            let (nw, nh) = (oh, ow);
             let mut rotated = vec![0u16; (nw * nh) as usize];
             for dy in 0..nh {
                 for dx in 0..nw {
@@ -1003,6 +1274,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         } else {
             Self {
@@ -1023,6 +1297,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         }
     }
@@ -1040,6 +1317,8 @@ impl DecodedFrame {
         }
 
         let alpha_plane = self.alpha_plane.as_ref().map(|alpha| {
+            // The alpha plane covers the cropped area only
+            let (w, h) = (self.cropped_width(), self.cropped_height());
             let mut mirrored = vec![0u16; (w * h) as usize];
             for dy in 0..h {
                 for dx in 0..w {
@@ -1082,6 +1361,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         } else {
             Self {
@@ -1102,6 +1384,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         }
     }
@@ -1119,6 +1404,8 @@ impl DecodedFrame {
         }
 
         let alpha_plane = self.alpha_plane.as_ref().map(|alpha| {
+            // The alpha plane covers the cropped area only
+            let (w, h) = (self.cropped_width(), self.cropped_height());
             let mut mirrored = vec![0u16; (w * h) as usize];
             for dy in 0..h {
                 for dx in 0..w {
@@ -1161,6 +1448,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         } else {
             Self {
@@ -1181,6 +1471,9 @@ impl DecodedFrame {
                 alpha_plane,
                 full_range: self.full_range,
                 matrix_coeffs: self.matrix_coeffs,
+                sei: self.transformed_sei(),
+                picture_hash_mismatch: self.picture_hash_mismatch,
+                hdr_metadata: self.hdr_metadata,
             }
         }
     }
@@ -0,0 +1,370 @@
+//! Inter-layer reference pictures (H.265 H.8.1.4)
+//!
+//! A picture of a reference layer becomes a reference picture of the
+//! current layer after resampling it to the current picture size with the
+//! 16-phase resampling filters, and mapping its motion field onto the
+//! current picture's 16x16 grid for temporal motion vector prediction. The
+//! scaled reference region in the current picture, the reference region in
+//! the reference layer picture and the filter phases come from the
+//! reference layer location offsets of the current PPS. Equally sized
+//! layers at zero offsets and phases come out unchanged.
+
+use alloc::vec::Vec;
+
+use super::motion::{BLOCK_DECODED, BLOCK_INTRA, BlockInfo, MotionField, Mv};
+use super::params::{Pps, Sps};
+use super::picture::DecodedFrame;
+use crate::error::HevcError;
+
+type Result<T> = core::result::Result<T, HevcError>;
+
+/// Luma resampling filter coefficients per 1/16 sample phase (Table H.1)
+pub(super) const LUMA_FILTER: [[i32; 8]; 16] = [
+    [0, 0, 0, 64, 0, 0, 0, 0],
+    [0, 1, -3, 63, 4, -2, 1, 0],
+    [-1, 2, -5, 62, 8, -3, 1, 0],
+    [-1, 3, -8, 60, 13, -4, 1, 0],
+    [-1, 4, -10, 58, 17, -5, 1, 0],
+    [-1, 4, -11, 52, 26, -8, 3, -1],
+    [-1, 3, -9, 47, 31, -10, 4, -1],
+    [-1, 4, -11, 45, 34, -10, 4, -1],
+    [-1, 4, -11, 40, 40, -11, 4, -1],
+    [-1, 4, -10, 34, 45, -11, 4, -1],
+    [-1, 4, -10, 31, 47, -9, 3, -1],
+    [-1, 3, -8, 26, 52, -11, 4, -1],
+    [0, 1, -5, 17, 58, -10, 4, -1],
+    [0, 1, -4, 13, 60, -8, 3, -1],
+    [0, 1, -3, 8, 62, -5, 2, -1],
+    [0, 1, -2, 4, 63, -3, 1, 0],
+];
+
+/// Chroma resampling filter coefficients per 1/16 sample phase (Table H.2)
+const CHROMA_FILTER: [[i32; 4]; 16] = [
+    [0, 64, 0, 0],
+    [-2, 62, 4, 0],
+    [-2, 58, 10, -2],
+    [-4, 56, 14, -2],
+    [-4, 54, 16, -2],
+    [-6, 52, 20, -2],
+    [-6, 46, 28, -4],
+    [-4, 42, 30, -4],
+    [-4, 36, 36, -4],
+    [-4, 30, 42, -4],
+    [-4, 28, 46, -6],
+    [-2, 20, 52, -6],
+    [-2, 16, 54, -4],
+    [-2, 14, 56, -4],
+    [-2, 10, 58, -2],
+    [0, 4, 62, -2],
+];
+
+/// Inter-layer reference picture and motion field for the current picture,
+/// from the decoded picture of reference layer `ref_layer_id`
+pub fn inter_layer_picture(
+    sps: &Sps,
+    pps: &Pps,
+    ref_layer_id: u8,
+    reference: &DecodedFrame,
+    motion: &MotionField,
+) -> Result<(DecodedFrame, MotionField)> {
+    if reference.chroma_format != sps.chroma_format_idc {
+        return Err(HevcError::Unsupported(
+            "inter-layer prediction across chroma formats",
+        ));
+    }
+    let geometry = Geometry::new(sps, pps, ref_layer_id, reference)?;
+    let (width, height) = (
+        sps.pic_width_in_luma_samples,
+        sps.pic_height_in_luma_samples,
+    );
+    let mut frame =
+        DecodedFrame::with_params(width, height, sps.bit_depth_y(), sps.chroma_format_idc);
+
+    let (sub_x, sub_y) = chroma_subsampling(sps.chroma_format_idc);
+    let bit_depths = (reference.bit_depth, frame.bit_depth);
+    for c_idx in 0..if sps.chroma_format_idc == 0 { 1 } else { 3 } {
+        let (subsampling, taps) = if c_idx == 0 {
+            ((1, 1), LUMA_FILTER.as_flattened())
+        } else {
+            ((sub_x, sub_y), CHROMA_FILTER.as_flattened())
+        };
+        let (samples, stride) = reference.plane(c_idx);
+        let src = Plane {
+            samples,
+            stride,
+            width: stride as i32,
+            height: (samples.len() / stride) as i32,
+        };
+        let (dst, dst_stride) = frame.plane_mut(c_idx);
+        let columns: Vec<i32> = (0..dst_stride as i32)
+            .map(|x| geometry.ref_position(0, c_idx, x, subsampling.0))
+            .collect();
+        let rows: Vec<i32> = (0..(dst.len() / dst_stride) as i32)
+            .map(|y| geometry.ref_position(1, c_idx, y, subsampling.1))
+            .collect();
+        resample_plane(&src, dst, dst_stride, &columns, &rows, taps, bit_depths);
+    }
+
+    let motion = geometry.map_motion(motion, reference, width, height);
+    Ok((frame, motion))
+}
+
+/// SubWidthC and SubHeightC of a chroma format
+fn chroma_subsampling(chroma_format: u8) -> (u32, u32) {
+    match chroma_format {
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    }
+}
+
+/// A colour plane of the reference layer picture
+struct Plane<'a> {
+    samples: &'a [u16],
+    stride: usize,
+    width: i32,
+    height: i32,
+}
+
+impl Plane<'_> {
+    /// Sample with the location clipped to the plane
+    fn sample(&self, x: i32, y: i32) -> i32 {
+        let x = x.clamp(0, self.width - 1) as usize;
+        let y = y.clamp(0, self.height - 1) as usize;
+        i32::from(self.samples[y * self.stride + x])
+    }
+}
+
+/// Resample a plane separably (H.8.1.4.1)
+///
+/// `columns` and `rows` hold the reference locations in 1/16 samples of the
+/// destination columns and rows; `taps` holds the 16 phases of a 4- or
+/// 8-tap filter.
+fn resample_plane(
+    src: &Plane<'_>,
+    dst: &mut [u16],
+    dst_stride: usize,
+    columns: &[i32],
+    rows: &[i32],
+    taps: &[i32],
+    (src_bit_depth, bit_depth): (u8, u8),
+) {
+    let num_taps = taps.len() / 16;
+    let first_tap = num_taps as i32 / 2 - 1;
+    let shift1 = (src_bit_depth - 8).min(4);
+    let shift2 = 6 + (14 - bit_depth as i32).max(2);
+    let max = (1i32 << bit_depth) - 1;
+
+    // Horizontally filtered reference rows, each computed once; the rows
+    // the vertical filter reaches, clipped to the plane
+    let row_min = rows.iter().min().map_or(0, |&y| (y >> 4) - first_tap);
+    let row_max = rows
+        .iter()
+        .max()
+        .map_or(0, |&y| (y >> 4) - first_tap + num_taps as i32);
+    let row_min = row_min.clamp(0, src.height - 1);
+    let row_max = row_max.clamp(row_min + 1, src.height);
+    let mut temp = alloc::vec![0i32; (row_max - row_min) as usize * dst_stride];
+    for (ref_y, temp_row) in (row_min..row_max).zip(temp.chunks_exact_mut(dst_stride)) {
+        for (out, &x16) in temp_row.iter_mut().zip(columns) {
+            let filter = &taps[(x16 & 15) as usize * num_taps..][..num_taps];
+            let x = (x16 >> 4) - first_tap;
+            let sum: i32 = (0..num_taps as i32)
+                .zip(filter)
+                .map(|(i, &f)| f * src.sample(x + i, ref_y))
+                .sum();
+            *out = sum >> shift1;
+        }
+    }
+
+    for (dst_row, &y16) in dst.chunks_exact_mut(dst_stride).zip(rows) {
+        let filter = &taps[(y16 & 15) as usize * num_taps..][..num_taps];
+        let y = (y16 >> 4) - first_tap;
+        for (x, out) in dst_row.iter_mut().enumerate() {
+            let sum: i32 = (0..num_taps as i32)
+                .zip(filter)
+                .map(|(i, &f)| {
+                    let ref_y = (y + i).clamp(0, src.height - 1);
+                    f * temp[(ref_y - row_min) as usize * dst_stride + x]
+                })
+                .sum();
+            *out = ((sum + (1 << (shift2 - 1))) >> shift2).clamp(0, max) as u16;
+        }
+    }
+}
+
+/// Position of the current picture relative to a reference layer picture
+struct Geometry {
+    /// ScaledRefLayerLeftOffset and ScaledRefLayerTopOffset in luma samples
+    scaled_offset: [i32; 2],
+    /// RefLayerRegionLeftOffset and RefLayerRegionTopOffset in reference
+    /// layer luma samples
+    region_offset: [i32; 2],
+    /// Chroma subsampling of the reference layer picture
+    region_subsampling: [i32; 2],
+    /// SpatialScaleFactorHorY and SpatialScaleFactorVerY in 1/65536 units
+    scale: [i64; 2],
+    /// Motion vector scale factors in 1/256 units
+    mv_scale: [i32; 2],
+    /// Horizontal and vertical luma phases, then chroma phases, in 1/16
+    /// samples
+    phase: [[i32; 2]; 2],
+}
+
+impl Geometry {
+    /// Derive the reference regions and phases from the PPS reference layer
+    /// location offsets
+    fn new(sps: &Sps, pps: &Pps, ref_layer_id: u8, reference: &DecodedFrame) -> Result<Self> {
+        let offsets = pps
+            .multilayer_extension
+            .ref_loc_offsets
+            .iter()
+            .find(|o| o.ref_loc_offset_layer_id == ref_layer_id);
+        // Offsets are signalled in chroma sample units
+        let (sub_x, sub_y) = chroma_subsampling(sps.chroma_format_idc);
+        let (ref_sub_x, ref_sub_y) = chroma_subsampling(reference.chroma_format);
+        let [left, top, right, bottom] = offsets
+            .and_then(|o| o.scaled_ref_layer_offsets)
+            .unwrap_or_default();
+        let scaled = [left * sub_x as i32, top * sub_y as i32];
+        let scaled_size = [
+            sps.pic_width_in_luma_samples as i32 - scaled[0] - right * sub_x as i32,
+            sps.pic_height_in_luma_samples as i32 - scaled[1] - bottom * sub_y as i32,
+        ];
+        let [left, top, right, bottom] = offsets
+            .and_then(|o| o.ref_region_offsets)
+            .unwrap_or_default();
+        let region = [left * ref_sub_x as i32, top * ref_sub_y as i32];
+        let region_size = [
+            reference.width as i32 - region[0] - right * ref_sub_x as i32,
+            reference.height as i32 - region[1] - bottom * ref_sub_y as i32,
+        ];
+        if scaled_size
+            .iter()
+            .chain(&region_size)
+            .any(|&size| size <= 0)
+        {
+            return Err(HevcError::InvalidBitstream(
+                "empty inter-layer reference region",
+            ));
+        }
+
+        let scale = [0, 1].map(|i| {
+            let (region, scaled) = (i64::from(region_size[i]), i64::from(scaled_size[i]));
+            ((region << 16) + (scaled >> 1)) / scaled
+        });
+        let mv_scale = [0, 1].map(|i| {
+            let (region, scaled) = (region_size[i], scaled_size[i]);
+            (((scaled << 8) + (region >> 1)) / region).clamp(-4096, 4095)
+        });
+        let phase = match offsets.and_then(|o| o.resample_phases) {
+            Some([hor_luma, ver_luma, hor_chroma_plus8, ver_chroma_plus8]) => [
+                [hor_luma as i32, ver_luma as i32],
+                [hor_chroma_plus8 as i32 - 8, ver_chroma_plus8 as i32 - 8],
+            ],
+            // Chroma sited between luma rows in both layers
+            None => {
+                let (region, scaled) = (region_size[1], scaled_size[1]);
+                let ver_chroma_plus8 = (4 * scaled + (region >> 1)) / region + 4;
+                [[0, 0], [0, ver_chroma_plus8 - 8]]
+            }
+        };
+        Ok(Self {
+            scaled_offset: scaled,
+            region_offset: region,
+            region_subsampling: [ref_sub_x as i32, ref_sub_y as i32],
+            scale,
+            mv_scale,
+            phase,
+        })
+    }
+
+    /// Reference layer location in 1/16 samples of a column (`axis` 0) or
+    /// row (`axis` 1) of a colour plane of the current picture (H.6.2)
+    fn ref_position(&self, axis: usize, c_idx: u8, pos: i32, subsampling: u32) -> i32 {
+        let (chroma, sub) = (c_idx != 0, subsampling as i32);
+        let offset = self.scaled_offset[axis] / sub;
+        let region_offset = if chroma {
+            self.region_offset[axis] / self.region_subsampling[axis]
+        } else {
+            self.region_offset[axis]
+        };
+        let phase = i64::from(self.phase[usize::from(chroma)][axis]);
+        let scale = self.scale[axis];
+        let add = (scale * phase + 8) >> 4;
+        let pos16 = (i64::from(pos - offset) * scale - add + (1 << 11)) >> 12;
+        pos16 as i32 + (region_offset << 4)
+    }
+
+    /// Motion field of the reference layer picture mapped onto the 16x16
+    /// blocks of the current picture (H.8.1.4.2)
+    fn map_motion(
+        &self,
+        motion: &MotionField,
+        reference: &DecodedFrame,
+        width: u32,
+        height: u32,
+    ) -> MotionField {
+        let mut mapped = MotionField::new(width, height);
+        mapped.slices = motion.slices.clone();
+        let intra = BlockInfo {
+            flags: BLOCK_DECODED | BLOCK_INTRA,
+            ..BlockInfo::default()
+        };
+        let ref_location = |axis: usize, pos: u32| {
+            let pos = i64::from(pos as i32 + 8 - self.scaled_offset[axis]);
+            let ref_pos = ((pos * self.scale[axis] + (1 << 15)) >> 16) as i32;
+            ((ref_pos + self.region_offset[axis] + 4) >> 4) << 4
+        };
+        for y in (0..height).step_by(16) {
+            let y_ref = ref_location(1, y);
+            for x in (0..width).step_by(16) {
+                let x_ref = ref_location(0, x);
+                let inside = (0..reference.width as i32).contains(&x_ref)
+                    && (0..reference.height as i32).contains(&y_ref);
+                let block = inside.then(|| *motion.block(x_ref as u32, y_ref as u32));
+                let info = match block {
+                    Some(block)
+                        if block.flags & BLOCK_DECODED != 0 && block.flags & BLOCK_INTRA == 0 =>
+                    {
+                        let mut info = block;
+                        for mv in &mut info.motion.mv {
+                            *mv = Mv {
+                                x: scale_mv_component(mv.x, self.mv_scale[0]),
+                                y: scale_mv_component(mv.y, self.mv_scale[1]),
+                            };
+                        }
+                        info.flags &= BLOCK_DECODED;
+                        info
+                    }
+                    _ => intra,
+                };
+                mapped.set(x, y, 16, 16, info);
+            }
+        }
+        mapped
+    }
+}
+
+/// Scale a motion vector component by a factor in 1/256 units
+fn scale_mv_component(mv: i16, scale: i32) -> i16 {
+    let product = i32::from(mv) * scale;
+    let scaled = product.signum() * ((product.abs() + 127) >> 8);
+    scaled.clamp(-32768, 32767) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_sum_to_64() {
+        for filter in LUMA_FILTER
+            .iter()
+            .map(|f| &f[..])
+            .chain(CHROMA_FILTER.iter().map(|f| &f[..]))
+        {
+            assert_eq!(filter.iter().sum::<i32>(), 64, "{filter:?}");
+        }
+    }
+}
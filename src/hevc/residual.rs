@@ -133,7 +133,7 @@ pub fn get_scan_4x4(order: ScanOrder) -> &'static [(u8, u8); 16] {
 #[derive(Clone)]
 pub struct CoeffBuffer {
     /// Coefficients for this TU
-    pub coeffs: [i16; MAX_COEFF],
+    pub coeffs: [i32; MAX_COEFF],
     /// Transform size (log2)
     pub log2_size: u8,
     /// Number of non-zero coefficients
@@ -170,14 +170,14 @@ impl CoeffBuffer {
     /// Get coefficient at position
     #[allow(dead_code)]
     #[inline]
-    pub fn get(&self, x: usize, y: usize) -> i16 {
+    pub fn get(&self, x: usize, y: usize) -> i32 {
         let stride = self.size();
         self.coeffs[y * stride + x]
     }
 
     /// Set coefficient at position
     #[inline]
-    pub fn set(&mut self, x: usize, y: usize, value: i16) {
+    pub fn set(&mut self, x: usize, y: usize, value: i32) {
         let stride = self.size();
         self.coeffs[y * stride + x] = value;
         if value != 0 {
@@ -206,6 +206,9 @@ pub fn decode_residual(
     sign_data_hiding_enabled: bool,
     cu_transquant_bypass: bool,
     transform_skip_enabled: bool,
+    log2_max_transform_skip_size: u8,
+    log2_transform_range: u8,
+    extended_precision: bool,
     _x0: u32,
     _y0: u32,
 ) -> Result<(CoeffBuffer, bool)> {
@@ -236,12 +239,18 @@ pub fn decode_residual(
 
     let mut buffer = CoeffBuffer::new(log2_size);
     let size = 1u32 << log2_size;
+    let coeff_max = (1i32 << log2_transform_range) - 1;
+    let coeff_min = -(1i32 << log2_transform_range);
+    let limited_range = extended_precision.then_some(log2_transform_range);
 
     // Decode transform_skip_flag (H.265 7.3.8.11)
     // Per spec: if transform_skip_enabled_flag && !cu_transquant_bypass_flag
     //           && log2TrafoSize <= Log2MaxTransformSkipSize
-    // Log2MaxTransformSkipSize defaults to 2 (4x4 blocks only)
-    let transform_skip = if transform_skip_enabled && !cu_transquant_bypass && log2_size <= 2 {
+    // Log2MaxTransformSkipSize is 2 (4x4 blocks only) unless raised by the PPS range extension
+    let transform_skip = if transform_skip_enabled
+        && !cu_transquant_bypass
+        && log2_size <= log2_max_transform_skip_size
+    {
         let ctx_idx = context::TRANSFORM_SKIP_FLAG + if c_idx > 0 { 1 } else { 0 };
         let flag = cabac.decode_bin(&mut ctx[ctx_idx])? != 0;
         if rc_trace {
@@ -383,7 +392,7 @@ pub fn decode_residual(
             15
         };
 
-        let mut coeff_values = [0i16; 16];
+        let mut coeff_values = [0i32; 16];
         let mut coeff_flags = [false; 16];
         let mut num_coeffs = 0u8;
         let mut can_infer_dc = infer_sb_dc_sig;
@@ -675,7 +684,7 @@ pub fn decode_residual(
             if coeff_flags[n as usize] && needs_remaining[n as usize] {
                 let base = coeff_values[n as usize];
                 let (remaining, new_rice) =
-                    decode_coeff_abs_level_remaining(cabac, rice_param, base)?;
+                    decode_coeff_abs_level_remaining(cabac, rice_param, base, limited_range)?;
                 if rc_trace {
                     let (range, _, _) = cabac.get_state_extended();
                     let (byte_pos, _, _) = cabac.get_position();
@@ -691,7 +700,7 @@ pub fn decode_residual(
                     );
                 }
                 rice_param = new_rice;
-                coeff_values[n as usize] = base + remaining;
+                coeff_values[n as usize] = base.saturating_add(remaining);
             }
         }
 
@@ -704,7 +713,7 @@ pub fn decode_residual(
             if coeff_signs[i] != 0 {
                 coeff_values[pos] = -coeff_values[pos];
             }
-            sum_abs_level += coeff_values[pos] as i32;
+            sum_abs_level = sum_abs_level.wrapping_add(coeff_values[pos]);
 
             // Infer hidden sign at the last coefficient (first in scan order)
             // Per H.265: if sum of signed coefficients is odd, flip the hidden sign
@@ -719,7 +728,8 @@ pub fn decode_residual(
                 let x = sb_x as usize * 4 + px as usize;
                 let y = sb_y as usize * 4 + py as usize;
 
-                buffer.set(x, y, coeff_values[n]);
+                // TransCoeffLevel is bounded by CoeffMin..=CoeffMax (H.265 7.4.9.11)
+                buffer.set(x, y, coeff_values[n].clamp(coeff_min, coeff_max));
 
                 // Track large coefficients (indicates CABAC desync)
                 if coeff_values[n].abs() > 500 {
@@ -744,7 +754,7 @@ pub fn decode_residual(
 /// Get sub-block scan order
 /// For TU of size 2^log2_size, sub-blocks are arranged in a grid of size 2^(log2_size-2)
 /// This function returns the diagonal scan order for sub-blocks
-fn get_scan_sub_block(log2_size: u8, order: ScanOrder) -> &'static [(u8, u8)] {
+pub(super) fn get_scan_sub_block(log2_size: u8, order: ScanOrder) -> &'static [(u8, u8)] {
     // Sub-block scan tables
     // Note: The order here must match how coefficients are accessed in the decoder
     static SCAN_1X1: [(u8, u8); 1] = [(0, 0)];
@@ -985,7 +995,7 @@ static CTX_IDX_MAP_4X4: [u8; 16] = [0, 1, 4, 5, 2, 3, 4, 5, 6, 6, 8, 8, 7, 7, 8,
 /// - c_idx: component (0=Y, 1=Cb, 2=Cr)
 /// - scan_idx: scan order (0=diagonal, 1=horizontal, 2=vertical)
 /// - prev_csbf: coded_sub_block_flag of neighbors (bit0=right, bit1=below per H.265/libde265)
-fn calc_sig_coeff_flag_ctx(
+pub(super) fn calc_sig_coeff_flag_ctx(
     x_c: u8,
     y_c: u8,
     log2_size: u8,
@@ -1135,14 +1145,22 @@ fn decode_coeff_greater2_flag(
 
 /// Decode coeff_abs_level_remaining (Golomb-Rice with adaptive rice parameter)
 /// Returns (value, updated_rice_param)
+///
+/// `limited_range` is log2TransformRange when extended_precision_processing_flag
+/// is set: the escape then uses the limited-prefix EGk binarization
+/// (H.265 9.3.3.12), whose prefix is capped at 32 - log2TransformRange bins
+/// with a fixed log2TransformRange-bit suffix at the cap.
 fn decode_coeff_abs_level_remaining(
     cabac: &mut CabacDecoder<'_>,
     rice_param: u8,
-    base_level: i16,
-) -> Result<(i16, u8)> {
+    base_level: i32,
+    limited_range: Option<u8>,
+) -> Result<(i32, u8)> {
+    let max_prefix = limited_range.map_or(32, |range| 32 - range as u32);
+
     // Decode prefix (unary part)
     let mut prefix = 0u32;
-    while cabac.decode_bypass()? != 0 && prefix < 32 {
+    while prefix < max_prefix && cabac.decode_bypass()? != 0 {
         prefix += 1;
     }
 
@@ -1153,19 +1171,24 @@ fn decode_coeff_abs_level_remaining(
         } else {
             0
         };
-        ((prefix << rice_param) + suffix) as i16
+        ((prefix << rice_param) + suffix) as u64
     } else {
-        // EGk part: suffix bits = prefix - 3 + rice_param
-        let suffix_bits = (prefix - 3 + rice_param as u32) as u8;
+        // EGk part: suffix bits = prefix - 3 + rice_param, or log2TransformRange
+        // once a limited prefix reaches its maximum length
+        let suffix_bits = match limited_range {
+            Some(range) if prefix == max_prefix => range,
+            _ => (prefix - 3 + rice_param as u32) as u8,
+        };
         let suffix = cabac.decode_bypass_bits(suffix_bits)?;
         // value = (((1 << (prefix-3)) + 3 - 1) << rice_param) + suffix
-        let base = ((1u32 << (prefix - 3)) + 2) << rice_param;
-        (base + suffix) as i16
+        let base = ((1u64 << (prefix - 3)) + 2) << rice_param;
+        base + suffix as u64
     };
+    let value = value.min(i32::MAX as u64) as i32;
 
     // Update rice parameter: if baseLevel + value > 3 * (1 << rice_param), increase
-    let threshold = 3 * (1 << rice_param);
-    let new_rice_param = if (base_level.unsigned_abs() as u32 + value as u32) > threshold {
+    let threshold = 3i64 * (1 << rice_param);
+    let new_rice_param = if base_level.unsigned_abs() as i64 + value as i64 > threshold {
         (rice_param + 1).min(4)
     } else {
         rice_param
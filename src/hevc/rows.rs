@@ -0,0 +1,199 @@
+//! In-loop filtering one CTB row at a time, for streaming decoded rows
+//!
+//! Deblocking a CTB row has to wait until the row below is decoded, since
+//! intra prediction of that row reads unfiltered samples along the boundary.
+//! SAO of a row reads the deblocked first sample row of the row below, so it
+//! waits one row more. Rows therefore become final two CTB rows behind the
+//! decoder.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::deblock;
+use super::motion::MotionField;
+use super::params::{Pps, Sps};
+use super::picture::DecodedFrame;
+use super::sao::{self, SaoMap};
+
+/// Progress of the in-loop filters over the CTB rows of a picture
+#[derive(Debug)]
+pub(super) struct RowFilter {
+    ctb_size: u32,
+    height_ctbs: u32,
+    /// Chroma QP offsets (Cb, Cr), or `None` when deblocking is skipped
+    deblock: Option<(i32, i32)>,
+    sao: bool,
+    /// CTB rows decoded / deblocked / SAO-filtered so far
+    decoded: u32,
+    deblocked: u32,
+    filtered: u32,
+    /// Deblocked last sample row of each plane of the previous SAO row
+    sao_above: [Vec<u16>; 3],
+}
+
+impl RowFilter {
+    pub(super) fn new(sps: &Sps, pps: &Pps) -> Self {
+        let (deblock, sao) = super::loop_filter_switches();
+        Self::with_params(
+            sps.ctb_size(),
+            sps.pic_height_in_ctbs(),
+            deblock.then_some((pps.pps_cb_qp_offset as i32, pps.pps_cr_qp_offset as i32)),
+            sao && sps.sample_adaptive_offset_enabled_flag,
+        )
+    }
+
+    fn with_params(
+        ctb_size: u32,
+        height_ctbs: u32,
+        deblock: Option<(i32, i32)>,
+        sao: bool,
+    ) -> Self {
+        Self {
+            ctb_size,
+            height_ctbs,
+            deblock,
+            sao,
+            decoded: 0,
+            deblocked: 0,
+            filtered: 0,
+            sao_above: Default::default(),
+        }
+    }
+
+    /// Record that CTB row `ctb_y` is decoded and filter what that allows
+    ///
+    /// Returns the luma rows that became final. CTB rows must be completed
+    /// top to bottom.
+    pub(super) fn row_decoded(
+        &mut self,
+        frame: &mut DecodedFrame,
+        motion: &MotionField,
+        sao_map: &SaoMap,
+        ctb_y: u32,
+    ) -> Range<u32> {
+        self.decoded = self.decoded.max(ctb_y + 1);
+        self.advance(frame, motion, sao_map, self.decoded.saturating_sub(1))
+    }
+
+    /// Filter the remaining rows once the picture is decoded
+    ///
+    /// Returns the luma rows that became final.
+    pub(super) fn finish(
+        &mut self,
+        frame: &mut DecodedFrame,
+        motion: &MotionField,
+        sao_map: &SaoMap,
+    ) -> Range<u32> {
+        self.decoded = self.height_ctbs;
+        self.advance(frame, motion, sao_map, self.height_ctbs)
+    }
+
+    /// Deblock CTB rows before `deblock_end` and SAO-filter the rows whose
+    /// lower neighbour is deblocked
+    fn advance(
+        &mut self,
+        frame: &mut DecodedFrame,
+        motion: &MotionField,
+        sao_map: &SaoMap,
+        deblock_end: u32,
+    ) -> Range<u32> {
+        let first = self.filtered;
+        while self.deblocked < deblock_end {
+            if let Some((cb_qp_offset, cr_qp_offset)) = self.deblock {
+                let rows = self.luma_rows(self.deblocked, self.deblocked + 1, frame);
+                deblock::deblock_rows(frame, motion, cb_qp_offset, cr_qp_offset, rows);
+            }
+            self.deblocked += 1;
+        }
+
+        let filter_end = if self.deblocked == self.height_ctbs {
+            self.height_ctbs
+        } else {
+            self.deblocked.saturating_sub(1)
+        };
+        while self.filtered < filter_end {
+            if self.sao {
+                sao::apply_sao_row(
+                    frame,
+                    sao_map,
+                    self.ctb_size,
+                    self.filtered,
+                    &mut self.sao_above,
+                );
+            }
+            self.filtered += 1;
+        }
+        self.luma_rows(first, self.filtered, frame)
+    }
+
+    /// Luma rows of CTB rows `start..end`
+    fn luma_rows(&self, start: u32, end: u32, frame: &DecodedFrame) -> Range<u32> {
+        (start * self.ctb_size).min(frame.height)..(end * self.ctb_size).min(frame.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_filter_matches_whole_picture() {
+        // 4:2:0 picture of 3x4 CTBs of 16x16, the last row partial
+        let (width, height, ctb_size) = (48, 56, 16);
+        let mut seed = 1u32;
+        let mut rand = move |n: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % n
+        };
+
+        // Blocky content: a level per 8x8 block plus noise, every block an
+        // intra TU with its own QP
+        let mut frame = DecodedFrame::with_params(width, height, 8, 1);
+        let level = |x: u32, y: u32| 100 + (x / 8 * 37 + y / 8 * 91) % 40;
+        for y in 0..height {
+            for x in 0..width {
+                frame.set_y(x, y, (level(x, y) + rand(3)) as u16);
+                if x % 2 == 0 && y % 2 == 0 {
+                    frame.set_cb(x / 2, y / 2, (level(x, y) + rand(3)) as u16);
+                    frame.set_cr(x / 2, y / 2, (level(y, x) + rand(3)) as u16);
+                }
+            }
+        }
+        for y in (0..height).step_by(8) {
+            for x in (0..width).step_by(8) {
+                frame.mark_tu_boundary(x, y, 8);
+                frame.store_block_qp(x, y, 8, 30 + rand(10) as i8);
+            }
+        }
+        let motion = MotionField::intra(width, height);
+        let mut sao_map = SaoMap::new(3, 4);
+        for info in &mut sao_map.data {
+            for c in 0..3 {
+                info.sao_type_idx[c] = rand(3) as u8;
+                info.sao_eo_class[c] = rand(4) as u8;
+                info.sao_band_position[c] = 12 + rand(4) as u8;
+                info.sao_offset_val[c] = core::array::from_fn(|_| rand(5) as i16 - 2);
+            }
+        }
+
+        let mut whole = frame.clone();
+        deblock::apply_deblocking_filter(&mut whole, &motion, 0, 0);
+        sao::apply_sao(&mut whole, &sao_map, ctb_size);
+        assert_ne!(whole.y_plane, frame.y_plane);
+        assert_ne!(whole.cb_plane, frame.cb_plane);
+
+        let mut filter = RowFilter::with_params(ctb_size, 4, Some((0, 0)), true);
+        let mut done = 0;
+        for ctb_y in 0..4 {
+            let rows = filter.row_decoded(&mut frame, &motion, &sao_map, ctb_y);
+            assert_eq!(rows.start, done);
+            assert!(rows.end + 2 * ctb_size >= (ctb_y + 1) * ctb_size);
+            done = rows.end;
+        }
+        assert_eq!(filter.finish(&mut frame, &motion, &sao_map), done..height);
+
+        assert_eq!(frame.y_plane, whole.y_plane);
+        assert_eq!(frame.cb_plane, whole.cb_plane);
+        assert_eq!(frame.cr_plane, whole.cr_plane);
+    }
+}
@@ -9,7 +9,7 @@ use alloc::vec::Vec;
 use super::picture::DecodedFrame;
 
 /// SAO parameters for one CTB
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct SaoInfo {
     /// SAO type per component: 0=off, 1=band offset, 2=edge offset
     /// [0]=Y, [1]=Cb, [2]=Cr
@@ -22,10 +22,11 @@ pub struct SaoInfo {
     /// Signed offset values per component, 4 values each
     /// For band offset: offsets for 4 consecutive bands starting at band_position
     /// For edge offset: offsets[0]=cat1(+), [1]=cat2(+), [2]=cat3(-), [3]=cat4(-)
-    pub sao_offset_val: [[i8; 4]; 3],
+    pub sao_offset_val: [[i16; 4]; 3],
 }
 
 /// SAO map for the entire frame, stored at CTB granularity
+#[derive(Debug)]
 pub struct SaoMap {
     pub data: Vec<SaoInfo>,
     pub width_ctbs: u32,
@@ -66,10 +67,6 @@ const EO_OFFSETS: [(i32, i32, i32, i32); 4] = [
 
 /// Apply SAO filter to the entire frame
 pub fn apply_sao(frame: &mut DecodedFrame, sao_map: &SaoMap, ctb_size: u32) {
-    let width = frame.width;
-    let height = frame.height;
-    let bit_depth = frame.bit_depth;
-
     // Only clone planes that have edge offset (type 2), since edge offset
     // reads neighbors that may be modified. Band offset (type 1) is in-place.
     let mut need_y_clone = false;
@@ -106,6 +103,83 @@ pub fn apply_sao(frame: &mut DecodedFrame, sao_map: &SaoMap, ctb_size: u32) {
         Vec::new()
     };
 
+    // Process each CTB
+    for ctb_y in 0..sao_map.height_ctbs {
+        for ctb_x in 0..sao_map.width_ctbs {
+            let sao = sao_map.get(ctb_x, ctb_y);
+            let src = [orig_y.as_slice(), &orig_cb, &orig_cr];
+            apply_sao_ctb(frame, sao, ctb_x, ctb_y, ctb_size, src, [0; 3]);
+        }
+    }
+}
+
+/// Apply SAO to one row of CTBs
+///
+/// The row and the first sample row below it must be deblocked. `above`
+/// holds the deblocked last sample row of each plane of the CTB row above
+/// (empty for the first row) and is replaced with this row's, so filtering
+/// consecutive rows from the top gives the same result as [`apply_sao`].
+pub fn apply_sao_row(
+    frame: &mut DecodedFrame,
+    sao_map: &SaoMap,
+    ctb_size: u32,
+    ctb_y: u32,
+    above: &mut [Vec<u16>; 3],
+) {
+    let sub_y = if frame.chroma_format == 1 { 2 } else { 1 };
+    let num_planes = if frame.chroma_format > 0 { 3 } else { 1 };
+
+    // Copy the pre-SAO samples edge offset reads: the row above, this CTB
+    // row and the row below
+    let mut windows: [Vec<u16>; 3] = Default::default();
+    let mut first_rows = [0u32; 3];
+    for c_idx in 0..num_planes {
+        let (plane, stride) = frame.plane(c_idx as u8);
+        let (sub, plane_h) = if c_idx == 0 {
+            (1, frame.height)
+        } else {
+            (sub_y, frame.height / sub_y)
+        };
+        let y_start = (ctb_y * ctb_size / sub).min(plane_h);
+        let y_end = ((ctb_y + 1) * ctb_size / sub).min(plane_h);
+        if y_start == y_end {
+            continue;
+        }
+        let window_end = (y_end + 1).min(plane_h) as usize * stride;
+        let window = &mut windows[c_idx];
+        if y_start > 0 {
+            window.extend_from_slice(&above[c_idx]);
+            first_rows[c_idx] = y_start - 1;
+        } else {
+            first_rows[c_idx] = y_start;
+        }
+        window.extend_from_slice(&plane[y_start as usize * stride..window_end]);
+        above[c_idx] = plane[(y_end as usize - 1) * stride..y_end as usize * stride].to_vec();
+    }
+
+    let [src_y, src_cb, src_cr] = &windows;
+    for ctb_x in 0..sao_map.width_ctbs {
+        let sao = sao_map.get(ctb_x, ctb_y);
+        apply_sao_ctb(frame, sao, ctb_x, ctb_y, ctb_size, [src_y, src_cb, src_cr], first_rows);
+    }
+}
+
+/// Apply the SAO of one CTB, reading pre-SAO samples of each plane from `src`
+///
+/// `src[c]` holds the plane's rows from `src_rows[c]` on.
+#[allow(clippy::too_many_arguments)]
+fn apply_sao_ctb(
+    frame: &mut DecodedFrame,
+    sao: &SaoInfo,
+    ctb_x: u32,
+    ctb_y: u32,
+    ctb_size: u32,
+    src: [&[u16]; 3],
+    src_rows: [u32; 3],
+) {
+    let width = frame.width;
+    let height = frame.height;
+    let bit_depth = frame.bit_depth;
     let y_stride = frame.y_stride();
     let c_stride = frame.c_stride();
 
@@ -116,128 +190,125 @@ pub fn apply_sao(frame: &mut DecodedFrame, sao_map: &SaoMap, ctb_size: u32) {
         _ => (1, 1),
     };
 
-    // Process each CTB
-    for ctb_y in 0..sao_map.height_ctbs {
-        for ctb_x in 0..sao_map.width_ctbs {
-            let sao = sao_map.get(ctb_x, ctb_y);
-            let ctb_x_px = ctb_x * ctb_size;
-            let ctb_y_px = ctb_y * ctb_size;
-
-            // Luma
-            match sao.sao_type_idx[0] {
-                1 => {
-                    let x_end = (ctb_x_px + ctb_size).min(width);
-                    let y_end = (ctb_y_px + ctb_size).min(height);
-                    apply_sao_band_inplace(
-                        &mut frame.y_plane,
-                        y_stride as u32,
-                        ctb_x_px,
-                        ctb_y_px,
-                        x_end,
-                        y_end,
-                        sao.sao_band_position[0],
-                        &sao.sao_offset_val[0],
-                        bit_depth,
-                    );
-                }
-                2 => {
-                    let x_end = (ctb_x_px + ctb_size).min(width);
-                    let y_end = (ctb_y_px + ctb_size).min(height);
-                    apply_sao_edge(
-                        &orig_y,
-                        &mut frame.y_plane,
-                        y_stride as u32,
-                        width,
-                        height,
-                        ctb_x_px,
-                        ctb_y_px,
-                        x_end,
-                        y_end,
-                        sao.sao_eo_class[0],
-                        &sao.sao_offset_val[0],
-                        bit_depth,
-                    );
-                }
-                _ => {}
+    let ctb_x_px = ctb_x * ctb_size;
+    let ctb_y_px = ctb_y * ctb_size;
+
+    // Luma
+    match sao.sao_type_idx[0] {
+        1 => {
+            let x_end = (ctb_x_px + ctb_size).min(width);
+            let y_end = (ctb_y_px + ctb_size).min(height);
+            apply_sao_band_inplace(
+                &mut frame.y_plane,
+                y_stride as u32,
+                ctb_x_px,
+                ctb_y_px,
+                x_end,
+                y_end,
+                sao.sao_band_position[0],
+                &sao.sao_offset_val[0],
+                bit_depth,
+            );
+        }
+        2 => {
+            let x_end = (ctb_x_px + ctb_size).min(width);
+            let y_end = (ctb_y_px + ctb_size).min(height);
+            apply_sao_edge(
+                src[0],
+                src_rows[0],
+                &mut frame.y_plane,
+                y_stride as u32,
+                width,
+                height,
+                ctb_x_px,
+                ctb_y_px,
+                x_end,
+                y_end,
+                sao.sao_eo_class[0],
+                &sao.sao_offset_val[0],
+                bit_depth,
+            );
+        }
+        _ => {}
+    }
+
+    // Chroma (4:2:0: halved coordinates)
+    if frame.chroma_format > 0 {
+        let cx_start = ctb_x_px / sub_x;
+        let cy_start = ctb_y_px / sub_y;
+        let cx_end = ((ctb_x_px + ctb_size) / sub_x).min(width / sub_x);
+        let cy_end = ((ctb_y_px + ctb_size) / sub_y).min(height / sub_y);
+        let c_w = width / sub_x;
+        let c_h = height / sub_y;
+
+        // Cb
+        match sao.sao_type_idx[1] {
+            1 => {
+                apply_sao_band_inplace(
+                    &mut frame.cb_plane,
+                    c_stride as u32,
+                    cx_start,
+                    cy_start,
+                    cx_end,
+                    cy_end,
+                    sao.sao_band_position[1],
+                    &sao.sao_offset_val[1],
+                    bit_depth,
+                );
+            }
+            2 => {
+                apply_sao_edge(
+                    src[1],
+                    src_rows[1],
+                    &mut frame.cb_plane,
+                    c_stride as u32,
+                    c_w,
+                    c_h,
+                    cx_start,
+                    cy_start,
+                    cx_end,
+                    cy_end,
+                    sao.sao_eo_class[1],
+                    &sao.sao_offset_val[1],
+                    bit_depth,
+                );
             }
+            _ => {}
+        }
 
-            // Chroma (4:2:0: halved coordinates)
-            if frame.chroma_format > 0 {
-                let cx_start = ctb_x_px / sub_x;
-                let cy_start = ctb_y_px / sub_y;
-                let cx_end = ((ctb_x_px + ctb_size) / sub_x).min(width / sub_x);
-                let cy_end = ((ctb_y_px + ctb_size) / sub_y).min(height / sub_y);
-                let c_w = width / sub_x;
-                let c_h = height / sub_y;
-
-                // Cb
-                match sao.sao_type_idx[1] {
-                    1 => {
-                        apply_sao_band_inplace(
-                            &mut frame.cb_plane,
-                            c_stride as u32,
-                            cx_start,
-                            cy_start,
-                            cx_end,
-                            cy_end,
-                            sao.sao_band_position[1],
-                            &sao.sao_offset_val[1],
-                            bit_depth,
-                        );
-                    }
-                    2 => {
-                        apply_sao_edge(
-                            &orig_cb,
-                            &mut frame.cb_plane,
-                            c_stride as u32,
-                            c_w,
-                            c_h,
-                            cx_start,
-                            cy_start,
-                            cx_end,
-                            cy_end,
-                            sao.sao_eo_class[1],
-                            &sao.sao_offset_val[1],
-                            bit_depth,
-                        );
-                    }
-                    _ => {}
-                }
-
-                // Cr
-                match sao.sao_type_idx[2] {
-                    1 => {
-                        apply_sao_band_inplace(
-                            &mut frame.cr_plane,
-                            c_stride as u32,
-                            cx_start,
-                            cy_start,
-                            cx_end,
-                            cy_end,
-                            sao.sao_band_position[2],
-                            &sao.sao_offset_val[2],
-                            bit_depth,
-                        );
-                    }
-                    2 => {
-                        apply_sao_edge(
-                            &orig_cr,
-                            &mut frame.cr_plane,
-                            c_stride as u32,
-                            c_w,
-                            c_h,
-                            cx_start,
-                            cy_start,
-                            cx_end,
-                            cy_end,
-                            sao.sao_eo_class[2],
-                            &sao.sao_offset_val[2],
-                            bit_depth,
-                        );
-                    }
-                    _ => {}
-                }
+        // Cr
+        match sao.sao_type_idx[2] {
+            1 => {
+                apply_sao_band_inplace(
+                    &mut frame.cr_plane,
+                    c_stride as u32,
+                    cx_start,
+                    cy_start,
+                    cx_end,
+                    cy_end,
+                    sao.sao_band_position[2],
+                    &sao.sao_offset_val[2],
+                    bit_depth,
+                );
+            }
+            2 => {
+                apply_sao_edge(
+                    src[2],
+                    src_rows[2],
+                    &mut frame.cr_plane,
+                    c_stride as u32,
+                    c_w,
+                    c_h,
+                    cx_start,
+                    cy_start,
+                    cx_end,
+                    cy_end,
+                    sao.sao_eo_class[2],
+                    &sao.sao_offset_val[2],
+                    bit_depth,
+                );
             }
+            _ => {}
         }
     }
 }
@@ -247,6 +318,7 @@ pub fn apply_sao(frame: &mut DecodedFrame, sao_map: &SaoMap, ctb_size: u32) {
 #[inline(always)]
 fn apply_sao_edge_pixel(
     src: &[u16],
+    src_base: usize,
     dst: &mut [u16],
     row: usize,
     x: u32,
@@ -278,9 +350,9 @@ fn apply_sao_edge_pixel(
     }
 
     let idx = row + x as usize;
-    let sample = src[idx] as i32;
-    let n0 = src[(ny0 as u32 * stride + nx0 as u32) as usize] as i32;
-    let n1 = src[(ny1 as u32 * stride + nx1 as u32) as usize] as i32;
+    let sample = src[idx - src_base] as i32;
+    let n0 = src[(ny0 as u32 * stride + nx0 as u32) as usize - src_base] as i32;
+    let n1 = src[(ny1 as u32 * stride + nx1 as u32) as usize - src_base] as i32;
 
     let sign0 = (sample - n0).signum();
     let sign1 = (sample - n1).signum();
@@ -302,14 +374,14 @@ fn apply_sao_band_inplace(
     x_end: u32,
     y_end: u32,
     band_position: u8,
-    offsets: &[i8; 4],
+    offsets: &[i16; 4],
     bit_depth: u8,
 ) {
     let max_val = (1i32 << bit_depth) - 1;
     let band_shift = bit_depth - 5;
 
     // Build lookup table for the 32 bands
-    let mut band_table = [0i8; 32];
+    let mut band_table = [0i16; 32];
     for k in 0..4u8 {
         let band_idx = (band_position + k) & 31;
         band_table[band_idx as usize] = offsets[k as usize];
@@ -333,6 +405,7 @@ fn apply_sao_band_inplace(
 #[allow(clippy::too_many_arguments)]
 fn apply_sao_edge(
     src: &[u16],
+    src_row0: u32,
     dst: &mut [u16],
     stride: u32,
     plane_w: u32,
@@ -342,7 +415,7 @@ fn apply_sao_edge(
     x_end: u32,
     y_end: u32,
     eo_class: u8,
-    offsets: &[i8; 4],
+    offsets: &[i16; 4],
     bit_depth: u8,
 ) {
     let max_val = (1i32 << bit_depth) - 1;
@@ -363,6 +436,7 @@ fn apply_sao_edge(
     let safe_y_end = y_end.min(plane_h - dy0.max(dy1).max(0) as u32);
 
     let stride_u = stride as usize;
+    let src_base = src_row0 as usize * stride_u;
     let dx0_u = dx0 as isize;
     let dy0_s = dy0 as isize * stride_u as isize;
     let dx1_u = dx1 as isize;
@@ -373,9 +447,10 @@ fn apply_sao_edge(
         let row = y as usize * stride_u;
         for x in safe_x_start..safe_x_end {
             let idx = row + x as usize;
-            let sample = src[idx] as i32;
-            let n0_idx = (idx as isize + dy0_s + dx0_u) as usize;
-            let n1_idx = (idx as isize + dy1_s + dx1_u) as usize;
+            let src_idx = idx - src_base;
+            let sample = src[src_idx] as i32;
+            let n0_idx = (src_idx as isize + dy0_s + dx0_u) as usize;
+            let n1_idx = (src_idx as isize + dy1_s + dx1_u) as usize;
             let n0 = src[n0_idx] as i32;
             let n1 = src[n1_idx] as i32;
 
@@ -396,13 +471,13 @@ fn apply_sao_edge(
             let row = y as usize * stride_u;
             for x in x_start..safe_x_start.min(x_end) {
                 apply_sao_edge_pixel(
-                    src, dst, row, x, dx0, dy0, dx1, dy1, stride, plane_w, plane_h,
+                    src, src_base, dst, row, x, dx0, dy0, dx1, dy1, stride, plane_w, plane_h,
                     max_val, &offset_table,
                 );
             }
             for x in safe_x_end.max(x_start)..x_end {
                 apply_sao_edge_pixel(
-                    src, dst, row, x, dx0, dy0, dx1, dy1, stride, plane_w, plane_h,
+                    src, src_base, dst, row, x, dx0, dy0, dx1, dy1, stride, plane_w, plane_h,
                     max_val, &offset_table,
                 );
             }
@@ -410,7 +485,7 @@ fn apply_sao_edge(
             let row = y as usize * stride_u;
             for x in x_start..x_end {
                 apply_sao_edge_pixel(
-                    src, dst, row, x, dx0, dy0, dx1, dy1, stride, plane_w, plane_h,
+                    src, src_base, dst, row, x, dx0, dy0, dx1, dy1, stride, plane_w, plane_h,
                     max_val, &offset_table,
                 );
             }
@@ -0,0 +1,673 @@
+//! Supplemental enhancement information (SEI) messages (H.265 7.3.5, Annex D)
+
+use super::bitstream::BitstreamReader;
+use crate::error::HevcError;
+use alloc::vec::Vec;
+
+type Result<T> = core::result::Result<T, HevcError>;
+
+/// A parsed SEI message
+///
+/// Messages are attached to the picture of the access unit they were sent
+/// in: prefix SEI before the picture's first slice, suffix SEI after it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SeiMessage {
+    /// Hash of the decoded picture (payload type 132)
+    DecodedPictureHash(DecodedPictureHash),
+    /// Mastering display colour volume (payload type 137)
+    MasteringDisplayColourVolume(MasteringDisplayColourVolume),
+    /// Content light level information (payload type 144)
+    ContentLightLevel(ContentLightLevel),
+    /// Alpha channel information (payload type 165), `None` when the message
+    /// cancels an earlier one
+    AlphaChannelInfo(Option<AlphaChannelInfo>),
+    /// Depth representation information (payload type 177)
+    DepthRepresentationInfo(DepthRepresentationInfo),
+    /// User data identified by a UUID (payload type 5)
+    UserDataUnregistered(UserDataUnregistered),
+    /// Film grain characteristics (payload type 19), `None` when the message
+    /// cancels an earlier one
+    FilmGrainCharacteristics(Option<FilmGrainCharacteristics>),
+    /// Any other payload type, undecoded
+    Other {
+        /// payloadType
+        payload_type: u32,
+        /// Payload bytes
+        data: Vec<u8>,
+    },
+}
+
+/// Decoded picture hash, one value per colour component (Y, Cb, Cr)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedPictureHash {
+    /// MD5 of the component samples
+    Md5(Vec<[u8; 16]>),
+    /// CRC-16 (CRC-CCITT) of the component samples
+    Crc(Vec<u16>),
+    /// Position-weighted 32-bit checksum of the component samples
+    Checksum(Vec<u32>),
+}
+
+/// Colour volume of the display the content was mastered on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MasteringDisplayColourVolume {
+    /// (x, y) chromaticity of the three display primaries in units of
+    /// 0.00002, in the order they were sent (usually G, B, R)
+    pub display_primaries: [(u16, u16); 3],
+    /// (x, y) chromaticity of the white point in units of 0.00002
+    pub white_point: (u16, u16),
+    /// Maximum display luminance in units of 0.0001 cd/m²
+    pub max_luminance: u32,
+    /// Minimum display luminance in units of 0.0001 cd/m²
+    pub min_luminance: u32,
+}
+
+/// Content light level of the coded video
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// Maximum content light level (MaxCLL) in cd/m²
+    pub max_content_light_level: u16,
+    /// Maximum frame-average light level (MaxFALL) in cd/m²
+    pub max_pic_average_light_level: u16,
+}
+
+/// HDR static metadata: content light level and mastering display colour
+/// volume
+///
+/// Taken from the `clli` and `mdcv` item properties of a HEIF image, or from
+/// the SEI messages of its bitstream when the item has no such property.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HdrStaticMetadata {
+    /// MaxCLL and MaxFALL
+    pub content_light_level: Option<ContentLightLevel>,
+    /// Primaries, white point and luminance range of the mastering display
+    pub mastering_display_colour_volume: Option<MasteringDisplayColourVolume>,
+}
+
+impl HdrStaticMetadata {
+    /// The last content light level and mastering display SEI messages
+    pub(crate) fn from_sei(messages: &[SeiMessage]) -> Self {
+        let mut metadata = Self::default();
+        for message in messages {
+            match message {
+                SeiMessage::ContentLightLevel(clli) => metadata.content_light_level = Some(*clli),
+                SeiMessage::MasteringDisplayColourVolume(mdcv) => {
+                    metadata.mastering_display_colour_volume = Some(*mdcv);
+                }
+                _ => {}
+            }
+        }
+        metadata
+    }
+
+    /// Fill the values this lacks from `fallback`
+    pub(crate) fn or(self, fallback: Self) -> Self {
+        Self {
+            content_light_level: self.content_light_level.or(fallback.content_light_level),
+            mastering_display_colour_volume: self
+                .mastering_display_colour_volume
+                .or(fallback.mastering_display_colour_volume),
+        }
+    }
+}
+
+/// Interpretation of the auxiliary alpha picture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlphaChannelInfo {
+    /// alpha_channel_use_idc: 0 = the primary samples are to be multiplied
+    /// by alpha, 1 = they are premultiplied, 2 = unspecified
+    pub use_idc: u8,
+    /// Bit depth of the alpha samples
+    pub bit_depth: u8,
+    /// Alpha sample value meaning fully transparent
+    pub transparent_value: u16,
+    /// Alpha sample value meaning fully opaque
+    pub opaque_value: u16,
+    /// alpha_channel_incr_flag: alpha values above the transparent value
+    /// are increased by one before use
+    pub incr_flag: bool,
+    /// When alpha values are clipped, alpha_channel_clip_type_flag:
+    /// false = clip to transparent or opaque at the midpoint, true = clip
+    /// values beyond the transparent and opaque values
+    pub clip_type: Option<bool>,
+}
+
+/// Depth range and representation of an auxiliary depth picture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthRepresentationInfo {
+    /// Nearest depth
+    pub z_near: Option<DepthValue>,
+    /// Farthest depth
+    pub z_far: Option<DepthValue>,
+    /// Minimum disparity
+    pub d_min: Option<DepthValue>,
+    /// Maximum disparity
+    pub d_max: Option<DepthValue>,
+    /// depth_representation_type: 0 = uniform inverse Z, 1 = uniform
+    /// disparity, 2 = uniform Z, 3 = nonlinear disparity
+    pub representation_type: u32,
+    /// View the disparities refer to, when sent
+    pub disparity_ref_view_id: Option<u32>,
+    /// Piecewise-linear mapping of the nonlinear representation
+    pub nonlinear_model: Vec<u32>,
+}
+
+/// Floating-point value of a depth representation element
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthValue {
+    /// Sign, true for negative values
+    pub negative: bool,
+    /// Exponent, 0..=126 (127 is reserved)
+    pub exponent: u8,
+    /// Mantissa bits
+    pub mantissa: u32,
+    /// Number of mantissa bits
+    pub mantissa_len: u8,
+}
+
+impl DepthValue {
+    /// The value (H.265 I.14.3.3)
+    pub fn value(&self) -> f64 {
+        let mantissa = f64::from(self.mantissa) * pow2(-i32::from(self.mantissa_len));
+        let magnitude = if self.exponent == 0 {
+            mantissa * pow2(-30)
+        } else {
+            (1.0 + mantissa) * pow2(i32::from(self.exponent) - 31)
+        };
+        if self.negative { -magnitude } else { magnitude }
+    }
+}
+
+/// 2 to the power of `exp` (f64::powi requires std)
+fn pow2(exp: i32) -> f64 {
+    let factor = if exp < 0 { 0.5 } else { 2.0 };
+    (0..exp.unsigned_abs()).fold(1.0, |value, _| value * factor)
+}
+
+/// User data identified by a UUID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserDataUnregistered {
+    /// uuid_iso_iec_11578
+    pub uuid: [u8; 16],
+    /// The user data after the UUID
+    pub data: Vec<u8>,
+}
+
+/// Parameters of film grain to synthesise on the decoded picture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilmGrainCharacteristics {
+    /// film_grain_model_id: 0 = frequency filtering, 1 = auto-regression
+    pub model_id: u8,
+    /// Colour description of the grain model, when it differs from the
+    /// coded video's
+    pub colour_description: Option<FilmGrainColourDescription>,
+    /// blending_mode_id: 0 = additive, 1 = multiplicative
+    pub blending_mode_id: u8,
+    /// Scale of the model values, as a power of two
+    pub log2_scale_factor: u8,
+    /// Grain model of each colour component (Y, Cb, Cr), when present
+    pub components: [Option<Vec<FilmGrainInterval>>; 3],
+    /// film_grain_characteristics_persistence_flag: the parameters apply to
+    /// later pictures as well
+    pub persistence: bool,
+}
+
+/// Colour description of a film grain model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilmGrainColourDescription {
+    /// Luma bit depth
+    pub bit_depth_luma: u8,
+    /// Chroma bit depth
+    pub bit_depth_chroma: u8,
+    /// Full range flag
+    pub full_range: bool,
+    /// Colour primaries
+    pub colour_primaries: u8,
+    /// Transfer characteristics
+    pub transfer_characteristics: u8,
+    /// Matrix coefficients
+    pub matrix_coeffs: u8,
+}
+
+/// Film grain model of one sample intensity interval
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilmGrainInterval {
+    /// Lowest sample intensity of the interval (8-bit scale)
+    pub lower_bound: u8,
+    /// Highest sample intensity of the interval (8-bit scale)
+    pub upper_bound: u8,
+    /// comp_model_value: model parameters, at most six
+    pub model_values: Vec<i32>,
+}
+
+/// Parse the messages of an SEI RBSP (prefix or suffix)
+pub(crate) fn parse_sei(rbsp: &[u8]) -> Result<Vec<SeiMessage>> {
+    let mut messages = Vec::new();
+    let mut pos = 0;
+    // Messages follow each other up to the rbsp_trailing_bits byte
+    while rbsp
+        .get(pos..)
+        .is_some_and(|rest| !rest.is_empty() && rest != [0x80])
+    {
+        let payload_type = read_sei_value(rbsp, &mut pos)?;
+        let payload_size = read_sei_value(rbsp, &mut pos)? as usize;
+        let payload = rbsp
+            .get(pos..)
+            .and_then(|rest| rest.get(..payload_size))
+            .ok_or(HevcError::InvalidBitstream("SEI payload exceeds NAL unit"))?;
+        pos += payload_size;
+        messages.push(parse_message(payload_type, payload)?);
+    }
+    Ok(messages)
+}
+
+/// payloadType or payloadSize: 0xFF bytes adding 255 each, then a last byte
+fn read_sei_value(rbsp: &[u8], pos: &mut usize) -> Result<u32> {
+    let mut value = 0u32;
+    loop {
+        let &byte = rbsp
+            .get(*pos)
+            .ok_or(HevcError::InvalidBitstream("truncated SEI message header"))?;
+        *pos += 1;
+        value = value
+            .checked_add(u32::from(byte))
+            .ok_or(HevcError::InvalidBitstream("SEI message header overflow"))?;
+        if byte != 0xFF {
+            return Ok(value);
+        }
+    }
+}
+
+fn parse_message(payload_type: u32, payload: &[u8]) -> Result<SeiMessage> {
+    let mut reader = BitstreamReader::new(payload);
+    let message = match payload_type {
+        5 => {
+            let uuid = payload.get(..16).ok_or(HevcError::InvalidBitstream(
+                "truncated user data unregistered SEI",
+            ))?;
+            SeiMessage::UserDataUnregistered(UserDataUnregistered {
+                uuid: uuid.try_into().unwrap_or_default(),
+                data: payload[16..].to_vec(),
+            })
+        }
+        19 => SeiMessage::FilmGrainCharacteristics(parse_film_grain(&mut reader)?),
+        132 => SeiMessage::DecodedPictureHash(parse_picture_hash(payload)?),
+        137 => {
+            let mut read_point = || -> Result<(u16, u16)> {
+                Ok((reader.read_bits(16)? as u16, reader.read_bits(16)? as u16))
+            };
+            let display_primaries = [read_point()?, read_point()?, read_point()?];
+            let white_point = read_point()?;
+            SeiMessage::MasteringDisplayColourVolume(MasteringDisplayColourVolume {
+                display_primaries,
+                white_point,
+                max_luminance: reader.read_bits(32)?,
+                min_luminance: reader.read_bits(32)?,
+            })
+        }
+        144 => SeiMessage::ContentLightLevel(ContentLightLevel {
+            max_content_light_level: reader.read_bits(16)? as u16,
+            max_pic_average_light_level: reader.read_bits(16)? as u16,
+        }),
+        165 => SeiMessage::AlphaChannelInfo(parse_alpha_channel_info(&mut reader)?),
+        177 => SeiMessage::DepthRepresentationInfo(parse_depth_representation(&mut reader)?),
+        _ => SeiMessage::Other {
+            payload_type,
+            data: payload.to_vec(),
+        },
+    };
+    Ok(message)
+}
+
+/// decoded_picture_hash: the number of components follows from the size
+fn parse_picture_hash(payload: &[u8]) -> Result<DecodedPictureHash> {
+    let (&hash_type, hashes) = payload.split_first().ok_or(HevcError::InvalidBitstream(
+        "empty decoded picture hash SEI",
+    ))?;
+    let size = match hash_type {
+        0 => 16,
+        1 => 2,
+        2 => 4,
+        _ => return Err(HevcError::InvalidBitstream("reserved picture hash type")),
+    };
+    if hashes.len() < size {
+        return Err(HevcError::InvalidBitstream(
+            "truncated decoded picture hash SEI",
+        ));
+    }
+    let components = hashes.chunks_exact(size).take(3);
+    Ok(match hash_type {
+        0 => DecodedPictureHash::Md5(
+            components
+                .map(|md5| md5.try_into().unwrap_or_default())
+                .collect(),
+        ),
+        1 => DecodedPictureHash::Crc(
+            components
+                .map(|crc| u16::from_be_bytes([crc[0], crc[1]]))
+                .collect(),
+        ),
+        _ => DecodedPictureHash::Checksum(
+            components
+                .map(|sum| u32::from_be_bytes([sum[0], sum[1], sum[2], sum[3]]))
+                .collect(),
+        ),
+    })
+}
+
+fn parse_alpha_channel_info(reader: &mut BitstreamReader<'_>) -> Result<Option<AlphaChannelInfo>> {
+    if reader.read_bit()? == 1 {
+        return Ok(None);
+    }
+    let use_idc = reader.read_bits(3)? as u8;
+    let bit_depth = reader.read_bits(3)? as u8 + 8;
+    // Values are one bit wider than the alpha samples
+    let transparent_value = reader.read_bits(bit_depth + 1)? as u16;
+    let opaque_value = reader.read_bits(bit_depth + 1)? as u16;
+    let incr_flag = reader.read_bit()? == 1;
+    let clip_type = if reader.read_bit()? == 1 {
+        Some(reader.read_bit()? == 1)
+    } else {
+        None
+    };
+    Ok(Some(AlphaChannelInfo {
+        use_idc,
+        bit_depth,
+        transparent_value,
+        opaque_value,
+        incr_flag,
+        clip_type,
+    }))
+}
+
+fn parse_depth_representation(reader: &mut BitstreamReader<'_>) -> Result<DepthRepresentationInfo> {
+    let z_near_flag = reader.read_bit()? == 1;
+    let z_far_flag = reader.read_bit()? == 1;
+    let d_min_flag = reader.read_bit()? == 1;
+    let d_max_flag = reader.read_bit()? == 1;
+    let representation_type = reader.read_ue()?;
+    let disparity_ref_view_id = if d_min_flag || d_max_flag {
+        Some(reader.read_ue()?)
+    } else {
+        None
+    };
+    let mut read_element = |present: bool| -> Result<Option<DepthValue>> {
+        if !present {
+            return Ok(None);
+        }
+        let negative = reader.read_bit()? == 1;
+        let exponent = reader.read_bits(7)? as u8;
+        let mantissa_len = reader.read_bits(5)? as u8 + 1;
+        let mantissa = reader.read_bits(mantissa_len)?;
+        Ok(Some(DepthValue {
+            negative,
+            exponent,
+            mantissa,
+            mantissa_len,
+        }))
+    };
+    let z_near = read_element(z_near_flag)?;
+    let z_far = read_element(z_far_flag)?;
+    let d_min = read_element(d_min_flag)?;
+    let d_max = read_element(d_max_flag)?;
+    let mut nonlinear_model = Vec::new();
+    if representation_type == 3 {
+        let count = reader.read_ue()?;
+        if count > 62 {
+            return Err(HevcError::InvalidBitstream(
+                "too many nonlinear depth model points",
+            ));
+        }
+        for _ in 0..=count {
+            nonlinear_model.push(reader.read_ue()?);
+        }
+    }
+    Ok(DepthRepresentationInfo {
+        z_near,
+        z_far,
+        d_min,
+        d_max,
+        representation_type,
+        disparity_ref_view_id,
+        nonlinear_model,
+    })
+}
+
+fn parse_film_grain(reader: &mut BitstreamReader<'_>) -> Result<Option<FilmGrainCharacteristics>> {
+    if reader.read_bit()? == 1 {
+        return Ok(None);
+    }
+    let model_id = reader.read_bits(2)? as u8;
+    let colour_description = if reader.read_bit()? == 1 {
+        Some(FilmGrainColourDescription {
+            bit_depth_luma: reader.read_bits(3)? as u8 + 8,
+            bit_depth_chroma: reader.read_bits(3)? as u8 + 8,
+            full_range: reader.read_bit()? == 1,
+            colour_primaries: reader.read_bits(8)? as u8,
+            transfer_characteristics: reader.read_bits(8)? as u8,
+            matrix_coeffs: reader.read_bits(8)? as u8,
+        })
+    } else {
+        None
+    };
+    let blending_mode_id = reader.read_bits(2)? as u8;
+    let log2_scale_factor = reader.read_bits(4)? as u8;
+    let mut present = [false; 3];
+    for flag in &mut present {
+        *flag = reader.read_bit()? == 1;
+    }
+    let mut components = [None, None, None];
+    for (component, _) in components.iter_mut().zip(present).filter(|(_, p)| *p) {
+        let num_intervals = reader.read_bits(8)? + 1;
+        let num_model_values = reader.read_bits(3)? + 1;
+        let mut intervals = Vec::with_capacity(num_intervals as usize);
+        for _ in 0..num_intervals {
+            let lower_bound = reader.read_bits(8)? as u8;
+            let upper_bound = reader.read_bits(8)? as u8;
+            let model_values = (0..num_model_values)
+                .map(|_| reader.read_se())
+                .collect::<Result<_>>()?;
+            intervals.push(FilmGrainInterval {
+                lower_bound,
+                upper_bound,
+                model_values,
+            });
+        }
+        *component = Some(intervals);
+    }
+    Ok(Some(FilmGrainCharacteristics {
+        model_id,
+        colour_description,
+        blending_mode_id,
+        log2_scale_factor,
+        components,
+        persistence: reader.read_bit()? == 1,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hevc::DecodedFrame;
+    use crate::hevc::bitstream::{BitstreamWriter, NalType, write_nal_unit};
+    use crate::hevc::encoder::encode_picture;
+    use crate::hevc::encoder::tests::{annex_b, test_picture};
+
+    #[test]
+    fn test_parse_sei_messages() {
+        let mut rbsp = alloc::vec![144, 4, 0x03, 0xE8, 0x01, 0x90];
+        // Mastering display: BT.2020 primaries, D65, 1000 / 0.005 cd/m²
+        rbsp.extend_from_slice(&[137, 24]);
+        for value in [8500u16, 39850, 6550, 2300, 35400, 14600, 15635, 16450] {
+            rbsp.extend_from_slice(&value.to_be_bytes());
+        }
+        rbsp.extend_from_slice(&10_000_000u32.to_be_bytes());
+        rbsp.extend_from_slice(&50u32.to_be_bytes());
+        // Alpha channel info: 8-bit, transparent 0, opaque 255, no clipping
+        rbsp.extend_from_slice(&[165, 4, 0, 0, 0b0111_1111, 0b1000_0000]);
+        // A payload type of 255 + 200 with an unknown payload
+        rbsp.extend_from_slice(&[0xFF, 200, 2, 0xAB, 0xCD, 0x80]);
+
+        let messages = parse_sei(&rbsp).unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(
+            messages[0],
+            SeiMessage::ContentLightLevel(ContentLightLevel {
+                max_content_light_level: 1000,
+                max_pic_average_light_level: 400,
+            })
+        );
+        let SeiMessage::MasteringDisplayColourVolume(mdcv) = &messages[1] else {
+            panic!("expected mastering display colour volume");
+        };
+        assert_eq!(mdcv.display_primaries[2], (35400, 14600));
+        assert_eq!(mdcv.white_point, (15635, 16450));
+        assert_eq!((mdcv.max_luminance, mdcv.min_luminance), (10_000_000, 50));
+        let SeiMessage::AlphaChannelInfo(Some(alpha)) = &messages[2] else {
+            panic!("expected alpha channel info");
+        };
+        assert_eq!((alpha.bit_depth, alpha.transparent_value), (8, 0));
+        assert_eq!((alpha.opaque_value, alpha.clip_type), (255, None));
+        assert_eq!(
+            messages[3],
+            SeiMessage::Other {
+                payload_type: 455,
+                data: alloc::vec![0xAB, 0xCD],
+            }
+        );
+
+        assert!(parse_sei(&[144, 8, 0, 0, 0x80]).is_err());
+    }
+
+    #[test]
+    fn test_depth_value() {
+        // 1.5 * 2^(32 - 31) and 2^-30 * 1/2
+        let value = |exponent, mantissa| DepthValue {
+            negative: false,
+            exponent,
+            mantissa,
+            mantissa_len: 1,
+        };
+        assert_eq!(value(32, 1).value(), 3.0);
+        assert_eq!(value(0, 1).value(), pow2(-31));
+    }
+
+    #[test]
+    fn test_sei_attached_to_pictures() {
+        let sei_nal = |nal_type, rbsp: &[u8]| {
+            let mut nal = alloc::vec![0, 0, 0, 1];
+            nal.extend_from_slice(&write_nal_unit(nal_type, 0, rbsp));
+            nal
+        };
+        let encoded = encode_picture(&test_picture(64, 48), 30, &[None]);
+        let mut user_data = alloc::vec![5, 18];
+        user_data.extend(1..=18);
+        user_data.push(0x80);
+
+        // Prefix SEI ahead of the first picture and a suffix SEI after it;
+        // the second picture's suffix SEI is truncated and dropped
+        let mut stream = sei_nal(
+            NalType::PrefixSeiNut,
+            &[144, 4, 0x03, 0xE8, 0x01, 0x90, 0x80],
+        );
+        stream.extend_from_slice(&annex_b(&encoded));
+        stream.extend_from_slice(&sei_nal(NalType::SuffixSeiNut, &user_data));
+        stream.extend_from_slice(&annex_b(&encoded));
+        stream.extend_from_slice(&sei_nal(NalType::SuffixSeiNut, &[132, 17, 0, 1, 2, 0x80]));
+
+        let frames: Vec<DecodedFrame> = crate::hevc::decode_pictures(&stream)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].sei.len(), 2);
+        assert_eq!(
+            frames[0].sei[0],
+            SeiMessage::ContentLightLevel(ContentLightLevel {
+                max_content_light_level: 1000,
+                max_pic_average_light_level: 400,
+            })
+        );
+        let SeiMessage::UserDataUnregistered(user_data) = &frames[0].sei[1] else {
+            panic!("expected user data unregistered");
+        };
+        assert_eq!(user_data.uuid[15], 16);
+        assert_eq!(user_data.data, [17, 18]);
+        assert!(frames[1].sei.is_empty());
+        let hdr_metadata = frames[0].hdr_metadata;
+        assert_eq!(
+            hdr_metadata
+                .content_light_level
+                .map(|clli| clli.max_content_light_level),
+            Some(1000)
+        );
+        assert_eq!(hdr_metadata.mastering_display_colour_volume, None);
+    }
+
+    #[test]
+    fn test_film_grain_persistence() {
+        // Film grain characteristics SEI with one luma interval, or a cancel
+        let film_grain = |persistence: Option<bool>| {
+            let mut w = BitstreamWriter::new();
+            w.write_flag(persistence.is_none()); // film_grain_characteristics_cancel_flag
+            if let Some(persistence) = persistence {
+                w.write_bits(0, 2); // film_grain_model_id
+                w.write_flag(false); // separate_colour_description_present_flag
+                w.write_bits(0, 2); // blending_mode_id
+                w.write_bits(0, 4); // log2_scale_factor
+                w.write_bits(0b100, 3); // comp_model_present_flag
+                w.write_bits(0, 8); // num_intensity_intervals_minus1
+                w.write_bits(0, 3); // num_model_values_minus1
+                w.write_bits(0, 8); // intensity_interval_lower_bound
+                w.write_bits(255, 8); // intensity_interval_upper_bound
+                w.write_se(10); // comp_model_value
+                w.write_flag(persistence);
+            }
+            w.write_trailing_bits();
+            let payload = w.finish();
+            let mut rbsp = alloc::vec![19, payload.len() as u8];
+            rbsp.extend_from_slice(&payload);
+            rbsp.push(0x80);
+            write_nal_unit(NalType::PrefixSeiNut, 0, &rbsp)
+        };
+
+        // Decoding order I, P, B, I: only the first picture carries
+        // persistent characteristics, the B picture cancels them
+        let nal_units = crate::hevc::encoder::inter::encode_ipb(&test_picture(64, 32), 30, 8);
+        let mut stream = Vec::new();
+        let mut push = |nal: &[u8]| {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(nal);
+        };
+        nal_units[..3].iter().for_each(|nal| push(nal));
+        push(&film_grain(Some(true)));
+        push(&nal_units[3]);
+        push(&nal_units[4]);
+        push(&film_grain(None));
+        push(&nal_units[5]);
+        push(&nal_units[3]);
+
+        // Output order I, B, P, I
+        let frames: Vec<DecodedFrame> = crate::hevc::decode_pictures(&stream)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(frames.len(), 4);
+        let grain = |frame: &DecodedFrame| {
+            frame.sei.iter().find_map(|message| match message {
+                SeiMessage::FilmGrainCharacteristics(grain) => Some(grain.clone()),
+                _ => None,
+            })
+        };
+        let first = grain(&frames[0]).flatten().unwrap();
+        assert!(first.persistence);
+        assert_eq!(grain(&frames[2]), Some(Some(first)));
+        assert_eq!(grain(&frames[1]), Some(None));
+        // The next IRAP picture ends the persistence
+        assert_eq!(grain(&frames[3]), None);
+
+        let mut with_grain = frames[2].clone();
+        assert!(with_grain.apply_film_grain(1));
+        assert_ne!(with_grain.y_plane, frames[2].y_plane);
+    }
+}
@@ -3,8 +3,10 @@
 //! This module handles parsing of slice segment headers (H.265 spec 7.3.6)
 //! and orchestrates CTU decoding for each slice.
 
+use alloc::vec::Vec;
+
 use super::bitstream::{BitstreamReader, NalUnit};
-use super::params::{Pps, Sps};
+use super::params::{Pps, ShortTermRps, Sps, parse_short_term_ref_pic_set};
 use crate::error::HevcError;
 
 type Result<T> = core::result::Result<T, HevcError>;
@@ -182,6 +184,11 @@ pub struct SliceHeader {
     /// Slice segment address (CTB index)
     pub slice_segment_address: u32,
 
+    /// Discardable flag (first extra slice header bit, multilayer)
+    pub discardable_flag: bool,
+    /// Cross-layer BLA flag (second extra slice header bit, multilayer)
+    pub cross_layer_bla_flag: bool,
+
     /// Slice type (I, P, B)
     pub slice_type: SliceType,
     /// Picture output flag
@@ -191,11 +198,40 @@ pub struct SliceHeader {
     /// Picture order count LSB
     pub slice_pic_order_cnt_lsb: u32,
 
+    /// Short-term reference picture set of the picture (empty for IDR)
+    pub st_rps: ShortTermRps,
+    /// Long-term reference pictures of the picture
+    pub long_term_refs: Vec<LongTermRef>,
+    /// Slice temporal MVP enabled flag
+    pub slice_temporal_mvp_enabled_flag: bool,
+
+    /// Inter-layer prediction enabled flag (multilayer)
+    pub inter_layer_pred_enabled_flag: bool,
+    /// nuh_layer_id of the active inter-layer reference layers
+    pub active_ref_layer_ids: Vec<u8>,
+
     /// SAO luma flag
     pub slice_sao_luma_flag: bool,
     /// SAO chroma flag
     pub slice_sao_chroma_flag: bool,
 
+    /// Number of active entries in each reference picture list
+    pub num_ref_idx_active: [u8; 2],
+    /// list_entry_lX of ref_pic_lists_modification(), if the list is modified
+    pub list_entry: [Option<Vec<u8>>; 2],
+    /// MVD L1 zero flag
+    pub mvd_l1_zero_flag: bool,
+    /// CABAC init flag
+    pub cabac_init_flag: bool,
+    /// Collocated picture is taken from list 0
+    pub collocated_from_l0_flag: bool,
+    /// Reference index of the collocated picture
+    pub collocated_ref_idx: u8,
+    /// Explicit weighted prediction parameters
+    pub pred_weight_table: Option<PredWeightTable>,
+    /// MaxNumMergeCand
+    pub max_num_merge_cand: u8,
+
     /// Slice QP delta
     pub slice_qp_delta: i8,
     /// Slice Cb QP offset
@@ -225,6 +261,41 @@ pub struct SliceHeader {
     pub slice_qp_y: i32,
 }
 
+/// Long-term reference picture entry of a slice header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LongTermRef {
+    /// PocLsbLt
+    pub poc_lsb: u32,
+    /// UsedByCurrPicLt
+    pub used_by_curr_pic: bool,
+    /// delta_poc_msb_present_flag
+    pub delta_poc_msb_present_flag: bool,
+    /// DeltaPocMsbCycleLt (accumulated)
+    pub delta_poc_msb_cycle: u32,
+}
+
+/// Explicit weighted prediction parameters (H.265 7.3.6.3)
+#[derive(Debug, Clone)]
+pub struct PredWeightTable {
+    /// luma_log2_weight_denom
+    pub luma_log2_weight_denom: u8,
+    /// ChromaLog2WeightDenom
+    pub chroma_log2_weight_denom: u8,
+    /// Weights per reference index of list 0 and list 1
+    pub weights: [Vec<PredWeight>; 2],
+}
+
+/// Weights and offsets of one reference picture
+///
+/// Offsets are already scaled to the sample bit depth (WpOffsetBdShift).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PredWeight {
+    /// LumaWeightLX and luma offset
+    pub luma: (i32, i32),
+    /// ChromaWeightLX and ChromaOffsetLX for Cb and Cr
+    pub chroma: [(i32, i32); 2],
+}
+
 /// Parse result containing header and data offset
 pub struct SliceParseResult {
     /// Parsed slice header
@@ -248,8 +319,8 @@ impl SliceHeader {
             false
         };
 
-        let pps_id = reader.read_ue()? as u8;
-        if pps_id != pps.pps_id {
+        let pps_id = reader.read_ue()?;
+        if pps_id != u32::from(pps.pps_id) {
             return Err(HevcError::InvalidBitstream("PPS ID mismatch"));
         }
 
@@ -278,9 +349,17 @@ impl SliceHeader {
             return Err(HevcError::Unsupported("dependent slice segments"));
         }
 
-        // Skip reserved bits
-        for _ in 0..pps.num_extra_slice_header_bits {
-            reader.read_bit()?;
+        // Extra slice header bits: the first two are assigned by the
+        // multilayer extensions (F.7.4.7.1), the rest are reserved
+        let mut discardable_flag = false;
+        let mut cross_layer_bla_flag = false;
+        for i in 0..pps.num_extra_slice_header_bits {
+            let bit = reader.read_bit()? != 0;
+            match i {
+                0 => discardable_flag = bit,
+                1 => cross_layer_bla_flag = bit,
+                _ => {}
+            }
         }
 
         let slice_type_val = reader.read_ue()? as u8;
@@ -299,20 +378,31 @@ impl SliceHeader {
             0
         };
 
-        // For IDR pictures, POC LSB and ref pic set are not present
-        let slice_pic_order_cnt_lsb = if !nal.nal_type.is_idr() {
+        // For IDR pictures, POC LSB and ref pic set are not present, except
+        // that enhancement-layer IDRs may still carry the POC LSB
+        let poc_lsb_present =
+            (nal.nuh_layer_id > 0 && !sps.layer.poc_lsb_not_present_flag) || !nal.nal_type.is_idr();
+        let slice_pic_order_cnt_lsb = if poc_lsb_present {
             let poc_bits = sps.log2_max_pic_order_cnt_lsb_minus4 + 4;
             reader.read_bits(poc_bits)?
         } else {
             0
         };
 
-        // Skip short-term and long-term ref pic set parsing for I-slices in still images
-        // These are not needed for single-frame HEIC decoding
+        let mut st_rps = ShortTermRps::default();
+        let mut long_term_refs = Vec::new();
+        let mut slice_temporal_mvp_enabled_flag = false;
         if !nal.nal_type.is_idr() {
-            skip_ref_pic_set(&mut reader, sps)?;
+            st_rps = parse_st_rps_selection(&mut reader, sps)?;
+            long_term_refs = parse_long_term_refs(&mut reader, sps)?;
+            if sps.sps_temporal_mvp_enabled_flag {
+                slice_temporal_mvp_enabled_flag = reader.read_bit()? != 0;
+            }
         }
 
+        let (inter_layer_pred_enabled_flag, active_ref_layer_ids) =
+            parse_inter_layer_refs(&mut reader, nal.nuh_layer_id, sps)?;
+
         // SAO flags
         let (slice_sao_luma_flag, slice_sao_chroma_flag) =
             if sps.sample_adaptive_offset_enabled_flag {
@@ -327,10 +417,92 @@ impl SliceHeader {
                 (false, false)
             };
 
-        // For P/B slices, there would be ref idx and weight table parsing here
-        // We skip this for I-slices (HEIC still images are typically I-frames)
+        let mut num_ref_idx_active = [0u8; 2];
+        let mut list_entry = [None, None];
+        let mut mvd_l1_zero_flag = false;
+        let mut cabac_init_flag = false;
+        let mut collocated_from_l0_flag = true;
+        let mut collocated_ref_idx = 0;
+        let mut pred_weight_table = None;
+        let mut max_num_merge_cand = 5;
         if slice_type != SliceType::I {
-            return Err(HevcError::Unsupported("P/B slices not yet implemented"));
+            let is_b = slice_type == SliceType::B;
+            num_ref_idx_active = [
+                pps.num_ref_idx_l0_default_active_minus1 + 1,
+                if is_b {
+                    pps.num_ref_idx_l1_default_active_minus1 + 1
+                } else {
+                    0
+                },
+            ];
+            let num_ref_idx_active_override_flag = reader.read_bit()? != 0;
+            if num_ref_idx_active_override_flag {
+                num_ref_idx_active[0] = read_num_ref_idx_active(&mut reader)?;
+                if is_b {
+                    num_ref_idx_active[1] = read_num_ref_idx_active(&mut reader)?;
+                }
+            }
+
+            let num_pic_total_curr = st_rps.used_by_curr_pic_s0.iter().filter(|&&u| u).count()
+                + st_rps.used_by_curr_pic_s1.iter().filter(|&&u| u).count()
+                + long_term_refs.iter().filter(|lt| lt.used_by_curr_pic).count()
+                + active_ref_layer_ids.len();
+            if num_pic_total_curr == 0 {
+                return Err(HevcError::InvalidBitstream(
+                    "inter slice without reference pictures",
+                ));
+            }
+            if pps.lists_modification_present_flag && num_pic_total_curr > 1 {
+                let entry_bits = ceil_log2(num_pic_total_curr as u32);
+                for list in 0..if is_b { 2 } else { 1 } {
+                    let ref_pic_list_modification_flag = reader.read_bit()? != 0;
+                    if ref_pic_list_modification_flag {
+                        let mut entries = Vec::with_capacity(num_ref_idx_active[list] as usize);
+                        for _ in 0..num_ref_idx_active[list] {
+                            entries.push(reader.read_bits(entry_bits)? as u8);
+                        }
+                        list_entry[list] = Some(entries);
+                    }
+                }
+            }
+
+            if is_b {
+                mvd_l1_zero_flag = reader.read_bit()? != 0;
+            }
+            if pps.cabac_init_present_flag {
+                cabac_init_flag = reader.read_bit()? != 0;
+            }
+            if slice_temporal_mvp_enabled_flag {
+                if is_b {
+                    collocated_from_l0_flag = reader.read_bit()? != 0;
+                }
+                let list = if collocated_from_l0_flag { 0 } else { 1 };
+                if num_ref_idx_active[list] > 1 {
+                    collocated_ref_idx = reader.read_ue()?.min(15) as u8;
+                    if collocated_ref_idx >= num_ref_idx_active[list] {
+                        return Err(HevcError::InvalidBitstream(
+                            "collocated_ref_idx out of range",
+                        ));
+                    }
+                }
+            }
+            if (pps.weighted_pred_flag && slice_type == SliceType::P)
+                || (pps.weighted_bipred_flag && is_b)
+            {
+                pred_weight_table = Some(parse_pred_weight_table(
+                    &mut reader,
+                    sps,
+                    num_ref_idx_active,
+                )?);
+            }
+
+            let five_minus_max_num_merge_cand = reader.read_ue()?;
+            if five_minus_max_num_merge_cand > 4 {
+                return Err(HevcError::InvalidBitstream(
+                    "five_minus_max_num_merge_cand out of range",
+                ));
+            }
+            max_num_merge_cand = 5 - five_minus_max_num_merge_cand as u8;
         }
 
         // slice_qp_delta
@@ -347,7 +519,12 @@ impl SliceHeader {
             };
 
         // CU chroma QP offset
-        let cu_chroma_qp_offset_enabled_flag = false; // Skip range extension for now
+        let cu_chroma_qp_offset_enabled_flag =
+            if pps.range_extension.chroma_qp_offset_list_enabled_flag {
+                reader.read_bit()? != 0
+            } else {
+                false
+            };
 
         // Deblocking filter
         let deblocking_filter_override_flag = if pps.deblocking_filter_override_enabled_flag {
@@ -424,15 +601,30 @@ impl SliceHeader {
             header: SliceHeader {
                 first_slice_segment_in_pic_flag,
                 no_output_of_prior_pics_flag,
-                pps_id,
+                pps_id: pps.pps_id,
                 dependent_slice_segment_flag,
                 slice_segment_address,
+                discardable_flag,
+                cross_layer_bla_flag,
                 slice_type,
                 pic_output_flag,
                 colour_plane_id,
                 slice_pic_order_cnt_lsb,
+                st_rps,
+                long_term_refs,
+                slice_temporal_mvp_enabled_flag,
+                inter_layer_pred_enabled_flag,
+                active_ref_layer_ids,
                 slice_sao_luma_flag,
                 slice_sao_chroma_flag,
+                num_ref_idx_active,
+                list_entry,
+                mvd_l1_zero_flag,
+                cabac_init_flag,
+                collocated_from_l0_flag,
+                collocated_ref_idx,
+                pred_weight_table,
+                max_num_merge_cand,
                 slice_qp_delta,
                 slice_cb_qp_offset,
                 slice_cr_qp_offset,
@@ -450,83 +642,246 @@ impl SliceHeader {
     }
 }
 
-/// Skip reference picture set parsing (for non-IDR pictures)
-fn skip_ref_pic_set(reader: &mut BitstreamReader<'_>, sps: &Sps) -> Result<()> {
-    let short_term_ref_pic_set_sps_flag = reader.read_bit()? != 0;
+/// Read the PPS ID of a slice segment without parsing the rest of its header
+pub fn peek_pps_id(nal: &NalUnit<'_>) -> Result<u8> {
+    let mut reader = BitstreamReader::new(&nal.payload);
+    let _first_slice_segment_in_pic_flag = reader.read_bit()?;
+    if nal.nal_type.is_irap() {
+        let _no_output_of_prior_pics_flag = reader.read_bit()?;
+    }
+    let pps_id = reader.read_ue()?;
+    if pps_id > u32::from(super::params::MAX_PPS_ID) {
+        return Err(HevcError::InvalidBitstream("slice_pic_parameter_set_id out of range"));
+    }
+    Ok(pps_id as u8)
+}
 
-    if !short_term_ref_pic_set_sps_flag {
-        // Parse inline short-term ref pic set
-        skip_short_term_ref_pic_set(reader, sps.num_short_term_ref_pic_sets)?;
-    } else if sps.num_short_term_ref_pic_sets > 1 {
-        let bits = ceil_log2(sps.num_short_term_ref_pic_sets as u32);
-        reader.read_bits(bits)?;
+/// Parse the inter-layer reference signalling of an enhancement-layer slice
+/// (F.7.3.6.1) and return the flag and the active reference layer IDs
+fn parse_inter_layer_refs(
+    reader: &mut BitstreamReader<'_>,
+    nuh_layer_id: u8,
+    sps: &Sps,
+) -> Result<(bool, Vec<u8>)> {
+    let direct = &sps.layer.direct_ref_layer_ids;
+    if nuh_layer_id == 0 || direct.is_empty() {
+        return Ok((false, Vec::new()));
+    }
+    if sps.layer.default_ref_layers_active_flag {
+        return Ok((true, direct.clone()));
     }
 
-    // Long-term ref pics
-    if sps.long_term_ref_pics_present_flag {
-        let num_long_term_sps = reader.read_ue()?;
-        let num_long_term_pics = reader.read_ue()?;
+    let inter_layer_pred_enabled_flag = reader.read_bit()? != 0;
+    if !inter_layer_pred_enabled_flag {
+        return Ok((false, Vec::new()));
+    }
+    if direct.len() == 1 {
+        return Ok((true, direct.clone()));
+    }
 
-        let poc_bits = sps.log2_max_pic_order_cnt_lsb_minus4 + 4;
+    let idc_bits = ceil_log2(direct.len() as u32);
+    let num_active = if sps.layer.max_one_active_ref_layer_flag {
+        1
+    } else {
+        reader.read_bits(idc_bits)? as usize + 1
+    };
+    if num_active == direct.len() {
+        return Ok((true, direct.clone()));
+    }
 
-        for i in 0..(num_long_term_sps + num_long_term_pics) {
-            if i < num_long_term_sps {
-                // lt_idx_sps - bits depend on num_long_term_ref_pics_sps
-                // For simplicity, skip
-                reader.read_ue()?;
-            } else {
-                reader.read_bits(poc_bits)?; // poc_lsb_lt
-                reader.read_bit()?; // used_by_curr_pic_lt_flag
-            }
-            let delta_poc_msb_present = reader.read_bit()? != 0;
-            if delta_poc_msb_present {
-                reader.read_ue()?; // delta_poc_msb_cycle_lt
-            }
-        }
+    let mut active = Vec::with_capacity(num_active);
+    for _ in 0..num_active {
+        let idc = reader.read_bits(idc_bits)? as usize;
+        let id = *direct.get(idc).ok_or(HevcError::InvalidBitstream(
+            "inter_layer_pred_layer_idc out of range",
+        ))?;
+        active.push(id);
     }
+    Ok((true, active))
+}
 
-    // Temporal MVP
-    if sps.sps_temporal_mvp_enabled_flag {
-        reader.read_bit()?; // slice_temporal_mvp_enabled_flag
+/// Parse the short-term RPS of a slice: either an index into the SPS sets
+/// or a set coded in the slice header
+fn parse_st_rps_selection(reader: &mut BitstreamReader<'_>, sps: &Sps) -> Result<ShortTermRps> {
+    let short_term_ref_pic_set_sps_flag = reader.read_bit()? != 0;
+    if !short_term_ref_pic_set_sps_flag {
+        return parse_short_term_ref_pic_set(reader, &sps.st_ref_pic_sets, true);
     }
 
-    Ok(())
+    let idx = if sps.st_ref_pic_sets.len() > 1 {
+        let bits = ceil_log2(sps.st_ref_pic_sets.len() as u32);
+        reader.read_bits(bits)? as usize
+    } else {
+        0
+    };
+    sps.st_ref_pic_sets
+        .get(idx)
+        .cloned()
+        .ok_or(HevcError::InvalidBitstream("short_term_ref_pic_set_idx out of range"))
 }
 
-/// Skip inline short-term ref pic set
-fn skip_short_term_ref_pic_set(reader: &mut BitstreamReader<'_>, idx: u8) -> Result<()> {
-    let inter_ref_pic_set_prediction_flag = if idx != 0 {
-        reader.read_bit()? != 0
+/// Parse the long-term reference pictures of a slice header (7.3.6.1)
+fn parse_long_term_refs(reader: &mut BitstreamReader<'_>, sps: &Sps) -> Result<Vec<LongTermRef>> {
+    if !sps.long_term_ref_pics_present_flag {
+        return Ok(Vec::new());
+    }
+
+    let num_lt_sps = sps.lt_ref_pic_poc_lsb_sps.len();
+    let num_long_term_sps = if num_lt_sps > 0 {
+        reader.read_ue()? as usize
     } else {
-        false
+        0
     };
+    let num_long_term_pics = reader.read_ue()? as usize;
+    if num_long_term_sps > num_lt_sps || num_long_term_pics > 32 {
+        return Err(HevcError::InvalidBitstream("too many long-term pictures"));
+    }
+
+    let poc_bits = sps.log2_max_pic_order_cnt_lsb_minus4 + 4;
+    let mut refs: Vec<LongTermRef> = Vec::with_capacity(num_long_term_sps + num_long_term_pics);
+    for i in 0..num_long_term_sps + num_long_term_pics {
+        let (poc_lsb, used_by_curr_pic) = if i < num_long_term_sps {
+            let lt_idx_sps = if num_lt_sps > 1 {
+                reader.read_bits(ceil_log2(num_lt_sps as u32))? as usize
+            } else {
+                0
+            };
+            let idx = lt_idx_sps.min(num_lt_sps - 1);
+            (
+                sps.lt_ref_pic_poc_lsb_sps[idx],
+                sps.used_by_curr_pic_lt_sps_flag[idx],
+            )
+        } else {
+            let poc_lsb = reader.read_bits(poc_bits)?;
+            (poc_lsb, reader.read_bit()? != 0)
+        };
 
-    if inter_ref_pic_set_prediction_flag {
-        // Would need previous set info
-        reader.read_bit()?; // delta_rps_sign
-        reader.read_ue()?; // abs_delta_rps_minus1
-        // This is incomplete - would need to loop based on previous set
-        return Err(HevcError::Unsupported("inter-predicted ref pic set"));
+        let delta_poc_msb_present_flag = reader.read_bit()? != 0;
+        let mut delta_poc_msb_cycle = if delta_poc_msb_present_flag {
+            reader.read_ue()?
+        } else {
+            0
+        };
+        // DeltaPocMsbCycleLt accumulates within each of the two groups (7-52)
+        if i != 0 && i != num_long_term_sps {
+            delta_poc_msb_cycle = delta_poc_msb_cycle
+                .checked_add(refs[i - 1].delta_poc_msb_cycle)
+                .ok_or(HevcError::InvalidBitstream("DeltaPocMsbCycleLt overflow"))?;
+        }
+        refs.push(LongTermRef {
+            poc_lsb,
+            used_by_curr_pic,
+            delta_poc_msb_present_flag,
+            delta_poc_msb_cycle,
+        });
     }
+    Ok(refs)
+}
 
-    let num_negative_pics = reader.read_ue()?;
-    let num_positive_pics = reader.read_ue()?;
+/// Read num_ref_idx_lX_active_minus1 and return the number of active entries
+fn read_num_ref_idx_active(reader: &mut BitstreamReader<'_>) -> Result<u8> {
+    let minus1 = reader.read_ue()?;
+    if minus1 > 14 {
+        return Err(HevcError::InvalidBitstream("num_ref_idx_active out of range"));
+    }
+    Ok(minus1 as u8 + 1)
+}
 
-    for _ in 0..num_negative_pics {
-        reader.read_ue()?; // delta_poc_s0_minus1
-        reader.read_bit()?; // used_by_curr_pic_s0_flag
+/// Parse pred_weight_table() (7.3.6.3) and derive the weights (7.4.7.3)
+fn parse_pred_weight_table(
+    reader: &mut BitstreamReader<'_>,
+    sps: &Sps,
+    num_ref_idx_active: [u8; 2],
+) -> Result<PredWeightTable> {
+    let luma_log2_weight_denom = reader.read_ue()?;
+    if luma_log2_weight_denom > 7 {
+        return Err(HevcError::InvalidBitstream("luma_log2_weight_denom out of range"));
     }
+    let luma_log2_weight_denom = luma_log2_weight_denom as u8;
+    let has_chroma = sps.chroma_array_type() != 0;
+    let chroma_log2_weight_denom = if has_chroma {
+        let denom = luma_log2_weight_denom as i32 + reader.read_se()?;
+        if !(0..=7).contains(&denom) {
+            return Err(HevcError::InvalidBitstream("ChromaLog2WeightDenom out of range"));
+        }
+        denom as u8
+    } else {
+        0
+    };
+
+    let high_precision = sps.range_extension.high_precision_offsets_enabled_flag;
+    let (shift_y, half_range_y) = if high_precision {
+        (0, 1 << (sps.bit_depth_y() - 1))
+    } else {
+        (sps.bit_depth_y() - 8, 1 << 7)
+    };
+    let (shift_c, half_range_c) = if high_precision {
+        (0, 1i32 << (sps.bit_depth_c() - 1))
+    } else {
+        (sps.bit_depth_c() - 8, 1 << 7)
+    };
+
+    let mut weights = [Vec::new(), Vec::new()];
+    for (list, count) in num_ref_idx_active.iter().enumerate() {
+        let count = *count as usize;
+        let mut luma_flags = [false; 16];
+        let mut chroma_flags = [false; 16];
+        for flag in luma_flags.iter_mut().take(count) {
+            *flag = reader.read_bit()? != 0;
+        }
+        if has_chroma {
+            for flag in chroma_flags.iter_mut().take(count) {
+                *flag = reader.read_bit()? != 0;
+            }
+        }
 
-    for _ in 0..num_positive_pics {
-        reader.read_ue()?; // delta_poc_s1_minus1
-        reader.read_bit()?; // used_by_curr_pic_s1_flag
+        for i in 0..count {
+            let mut weight = PredWeight {
+                luma: (1 << luma_log2_weight_denom, 0),
+                chroma: [(1 << chroma_log2_weight_denom, 0); 2],
+            };
+            if luma_flags[i] {
+                let delta_weight = reader.read_se()?;
+                let offset = reader.read_se()?;
+                if !(-128..=127).contains(&delta_weight)
+                    || !(-half_range_y..half_range_y).contains(&offset)
+                {
+                    return Err(HevcError::InvalidBitstream("luma weight out of range"));
+                }
+                weight.luma = (
+                    (1 << luma_log2_weight_denom) + delta_weight,
+                    offset << shift_y,
+                );
+            }
+            if chroma_flags[i] {
+                for c in &mut weight.chroma {
+                    let delta_weight = reader.read_se()?;
+                    let delta_offset = reader.read_se()?;
+                    if !(-128..=127).contains(&delta_weight)
+                        || !(-4 * half_range_c..4 * half_range_c).contains(&delta_offset)
+                    {
+                        return Err(HevcError::InvalidBitstream("chroma weight out of range"));
+                    }
+                    let w = (1 << chroma_log2_weight_denom) + delta_weight;
+                    let offset = (half_range_c - ((half_range_c * w) >> chroma_log2_weight_denom)
+                        + delta_offset)
+                        .clamp(-half_range_c, half_range_c - 1);
+                    *c = (w, offset << shift_c);
+                }
+            }
+            weights[list].push(weight);
+        }
     }
 
-    Ok(())
+    Ok(PredWeightTable {
+        luma_log2_weight_denom,
+        chroma_log2_weight_denom,
+        weights,
+    })
 }
 
 /// Calculate ceil(log2(x))
-fn ceil_log2(x: u32) -> u8 {
+pub(super) fn ceil_log2(x: u32) -> u8 {
     if x <= 1 {
         0
     } else {
@@ -5,6 +5,8 @@
 //! - 4x4, 8x8, 16x16, 32x32 Inverse DCT
 //!
 //! The 8x8 and 16x16 IDCTs dispatch to AVX2 SIMD via `incant!` when available.
+//! The forward transform and quantization used by the encoder are plain
+//! scalar code.
 
 // Transform and inverse quantization for HEVC
 use archmage::incant;
@@ -642,6 +644,234 @@ pub fn inverse_transform(
     }
 }
 
+/// Forward transform of a residual block, the counterpart of
+/// [`inverse_transform`] (H.265 8.6.4.2 transposed, with the scaling of the
+/// HEVC test model)
+pub fn forward_transform(
+    residual: &[i16],
+    coeffs: &mut [i16],
+    size: usize,
+    bit_depth: u8,
+    is_intra_4x4_luma: bool,
+) {
+    let log2_size = size.trailing_zeros() as i32;
+    let shift1 = log2_size + bit_depth as i32 - 9;
+    let shift2 = log2_size + 6;
+    let basis = |k: usize, n: usize| -> i32 {
+        if is_intra_4x4_luma {
+            DST4_MATRIX[k][n] as i32
+        } else {
+            DCT32_MATRIX[k * 32 / size][n] as i32
+        }
+    };
+    let round = |sum: i32, shift: i32| {
+        if shift > 0 {
+            (sum + (1 << (shift - 1))) >> shift
+        } else {
+            sum
+        }
+    };
+
+    // First pass (horizontal)
+    let mut tmp = [0i32; MAX_COEFF];
+    for y in 0..size {
+        for u in 0..size {
+            let sum: i32 = (0..size)
+                .map(|x| basis(u, x) * residual[y * size + x] as i32)
+                .sum();
+            tmp[y * size + u] = round(sum, shift1);
+        }
+    }
+
+    // Second pass (vertical)
+    for v in 0..size {
+        for u in 0..size {
+            let sum: i32 = (0..size).map(|y| basis(v, y) * tmp[y * size + u]).sum();
+            coeffs[v * size + u] = round(sum, shift2).clamp(-32768, 32767) as i16;
+        }
+    }
+}
+
+/// Quantize transform coefficients with flat scaling, the counterpart of
+/// [`dequantize`]
+///
+/// Levels are rounded down unless their fraction exceeds `1 - rounding / 512`;
+/// about 171 (a third) suits intra blocks.
+pub fn quantize(coeffs: &mut [i16], params: DequantParams, rounding: i64) {
+    // Inverse of LEVEL_SCALE in units of 1/2^20
+    static QUANT_SCALE: [i64; 6] = [26214, 23302, 20560, 18396, 16384, 14564];
+
+    let transform_shift = 15 - params.bit_depth as i32 - params.log2_tr_size as i32;
+    let q_bits = 14 + params.qp / 6 + transform_shift;
+    let scale = QUANT_SCALE[(params.qp % 6) as usize];
+    let offset = rounding << (q_bits - 9);
+    for coef in coeffs.iter_mut() {
+        let level = (i64::from(coef.unsigned_abs()) * scale + offset) >> q_bits;
+        let level = level.min(32767) as i16;
+        *coef = if *coef < 0 { -level } else { level };
+    }
+}
+
+/// Cosine table for the 32-point DCT: round(64 * sqrt(2) * cos(j * pi / 64)) as
+/// specified by H.265 Eq. 8-319 (j = 0 only occurs for the DC row, which uses 64)
+static DCT32_COS: [i16; 33] = [
+    90, 90, 90, 90, 89, 88, 87, 85, 83, 82, 80, 78, 75, 73, 70, 67, 64, 61, 57, 54, 50, 46, 43, 38,
+    36, 31, 25, 22, 18, 13, 9, 4, 0,
+];
+
+/// Full 32x32 DCT-II matrix (H.265 8.6.4.2). Row k of the N-point transform is
+/// row k * 32 / N of this matrix, truncated to N columns.
+static DCT32_MATRIX: [[i16; 32]; 32] = build_dct32_matrix();
+
+const fn build_dct32_matrix() -> [[i16; 32]; 32] {
+    let mut m = [[0i16; 32]; 32];
+    let mut k = 0;
+    while k < 32 {
+        let mut n = 0;
+        while n < 32 {
+            m[k][n] = if k == 0 {
+                64
+            } else {
+                // cos(k * (2n + 1) * pi / 64), folded into the first quarter period
+                let j = (k * (2 * n + 1)) % 128;
+                let (j, sign) = if j >= 64 { (j - 64, -1) } else { (j, 1) };
+                if j <= 32 {
+                    sign * DCT32_COS[j]
+                } else {
+                    -sign * DCT32_COS[64 - j]
+                }
+            };
+            n += 1;
+        }
+        k += 1;
+    }
+    m
+}
+
+/// Dequantize in wide precision (H.265 8.6.3 with the range extension bdShift)
+///
+/// Used when coefficients may exceed 16 bits. `log2_range` is
+/// log2TransformRange from [`super::params::Sps::log2_transform_range`];
+/// results are clipped to CoeffMin..=CoeffMax.
+pub fn dequantize_wide(
+    coeffs: &mut [i32],
+    params: DequantParams,
+    scaling_matrix: Option<&[u8]>,
+    log2_range: u8,
+) {
+    static LEVEL_SCALE: [i64; 6] = [40, 45, 51, 57, 64, 72];
+
+    let qp_per = params.qp / 6;
+    let level_scale = LEVEL_SCALE[(params.qp % 6) as usize];
+    let coeff_max = (1i64 << log2_range) - 1;
+    let coeff_min = -(1i64 << log2_range);
+
+    // bdShift = BitDepth + Log2(nTbS) + 10 - log2TransformRange, always >= 5
+    let bd_shift = params.bit_depth as i32 + params.log2_tr_size as i32 + 10 - log2_range as i32;
+    let add = 1i64 << (bd_shift - 1);
+
+    for (i, coef) in coeffs.iter_mut().enumerate() {
+        if *coef == 0 {
+            continue;
+        }
+        let m = scaling_matrix
+            .and_then(|sm| sm.get(i).copied())
+            .unwrap_or(16) as i64;
+        let value = (((*coef as i64 * m * level_scale) << qp_per) + add) >> bd_shift;
+        *coef = value.clamp(coeff_min, coeff_max) as i32;
+    }
+}
+
+/// Inverse transform in wide precision (H.265 8.6.4.2)
+///
+/// Straight matrix multiply over [`DCT32_MATRIX`] (or the DST for intra 4x4
+/// luma) with 64-bit accumulation. The first stage clips to
+/// CoeffMin..=CoeffMax and the second uses
+/// bdShift = Max(20 - bitDepth, extended_precision ? 11 : 0), so residuals
+/// for bit depths above 12 are not truncated to 16 bits.
+pub fn inverse_transform_wide(
+    coeffs: &[i32],
+    output: &mut [i32],
+    size: usize,
+    bit_depth: u8,
+    is_intra_4x4_luma: bool,
+    log2_range: u8,
+    extended_precision: bool,
+) {
+    let row_step = 32 / size;
+    let basis = |k: usize, n: usize| -> i64 {
+        if is_intra_4x4_luma {
+            DST4_MATRIX[k][n] as i64
+        } else {
+            DCT32_MATRIX[k * row_step][n] as i64
+        }
+    };
+    let coeff_max = (1i64 << log2_range) - 1;
+    let coeff_min = -(1i64 << log2_range);
+    let bd_shift = (20 - bit_depth as i32).max(if extended_precision { 11 } else { 0 });
+    let add2 = if bd_shift > 0 {
+        1i64 << (bd_shift - 1)
+    } else {
+        0
+    };
+
+    let mut tmp = [0i64; MAX_COEFF];
+
+    // First stage (vertical): columns of coefficients to intermediate samples
+    for x in 0..size {
+        for y in 0..size {
+            let mut sum = 0i64;
+            for k in 0..size {
+                let c = coeffs[k * size + x];
+                if c != 0 {
+                    sum += basis(k, y) * c as i64;
+                }
+            }
+            tmp[y * size + x] = ((sum + 64) >> 7).clamp(coeff_min, coeff_max);
+        }
+    }
+
+    // Second stage (horizontal)
+    for y in 0..size {
+        let row = &tmp[y * size..y * size + size];
+        for x in 0..size {
+            let mut sum = 0i64;
+            for (k, &g) in row.iter().enumerate() {
+                sum += basis(k, x) * g;
+            }
+            output[y * size + x] = ((sum + add2) >> bd_shift) as i32;
+        }
+    }
+}
+
+/// Transform-skip residual in wide precision (H.265 8.6.4.2)
+///
+/// tsShift = (extended_precision ? Min(5, bdShift - 2) : 5) + Log2(nTbS).
+pub fn transform_skip_wide(
+    coeffs: &[i32],
+    output: &mut [i32],
+    log2_size: u8,
+    bit_depth: u8,
+    extended_precision: bool,
+) {
+    let bd_shift = (20 - bit_depth as i32).max(if extended_precision { 11 } else { 0 });
+    let ts_base = if extended_precision {
+        5.min(bd_shift - 2)
+    } else {
+        5
+    };
+    let ts_shift = ts_base + log2_size as i32;
+    let rnd = if bd_shift > 0 {
+        1i64 << (bd_shift - 1)
+    } else {
+        0
+    };
+    let num_coeffs = 1usize << (2 * log2_size);
+    for (out, &c) in output[..num_coeffs].iter_mut().zip(&coeffs[..num_coeffs]) {
+        *out = ((((c as i64) << ts_shift) + rnd) >> bd_shift) as i32;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -671,6 +901,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_inverse_transform_wide_matches_16bit() {
+        // For 8-bit video without extended precision the wide path must be bit-exact
+        for (size, is_dst) in [(4, true), (4, false), (8, false), (16, false), (32, false)] {
+            let mut narrow = [0i16; MAX_COEFF];
+            let mut wide = [0i32; MAX_COEFF];
+            let mut seed = 0x1234_5678u32;
+            for i in (0..size * size).step_by(3) {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let v = ((seed >> 16) % 512) as i32 - 256;
+                narrow[i] = v as i16;
+                wide[i] = v;
+            }
+
+            let mut out16 = [0i16; MAX_COEFF];
+            let mut out32 = [0i32; MAX_COEFF];
+            inverse_transform(&narrow, &mut out16, size, 8, is_dst);
+            inverse_transform_wide(&wide, &mut out32, size, 8, is_dst, 15, false);
+            for i in 0..size * size {
+                assert_eq!(out16[i] as i32, out32[i], "size {size} index {i}");
+            }
+        }
+    }
+
     #[test]
     fn test_idst4_dc_only() {
         let mut coeffs = [0i16; 16];
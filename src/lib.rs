@@ -41,14 +41,34 @@ extern crate alloc;
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
+mod annexb;
+mod edit;
+mod encode;
 mod error;
+mod exif;
+mod hdr;
 #[doc(hidden)]
 pub mod heif;
 #[doc(hidden)]
 pub mod hevc;
-
+#[cfg(feature = "std")]
+mod reader;
+mod ranges;
+mod resize;
+mod sequence;
+mod sink;
+
+pub use annexb::HevcBitstream;
+pub use edit::{HeifEditor, Orientation};
+pub use encode::EncoderConfig;
 pub use error::{HeicError, HevcError, ProbeError, Result};
-pub use hevc::DecodedFrame;
+pub use hdr::{HdrImage, HdrTransfer};
+pub use hevc::{DecodedFrame, HdrStaticMetadata};
+pub use ranges::{ByteRange, FilePart, HeaderStatus, RangePlanner};
+#[cfg(feature = "std")]
+pub use reader::HeifReader;
+pub use sequence::{ImageSequence, SequenceFrame};
+pub use sink::ImageSink;
 
 // Re-export Stop and Unstoppable for ergonomics
 pub use enough::{Stop, StopReason, Unstoppable};
@@ -75,6 +95,12 @@ pub enum PixelLayout {
     Bgr8,
     /// 4 bytes per pixel: blue, green, red, alpha
     Bgra8,
+    /// 6 bytes per pixel: red, green, blue as native-endian `u16`,
+    /// scaled to the full 0-65535 range regardless of source bit depth
+    Rgb16,
+    /// 8 bytes per pixel: red, green, blue, alpha as native-endian `u16`,
+    /// scaled to the full 0-65535 range regardless of source bit depth
+    Rgba16,
 }
 
 impl PixelLayout {
@@ -84,13 +110,15 @@ impl PixelLayout {
         match self {
             Self::Rgb8 | Self::Bgr8 => 3,
             Self::Rgba8 | Self::Bgra8 => 4,
+            Self::Rgb16 => 6,
+            Self::Rgba16 => 8,
         }
     }
 
     /// Whether this layout includes an alpha channel
     #[must_use]
     pub const fn has_alpha(self) -> bool {
-        matches!(self, Self::Rgba8 | Self::Bgra8)
+        matches!(self, Self::Rgba8 | Self::Bgra8 | Self::Rgba16)
     }
 }
 
@@ -166,6 +194,29 @@ pub struct DecodeOutput {
     pub height: u32,
     /// Pixel layout of the output data
     pub layout: PixelLayout,
+    /// Content light level and mastering display colour volume of the image
+    pub hdr_metadata: HdrStaticMetadata,
+    /// A picture of the image did not match its decoded picture hash SEI
+    /// (only set with [`PictureHashCheck::Warn`])
+    pub picture_hash_mismatch: bool,
+}
+
+/// Image that [`DecoderConfig::decode_for_size`] decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeSource {
+    /// The primary image
+    Primary,
+    /// A thumbnail item of the primary image
+    Thumbnail {
+        /// Item ID of the thumbnail
+        item_id: u32,
+    },
+    /// A layer of an image pyramid (`pymd` group) containing the primary image
+    PyramidLayer {
+        /// Item ID of the layer image
+        item_id: u32,
+    },
 }
 
 /// Image metadata without full decode
@@ -187,43 +238,147 @@ pub struct ImageInfo {
     pub has_xmp: bool,
     /// Whether the file contains a thumbnail image
     pub has_thumbnail: bool,
+    /// Content light level and mastering display colour volume, from the
+    /// `clli` and `mdcv` properties or the SEI in the `hvcC`
+    pub hdr_metadata: HdrStaticMetadata,
 }
 
 impl ImageInfo {
-    /// Minimum bytes needed to attempt header parsing.
+    /// Typical number of leading bytes holding the header.
     ///
-    /// HEIF containers have variable-length headers, so this is a typical
-    /// minimum. [`from_bytes`](Self::from_bytes) may return
-    /// [`ProbeError::NeedMoreData`] if the header extends beyond this.
+    /// HEIF containers have variable-length headers, so this is only a first
+    /// guess; files that put `meta` after `mdat` need much more.
+    /// [`from_bytes`](Self::from_bytes) returns [`ProbeError::NeedMoreData`]
+    /// with the bytes it needs if the header extends beyond the data given.
     pub const PROBE_BYTES: usize = 4096;
 
-    /// Parse image metadata from a byte slice without full decoding.
+    /// Parse image metadata from the first bytes of a file without full
+    /// decoding.
     ///
     /// This only parses the HEIF container and HEVC parameter sets,
     /// without decoding any pixel data.
     ///
     /// # Errors
     ///
-    /// Returns [`ProbeError::NeedMoreData`] if the buffer is too small,
-    /// [`ProbeError::InvalidFormat`] if this is not a HEIC/HEIF file,
-    /// or [`ProbeError::Corrupt`] if the header is malformed.
+    /// Returns [`ProbeError::NeedMoreData`] with the next bytes needed if
+    /// the buffer is too short, [`ProbeError::InvalidFormat`] if this is not
+    /// a HEIC/HEIF file, or [`ProbeError::Corrupt`] if the header is
+    /// malformed. When `meta` follows a large `mdat`, the bytes needed are
+    /// past the `mdat`; [`from_head_and_tail`](Self::from_head_and_tail)
+    /// can probe without the data between.
     pub fn from_bytes(data: &[u8]) -> core::result::Result<Self, ProbeError> {
-        if data.len() < 12 {
-            return Err(ProbeError::NeedMoreData);
+        // Quick format check: HEIF files start with ftyp box
+        if data.len() >= 8 && &data[4..8] != b"ftyp" {
+            return Err(ProbeError::InvalidFormat);
         }
 
-        // Quick format check: HEIF files start with ftyp box
-        let box_type = &data[4..8];
-        if box_type != b"ftyp" {
+        let mut planner = RangePlanner::with_unknown_len();
+        let status = planner.push(0, data).map_err(corrupt)?;
+        if let HeaderStatus::NeedMoreData { offset, len } = status {
+            // Image sequence files without a meta box: describe the track
+            if data.len() >= 12
+                && let Ok(container) = heif::parse(data)
+                && let Some(info) = Self::from_track(&container)?
+            {
+                return Ok(info);
+            }
+            return Err(ProbeError::NeedMoreData { offset, len });
+        }
+
+        let container = heif::parse(data).map_err(corrupt)?;
+        match Self::from_container(&container)? {
+            Some(info) => Ok(info),
+            None => Err(missing_image_data(&planner, &[(0, data)])),
+        }
+    }
+
+    /// Parse image metadata from the start and the end of a file of
+    /// `file_len` bytes, without the data between.
+    ///
+    /// Writers that stream image data often put `meta` after `mdat`, at the
+    /// end of the file. A head window of [`PROBE_BYTES`](Self::PROBE_BYTES)
+    /// and a tail window of similar size (`tail` holds the last `tail.len()`
+    /// bytes) then describe the image in one round trip either way.
+    ///
+    /// # Errors
+    ///
+    /// As [`from_bytes`](Self::from_bytes); [`ProbeError::NeedMoreData`]
+    /// asks for bytes outside both windows.
+    pub fn from_head_and_tail(
+        head: &[u8],
+        tail: &[u8],
+        file_len: u64,
+    ) -> core::result::Result<Self, ProbeError> {
+        if head.len() >= 8 && &head[4..8] != b"ftyp" {
             return Err(ProbeError::InvalidFormat);
         }
+        let tail_offset = file_len
+            .checked_sub(tail.len() as u64)
+            .ok_or(ProbeError::Corrupt(HeicError::InvalidData("tail longer than file")))?;
+        let windows = [(0, head), (tail_offset, tail)];
+
+        let mut planner = RangePlanner::new(file_len);
+        planner.push(0, head).map_err(corrupt)?;
+        let status = planner.push(tail_offset, tail).map_err(corrupt)?;
+        if let HeaderStatus::NeedMoreData { offset, len } = status {
+            return Err(ProbeError::NeedMoreData { offset, len });
+        }
 
-        let container = heif::parse(data).map_err(|e| ProbeError::Corrupt(e.into_inner()))?;
+        let container = heif::parse(planner.header()).map_err(corrupt)?;
+        if let Some(info) = Self::from_container(&container)? {
+            return Ok(info);
+        }
+        // The parameter sets are only in the image data
+        let file = planner
+            .ranges(FilePart::PrimaryImage)
+            .and_then(|ranges| {
+                let fetched: Vec<_> = ranges
+                    .unwrap_or_default()
+                    .iter()
+                    .map_while(|range| window_range(&windows, *range))
+                    .collect();
+                planner.assemble(FilePart::PrimaryImage, &fetched)
+            })
+            .map_err(|_| missing_image_data(&planner, &windows))?;
+        let container = heif::parse(&file).map_err(corrupt)?;
+        Self::from_container(&container)?.ok_or_else(|| missing_image_data(&planner, &windows))
+    }
 
-        let primary_item = container
-            .primary_item()
-            .ok_or(ProbeError::Corrupt(HeicError::NoPrimaryImage))?;
+    /// Describe the first image sequence track, if any
+    fn from_track(
+        container: &heif::HeifContainer<'_>,
+    ) -> core::result::Result<Option<Self>, ProbeError> {
+        let Some(config) = container
+            .sequence_track()
+            .and_then(|track| track.sample_entries.iter().find_map(|e| e.hevc_config.as_ref()))
+        else {
+            return Ok(None);
+        };
+        let hevc_info = hevc::get_info_from_config(config)
+            .map_err(|e| ProbeError::Corrupt(HeicError::from(e)))?;
+        Ok(Some(ImageInfo {
+            width: hevc_info.width,
+            height: hevc_info.height,
+            has_alpha: false,
+            bit_depth: hevc_info.bit_depth,
+            chroma_format: hevc_info.chroma_format,
+            has_exif: false,
+            has_xmp: false,
+            has_thumbnail: false,
+            hdr_metadata: hevc::hdr_metadata_from_config(config),
+        }))
+    }
 
+    /// Describe the primary image of a parsed container, or `None` if that
+    /// needs image data the container does not hold
+    fn from_container(
+        container: &heif::HeifContainer<'_>,
+    ) -> core::result::Result<Option<Self>, ProbeError> {
+        let Some(primary_item) = container.primary_item() else {
+            return Self::from_track(container)?
+                .map(Some)
+                .ok_or(ProbeError::Corrupt(HeicError::NoPrimaryImage));
+        };
         // Check for alpha auxiliary image
         let has_alpha = !container
             .find_auxiliary_items(primary_item.id, "urn:mpeg:hevc:2015:auxid:1")
@@ -245,23 +400,25 @@ impl ImageInfo {
                 && (i.content_type.contains("xmp") || i.content_type.contains("rdf+xml"))
         });
         let has_thumbnail = !container.find_thumbnails(primary_item.id).is_empty();
+        let hdr_metadata = probe_hdr_metadata(container, &primary_item);
 
-        // Try to get info from HEVC config (fast path for direct HEVC items)
-        if let Some(ref config) = primary_item.hevc_config
+        // Try to get info from HEVC config (fast path for direct HEVC items;
+        // the hvcC of a layered item only describes its base layer)
+        if primary_item.item_type != ItemType::Lhv1
+            && let Some(ref config) = primary_item.hevc_config
             && let Ok(hevc_info) = hevc::get_info_from_config(config)
         {
-            let bit_depth = config.bit_depth_luma_minus8 + 8;
-            let chroma_format = config.chroma_format;
-            return Ok(ImageInfo {
+            return Ok(Some(ImageInfo {
                 width: hevc_info.width,
                 height: hevc_info.height,
                 has_alpha,
-                bit_depth,
-                chroma_format,
+                bit_depth: hevc_info.bit_depth,
+                chroma_format: hevc_info.chroma_format,
                 has_exif,
                 has_xmp,
                 has_thumbnail,
-            });
+                hdr_metadata,
+            }));
         }
 
         // For grid/iden/iovl: get dimensions from ispe, bit depth from first tile's hvcC
@@ -283,7 +440,7 @@ impl ImageInfo {
                     break;
                 }
             }
-            return Ok(ImageInfo {
+            return Ok(Some(ImageInfo {
                 width: w,
                 height: h,
                 has_alpha,
@@ -292,27 +449,29 @@ impl ImageInfo {
                 has_exif,
                 has_xmp,
                 has_thumbnail,
-            });
+                hdr_metadata,
+            }));
         }
 
         // Fallback to reading image data
-        let image_data = container
-            .get_item_data(primary_item.id)
-            .ok_or(ProbeError::NeedMoreData)?;
+        let Some(image_data) = container.get_item_data(primary_item.id) else {
+            return Ok(None);
+        };
 
         let hevc_info =
             hevc::get_info(image_data).map_err(|e| ProbeError::Corrupt(HeicError::from(e)))?;
 
-        Ok(ImageInfo {
+        Ok(Some(ImageInfo {
             width: hevc_info.width,
             height: hevc_info.height,
             has_alpha,
-            bit_depth: 8,
-            chroma_format: 1,
+            bit_depth: hevc_info.bit_depth,
+            chroma_format: hevc_info.chroma_format,
             has_exif,
             has_xmp,
             has_thumbnail,
-        })
+            hdr_metadata,
+        }))
     }
 
     /// Calculate the required output buffer size for a given pixel layout.
@@ -326,6 +485,37 @@ impl ImageInfo {
     }
 }
 
+fn corrupt(e: At<HeicError>) -> ProbeError {
+    ProbeError::Corrupt(e.into_inner())
+}
+
+/// The bytes of `range`, if one of the (offset, data) `windows` holds it all
+fn window_range<'a>(windows: &[(u64, &'a [u8])], range: ByteRange) -> Option<&'a [u8]> {
+    windows.iter().find_map(|&(offset, data)| {
+        let start = usize::try_from(range.offset.checked_sub(offset)?).ok()?;
+        data.get(start..start.checked_add(usize::try_from(range.len).ok()?)?)
+    })
+}
+
+/// [`ProbeError::NeedMoreData`] for the first range of the primary image's
+/// data missing from `windows`
+fn missing_image_data(planner: &RangePlanner, windows: &[(u64, &[u8])]) -> ProbeError {
+    match planner.ranges(FilePart::PrimaryImage) {
+        Ok(ranges) => ranges
+            .unwrap_or_default()
+            .into_iter()
+            .find(|range| window_range(windows, *range).is_none())
+            .map_or(
+                ProbeError::Corrupt(HeicError::InvalidData("missing image data")),
+                |range| ProbeError::NeedMoreData {
+                    offset: range.offset,
+                    len: range.len,
+                },
+            ),
+        Err(e) => corrupt(e),
+    }
+}
+
 /// HDR gain map data extracted from an auxiliary image.
 ///
 /// The gain map can be used with the Apple HDR formula to reconstruct HDR:
@@ -335,7 +525,9 @@ impl ImageInfo {
 /// scale = 1.0 + (headroom - 1.0) * gainmap_linear
 /// hdr_linear = sdr_linear * scale
 /// ```
-/// Where `headroom` comes from EXIF maker notes (tags 0x0021 and 0x0030).
+/// Where `headroom` comes from XMP or EXIF maker notes (tags 0x0021 and
+/// 0x0030). [`DecoderConfig::decode_hdr`] applies this formula, with the
+/// headroom from [`DecoderConfig::gain_map_headroom`].
 #[derive(Debug, Clone)]
 pub struct HdrGainMap {
     /// Gain map pixel data normalized to 0.0-1.0
@@ -361,7 +553,27 @@ pub struct HdrGainMap {
 /// ```
 #[derive(Debug, Clone)]
 pub struct DecoderConfig {
-    _private: (),
+    picture_hash_check: PictureHashCheck,
+    film_grain_seed: Option<u32>,
+}
+
+/// Checking of decoded pictures against their decoded picture hash SEI.
+///
+/// The hash (MD5, CRC or checksum, whichever the encoder sent) is computed
+/// over the reconstructed planes after deblocking and SAO, and compared for
+/// every coded image and grid tile that carries one. Pictures without a hash
+/// are not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PictureHashCheck {
+    /// Hashes are ignored.
+    #[default]
+    Off,
+    /// A mismatch sets the `picture_hash_mismatch` flag of the output
+    /// ([`DecodedFrame`], [`DecodeOutput`] or [`HdrImage`])
+    /// and decoding continues.
+    Warn,
+    /// A mismatch fails the decode with [`HevcError::PictureHashMismatch`].
+    Error,
 }
 
 impl Default for DecoderConfig {
@@ -374,7 +586,37 @@ impl DecoderConfig {
     /// Create a new decoder configuration with sensible defaults.
     #[must_use]
     pub fn new() -> Self {
-        Self { _private: () }
+        Self {
+            picture_hash_check: PictureHashCheck::Off,
+            film_grain_seed: None,
+        }
+    }
+
+    /// Check decoded pictures against their decoded picture hash SEI.
+    ///
+    /// Default is [`PictureHashCheck::Off`]. Checking a picture costs about
+    /// as much as converting it to RGB.
+    #[must_use]
+    pub fn with_picture_hash_check(mut self, check: PictureHashCheck) -> Self {
+        self.picture_hash_check = check;
+        self
+    }
+
+    /// Add film grain described by film grain characteristics SEI.
+    ///
+    /// Off by default. The grain is generated from `seed`, so decoding the
+    /// same file with the same seed always gives the same pixels. It is
+    /// added after the decoded picture hash check, to still items and to
+    /// every frame of an image sequence (seeded with the item ID or sample
+    /// index as well).
+    ///
+    /// The grain is not bit-exact with other decoders. The cross-component
+    /// correlation and aspect ratio model values of the auto-regressive
+    /// model are ignored.
+    #[must_use]
+    pub fn with_film_grain(mut self, seed: u32) -> Self {
+        self.film_grain_seed = Some(seed);
+        self
     }
 
     /// One-shot decode: decode HEIC data to pixels in the requested layout.
@@ -403,11 +645,12 @@ impl DecoderConfig {
     #[must_use]
     pub fn decode_request<'a>(&'a self, data: &'a [u8]) -> DecodeRequest<'a> {
         DecodeRequest {
-            _config: self,
+            config: self,
             data,
             layout: PixelLayout::Rgba8,
             limits: None,
             stop: None,
+            layer: None,
         }
     }
 
@@ -421,7 +664,21 @@ impl DecoderConfig {
     ///
     /// Returns an error if the data is not valid HEIC/HEIF format.
     pub fn decode_to_frame(&self, data: &[u8]) -> Result<hevc::DecodedFrame> {
-        decode_to_frame_inner(data, None, &Unstoppable)
+        decode_to_frame_inner(data, None, &Unstoppable, None, self)
+    }
+
+    /// Decode the first HEVC image sequence track (`.heics`, `msf1`).
+    ///
+    /// Returns an iterator over the frames in presentation order, with
+    /// timestamps from the track's sample table and edit list.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not valid HEIF format or has no
+    /// HEVC image sequence track. Decoding errors are returned by the
+    /// iterator.
+    pub fn decode_sequence<'a>(&'a self, data: &'a [u8]) -> Result<ImageSequence<'a>> {
+        ImageSequence::new(data, self)
     }
 
     /// Estimate the peak memory usage for decoding an image of given dimensions.
@@ -468,6 +725,63 @@ impl DecoderConfig {
         decode_gain_map_inner(data)
     }
 
+    /// Read the HDR headroom of an Apple HDR HEIC file.
+    ///
+    /// The headroom is the linear gain of a full-strength gain map pixel
+    /// over SDR white. It is read from XMP (`HDRGainMap:HDRGainMapHeadroom`)
+    /// or derived from the EXIF maker note tags 0x0021 and 0x0030. Returns
+    /// `None` if the file has neither.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEIF container is malformed.
+    pub fn gain_map_headroom(&self, data: &[u8]) -> Result<Option<f32>> {
+        gain_map_headroom_inner(data)
+    }
+
+    /// Reconstruct linear HDR from an Apple HDR HEIC file.
+    ///
+    /// The primary image is linearised with the sRGB transfer function and
+    /// scaled by its gain map, which is oriented like the primary image and
+    /// upsampled to its size. `display_headroom` is the linear headroom of
+    /// the target display; when it is below the content's headroom the gain
+    /// is reduced in the log domain, and 1.0 gives the SDR image.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file has no gain map or headroom metadata,
+    /// or decoding fails.
+    pub fn decode_hdr(&self, data: &[u8], display_headroom: f32) -> Result<HdrImage> {
+        Ok(decode_hdr_inner(data, display_headroom, self)?.0)
+    }
+
+    /// Reconstruct HDR from an Apple HDR HEIC file as 16-bit PQ or HLG.
+    ///
+    /// Like [`decode_hdr`](Self::decode_hdr), with the linear output encoded
+    /// by `transfer` into [`PixelLayout::Rgb16`]. The colour primaries are
+    /// those of the base image.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file has no gain map or headroom metadata,
+    /// or decoding fails.
+    pub fn decode_hdr_with_transfer(
+        &self,
+        data: &[u8],
+        display_headroom: f32,
+        transfer: HdrTransfer,
+    ) -> Result<DecodeOutput> {
+        let (image, hdr_metadata) = decode_hdr_inner(data, display_headroom, self)?;
+        Ok(DecodeOutput {
+            data: hdr::encode(&image, transfer),
+            width: image.width,
+            height: image.height,
+            layout: PixelLayout::Rgb16,
+            hdr_metadata,
+            picture_hash_mismatch: image.picture_hash_mismatch,
+        })
+    }
+
     /// Extract raw EXIF (TIFF) data from a HEIC file.
     ///
     /// Returns the TIFF-header data (starting with byte-order mark `II` or `MM`)
@@ -498,6 +812,24 @@ impl DecoderConfig {
         extract_xmp_inner(data)
     }
 
+    /// Extract the HEVC bitstream of an image item in Annex B format.
+    ///
+    /// Returns one bitstream for a coded (`hvc1` or `lhv1`) item and one per
+    /// tile, in row-major order, for a grid. Each holds the item's decoder
+    /// configuration NAL units (parameter sets first, SEI kept) followed by
+    /// its own NAL units, with suffix SEI after them, ready for hardware or
+    /// external decoders. An `lhv1` item's stream also carries the lower
+    /// layers it references with `tbas`, base layer first. The primary
+    /// item's ID is `heif::parse(data)?.primary_item_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEIF container is malformed, there is no such
+    /// item, the item is not a coded image or grid, or its data is truncated.
+    pub fn extract_hevc_annexb(&self, data: &[u8], item_id: u32) -> Result<Vec<HevcBitstream>> {
+        annexb::extract_hevc_annexb(data, item_id)
+    }
+
     /// Decode the thumbnail image from a HEIC file.
     ///
     /// Returns the decoded thumbnail as a `DecodeOutput` in the requested layout,
@@ -508,7 +840,52 @@ impl DecoderConfig {
     ///
     /// Returns an error if the HEIF container is malformed or thumbnail decoding fails.
     pub fn decode_thumbnail(&self, data: &[u8], layout: PixelLayout) -> Result<Option<DecodeOutput>> {
-        decode_thumbnail_inner(data, layout)
+        self.decode_request(data)
+            .with_output_layout(layout)
+            .decode_thumbnail()
+    }
+
+    /// Decode an image of at least the given size.
+    ///
+    /// Picks the cheapest source that covers `target_width` x `target_height`
+    /// (a thumbnail, an image pyramid layer or the primary image) and
+    /// downscales it to the smallest size that covers the target while keeping
+    /// the aspect ratio. A target dimension of 0 is unconstrained. If no source
+    /// is large enough, the primary image is returned at full size.
+    ///
+    /// Returns the decoded image and the source it came from.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEIF container is malformed or decoding fails.
+    pub fn decode_for_size(
+        &self,
+        data: &[u8],
+        target_width: u32,
+        target_height: u32,
+        layout: PixelLayout,
+    ) -> Result<(DecodeOutput, DecodeSource)> {
+        self.decode_request(data)
+            .with_output_layout(layout)
+            .decode_for_size(target_width, target_height)
+    }
+
+    /// Decode the left and right views of a stereo pair.
+    ///
+    /// Returns the images of the first `ster` entity group as `(left, right)`,
+    /// or `None` if the file has no stereo pair.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEIF container is malformed or decoding fails.
+    pub fn decode_stereo_pair(
+        &self,
+        data: &[u8],
+        layout: PixelLayout,
+    ) -> Result<Option<(DecodeOutput, DecodeOutput)>> {
+        self.decode_request(data)
+            .with_output_layout(layout)
+            .decode_stereo_pair()
     }
 }
 
@@ -518,11 +895,12 @@ impl DecoderConfig {
 /// configure, then call [`decode`](Self::decode) or
 /// [`decode_into`](Self::decode_into).
 pub struct DecodeRequest<'a> {
-    _config: &'a DecoderConfig,
+    config: &'a DecoderConfig,
     data: &'a [u8],
     layout: PixelLayout,
     limits: Option<&'a Limits>,
     stop: Option<&'a dyn Stop>,
+    layer: Option<u8>,
 }
 
 impl<'a> DecodeRequest<'a> {
@@ -555,6 +933,17 @@ impl<'a> DecodeRequest<'a> {
         self
     }
 
+    /// Select the layer to decode from a layered (L-HEVC) primary image.
+    ///
+    /// Overrides the layer chosen by the item's `lsel`/`tols` properties.
+    /// Layers that the selected layer predicts from are decoded along with
+    /// it, but only the selected layer is output.
+    #[must_use]
+    pub fn with_layer(mut self, layer_id: u8) -> Self {
+        self.layer = Some(layer_id);
+        self
+    }
+
     /// Execute the decode and return pixel data.
     ///
     /// # Errors
@@ -563,32 +952,55 @@ impl<'a> DecodeRequest<'a> {
     /// or the operation is cancelled.
     pub fn decode(self) -> Result<DecodeOutput> {
         let stop: &dyn Stop = self.stop.unwrap_or(&Unstoppable);
-        let frame = decode_to_frame_inner(self.data, self.limits, stop)?;
-
-        let width = frame.cropped_width();
-        let height = frame.cropped_height();
+        let frame = decode_to_frame_inner(self.data, self.limits, stop, self.layer, self.config)?;
+        frame_to_output(&frame, self.layout, self.limits)
+    }
 
-        // Check limits on final output dimensions
-        if let Some(limits) = self.limits {
-            limits.check_dimensions(width, height)?;
-            let output_bytes =
-                u64::from(width) * u64::from(height) * self.layout.bytes_per_pixel() as u64;
-            limits.check_memory(output_bytes)?;
-        }
+    /// Decode the primary image's thumbnail instead of the primary image.
+    ///
+    /// Returns `None` if no thumbnail is present. The request's limits and
+    /// cancellation token apply to the thumbnail.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEIF container is malformed, a limit is
+    /// exceeded, the operation is cancelled or thumbnail decoding fails.
+    pub fn decode_thumbnail(self) -> Result<Option<DecodeOutput>> {
+        let stop: &dyn Stop = self.stop.unwrap_or(&Unstoppable);
+        decode_thumbnail_inner(self.data, self.layout, self.limits, stop, self.config)
+    }
 
-        let data = match self.layout {
-            PixelLayout::Rgb8 => frame.to_rgb(),
-            PixelLayout::Rgba8 => frame.to_rgba(),
-            PixelLayout::Bgr8 => frame.to_bgr(),
-            PixelLayout::Bgra8 => frame.to_bgra(),
-        };
+    /// Decode an image of at least the given size, as
+    /// [`DecoderConfig::decode_for_size`] does.
+    ///
+    /// The request's limits and cancellation token apply to whichever
+    /// source is decoded, and its layer selection to the primary image.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEIF container is malformed, a limit is
+    /// exceeded, the operation is cancelled or decoding fails.
+    pub fn decode_for_size(
+        self,
+        target_width: u32,
+        target_height: u32,
+    ) -> Result<(DecodeOutput, DecodeSource)> {
+        decode_for_size_inner(self, target_width, target_height)
+    }
 
-        Ok(DecodeOutput {
-            data,
-            width,
-            height,
-            layout: self.layout,
-        })
+    /// Decode the left and right views of a stereo pair instead of the
+    /// primary image.
+    ///
+    /// Returns `None` if the file has no stereo pair. The request's limits
+    /// and cancellation token apply to each view.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HEIF container is malformed, a limit is
+    /// exceeded, the operation is cancelled or decoding fails.
+    pub fn decode_stereo_pair(self) -> Result<Option<(DecodeOutput, DecodeOutput)>> {
+        let stop: &dyn Stop = self.stop.unwrap_or(&Unstoppable);
+        decode_stereo_pair_inner(self.data, self.layout, self.limits, stop, self.config)
     }
 
     /// Decode directly into a pre-allocated buffer.
@@ -604,7 +1016,7 @@ impl<'a> DecodeRequest<'a> {
     /// or other errors if decoding fails.
     pub fn decode_into(self, output: &mut [u8]) -> Result<ImageInfo> {
         let stop: &dyn Stop = self.stop.unwrap_or(&Unstoppable);
-        let frame = decode_to_frame_inner(self.data, self.limits, stop)?;
+        let frame = decode_to_frame_inner(self.data, self.limits, stop, self.layer, self.config)?;
 
         let width = frame.cropped_width();
         let height = frame.cropped_height();
@@ -636,6 +1048,12 @@ impl<'a> DecodeRequest<'a> {
             PixelLayout::Bgra8 => {
                 frame.write_bgra_into(output);
             }
+            PixelLayout::Rgb16 => {
+                frame.write_rgb16_into(output);
+            }
+            PixelLayout::Rgba16 => {
+                frame.write_rgba16_into(output);
+            }
         }
 
         Ok(ImageInfo {
@@ -647,6 +1065,7 @@ impl<'a> DecodeRequest<'a> {
             has_exif: false, // Use ImageInfo::from_bytes() for metadata probing
             has_xmp: false,
             has_thumbnail: false,
+            hdr_metadata: frame.hdr_metadata,
         })
     }
 
@@ -661,7 +1080,120 @@ impl<'a> DecodeRequest<'a> {
     /// or the operation is cancelled.
     pub fn decode_yuv(self) -> Result<hevc::DecodedFrame> {
         let stop: &dyn Stop = self.stop.unwrap_or(&Unstoppable);
-        decode_to_frame_inner(self.data, self.limits, stop)
+        decode_to_frame_inner(self.data, self.limits, stop, self.layer, self.config)
+    }
+
+    /// Decode into a streaming [`ImageSink`].
+    ///
+    /// Grid images are decoded one row of tiles at a time, and each tile is
+    /// colour-converted and transformed on its own and passed to
+    /// [`ImageSink::write_tile`], so peak memory stays close to one row of
+    /// tiles instead of the whole image. An alpha grid with the same tiling
+    /// and transforms is streamed along with them; any other alpha image is
+    /// decoded in full up front.
+    /// Other images are written through [`ImageSink::write_rows`]: single
+    /// coded images without alpha, rotation or mirroring are handed out in
+    /// bands of rows while they decode, as soon as the in-loop filters are
+    /// done with them; the rest are decoded in full first.
+    ///
+    /// Returns the image info of the output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is invalid, a limit is exceeded, the
+    /// operation is cancelled, or the sink returns an error.
+    pub fn decode_to_sink(self, sink: &mut dyn ImageSink) -> Result<ImageInfo> {
+        let stop: &dyn Stop = self.stop.unwrap_or(&Unstoppable);
+        let limits = self.limits.unwrap_or(&NO_LIMITS);
+
+        let container = heif::parse(self.data)?;
+        if let Some(item) = container.primary_item() {
+            if item.item_type == ItemType::Grid {
+                return stream_grid(
+                    &container,
+                    &item,
+                    self.layout,
+                    limits,
+                    stop,
+                    self.config,
+                    sink,
+                );
+            }
+            // Grain is added to whole pictures, so those are not streamed
+            if self.layer.is_none()
+                && self.config.film_grain_seed.is_none()
+                && streams_rows(&container, &item)
+            {
+                return stream_rows(
+                    &container,
+                    &item,
+                    self.layout,
+                    limits,
+                    stop,
+                    self.config,
+                    sink,
+                );
+            }
+        }
+
+        let frame = decode_to_frame_inner(self.data, self.limits, stop, self.layer, self.config)?;
+        let (width, height) = (frame.cropped_width(), frame.cropped_height());
+        limits.check_dimensions(width, height)?;
+        limits.check_memory(
+            u64::from(width) * u64::from(height) * self.layout.bytes_per_pixel() as u64,
+        )?;
+        sink.begin(width, height, self.layout)?;
+        sink.write_rows(0, height, &frame_to_layout(&frame, self.layout))?;
+
+        Ok(ImageInfo {
+            width,
+            height,
+            has_alpha: frame.alpha_plane.is_some(),
+            bit_depth: frame.bit_depth,
+            chroma_format: frame.chroma_format,
+            has_exif: false, // Use ImageInfo::from_bytes() for metadata probing
+            has_xmp: false,
+            has_thumbnail: false,
+            hdr_metadata: frame.hdr_metadata,
+        })
+    }
+
+    /// Decode a rectangle of the image.
+    ///
+    /// The rectangle is in the coordinates of the final image, after its
+    /// `clap`, `irot` and `imir` transforms. For grid images only the tiles
+    /// that intersect the rectangle are decoded, along with the matching
+    /// tiles of an alpha grid; other images are decoded in full and cropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rectangle is empty or extends past the image,
+    /// the data is invalid, a limit is exceeded, or the operation is cancelled.
+    pub fn decode_region(self, x: u32, y: u32, width: u32, height: u32) -> Result<DecodeOutput> {
+        let stop: &dyn Stop = self.stop.unwrap_or(&Unstoppable);
+        let frame = decode_region_inner(
+            self.data,
+            (x, y, width, height),
+            self.limits,
+            stop,
+            self.layer,
+            self.config,
+        )?;
+
+        if let Some(limits) = self.limits {
+            let output_bytes =
+                u64::from(width) * u64::from(height) * self.layout.bytes_per_pixel() as u64;
+            limits.check_memory(output_bytes)?;
+        }
+
+        Ok(DecodeOutput {
+            data: frame_to_layout(&frame, self.layout),
+            width,
+            height,
+            layout: self.layout,
+            hdr_metadata: frame.hdr_metadata,
+            picture_hash_mismatch: frame.picture_hash_mismatch,
+        })
     }
 }
 
@@ -704,49 +1236,199 @@ fn decode_to_frame_inner(
     data: &[u8],
     limits: Option<&Limits>,
     stop: &dyn Stop,
+    layer: Option<u8>,
+    options: &DecoderConfig,
 ) -> Result<hevc::DecodedFrame> {
     let limits = limits.unwrap_or(&NO_LIMITS);
 
     check_stop(stop)?;
 
     let container = heif::parse(data)?;
-    let primary_item = container.primary_item().ok_or(HeicError::NoPrimaryImage)?;
+    let Some(primary_item) = container.primary_item() else {
+        // Image sequence files without a meta box show the first frame
+        if container.sequence_track().is_none() {
+            return Err(HeicError::NoPrimaryImage.into());
+        }
+        let first = ImageSequence::new(data, options)?
+            .next()
+            .ok_or(HeicError::NoPrimaryImage)??;
+        limits.check_dimensions(first.frame.cropped_width(), first.frame.cropped_height())?;
+        return Ok(first.frame);
+    };
 
-    // Check limits on primary item dimensions if available from ispe
-    if let Some((w, h)) = primary_item.dimensions {
-        limits.check_dimensions(w, h)?;
-        // Estimate memory before allocating frames
-        let estimated = DecoderConfig::estimate_memory(w, h, PixelLayout::Rgba8);
-        limits.check_memory(estimated)?;
-    }
+    let (primary_item, mut frame) =
+        decode_first_alternative(&container, primary_item.id, layer, limits, stop, options)?;
 
     check_stop(stop)?;
 
-    let mut frame = decode_item(&container, &primary_item, 0, limits, stop)?;
+    // Try to decode alpha plane from auxiliary image.
+    let size = (frame.cropped_width(), frame.cropped_height());
+    let depth = frame.bit_depth;
+    if let Some(alpha_id) = alpha_item(&container, primary_item.id)
+        && let Some(alpha_plane) =
+            decode_alpha_plane(&container, alpha_id, size, depth, limits, stop, options)
+    {
+        frame.alpha_plane = Some(alpha_plane);
+    }
+
+    Ok(frame)
+}
 
+/// Decode an image item other than the primary image along with its
+/// auxiliary alpha image, if it has one
+fn decode_item_with_alpha(
+    container: &heif::HeifContainer<'_>,
+    item: &heif::Item,
+    limits: Option<&Limits>,
+    stop: &dyn Stop,
+    options: &DecoderConfig,
+) -> Result<hevc::DecodedFrame> {
+    let limits = limits.unwrap_or(&NO_LIMITS);
+    let mut frame = decode_item(container, item, 0, limits, stop, options)?;
     check_stop(stop)?;
+    if let Some(alpha_id) = alpha_item(container, item.id) {
+        let size = (frame.cropped_width(), frame.cropped_height());
+        let depth = frame.bit_depth;
+        frame.alpha_plane =
+            decode_alpha_plane(container, alpha_id, size, depth, limits, stop, options);
+    }
+    Ok(frame)
+}
 
-    // Try to decode alpha plane from auxiliary image.
-    let alpha_id = container
-        .find_auxiliary_items(primary_item.id, "urn:mpeg:hevc:2015:auxid:1")
+/// Find the auxiliary alpha image of an item
+fn alpha_item(container: &heif::HeifContainer<'_>, item_id: u32) -> Option<u32> {
+    container
+        .find_auxiliary_items(item_id, "urn:mpeg:hevc:2015:auxid:1")
         .first()
         .copied()
         .or_else(|| {
             container
-                .find_auxiliary_items(
-                    primary_item.id,
-                    "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha",
-                )
+                .find_auxiliary_items(item_id, "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha")
                 .first()
                 .copied()
+        })
+}
+
+/// Decode the first decodable alternative of an item.
+///
+/// Alternatives come from the item's `altr` entity group in preference order.
+/// Items of types this decoder does not handle, and items that fail to decode,
+/// give way to the next alternative; the first error is returned if none
+/// decodes.
+fn decode_first_alternative(
+    container: &heif::HeifContainer<'_>,
+    item_id: u32,
+    layer: Option<u8>,
+    limits: &Limits,
+    stop: &dyn Stop,
+    options: &DecoderConfig,
+) -> Result<(heif::Item, hevc::DecodedFrame)> {
+    let mut first_error = None;
+    for id in container.alternatives(item_id) {
+        let Some(mut item) = container.get_item(id) else {
+            continue;
+        };
+        if matches!(
+            item.item_type,
+            ItemType::Unknown(_) | ItemType::Exif | ItemType::Mime
+        ) {
+            first_error.get_or_insert(HeicError::Unsupported("item type").into());
+            continue;
+        }
+        if let Some(layer_id) = layer {
+            item.layer_selector = Some(layer_id.into());
+        }
+
+        // Check limits on item dimensions if available from ispe
+        if let Some((w, h)) = item.dimensions {
+            limits.check_dimensions(w, h)?;
+            // Estimate memory before allocating frames
+            let estimated = DecoderConfig::estimate_memory(w, h, PixelLayout::Rgba8);
+            limits.check_memory(estimated)?;
+        }
+
+        check_stop(stop)?;
+
+        match decode_item(container, &item, 0, limits, stop, options) {
+            Ok(frame) => return Ok((item, frame)),
+            Err(e) => {
+                check_stop(stop)?;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| HeicError::NoPrimaryImage.into()))
+}
+
+/// Pick the layer of a coded item to decode from its lsel, tols and oinf
+/// properties.
+///
+/// An explicit layer selector wins; a target output layer set resolves to its
+/// highest output layer. Layered items without either show their highest
+/// layer, single-layer items the base layer.
+fn layer_selection(item: &heif::Item) -> Result<hevc::LayerSelection> {
+    if let Some(layer_id) = item.layer_selector {
+        let layer_id = u8::try_from(layer_id)
+            .ok()
+            .filter(|&id| id < 64)
+            .ok_or(HeicError::InvalidData("lsel layer_id out of range"))?;
+        return Ok(hevc::LayerSelection::Layer(layer_id));
+    }
+    if let Some(ols_idx) = item.target_output_layer_set {
+        let output_layer = item
+            .operating_points
+            .iter()
+            .flat_map(|oinf| &oinf.operating_points)
+            .filter(|op| op.output_layer_set_idx == ols_idx)
+            .flat_map(|op| &op.layers)
+            .filter(|layer| layer.is_output_layer)
+            .map(|layer| layer.layer_id)
+            .max();
+        return Ok(match output_layer {
+            Some(layer_id) => hevc::LayerSelection::Layer(layer_id),
+            None => hevc::LayerSelection::OutputLayerSet(ols_idx),
         });
-    if let Some(alpha_id) = alpha_id
-        && let Some(alpha_plane) = decode_alpha_plane(&container, alpha_id, &frame)
-    {
-        frame.alpha_plane = Some(alpha_plane);
     }
+    Ok(if item.item_type == ItemType::Lhv1 {
+        hevc::LayerSelection::Highest
+    } else {
+        hevc::LayerSelection::Layer(0)
+    })
+}
 
-    Ok(frame)
+/// Longest chain of `tbas` references followed to the base layer
+const MAX_LAYER_ITEMS: usize = 8;
+
+/// The items holding the lower layers of a coded image item, found by
+/// following `tbas` references, base layer first.
+pub(crate) fn base_layer_items(
+    container: &heif::HeifContainer<'_>,
+    item: &heif::Item,
+) -> Result<Vec<heif::Item>> {
+    // Nearest first while walking the chain
+    let mut bases: Vec<heif::Item> = Vec::new();
+    let mut layer_id = item.id;
+    while let Some(&base_id) = container
+        .get_item_references(layer_id, FourCC::TBAS)
+        .first()
+    {
+        if base_id == item.id || bases.iter().any(|base| base.id == base_id) {
+            return Err(HeicError::InvalidData("tbas reference cycle").into());
+        }
+        if bases.len() == MAX_LAYER_ITEMS {
+            return Err(HeicError::LimitExceeded("tbas reference chain too long").into());
+        }
+        let base = container
+            .get_item(base_id)
+            .ok_or(HeicError::InvalidData("Missing base layer item"))?;
+        if !matches!(base.item_type, ItemType::Hvc1 | ItemType::Lhv1) {
+            return Err(HeicError::InvalidData("tbas reference to a non-HEVC item").into());
+        }
+        bases.push(base);
+        layer_id = base_id;
+    }
+    bases.reverse();
+    Ok(bases)
 }
 
 /// Decode an item, handling derived image types (iden, grid, iovl).
@@ -757,6 +1439,7 @@ fn decode_item(
     depth: u32,
     limits: &Limits,
     stop: &dyn Stop,
+    options: &DecoderConfig,
 ) -> Result<hevc::DecodedFrame> {
     if depth > 8 {
         return Err(HeicError::InvalidData("Derived image reference chain too deep").into());
@@ -765,19 +1448,49 @@ fn decode_item(
     check_stop(stop)?;
 
     let mut frame = match item.item_type {
-        ItemType::Grid => decode_grid(container, item, limits, stop)?,
-        ItemType::Iden => decode_iden(container, item, depth, limits, stop)?,
-        ItemType::Iovl => decode_iovl(container, item, depth, limits, stop)?,
+        ItemType::Grid => decode_grid(container, item, limits, stop, options)?,
+        ItemType::Iden => decode_iden(container, item, depth, limits, stop, options)?,
+        ItemType::Iovl => decode_iovl(container, item, depth, limits, stop, options)?,
         _ => {
             let image_data = container
                 .get_item_data(item.id)
                 .ok_or(HeicError::InvalidData("Missing image data"))?;
 
-            if let Some(ref config) = item.hevc_config {
+            let selection = layer_selection(item)?;
+            let mut frame = if item.item_type == ItemType::Lhv1 {
+                // Lower layers that the item's layers predict from are
+                // decoded along with it
+                let bases = base_layer_items(container, item)?;
+                let mut layers = Vec::with_capacity(bases.len() + 1);
+                for base in &bases {
+                    layers.push(hevc::LayerItem {
+                        config: base.hevc_config.as_ref(),
+                        layer_config: base.lhevc_config.as_ref(),
+                        image_data: container
+                            .get_item_data(base.id)
+                            .ok_or(HeicError::InvalidData("Missing base layer data"))?,
+                    });
+                }
+                layers.push(hevc::LayerItem {
+                    config: item.hevc_config.as_ref(),
+                    layer_config: item.lhevc_config.as_ref(),
+                    image_data,
+                });
+                hevc::decode_layer_items(&layers, selection)?
+            } else if selection != hevc::LayerSelection::Layer(0) {
+                hevc::decode_layer_with_config(
+                    item.hevc_config.as_ref(),
+                    item.lhevc_config.as_ref(),
+                    image_data,
+                    selection,
+                )?
+            } else if let Some(ref config) = item.hevc_config {
                 hevc::decode_with_config(config, image_data)?
             } else {
                 hevc::decode(image_data)?
-            }
+            };
+            finish_coded_image(&mut frame, item.id, options)?;
+            frame
         }
     };
 
@@ -791,6 +1504,7 @@ fn decode_item(
         frame.full_range = *full_range;
         frame.matrix_coeffs = *matrix_coefficients as u8;
     }
+    frame.hdr_metadata = item_hdr_metadata(item).or(frame.hdr_metadata);
 
     // Apply transformative properties in ipma listing order (HEIF spec requirement)
     for transform in &item.transforms {
@@ -798,20 +1512,8 @@ fn decode_item(
             Transform::CleanAperture(clap) => {
                 apply_clean_aperture(&mut frame, clap);
             }
-            Transform::Mirror(mirror) => {
-                frame = match mirror.axis {
-                    0 => frame.mirror_vertical(),
-                    1 => frame.mirror_horizontal(),
-                    _ => frame,
-                };
-            }
-            Transform::Rotation(rotation) => {
-                frame = match rotation.angle {
-                    90 => frame.rotate_90_cw(),
-                    180 => frame.rotate_180(),
-                    270 => frame.rotate_270_cw(),
-                    _ => frame,
-                };
+            Transform::Mirror(_) | Transform::Rotation(_) => {
+                frame = apply_orientation(frame, transform);
             }
         }
     }
@@ -819,6 +1521,74 @@ fn decode_item(
     Ok(frame)
 }
 
+/// Post-process the picture of a coded image item or sequence sample:
+/// compare it with its picture hash SEI, then add film grain seeded by `id`
+/// (the item ID or sample index)
+pub(crate) fn finish_coded_image(
+    frame: &mut hevc::DecodedFrame,
+    id: u32,
+    options: &DecoderConfig,
+) -> Result<()> {
+    let check = options.picture_hash_check;
+    if check != PictureHashCheck::Off && frame.verify_picture_hash() == Some(false) {
+        if check == PictureHashCheck::Error {
+            return Err(HevcError::PictureHashMismatch.into());
+        }
+        frame.picture_hash_mismatch = true;
+    }
+    // Each item gets its own grain, the same however the image is decoded
+    if let Some(seed) = options.film_grain_seed {
+        frame.apply_film_grain(seed ^ id.wrapping_mul(0x9E37_79B9));
+    }
+    Ok(())
+}
+
+/// HDR static metadata of an item's clli and mdcv properties
+fn item_hdr_metadata(item: &heif::Item) -> HdrStaticMetadata {
+    HdrStaticMetadata {
+        content_light_level: item.content_light_level,
+        mastering_display_colour_volume: item.mastering_display_colour_volume,
+    }
+}
+
+/// HDR static metadata of an image without decoding it: the item's
+/// properties and hvcC SEI, then those of its first input image
+fn probe_hdr_metadata(container: &heif::HeifContainer<'_>, item: &heif::Item) -> HdrStaticMetadata {
+    let input = container
+        .get_item_references(item.id, FourCC::DIMG)
+        .first()
+        .and_then(|&id| container.get_item(id));
+    [Some(item), input.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|item| {
+            let sei = item
+                .hevc_config
+                .as_ref()
+                .map(hevc::hdr_metadata_from_config);
+            item_hdr_metadata(item).or(sei.unwrap_or_default())
+        })
+        .fold(HdrStaticMetadata::default(), HdrStaticMetadata::or)
+}
+
+/// Apply an irot or imir transform (clap is ignored)
+fn apply_orientation(frame: hevc::DecodedFrame, transform: &Transform) -> hevc::DecodedFrame {
+    match transform {
+        Transform::Mirror(mirror) => match mirror.axis {
+            0 => frame.mirror_vertical(),
+            1 => frame.mirror_horizontal(),
+            _ => frame,
+        },
+        Transform::Rotation(rotation) => match rotation.angle {
+            90 => frame.rotate_90_cw(),
+            180 => frame.rotate_180(),
+            270 => frame.rotate_270_cw(),
+            _ => frame,
+        },
+        Transform::CleanAperture(_) => frame,
+    }
+}
+
 /// Decode an identity-derived image by following dimg references.
 fn decode_iden(
     container: &heif::HeifContainer<'_>,
@@ -826,6 +1596,7 @@ fn decode_iden(
     depth: u32,
     limits: &Limits,
     stop: &dyn Stop,
+    options: &DecoderConfig,
 ) -> Result<hevc::DecodedFrame> {
     let source_ids = container.get_item_references(iden_item.id, FourCC::DIMG);
     let source_id = source_ids
@@ -836,7 +1607,7 @@ fn decode_iden(
         .get_item(*source_id)
         .ok_or(HeicError::InvalidData("iden dimg target item not found"))?;
 
-    decode_item(container, &source_item, depth + 1, limits, stop)
+    decode_item(container, &source_item, depth + 1, limits, stop, options)
 }
 
 /// Decode an image overlay (iovl) by compositing referenced tiles onto a canvas.
@@ -846,6 +1617,7 @@ fn decode_iovl(
     depth: u32,
     limits: &Limits,
     stop: &dyn Stop,
+    options: &DecoderConfig,
 ) -> Result<hevc::DecodedFrame> {
     let iovl_data = container
         .get_item_data(iovl_item.id)
@@ -992,13 +1764,15 @@ fn decode_iovl(
             .get_item(tile_id)
             .ok_or(HeicError::InvalidData("Missing overlay tile"))?;
 
-        let tile_frame = decode_item(container, &tile_item, depth + 1, limits, stop)?;
+        let tile_frame = decode_item(container, &tile_item, depth + 1, limits, stop, options)?;
 
         // Propagate color conversion settings from first tile
         if idx == 0 {
             output.full_range = tile_frame.full_range;
             output.matrix_coeffs = tile_frame.matrix_coeffs;
+            output.hdr_metadata = tile_frame.hdr_metadata;
         }
+        output.picture_hash_mismatch |= tile_frame.picture_hash_mismatch;
 
         let (off_x, off_y) = offsets[idx];
         let dst_x = off_x.max(0) as u32;
@@ -1068,72 +1842,595 @@ fn decode_grid(
     grid_item: &heif::Item,
     limits: &Limits,
     stop: &dyn Stop,
+    options: &DecoderConfig,
 ) -> Result<hevc::DecodedFrame> {
-    // Parse grid descriptor
-    let grid_data = container
-        .get_item_data(grid_item.id)
-        .ok_or(HeicError::InvalidData("Missing grid descriptor"))?;
-
-    if grid_data.len() < 8 {
-        return Err(HeicError::InvalidData("Grid descriptor too short").into());
-    }
-
-    let flags = grid_data[1];
-    let rows = grid_data[2] as u32 + 1;
-    let cols = grid_data[3] as u32 + 1;
-    let (output_width, output_height) = if (flags & 1) != 0 {
-        if grid_data.len() < 12 {
-            return Err(HeicError::InvalidData("Grid descriptor too short for 32-bit dims").into());
-        }
-        (
-            u32::from_be_bytes([grid_data[4], grid_data[5], grid_data[6], grid_data[7]]),
-            u32::from_be_bytes([grid_data[8], grid_data[9], grid_data[10], grid_data[11]]),
-        )
-    } else {
-        (
-            u16::from_be_bytes([grid_data[4], grid_data[5]]) as u32,
-            u16::from_be_bytes([grid_data[6], grid_data[7]]) as u32,
-        )
-    };
+    let grid = GridLayout::parse(container, grid_item)?;
+    let (output_width, output_height) = (grid.width, grid.height);
 
     // Check grid output dimensions against limits
     limits.check_dimensions(output_width, output_height)?;
+    let tile_config = &grid.tile_config;
 
-    // Get tile item IDs from iref
-    let tile_ids = container.get_item_references(grid_item.id, FourCC::DIMG);
-    let expected_tiles = (rows * cols) as usize;
-    if tile_ids.len() != expected_tiles {
-        return Err(HeicError::InvalidData("Grid tile count mismatch").into());
-    }
-
-    // Get hvcC config from the first tile item
-    let first_tile = container
-        .get_item(tile_ids[0])
-        .ok_or(HeicError::InvalidData("Missing tile item"))?;
-    let tile_config = first_tile
-        .hevc_config
-        .as_ref()
-        .ok_or(HeicError::InvalidData("Missing tile hvcC config"))?;
-
-    // Get tile dimensions from ispe
-    let (tile_width, tile_height) = first_tile
-        .dimensions
-        .ok_or(HeicError::InvalidData("Missing tile dimensions"))?;
-
-    // Create output frame at the grid's output dimensions
+    // Create output frame at the grid's output dimensions, padded to whole
+    // chroma samples so that mirrors and rotations keep each chroma sample
+    // with its luma samples
     let bit_depth = tile_config.bit_depth_luma_minus8 + 8;
     let chroma_format = tile_config.chroma_format;
+    let (sub_x, sub_y) = match chroma_format {
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    };
+    let padded_width = output_width.next_multiple_of(sub_x);
+    let padded_height = output_height.next_multiple_of(sub_y);
     let mut output =
-        hevc::DecodedFrame::with_params(output_width, output_height, bit_depth, chroma_format);
+        hevc::DecodedFrame::with_params(padded_width, padded_height, bit_depth, chroma_format);
+    output.crop_right = padded_width - output_width;
+    output.crop_bottom = padded_height - output_height;
 
     // Decode tiles — parallel when rayon is available, sequential otherwise.
     // Each tile is an independent HEVC stream, so they can be decoded concurrently.
+    let decoded_tiles = decode_tiles(container, tile_config, &grid.tile_ids, stop, options)?;
+
+    // Copy decoded tiles into the output frame
+    for (tile_idx, tile_frame) in decoded_tiles.iter().enumerate() {
+        // Propagate color conversion settings from first tile
+        if tile_idx == 0 {
+            output.full_range = tile_frame.full_range;
+            output.matrix_coeffs = tile_frame.matrix_coeffs;
+            output.hdr_metadata = tile_frame.hdr_metadata;
+            // Picture hashes cover a single tile
+            output.sei = tile_frame
+                .sei
+                .iter()
+                .filter(|message| !matches!(message, hevc::SeiMessage::DecodedPictureHash(_)))
+                .cloned()
+                .collect();
+        }
+        output.picture_hash_mismatch |= tile_frame.picture_hash_mismatch;
+
+        let tile_row = tile_idx as u32 / grid.cols;
+        let tile_col = tile_idx as u32 % grid.cols;
+        copy_tile(
+            &mut output,
+            (0, 0),
+            tile_frame,
+            (tile_col * grid.tile_width, tile_row * grid.tile_height),
+        );
+    }
+
+    Ok(output)
+}
+
+/// A rectangle as (x, y, width, height)
+type Rect = (u32, u32, u32, u32);
+
+/// Check that a non-empty rectangle lies within a `width` x `height` image
+fn check_region(region: Rect, width: u32, height: u32) -> Result<()> {
+    let (x, y, w, h) = region;
+    if w == 0
+        || h == 0
+        || x.checked_add(w).is_none_or(|end| end > width)
+        || y.checked_add(h).is_none_or(|end| end > height)
+    {
+        return Err(HeicError::InvalidData("region outside image").into());
+    }
+    Ok(())
+}
+
+/// A transform with the image width and height before it and, for clap, the
+/// clean aperture rectangle
+type Stage<'t> = (&'t Transform, u32, u32, Rect);
+
+/// Image size before each transform of an item, and the size after all
+/// transforms
+fn transform_stages(
+    transforms: &[Transform],
+    (mut width, mut height): (u32, u32),
+) -> (Vec<Stage<'_>>, (u32, u32)) {
+    let mut stages = Vec::with_capacity(transforms.len());
+    for transform in transforms {
+        let clean = match transform {
+            Transform::CleanAperture(clap) => clean_aperture_rect(width, height, clap),
+            _ => (0, 0, width, height),
+        };
+        stages.push((transform, width, height, clean));
+        (width, height) = match transform {
+            Transform::CleanAperture(_) => (clean.2, clean.3),
+            Transform::Rotation(rotation) if matches!(rotation.angle, 90 | 270) => (height, width),
+            _ => (width, height),
+        };
+    }
+    (stages, (width, height))
+}
+
+/// Map a region of a transformed image back onto its untransformed canvas.
+///
+/// Undoes the transforms in reverse order (see the sample mappings of the
+/// rotate/mirror methods).
+fn region_on_canvas(transforms: &[Transform], canvas: (u32, u32), region: Rect) -> Result<Rect> {
+    let (stages, (width, height)) = transform_stages(transforms, canvas);
+    check_region(region, width, height)?;
+
+    let (mut x, mut y, mut w, mut h) = region;
+    for &(transform, pre_w, pre_h, clean) in stages.iter().rev() {
+        (x, y, w, h) = match transform {
+            Transform::CleanAperture(_) => (x + clean.0, y + clean.1, w, h),
+            Transform::Mirror(mirror) => match mirror.axis {
+                0 => (x, pre_h - y - h, w, h),
+                1 => (pre_w - x - w, y, w, h),
+                _ => (x, y, w, h),
+            },
+            Transform::Rotation(rotation) => match rotation.angle {
+                90 => (y, pre_h - x - w, h, w),
+                180 => (pre_w - x - w, pre_h - y - h, w, h),
+                270 => (pre_w - y - h, x, h, w),
+                _ => (x, y, w, h),
+            },
+        };
+    }
+    Ok((x, y, w, h))
+}
+
+/// Map a rectangle of a `size` image through the irot/imir transforms
+/// (clap is skipped), the forward counterpart of [`region_on_canvas`]
+fn orient_rect(transforms: &[Transform], (mut width, mut height): (u32, u32), rect: Rect) -> Rect {
+    let (mut x, mut y, mut w, mut h) = rect;
+    for transform in transforms {
+        match transform {
+            Transform::CleanAperture(_) => {}
+            Transform::Mirror(mirror) => match mirror.axis {
+                0 => y = height - y - h,
+                1 => x = width - x - w,
+                _ => {}
+            },
+            Transform::Rotation(rotation) => match rotation.angle {
+                90 => {
+                    (x, y, w, h) = (height - y - h, x, h, w);
+                    (width, height) = (height, width);
+                }
+                180 => (x, y) = (width - x - w, height - y - h),
+                270 => {
+                    (x, y, w, h) = (y, width - x - w, h, w);
+                    (width, height) = (height, width);
+                }
+                _ => {}
+            },
+        }
+    }
+    (x, y, w, h)
+}
+
+/// Core region decode: only the grid tiles under the region are decoded.
+fn decode_region_inner(
+    data: &[u8],
+    region: Rect,
+    limits: Option<&Limits>,
+    stop: &dyn Stop,
+    layer: Option<u8>,
+    options: &DecoderConfig,
+) -> Result<hevc::DecodedFrame> {
+    let container = heif::parse(data)?;
+    let Some(item) = container
+        .primary_item()
+        .filter(|item| item.item_type == ItemType::Grid)
+    else {
+        let mut frame = decode_to_frame_inner(data, limits, stop, layer, options)?;
+        crop_to_region(&mut frame, region)?;
+        return Ok(frame);
+    };
+    let limits = limits.unwrap_or(&NO_LIMITS);
+    limits.check_dimensions(region.2, region.3)?;
+
+    let grid = GridLayout::parse(&container, &item)?;
+    let (canvas_width, canvas_height) = (grid.width, grid.height);
+
+    let (x, y, w, h) = region_on_canvas(&item.transforms, (canvas_width, canvas_height), region)?;
+
+    let tile_config = &grid.tile_config;
+    let (tile_width, tile_height) = (grid.tile_width, grid.tile_height);
+
+    // Start and end the output at chroma-aligned canvas positions, so that
+    // orienting it keeps each chroma sample with its luma samples, and crop
+    // the rest
+    let chroma_format = tile_config.chroma_format;
+    let (sub_x, sub_y) = match chroma_format {
+        0 | 3 => (1, 1),
+        2 => (2, 1),
+        _ => (2, 2),
+    };
+    let (origin_x, origin_y) = (x - x % sub_x, y - y % sub_y);
+    let frame_width = (x + w - origin_x).next_multiple_of(sub_x);
+    let frame_height = (y + h - origin_y).next_multiple_of(sub_y);
+    limits.check_memory(DecoderConfig::estimate_memory(
+        frame_width,
+        frame_height,
+        PixelLayout::Rgba8,
+    ))?;
+    let mut frame = hevc::DecodedFrame::with_params(
+        frame_width,
+        frame_height,
+        tile_config.bit_depth_luma_minus8 + 8,
+        chroma_format,
+    );
+    frame.crop_left = x - origin_x;
+    frame.crop_top = y - origin_y;
+    frame.crop_right = origin_x + frame_width - x - w;
+    frame.crop_bottom = origin_y + frame_height - y - h;
+
+    // An alpha grid tiled like this one is decoded over the same tiles and
+    // copied along; any other alpha image is decoded whole and cropped
+    let bit_depth = frame.bit_depth;
+    let alpha = alpha_item(&container, item.id).and_then(|id| container.get_item(id));
+    let alpha_grid = alpha
+        .as_ref()
+        .and_then(|alpha| matching_alpha_grid(&container, &item, alpha, &grid));
+    if alpha_grid.is_some() {
+        frame.alpha_plane = Some(alloc::vec![0; (w * h) as usize]);
+    }
+
+    // Decode only the tiles that intersect the region
+    let col_range = origin_x / tile_width..((x + w - 1) / tile_width + 1).min(grid.cols);
+    let row_range = origin_y / tile_height..((y + h - 1) / tile_height + 1).min(grid.rows);
+    let positions: Vec<(u32, u32)> = row_range
+        .flat_map(|row| col_range.clone().map(move |col| (row, col)))
+        .collect();
+    let region_tile_ids: Vec<u32> = positions
+        .iter()
+        .map(|&(row, col)| grid.tile_ids[(row * grid.cols + col) as usize])
+        .collect();
+    let mut decoded_tiles = decode_tiles(&container, tile_config, &region_tile_ids, stop, options)?;
+    if let Some(alpha_grid) = &alpha_grid {
+        let alpha_ids: Vec<u32> = positions
+            .iter()
+            .map(|&(row, col)| alpha_grid.tile_ids[(row * grid.cols + col) as usize])
+            .collect();
+        let config = &alpha_grid.tile_config;
+        let alpha_tiles = decode_tiles(&container, config, &alpha_ids, stop, options)?;
+        for (tile_frame, alpha_tile) in decoded_tiles.iter_mut().zip(&alpha_tiles) {
+            let too_small = HeicError::InvalidData("Alpha grid tile smaller than its ispe");
+            let (tile_w, tile_h) = (tile_frame.cropped_width(), tile_frame.cropped_height());
+            let mut alpha = luma_rect(alpha_tile, (0, 0, tile_w, tile_h)).ok_or(too_small)?;
+            rescale_alpha(&mut alpha, alpha_tile.bit_depth, bit_depth);
+            tile_frame.alpha_plane = Some(alpha);
+        }
+    }
+
+    for (&(row, col), tile_frame) in positions.iter().zip(&decoded_tiles) {
+        frame.picture_hash_mismatch |= tile_frame.picture_hash_mismatch;
+        copy_tile(
+            &mut frame,
+            (origin_x, origin_y),
+            tile_frame,
+            (col * tile_width, row * tile_height),
+        );
+    }
+    if let Some(tile_frame) = decoded_tiles.first() {
+        frame.full_range = tile_frame.full_range;
+        frame.matrix_coeffs = tile_frame.matrix_coeffs;
+        frame.hdr_metadata = item_hdr_metadata(&item).or(tile_frame.hdr_metadata);
+    }
+
+    // Set color conversion parameters from colr nclx box if present.
+    if let Some(ColorInfo::Nclx {
+        full_range,
+        matrix_coefficients,
+        ..
+    }) = &item.color_info
+    {
+        frame.full_range = *full_range;
+        frame.matrix_coeffs = *matrix_coefficients as u8;
+    }
+
+    // The clean aperture is already part of the region; orient the crop
+    for transform in &item.transforms {
+        frame = apply_orientation(frame, transform);
+    }
+
+    if let Some(alpha) = alpha.filter(|_| alpha_grid.is_none()) {
+        let (_, size) = transform_stages(&item.transforms, (canvas_width, canvas_height));
+        let alpha_plane =
+            decode_alpha_plane(&container, alpha.id, size, bit_depth, limits, stop, options);
+        frame.alpha_plane = alpha_plane.map(|plane| {
+            let (x, y, w, h) = region;
+            let rows = (y..y + h).flat_map(|row| {
+                let start = (row * size.0 + x) as usize;
+                &plane[start..start + w as usize]
+            });
+            rows.copied().collect()
+        });
+    }
+
+    Ok(frame)
+}
+
+/// Stream a grid item to a sink one row of tiles at a time.
+///
+/// Each tile is cropped to the part left visible by the clean apertures,
+/// oriented, converted and written at its place in the transformed image.
+fn stream_grid(
+    container: &heif::HeifContainer<'_>,
+    item: &heif::Item,
+    layout: PixelLayout,
+    limits: &Limits,
+    stop: &dyn Stop,
+    options: &DecoderConfig,
+    sink: &mut dyn ImageSink,
+) -> Result<ImageInfo> {
+    let grid = GridLayout::parse(container, item)?;
+    limits.check_dimensions(grid.width, grid.height)?;
+
+    let canvas = (grid.width, grid.height);
+    let (_, (width, height)) = transform_stages(&item.transforms, canvas);
+    // Canvas area that remains after the clean apertures
+    let visible = region_on_canvas(&item.transforms, canvas, (0, 0, width, height))?;
+    sink.begin(width, height, layout)?;
+    let mut hdr_metadata = item_hdr_metadata(item);
+
+    // An alpha grid tiled like this one and with the same transforms is
+    // streamed along; any other alpha image is decoded whole up front
+    let bit_depth = grid.tile_config.bit_depth_luma_minus8 + 8;
+    let alpha = alpha_item(container, item.id).and_then(|id| container.get_item(id));
+    let alpha_grid = alpha
+        .as_ref()
+        .and_then(|alpha| matching_alpha_grid(container, item, alpha, &grid));
+    let alpha_plane = match &alpha {
+        Some(alpha) if alpha_grid.is_none() => {
+            let size = (width, height);
+            decode_alpha_plane(container, alpha.id, size, bit_depth, limits, stop, options)
+        }
+        _ => None,
+    };
+
+    for row in 0..grid.rows {
+        check_stop(stop)?;
+
+        // Visible canvas rectangle of each tile in this row
+        let ty = row * grid.tile_height;
+        let placements: Vec<(u32, Rect)> = (0..grid.cols)
+            .filter_map(|col| {
+                let tx = col * grid.tile_width;
+                let x0 = tx.max(visible.0);
+                let y0 = ty.max(visible.1);
+                let x1 = (tx + grid.tile_width).min(visible.0 + visible.2);
+                let y1 = (ty + grid.tile_height).min(visible.1 + visible.3);
+                (x0 < x1 && y0 < y1).then(|| (col, (x0, y0, x1 - x0, y1 - y0)))
+            })
+            .collect();
+        let tile_ids: Vec<u32> = placements
+            .iter()
+            .map(|&(col, _)| grid.tile_ids[(row * grid.cols + col) as usize])
+            .collect();
+        let decoded_tiles = decode_tiles(container, &grid.tile_config, &tile_ids, stop, options)?;
+        let alpha_tiles = match &alpha_grid {
+            Some(alpha_grid) => {
+                let alpha_ids: Vec<u32> = placements
+                    .iter()
+                    .map(|&(col, _)| alpha_grid.tile_ids[(row * grid.cols + col) as usize])
+                    .collect();
+                let config = &alpha_grid.tile_config;
+                decode_tiles(container, config, &alpha_ids, stop, options)?
+            }
+            None => Vec::new(),
+        };
+        let mut alpha_tiles = alpha_tiles.into_iter();
+
+        for ((col, rect), mut tile) in placements.into_iter().zip(decoded_tiles) {
+            // Crop the tile to its visible part
+            let left = rect.0 - col * grid.tile_width;
+            let top = rect.1 - ty;
+            let (tile_w, tile_h) = (tile.cropped_width(), tile.cropped_height());
+            if left + rect.2 > tile_w || top + rect.3 > tile_h {
+                return Err(HeicError::InvalidData("Grid tile smaller than its ispe").into());
+            }
+            if let Some(alpha_tile) = alpha_tiles.next() {
+                let too_small = HeicError::InvalidData("Alpha grid tile smaller than its ispe");
+                let mut alpha =
+                    luma_rect(&alpha_tile, (left, top, rect.2, rect.3)).ok_or(too_small)?;
+                rescale_alpha(&mut alpha, alpha_tile.bit_depth, tile.bit_depth);
+                tile.alpha_plane = Some(alpha);
+            }
+            tile.crop_left += left;
+            tile.crop_top += top;
+            tile.crop_right += tile_w - left - rect.2;
+            tile.crop_bottom += tile_h - top - rect.3;
+            hdr_metadata = hdr_metadata.or(tile.hdr_metadata);
+
+            // Set color conversion parameters from colr nclx box if present.
+            if let Some(ColorInfo::Nclx {
+                full_range,
+                matrix_coefficients,
+                ..
+            }) = &item.color_info
+            {
+                tile.full_range = *full_range;
+                tile.matrix_coeffs = *matrix_coefficients as u8;
+            }
+            for transform in &item.transforms {
+                tile = apply_orientation(tile, transform);
+            }
+
+            let local = (rect.0 - visible.0, rect.1 - visible.1, rect.2, rect.3);
+            let (x, y, w, h) = orient_rect(&item.transforms, (visible.2, visible.3), local);
+            if let Some(plane) = &alpha_plane {
+                let rows = (y..y + h).flat_map(|row| {
+                    let start = (row * width + x) as usize;
+                    &plane[start..start + w as usize]
+                });
+                tile.alpha_plane = Some(rows.copied().collect());
+            }
+            sink.write_tile(x, y, w, h, &frame_to_layout(&tile, layout))?;
+        }
+    }
+
+    Ok(ImageInfo {
+        width,
+        height,
+        has_alpha: alpha_grid.is_some() || alpha_plane.is_some(),
+        bit_depth,
+        chroma_format: grid.tile_config.chroma_format,
+        has_exif: false, // Use ImageInfo::from_bytes() for metadata probing
+        has_xmp: false,
+        has_thumbnail: false,
+        hdr_metadata,
+    })
+}
+
+/// Layout of an alpha grid that can be decoded tile by tile along with
+/// `grid`: tiled the same way and with the same transforms
+fn matching_alpha_grid(
+    container: &heif::HeifContainer<'_>,
+    item: &heif::Item,
+    alpha: &heif::Item,
+    grid: &GridLayout,
+) -> Option<GridLayout> {
+    if alpha.item_type != ItemType::Grid || alpha.transforms != item.transforms {
+        return None;
+    }
+    GridLayout::parse(container, alpha)
+        .ok()
+        .filter(|alpha_grid| alpha_grid.same_tiling(grid))
+}
+
+/// Luma samples of the `rect` of a frame's cropped area, or `None` if it
+/// extends past that area
+fn luma_rect(frame: &hevc::DecodedFrame, (x, y, w, h): Rect) -> Option<Vec<u16>> {
+    if x + w > frame.cropped_width() || y + h > frame.cropped_height() {
+        return None;
+    }
+    let (x, y) = (frame.crop_left + x, frame.crop_top + y);
+    let rows = (y..y + h).flat_map(|row| {
+        let start = (row * frame.width + x) as usize;
+        &frame.y_plane[start..start + w as usize]
+    });
+    Some(rows.copied().collect())
+}
+
+/// Crop a fully decoded frame (and its alpha plane) to a region
+fn crop_to_region(frame: &mut hevc::DecodedFrame, region: Rect) -> Result<()> {
+    let (width, height) = (frame.cropped_width(), frame.cropped_height());
+    check_region(region, width, height)?;
+    let (x, y, w, h) = region;
+
+    if let Some(alpha) = &frame.alpha_plane {
+        let mut cropped = Vec::with_capacity((w * h) as usize);
+        for row in y..y + h {
+            let start = (row * width + x) as usize;
+            cropped.extend_from_slice(alpha.get(start..start + w as usize).unwrap_or(&[]));
+        }
+        frame.alpha_plane = Some(cropped);
+    }
+
+    frame.crop_left += x;
+    frame.crop_top += y;
+    frame.crop_right += width - x - w;
+    frame.crop_bottom += height - y - h;
+    Ok(())
+}
+
+/// Tile layout of a grid item
+struct GridLayout {
+    rows: u32,
+    cols: u32,
+    /// Output (canvas) width
+    width: u32,
+    /// Output (canvas) height
+    height: u32,
+    /// Tile item IDs in row-major order
+    tile_ids: Vec<u32>,
+    /// hvcC config of the first tile, shared by all tiles
+    tile_config: heif::HevcDecoderConfig,
+    tile_width: u32,
+    tile_height: u32,
+}
+
+impl GridLayout {
+    /// Whether `other` has the same canvas and tiles
+    fn same_tiling(&self, other: &Self) -> bool {
+        (self.rows, self.cols, self.width, self.height)
+            == (other.rows, other.cols, other.width, other.height)
+            && (self.tile_width, self.tile_height) == (other.tile_width, other.tile_height)
+    }
+
+    /// Parse the grid descriptor and look up the tiles of a grid item
+    fn parse(container: &heif::HeifContainer<'_>, grid_item: &heif::Item) -> Result<Self> {
+        let grid_data = container
+            .get_item_data(grid_item.id)
+            .ok_or(HeicError::InvalidData("Missing grid descriptor"))?;
+
+        if grid_data.len() < 8 {
+            return Err(HeicError::InvalidData("Grid descriptor too short").into());
+        }
+
+        let flags = grid_data[1];
+        let rows = grid_data[2] as u32 + 1;
+        let cols = grid_data[3] as u32 + 1;
+        let (width, height) = if (flags & 1) != 0 {
+            if grid_data.len() < 12 {
+                return Err(
+                    HeicError::InvalidData("Grid descriptor too short for 32-bit dims").into(),
+                );
+            }
+            (
+                u32::from_be_bytes([grid_data[4], grid_data[5], grid_data[6], grid_data[7]]),
+                u32::from_be_bytes([grid_data[8], grid_data[9], grid_data[10], grid_data[11]]),
+            )
+        } else {
+            (
+                u16::from_be_bytes([grid_data[4], grid_data[5]]) as u32,
+                u16::from_be_bytes([grid_data[6], grid_data[7]]) as u32,
+            )
+        };
+
+        // Get tile item IDs from iref
+        let tile_ids = container.get_item_references(grid_item.id, FourCC::DIMG);
+        let expected_tiles = (rows * cols) as usize;
+        if tile_ids.len() != expected_tiles {
+            return Err(HeicError::InvalidData("Grid tile count mismatch").into());
+        }
+
+        // Get hvcC config from the first tile item
+        let first_tile = container
+            .get_item(tile_ids[0])
+            .ok_or(HeicError::InvalidData("Missing tile item"))?;
+        let tile_config = first_tile
+            .hevc_config
+            .ok_or(HeicError::InvalidData("Missing tile hvcC config"))?;
+
+        // Get tile dimensions from ispe
+        let (tile_width, tile_height) = first_tile
+            .dimensions
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .ok_or(HeicError::InvalidData("Missing tile dimensions"))?;
+
+        Ok(Self {
+            rows,
+            cols,
+            width,
+            height,
+            tile_ids,
+            tile_config,
+            tile_width,
+            tile_height,
+        })
+    }
+}
+
+/// Decode grid tiles with a shared hvcC config
+fn decode_tiles(
+    container: &heif::HeifContainer<'_>,
+    tile_config: &heif::HevcDecoderConfig,
+    tile_ids: &[u32],
+    stop: &dyn Stop,
+    options: &DecoderConfig,
+) -> Result<Vec<hevc::DecodedFrame>> {
     check_stop(stop)?;
-    let tile_data_list: Vec<&[u8]> = tile_ids
+    let tile_data_list: Vec<(u32, &[u8])> = tile_ids
         .iter()
         .map(|&tid| {
             container
                 .get_item_data(tid)
+                .map(|data| (tid, data))
                 .ok_or_else(|| At::from(HeicError::InvalidData("Missing tile data")))
         })
         .collect::<core::result::Result<_, _>>()?;
@@ -1141,112 +2438,136 @@ fn decode_grid(
     #[cfg(feature = "parallel")]
     let decoded_tiles: Vec<hevc::DecodedFrame> = tile_data_list
         .par_iter()
-        .map(|tile_data| hevc::decode_with_config(tile_config, tile_data).map_err(Into::into))
+        .map(|&(tile_id, tile_data)| {
+            let mut tile = hevc::decode_with_config(tile_config, tile_data)?;
+            finish_coded_image(&mut tile, tile_id, options)?;
+            Ok(tile)
+        })
         .collect::<Result<_>>()?;
 
     #[cfg(not(feature = "parallel"))]
     let decoded_tiles: Vec<hevc::DecodedFrame> = {
         let mut tiles = Vec::with_capacity(tile_data_list.len());
-        for tile_data in &tile_data_list {
+        for &(tile_id, tile_data) in &tile_data_list {
             check_stop(stop)?;
-            tiles.push(hevc::decode_with_config(tile_config, tile_data)?);
+            let mut tile = hevc::decode_with_config(tile_config, tile_data)?;
+            finish_coded_image(&mut tile, tile_id, options)?;
+            tiles.push(tile);
         }
         tiles
     };
 
-    // Copy decoded tiles into the output frame
-    for (tile_idx, tile_frame) in decoded_tiles.iter().enumerate() {
-        // Propagate color conversion settings from first tile
-        if tile_idx == 0 {
-            output.full_range = tile_frame.full_range;
-            output.matrix_coeffs = tile_frame.matrix_coeffs;
-        }
-
-        let tile_row = tile_idx as u32 / cols;
-        let tile_col = tile_idx as u32 % cols;
-        let dst_x = tile_col * tile_width;
-        let dst_y = tile_row * tile_height;
-
-        // Luma: copy visible portion (clamp to output dimensions)
-        let copy_w = tile_frame
-            .cropped_width()
-            .min(output_width.saturating_sub(dst_x));
-        let copy_h = tile_frame
-            .cropped_height()
-            .min(output_height.saturating_sub(dst_y));
-
-        let src_y_start = tile_frame.crop_top;
-        let src_x_start = tile_frame.crop_left;
-
-        for row in 0..copy_h {
-            let src_row = (src_y_start + row) as usize;
-            let dst_row = (dst_y + row) as usize;
-            for col in 0..copy_w {
-                let src_col = (src_x_start + col) as usize;
-                let dst_col = (dst_x + col) as usize;
+    Ok(decoded_tiles)
+}
 
-                let src_idx = src_row * tile_frame.y_stride() + src_col;
-                let dst_idx = dst_row * output.y_stride() + dst_col;
-                output.y_plane[dst_idx] = tile_frame.y_plane[src_idx];
+/// Copy the part of a decoded tile that overlaps `output`.
+///
+/// `out_origin` and `tile_origin` place the output frame and the tile's
+/// cropped area on the same canvas; both must be aligned to the chroma
+/// subsampling. The tile's alpha plane is copied when both frames have one.
+fn copy_tile(
+    output: &mut hevc::DecodedFrame,
+    out_origin: (u32, u32),
+    tile_frame: &hevc::DecodedFrame,
+    tile_origin: (u32, u32),
+) {
+    let (out_x, out_y) = out_origin;
+    let (tile_x, tile_y) = tile_origin;
+    let x0 = tile_x.max(out_x);
+    let y0 = tile_y.max(out_y);
+    let x1 = (tile_x + tile_frame.cropped_width()).min(out_x + output.width);
+    let y1 = (tile_y + tile_frame.cropped_height()).min(out_y + output.height);
+    if x0 >= x1 || y0 >= y1 {
+        return;
+    }
+    let (copy_w, copy_h) = (x1 - x0, y1 - y0);
+
+    // Alpha: both planes cover the cropped areas only
+    let (alpha_x, alpha_y) = (out_x + output.crop_left, out_y + output.crop_top);
+    let alpha_w = output.cropped_width();
+    let alpha_h = output.cropped_height();
+    let tile_w = tile_frame.cropped_width();
+    if let (Some(dst), Some(src)) = (&mut output.alpha_plane, &tile_frame.alpha_plane) {
+        let ax0 = x0.max(alpha_x);
+        let ax1 = x1.min(alpha_x + alpha_w);
+        let ay0 = y0.max(alpha_y);
+        let ay1 = y1.min(alpha_y + alpha_h);
+        if ax0 < ax1 {
+            let len = (ax1 - ax0) as usize;
+            for row in ay0..ay1 {
+                let src_idx = ((row - tile_y) * tile_w + ax0 - tile_x) as usize;
+                let dst_idx = ((row - alpha_y) * alpha_w + ax0 - alpha_x) as usize;
+                dst[dst_idx..dst_idx + len].copy_from_slice(&src[src_idx..src_idx + len]);
             }
         }
+    }
 
-        // Chroma: copy with subsampling
-        if chroma_format > 0 {
-            let (sub_x, sub_y) = match chroma_format {
-                1 => (2u32, 2u32), // 4:2:0
-                2 => (2, 1),       // 4:2:2
-                3 => (1, 1),       // 4:4:4
-                _ => (2, 2),
-            };
-            let c_copy_w = copy_w.div_ceil(sub_x);
-            let c_copy_h = copy_h.div_ceil(sub_y);
-            let c_dst_x = dst_x / sub_x;
-            let c_dst_y = dst_y / sub_y;
-            let c_src_x = src_x_start / sub_x;
-            let c_src_y = src_y_start / sub_y;
-
-            let src_c_stride = tile_frame.c_stride();
-            let dst_c_stride = output.c_stride();
-
-            for row in 0..c_copy_h {
-                let src_row = (c_src_y + row) as usize;
-                let dst_row = (c_dst_y + row) as usize;
-                for col in 0..c_copy_w {
-                    let src_col = (c_src_x + col) as usize;
-                    let dst_col = (c_dst_x + col) as usize;
+    // Luma: copy the overlapping rows
+    let src_x = (tile_frame.crop_left + x0 - tile_x) as usize;
+    let src_y = tile_frame.crop_top + y0 - tile_y;
+    let dst_x = (x0 - out_x) as usize;
+    let dst_y = y0 - out_y;
+    for row in 0..copy_h {
+        let src_idx = (src_y + row) as usize * tile_frame.y_stride() + src_x;
+        let dst_idx = (dst_y + row) as usize * output.y_stride() + dst_x;
+        output.y_plane[dst_idx..dst_idx + copy_w as usize]
+            .copy_from_slice(&tile_frame.y_plane[src_idx..src_idx + copy_w as usize]);
+    }
 
-                    let src_idx = src_row * src_c_stride + src_col;
-                    let dst_idx = dst_row * dst_c_stride + dst_col;
-                    if src_idx < tile_frame.cb_plane.len() && dst_idx < output.cb_plane.len() {
-                        output.cb_plane[dst_idx] = tile_frame.cb_plane[src_idx];
-                        output.cr_plane[dst_idx] = tile_frame.cr_plane[src_idx];
-                    }
+    // Chroma: copy with subsampling
+    if output.chroma_format > 0 {
+        let (sub_x, sub_y) = match output.chroma_format {
+            1 => (2u32, 2u32), // 4:2:0
+            2 => (2, 1),       // 4:2:2
+            3 => (1, 1),       // 4:4:4
+            _ => (2, 2),
+        };
+        let c_copy_w = copy_w.div_ceil(sub_x);
+        let c_copy_h = copy_h.div_ceil(sub_y);
+        let c_dst_x = dst_x as u32 / sub_x;
+        let c_dst_y = dst_y / sub_y;
+        let c_src_x = src_x as u32 / sub_x;
+        let c_src_y = src_y / sub_y;
+
+        let src_c_stride = tile_frame.c_stride();
+        let dst_c_stride = output.c_stride();
+
+        for row in 0..c_copy_h {
+            let src_row = (c_src_y + row) as usize;
+            let dst_row = (c_dst_y + row) as usize;
+            for col in 0..c_copy_w {
+                let src_col = (c_src_x + col) as usize;
+                let dst_col = (c_dst_x + col) as usize;
+
+                let src_idx = src_row * src_c_stride + src_col;
+                let dst_idx = dst_row * dst_c_stride + dst_col;
+                if src_idx < tile_frame.cb_plane.len() && dst_idx < output.cb_plane.len() {
+                    output.cb_plane[dst_idx] = tile_frame.cb_plane[src_idx];
+                    output.cr_plane[dst_idx] = tile_frame.cr_plane[src_idx];
                 }
             }
         }
     }
-
-    Ok(output)
 }
 
-/// Decode an auxiliary alpha plane and return it sized to match the primary frame.
+/// Decode an auxiliary alpha plane and return it sized to match the primary
+/// image's `primary_w` x `primary_h` cropped size, at its `bit_depth`.
 ///
+/// The alpha item may be a coded image or a grid; its own transforms are applied.
 /// Returns the alpha plane as a Vec<u16> with one value per cropped pixel,
 /// or None if decoding fails.
 fn decode_alpha_plane(
     container: &heif::HeifContainer<'_>,
     alpha_id: u32,
-    primary_frame: &hevc::DecodedFrame,
+    (primary_w, primary_h): (u32, u32),
+    bit_depth: u8,
+    limits: &Limits,
+    stop: &dyn Stop,
+    options: &DecoderConfig,
 ) -> Option<Vec<u16>> {
     let alpha_item = container.get_item(alpha_id)?;
-    let alpha_data = container.get_item_data(alpha_id)?;
-    let alpha_config = alpha_item.hevc_config.as_ref()?;
-
-    let alpha_frame = hevc::decode_with_config(alpha_config, alpha_data).ok()?;
+    let alpha_frame = decode_item(container, &alpha_item, 0, limits, stop, options).ok()?;
 
-    let primary_w = primary_frame.cropped_width();
-    let primary_h = primary_frame.cropped_height();
     let alpha_w = alpha_frame.cropped_width();
     let alpha_h = alpha_frame.cropped_height();
 
@@ -1301,14 +2622,34 @@ fn decode_alpha_plane(
         }
     }
 
+    rescale_alpha(&mut alpha_plane, alpha_frame.bit_depth, bit_depth);
     Some(alpha_plane)
 }
 
+/// Store alpha at the primary frame's bit depth so conversions share its scaling
+fn rescale_alpha(alpha: &mut [u16], from_depth: u8, to_depth: u8) {
+    if from_depth != to_depth {
+        let src_max = (1u32 << from_depth) - 1;
+        let dst_max = (1u32 << to_depth) - 1;
+        for v in alpha {
+            *v = ((*v as u32 * dst_max + src_max / 2) / src_max) as u16;
+        }
+    }
+}
+
 /// Internal: decode gain map
 fn decode_gain_map_inner(data: &[u8]) -> Result<HdrGainMap> {
     let container = heif::parse(data)?;
     let primary_item = container.primary_item().ok_or(HeicError::NoPrimaryImage)?;
+    let (_, frame) = decode_gain_map_frame(&container, &primary_item)?;
+    Ok(gain_map_from_frame(&frame))
+}
 
+/// Decode the Apple HDR gain map item of `primary_item`, without transforms
+fn decode_gain_map_frame(
+    container: &heif::HeifContainer<'_>,
+    primary_item: &heif::Item,
+) -> Result<(heif::Item, hevc::DecodedFrame)> {
     let gainmap_ids =
         container.find_auxiliary_items(primary_item.id, "urn:com:apple:photo:2020:aux:hdrgainmap");
 
@@ -1328,7 +2669,11 @@ fn decode_gain_map_inner(data: &[u8]) -> Result<HdrGainMap> {
         .ok_or(HeicError::InvalidData("Missing gain map hvcC config"))?;
 
     let frame = hevc::decode_with_config(gainmap_config, gainmap_data)?;
+    Ok((gainmap_item, frame))
+}
 
+/// Luma of the visible area of a gain map frame, normalized to 0.0-1.0
+fn gain_map_from_frame(frame: &hevc::DecodedFrame) -> HdrGainMap {
     let width = frame.cropped_width();
     let height = frame.cropped_height();
     let max_val = ((1u32 << frame.bit_depth) - 1) as f32;
@@ -1345,18 +2690,92 @@ fn decode_gain_map_inner(data: &[u8]) -> Result<HdrGainMap> {
         }
     }
 
-    Ok(HdrGainMap {
+    HdrGainMap {
         data: float_data,
         width,
         height,
-    })
+    }
+}
+
+/// Internal: read the gain map headroom from XMP or the Apple maker note
+fn gain_map_headroom_inner(data: &[u8]) -> Result<Option<f32>> {
+    if let Some(headroom) = extract_xmp_inner(data)?.and_then(hdr::xmp_headroom) {
+        return Ok(Some(headroom));
+    }
+    Ok(extract_exif_inner(data)?
+        .and_then(exif::apple_hdr_tags)
+        .map(|(maker33, maker48)| hdr::maker_note_headroom(maker33, maker48)))
+}
+
+/// Internal: reconstruct HDR from the primary image and its gain map
+fn decode_hdr_inner(
+    data: &[u8],
+    display_headroom: f32,
+    options: &DecoderConfig,
+) -> Result<(HdrImage, HdrStaticMetadata)> {
+    let headroom = gain_map_headroom_inner(data)?
+        .ok_or(HeicError::InvalidData("No HDR gain map headroom found"))?;
+    let container = heif::parse(data)?;
+    let primary_item = container.primary_item().ok_or(HeicError::NoPrimaryImage)?;
+    let (gainmap_item, mut gain_frame) = decode_gain_map_frame(&container, &primary_item)?;
+
+    // The gain map takes the primary image's orientation unless it has its own
+    let has_orientation = |item: &heif::Item| {
+        item.transforms
+            .iter()
+            .any(|t| !matches!(t, Transform::CleanAperture(_)))
+    };
+    let orientation_item = if has_orientation(&gainmap_item) {
+        &gainmap_item
+    } else {
+        &primary_item
+    };
+    for transform in &gainmap_item.transforms {
+        if let Transform::CleanAperture(clap) = transform {
+            apply_clean_aperture(&mut gain_frame, clap);
+        }
+    }
+    for transform in &orientation_item.transforms {
+        gain_frame = apply_orientation(gain_frame, transform);
+    }
+    let gain_map = gain_map_from_frame(&gain_frame);
+
+    let frame = decode_to_frame_inner(data, None, &Unstoppable, None, options)?;
+    let sdr: Vec<u16> = frame_to_layout(&frame, PixelLayout::Rgb16)
+        .chunks_exact(2)
+        .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+        .collect();
+    let mut image = hdr::reconstruct(
+        &sdr,
+        frame.cropped_width(),
+        frame.cropped_height(),
+        &gain_map,
+        headroom,
+        display_headroom,
+    );
+    image.picture_hash_mismatch = frame.picture_hash_mismatch;
+    Ok((image, frame.hdr_metadata))
 }
 
 /// Apply clean aperture (clap box) crop to a decoded frame
 fn apply_clean_aperture(frame: &mut hevc::DecodedFrame, clap: &heif::CleanAperture) {
     let conf_width = frame.cropped_width();
     let conf_height = frame.cropped_height();
+    let (left, top, width, height) = clean_aperture_rect(conf_width, conf_height, clap);
 
+    frame.crop_left += left;
+    frame.crop_right += conf_width - left - width;
+    frame.crop_top += top;
+    frame.crop_bottom += conf_height - top - height;
+}
+
+/// Visible (left, top, width, height) of a clean aperture within a
+/// `conf_width` x `conf_height` image
+fn clean_aperture_rect(
+    conf_width: u32,
+    conf_height: u32,
+    clap: &heif::CleanAperture,
+) -> (u32, u32, u32, u32) {
     let clean_width = if clap.width_d > 0 {
         clap.width_n / clap.width_d
     } else {
@@ -1369,7 +2788,7 @@ fn apply_clean_aperture(frame: &mut hevc::DecodedFrame, clap: &heif::CleanApertu
     };
 
     if clean_width >= conf_width && clean_height >= conf_height {
-        return;
+        return (0, 0, conf_width, conf_height);
     }
 
     let horiz_off_pixels = if clap.horiz_off_d > 0 {
@@ -1383,10 +2802,12 @@ fn apply_clean_aperture(frame: &mut hevc::DecodedFrame, clap: &heif::CleanApertu
         0.0
     };
 
-    let extra_left =
-        round_f64((conf_width as f64 - clean_width as f64) / 2.0 + horiz_off_pixels) as u32;
-    let extra_top =
-        round_f64((conf_height as f64 - clean_height as f64) / 2.0 + vert_off_pixels) as u32;
+    let extra_left = (round_f64((conf_width as f64 - clean_width as f64) / 2.0 + horiz_off_pixels)
+        as u32)
+        .min(conf_width);
+    let extra_top = (round_f64((conf_height as f64 - clean_height as f64) / 2.0 + vert_off_pixels)
+        as u32)
+        .min(conf_height);
     let extra_right = conf_width
         .saturating_sub(clean_width)
         .saturating_sub(extra_left);
@@ -1394,10 +2815,12 @@ fn apply_clean_aperture(frame: &mut hevc::DecodedFrame, clap: &heif::CleanApertu
         .saturating_sub(clean_height)
         .saturating_sub(extra_top);
 
-    frame.crop_left += extra_left;
-    frame.crop_right += extra_right;
-    frame.crop_top += extra_top;
-    frame.crop_bottom += extra_bottom;
+    (
+        extra_left,
+        extra_top,
+        conf_width - extra_left - extra_right,
+        conf_height - extra_top - extra_bottom,
+    )
 }
 
 /// Internal: extract EXIF TIFF data from HEIC container
@@ -1428,8 +2851,177 @@ fn extract_exif_inner(data: &[u8]) -> Result<Option<&[u8]>> {
     Ok(None)
 }
 
+/// Whether an item can be written to a sink row by row while it decodes: a
+/// base layer HEVC image without alpha or alternatives, whose only
+/// transforms are clean apertures
+fn streams_rows(container: &heif::HeifContainer<'_>, item: &heif::Item) -> bool {
+    item.item_type == ItemType::Hvc1
+        && item.hevc_config.is_some()
+        && layer_selection(item).is_ok_and(|s| s == hevc::LayerSelection::Layer(0))
+        && item
+            .transforms
+            .iter()
+            .all(|t| matches!(t, Transform::CleanAperture(_)))
+        && container.alternatives(item.id) == [item.id]
+        && alpha_item(container, item.id).is_none()
+}
+
+/// Decode an item accepted by [`streams_rows`] into a sink, converting and
+/// writing each band of rows as soon as the decoder has finished it
+fn stream_rows(
+    container: &heif::HeifContainer<'_>,
+    item: &heif::Item,
+    layout: PixelLayout,
+    limits: &Limits,
+    stop: &dyn Stop,
+    options: &DecoderConfig,
+    sink: &mut dyn ImageSink,
+) -> Result<ImageInfo> {
+    if let Some((w, h)) = item.dimensions {
+        limits.check_dimensions(w, h)?;
+    }
+    check_stop(stop)?;
+    let config = item
+        .hevc_config
+        .as_ref()
+        .ok_or(HeicError::InvalidData("Missing hvcC"))?;
+    let image_data = container
+        .get_item_data(item.id)
+        .ok_or(HeicError::InvalidData("Missing image data"))?;
+
+    // Visible window of the picture, known once its first rows arrive
+    let mut window: Option<Rect> = None;
+    let mut write_band = |frame: &hevc::DecodedFrame, rows: core::ops::Range<u32>| -> Result<()> {
+        check_stop(stop)?;
+        let (left, top, width, height) = match window {
+            Some(window) => window,
+            None => {
+                let visible = output_window(frame, &item.transforms);
+                limits.check_dimensions(visible.2, visible.3)?;
+                sink.begin(visible.2, visible.3, layout)?;
+                *window.insert(visible)
+            }
+        };
+        let (start, end) = (rows.start.max(top), rows.end.min(top + height));
+        if start >= end {
+            return Ok(());
+        }
+
+        let mut band = frame.copy_rows(start, end);
+        band.crop_left = left;
+        band.crop_right = frame.width - left - width;
+        // Set color conversion parameters from colr nclx box if present.
+        if let Some(ColorInfo::Nclx {
+            full_range,
+            matrix_coefficients,
+            ..
+        }) = &item.color_info
+        {
+            band.full_range = *full_range;
+            band.matrix_coeffs = *matrix_coefficients as u8;
+        }
+        sink.write_rows(start - top, end - start, &frame_to_layout(&band, layout))
+    };
+
+    let mut failure = None;
+    let result = hevc::decode_rows_with_config(config, image_data, &mut |frame, rows| {
+        write_band(frame, rows).map_err(|e| {
+            failure = Some(e);
+            HevcError::DecodingError("image sink failed")
+        })
+    });
+    let mut frame = match result {
+        Ok(frame) => frame,
+        Err(e) => return Err(failure.unwrap_or_else(|| e.into())),
+    };
+    finish_coded_image(&mut frame, item.id, options)?;
+    let (_, _, width, height) = window.ok_or(HeicError::InvalidData("no rows decoded"))?;
+
+    Ok(ImageInfo {
+        width,
+        height,
+        has_alpha: false,
+        bit_depth: frame.bit_depth,
+        chroma_format: frame.chroma_format,
+        has_exif: false, // Use ImageInfo::from_bytes() for metadata probing
+        has_xmp: false,
+        has_thumbnail: false,
+        hdr_metadata: item_hdr_metadata(item).or(frame.hdr_metadata),
+    })
+}
+
+/// Visible (left, top, width, height) of a decoded picture after its
+/// conformance window and clean apertures
+fn output_window(frame: &hevc::DecodedFrame, transforms: &[Transform]) -> Rect {
+    let (mut left, mut top) = (frame.crop_left, frame.crop_top);
+    let (mut width, mut height) = (frame.cropped_width(), frame.cropped_height());
+    for transform in transforms {
+        if let Transform::CleanAperture(clap) = transform {
+            let (l, t, w, h) = clean_aperture_rect(width, height, clap);
+            (left, top, width, height) = (left + l, top + t, w, h);
+        }
+    }
+    (left, top, width, height)
+}
+
+/// Convert a decoded frame to packed pixels in the given layout
+fn frame_to_layout(frame: &hevc::DecodedFrame, layout: PixelLayout) -> Vec<u8> {
+    match layout {
+        PixelLayout::Rgb8 => frame.to_rgb(),
+        PixelLayout::Rgba8 => frame.to_rgba(),
+        PixelLayout::Bgr8 => frame.to_bgr(),
+        PixelLayout::Bgra8 => frame.to_bgra(),
+        PixelLayout::Rgb16 | PixelLayout::Rgba16 => {
+            let size = frame.cropped_width() as usize
+                * frame.cropped_height() as usize
+                * layout.bytes_per_pixel();
+            let mut data = alloc::vec![0u8; size];
+            if layout == PixelLayout::Rgb16 {
+                frame.write_rgb16_into(&mut data);
+            } else {
+                frame.write_rgba16_into(&mut data);
+            }
+            data
+        }
+    }
+}
+
+/// Convert a decoded frame to the output layout, checking the output
+/// dimensions and size against `limits`
+fn frame_to_output(
+    frame: &hevc::DecodedFrame,
+    layout: PixelLayout,
+    limits: Option<&Limits>,
+) -> Result<DecodeOutput> {
+    let width = frame.cropped_width();
+    let height = frame.cropped_height();
+
+    // Check limits on final output dimensions
+    if let Some(limits) = limits {
+        limits.check_dimensions(width, height)?;
+        let output_bytes = u64::from(width) * u64::from(height) * layout.bytes_per_pixel() as u64;
+        limits.check_memory(output_bytes)?;
+    }
+
+    Ok(DecodeOutput {
+        data: frame_to_layout(frame, layout),
+        width,
+        height,
+        layout,
+        hdr_metadata: frame.hdr_metadata,
+        picture_hash_mismatch: frame.picture_hash_mismatch,
+    })
+}
+
 /// Internal: decode thumbnail image from HEIC container
-fn decode_thumbnail_inner(data: &[u8], layout: PixelLayout) -> Result<Option<DecodeOutput>> {
+fn decode_thumbnail_inner(
+    data: &[u8],
+    layout: PixelLayout,
+    limits: Option<&Limits>,
+    stop: &dyn Stop,
+    options: &DecoderConfig,
+) -> Result<Option<DecodeOutput>> {
+    check_stop(stop)?;
     let container = heif::parse(data)?;
     let primary_item = container.primary_item().ok_or(HeicError::NoPrimaryImage)?;
 
@@ -1442,25 +3034,147 @@ fn decode_thumbnail_inner(data: &[u8], layout: PixelLayout) -> Result<Option<Dec
         .get_item(thumb_id)
         .ok_or(HeicError::InvalidData("Thumbnail item not found"))?;
 
-    let stop: &dyn Stop = &Unstoppable;
-    let frame = decode_item(&container, &thumb_item, 0, &NO_LIMITS, stop)?;
+    let frame = decode_item_with_alpha(&container, &thumb_item, limits, stop, options)?;
+    frame_to_output(&frame, layout, limits).map(Some)
+}
 
-    let width = frame.cropped_width();
-    let height = frame.cropped_height();
+/// Displayed size of an item from its ispe (or hvcC) and transforms
+fn item_display_size(item: &heif::Item) -> Option<(u32, u32)> {
+    let (mut w, mut h) = item.dimensions.or_else(|| {
+        let info = hevc::get_info_from_config(item.hevc_config.as_ref()?).ok()?;
+        Some((info.width, info.height))
+    })?;
+    for transform in &item.transforms {
+        match transform {
+            Transform::CleanAperture(clap) => {
+                if let (Some(cw), Some(ch)) = (
+                    clap.width_n.checked_div(clap.width_d),
+                    clap.height_n.checked_div(clap.height_d),
+                ) {
+                    (w, h) = (cw.min(w), ch.min(h));
+                }
+            }
+            Transform::Rotation(rotation) if rotation.angle % 180 == 90 => (w, h) = (h, w),
+            _ => {}
+        }
+    }
+    Some((w, h))
+}
 
-    let pixels = match layout {
-        PixelLayout::Rgb8 => frame.to_rgb(),
-        PixelLayout::Rgba8 => frame.to_rgba(),
-        PixelLayout::Bgr8 => frame.to_bgr(),
-        PixelLayout::Bgra8 => frame.to_bgra(),
+/// Internal: decode the cheapest image covering a target size
+fn decode_for_size_inner(
+    request: DecodeRequest<'_>,
+    target_width: u32,
+    target_height: u32,
+) -> Result<(DecodeOutput, DecodeSource)> {
+    let (data, layout, options) = (request.data, request.layout, request.config);
+    let stop: &dyn Stop = request.stop.unwrap_or(&Unstoppable);
+    check_stop(stop)?;
+    let container = heif::parse(data)?;
+    let covers = |(w, h): (u32, u32)| w >= target_width && h >= target_height;
+
+    // Cheapest adequate source by pixel count; the primary image otherwise
+    let mut best: Option<(u64, heif::Item, DecodeSource)> = None;
+    if let Some(primary) = container.primary_item() {
+        let mut candidates: Vec<(u32, DecodeSource)> = container
+            .find_thumbnails(primary.id)
+            .into_iter()
+            .map(|item_id| (item_id, DecodeSource::Thumbnail { item_id }))
+            .collect();
+        if let Some(pyramid) = container
+            .groups_of_type(FourCC::PYMD)
+            .find(|g| g.entity_ids.contains(&primary.id))
+            .and_then(|g| g.pyramid.as_ref())
+        {
+            candidates.extend(
+                pyramid
+                    .layers
+                    .iter()
+                    .filter(|layer| layer.item_id != primary.id)
+                    .map(|layer| {
+                        let item_id = layer.item_id;
+                        (item_id, DecodeSource::PyramidLayer { item_id })
+                    }),
+            );
+        }
+
+        let primary_pixels = item_display_size(&primary)
+            .map_or(u64::MAX, |(w, h)| u64::from(w) * u64::from(h));
+        for (item_id, source) in candidates {
+            let Some(item) = container.get_item(item_id) else {
+                continue;
+            };
+            let Some(size) = item_display_size(&item).filter(|&size| covers(size)) else {
+                continue;
+            };
+            let pixels = u64::from(size.0) * u64::from(size.1);
+            if pixels < primary_pixels && best.as_ref().is_none_or(|b| pixels < b.0) {
+                best = Some((pixels, item, source));
+            }
+        }
+    }
+
+    let limits = request.limits;
+    let (frame, source) = match best {
+        Some((_, item, source)) => (
+            decode_item_with_alpha(&container, &item, limits, stop, options)?,
+            source,
+        ),
+        None => (
+            decode_to_frame_inner(data, limits, stop, request.layer, options)?,
+            DecodeSource::Primary,
+        ),
     };
+    let output = frame_to_output(&frame, layout, limits)?;
+    drop(frame);
+
+    // Smallest size covering the target with the source aspect ratio
+    let (width, height) = (output.width, output.height);
+    let scale = (f64::from(target_width) / f64::from(width.max(1)))
+        .max(f64::from(target_height) / f64::from(height.max(1)));
+    if scale <= 0.0 || scale >= 1.0 {
+        return Ok((output, source));
+    }
+    let dst_width = (round_f64(f64::from(width) * scale) as u32).clamp(1, width);
+    let dst_height = (round_f64(f64::from(height) * scale) as u32).clamp(1, height);
+    let data = resize::downscale(&output.data, width, height, layout, dst_width, dst_height);
 
-    Ok(Some(DecodeOutput {
-        data: pixels,
-        width,
-        height,
-        layout,
-    }))
+    Ok((
+        DecodeOutput {
+            data,
+            width: dst_width,
+            height: dst_height,
+            ..output
+        },
+        source,
+    ))
+}
+
+/// Internal: decode the views of the first stereo pair entity group
+fn decode_stereo_pair_inner(
+    data: &[u8],
+    layout: PixelLayout,
+    limits: Option<&Limits>,
+    stop: &dyn Stop,
+    options: &DecoderConfig,
+) -> Result<Option<(DecodeOutput, DecodeOutput)>> {
+    check_stop(stop)?;
+    let container = heif::parse(data)?;
+    let Some((left_id, right_id)) = container.stereo_pair() else {
+        return Ok(None);
+    };
+
+    let decode_view = |id| {
+        let item = container
+            .get_item(id)
+            .ok_or(HeicError::InvalidData("Stereo view item not found"))?;
+        let frame = decode_item_with_alpha(&container, &item, limits, stop, options)?;
+        frame_to_output(&frame, layout, limits)
+    };
+    let left = decode_view(left_id)?;
+    let right = decode_view(right_id)?;
+
+    Ok(Some((left, right)))
 }
 
 /// Internal: extract XMP XML data from HEIC container
@@ -1481,3 +3195,455 @@ fn extract_xmp_inner(data: &[u8]) -> Result<Option<&[u8]>> {
 
     Ok(None)
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::encode::tests::test_pixels;
+    use crate::heif::{EntityGroup, ItemProperty};
+    use crate::hevc::bitstream::{NalType, write_nal_unit};
+
+    /// A single-image HEIC whose picture carries a wrong MD5 picture hash
+    pub(crate) fn heic_with_wrong_hash() -> Vec<u8> {
+        let heic = EncoderConfig::new()
+            .with_alpha(false)
+            .encode(&test_pixels(64, 48), 64, 48, PixelLayout::Rgba8)
+            .unwrap();
+        let mut editor = HeifEditor::new(&heic).unwrap();
+        let container = heif::parse(&heic).unwrap();
+        let mut item_data = container
+            .get_item_data(container.primary_item_id)
+            .unwrap()
+            .to_vec();
+        // decoded_picture_hash SEI: hash_type 0 and a zero MD5 per plane
+        let mut rbsp = alloc::vec![132, 49, 0];
+        rbsp.extend([0; 48]);
+        rbsp.push(0x80);
+        let sei = write_nal_unit(NalType::SuffixSeiNut, 0, &rbsp);
+        item_data.extend_from_slice(&(sei.len() as u32).to_be_bytes());
+        item_data.extend(sei);
+        editor
+            .replace_item_data(container.primary_item_id, item_data)
+            .unwrap();
+        editor.to_bytes().unwrap()
+    }
+
+    /// Sink assembling the rows and tiles it receives into one image
+    #[derive(Default)]
+    struct BufferSink {
+        width: u32,
+        bytes_per_pixel: usize,
+        data: Vec<u8>,
+    }
+
+    impl ImageSink for BufferSink {
+        fn begin(&mut self, width: u32, height: u32, layout: PixelLayout) -> Result<()> {
+            self.width = width;
+            self.bytes_per_pixel = layout.bytes_per_pixel();
+            self.data = alloc::vec![0; width as usize * height as usize * self.bytes_per_pixel];
+            Ok(())
+        }
+
+        fn write_rows(&mut self, y: u32, height: u32, data: &[u8]) -> Result<()> {
+            self.write_tile(0, y, self.width, height, data)
+        }
+
+        fn write_tile(&mut self, x: u32, y: u32, w: u32, h: u32, data: &[u8]) -> Result<()> {
+            let row_bytes = w as usize * self.bytes_per_pixel;
+            for (row, src) in data.chunks_exact(row_bytes).take(h as usize).enumerate() {
+                let pixel = (y as usize + row) * self.width as usize + x as usize;
+                let start = pixel * self.bytes_per_pixel;
+                self.data[start..start + row_bytes].copy_from_slice(src);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_picture_hash_mismatch_reported() {
+        let heic = heic_with_wrong_hash();
+        let layout = PixelLayout::Rgb8;
+        let mut buffer = alloc::vec![0; 64 * 48 * 3];
+        for (check, expected) in [
+            (PictureHashCheck::Off, false),
+            (PictureHashCheck::Warn, true),
+        ] {
+            let config = DecoderConfig::new().with_picture_hash_check(check);
+            let output = config.decode(&heic, layout).unwrap();
+            assert_eq!(output.picture_hash_mismatch, expected);
+            let request = config.decode_request(&heic).with_output_layout(layout);
+            request.decode_into(&mut buffer).unwrap();
+            assert_eq!(buffer, output.data);
+            let request = config.decode_request(&heic).with_output_layout(layout);
+            let mut sink = BufferSink::default();
+            request.decode_to_sink(&mut sink).unwrap();
+            assert_eq!(sink.data, output.data);
+        }
+        let config = DecoderConfig::new().with_picture_hash_check(PictureHashCheck::Error);
+        assert!(config.decode(&heic, layout).is_err());
+        let request = config.decode_request(&heic).with_output_layout(layout);
+        assert!(request.decode_into(&mut buffer).is_err());
+        let request = config.decode_request(&heic).with_output_layout(layout);
+        assert!(request.decode_to_sink(&mut BufferSink::default()).is_err());
+    }
+
+    /// A single-image HEIC and its file with a second image item, a copy of
+    /// the primary image with the same properties
+    fn file_with_copy() -> (Vec<u8>, heif::HeifFile, u32) {
+        let heic = EncoderConfig::new()
+            .with_alpha(false)
+            .encode(&test_pixels(64, 48), 64, 48, PixelLayout::Rgba8)
+            .unwrap();
+        let mut file = HeifEditor::new(&heic).unwrap().into_file();
+        let primary = file.primary_item_id;
+        let data = file.item_data(primary).unwrap().to_vec();
+        let copy = file.add_item(FourCC::HVC1, "", data);
+        let mut assoc = file
+            .property_associations
+            .iter()
+            .find(|a| a.item_id == primary)
+            .unwrap()
+            .clone();
+        assoc.item_id = copy;
+        file.property_associations.push(assoc);
+        (heic, file, copy)
+    }
+
+    fn entity_group(grouping_type: FourCC, group_id: u32, entity_ids: Vec<u32>) -> EntityGroup {
+        EntityGroup {
+            grouping_type,
+            group_id,
+            entity_ids,
+            pyramid: None,
+        }
+    }
+
+    #[test]
+    fn test_decode_stereo_pair() {
+        let (heic, mut file, copy) = file_with_copy();
+        let primary = file.primary_item_id;
+        let group_id = file.next_item_id();
+        let group = entity_group(FourCC::STER, group_id, alloc::vec![primary, copy]);
+        file.entity_groups.push(group);
+        let stereo = file.to_bytes().unwrap();
+
+        let config = DecoderConfig::new();
+        let layout = PixelLayout::Rgb8;
+        let expected = config.decode(&heic, layout).unwrap();
+        let (left, right) = config.decode_stereo_pair(&stereo, layout).unwrap().unwrap();
+        assert_eq!(left.data, expected.data);
+        assert_eq!(right.data, expected.data);
+        assert!(config.decode_stereo_pair(&heic, layout).unwrap().is_none());
+
+        // The request's limits apply to each view
+        let limits = Limits {
+            max_pixels: Some(64 * 47),
+            ..Limits::default()
+        };
+        let request = config.decode_request(&stereo).with_limits(&limits);
+        let error = request.decode_stereo_pair().unwrap_err();
+        assert!(matches!(error.error(), HeicError::LimitExceeded(_)));
+    }
+
+    #[test]
+    fn test_decode_first_alternative() {
+        // The primary item has an unknown type; its alternative is decoded
+        let (heic, mut file, _) = file_with_copy();
+        let primary = file.primary_item_id;
+        let unknown = file.add_item(FourCC(*b"zzzz"), "", alloc::vec![0; 16]);
+        file.primary_item_id = unknown;
+        let group_id = file.next_item_id();
+        let group = entity_group(FourCC::ALTR, group_id, alloc::vec![unknown, primary]);
+        file.entity_groups.push(group);
+        let alternative = file.to_bytes().unwrap();
+
+        let config = DecoderConfig::new();
+        let expected = config.decode(&heic, PixelLayout::Rgb8).unwrap();
+        let output = config.decode(&alternative, PixelLayout::Rgb8).unwrap();
+        assert_eq!(output.data, expected.data);
+
+        // Without the group the primary item cannot be decoded
+        file.entity_groups.clear();
+        let error = config
+            .decode(&file.to_bytes().unwrap(), PixelLayout::Rgb8)
+            .unwrap_err();
+        assert!(matches!(error.error(), HeicError::Unsupported(_)));
+    }
+
+    /// Length-prefixed sample data of NAL units
+    fn sample_data(nal_units: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for nal in nal_units {
+            data.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+            data.extend_from_slice(nal);
+        }
+        data
+    }
+
+    /// A HEIC whose 64x64 base layer is in an hvc1 item, and whose 128x128
+    /// layer predicted from it is in an lhv1 primary item that references
+    /// it with tbas, with the NAL units of both layers
+    pub(crate) fn inter_layer_file() -> (Vec<u8>, Vec<Vec<u8>>) {
+        let picture = hevc::encoder::tests::test_picture(64, 64);
+        let nal_units = hevc::encoder::layers::encode_two_layers(&picture, 30, 128, 128);
+        let heic = EncoderConfig::new()
+            .with_alpha(false)
+            .encode(&test_pixels(64, 64), 64, 64, PixelLayout::Rgba8)
+            .unwrap();
+        let mut file = HeifEditor::new(&heic).unwrap().into_file();
+        let base = file.primary_item_id;
+        for property in &mut file.properties {
+            if let ItemProperty::HevcConfig(config) = &mut property.property {
+                config.nal_units = nal_units[..3].to_vec();
+                property.raw = None;
+            }
+        }
+        let base_data = sample_data(&nal_units[5..6]);
+        file.set_item_data(base, base_data).unwrap();
+
+        let layered = file.add_item(FourCC(*b"lhv1"), "", sample_data(&nal_units[6..]));
+        let mut content = alloc::vec![1, 0xF0, 0x00, 0xFC, 0xCB, 2];
+        for nal in &nal_units[3..5] {
+            content.push(0x80 | nal[0] >> 1);
+            content.extend_from_slice(&1u16.to_be_bytes());
+            content.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            content.extend_from_slice(nal);
+        }
+        let mut raw = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        raw.extend_from_slice(b"lhvC");
+        raw.extend_from_slice(&content);
+        file.properties.push(heif::FileProperty {
+            property: ItemProperty::LHevcConfig(heif::LHevcDecoderConfig {
+                config_version: 1,
+                length_size_minus_one: 3,
+                nal_units: nal_units[3..5].to_vec(),
+            }),
+            raw: Some(raw),
+        });
+        let extents = heif::ImageSpatialExtents {
+            width: 128,
+            height: 128,
+        };
+        let ispe = heif::FileProperty::new(ItemProperty::ImageExtents(extents));
+        file.properties.push(ispe);
+        let count = file.properties.len() as u16;
+        file.property_associations.push(heif::PropertyAssociation {
+            item_id: layered,
+            properties: alloc::vec![(count - 1, true), (count, false)],
+        });
+        file.item_references.push(heif::ItemReference {
+            reference_type: FourCC::TBAS,
+            from_item_id: layered,
+            to_item_ids: alloc::vec![base],
+        });
+        file.primary_item_id = layered;
+        (file.to_bytes().unwrap(), nal_units)
+    }
+
+    #[test]
+    fn test_decode_inter_layer_primary() {
+        let (bytes, nal_units) = inter_layer_file();
+
+        // The primary image shows the enhancement layer, the same as the
+        // layers decoded from the plain stream
+        let mut stream = Vec::new();
+        for nal in &nal_units {
+            stream.extend_from_slice(&[0, 0, 0, 1]);
+            stream.extend_from_slice(nal);
+        }
+        let mut decoder = hevc::SequenceDecoder::with_layer(hevc::LayerSelection::Highest);
+        let mut expected = decoder.decode(&stream).unwrap();
+        expected.extend(decoder.flush().unwrap());
+        let config = DecoderConfig::new();
+        let frame = config.decode_request(&bytes).decode_yuv().unwrap();
+        assert_eq!((frame.width, frame.height), (128, 128));
+        assert_eq!(frame.y_plane, expected[0].y_plane);
+        assert_eq!(frame.cb_plane, expected[0].cb_plane);
+
+        // The base layer alone on request
+        let base_frame = config
+            .decode_request(&bytes)
+            .with_layer(0)
+            .decode_yuv()
+            .unwrap();
+        assert_eq!((base_frame.width, base_frame.height), (64, 64));
+    }
+
+    /// `base` with the items of `other` added, and the new ID of the
+    /// primary item of `other`
+    fn merge_files(base: &[u8], other: &[u8]) -> (heif::HeifFile, u32) {
+        let mut file = HeifEditor::new(base).unwrap().into_file();
+        let other = HeifEditor::new(other).unwrap().into_file();
+        let id_offset = file.next_item_id() - 1;
+        let property_offset = file.properties.len() as u16;
+        for mut info in other.item_infos {
+            info.item_id += id_offset;
+            file.item_infos.push(info);
+        }
+        for (item_id, data) in other.item_data {
+            file.item_data.push((item_id + id_offset, data));
+        }
+        file.properties.extend(other.properties);
+        for mut assoc in other.property_associations {
+            assoc.item_id += id_offset;
+            for (index, _) in &mut assoc.properties {
+                *index += property_offset;
+            }
+            file.property_associations.push(assoc);
+        }
+        for mut reference in other.item_references {
+            reference.from_item_id += id_offset;
+            for id in &mut reference.to_item_ids {
+                *id += id_offset;
+            }
+            file.item_references.push(reference);
+        }
+        (file, other.primary_item_id + id_offset)
+    }
+
+    #[test]
+    fn test_decode_for_size_thumbnail_alpha() {
+        let encode = |width, height| {
+            let pixels = test_pixels(width, height);
+            EncoderConfig::new()
+                .encode(&pixels, width, height, PixelLayout::Rgba8)
+                .unwrap()
+        };
+        let thumb = encode(32, 24);
+        let (mut file, thumb_id) = merge_files(&encode(64, 48), &thumb);
+        file.item_references.push(heif::ItemReference {
+            reference_type: FourCC::THMB,
+            from_item_id: thumb_id,
+            to_item_ids: alloc::vec![file.primary_item_id],
+        });
+        let heic = file.to_bytes().unwrap();
+
+        // The thumbnail is decoded with its alpha plane
+        let config = DecoderConfig::new();
+        let layout = PixelLayout::Rgba8;
+        let expected = config.decode(&thumb, layout).unwrap();
+        assert!(expected.data[3] < 8);
+        let (output, source) = config.decode_for_size(&heic, 32, 24, layout).unwrap();
+        assert_eq!(source, DecodeSource::Thumbnail { item_id: thumb_id });
+        assert_eq!(output.data, expected.data);
+        let output = config.decode_thumbnail(&heic, layout).unwrap().unwrap();
+        assert_eq!(output.data, expected.data);
+
+        // The request's limits apply to the thumbnail
+        let limits = Limits {
+            max_pixels: Some(32 * 23),
+            ..Limits::default()
+        };
+        let request = config.decode_request(&heic).with_limits(&limits);
+        let error = request.decode_for_size(32, 24).unwrap_err();
+        assert!(matches!(error.error(), HeicError::LimitExceeded(_)));
+    }
+
+    /// Editor for `heic` with a 90x51 clean aperture on `items`
+    fn with_clean_aperture(heic: &[u8], items: &[u32]) -> HeifEditor {
+        let mut editor = HeifEditor::new(heic).unwrap();
+        let file = editor.file_mut();
+        let clap = ItemProperty::CleanAperture(heif::CleanAperture {
+            width_n: 90,
+            width_d: 1,
+            height_n: 51,
+            height_d: 1,
+            horiz_off_n: -3,
+            horiz_off_d: 1,
+            vert_off_n: 1,
+            vert_off_d: 2,
+        });
+        file.properties.push(heif::FileProperty::new(clap));
+        let clap = file.properties.len() as u16;
+        for assoc in &mut file.property_associations {
+            if items.contains(&assoc.item_id) {
+                assoc.properties.push((clap, true));
+            }
+        }
+        editor
+    }
+
+    #[test]
+    fn test_decode_to_sink_grid_alpha() {
+        let (width, height) = (101, 67);
+        let pixels = test_pixels(width, height);
+        let heic = EncoderConfig::new()
+            .with_tile_size(64)
+            .encode(&pixels, width, height, PixelLayout::Rgba8)
+            .unwrap();
+        let container = heif::parse(&heic).unwrap();
+        let primary = container.primary_item_id;
+        let alpha = alpha_item(&container, primary).unwrap();
+
+        // Clean aperture on both images streams the alpha tiles; on the
+        // primary image only, the alpha image is decoded whole
+        for clap_items in [&[primary, alpha][..], &[primary]] {
+            let mut editor = with_clean_aperture(&heic, clap_items);
+            for value in 1..=8 {
+                editor.set_orientation(Orientation::from_exif(value).unwrap());
+                let oriented = editor.to_bytes().unwrap();
+                let layout = PixelLayout::Rgba8;
+                let config = DecoderConfig::new();
+                let expected = config.decode(&oriented, layout).unwrap();
+                let mut sink = BufferSink::default();
+                let request = config.decode_request(&oriented).with_output_layout(layout);
+                let info = request.decode_to_sink(&mut sink).unwrap();
+                assert!(info.has_alpha);
+                assert_eq!((info.width, info.height), (expected.width, expected.height));
+                assert_eq!(sink.data, expected.data, "orientation {value}");
+                // Some of the transparent corner is visible
+                assert!(expected.data.chunks_exact(4).any(|px| px[3] < 8));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_region_matches_cropped_decode() {
+        let (width, height) = (101, 67);
+        let pixels = test_pixels(width, height);
+        for tile_size in [64, 512] {
+            let heic = EncoderConfig::new()
+                .with_tile_size(tile_size)
+                .encode(&pixels, width, height, PixelLayout::Rgba8)
+                .unwrap();
+            let container = heif::parse(&heic).unwrap();
+            let primary = container.primary_item().unwrap();
+            assert_eq!(primary.item_type == ItemType::Grid, tile_size == 64);
+            let alpha = alpha_item(&container, primary.id).unwrap();
+
+            for clap_items in [&[primary.id, alpha][..], &[primary.id]] {
+                let mut editor = with_clean_aperture(&heic, clap_items);
+                for value in 1..=8 {
+                    editor.set_orientation(Orientation::from_exif(value).unwrap());
+                    let oriented = editor.to_bytes().unwrap();
+                    let layout = PixelLayout::Rgba8;
+                    let config = DecoderConfig::new();
+                    let full = config.decode(&oriented, layout).unwrap();
+                    let (w, h) = (full.width, full.height);
+
+                    // The whole image, odd offsets, inside one tile and across tiles
+                    let regions = [
+                        (0, 0, w, h),
+                        (3, 5, 17, 9),
+                        (w - 40, h - 30, 40, 30),
+                        (1, 1, w - 2, h - 2),
+                    ];
+                    for (x, y, region_w, region_h) in regions {
+                        let request = config.decode_request(&oriented).with_output_layout(layout);
+                        let region = request.decode_region(x, y, region_w, region_h).unwrap();
+                        let expected: Vec<u8> = (y..y + region_h)
+                            .flat_map(|row| {
+                                let start = ((row * w + x) * 4) as usize;
+                                &full.data[start..start + (region_w * 4) as usize]
+                            })
+                            .copied()
+                            .collect();
+                        assert_eq!((region.width, region.height), (region_w, region_h));
+                        let at = (tile_size, clap_items.len(), value, x, y);
+                        assert_eq!(region.data, expected, "{at:?}");
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,543 @@
+//! Planning the byte ranges to fetch from files read piecewise
+
+use alloc::vec::Vec;
+
+use crate::heif::{self, FourCC, HeifContainer, subset};
+use crate::{HeicError, Result};
+
+/// A span of bytes of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    /// Offset of the first byte from the start of the file
+    pub offset: u64,
+    /// Number of bytes
+    pub len: u64,
+}
+
+/// Progress of a [`RangePlanner`] through the file header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderStatus {
+    /// Fetch `len` bytes at `offset` and pass them to [`RangePlanner::push`]
+    NeedMoreData {
+        /// Offset of the first byte to fetch
+        offset: u64,
+        /// Number of bytes to fetch
+        len: u64,
+    },
+    /// The `ftyp` and `meta` boxes are loaded and parts can be planned
+    Complete,
+}
+
+/// Part of a file an operation needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FilePart {
+    /// The primary image with its alternatives, derived-from images and
+    /// auxiliary images (alpha, depth, gain map)
+    PrimaryImage,
+    /// The primary image's thumbnail and its auxiliary images
+    Thumbnail,
+    /// EXIF metadata
+    Exif,
+    /// XMP metadata
+    Xmp,
+}
+
+/// Plans the minimum byte ranges to fetch from a file read piecewise, such
+/// as an object in remote storage read with range requests
+///
+/// The planner first walks the top-level boxes, asking through
+/// [`HeaderStatus::NeedMoreData`] for the box headers and for the whole
+/// `ftyp` and `meta` boxes, wherever they are in the file; box contents
+/// such as `mdat` are skipped. It then lists the exact ranges holding a
+/// [`FilePart`], and assembles the fetched bytes into a small self-contained
+/// HEIF file that every slice-based function of
+/// [`DecoderConfig`](crate::DecoderConfig) accepts.
+///
+/// # Example
+///
+/// ```ignore
+/// use heic_decoder::{DecoderConfig, FilePart, HeaderStatus, PixelLayout, RangePlanner};
+///
+/// let mut planner = RangePlanner::new(object_size);
+/// let mut status = planner.next_request();
+/// while let HeaderStatus::NeedMoreData { offset, len } = status {
+///     status = planner.push(offset, &store.get_range(offset, len)?)?;
+/// }
+///
+/// let ranges = planner.ranges(FilePart::PrimaryImage)?.unwrap_or_default();
+/// let fetched: Vec<_> = ranges.iter().map(|r| store.get_range(r.offset, r.len)).collect();
+/// let file = planner.assemble(FilePart::PrimaryImage, &fetched)?;
+/// let output = DecoderConfig::new().decode(&file, PixelLayout::Rgba8)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RangePlanner {
+    /// File length, unknown when probing a prefix of the file
+    file_len: Option<u64>,
+    /// Offset of the next top-level box to examine
+    pos: u64,
+    /// Size of the box at `pos` once its header is known and it is to be loaded
+    pending: Option<u64>,
+    /// The `ftyp` and `meta` boxes, back to back
+    header: Vec<u8>,
+    has_ftyp: bool,
+    has_meta: bool,
+}
+
+impl RangePlanner {
+    /// Start planning for a file of `file_len` bytes
+    ///
+    /// The length is needed to resolve boxes and extents that run to the
+    /// end of the file; object stores report it in `Content-Length` or
+    /// `Content-Range` headers.
+    #[must_use]
+    pub fn new(file_len: u64) -> Self {
+        Self {
+            file_len: Some(file_len),
+            pos: 0,
+            pending: None,
+            header: Vec::new(),
+            has_ftyp: false,
+            has_meta: false,
+        }
+    }
+
+    /// Start planning for a file of unknown length, of which only some
+    /// windows are at hand
+    ///
+    /// Boxes of size 0 then extend to the end of the data pushed.
+    pub(crate) fn with_unknown_len() -> Self {
+        Self {
+            file_len: None,
+            ..Self::new(0)
+        }
+    }
+
+    /// The bytes to fetch next, or [`HeaderStatus::Complete`]
+    ///
+    /// Box headers are requested 16 bytes at a time. Fetching more than
+    /// asked for saves round trips: [`push`](Self::push) uses every box
+    /// that starts within the data it is given.
+    #[must_use]
+    pub fn next_request(&self) -> HeaderStatus {
+        if self.is_complete() {
+            return HeaderStatus::Complete;
+        }
+        let remaining = self
+            .file_len
+            .map_or(u64::MAX, |len| len.saturating_sub(self.pos));
+        HeaderStatus::NeedMoreData {
+            offset: self.pos,
+            len: self.pending.unwrap_or(16).min(remaining),
+        }
+    }
+
+    /// Take in `data` fetched from `offset` and return what is needed next
+    ///
+    /// Data need not match the requested range; bytes before the next box
+    /// are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the top-level boxes are malformed, or the file
+    /// ends without both an `ftyp` and a `meta` box.
+    pub fn push(&mut self, offset: u64, data: &[u8]) -> Result<HeaderStatus> {
+        while !self.is_complete() {
+            if self
+                .file_len
+                .is_some_and(|len| len.saturating_sub(self.pos) < 8)
+            {
+                return Err(HeicError::InvalidContainer(if self.has_ftyp {
+                    "missing meta box"
+                } else {
+                    "missing ftyp box"
+                })
+                .into());
+            }
+            let Some(window) = self
+                .pos
+                .checked_sub(offset)
+                .and_then(|start| data.get(usize::try_from(start).ok()?..))
+                .filter(|window| window.len() >= 8)
+            else {
+                break;
+            };
+
+            let size_32 = u32::from_be_bytes([window[0], window[1], window[2], window[3]]);
+            let box_type = FourCC([window[4], window[5], window[6], window[7]]);
+            let (size, header_len) = match size_32 {
+                0 => (
+                    self.file_len.unwrap_or(offset + data.len() as u64) - self.pos,
+                    8,
+                ),
+                1 => match window.get(8..16) {
+                    Some(ext) => (u64::from_be_bytes(ext.try_into().unwrap()), 16),
+                    None => break,
+                },
+                n => (u64::from(n), 8),
+            };
+            if size < header_len || self.file_len.is_some_and(|len| size > len - self.pos) {
+                return Err(HeicError::InvalidContainer("box extends beyond end of file").into());
+            }
+
+            let load = match box_type {
+                FourCC::FTYP => !self.has_ftyp,
+                FourCC::META => !self.has_meta,
+                _ => false,
+            };
+            if load {
+                let Some(bytes) = usize::try_from(size)
+                    .ok()
+                    .and_then(|size| window.get(..size))
+                else {
+                    self.pending = Some(size);
+                    break;
+                };
+                self.header.extend_from_slice(bytes);
+                if box_type == FourCC::FTYP {
+                    self.has_ftyp = true;
+                } else {
+                    self.has_meta = true;
+                }
+            }
+            self.pending = None;
+            self.pos += size;
+        }
+        Ok(self.next_request())
+    }
+
+    /// Whether the `ftyp` and `meta` boxes are loaded
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.has_ftyp && self.has_meta
+    }
+
+    /// The `ftyp` and `meta` boxes loaded so far, back to back
+    ///
+    /// Once [`is_complete`](Self::is_complete), this parses as a HEIF file
+    /// without item data, enough for
+    /// [`ImageInfo::from_bytes`](crate::ImageInfo::from_bytes) when the
+    /// image's parameter sets are in the `meta` box.
+    #[must_use]
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// The byte ranges holding `part`, in file order of each item's extents
+    ///
+    /// Returns `None` if the file has no such part. The list is empty when
+    /// the part is stored entirely inside the `meta` box.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is not complete, the container is
+    /// malformed or an extent lies beyond the end of the file.
+    pub fn ranges(&self, part: FilePart) -> Result<Option<Vec<ByteRange>>> {
+        let container = self.container()?;
+        let Some(items) = part_items(&container, part)? else {
+            return Ok(None);
+        };
+        let mut ranges = Vec::new();
+        for item_id in items {
+            let extents = self.item_ranges(&container, item_id)?;
+            ranges.extend(
+                extents
+                    .into_iter()
+                    .flatten()
+                    .map(|(offset, len)| ByteRange { offset, len }),
+            );
+        }
+        Ok(Some(ranges))
+    }
+
+    /// Build a HEIF file holding `part` from the bytes of the ranges
+    /// [`ranges`](Self::ranges) listed for it, in the same order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is not complete, the fetched data
+    /// does not match the ranges or the container is malformed.
+    pub fn assemble<B: AsRef<[u8]>>(&self, part: FilePart, fetched: &[B]) -> Result<Vec<u8>> {
+        let container = self.container()?;
+        let mut fetched = fetched.iter().map(AsRef::as_ref);
+        let mut items = Vec::new();
+        for item_id in part_items(&container, part)?.unwrap_or_default() {
+            let Some(extents) = self.item_ranges(&container, item_id)? else {
+                continue;
+            };
+            let mut data = Vec::new();
+            for (_, len) in extents {
+                let bytes = fetched
+                    .next()
+                    .filter(|bytes| bytes.len() as u64 == len)
+                    .ok_or(HeicError::InvalidData("fetched data does not match ranges"))?;
+                data.extend_from_slice(bytes);
+            }
+            items.push((item_id, data));
+        }
+        if fetched.next().is_some() {
+            return Err(HeicError::InvalidData("fetched data does not match ranges").into());
+        }
+        subset::assemble(&self.header, &container, &items)
+    }
+
+    fn item_ranges(
+        &self,
+        container: &HeifContainer<'_>,
+        item_id: u32,
+    ) -> Result<Option<Vec<(u64, u64)>>> {
+        subset::item_ranges(container, item_id, self.file_len.unwrap_or(u64::MAX))
+    }
+
+    fn container(&self) -> Result<HeifContainer<'_>> {
+        if !self.is_complete() {
+            return Err(HeicError::InvalidContainer("header not loaded").into());
+        }
+        heif::parse(&self.header)
+    }
+}
+
+/// Items making up `part`, or `None` if the file has no such part
+fn part_items(container: &HeifContainer<'_>, part: FilePart) -> Result<Option<Vec<u32>>> {
+    let items = match part {
+        FilePart::PrimaryImage => {
+            let primary = container.primary_item().ok_or(HeicError::NoPrimaryImage)?;
+            subset::image_items(container, primary.id)
+        }
+        FilePart::Thumbnail => {
+            let primary = container.primary_item().ok_or(HeicError::NoPrimaryImage)?;
+            match container.find_thumbnails(primary.id).first() {
+                Some(&thumb_id) => subset::image_items(container, thumb_id),
+                None => Vec::new(),
+            }
+        }
+        FilePart::Exif => subset::exif_items(container),
+        FilePart::Xmp => subset::xmp_items(container),
+    };
+    Ok((!items.is_empty()).then_some(items))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::heif::ItemLocation;
+    use crate::heif::writer::{write_box, write_iloc};
+    use alloc::vec;
+
+    /// A file laid out as streaming writers do: ftyp, then mdat holding an
+    /// image (item 1), EXIF (item 2) and a thumbnail (item 3), then meta
+    pub(crate) struct SampleFile {
+        pub(crate) file: Vec<u8>,
+        pub(crate) image: Vec<u8>,
+        pub(crate) exif: Vec<u8>,
+        pub(crate) thumb: Vec<u8>,
+        pub(crate) data_start: u64,
+        pub(crate) meta_len: usize,
+    }
+
+    fn infe(item_id: u16, item_type: &[u8; 4]) -> Vec<u8> {
+        let mut content = vec![2, 0, 0, 0];
+        content.extend_from_slice(&item_id.to_be_bytes());
+        content.extend_from_slice(&[0, 0]);
+        content.extend_from_slice(item_type);
+        content.push(0);
+        let mut out = Vec::new();
+        write_box(&mut out, FourCC::INFE, &content);
+        out
+    }
+
+    pub(crate) fn sample_file() -> SampleFile {
+        let image = vec![0xAA; 300];
+        let exif = [&[0u8, 0, 0, 0][..], b"MM\0*\0\0\0\x08"].concat();
+        let thumb = vec![0xBB; 40];
+
+        let mut file = Vec::new();
+        write_box(&mut file, FourCC::FTYP, b"heic\0\0\0\0mif1heic");
+        let data_start = file.len() as u64 + 8;
+        write_box(
+            &mut file,
+            FourCC::MDAT,
+            &[&image[..], &exif, &thumb].concat(),
+        );
+
+        let mut iinf = vec![0, 0, 0, 0, 0, 3];
+        iinf.extend(infe(1, b"hvc1"));
+        iinf.extend(infe(2, b"Exif"));
+        iinf.extend(infe(3, b"hvc1"));
+        // Item 3 is the thumbnail of item 1
+        let mut iref = vec![0, 0, 0, 0];
+        write_box(&mut iref, FourCC::THMB, &[0, 3, 0, 1, 0, 1]);
+        let location = |item_id, offset: usize, len: usize| ItemLocation {
+            item_id,
+            construction_method: 0,
+            base_offset: data_start,
+            extents: vec![(offset as u64, len as u64)],
+        };
+        let locations = [
+            location(1, 0, image.len()),
+            location(2, image.len(), exif.len()),
+            location(3, image.len() + exif.len(), thumb.len()),
+        ];
+
+        let mut meta = vec![0, 0, 0, 0];
+        write_box(&mut meta, FourCC::PITM, &[0, 0, 0, 0, 0, 1]);
+        write_box(&mut meta, FourCC::IINF, &iinf);
+        write_box(&mut meta, FourCC::IREF, &iref);
+        write_iloc(&mut meta, &locations);
+        write_box(&mut file, FourCC::META, &meta);
+
+        SampleFile {
+            file,
+            image,
+            exif,
+            thumb,
+            data_start,
+            meta_len: meta.len(),
+        }
+    }
+
+    #[test]
+    fn test_plan_ranges() {
+        let sample = sample_file();
+        // In-memory stand-in for an object store, logging range requests
+        let mut requests = Vec::new();
+        let get_range = |requests: &mut Vec<ByteRange>, offset: u64, len: u64| {
+            requests.push(ByteRange { offset, len });
+            sample.file[offset as usize..][..len as usize].to_vec()
+        };
+
+        let mut planner = RangePlanner::new(sample.file.len() as u64);
+        let mut status = planner.next_request();
+        while let HeaderStatus::NeedMoreData { offset, len } = status {
+            status = planner
+                .push(offset, &get_range(&mut requests, offset, len))
+                .unwrap();
+        }
+        assert!(planner.is_complete());
+        let image_len = sample.image.len() as u64;
+        let exif_len = sample.exif.len() as u64;
+        let exif_range = ByteRange {
+            offset: sample.data_start + image_len,
+            len: exif_len,
+        };
+        let fetched: u64 = requests.iter().map(|r| r.len).sum();
+        assert!(fetched < 100 + sample.meta_len as u64);
+        // The mdat content is skipped, apart from 8 bytes fetched with its header
+        let meta_offset = (sample.file.len() - sample.meta_len - 8) as u64;
+        assert!(
+            requests
+                .iter()
+                .all(|r| r.offset + r.len <= sample.data_start + 8 || r.offset >= meta_offset)
+        );
+
+        let ranges = planner.ranges(FilePart::Exif).unwrap().unwrap();
+        assert_eq!(ranges, [exif_range]);
+        let data: Vec<_> = ranges
+            .iter()
+            .map(|r| get_range(&mut requests, r.offset, r.len))
+            .collect();
+        let file = planner.assemble(FilePart::Exif, &data).unwrap();
+        let exif = crate::DecoderConfig::new().extract_exif(&file).unwrap();
+        assert_eq!(exif, Some(&sample.exif[4..]));
+
+        let primary = planner.ranges(FilePart::PrimaryImage).unwrap().unwrap();
+        assert_eq!(
+            primary,
+            [ByteRange {
+                offset: sample.data_start,
+                len: image_len
+            }]
+        );
+        let thumb = planner.ranges(FilePart::Thumbnail).unwrap().unwrap();
+        assert_eq!(thumb[0].offset, exif_range.offset + exif_len);
+        assert_eq!(planner.ranges(FilePart::Xmp).unwrap(), None);
+
+        // Data that does not match the plan is rejected
+        assert!(planner.assemble(FilePart::PrimaryImage, &data).is_err());
+
+        // One large first fetch covers both header boxes after the ftyp
+        let mut planner = RangePlanner::new(sample.file.len() as u64);
+        let status = planner.push(0, &sample.file[..100]).unwrap();
+        assert_eq!(
+            status,
+            HeaderStatus::NeedMoreData {
+                offset: sample.data_start + image_len + exif_len + sample.thumb.len() as u64,
+                len: 16
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_layered_primary() {
+        // The lhv1 primary needs the data of its hvc1 base layer as well
+        let (file, _) = crate::tests::inter_layer_file();
+        let mut planner = RangePlanner::new(file.len() as u64);
+        let mut status = planner.next_request();
+        while let HeaderStatus::NeedMoreData { offset, len } = status {
+            let data = &file[offset as usize..][..len as usize];
+            status = planner.push(offset, data).unwrap();
+        }
+        let ranges = planner.ranges(FilePart::PrimaryImage).unwrap().unwrap();
+        assert_eq!(ranges.len(), 2);
+        let data: Vec<_> = ranges
+            .iter()
+            .map(|r| file[r.offset as usize..][..r.len as usize].to_vec())
+            .collect();
+        let subset = planner.assemble(FilePart::PrimaryImage, &data).unwrap();
+
+        let config = crate::DecoderConfig::new();
+        let expected = config.decode_request(&file).decode_yuv().unwrap();
+        let frame = config.decode_request(&subset).decode_yuv().unwrap();
+        assert_eq!((frame.width, frame.height), (128, 128));
+        assert_eq!(frame.y_plane, expected.y_plane);
+    }
+
+    #[test]
+    fn test_probe_hints() {
+        use crate::{ImageInfo, ProbeError};
+
+        let sample = sample_file();
+        let file = &sample.file[..];
+        let meta_offset = (file.len() - sample.meta_len - 8) as u64;
+        let need = |offset, len| Some((offset, len));
+        let hint = |result: core::result::Result<ImageInfo, ProbeError>| match result {
+            Err(ProbeError::NeedMoreData { offset, len }) => Some((offset, len)),
+            _ => None,
+        };
+
+        // A prefix asks for the meta box header past the mdat, then the box
+        assert_eq!(hint(ImageInfo::from_bytes(&file[..6])), need(0, 16));
+        assert_eq!(
+            hint(ImageInfo::from_bytes(&file[..100])),
+            need(meta_offset, 16)
+        );
+        let end = meta_offset as usize + 16;
+        let meta_size = sample.meta_len as u64 + 8;
+        assert_eq!(
+            hint(ImageInfo::from_bytes(&file[..end])),
+            need(meta_offset, meta_size)
+        );
+
+        // Head and tail windows hold the header; the parameter sets are only
+        // in the image data, which neither window holds
+        let tail = &file[meta_offset as usize - 10..];
+        let image_len = sample.image.len() as u64;
+        assert_eq!(
+            hint(ImageInfo::from_head_and_tail(
+                &file[..64],
+                tail,
+                file.len() as u64
+            )),
+            need(sample.data_start, image_len)
+        );
+        // A tail window starting inside the meta box asks for its header
+        assert_eq!(
+            hint(ImageInfo::from_head_and_tail(
+                &file[..64],
+                &tail[20..],
+                file.len() as u64
+            )),
+            need(meta_offset, 16)
+        );
+    }
+}
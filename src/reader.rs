@@ -0,0 +1,293 @@
+//! Reading HEIF files from seekable sources on demand
+
+use alloc::vec::Vec;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+    ByteRange, DecodeOutput, DecodeRequest, DecoderConfig, FilePart, HeaderStatus, HeicError,
+    ImageInfo, Limits, PixelLayout, ProbeError, RangePlanner, Result,
+};
+
+/// A HEIF file read piecewise from a [`Read`] + [`Seek`] source
+///
+/// Construction reads only the `ftyp` and `meta` boxes, wherever they are
+/// in the file, and seeks over everything else. Each operation then reads
+/// just the item data it needs: decoding the primary image does not touch
+/// the thumbnail or metadata, and extracting EXIF does not touch any image.
+///
+/// Built on [`RangePlanner`]: the `read_*` methods return a small
+/// self-contained HEIF file holding the `meta` box and the needed item data,
+/// which every slice-based function of [`DecoderConfig`] accepts. Image
+/// sequence tracks (`moov`) are not read.
+///
+/// # Example
+///
+/// ```ignore
+/// use heic_decoder::{DecoderConfig, HeifReader, PixelLayout};
+///
+/// let mut reader = HeifReader::new(std::fs::File::open("image.heic")?)?;
+/// let exif = reader.extract_exif()?;
+/// let output = reader.decode(&DecoderConfig::new(), PixelLayout::Rgba8, None)?;
+/// ```
+#[derive(Debug)]
+pub struct HeifReader<R> {
+    source: R,
+    planner: RangePlanner,
+}
+
+impl<R: Read + Seek> HeifReader<R> {
+    /// Read the `ftyp` and `meta` boxes of a HEIF file starting at offset 0
+    /// of `source`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, the top-level boxes are malformed
+    /// or either box is missing.
+    pub fn new(mut source: R) -> Result<Self> {
+        let file_len = source.seek(SeekFrom::End(0)).map_err(HeicError::Io)?;
+        let mut planner = RangePlanner::new(file_len);
+        let mut status = planner.next_request();
+        while let HeaderStatus::NeedMoreData { offset, len } = status {
+            let data = read_range(&mut source, ByteRange { offset, len })?;
+            status = planner.push(offset, &data)?;
+        }
+        Ok(Self { source, planner })
+    }
+
+    /// Image metadata, read from the `meta` box
+    ///
+    /// Falls back to reading the primary image's data when its parameter
+    /// sets are not in the `meta` box.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the container is malformed or reading fails.
+    pub fn info(&mut self) -> Result<ImageInfo> {
+        match ImageInfo::from_bytes(self.planner.header()) {
+            Err(ProbeError::NeedMoreData { .. }) => {
+                ImageInfo::from_bytes(&self.read_primary()?).map_err(probe_error)
+            }
+            result => result.map_err(probe_error),
+        }
+    }
+
+    /// Read the primary image with its alternatives, derived-from images
+    /// and auxiliary images (alpha, depth, gain map)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no primary image, the container is
+    /// malformed or reading fails.
+    pub fn read_primary(&mut self) -> Result<Vec<u8>> {
+        self.read_part(FilePart::PrimaryImage)?
+            .ok_or_else(|| HeicError::NoPrimaryImage.into())
+    }
+
+    /// Read the primary image's thumbnail, or `None` if it has none
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the container is malformed or reading fails.
+    pub fn read_thumbnail(&mut self) -> Result<Option<Vec<u8>>> {
+        self.read_part(FilePart::Thumbnail)
+    }
+
+    /// Decode the primary image with `config`, reading only its data
+    ///
+    /// `limits`, if given, apply as with [`DecodeRequest::with_limits`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or decoding fails or a limit is exceeded.
+    pub fn decode(
+        &mut self,
+        config: &DecoderConfig,
+        layout: PixelLayout,
+        limits: Option<&Limits>,
+    ) -> Result<DecodeOutput> {
+        let file = self.read_primary()?;
+        with_limits(config.decode_request(&file), limits)
+            .with_output_layout(layout)
+            .decode()
+    }
+
+    /// Decode the thumbnail with `config`, reading only its data
+    ///
+    /// `limits`, if given, apply as with [`DecodeRequest::with_limits`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or decoding fails or a limit is exceeded.
+    pub fn decode_thumbnail(
+        &mut self,
+        config: &DecoderConfig,
+        layout: PixelLayout,
+        limits: Option<&Limits>,
+    ) -> Result<Option<DecodeOutput>> {
+        let Some(file) = self.read_thumbnail()? else {
+            return Ok(None);
+        };
+        with_limits(config.decode_request(&file), limits)
+            .with_output_layout(layout)
+            .decode_thumbnail()
+    }
+
+    /// Read the EXIF (TIFF) data, as [`DecoderConfig::extract_exif`] returns it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the container is malformed or reading fails.
+    pub fn extract_exif(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(file) = self.read_part(FilePart::Exif)? else {
+            return Ok(None);
+        };
+        Ok(DecoderConfig::new()
+            .extract_exif(&file)?
+            .map(<[u8]>::to_vec))
+    }
+
+    /// Read the XMP data, as [`DecoderConfig::extract_xmp`] returns it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the container is malformed or reading fails.
+    pub fn extract_xmp(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(file) = self.read_part(FilePart::Xmp)? else {
+            return Ok(None);
+        };
+        Ok(DecoderConfig::new().extract_xmp(&file)?.map(<[u8]>::to_vec))
+    }
+
+    /// Give back the source
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Read the ranges of `part` and assemble them into a file
+    fn read_part(&mut self, part: FilePart) -> Result<Option<Vec<u8>>> {
+        let Some(ranges) = self.planner.ranges(part)? else {
+            return Ok(None);
+        };
+        let fetched = ranges
+            .into_iter()
+            .map(|range| read_range(&mut self.source, range))
+            .collect::<Result<Vec<_>>>()?;
+        self.planner.assemble(part, &fetched).map(Some)
+    }
+}
+
+/// Apply optional limits to a request
+fn with_limits<'a>(request: DecodeRequest<'a>, limits: Option<&'a Limits>) -> DecodeRequest<'a> {
+    match limits {
+        Some(limits) => request.with_limits(limits),
+        None => request,
+    }
+}
+
+/// Read exactly the bytes of `range`
+fn read_range(source: &mut (impl Read + Seek), range: ByteRange) -> Result<Vec<u8>> {
+    source
+        .seek(SeekFrom::Start(range.offset))
+        .map_err(HeicError::Io)?;
+    let mut data = Vec::new();
+    source
+        .take(range.len)
+        .read_to_end(&mut data)
+        .map_err(HeicError::Io)?;
+    if data.len() as u64 != range.len {
+        return Err(HeicError::InvalidContainer("unexpected end of file").into());
+    }
+    Ok(data)
+}
+
+fn probe_error(e: ProbeError) -> whereat::At<HeicError> {
+    match e {
+        ProbeError::Corrupt(e) => e.into(),
+        ProbeError::InvalidFormat => HeicError::InvalidContainer("not a HEIF file").into(),
+        _ => HeicError::InvalidContainer("incomplete header").into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PictureHashCheck;
+    use crate::heif;
+    use crate::ranges::tests::sample_file;
+    use std::io::Cursor;
+
+    /// Cursor counting the bytes read through it
+    struct Counting {
+        inner: Cursor<Vec<u8>>,
+        read: usize,
+    }
+
+    impl Read for Counting {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n;
+            Ok(n)
+        }
+    }
+
+    impl Seek for Counting {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_reads_only_needed_items() {
+        let sample = sample_file();
+        let source = Counting {
+            inner: Cursor::new(sample.file.clone()),
+            read: 0,
+        };
+        let mut reader = HeifReader::new(source).unwrap();
+        let header_read = reader.source.read;
+        assert!(header_read < 100 + sample.meta_len);
+
+        let exif_out = reader.extract_exif().unwrap().unwrap();
+        assert_eq!(exif_out, &sample.exif[4..]);
+        assert_eq!(reader.source.read - header_read, sample.exif.len());
+        assert_eq!(reader.extract_xmp().unwrap(), None);
+
+        let primary = reader.read_primary().unwrap();
+        let container = heif::parse(&primary).unwrap();
+        assert_eq!(container.primary_item_id, 1);
+        assert_eq!(container.get_item_data(1), Some(&sample.image[..]));
+        assert_eq!(container.get_item_data(2), None);
+        assert_eq!(container.get_item_data(3), None);
+
+        let thumbnail = reader.read_thumbnail().unwrap().unwrap();
+        let container = heif::parse(&thumbnail).unwrap();
+        assert_eq!(container.get_item_data(3), Some(&sample.thumb[..]));
+        assert_eq!(container.get_item_data(1), None);
+
+        let total = sample.image.len() + sample.exif.len() + sample.thumb.len();
+        assert_eq!(reader.into_inner().read - header_read, total);
+    }
+
+    #[test]
+    fn test_decode_applies_config_and_limits() {
+        let heic = crate::tests::heic_with_wrong_hash();
+        let config = DecoderConfig::new().with_picture_hash_check(PictureHashCheck::Warn);
+        let expected = config.decode(&heic, PixelLayout::Rgb8).unwrap();
+
+        let mut reader = HeifReader::new(Cursor::new(heic)).unwrap();
+        let output = reader.decode(&config, PixelLayout::Rgb8, None).unwrap();
+        assert!(output.picture_hash_mismatch);
+        assert_eq!(output.data, expected.data);
+
+        let limits = Limits {
+            max_width: Some(32),
+            ..Limits::default()
+        };
+        let error = reader
+            .decode(&config, PixelLayout::Rgb8, Some(&limits))
+            .unwrap_err();
+        assert!(matches!(error.error(), HeicError::LimitExceeded(_)));
+        let thumbnail = reader.decode_thumbnail(&config, PixelLayout::Rgb8, None);
+        assert!(thumbnail.unwrap().is_none());
+    }
+}
@@ -0,0 +1,168 @@
+//! Area-averaging downscale of packed output pixels
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::PixelLayout;
+
+/// Source span of one output sample: first source index and the coverage
+/// weight of each source sample (summing to 1)
+struct Span {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Spans mapping `src_len` samples onto `dst_len` samples (`dst_len <= src_len`)
+fn spans(src_len: usize, dst_len: usize) -> Vec<Span> {
+    let scale = src_len as f64 / dst_len as f64;
+    (0..dst_len)
+        .map(|i| {
+            let lo = i as f64 * scale;
+            let hi = ((i + 1) as f64 * scale).min(src_len as f64);
+            let start = lo as usize;
+            let end = ceil_usize(hi).min(src_len).max(start + 1);
+            let weights = (start..end)
+                .map(|s| {
+                    let cover = (hi.min((s + 1) as f64) - lo.max(s as f64)).max(0.0);
+                    (cover / (hi - lo)) as f32
+                })
+                .collect();
+            Span { start, weights }
+        })
+        .collect()
+}
+
+/// Ceiling of a non-negative f64 (f64::ceil requires std)
+fn ceil_usize(x: f64) -> usize {
+    let i = x as usize;
+    if (i as f64) < x { i + 1 } else { i }
+}
+
+/// Downscale packed pixels in `layout` from `w` x `h` to `dst_w` x `dst_h`
+///
+/// Each output pixel is the coverage-weighted average of the source pixels
+/// under it. Colour channels are averaged premultiplied by alpha so that
+/// transparent pixels do not bleed into opaque ones. The averages are taken
+/// on the gamma-encoded values, not in linear light, so fine high-contrast
+/// detail comes out slightly darker than a linear-light filter makes it.
+///
+/// Output rows are accumulated one at a time from the source rows under
+/// them, so the working memory is a few rows besides the output.
+pub(crate) fn downscale(
+    src: &[u8],
+    w: u32,
+    h: u32,
+    layout: PixelLayout,
+    dst_w: u32,
+    dst_h: u32,
+) -> Vec<u8> {
+    let (w, h, dst_w, dst_h) = (w as usize, h as usize, dst_w as usize, dst_h as usize);
+    let wide = matches!(layout, PixelLayout::Rgb16 | PixelLayout::Rgba16);
+    let channels = if layout.has_alpha() { 4 } else { 3 };
+    let max = if wide { 65535.0 } else { 255.0 };
+    let bytes_per_sample = if wide { 2 } else { 1 };
+    let src_row_len = w * channels * bytes_per_sample;
+    let row_len = dst_w * channels;
+
+    // Source row `y` unpacked to f32, premultiplied by alpha and filtered
+    // horizontally into `dst`
+    let h_spans = spans(w, dst_w);
+    let mut samples = vec![0f32; w * channels];
+    let mut filter_row = |y: usize, dst: &mut [f32]| {
+        let src_row = &src[y * src_row_len..][..src_row_len];
+        if wide {
+            for (s, b) in samples.iter_mut().zip(src_row.chunks_exact(2)) {
+                *s = f32::from(u16::from_ne_bytes([b[0], b[1]]));
+            }
+        } else {
+            for (s, &v) in samples.iter_mut().zip(src_row) {
+                *s = f32::from(v);
+            }
+        }
+        if channels == 4 {
+            for px in samples.chunks_exact_mut(4) {
+                let a = px[3] / max;
+                px[..3].iter_mut().for_each(|c| *c *= a);
+            }
+        }
+        dst.fill(0.0);
+        for (span, dst) in h_spans.iter().zip(dst.chunks_exact_mut(channels)) {
+            for (k, &weight) in span.weights.iter().enumerate() {
+                let px = &samples[(span.start + k) * channels..][..channels];
+                dst.iter_mut().zip(px).for_each(|(d, &s)| *d += s * weight);
+            }
+        }
+    };
+
+    let mut out = Vec::with_capacity(dst_w * dst_h * channels * bytes_per_sample);
+    let mut acc = vec![0f32; row_len];
+    // Adjacent output rows share a boundary source row; keep the last one
+    let mut row = vec![0f32; row_len];
+    let mut row_y = None;
+    let quantize = |v: f32| (v + 0.5).clamp(0.0, max) as u16;
+    for span in spans(h, dst_h) {
+        acc.fill(0.0);
+        for (k, &weight) in span.weights.iter().enumerate() {
+            let y = span.start + k;
+            if row_y != Some(y) {
+                filter_row(y, &mut row);
+                row_y = Some(y);
+            }
+            for (a, &s) in acc.iter_mut().zip(&row) {
+                *a += s * weight;
+            }
+        }
+
+        // Un-premultiply and pack
+        if channels == 4 {
+            for px in acc.chunks_exact_mut(4) {
+                let a = px[3] / max;
+                if a > 0.0 {
+                    px[..3].iter_mut().for_each(|c| *c /= a);
+                }
+            }
+        }
+        if wide {
+            out.extend(acc.iter().flat_map(|&v| quantize(v).to_ne_bytes()));
+        } else {
+            out.extend(acc.iter().map(|&v| quantize(v) as u8));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downscale_average() {
+        // 4x2 RGB: left half black, right half white -> 2x1
+        let mut src = vec![0u8; 4 * 2 * 3];
+        for y in 0..2 {
+            for x in 2..4 {
+                src[(y * 4 + x) * 3..][..3].fill(255);
+            }
+        }
+        let out = downscale(&src, 4, 2, PixelLayout::Rgb8, 2, 1);
+        assert_eq!(out, [0, 0, 0, 255, 255, 255]);
+
+        // Non-integer ratio: 3 -> 2 keeps a flat image flat
+        let flat = vec![100u8; 3 * 3 * 4];
+        let out = downscale(&flat, 3, 3, PixelLayout::Rgba8, 2, 2);
+        assert!(out.iter().all(|&v| v == 100));
+
+        // 3 -> 2 rows share the middle row: (0 + 90 / 2) / 1.5 and
+        // (90 / 2 + 180) / 1.5
+        let rows: Vec<u8> = [0u16, 90, 180]
+            .iter()
+            .flat_map(|&v| v.to_ne_bytes().repeat(3))
+            .collect();
+        let out = downscale(&rows, 1, 3, PixelLayout::Rgb16, 1, 2);
+        let out: Vec<u16> = out
+            .chunks_exact(2)
+            .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(out, [30, 30, 30, 150, 150, 150]);
+    }
+}
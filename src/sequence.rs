@@ -0,0 +1,188 @@
+//! Image sequence decoding (HEIF `msf1` / `.heics` tracks)
+
+use alloc::collections::VecDeque;
+
+use crate::error::{HeicError, Result};
+use crate::heif::{self, ColorInfo, HeifContainer, Track};
+use crate::hevc::{DecodedFrame, SequenceDecoder};
+use crate::{DecoderConfig, finish_coded_image};
+
+/// A decoded frame of an image sequence
+#[derive(Debug, Clone)]
+pub struct SequenceFrame {
+    /// Decoded picture
+    pub frame: DecodedFrame,
+    /// Presentation time in [`timescale`](Self::timescale) units, with the
+    /// track's edit list applied
+    pub presentation_time: i64,
+    /// Display duration in [`timescale`](Self::timescale) units
+    pub duration: u32,
+    /// Ticks per second of the timestamps
+    pub timescale: u32,
+}
+
+/// Iterator over the frames of an image sequence track, in presentation order
+///
+/// Created by [`DecoderConfig::decode_sequence`](crate::DecoderConfig::decode_sequence).
+/// Samples are decoded one at a time; pictures are yielded as soon as the
+/// decoded picture buffer outputs them, with the timestamps of the samples
+/// they were decoded from. Pictures that the edit list places before the
+/// start of the presentation are skipped.
+#[derive(Debug)]
+pub struct ImageSequence<'a> {
+    container: HeifContainer<'a>,
+    config: &'a DecoderConfig,
+    track_idx: usize,
+    decoder: SequenceDecoder,
+    /// Sample entry whose hvcC the decoder was configured with
+    active_entry: Option<u32>,
+    next_sample: usize,
+    /// Output pictures with the index of the sample they were decoded from
+    pending: VecDeque<(u64, DecodedFrame)>,
+    flushed: bool,
+    failed: bool,
+}
+
+impl<'a> ImageSequence<'a> {
+    /// Open the first HEVC image sequence track of a HEIF file, to be decoded
+    /// with `config`
+    pub(crate) fn new(data: &'a [u8], config: &'a DecoderConfig) -> Result<Self> {
+        let container = heif::parse(data)?;
+        let track_idx = container
+            .tracks
+            .iter()
+            .position(Track::is_hevc_sequence)
+            .ok_or(HeicError::InvalidContainer("no HEVC image sequence track"))?;
+
+        Ok(Self {
+            container,
+            config,
+            track_idx,
+            decoder: SequenceDecoder::new(),
+            active_entry: None,
+            next_sample: 0,
+            pending: VecDeque::new(),
+            flushed: false,
+            failed: false,
+        })
+    }
+
+    /// The track being decoded
+    pub fn track(&self) -> &Track {
+        &self.container.tracks[self.track_idx]
+    }
+
+    /// Number of samples in the track
+    pub fn frame_count(&self) -> usize {
+        self.track().samples.len()
+    }
+
+    /// Ticks per second of the frame timestamps
+    pub fn timescale(&self) -> u32 {
+        self.track().timescale
+    }
+
+    /// Decode the next sample in decoding order, or flush at the end
+    fn decode_next(&mut self) -> Result<()> {
+        let track = &self.container.tracks[self.track_idx];
+        let Some(sample) = track.samples.get(self.next_sample) else {
+            self.pending.extend(self.decoder.flush_tagged()?);
+            self.flushed = true;
+            return Ok(());
+        };
+        let tag = self.next_sample as u64;
+        self.next_sample += 1;
+
+        let entry = track
+            .sample_entries
+            .get(sample.entry_index as usize)
+            .ok_or(HeicError::InvalidContainer(
+                "sample entry index out of range",
+            ))?;
+        let config = entry
+            .hevc_config
+            .as_ref()
+            .ok_or(HeicError::InvalidContainer("sample entry without hvcC"))?;
+        if self.active_entry != Some(sample.entry_index) {
+            self.decoder.add_config(config)?;
+            self.active_entry = Some(sample.entry_index);
+        }
+
+        let data = self
+            .container
+            .get_sample_data(sample)
+            .ok_or(HeicError::InvalidData("sample data out of bounds"))?;
+        let length_size = config.length_size_minus_one as usize + 1;
+        let frames = self.decoder.decode_tagged_sample(data, length_size, tag)?;
+        self.pending.extend(frames);
+        Ok(())
+    }
+
+    /// Attach the timing of the picture's sample and apply the colr of its
+    /// sample entry and the decoder configuration
+    ///
+    /// Returns None for a picture before the start of the presentation.
+    fn finish_frame(
+        &self,
+        sample_idx: u64,
+        mut frame: DecodedFrame,
+    ) -> Result<Option<SequenceFrame>> {
+        let track = self.track();
+        let sample = usize::try_from(sample_idx)
+            .ok()
+            .and_then(|i| track.samples.get(i))
+            .ok_or(HeicError::InvalidData("sample index out of range"))?;
+        let presentation_time = track.presentation_time(sample);
+        if presentation_time < 0 {
+            return Ok(None);
+        }
+        // Set color conversion parameters from colr nclx box if present
+        if let Some(ColorInfo::Nclx {
+            full_range,
+            matrix_coefficients,
+            ..
+        }) = track
+            .sample_entries
+            .get(sample.entry_index as usize)
+            .and_then(|entry| entry.color_info.as_ref())
+        {
+            frame.full_range = *full_range;
+            frame.matrix_coeffs = *matrix_coefficients as u8;
+        }
+        finish_coded_image(&mut frame, sample_idx as u32, self.config)?;
+        Ok(Some(SequenceFrame {
+            frame,
+            presentation_time,
+            duration: sample.duration,
+            timescale: track.timescale,
+        }))
+    }
+}
+
+impl Iterator for ImageSequence<'_> {
+    type Item = Result<SequenceFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            if let Some((sample_idx, frame)) = self.pending.pop_front() {
+                match self.finish_frame(sample_idx, frame) {
+                    Ok(None) => continue,
+                    result => {
+                        self.failed = result.is_err();
+                        return result.transpose();
+                    }
+                }
+            }
+            if self.flushed {
+                return None;
+            }
+            if let Err(e) = self.decode_next() {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Streaming output sinks
+
+use crate::{PixelLayout, Result};
+
+/// Receiver of decoded pixels for streaming decodes
+///
+/// Created by the caller and passed to
+/// [`DecodeRequest::decode_to_sink`](crate::DecodeRequest::decode_to_sink).
+/// Pixel data is packed in the layout given to [`begin`](Self::begin), with
+/// rows of `width * layout.bytes_per_pixel()` bytes and no padding.
+///
+/// Grid images are delivered tile by tile through
+/// [`write_tile`](Self::write_tile), in tile row order; other images are
+/// delivered as bands of complete rows through [`write_rows`](Self::write_rows),
+/// top to bottom. Returning an error aborts the decode.
+pub trait ImageSink {
+    /// Called once with the output image size before any pixel data
+    fn begin(&mut self, width: u32, height: u32, layout: PixelLayout) -> Result<()> {
+        let _ = (width, height, layout);
+        Ok(())
+    }
+
+    /// Receive `height` complete rows of the image starting at row `y`
+    fn write_rows(&mut self, y: u32, height: u32, data: &[u8]) -> Result<()>;
+
+    /// Receive a `width` x `height` rectangle whose top-left pixel is at (`x`, `y`)
+    fn write_tile(&mut self, x: u32, y: u32, width: u32, height: u32, data: &[u8]) -> Result<()>;
+}
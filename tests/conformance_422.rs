@@ -0,0 +1,52 @@
+//! 4:2:2 decoding checked against the JCT-VC range extension conformance
+//! streams
+//!
+//! The decoded picture hash SEI of every picture, written by the reference
+//! encoder, must match the decoded planes. Between them the streams cover
+//! 4:2:2 residual coding with both chroma cbfs per block, the Min(qPi, 51)
+//! chroma QP rule, the 4:2:2 intra mode mapping and chroma deblocking.
+//! Streams that are not present are skipped.
+
+use heic_decoder::hevc;
+
+/// Directory holding the RExt conformance bitstreams
+const CONFORMANCE_DIR: &str = "/home/lilith/work/heic/conformance/RExt";
+
+/// Name prefixes of the 4:2:2 streams (the revision suffix varies)
+const STREAMS_422: &[&str] = &[
+    "ADJUST_IPRED_ANGLE_A_RExt_Mitsubishi",
+    "Main_422_10_A_RExt_Sony",
+    "Main_422_10_B_RExt_Sony",
+];
+
+#[test]
+fn test_422_picture_hashes() {
+    let Ok(entries) = std::fs::read_dir(CONFORMANCE_DIR) else {
+        println!("{CONFORMANCE_DIR} not found, skipping");
+        return;
+    };
+    let names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+
+    for prefix in STREAMS_422 {
+        let Some(name) = names
+            .iter()
+            .find(|name| name.starts_with(prefix) && name.ends_with(".bit"))
+        else {
+            println!("{prefix}: not found, skipping");
+            continue;
+        };
+        let data = std::fs::read(format!("{CONFORMANCE_DIR}/{name}")).expect("read");
+        let mut count = 0;
+        for (i, picture) in hevc::decode_pictures(&data).expect("parse").enumerate() {
+            let picture = picture.unwrap_or_else(|e| panic!("{name} picture {i}: {e}"));
+            assert_eq!(picture.chroma_format, 2, "{name}");
+            let matches = picture.verify_picture_hash();
+            assert_eq!(matches, Some(true), "{name} picture {i}");
+            count += 1;
+        }
+        assert!(count > 0, "{name} has no pictures");
+        println!("{name}: {count} picture hashes match");
+    }
+}